@@ -0,0 +1,323 @@
+//! Command-line argument parsing.
+//!
+//! Hand-rolled: the daemon takes only a few flags, which does not justify a
+//! parser dependency.
+
+use std::path::PathBuf;
+
+// ---------------------------------------------------------------------------
+// Public types
+// ---------------------------------------------------------------------------
+
+/// Errors produced while parsing command-line arguments.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CliError {
+    #[error(
+        "unknown argument '{0}' (usage: pcunifier [--config PATH] [--start-paused] \
+         [--no-permission-prompt] [--allow-uiaccess] | pcunifier check [--config PATH] [--lint] \
+         [--dump | --dump-json] | \
+         pcunifier install-agent [--config PATH] [--dry-run] | pcunifier uninstall-agent | \
+         pcunifier install-startup [--config PATH] [--task] [--dry-run] | \
+         pcunifier uninstall-startup | pcunifier monitor)"
+    )]
+    UnknownArgument(String),
+    #[error("'{0}' needs a value")]
+    MissingValue(String),
+}
+
+/// What the process does after parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    /// Run the daemon.
+    #[default]
+    Run,
+    /// Validate the config and exit. With `lint`, also report rules that are
+    /// shadowed by other rules. With `dump`, print the resolved rules.
+    Check {
+        lint: bool,
+        dump: Option<DumpFormat>,
+    },
+    /// Register the daemon as a macOS LaunchAgent. With `dry_run`, print the
+    /// plist instead.
+    InstallAgent { dry_run: bool },
+    /// Remove the LaunchAgent.
+    UninstallAgent,
+    /// Register the daemon to start at login on Windows. With `dry_run`,
+    /// print what would be written instead.
+    InstallStartup {
+        method: StartupMethod,
+        dry_run: bool,
+    },
+    /// Remove the Windows startup entry, whichever method wrote it.
+    UninstallStartup,
+    /// Show key events and daemon state live in the terminal.
+    Monitor,
+}
+
+/// How `check --dump` prints the resolved rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Toml,
+    Json,
+}
+
+/// How `install-startup` starts the daemon at login.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartupMethod {
+    /// A value in the user's Run key.
+    #[default]
+    RunKey,
+    /// A scheduled task with the highest run level (`--task`).
+    Task,
+    /// A Windows service (`--service`), which is refused with the reason.
+    Service,
+}
+
+/// Parsed command-line options.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Options {
+    pub command: Command,
+    /// Config file to use instead of the OS-conventional path.
+    pub config: Option<PathBuf>,
+    /// Install capture but pass every key through until a `resume` command
+    /// arrives on the control socket (or a `toggle_pause` hotkey fires).
+    pub start_paused: bool,
+    /// Fail at startup when a permission is missing instead of asking for it
+    /// with a system dialog. For service contexts with nobody to answer.
+    pub no_permission_prompt: bool,
+    /// The binary is built and signed to run with UIAccess (Windows), which
+    /// lets it reach elevated windows; warn at startup if it does not.
+    pub allow_uiaccess: bool,
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+/// Parse arguments, excluding the program name.
+pub fn parse<I>(args: I) -> Result<Options, CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut options = Options::default();
+    let mut args = args.into_iter().peekable();
+    let subcommand = match args.peek().map(String::as_str) {
+        Some("check") => Some(Command::Check {
+            lint: false,
+            dump: None,
+        }),
+        Some("install-agent") => Some(Command::InstallAgent { dry_run: false }),
+        Some("uninstall-agent") => Some(Command::UninstallAgent),
+        Some("install-startup") => Some(Command::InstallStartup {
+            method: StartupMethod::RunKey,
+            dry_run: false,
+        }),
+        Some("uninstall-startup") => Some(Command::UninstallStartup),
+        Some("monitor") => Some(Command::Monitor),
+        _ => None,
+    };
+    if let Some(command) = subcommand {
+        args.next();
+        options.command = command;
+    }
+    while let Some(arg) = args.next() {
+        match (&mut options.command, arg.as_str()) {
+            (Command::Run, "--start-paused") => options.start_paused = true,
+            (Command::Run, "--no-permission-prompt") => options.no_permission_prompt = true,
+            (Command::Run, "--allow-uiaccess") => options.allow_uiaccess = true,
+            (Command::Check { lint, .. }, "--lint") => *lint = true,
+            (Command::Check { dump, .. }, "--dump") => *dump = Some(DumpFormat::Toml),
+            (Command::Check { dump, .. }, "--dump-json") => *dump = Some(DumpFormat::Json),
+            (Command::InstallAgent { dry_run }, "--dry-run") => *dry_run = true,
+            (Command::InstallStartup { dry_run, .. }, "--dry-run") => *dry_run = true,
+            (Command::InstallStartup { method, .. }, "--task") => *method = StartupMethod::Task,
+            (Command::InstallStartup { method, .. }, "--service") => {
+                *method = StartupMethod::Service
+            }
+            (
+                Command::Run
+                | Command::Check { .. }
+                | Command::InstallAgent { .. }
+                | Command::InstallStartup { .. },
+                "--config",
+            ) => {
+                let path = args.next().ok_or(CliError::MissingValue(arg))?;
+                options.config = Some(PathBuf::from(path));
+            }
+            _ => return Err(CliError::UnknownArgument(arg)),
+        }
+    }
+    Ok(options)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_strs(args: &[&str]) -> Result<Options, CliError> {
+        parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_gives_defaults() {
+        assert_eq!(parse_strs(&[]).unwrap(), Options::default());
+    }
+
+    #[test]
+    fn start_paused_flag() {
+        assert!(parse_strs(&["--start-paused"]).unwrap().start_paused);
+    }
+
+    #[test]
+    fn no_permission_prompt_flag() {
+        let options = parse_strs(&["--no-permission-prompt", "--start-paused"]).unwrap();
+        assert!(options.no_permission_prompt);
+        assert!(options.start_paused);
+        assert_eq!(
+            parse_strs(&["check", "--no-permission-prompt"]),
+            Err(CliError::UnknownArgument("--no-permission-prompt".into()))
+        );
+    }
+
+    #[test]
+    fn allow_uiaccess_flag() {
+        assert!(parse_strs(&["--allow-uiaccess"]).unwrap().allow_uiaccess);
+        assert_eq!(
+            parse_strs(&["check", "--allow-uiaccess"]),
+            Err(CliError::UnknownArgument("--allow-uiaccess".into()))
+        );
+    }
+
+    #[test]
+    fn check_subcommand_with_and_without_lint() {
+        assert_eq!(
+            parse_strs(&["check"]).unwrap().command,
+            Command::Check {
+                lint: false,
+                dump: None
+            }
+        );
+        assert_eq!(
+            parse_strs(&["check", "--lint"]).unwrap().command,
+            Command::Check {
+                lint: true,
+                dump: None
+            }
+        );
+    }
+
+    #[test]
+    fn check_dump_formats() {
+        assert_eq!(
+            parse_strs(&["check", "--dump"]).unwrap().command,
+            Command::Check {
+                lint: false,
+                dump: Some(DumpFormat::Toml)
+            }
+        );
+        assert_eq!(
+            parse_strs(&["check", "--dump-json", "--lint"])
+                .unwrap()
+                .command,
+            Command::Check {
+                lint: true,
+                dump: Some(DumpFormat::Json)
+            }
+        );
+        assert_eq!(
+            parse_strs(&["--dump"]),
+            Err(CliError::UnknownArgument("--dump".into()))
+        );
+    }
+
+    #[test]
+    fn flags_are_scoped_to_their_command() {
+        assert_eq!(
+            parse_strs(&["--lint"]),
+            Err(CliError::UnknownArgument("--lint".into()))
+        );
+        assert_eq!(
+            parse_strs(&["check", "--start-paused"]),
+            Err(CliError::UnknownArgument("--start-paused".into()))
+        );
+    }
+
+    #[test]
+    fn install_agent_subcommands() {
+        let options = parse_strs(&["install-agent", "--dry-run", "--config", "/tmp/c.toml"]);
+        let options = options.unwrap();
+        assert_eq!(options.command, Command::InstallAgent { dry_run: true });
+        assert_eq!(options.config, Some(PathBuf::from("/tmp/c.toml")));
+        assert_eq!(
+            parse_strs(&["uninstall-agent"]).unwrap().command,
+            Command::UninstallAgent
+        );
+        assert_eq!(
+            parse_strs(&["uninstall-agent", "--dry-run"]),
+            Err(CliError::UnknownArgument("--dry-run".into()))
+        );
+    }
+
+    #[test]
+    fn install_startup_subcommands() {
+        let options = parse_strs(&["install-startup", "--task", "--config", "c.toml"]).unwrap();
+        assert_eq!(
+            options.command,
+            Command::InstallStartup {
+                method: StartupMethod::Task,
+                dry_run: false
+            }
+        );
+        assert_eq!(options.config, Some(PathBuf::from("c.toml")));
+        assert_eq!(
+            parse_strs(&["install-startup", "--service", "--dry-run"])
+                .unwrap()
+                .command,
+            Command::InstallStartup {
+                method: StartupMethod::Service,
+                dry_run: true
+            }
+        );
+        assert_eq!(
+            parse_strs(&["uninstall-startup"]).unwrap().command,
+            Command::UninstallStartup
+        );
+        assert_eq!(
+            parse_strs(&["uninstall-startup", "--task"]),
+            Err(CliError::UnknownArgument("--task".into()))
+        );
+    }
+
+    #[test]
+    fn monitor_subcommand_takes_no_flags() {
+        assert_eq!(parse_strs(&["monitor"]).unwrap().command, Command::Monitor);
+        assert_eq!(
+            parse_strs(&["monitor", "--config", "c.toml"]),
+            Err(CliError::UnknownArgument("--config".into()))
+        );
+    }
+
+    #[test]
+    fn config_flag_needs_a_path() {
+        assert_eq!(
+            parse_strs(&["--config"]),
+            Err(CliError::MissingValue("--config".into()))
+        );
+        assert_eq!(
+            parse_strs(&["check", "--config", "c.toml"]).unwrap().config,
+            Some(PathBuf::from("c.toml"))
+        );
+    }
+
+    #[test]
+    fn unknown_argument_is_rejected() {
+        assert_eq!(
+            parse_strs(&["--bogus"]),
+            Err(CliError::UnknownArgument("--bogus".into()))
+        );
+    }
+}
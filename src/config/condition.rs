@@ -0,0 +1,159 @@
+//! Window conditions: regexes over the focused window's title and app id.
+//!
+//! A `when` table on a rule is compiled once, at config load, into a
+//! `WindowCondition`. Matching an event then runs the compiled regexes
+//! against its `WindowContext` without recompiling or allocating, so it is
+//! safe to do on every keystroke.
+
+use regex::{Regex, RegexBuilder};
+
+use super::ConfigError;
+use crate::platform::WindowContext;
+
+/// Compiled `when = { title = "...", app = "..." }` condition.
+///
+/// Every pattern present must match. A pattern matches anywhere in the text
+/// unless anchored with `^` or `$`. A context lacking the text a pattern
+/// needs (no title, no app id) never matches.
+#[derive(Debug, Clone)]
+pub struct WindowCondition {
+    title: Option<Regex>,
+    app: Option<Regex>,
+    ignore_case: bool,
+}
+
+impl WindowCondition {
+    /// Compile the patterns of a `when` table. At least one is required.
+    pub(super) fn compile(
+        title: Option<&str>,
+        app: Option<&str>,
+        ignore_case: bool,
+    ) -> Result<Self, ConfigError> {
+        if title.is_none() && app.is_none() {
+            return Err(ConfigError::EmptyCondition);
+        }
+        let build = |field: &'static str, pattern: Option<&str>| {
+            pattern
+                .map(|p| {
+                    RegexBuilder::new(p)
+                        .case_insensitive(ignore_case)
+                        .build()
+                        .map_err(|source| ConfigError::InvalidPattern { field, source })
+                })
+                .transpose()
+        };
+        Ok(Self {
+            title: build("title", title)?,
+            app: build("app", app)?,
+            ignore_case,
+        })
+    }
+
+    /// The title pattern as written, if any.
+    pub fn title_pattern(&self) -> Option<&str> {
+        self.title.as_ref().map(Regex::as_str)
+    }
+
+    /// The app pattern as written, if any.
+    pub fn app_pattern(&self) -> Option<&str> {
+        self.app.as_ref().map(Regex::as_str)
+    }
+
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// Whether `window` satisfies every pattern in the condition.
+    pub fn matches(&self, window: &WindowContext) -> bool {
+        fn check(pattern: &Option<Regex>, text: &Option<String>) -> bool {
+            match (pattern, text) {
+                (None, _) => true,
+                (Some(re), Some(text)) => re.is_match(text),
+                (Some(_), None) => false,
+            }
+        }
+        check(&self.title, &window.title) && check(&self.app, &window.app_id)
+    }
+}
+
+/// Conditions are equal when written the same way.
+impl PartialEq for WindowCondition {
+    fn eq(&self, other: &Self) -> bool {
+        fn source(re: &Option<Regex>) -> Option<&str> {
+            re.as_ref().map(Regex::as_str)
+        }
+        self.ignore_case == other.ignore_case
+            && source(&self.title) == source(&other.title)
+            && source(&self.app) == source(&other.app)
+    }
+}
+
+impl Eq for WindowCondition {}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(app_id: Option<&str>, title: Option<&str>) -> WindowContext {
+        WindowContext {
+            app_id: app_id.map(str::to_owned),
+            title: title.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn title_pattern_matches_anywhere_unless_anchored() {
+        let cond = WindowCondition::compile(Some(r"\.rs - "), None, false).unwrap();
+        assert!(cond.matches(&window(None, Some("main.rs - pcunifier - Code"))));
+        assert!(!cond.matches(&window(None, Some("notes.md - Code"))));
+
+        let anchored = WindowCondition::compile(Some("^Inbox"), None, false).unwrap();
+        assert!(anchored.matches(&window(None, Some("Inbox (3)"))));
+        assert!(!anchored.matches(&window(None, Some("Re: Inbox"))));
+    }
+
+    #[test]
+    fn every_pattern_must_match() {
+        let cond =
+            WindowCondition::compile(Some("YouTube"), Some(r"^org\.mozilla\."), false).unwrap();
+        assert!(cond.matches(&window(Some("org.mozilla.firefox"), Some("Cats - YouTube"))));
+        assert!(!cond.matches(&window(Some("chromium"), Some("Cats - YouTube"))));
+        assert!(!cond.matches(&window(Some("org.mozilla.firefox"), Some("Docs"))));
+    }
+
+    #[test]
+    fn missing_context_never_matches() {
+        let cond = WindowCondition::compile(Some(".*"), None, false).unwrap();
+        assert!(!cond.matches(&window(Some("kitty"), None)));
+        let cond = WindowCondition::compile(None, Some(".*"), false).unwrap();
+        assert!(!cond.matches(&WindowContext::default()));
+    }
+
+    #[test]
+    fn ignore_case_applies_to_all_patterns() {
+        let cond = WindowCondition::compile(Some("youtube"), Some("FIREFOX"), true).unwrap();
+        assert!(cond.matches(&window(Some("firefox"), Some("Cats - YouTube"))));
+        let strict = WindowCondition::compile(Some("youtube"), None, false).unwrap();
+        assert!(!strict.matches(&window(None, Some("Cats - YouTube"))));
+    }
+
+    #[test]
+    fn invalid_pattern_names_its_field() {
+        match WindowCondition::compile(Some("ok"), Some("(unclosed"), false) {
+            Err(ConfigError::InvalidPattern { field: "app", .. }) => {}
+            other => panic!("expected InvalidPattern for app, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn condition_without_patterns_is_rejected() {
+        assert!(matches!(
+            WindowCondition::compile(None, None, true),
+            Err(ConfigError::EmptyCondition)
+        ));
+    }
+}
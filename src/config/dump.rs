@@ -0,0 +1,549 @@
+//! The effective rule set, written back out as config.
+//!
+//! `Config::dump_effective_config` turns a loaded `Config` into the TOML the engine
+//! would read to get the same rules, with everything the loader resolved
+//! spelled out: key aliases replaced by canonical names, each rule's timing
+//! with the `[timing]` defaults applied, and combinations in one form. It
+//! reflects what the engine runs with, not how the source file was written,
+//! which is what "why does this rule not fire" usually needs.
+//!
+//! Only rules and the settings that decide which rule applies are included;
+//! backend, logging, and platform options are left out. `[capture] match`,
+//! `modifiers`, and `sides` are written when not the default: the first two
+//! decide which rule a key fires, and a rule naming a left-side modifier
+//! only loads with the last.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::{
+    Config, DisableRule, HotkeyAction, HotkeyRule, HotstringRule, KeyMatch, ModifierMatch,
+    PassthroughRule, RemapRule, RemapStep, RemapTarget, SideMatch, Timing, WindowCondition,
+};
+use crate::platform::{keynames, CommandLine, KeyCode, MouseButton, StepFailure, SystemCmd};
+
+/// The resolved rule set, in config file form.
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    safety: Option<EffectiveSafety>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capture: Option<EffectiveCapture>,
+    timing: EffectiveTiming,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    remap: Vec<EffectiveRemap>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hotkey: Vec<EffectiveHotkey>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    disable: Vec<EffectiveDisable>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hotstring: Vec<EffectiveHotstring>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    passthrough: Vec<EffectivePassthrough>,
+}
+
+impl EffectiveConfig {
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("the effective config is always valid TOML")
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("the effective config is always valid JSON")
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveSafety {
+    key: String,
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveCapture {
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    key_match: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modifiers: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sides: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveTiming {
+    tap_ms: u64,
+    chord_window_ms: u64,
+    sequence_step_ms: u64,
+    oneshot_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveRemap {
+    from: String,
+    to: EffectiveTarget,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeat: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeat_every_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apps: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    when: Option<EffectiveWhen>,
+    timing: EffectiveTiming,
+}
+
+/// A remap `to` value in each of its written forms.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum EffectiveTarget {
+    Name(String),
+    Char {
+        char: String,
+    },
+    With {
+        with: Vec<String>,
+    },
+    Split {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        down: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        up: Option<String>,
+    },
+    Button {
+        button: &'static str,
+    },
+    Paste {
+        paste: String,
+    },
+    Steps {
+        steps: Vec<EffectiveStep>,
+        on_error: &'static str,
+    },
+}
+
+/// A `steps` entry in each of its written forms.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum EffectiveStep {
+    Keys(String),
+    Delay { delay_ms: u64 },
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveHotkey {
+    keys: Vec<String>,
+    action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<EffectiveCommand>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cwd: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apps: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    when: Option<EffectiveWhen>,
+    timing: EffectiveTiming,
+}
+
+/// A hotkey `command` in each of its written forms.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum EffectiveCommand {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveDisable {
+    while_held: String,
+    keys: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveHotstring {
+    trigger: String,
+    replacement: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apps: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+struct EffectivePassthrough {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apps: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    when: Option<EffectiveWhen>,
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveWhen {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app: Option<String>,
+    ignore_case: bool,
+}
+
+// ---------------------------------------------------------------------------
+// Conversion
+// ---------------------------------------------------------------------------
+
+impl Config {
+    /// The rules this config resolved to, ready to serialize.
+    pub fn dump_effective_config(&self) -> EffectiveConfig {
+        EffectiveConfig {
+            safety: self.safety_key.map(|key| EffectiveSafety {
+                key: keynames::name(key),
+            }),
+            capture: capture(self),
+            timing: timing(&self.timing),
+            remap: self.remaps.iter().map(remap).collect(),
+            hotkey: self.hotkeys.iter().map(hotkey).collect(),
+            disable: self.disables.iter().map(disable).collect(),
+            hotstring: self.hotstrings.iter().map(hotstring).collect(),
+            passthrough: self.passthroughs.iter().map(passthrough).collect(),
+        }
+    }
+}
+
+fn key_names(keys: &[KeyCode]) -> Vec<String> {
+    keys.iter().copied().map(keynames::name).collect()
+}
+
+/// A key or combination as written in a remap target: `Ctrl+Shift+T`.
+fn combo(keys: &[KeyCode]) -> String {
+    key_names(keys).join("+")
+}
+
+/// The `[capture]` settings that differ from the default, if any do.
+fn capture(config: &Config) -> Option<EffectiveCapture> {
+    let capture = EffectiveCapture {
+        key_match: match config.key_match {
+            KeyMatch::Key => None,
+            KeyMatch::Position => Some("position"),
+        },
+        modifiers: match config.modifier_match {
+            ModifierMatch::Logical => None,
+            ModifierMatch::Physical => Some("physical"),
+        },
+        sides: match config.side_match {
+            SideMatch::Right => None,
+            SideMatch::Unified => Some("unified"),
+            SideMatch::Both => Some("both"),
+        },
+    };
+    let defaults =
+        capture.key_match.is_none() && capture.modifiers.is_none() && capture.sides.is_none();
+    (!defaults).then_some(capture)
+}
+
+fn timing(timing: &Timing) -> EffectiveTiming {
+    let ms = |d: std::time::Duration| d.as_millis() as u64;
+    EffectiveTiming {
+        tap_ms: ms(timing.tap),
+        chord_window_ms: ms(timing.chord_window),
+        sequence_step_ms: ms(timing.sequence_step),
+        oneshot_ms: ms(timing.oneshot),
+    }
+}
+
+fn when(condition: &WindowCondition) -> EffectiveWhen {
+    EffectiveWhen {
+        title: condition.title_pattern().map(str::to_owned),
+        app: condition.app_pattern().map(str::to_owned),
+        ignore_case: condition.ignore_case(),
+    }
+}
+
+fn remap(rule: &RemapRule) -> EffectiveRemap {
+    let side = |keys: &[KeyCode]| (!keys.is_empty()).then(|| combo(keys));
+    let to = match &rule.to {
+        RemapTarget::Key(key) => EffectiveTarget::Name(keynames::name(*key)),
+        RemapTarget::Combo(keys) => EffectiveTarget::Name(combo(keys)),
+        RemapTarget::Char(c) => EffectiveTarget::Char {
+            char: c.to_string(),
+        },
+        RemapTarget::With(modifiers) => EffectiveTarget::With {
+            with: key_names(modifiers),
+        },
+        RemapTarget::Split { down, up } => EffectiveTarget::Split {
+            down: side(down),
+            up: side(up),
+        },
+        RemapTarget::Button(button) => EffectiveTarget::Button {
+            button: match button {
+                MouseButton::Left => "left",
+                MouseButton::Right => "right",
+                MouseButton::Middle => "middle",
+                MouseButton::Back => "back",
+                MouseButton::Forward => "forward",
+                // The config names no other button.
+                MouseButton::Other(_) => "other",
+            },
+        },
+        RemapTarget::Paste(text) => EffectiveTarget::Paste {
+            paste: text.clone(),
+        },
+        RemapTarget::Steps { steps, on_error } => EffectiveTarget::Steps {
+            steps: steps
+                .iter()
+                .map(|step| match step {
+                    RemapStep::Keys(keys) => EffectiveStep::Keys(combo(keys)),
+                    RemapStep::Delay(ms) => EffectiveStep::Delay { delay_ms: *ms },
+                })
+                .collect(),
+            on_error: match on_error {
+                StepFailure::Abort => "abort",
+                StepFailure::Continue => "continue",
+            },
+        },
+    };
+    EffectiveRemap {
+        from: keynames::name(rule.from),
+        to,
+        repeat: rule.repeat,
+        repeat_every_ms: rule.repeat_every_ms,
+        apps: rule.apps.clone(),
+        when: rule.when.as_ref().map(when),
+        timing: timing(&rule.timing),
+    }
+}
+
+fn hotkey(rule: &HotkeyRule) -> EffectiveHotkey {
+    let (action, command) = match &rule.action {
+        HotkeyAction::Exec(command) => ("exec", Some(command)),
+        HotkeyAction::TypeOutput(command) => ("type_output", Some(command)),
+        HotkeyAction::SetPaused(None) => ("toggle_pause", None),
+        HotkeyAction::SetPaused(Some(true)) => ("pause", None),
+        HotkeyAction::SetPaused(Some(false)) => ("resume", None),
+        HotkeyAction::System(SystemCmd::LockScreen) => ("lock_screen", None),
+        HotkeyAction::System(SystemCmd::Sleep) => ("sleep", None),
+        HotkeyAction::System(SystemCmd::LogOut) => ("log_out", None),
+        HotkeyAction::TypeClipboard => ("type_clipboard", None),
+    };
+    EffectiveHotkey {
+        keys: key_names(&rule.keys),
+        action,
+        command: command.map(|command| match &command.line {
+            CommandLine::Shell(line) => EffectiveCommand::Shell(line.clone()),
+            CommandLine::Argv(argv) => EffectiveCommand::Argv(argv.clone()),
+        }),
+        cwd: command
+            .and_then(|command| command.cwd.as_ref())
+            .map(|cwd| cwd.display().to_string()),
+        env: command
+            .map(|command| command.env.iter().cloned().collect())
+            .unwrap_or_default(),
+        apps: rule.apps.clone(),
+        when: rule.when.as_ref().map(when),
+        timing: timing(&rule.timing),
+    }
+}
+
+fn disable(rule: &DisableRule) -> EffectiveDisable {
+    EffectiveDisable {
+        while_held: keynames::name(rule.while_held),
+        keys: key_names(&rule.keys),
+    }
+}
+
+fn hotstring(rule: &HotstringRule) -> EffectiveHotstring {
+    EffectiveHotstring {
+        trigger: rule.trigger.clone(),
+        replacement: rule.replacement.clone(),
+        apps: rule.apps.clone(),
+    }
+}
+
+fn passthrough(rule: &PassthroughRule) -> EffectivePassthrough {
+    EffectivePassthrough {
+        apps: rule.apps.clone(),
+        when: rule.when.as_ref().map(when),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_str;
+
+    const ALIASED: &str = r#"
+        [timing]
+        tap_ms = 150
+
+        [[remap]]
+        from = "CAPSLOCK"
+        to   = "control"
+
+        [[remap]]
+        from = "f13"
+        to   = "Cmd+Option+1"
+        when = { app = "^org\\.mozilla\\." }
+        timing = { tap_ms = 300 }
+
+        [[remap]]
+        from = "F14"
+        to   = { down = "return" }
+
+        [[hotkey]]
+        keys   = ["Super", "l"]
+        action = "lock_screen"
+    "#;
+
+    #[test]
+    fn aliases_and_defaults_are_resolved() {
+        let dumped = parse_str(ALIASED)
+            .unwrap()
+            .dump_effective_config()
+            .to_toml();
+        let expected = r#"[timing]
+tap_ms = 150
+chord_window_ms = 50
+sequence_step_ms = 1000
+oneshot_ms = 1000
+
+[[remap]]
+from = "CapsLock"
+to = "Ctrl"
+
+[remap.timing]
+tap_ms = 150
+chord_window_ms = 50
+sequence_step_ms = 1000
+oneshot_ms = 1000
+
+[[remap]]
+from = "F13"
+to = "Meta+Alt+1"
+
+[remap.when]
+app = '^org\.mozilla\.'
+ignore_case = false
+
+[remap.timing]
+tap_ms = 300
+chord_window_ms = 50
+sequence_step_ms = 1000
+oneshot_ms = 1000
+
+[[remap]]
+from = "F14"
+
+[remap.to]
+down = "Enter"
+
+[remap.timing]
+tap_ms = 150
+chord_window_ms = 50
+sequence_step_ms = 1000
+oneshot_ms = 1000
+
+[[hotkey]]
+keys = ["Meta", "L"]
+action = "lock_screen"
+
+[hotkey.timing]
+tap_ms = 150
+chord_window_ms = 50
+sequence_step_ms = 1000
+oneshot_ms = 1000
+"#;
+        assert_eq!(dumped, expected);
+    }
+
+    /// The dump is itself a config that loads to the same rules.
+    #[test]
+    fn dump_reads_back_to_the_same_config() {
+        let config = parse_str(ALIASED).unwrap();
+        let reparsed = parse_str(&config.dump_effective_config().to_toml()).unwrap();
+        assert_eq!(reparsed, config);
+    }
+
+    #[test]
+    fn sides_are_kept_for_left_side_rules() {
+        let config = parse_str(
+            r#"
+            [capture]
+            sides = "both"
+
+            [[remap]]
+            from = "LCtrl"
+            to   = "Escape"
+            "#,
+        )
+        .unwrap();
+        let dumped = config.dump_effective_config().to_toml();
+        assert!(
+            dumped.starts_with("[capture]\nsides = \"both\"\n"),
+            "{dumped}"
+        );
+        assert_eq!(parse_str(&dumped).unwrap(), config);
+    }
+
+    const MATCHING: &str = r#"
+        [capture]
+        match     = "position"
+        modifiers = "physical"
+
+        [[remap]]
+        from = "Q"
+        to   = "Escape"
+    "#;
+
+    #[test]
+    fn match_and_modifier_modes_are_kept() {
+        let config = parse_str(MATCHING).unwrap();
+        let dumped = config.dump_effective_config().to_toml();
+        assert!(
+            dumped.starts_with("[capture]\nmatch = \"position\"\nmodifiers = \"physical\"\n\n"),
+            "{dumped}"
+        );
+        assert_eq!(parse_str(&dumped).unwrap(), config);
+
+        let defaults = parse_str("[[remap]]\nfrom = \"Q\"\nto = \"Escape\"").unwrap();
+        assert!(!defaults
+            .dump_effective_config()
+            .to_toml()
+            .contains("[capture]"));
+    }
+
+    #[test]
+    fn json_dump_carries_the_capture_settings() {
+        let config = parse_str(MATCHING).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&config.dump_effective_config().to_json()).unwrap();
+        assert_eq!(
+            json["capture"],
+            serde_json::json!({ "match": "position", "modifiers": "physical" })
+        );
+    }
+
+    #[test]
+    fn exec_commands_read_back_in_their_form() {
+        let config = parse_str(
+            r#"
+            [[hotkey]]
+            keys    = ["F9"]
+            action  = "exec"
+            command = "notify-send 'hi there'"
+
+            [[hotkey]]
+            keys    = ["F10"]
+            action  = "type_output"
+            command = ["date", "+%F %T"]
+            cwd     = "/srv"
+            env     = { TZ = "UTC" }
+            "#,
+        )
+        .unwrap();
+        let dumped = config.dump_effective_config().to_toml();
+        assert!(
+            dumped.contains(r#"command = ["date", "+%F %T"]"#),
+            "{dumped}"
+        );
+        assert_eq!(parse_str(&dumped).unwrap(), config);
+    }
+}
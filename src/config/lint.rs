@@ -0,0 +1,455 @@
+//! Static analysis over a validated config: finds rules that can never fire.
+//!
+//! The checks mirror the rule engine's precedence:
+//!   - a `toggle_pause` chord is matched before every other rule,
+//!   - hotkeys are matched before remaps,
+//!   - per-app rules are matched before global rules,
+//!   - within one scope, the first rule in file order wins.
+//!
+//! A per-app rule overriding a global one is the intended way to specialise a
+//! rule for one application, so that pairing is never reported. Rules with a
+//! `when` condition are scoped too, but whether two patterns overlap cannot be
+//! decided here, so they are only reported as shadowed by rules that win in
+//! every window.
+//!
+//! Lints are warnings: they are logged at startup and printed by
+//! `pcunifier check --lint`, but never stop the daemon.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{Config, HotkeyAction, HotkeyRule};
+use crate::platform::KeyCode;
+
+// ---------------------------------------------------------------------------
+// Public types
+// ---------------------------------------------------------------------------
+
+/// The config section a rule came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    Remap,
+    Hotkey,
+    Hotstring,
+}
+
+/// Where a rule sits in the config: its section and 0-based position among
+/// the entries of that section, in file order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleRef {
+    pub kind: RuleKind,
+    pub index: usize,
+}
+
+impl fmt::Display for RuleRef {
+    /// Renders as `[[remap]] #2`, counting from 1 as a reader would.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let section = match self.kind {
+            RuleKind::Remap => "remap",
+            RuleKind::Hotkey => "hotkey",
+            RuleKind::Hotstring => "hotstring",
+        };
+        write!(f, "[[{section}]] #{}", self.index + 1)
+    }
+}
+
+/// One rule that is hidden, fully or in some apps, by a rule that takes
+/// precedence over it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    /// The rule that does not fire.
+    pub rule: RuleRef,
+    /// The rule that matches first.
+    pub shadowed_by: RuleRef,
+    /// `None` when `rule` can never fire. Otherwise the apps in which
+    /// `shadowed_by` wins; `rule` still fires everywhere else.
+    pub apps: Option<Vec<String>>,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.apps {
+            None => write!(
+                f,
+                "{} is unreachable: {} always matches first",
+                self.rule, self.shadowed_by
+            ),
+            Some(apps) => write!(
+                f,
+                "{} conflicts with {} in {}: {} matches first there",
+                self.rule,
+                self.shadowed_by,
+                apps.join(", "),
+                self.shadowed_by
+            ),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+/// Report every rule in `config` that another rule shadows, in file order.
+pub fn lint(config: &Config) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    lint_hotkeys(&config.hotkeys, &mut warnings);
+    lint_remaps(config, &mut warnings);
+    warnings
+}
+
+fn hotkey_ref(index: usize) -> RuleRef {
+    RuleRef {
+        kind: RuleKind::Hotkey,
+        index,
+    }
+}
+
+fn remap_ref(index: usize) -> RuleRef {
+    RuleRef {
+        kind: RuleKind::Remap,
+        index,
+    }
+}
+
+fn key_set(rule: &HotkeyRule) -> HashSet<KeyCode> {
+    rule.keys.iter().copied().collect()
+}
+
+fn is_pause(rule: &HotkeyRule) -> bool {
+    matches!(rule.action, HotkeyAction::SetPaused(_))
+}
+
+/// A hotkey fires once all of its keys are held, so any chord that contains
+/// an earlier chord's keys is always preceded by it.
+fn lint_hotkeys(hotkeys: &[HotkeyRule], out: &mut Vec<LintWarning>) {
+    for (j, later) in hotkeys.iter().enumerate() {
+        let later_keys = key_set(later);
+        for (i, earlier) in hotkeys.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            let earlier_keys = key_set(earlier);
+            if !earlier_keys.is_subset(&later_keys) {
+                continue;
+            }
+            let apps = match (is_pause(earlier), is_pause(later)) {
+                // Pause chords are matched ahead of every rule, in any scope.
+                // Two pause chords only make each other redundant when equal.
+                (true, true) if i < j && earlier_keys == later_keys => Some(None),
+                (true, true) => None,
+                (true, false) => Some(None),
+                (false, true) => None,
+                (false, false) if i < j && earlier.when.is_none() && later.when.is_none() => {
+                    same_scope(earlier.apps.as_deref(), later.apps.as_deref())
+                }
+                (false, false) => None,
+            };
+            if let Some(apps) = apps {
+                out.push(LintWarning {
+                    rule: hotkey_ref(j),
+                    shadowed_by: hotkey_ref(i),
+                    apps,
+                });
+                break;
+            }
+        }
+    }
+}
+
+/// Remaps are shadowed by an earlier remap of the same key in the same
+/// scope, and by a single-key hotkey on that key, which claims its Down.
+fn lint_remaps(config: &Config, out: &mut Vec<LintWarning>) {
+    for (j, later) in config.remaps.iter().enumerate() {
+        let by_hotkey = config.hotkeys.iter().enumerate().find_map(|(i, h)| {
+            if h.keys.len() != 1 || h.keys[0] != later.from {
+                return None;
+            }
+            // Any global hotkey beats any remap; a per-app hotkey only
+            // beats per-app remaps.
+            let apps = if is_pause(h) || (h.apps.is_none() && h.when.is_none()) {
+                Some(None)
+            } else if h.when.is_some() || later.when.is_some() {
+                None
+            } else {
+                same_scope(h.apps.as_deref(), later.apps.as_deref())
+            };
+            apps.map(|apps| (hotkey_ref(i), apps))
+        });
+        let by_remap = || {
+            config.remaps[..j]
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| r.from == later.from && r.when.is_none() && later.when.is_none())
+                .find_map(|(i, r)| {
+                    same_scope(r.apps.as_deref(), later.apps.as_deref())
+                        .map(|apps| (remap_ref(i), apps))
+                })
+        };
+        if let Some((shadowed_by, apps)) = by_hotkey.or_else(by_remap) {
+            out.push(LintWarning {
+                rule: remap_ref(j),
+                shadowed_by,
+                apps,
+            });
+        }
+    }
+}
+
+/// How far an earlier rule hides a later one of equal precedence.
+///
+/// Returns `Some(None)` when the later rule is hidden everywhere,
+/// `Some(Some(apps))` when only in `apps`, and `None` when the two never
+/// compete. Global and per-app rules never compete here, since per-app rules
+/// are always tried first.
+fn same_scope(earlier: Option<&[String]>, later: Option<&[String]>) -> Option<Option<Vec<String>>> {
+    match (earlier, later) {
+        (None, None) => Some(None),
+        (Some(earlier), Some(later)) => {
+            let shared: Vec<String> = later
+                .iter()
+                .filter(|a| earlier.contains(a))
+                .cloned()
+                .collect();
+            if shared.is_empty() {
+                None
+            } else if shared.len() == later.len() {
+                Some(None)
+            } else {
+                Some(Some(shared))
+            }
+        }
+        _ => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_str;
+
+    fn lint_toml(toml: &str) -> Vec<LintWarning> {
+        lint(&parse_str(toml).expect("config should parse"))
+    }
+
+    #[test]
+    fn second_global_remap_of_same_key_is_unreachable() {
+        let warnings = lint_toml(
+            r#"
+            [[remap]]
+            from = "A"
+            to   = "B"
+
+            [[remap]]
+            from = "A"
+            to   = "C"
+        "#,
+        );
+        assert_eq!(
+            warnings,
+            vec![LintWarning {
+                rule: remap_ref(1),
+                shadowed_by: remap_ref(0),
+                apps: None,
+            }]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "[[remap]] #2 is unreachable: [[remap]] #1 always matches first"
+        );
+    }
+
+    #[test]
+    fn non_conflicting_rules_produce_no_warnings() {
+        let warnings = lint_toml(
+            r#"
+            [[remap]]
+            from = "CapsLock"
+            to   = "Escape"
+
+            [[remap]]
+            from = "Meta"
+            to   = "Ctrl"
+            apps = ["firefox"]
+
+            [[remap]]
+            from = "Meta"
+            to   = "Alt"
+
+            [[remap]]
+            from = "Meta"
+            to   = "Ctrl"
+            apps = ["kitty"]
+
+            [[hotkey]]
+            keys    = ["Ctrl", "Alt", "T"]
+            action  = "exec"
+            command = "kitty"
+
+            [[hotkey]]
+            keys    = ["Ctrl", "T"]
+            action  = "exec"
+            command = "foot"
+        "#,
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+
+    #[test]
+    fn partially_overlapping_apps_report_only_shared_apps() {
+        let warnings = lint_toml(
+            r#"
+            [[remap]]
+            from = "Meta"
+            to   = "Ctrl"
+            apps = ["firefox", "chromium"]
+
+            [[remap]]
+            from = "Meta"
+            to   = "Alt"
+            apps = ["chromium", "kitty"]
+        "#,
+        );
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].apps, Some(vec!["chromium".to_owned()]));
+        assert_eq!(
+            warnings[0].to_string(),
+            "[[remap]] #2 conflicts with [[remap]] #1 in chromium: [[remap]] #1 matches first there"
+        );
+    }
+
+    #[test]
+    fn earlier_hotkey_subset_shadows_later_chord() {
+        let warnings = lint_toml(
+            r#"
+            [[hotkey]]
+            keys    = ["Ctrl", "T"]
+            action  = "exec"
+            command = "foot"
+
+            [[hotkey]]
+            keys    = ["Ctrl", "Shift", "T"]
+            action  = "exec"
+            command = "kitty"
+        "#,
+        );
+        assert_eq!(
+            warnings,
+            vec![LintWarning {
+                rule: hotkey_ref(1),
+                shadowed_by: hotkey_ref(0),
+                apps: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn pause_chord_shadows_hotkeys_regardless_of_order_and_apps() {
+        let warnings = lint_toml(
+            r#"
+            [[hotkey]]
+            keys    = ["Ctrl", "Alt", "Pause"]
+            action  = "exec"
+            command = "kitty"
+            apps    = ["firefox"]
+
+            [[hotkey]]
+            keys   = ["Ctrl", "Alt", "Pause"]
+            action = "toggle_pause"
+        "#,
+        );
+        assert_eq!(
+            warnings,
+            vec![LintWarning {
+                rule: hotkey_ref(0),
+                shadowed_by: hotkey_ref(1),
+                apps: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn single_key_hotkey_shadows_remap_of_that_key() {
+        let warnings = lint_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = "Ctrl+Shift+T"
+            apps = ["firefox"]
+
+            [[hotkey]]
+            keys    = ["F13"]
+            action  = "exec"
+            command = "kitty"
+        "#,
+        );
+        assert_eq!(
+            warnings,
+            vec![LintWarning {
+                rule: remap_ref(0),
+                shadowed_by: hotkey_ref(0),
+                apps: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn when_conditions_are_not_compared() {
+        let warnings = lint_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = "F14"
+            when = { title = "YouTube" }
+
+            [[remap]]
+            from = "F13"
+            to   = "F15"
+            when = { title = "YouTube" }
+
+            [[hotkey]]
+            keys    = ["F13"]
+            action  = "exec"
+            command = "kitty"
+        "#,
+        );
+        // Only the global hotkey, which wins in every window, is reported.
+        assert_eq!(
+            warnings,
+            vec![
+                LintWarning {
+                    rule: remap_ref(0),
+                    shadowed_by: hotkey_ref(0),
+                    apps: None,
+                },
+                LintWarning {
+                    rule: remap_ref(1),
+                    shadowed_by: hotkey_ref(0),
+                    apps: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn per_app_hotkey_does_not_shadow_global_remap() {
+        let warnings = lint_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = "F14"
+
+            [[hotkey]]
+            keys    = ["F13"]
+            action  = "exec"
+            command = "kitty"
+            apps    = ["firefox"]
+        "#,
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+}
@@ -11,12 +11,31 @@
 //!   - `parse_str(s)`           -- parse from a string (used in tests)
 //!   - `load(path)`             -- read and validate from disk
 //!   - `default_config_path()`  -- OS-conventional config file location
+//!   - `lint(config)`           -- find rules shadowed by other rules
+//!   - `config.dump_effective_config()` -- the resolved rules, as config
 
+mod condition;
+mod dump;
+mod lint;
+mod timing;
+
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use serde::Deserialize;
 
-use crate::platform::KeyCode;
+use crate::logging::{self, LogFilter};
+use crate::platform::keynames::{self, ParseKeyError};
+use crate::platform::{
+    CommandLine, ExecCommand, KeyCode, MouseButton, ScancodeInjection, StepFailure, SystemCmd,
+    WindowContext, WindowTarget,
+};
+
+pub use condition::WindowCondition;
+pub use lint::{lint, RuleKind, RuleRef};
+use timing::RawTiming;
+pub use timing::Timing;
 
 // ---------------------------------------------------------------------------
 // Public error type
@@ -41,17 +60,22 @@ pub enum ConfigError {
     Parse(#[from] toml::de::Error),
 
     /// A key name string is not recognized.
-    #[error("unknown key name '{0}' -- see the config schema for valid key names")]
-    UnknownKey(String),
+    #[error("{0} -- see the config schema for valid key names")]
+    UnknownKey(ParseKeyError),
 
     /// A hotkey `action` value is not recognized.
-    #[error("unknown hotkey action '{0}' (valid actions: exec)")]
+    #[error("unknown hotkey action '{0}' (valid actions: exec, type_output, toggle_pause, pause, resume, lock_screen, sleep, log_out, type_clipboard)")]
     UnknownAction(String),
 
-    /// A `[[hotkey]]` with `action = "exec"` is missing the `command` field.
-    #[error("hotkey with action 'exec' requires a 'command' field")]
+    /// A `[[hotkey]]` with `action = "exec"` or `"type_output"` is missing
+    /// the `command` field.
+    #[error("hotkey with action 'exec' or 'type_output' requires a 'command' field")]
     MissingCommand,
 
+    /// A hotkey `command` written as a list is empty or names no program.
+    #[error("hotkey command list must start with the program to run")]
+    EmptyArgv,
+
     /// An `apps` array is present but empty. Provide at least one identifier
     /// or remove the field for a global rule.
     #[error("apps field must contain at least one application identifier if present")]
@@ -60,26 +84,320 @@ pub enum ConfigError {
     /// A `[[hotkey]]` with an empty `keys` array is invalid.
     #[error("hotkey keys field must contain at least one key")]
     EmptyKeys,
+
+    /// A `[[disable]]` with an empty `keys` array disables nothing.
+    #[error("disable keys field must contain at least one key")]
+    EmptyDisable,
+
+    /// A `[[passthrough]]` has neither `apps` nor `when`, so it would match
+    /// every window.
+    #[error("passthrough entry must contain 'apps', 'when', or both")]
+    EmptyPassthrough,
+
+    /// A key combination such as `Ctrl+Shift+T` is malformed: every key but
+    /// the last must be a modifier, and at least two keys are required.
+    #[error("invalid key combination '{0}' -- expected modifiers followed by one key, e.g. Ctrl+Shift+T")]
+    InvalidCombo(String),
+
+    /// A `{ char = "..." }` remap target is not exactly one character.
+    #[error("char target '{0}' must be exactly one character")]
+    InvalidChar(String),
+
+    /// A `{ with = [...] }` remap target is empty or lists a key that is not
+    /// a modifier.
+    #[error("with target '{0}' must list one or more modifiers, e.g. [\"Ctrl\"]")]
+    InvalidWith(String),
+
+    /// A `{ down, up }` remap target has neither side, or is combined with
+    /// `repeat`.
+    #[error("remap of '{0}' with a down/up target needs 'down', 'up', or both, and no 'repeat'")]
+    InvalidSplit(String),
+
+    /// A `{ steps = [...] }` remap target lists no steps, or is combined
+    /// with `repeat`.
+    #[error("remap of '{0}' with a steps target needs one or more steps, and no 'repeat'")]
+    InvalidSteps(String),
+
+    /// A `{ delay_ms = ... }` step is above `MAX_STEP_DELAY_MS`.
+    #[error("step delay {0}ms is too long (at most {MAX_STEP_DELAY_MS}ms)")]
+    StepDelayTooLong(u64),
+
+    /// An `[executor]` `backend` value is not recognized on any platform.
+    #[error("unknown executor backend '{0}' (valid backends: portal, uinput, virtual_keyboard, xtest, sendinput, cgevent)")]
+    UnknownBackend(String),
+
+    /// A `[capture]` `backend` value is not recognized on any platform.
+    #[error("unknown capture backend '{0}' (valid backends: evdev, event_tap, hook, raw_input)")]
+    UnknownCaptureBackend(String),
+
+    /// `[executor] headless = true` is combined with a backend other than
+    /// uinput, the only one that works without a compositor.
+    #[error("headless mode injects through uinput, but backend '{0}' is pinned")]
+    HeadlessBackend(String),
+
+    /// A `[numpad]` `policy` value is not recognized.
+    #[error("unknown numpad policy '{0}' (valid policies: toggle_numlock, top_row)")]
+    UnknownNumpadPolicy(String),
+
+    /// A `[capture]` `match` value is not recognized.
+    #[error("unknown capture match mode '{0}' (valid modes: key, position)")]
+    UnknownKeyMatch(String),
+
+    /// An `[executor]` `injection` value is not recognized.
+    #[error("unknown executor injection mode '{0}' (valid modes: virtual_key, scancode)")]
+    UnknownInjection(String),
+
+    /// A `{ button = "..." }` remap target names no mouse button.
+    #[error("unknown mouse button '{0}' (valid buttons: left, right, middle, back, forward)")]
+    UnknownButton(String),
+
+    /// A steps target's `on_error` value is not recognized.
+    #[error("unknown on_error value '{0}' (valid values: abort, continue)")]
+    UnknownStepFailure(String),
+
+    /// A `[capture]` `modifiers` value is not recognized.
+    #[error("unknown modifier match mode '{0}' (valid modes: logical, physical)")]
+    UnknownModifierMatch(String),
+
+    /// A `[capture]` `sides` value is not recognized.
+    #[error("unknown modifier sides mode '{0}' (valid modes: unified, right, both)")]
+    UnknownSideMatch(String),
+
+    /// A rule waits for a modifier side that `[capture] sides` folds away
+    /// before matching, so it could never fire.
+    #[error("{0:?} is never matched under this [capture] sides mode; set sides = \"both\"")]
+    SideNeverMatched(KeyCode),
+
+    /// A `[[remap]]` `repeat` is above `MAX_REPEAT`.
+    #[error("remap repeat count {0} is too large (at most {MAX_REPEAT})")]
+    RepeatTooLarge(u32),
+
+    /// A `[[remap]]` `repeat_every_ms` is outside `MIN_REPEAT_EVERY_MS` to
+    /// `MAX_REPEAT_EVERY_MS`.
+    #[error("remap repeat_every_ms = {0} is out of range ({MIN_REPEAT_EVERY_MS} to {MAX_REPEAT_EVERY_MS} ms)")]
+    RepeatEveryOutOfRange(u64),
+
+    /// A `[[remap]]` with `repeat_every_ms` also has `repeat`, or a down/up
+    /// or steps target.
+    #[error(
+        "remap of '{0}' with 'repeat_every_ms' cannot have 'repeat' or a down/up or steps target"
+    )]
+    InvalidRepeatEvery(String),
+
+    /// A `when` table has neither `title` nor `app`.
+    #[error("when condition must contain 'title', 'app', or both")]
+    EmptyCondition,
+
+    /// A `when` pattern is not a valid regular expression.
+    #[error("invalid {field} pattern in when condition: {source}")]
+    InvalidPattern {
+        field: &'static str,
+        #[source]
+        source: regex::Error,
+    },
+
+    /// A `[log]` level is not recognized.
+    #[error("unknown log level '{0}' (valid levels: off, error, warn, info, debug, trace)")]
+    UnknownLogLevel(String),
+
+    /// A `[capture]` `dedup_window_us` is above `MAX_DEDUP_WINDOW_US`.
+    #[error("dedup window {0}us is too large (at most {MAX_DEDUP_WINDOW_US}us)")]
+    DedupWindowTooLarge(u64),
+
+    /// A `[capture]` `device_wait_ms` is above `MAX_DEVICE_WAIT_MS`.
+    #[error("device wait {0}ms is too long (at most {MAX_DEVICE_WAIT_MS}ms)")]
+    DeviceWaitTooLong(u64),
+
+    /// A `[timing]` or rule `timing` value is zero or above `MAX_TIMING_MS`.
+    #[error("timing {field} = {ms} is out of range (1 to {max} ms)", max = timing::MAX_TIMING_MS)]
+    TimingOutOfRange { field: &'static str, ms: u64 },
+
+    /// An `[executor]` `target` table has neither `title` nor `class`.
+    #[error("executor target must contain 'title', 'class', or both")]
+    EmptyTarget,
+
+    /// `[macos] capslock_to` names a key other than F13-F20.
+    #[error("macos capslock_to must be a function key from F13 to F20, not '{0}'")]
+    CapsLockTarget(String),
+
+    /// An `[executor]` `type_delay_ms` is above `MAX_TYPE_DELAY_MS`.
+    #[error("type delay {0}ms is too long (at most {MAX_TYPE_DELAY_MS}ms)")]
+    TypeDelayTooLong(u64),
+
+    /// An `[exec]` or hotkey `env` name is empty or holds `=` or a NUL.
+    #[error("invalid exec environment variable name '{0}'")]
+    ExecEnvName(String),
+
+    /// An `[exec]` `capture_timeout_ms` is zero or above
+    /// `MAX_CAPTURE_TIMEOUT_MS`.
+    #[error("exec capture_timeout_ms = {0} is out of range (1 to {MAX_CAPTURE_TIMEOUT_MS} ms)")]
+    CaptureTimeoutOutOfRange(u64),
+
+    /// A `{ paste = "..." }` remap target has no text.
+    #[error("remap of '{0}' pastes no text")]
+    EmptyPaste(String),
+
+    /// A `[clipboard]` `restore_after_ms` is above `MAX_CLIPBOARD_RESTORE_MS`.
+    #[error("clipboard restore delay {0}ms is too long (at most {MAX_CLIPBOARD_RESTORE_MS}ms)")]
+    ClipboardRestoreTooLong(u64),
 }
 
 // ---------------------------------------------------------------------------
 // Public typed output structs
 // ---------------------------------------------------------------------------
 
+/// What a `[[remap]]` rule emits in place of the source key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemapTarget {
+    /// A single key; Down and Up mirror the source key's state.
+    Key(KeyCode),
+    /// A key combination written as `Ctrl+Shift+T`, tapped on the source
+    /// key's Down. The last key is the main key; the rest are modifiers.
+    Combo(Vec<KeyCode>),
+    /// A single Unicode character written as `{ char = "..." }`, typed on the
+    /// source key's Down.
+    Char(char),
+    /// The source key itself, pressed with these modifiers held, written as
+    /// `{ with = ["Ctrl"] }`. Down and Up mirror the source key's state.
+    With(Vec<KeyCode>),
+    /// Different keys for the source key's Down and Up, written as
+    /// `{ down = "F13", up = "Ctrl+M" }`. Each side is a key or combination
+    /// tapped in full when the source key goes that way; an empty side emits
+    /// nothing. Auto-repeat of the source key emits nothing either.
+    Split {
+        down: Vec<KeyCode>,
+        up: Vec<KeyCode>,
+    },
+    /// Keys and combinations tapped one after another on the source key's
+    /// Down, with waits between them, written as
+    /// `{ steps = ["Ctrl+C", { delay_ms = 50 }, "Ctrl+V"] }`. With
+    /// `on_error = "continue"`, a step that fails to inject does not stop
+    /// the ones after it.
+    Steps {
+        steps: Vec<RemapStep>,
+        on_error: StepFailure,
+    },
+    /// A mouse button written as `{ button = "left" }`; Down and Up mirror
+    /// the source key's state, so holding the key holds the button.
+    Button(MouseButton),
+    /// Text pasted through the clipboard on the source key's Down, written
+    /// as `{ paste = "..." }`.
+    Paste(String),
+}
+
+/// One step of a `{ steps = [...] }` remap target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemapStep {
+    /// A key or combination, tapped in full.
+    Keys(Vec<KeyCode>),
+    /// A wait of this many milliseconds, at most `MAX_STEP_DELAY_MS`.
+    Delay(u64),
+}
+
 /// A single `[[remap]]` rule.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RemapRule {
     pub from: KeyCode,
-    pub to: KeyCode,
+    pub to: RemapTarget,
     /// `None` means the rule is global (applies to all applications).
     pub apps: Option<Vec<String>>,
+    /// `when` title / app id patterns. Scopes the rule like `apps` does.
+    pub when: Option<WindowCondition>,
+    /// `repeat = N`: tap the target N times on the source key's Down instead
+    /// of mirroring its state. At most `MAX_REPEAT`.
+    pub repeat: Option<u32>,
+    /// `repeat_every_ms = N`: tap the target on the source key's Down, then
+    /// every N milliseconds for as long as it is held.
+    pub repeat_every_ms: Option<u64>,
+    /// `[timing]` defaults with this rule's `timing` overrides applied.
+    pub timing: Timing,
+}
+
+/// The largest `repeat` a `[[remap]]` accepts.
+pub const MAX_REPEAT: u32 = 100;
+
+/// The range of `repeat_every_ms` a `[[remap]]` accepts. The engine's timer
+/// runs every few milliseconds, so shorter intervals would not be kept.
+pub const MIN_REPEAT_EVERY_MS: u64 = 10;
+pub const MAX_REPEAT_EVERY_MS: u64 = 10_000;
+
+/// The longest `delay_ms` step a remap target accepts.
+pub const MAX_STEP_DELAY_MS: u64 = 60_000;
+
+/// Default `[capture] dedup_window_us`.
+pub const DEFAULT_DEDUP_WINDOW_US: u64 = 1_000;
+
+/// The largest `[capture] dedup_window_us` accepted. Wider windows would start
+/// to swallow fast auto-repeat.
+pub const MAX_DEDUP_WINDOW_US: u64 = 10_000;
+
+/// `[executor] max_rate` when not set. Far above any typing or macro, so
+/// only a runaway reaches it.
+pub const DEFAULT_MAX_RATE: u32 = 1_000;
+
+/// The largest `[executor] type_delay_ms` accepted. The steps after typed
+/// text wait for all of it, so a longer pause would hold a rule's output up
+/// for seconds.
+pub const MAX_TYPE_DELAY_MS: u64 = 100;
+
+/// The largest `[clipboard] restore_after_ms` accepted. The old text is
+/// held by the clipboard worker until then.
+pub const MAX_CLIPBOARD_RESTORE_MS: u64 = 10_000;
+
+/// Default `[exec] min_interval_ms`. Enough to drop the runs a held
+/// hotkey's auto-repeat would start, short of a deliberate second press.
+pub const DEFAULT_EXEC_INTERVAL_MS: u64 = 250;
+
+/// Default `[exec] capture_timeout_ms`.
+pub const DEFAULT_CAPTURE_TIMEOUT_MS: u64 = 5_000;
+
+/// The longest `[exec] capture_timeout_ms` accepted.
+pub const MAX_CAPTURE_TIMEOUT_MS: u64 = 60_000;
+
+/// `[exec]`: how `exec` commands are started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecSettings {
+    /// Working directory of every command. `None` keeps the daemon's own.
+    pub cwd: Option<PathBuf>,
+    /// Variables set for every command, on top of the daemon's environment.
+    pub env: Vec<(String, String)>,
+    /// The least time between two starts of the same command; a start
+    /// sooner than that is dropped. Zero is no limit.
+    pub min_interval: Duration,
+    /// How long a command whose output is captured may run before it is
+    /// killed.
+    pub capture_timeout: Duration,
+}
+
+impl Default for ExecSettings {
+    fn default() -> Self {
+        Self {
+            cwd: None,
+            env: Vec::new(),
+            min_interval: Duration::from_millis(DEFAULT_EXEC_INTERVAL_MS),
+            capture_timeout: Duration::from_millis(DEFAULT_CAPTURE_TIMEOUT_MS),
+        }
+    }
 }
 
+/// The largest `[capture] device_wait_ms` accepted.
+pub const MAX_DEVICE_WAIT_MS: u64 = 60_000;
+
 /// The action performed by a `[[hotkey]]` rule.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HotkeyAction {
-    /// Spawn a shell command non-blocking.
-    Exec(String),
+    /// Spawn a command non-blocking.
+    Exec(ExecCommand),
+    /// Run a command and type what it prints.
+    TypeOutput(ExecCommand),
+    /// Pause remapping with `Some(true)`, resume it with `Some(false)`, or
+    /// flip it with `None`. Handled by the engine ahead of all other rules
+    /// so it keeps working while paused; `apps` is ignored.
+    SetPaused(Option<bool>),
+    /// Lock the screen, sleep, or log out, by whatever means the platform has.
+    System(SystemCmd),
+    /// Type the clipboard's text.
+    TypeClipboard,
 }
 
 /// A single `[[hotkey]]` rule.
@@ -89,6 +407,18 @@ pub struct HotkeyRule {
     pub action: HotkeyAction,
     /// `None` means the rule is global.
     pub apps: Option<Vec<String>>,
+    /// `when` title / app id patterns. Scopes the rule like `apps` does.
+    pub when: Option<WindowCondition>,
+    /// `[timing]` defaults with this rule's `timing` overrides applied.
+    pub timing: Timing,
+}
+
+/// A single `[[disable]]` rule: `keys` do nothing while `while_held` is
+/// held.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisableRule {
+    pub while_held: KeyCode,
+    pub keys: Vec<KeyCode>,
 }
 
 /// A single `[[hotstring]]` rule.
@@ -100,19 +430,163 @@ pub struct HotstringRule {
     pub apps: Option<Vec<String>>,
 }
 
+/// A single `[[passthrough]]` entry: while a window it matches is focused,
+/// the engine applies no rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassthroughRule {
+    /// `None` when only `when` is given.
+    pub apps: Option<Vec<String>>,
+    pub when: Option<WindowCondition>,
+}
+
+impl PassthroughRule {
+    /// Whether `window` is in the app list (if given) and satisfies `when`
+    /// (if given).
+    pub fn matches(&self, window: &WindowContext) -> bool {
+        let app_ok = self.apps.as_ref().map_or(true, |apps| {
+            window
+                .app_id
+                .as_deref()
+                .is_some_and(|id| apps.iter().any(|a| a == id))
+        });
+        app_ok && self.when.as_ref().map_or(true, |w| w.matches(window))
+    }
+}
+
 /// A single `[[script]]` entry.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ScriptEntry {
     pub path: PathBuf,
 }
 
+/// How the engine injects a numpad digit while NumLock is off.
+///
+/// With NumLock off most applications read Numpad8 as Up, Numpad4 as Left,
+/// and so on, so a rule that outputs a numpad digit needs one of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumpadPolicy {
+    /// Tap NumLock before and after the digit so it is read as a digit.
+    #[default]
+    ToggleNumLock,
+    /// Inject the matching top-row digit (`Numpad8` becomes `8`) instead.
+    TopRow,
+}
+
+/// What the key names in rules are matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyMatch {
+    /// The key the active layout produces, as the platform reports it.
+    #[default]
+    Key,
+    /// The physical position of the key, named after the key found there on
+    /// a US ANSI keyboard, whatever the active layout.
+    Position,
+}
+
+/// Which held keys hotkey chords are matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModifierMatch {
+    /// The keys as the engine's own remaps left them, so with CapsLock
+    /// remapped to Ctrl, holding CapsLock satisfies a `Ctrl` in a chord.
+    #[default]
+    Logical,
+    /// The keys as captured, before any remap.
+    Physical,
+}
+
+/// Which modifier sides rules see. Capture reports the side of every
+/// modifier; this folds sides away before rules are matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SideMatch {
+    /// Both sides become the unified modifier: `RightCtrl` is seen as `Ctrl`.
+    Unified,
+    /// The left side becomes the unified modifier and the right side is kept,
+    /// as capture reported modifiers before sides were told apart.
+    #[default]
+    Right,
+    /// Both sides are kept. `Ctrl` in a rule still matches either.
+    Both,
+}
+
+impl SideMatch {
+    /// `key` as rules see it under this mode.
+    pub fn normalize(self, key: KeyCode) -> KeyCode {
+        match self {
+            SideMatch::Unified => key.unify_side(),
+            SideMatch::Right if key.is_left_modifier() => key.unify_side(),
+            SideMatch::Right | SideMatch::Both => key,
+        }
+    }
+}
+
 /// The fully parsed and validated configuration.
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct Config {
     pub remaps: Vec<RemapRule>,
     pub hotkeys: Vec<HotkeyRule>,
+    pub disables: Vec<DisableRule>,
     pub hotstrings: Vec<HotstringRule>,
+    /// `[[passthrough]]`: windows in which no rule applies.
+    pub passthroughs: Vec<PassthroughRule>,
     pub scripts: Vec<ScriptEntry>,
+    pub numpad: NumpadPolicy,
+    /// Injection backend pinned by `[executor] backend`. `None` lets the
+    /// platform pick (on Linux, by walking its fallback chain).
+    pub executor_backend: Option<String>,
+    /// `[executor] headless`: no compositor and no window context. Implies
+    /// the uinput backend.
+    pub headless: bool,
+    /// `[executor] target`: inject into this window instead of the focused
+    /// one. Only the Windows executor supports it.
+    pub executor_target: Option<WindowTarget>,
+    /// `[executor] injection` and `scancode_apps`: when keys are injected by
+    /// scan code. Only the Windows executor follows it.
+    pub scancode_injection: ScancodeInjection,
+    /// `[executor] max_rate`: the most actions sent on per second, beyond
+    /// which they are dropped; `0` is no limit. `None` when not set, which
+    /// means `DEFAULT_MAX_RATE`.
+    pub max_rate: Option<u32>,
+    /// `[executor] type_delay_ms`: the pause between two characters of
+    /// typed text, and between the copies of an `Action::Repeat`. Zero
+    /// types them back to back.
+    pub type_delay: Duration,
+    /// Log every captured key the engine does not re-emit, with the reason.
+    pub log_suppressed: bool,
+    /// `[safety] key`: when set, rules only apply while this key is held.
+    pub safety_key: Option<KeyCode>,
+    /// `[log]` levels, overall and per module.
+    pub log: LogFilter,
+    /// `[capture] dedup`: drop an event identical to the previous one for
+    /// its key when it arrives within this window. `None` when off.
+    pub dedup_window: Option<Duration>,
+    /// `[capture] match`: whether rules match keys or physical positions.
+    pub key_match: KeyMatch,
+    /// `[capture] modifiers`: whether chords see held keys before or after
+    /// remapping.
+    pub modifier_match: ModifierMatch,
+    /// `[capture] sides`: which modifier sides rules are matched against.
+    pub side_match: SideMatch,
+    /// `[capture] device_wait_ms`: how long startup waits for keyboards to
+    /// appear. `None` keeps the backend's default. Linux only.
+    pub device_wait: Option<Duration>,
+    /// Capture backend pinned by `[capture] backend`. `None` uses the
+    /// platform's default.
+    pub capture_backend: Option<String>,
+    /// `[timing]`: thresholds for rules that set no `timing` of their own.
+    pub timing: Timing,
+    /// `[macos] capslock_to`: map CapsLock to this key at the HID layer
+    /// while running. macOS only.
+    pub caps_lock_to: Option<KeyCode>,
+    /// `[macos] fkeys_as_system_keys`: capture reports the keys that share a
+    /// code with F13-F15 as PrintScreen, ScrollLock, and Pause. `None` when
+    /// not set, which behaves as `false`. macOS only.
+    pub fkeys_as_system_keys: Option<bool>,
+    /// `[exec]`: working directory, environment, and rate limit of `exec`
+    /// commands.
+    pub exec: ExecSettings,
+    /// `[clipboard] restore_after_ms`: how long after a paste to put back
+    /// what the clipboard held. `None` leaves the pasted text there.
+    pub clipboard_restore: Option<Duration>,
 }
 
 // ---------------------------------------------------------------------------
@@ -123,9 +597,78 @@ pub struct Config {
 #[serde(deny_unknown_fields)]
 struct RawRemap {
     from: String,
-    to: String,
+    to: RawRemapTarget,
     #[serde(default)]
     apps: Option<Vec<String>>,
+    repeat: Option<u32>,
+    repeat_every_ms: Option<u64>,
+    when: Option<RawWhen>,
+    timing: Option<RawTiming>,
+}
+
+/// A remap `to` value: a key name or combination string, or a table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawRemapTarget {
+    Name(String),
+    Char(RawCharTarget),
+    With(RawWithTarget),
+    Split(RawSplitTarget),
+    Steps(RawStepsTarget),
+    Button(RawButtonTarget),
+    Paste(RawPasteTarget),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCharTarget {
+    char: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawWithTarget {
+    with: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSplitTarget {
+    down: Option<String>,
+    up: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawButtonTarget {
+    button: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPasteTarget {
+    paste: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStepsTarget {
+    steps: Vec<RawStep>,
+    on_error: Option<String>,
+}
+
+/// A `steps` entry: a key name or combination string, or a delay table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawStep {
+    Keys(String),
+    Delay(RawDelayStep),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDelayStep {
+    delay_ms: u64,
 }
 
 #[derive(Deserialize)]
@@ -133,9 +676,45 @@ struct RawRemap {
 struct RawHotkey {
     keys: Vec<String>,
     action: String,
-    command: Option<String>,
+    command: Option<RawCommand>,
+    cwd: Option<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    apps: Option<Vec<String>>,
+    when: Option<RawWhen>,
+    timing: Option<RawTiming>,
+}
+
+/// A hotkey `command`: a shell line, or a program and its arguments.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawCommand {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDisable {
+    while_held: String,
+    keys: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawWhen {
+    title: Option<String>,
+    app: Option<String>,
     #[serde(default)]
+    ignore_case: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPassthrough {
     apps: Option<Vec<String>>,
+    when: Option<RawWhen>,
 }
 
 #[derive(Deserialize)]
@@ -153,6 +732,90 @@ struct RawScript {
     path: String,
 }
 
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDebug {
+    #[serde(default)]
+    log_suppressed: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawExecutor {
+    backend: Option<String>,
+    #[serde(default)]
+    headless: bool,
+    target: Option<RawTarget>,
+    injection: Option<String>,
+    scancode_apps: Option<Vec<String>>,
+    max_rate: Option<u32>,
+    type_delay_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTarget {
+    title: Option<String>,
+    class: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLog {
+    level: Option<String>,
+    #[serde(default)]
+    modules: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSafety {
+    key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCapture {
+    #[serde(default)]
+    dedup: bool,
+    dedup_window_us: Option<u64>,
+    #[serde(rename = "match")]
+    key_match: Option<String>,
+    modifiers: Option<String>,
+    sides: Option<String>,
+    device_wait_ms: Option<u64>,
+    backend: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawExec {
+    cwd: Option<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    min_interval_ms: Option<u64>,
+    capture_timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawClipboard {
+    restore_after_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMacos {
+    capslock_to: Option<String>,
+    fkeys_as_system_keys: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawNumpad {
+    policy: String,
+}
+
 #[derive(Deserialize, Default)]
 #[serde(deny_unknown_fields)]
 struct RawConfig {
@@ -161,9 +824,23 @@ struct RawConfig {
     #[serde(default)]
     hotkey: Vec<RawHotkey>,
     #[serde(default)]
+    disable: Vec<RawDisable>,
+    #[serde(default)]
     hotstring: Vec<RawHotstring>,
     #[serde(default)]
+    passthrough: Vec<RawPassthrough>,
+    #[serde(default)]
     script: Vec<RawScript>,
+    numpad: Option<RawNumpad>,
+    executor: Option<RawExecutor>,
+    debug: Option<RawDebug>,
+    safety: Option<RawSafety>,
+    log: Option<RawLog>,
+    capture: Option<RawCapture>,
+    timing: Option<RawTiming>,
+    macos: Option<RawMacos>,
+    exec: Option<RawExec>,
+    clipboard: Option<RawClipboard>,
 }
 
 // ---------------------------------------------------------------------------
@@ -206,11 +883,73 @@ pub fn parse_str(s: &str) -> Result<Config, ConfigError> {
 fn validate(raw: RawConfig) -> Result<Config, ConfigError> {
     let mut config = Config::default();
 
+    // Before the rules, which start from these values.
+    if let Some(t) = &raw.timing {
+        config.timing = Timing::default().with_overrides(t)?;
+    }
+    let rule_timing = |t: Option<RawTiming>| match t {
+        Some(t) => config.timing.with_overrides(&t),
+        None => Ok(config.timing),
+    };
+
     for r in raw.remap {
+        if let Some(ms) = r.repeat_every_ms {
+            if !(MIN_REPEAT_EVERY_MS..=MAX_REPEAT_EVERY_MS).contains(&ms) {
+                return Err(ConfigError::RepeatEveryOutOfRange(ms));
+            }
+            // `repeat` would nest one repetition in the other.
+            if r.repeat.is_some()
+                || matches!(r.to, RawRemapTarget::Split(_) | RawRemapTarget::Steps(_))
+            {
+                return Err(ConfigError::InvalidRepeatEvery(r.from));
+            }
+        }
         config.remaps.push(RemapRule {
             from: parse_key(&r.from)?,
-            to: parse_key(&r.to)?,
+            to: match r.to {
+                RawRemapTarget::Name(name) => parse_remap_target(&name)?,
+                RawRemapTarget::Char(t) => RemapTarget::Char(parse_char(&t.char)?),
+                RawRemapTarget::With(t) => RemapTarget::With(parse_with(&t.with)?),
+                RawRemapTarget::Split(t) => {
+                    if r.repeat.is_some() || (t.down.is_none() && t.up.is_none()) {
+                        return Err(ConfigError::InvalidSplit(r.from));
+                    }
+                    RemapTarget::Split {
+                        down: parse_split_side(t.down.as_deref())?,
+                        up: parse_split_side(t.up.as_deref())?,
+                    }
+                }
+                RawRemapTarget::Button(t) => RemapTarget::Button(parse_button(&t.button)?),
+                RawRemapTarget::Paste(t) => {
+                    if t.paste.is_empty() {
+                        return Err(ConfigError::EmptyPaste(r.from));
+                    }
+                    RemapTarget::Paste(t.paste)
+                }
+                RawRemapTarget::Steps(t) => {
+                    if r.repeat.is_some() || t.steps.is_empty() {
+                        return Err(ConfigError::InvalidSteps(r.from));
+                    }
+                    RemapTarget::Steps {
+                        steps: t.steps.iter().map(parse_step).collect::<Result<_, _>>()?,
+                        on_error: match t.on_error.as_deref() {
+                            None | Some("abort") => StepFailure::Abort,
+                            Some("continue") => StepFailure::Continue,
+                            Some(other) => {
+                                return Err(ConfigError::UnknownStepFailure(other.to_owned()))
+                            }
+                        },
+                    }
+                }
+            },
             apps: validate_apps(r.apps)?,
+            when: r.when.map(compile_when).transpose()?,
+            repeat: match r.repeat {
+                Some(n) if n > MAX_REPEAT => return Err(ConfigError::RepeatTooLarge(n)),
+                repeat => repeat,
+            },
+            repeat_every_ms: r.repeat_every_ms,
+            timing: rule_timing(r.timing)?,
         });
     }
 
@@ -224,13 +963,38 @@ fn validate(raw: RawConfig) -> Result<Config, ConfigError> {
             return Err(ConfigError::EmptyKeys);
         }
         let action = match h.action.as_str() {
-            "exec" => HotkeyAction::Exec(h.command.ok_or(ConfigError::MissingCommand)?),
+            "exec" => HotkeyAction::Exec(exec_command(h.command, h.cwd, h.env)?),
+            "type_output" => HotkeyAction::TypeOutput(exec_command(h.command, h.cwd, h.env)?),
+            "toggle_pause" => HotkeyAction::SetPaused(None),
+            "pause" => HotkeyAction::SetPaused(Some(true)),
+            "resume" => HotkeyAction::SetPaused(Some(false)),
+            "lock_screen" => HotkeyAction::System(SystemCmd::LockScreen),
+            "sleep" => HotkeyAction::System(SystemCmd::Sleep),
+            "log_out" => HotkeyAction::System(SystemCmd::LogOut),
+            "type_clipboard" => HotkeyAction::TypeClipboard,
             other => return Err(ConfigError::UnknownAction(other.to_owned())),
         };
         config.hotkeys.push(HotkeyRule {
             keys,
             action,
             apps: validate_apps(h.apps)?,
+            when: h.when.map(compile_when).transpose()?,
+            timing: rule_timing(h.timing)?,
+        });
+    }
+
+    for d in raw.disable {
+        let keys = d
+            .keys
+            .iter()
+            .map(|k| parse_key(k))
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            return Err(ConfigError::EmptyDisable);
+        }
+        config.disables.push(DisableRule {
+            while_held: parse_key(&d.while_held)?,
+            keys,
         });
     }
 
@@ -243,211 +1007,491 @@ fn validate(raw: RawConfig) -> Result<Config, ConfigError> {
         });
     }
 
+    for p in raw.passthrough {
+        if p.apps.is_none() && p.when.is_none() {
+            return Err(ConfigError::EmptyPassthrough);
+        }
+        config.passthroughs.push(PassthroughRule {
+            apps: validate_apps(p.apps)?,
+            when: p.when.map(compile_when).transpose()?,
+        });
+    }
+
     for s in raw.script {
         config.scripts.push(ScriptEntry {
             path: PathBuf::from(s.path),
         });
     }
 
-    Ok(config)
-}
+    if let Some(n) = raw.numpad {
+        config.numpad = match n.policy.as_str() {
+            "toggle_numlock" => NumpadPolicy::ToggleNumLock,
+            "top_row" => NumpadPolicy::TopRow,
+            other => return Err(ConfigError::UnknownNumpadPolicy(other.to_owned())),
+        };
+    }
 
-/// Validate an optional `apps` array. If present it must be non-empty.
-fn validate_apps(apps: Option<Vec<String>>) -> Result<Option<Vec<String>>, ConfigError> {
-    match apps {
-        Some(v) if v.is_empty() => Err(ConfigError::EmptyApps),
-        other => Ok(other),
+    if let Some(s) = raw.safety {
+        config.safety_key = Some(parse_key(&s.key)?);
     }
-}
 
-// ---------------------------------------------------------------------------
-// Key name resolution
-// ---------------------------------------------------------------------------
+    if let Some(l) = raw.log {
+        let default = match l.level {
+            Some(level) => parse_log_level(&level)?,
+            None => logging::DEFAULT_LEVEL,
+        };
+        let modules = l
+            .modules
+            .into_iter()
+            .map(|(module, level)| Ok((module, parse_log_level(&level)?)))
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        config.log = LogFilter::new(default, modules);
+    }
 
-/// Resolve a key name string to a `KeyCode`.
-///
-/// Matching is case-insensitive. Accepts canonical names, aliases from the
-/// config schema (Control, Option, Super, Return, etc.), punctuation symbols,
-/// and single-character letters/digits.
-fn parse_key(s: &str) -> Result<KeyCode, ConfigError> {
-    let lower = s.to_lowercase();
-    match lower.as_str() {
-        // Modifiers and aliases
-        "ctrl" | "control" => Ok(KeyCode::Ctrl),
-        "shift" => Ok(KeyCode::Shift),
-        "alt" | "option" => Ok(KeyCode::Alt),
-        "meta" | "super" | "win" | "cmd" | "command" => Ok(KeyCode::Meta),
-
-        // Letters
-        "a" => Ok(KeyCode::A),
-        "b" => Ok(KeyCode::B),
-        "c" => Ok(KeyCode::C),
-        "d" => Ok(KeyCode::D),
-        "e" => Ok(KeyCode::E),
-        "f" => Ok(KeyCode::F),
-        "g" => Ok(KeyCode::G),
-        "h" => Ok(KeyCode::H),
-        "i" => Ok(KeyCode::I),
-        "j" => Ok(KeyCode::J),
-        "k" => Ok(KeyCode::K),
-        "l" => Ok(KeyCode::L),
-        "m" => Ok(KeyCode::M),
-        "n" => Ok(KeyCode::N),
-        "o" => Ok(KeyCode::O),
-        "p" => Ok(KeyCode::P),
-        "q" => Ok(KeyCode::Q),
-        "r" => Ok(KeyCode::R),
-        "s" => Ok(KeyCode::S),
-        "t" => Ok(KeyCode::T),
-        "u" => Ok(KeyCode::U),
-        "v" => Ok(KeyCode::V),
-        "w" => Ok(KeyCode::W),
-        "x" => Ok(KeyCode::X),
-        "y" => Ok(KeyCode::Y),
-        "z" => Ok(KeyCode::Z),
-
-        // Digits
-        "0" => Ok(KeyCode::Key0),
-        "1" => Ok(KeyCode::Key1),
-        "2" => Ok(KeyCode::Key2),
-        "3" => Ok(KeyCode::Key3),
-        "4" => Ok(KeyCode::Key4),
-        "5" => Ok(KeyCode::Key5),
-        "6" => Ok(KeyCode::Key6),
-        "7" => Ok(KeyCode::Key7),
-        "8" => Ok(KeyCode::Key8),
-        "9" => Ok(KeyCode::Key9),
-
-        // Function keys
-        "f1" => Ok(KeyCode::F1),
-        "f2" => Ok(KeyCode::F2),
-        "f3" => Ok(KeyCode::F3),
-        "f4" => Ok(KeyCode::F4),
-        "f5" => Ok(KeyCode::F5),
-        "f6" => Ok(KeyCode::F6),
-        "f7" => Ok(KeyCode::F7),
-        "f8" => Ok(KeyCode::F8),
-        "f9" => Ok(KeyCode::F9),
-        "f10" => Ok(KeyCode::F10),
-        "f11" => Ok(KeyCode::F11),
-        "f12" => Ok(KeyCode::F12),
-        "f13" => Ok(KeyCode::F13),
-        "f14" => Ok(KeyCode::F14),
-        "f15" => Ok(KeyCode::F15),
-        "f16" => Ok(KeyCode::F16),
-        "f17" => Ok(KeyCode::F17),
-        "f18" => Ok(KeyCode::F18),
-        "f19" => Ok(KeyCode::F19),
-        "f20" => Ok(KeyCode::F20),
-        "f21" => Ok(KeyCode::F21),
-        "f22" => Ok(KeyCode::F22),
-        "f23" => Ok(KeyCode::F23),
-        "f24" => Ok(KeyCode::F24),
-
-        // Navigation and editing
-        "space" => Ok(KeyCode::Space),
-        "enter" | "return" => Ok(KeyCode::Enter),
-        "tab" => Ok(KeyCode::Tab),
-        "escape" | "esc" => Ok(KeyCode::Escape),
-        "backspace" => Ok(KeyCode::Backspace),
-        "delete" | "del" => Ok(KeyCode::Delete),
-        "insert" | "ins" => Ok(KeyCode::Insert),
-        "home" => Ok(KeyCode::Home),
-        "end" => Ok(KeyCode::End),
-        "pageup" | "pgup" => Ok(KeyCode::PageUp),
-        "pagedown" | "pgdn" | "pgdown" => Ok(KeyCode::PageDown),
-        "up" => Ok(KeyCode::Up),
-        "down" => Ok(KeyCode::Down),
-        "left" => Ok(KeyCode::Left),
-        "right" => Ok(KeyCode::Right),
-
-        // Lock and system keys
-        "capslock" => Ok(KeyCode::CapsLock),
-        "numlock" => Ok(KeyCode::NumLock),
-        "scrolllock" => Ok(KeyCode::ScrollLock),
-        "printscreen" | "prtsc" | "prtscn" => Ok(KeyCode::PrintScreen),
-        "pause" | "break" => Ok(KeyCode::Pause),
-
-        // Numeric keypad
-        "numpad0" => Ok(KeyCode::Numpad0),
-        "numpad1" => Ok(KeyCode::Numpad1),
-        "numpad2" => Ok(KeyCode::Numpad2),
-        "numpad3" => Ok(KeyCode::Numpad3),
-        "numpad4" => Ok(KeyCode::Numpad4),
-        "numpad5" => Ok(KeyCode::Numpad5),
-        "numpad6" => Ok(KeyCode::Numpad6),
-        "numpad7" => Ok(KeyCode::Numpad7),
-        "numpad8" => Ok(KeyCode::Numpad8),
-        "numpad9" => Ok(KeyCode::Numpad9),
-        "numpadadd" | "numpad+" => Ok(KeyCode::NumpadAdd),
-        "numpadsub" | "numpad-" => Ok(KeyCode::NumpadSub),
-        "numpadmul" | "numpad*" => Ok(KeyCode::NumpadMul),
-        "numpaddiv" | "numpad/" => Ok(KeyCode::NumpadDiv),
-        "numpadenter" => Ok(KeyCode::NumpadEnter),
-
-        // Punctuation -- accept both the symbol and a spelled-out name
-        "`" | "backtick" | "grave" => Ok(KeyCode::Backtick),
-        "-" | "minus" | "hyphen" | "dash" => Ok(KeyCode::Minus),
-        "=" | "equal" | "equals" => Ok(KeyCode::Equal),
-        "[" | "leftbracket" | "lbracket" => Ok(KeyCode::LeftBracket),
-        "]" | "rightbracket" | "rbracket" => Ok(KeyCode::RightBracket),
-        "\\" | "backslash" => Ok(KeyCode::Backslash),
-        ";" | "semicolon" => Ok(KeyCode::Semicolon),
-        "'" | "apostrophe" | "quote" => Ok(KeyCode::Apostrophe),
-        "," | "comma" => Ok(KeyCode::Comma),
-        "." | "period" | "dot" => Ok(KeyCode::Period),
-        "/" | "slash" => Ok(KeyCode::Slash),
-
-        _ => Err(ConfigError::UnknownKey(s.to_owned())),
+    if let Some(c) = raw.capture {
+        let window_us = c.dedup_window_us.unwrap_or(DEFAULT_DEDUP_WINDOW_US);
+        if window_us > MAX_DEDUP_WINDOW_US {
+            return Err(ConfigError::DedupWindowTooLarge(window_us));
+        }
+        config.dedup_window = c.dedup.then(|| Duration::from_micros(window_us));
+        config.key_match = match c.key_match.as_deref() {
+            None | Some("key") => KeyMatch::Key,
+            Some("position") => KeyMatch::Position,
+            Some(other) => return Err(ConfigError::UnknownKeyMatch(other.to_owned())),
+        };
+        config.modifier_match = match c.modifiers.as_deref() {
+            None | Some("logical") => ModifierMatch::Logical,
+            Some("physical") => ModifierMatch::Physical,
+            Some(other) => return Err(ConfigError::UnknownModifierMatch(other.to_owned())),
+        };
+        config.side_match = match c.sides.as_deref() {
+            None | Some("right") => SideMatch::Right,
+            Some("unified") => SideMatch::Unified,
+            Some("both") => SideMatch::Both,
+            Some(other) => return Err(ConfigError::UnknownSideMatch(other.to_owned())),
+        };
+        if let Some(ms) = c.device_wait_ms {
+            if ms > MAX_DEVICE_WAIT_MS {
+                return Err(ConfigError::DeviceWaitTooLong(ms));
+            }
+            config.device_wait = Some(Duration::from_millis(ms));
+        }
+        if let Some(backend) = &c.backend {
+            if !CAPTURE_BACKENDS.contains(&backend.as_str()) {
+                return Err(ConfigError::UnknownCaptureBackend(backend.clone()));
+            }
+        }
+        config.capture_backend = c.backend;
     }
-}
 
-// ---------------------------------------------------------------------------
-// Config directory (platform-specific, no third-party deps)
-// ---------------------------------------------------------------------------
+    if let Some(d) = raw.debug {
+        config.log_suppressed = d.log_suppressed;
+    }
 
-fn config_dir() -> PathBuf {
-    #[cfg(target_os = "linux")]
-    {
-        // Respect XDG_CONFIG_HOME; fall back to ~/.config per XDG spec.
-        if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
-            return PathBuf::from(xdg).join("pc-unifier");
+    if let Some(e) = raw.exec {
+        check_env_names(&e.env)?;
+        config.exec = ExecSettings {
+            cwd: e.cwd.map(PathBuf::from),
+            env: e.env.into_iter().collect(),
+            min_interval: Duration::from_millis(
+                e.min_interval_ms.unwrap_or(DEFAULT_EXEC_INTERVAL_MS),
+            ),
+            capture_timeout: Duration::from_millis(
+                match e.capture_timeout_ms.unwrap_or(DEFAULT_CAPTURE_TIMEOUT_MS) {
+                    ms @ 1..=MAX_CAPTURE_TIMEOUT_MS => ms,
+                    ms => return Err(ConfigError::CaptureTimeoutOutOfRange(ms)),
+                },
+            ),
+        };
+    }
+
+    if let Some(ms) = raw.clipboard.and_then(|c| c.restore_after_ms) {
+        if ms > MAX_CLIPBOARD_RESTORE_MS {
+            return Err(ConfigError::ClipboardRestoreTooLong(ms));
         }
-        home_dir().join(".config").join("pc-unifier")
+        config.clipboard_restore = Some(Duration::from_millis(ms));
     }
 
-    #[cfg(target_os = "macos")]
-    {
-        home_dir()
-            .join("Library")
-            .join("Application Support")
-            .join("pc-unifier")
+    let macos = raw.macos.unwrap_or(RawMacos {
+        capslock_to: None,
+        fkeys_as_system_keys: None,
+    });
+    config.fkeys_as_system_keys = macos.fkeys_as_system_keys;
+    if let Some(name) = macos.capslock_to {
+        let key = parse_key(&name)?;
+        let function_key = matches!(
+            key,
+            KeyCode::F13
+                | KeyCode::F14
+                | KeyCode::F15
+                | KeyCode::F16
+                | KeyCode::F17
+                | KeyCode::F18
+                | KeyCode::F19
+                | KeyCode::F20
+        );
+        if !function_key {
+            return Err(ConfigError::CapsLockTarget(name));
+        }
+        config.caps_lock_to = Some(key);
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        // APPDATA is always set on Windows; USERPROFILE is the fallback.
-        if let Ok(appdata) = std::env::var("APPDATA") {
-            return PathBuf::from(appdata).join("pc-unifier");
+    if let Some(e) = raw.executor {
+        if let Some(backend) = &e.backend {
+            if !EXECUTOR_BACKENDS.contains(&backend.as_str()) {
+                return Err(ConfigError::UnknownBackend(backend.clone()));
+            }
+        }
+        config.executor_backend = match (e.headless, e.backend) {
+            (true, Some(backend)) if backend != "uinput" => {
+                return Err(ConfigError::HeadlessBackend(backend))
+            }
+            (true, _) => Some("uinput".to_owned()),
+            (false, backend) => backend,
+        };
+        config.headless = e.headless;
+        if let Some(t) = e.target {
+            if t.title.is_none() && t.class.is_none() {
+                return Err(ConfigError::EmptyTarget);
+            }
+            config.executor_target = Some(WindowTarget {
+                title: t.title,
+                class: t.class,
+            });
+        }
+        config.scancode_injection = match (e.injection.as_deref(), validate_apps(e.scancode_apps)?)
+        {
+            (Some("scancode"), _) => ScancodeInjection::Always,
+            (None | Some("virtual_key"), Some(apps)) => ScancodeInjection::Apps(apps),
+            (None | Some("virtual_key"), None) => ScancodeInjection::Off,
+            (Some(other), _) => return Err(ConfigError::UnknownInjection(other.to_owned())),
+        };
+        config.max_rate = e.max_rate;
+        if let Some(ms) = e.type_delay_ms {
+            if ms > MAX_TYPE_DELAY_MS {
+                return Err(ConfigError::TypeDelayTooLong(ms));
+            }
+            config.type_delay = Duration::from_millis(ms);
         }
-        home_dir()
-            .join("AppData")
-            .join("Roaming")
-            .join("pc-unifier")
     }
 
-    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    if let Some(key) = config
+        .triggers()
+        .find(|&key| config.side_match.normalize(key) != key)
     {
-        // Unsupported OS: use fixed absolute path so config location does not depend on CWD.
-        PathBuf::from("/tmp").join("pc-unifier")
+        return Err(ConfigError::SideNeverMatched(key));
     }
+
+    Ok(config)
 }
 
-fn home_dir() -> PathBuf {
-    #[cfg(not(target_os = "windows"))]
-    {
-        std::env::var("HOME")
-            .map(PathBuf::from)
+impl Config {
+    /// Remove every rule scoped to `apps` or a `when` condition, returning
+    /// how many were removed.
+    ///
+    /// Used in headless mode, where there is no focused window to match.
+    pub fn drop_app_rules(&mut self) -> usize {
+        let before = self.remaps.len() + self.hotkeys.len() + self.hotstrings.len();
+        self.remaps.retain(|r| r.apps.is_none() && r.when.is_none());
+        self.hotkeys
+            .retain(|h| h.apps.is_none() && h.when.is_none());
+        self.hotstrings.retain(|h| h.apps.is_none());
+        before - (self.remaps.len() + self.hotkeys.len() + self.hotstrings.len())
+    }
+
+    /// The settings `reloaded` changes that only take effect at startup, by
+    /// name, in file order. A reload applies the rules and `[log]` but not
+    /// these, since the backends and the executor chain are built once.
+    pub fn needs_restart(&self, reloaded: &Config) -> Vec<&'static str> {
+        let changed = [
+            (
+                "[executor] backend",
+                self.executor_backend != reloaded.executor_backend,
+            ),
+            ("[executor] headless", self.headless != reloaded.headless),
+            (
+                "[executor] target",
+                self.executor_target != reloaded.executor_target,
+            ),
+            (
+                "[executor] injection",
+                self.scancode_injection != reloaded.scancode_injection,
+            ),
+            ("[executor] max_rate", self.max_rate != reloaded.max_rate),
+            (
+                "[capture] device_wait_ms",
+                self.device_wait != reloaded.device_wait,
+            ),
+            (
+                "[capture] backend",
+                self.capture_backend != reloaded.capture_backend,
+            ),
+            (
+                "[macos] capslock_to",
+                self.caps_lock_to != reloaded.caps_lock_to,
+            ),
+            (
+                "[macos] fkeys_as_system_keys",
+                self.fkeys_as_system_keys != reloaded.fkeys_as_system_keys,
+            ),
+        ];
+        changed
+            .into_iter()
+            .filter(|&(_, changed)| changed)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Keys that rules wait for but macOS cannot tell apart, in file order
+    /// and without repeats: F13-F15 and PrintScreen, ScrollLock, and Pause
+    /// share key codes there, and capture reports only one name of each
+    /// pair. Empty once `[macos] fkeys_as_system_keys` says which.
+    pub fn ambiguous_fkeys(&self) -> Vec<KeyCode> {
+        if self.fkeys_as_system_keys.is_some() {
+            return Vec::new();
+        }
+        let mut keys = Vec::new();
+        for key in self.triggers() {
+            let ambiguous = matches!(
+                key,
+                KeyCode::F13
+                    | KeyCode::F14
+                    | KeyCode::F15
+                    | KeyCode::PrintScreen
+                    | KeyCode::ScrollLock
+                    | KeyCode::Pause
+            );
+            if ambiguous && !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    /// Every key a rule waits for, in file order.
+    fn triggers(&self) -> impl Iterator<Item = KeyCode> + '_ {
+        self.remaps
+            .iter()
+            .map(|r| r.from)
+            .chain(self.hotkeys.iter().flat_map(|h| h.keys.iter().copied()))
+            .chain(
+                self.disables
+                    .iter()
+                    .flat_map(|d| d.keys.iter().copied().chain([d.while_held])),
+            )
+            .chain(self.safety_key)
+    }
+}
+
+/// Capture backend names across all platforms. Whether a backend exists on
+/// the running platform is checked when capture is created.
+const CAPTURE_BACKENDS: &[&str] = &["evdev", "event_tap", "hook", "raw_input"];
+
+/// Executor backend names across all platforms. Whether a backend exists on
+/// the running platform is checked when the executor is created.
+const EXECUTOR_BACKENDS: &[&str] = &[
+    "portal",
+    "uinput",
+    "virtual_keyboard",
+    "xtest",
+    "sendinput",
+    "cgevent",
+];
+
+fn parse_log_level(s: &str) -> Result<log::LevelFilter, ConfigError> {
+    logging::parse_level(s).ok_or_else(|| ConfigError::UnknownLogLevel(s.to_owned()))
+}
+
+fn compile_when(w: RawWhen) -> Result<WindowCondition, ConfigError> {
+    WindowCondition::compile(w.title.as_deref(), w.app.as_deref(), w.ignore_case)
+}
+
+/// A hotkey's `command`, with its own `cwd` and `env`.
+fn exec_command(
+    command: Option<RawCommand>,
+    cwd: Option<String>,
+    env: BTreeMap<String, String>,
+) -> Result<ExecCommand, ConfigError> {
+    let line = match command.ok_or(ConfigError::MissingCommand)? {
+        RawCommand::Shell(line) => CommandLine::Shell(line),
+        RawCommand::Argv(argv) => match argv.first() {
+            Some(program) if !program.is_empty() => CommandLine::Argv(argv),
+            _ => return Err(ConfigError::EmptyArgv),
+        },
+    };
+    check_env_names(&env)?;
+    Ok(ExecCommand {
+        line,
+        cwd: cwd.map(PathBuf::from),
+        env: env.into_iter().collect(),
+    })
+}
+
+/// Reject an `env` name that is empty or holds `=` or a NUL.
+fn check_env_names(env: &BTreeMap<String, String>) -> Result<(), ConfigError> {
+    match env
+        .keys()
+        .find(|name| name.is_empty() || name.contains('=') || name.contains('\0'))
+    {
+        Some(name) => Err(ConfigError::ExecEnvName(name.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Validate an optional `apps` array. If present it must be non-empty.
+fn validate_apps(apps: Option<Vec<String>>) -> Result<Option<Vec<String>>, ConfigError> {
+    match apps {
+        Some(v) if v.is_empty() => Err(ConfigError::EmptyApps),
+        other => Ok(other),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Key name resolution
+// ---------------------------------------------------------------------------
+
+/// Resolve a remap `to` string to a single key or a `+`-joined combination.
+///
+/// The whole string is tried as a key name first so that names containing
+/// `+` (such as `numpad+`) keep resolving to a single key.
+fn parse_remap_target(s: &str) -> Result<RemapTarget, ConfigError> {
+    let keys = parse_key_or_combo(s)?;
+    Ok(match keys[..] {
+        [key] => RemapTarget::Key(key),
+        _ => RemapTarget::Combo(keys),
+    })
+}
+
+/// The keys of a single key name or a combination; a combination has at
+/// least two.
+fn parse_key_or_combo(s: &str) -> Result<Vec<KeyCode>, ConfigError> {
+    match parse_key(s) {
+        Ok(key) => Ok(vec![key]),
+        Err(e) if s.contains('+') => {
+            log::trace!("config: '{s}' is not a key name ({e}), parsing as combination");
+            parse_combo(s)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Parse one side of a `{ down, up }` target into the keys it taps; a
+/// missing side taps nothing.
+fn parse_split_side(s: Option<&str>) -> Result<Vec<KeyCode>, ConfigError> {
+    s.map_or(Ok(Vec::new()), parse_key_or_combo)
+}
+
+/// Parse a mouse button name, case-insensitively.
+fn parse_button(s: &str) -> Result<MouseButton, ConfigError> {
+    match s.to_ascii_lowercase().as_str() {
+        "left" => Ok(MouseButton::Left),
+        "right" => Ok(MouseButton::Right),
+        "middle" => Ok(MouseButton::Middle),
+        "back" => Ok(MouseButton::Back),
+        "forward" => Ok(MouseButton::Forward),
+        _ => Err(ConfigError::UnknownButton(s.to_owned())),
+    }
+}
+
+/// Parse one entry of a `steps` target.
+fn parse_step(step: &RawStep) -> Result<RemapStep, ConfigError> {
+    match step {
+        RawStep::Keys(s) => parse_key_or_combo(s).map(RemapStep::Keys),
+        RawStep::Delay(d) if d.delay_ms > MAX_STEP_DELAY_MS => {
+            Err(ConfigError::StepDelayTooLong(d.delay_ms))
+        }
+        RawStep::Delay(d) => Ok(RemapStep::Delay(d.delay_ms)),
+    }
+}
+
+/// Parse a `char` target, which must hold exactly one Unicode scalar value.
+fn parse_char(s: &str) -> Result<char, ConfigError> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(ConfigError::InvalidChar(s.to_owned())),
+    }
+}
+
+/// Parse a `with` target: one or more modifier names.
+fn parse_with(names: &[String]) -> Result<Vec<KeyCode>, ConfigError> {
+    let keys = names
+        .iter()
+        .map(|name| parse_key(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() || !keys.iter().all(|k| k.is_modifier()) {
+        return Err(ConfigError::InvalidWith(names.join(", ")));
+    }
+    Ok(keys)
+}
+
+/// Parse a combination such as `Ctrl+Shift+T` into its keys, in order.
+fn parse_combo(s: &str) -> Result<Vec<KeyCode>, ConfigError> {
+    let keys = s
+        .split('+')
+        .map(|part| parse_key(part.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some((main, modifiers)) = keys.split_last() else {
+        return Err(ConfigError::InvalidCombo(s.to_owned()));
+    };
+    if modifiers.is_empty() || main.is_modifier() || !modifiers.iter().all(|k| k.is_modifier()) {
+        return Err(ConfigError::InvalidCombo(s.to_owned()));
+    }
+    Ok(keys)
+}
+
+/// Resolve a key name string to a `KeyCode` (see `platform::keynames`).
+fn parse_key(s: &str) -> Result<KeyCode, ConfigError> {
+    keynames::parse(s).map_err(ConfigError::UnknownKey)
+}
+
+// ---------------------------------------------------------------------------
+// Config directory (platform-specific, no third-party deps)
+// ---------------------------------------------------------------------------
+
+fn config_dir() -> PathBuf {
+    #[cfg(target_os = "linux")]
+    {
+        // Respect XDG_CONFIG_HOME; fall back to ~/.config per XDG spec.
+        if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
+            return PathBuf::from(xdg).join("pc-unifier");
+        }
+        home_dir().join(".config").join("pc-unifier")
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        home_dir()
+            .join("Library")
+            .join("Application Support")
+            .join("pc-unifier")
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        // APPDATA is always set on Windows; USERPROFILE is the fallback.
+        if let Ok(appdata) = std::env::var("APPDATA") {
+            return PathBuf::from(appdata).join("pc-unifier");
+        }
+        home_dir()
+            .join("AppData")
+            .join("Roaming")
+            .join("pc-unifier")
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        // Unsupported OS: use fixed absolute path so config location does not depend on CWD.
+        PathBuf::from("/tmp").join("pc-unifier")
+    }
+}
+
+/// The user's home directory, from `HOME` (`USERPROFILE` on Windows).
+pub fn home_dir() -> PathBuf {
+    #[cfg(not(target_os = "windows"))]
+    {
+        std::env::var("HOME")
+            .map(PathBuf::from)
             .unwrap_or_else(|_| {
                 log::warn!("HOME unset, using / as config base; config_dir may be unwritable");
                 PathBuf::from("/")
@@ -491,7 +1535,7 @@ mod tests {
 
     fn assert_unknown_key(result: Result<Config, ConfigError>, expected: &str) {
         match result.unwrap_err() {
-            ConfigError::UnknownKey(k) if k == expected => {}
+            ConfigError::UnknownKey(e) if e.given == expected => {}
             other => panic!("expected ConfigError::UnknownKey({expected}), got: {other}"),
         }
     }
@@ -517,6 +1561,48 @@ mod tests {
         }
     }
 
+    fn assert_invalid_combo(result: Result<Config, ConfigError>, expected: &str) {
+        match result.unwrap_err() {
+            ConfigError::InvalidCombo(c) if c == expected => {}
+            other => panic!("expected ConfigError::InvalidCombo({expected}), got: {other}"),
+        }
+    }
+
+    fn assert_invalid_char(result: Result<Config, ConfigError>, expected: &str) {
+        match result.unwrap_err() {
+            ConfigError::InvalidChar(c) if c == expected => {}
+            other => panic!("expected ConfigError::InvalidChar({expected}), got: {other}"),
+        }
+    }
+
+    fn assert_unknown_backend(result: Result<Config, ConfigError>, expected: &str) {
+        match result.unwrap_err() {
+            ConfigError::UnknownBackend(b) if b == expected => {}
+            other => panic!("expected ConfigError::UnknownBackend({expected}), got: {other}"),
+        }
+    }
+
+    fn assert_headless_backend(result: Result<Config, ConfigError>, expected: &str) {
+        match result.unwrap_err() {
+            ConfigError::HeadlessBackend(b) if b == expected => {}
+            other => panic!("expected ConfigError::HeadlessBackend({expected}), got: {other}"),
+        }
+    }
+
+    fn assert_unknown_log_level(result: Result<Config, ConfigError>, expected: &str) {
+        match result {
+            Err(ConfigError::UnknownLogLevel(ref s)) if s == expected => {}
+            other => panic!("expected UnknownLogLevel({expected:?}), got {other:?}"),
+        }
+    }
+
+    fn assert_unknown_numpad_policy(result: Result<Config, ConfigError>, expected: &str) {
+        match result.unwrap_err() {
+            ConfigError::UnknownNumpadPolicy(p) if p == expected => {}
+            other => panic!("expected ConfigError::UnknownNumpadPolicy({expected}), got: {other}"),
+        }
+    }
+
     // --- Valid configs ---
 
     #[test]
@@ -531,7 +1617,7 @@ mod tests {
         .unwrap();
         assert_eq!(cfg.remaps.len(), 1);
         assert_eq!(cfg.remaps[0].from, KeyCode::Meta);
-        assert_eq!(cfg.remaps[0].to, KeyCode::Ctrl);
+        assert_eq!(cfg.remaps[0].to, RemapTarget::Key(KeyCode::Ctrl));
         assert!(cfg.remaps[0].apps.is_none());
     }
 
@@ -572,6 +1658,47 @@ mod tests {
         assert!(cfg.hotkeys[0].apps.is_none());
     }
 
+    #[test]
+    fn valid_hotkey_exec_argv_with_cwd_and_env() {
+        let cfg = parse_str(
+            r#"
+            [[hotkey]]
+            keys    = ["F9"]
+            action  = "exec"
+            command = ["notify-send", "hi there", "say \"ça\""]
+            cwd     = "/srv"
+            env     = { LANG = "fr_FR.UTF-8" }
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.hotkeys[0].action,
+            HotkeyAction::Exec(ExecCommand {
+                line: CommandLine::Argv(vec![
+                    "notify-send".into(),
+                    "hi there".into(),
+                    "say \"ça\"".into()
+                ]),
+                cwd: Some("/srv".into()),
+                env: vec![("LANG".into(), "fr_FR.UTF-8".into())],
+            })
+        );
+    }
+
+    #[test]
+    fn hotkey_argv_must_name_a_program() {
+        for command in ["[]", "[\"\", \"x\"]"] {
+            let toml =
+                format!("[[hotkey]]\nkeys = [\"F9\"]\naction = \"exec\"\ncommand = {command}");
+            assert!(
+                matches!(parse_str(&toml), Err(ConfigError::EmptyArgv)),
+                "{command}"
+            );
+        }
+        let toml = "[[hotkey]]\nkeys = [\"F9\"]\naction = \"exec\"\ncommand = \"x\"\nenv = { \"A=B\" = \"1\" }";
+        assert!(matches!(parse_str(toml), Err(ConfigError::ExecEnvName(_))));
+    }
+
     #[test]
     fn valid_hotstring() {
         let cfg = parse_str(
@@ -769,6 +1896,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unknown_key_name_suggests_the_close_one() {
+        let err = parse_str("[[remap]]\nfrom = \"CapsLok\"\nto = \"Escape\"\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown key name 'CapsLok' (did you mean 'CapsLock'?) -- see the config schema for valid key names"
+        );
+    }
+
     #[test]
     fn unknown_hotkey_action() {
         assert_unknown_action(
@@ -830,6 +1966,99 @@ mod tests {
         );
     }
 
+    #[test]
+    fn right_side_modifier_names() {
+        let cfg = parse_str(
+            r#"
+            [[hotkey]]
+            keys   = ["RCtrl", "RightOption", "RightCmd", "F1"]
+            action = "exec"
+            command = "true"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.hotkeys[0].keys,
+            vec![
+                KeyCode::RightCtrl,
+                KeyCode::RightAlt,
+                KeyCode::RightMeta,
+                KeyCode::F1
+            ]
+        );
+    }
+
+    #[test]
+    fn iso_and_menu_key_names() {
+        let cfg = parse_str(
+            r#"
+            [[hotkey]]
+            keys   = ["IntlBackslash", "Apps", "Compose"]
+            action = "exec"
+            command = "true"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.hotkeys[0].keys,
+            vec![KeyCode::IntlBackslash, KeyCode::Menu, KeyCode::Menu]
+        );
+    }
+
+    #[test]
+    fn jis_and_keypad_key_names() {
+        let cfg = parse_str(
+            r#"
+            [[hotkey]]
+            keys   = ["Yen", "IntlRo", "Kana", "Eisu", "Numpad.", "NumpadEquals"]
+            action = "exec"
+            command = "true"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.hotkeys[0].keys,
+            vec![
+                KeyCode::IntlYen,
+                KeyCode::IntlRo,
+                KeyCode::Lang1,
+                KeyCode::Lang2,
+                KeyCode::NumpadDecimal,
+                KeyCode::NumpadEquals,
+            ]
+        );
+    }
+
+    #[test]
+    fn media_key_names() {
+        let cfg = parse_str(
+            r#"
+            [[remap]]
+            from = "F8"
+            to   = "PlayPause"
+
+            [[remap]]
+            from = "volumeup"
+            to   = "BrightnessUp"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.remaps[0].to, RemapTarget::Key(KeyCode::PlayPause));
+        assert_eq!(cfg.remaps[1].from, KeyCode::VolumeUp);
+        assert_eq!(cfg.remaps[1].to, RemapTarget::Key(KeyCode::BrightnessUp));
+
+        for (alias, key) in [
+            ("Play", KeyCode::PlayPause),
+            ("VolUp", KeyCode::VolumeUp),
+            ("VolDown", KeyCode::VolumeDown),
+            ("VolumeMute", KeyCode::Mute),
+            ("MediaNext", KeyCode::NextTrack),
+            ("PreviousTrack", KeyCode::PrevTrack),
+        ] {
+            assert_eq!(parse_key(alias).unwrap(), key, "{alias}");
+        }
+    }
+
     #[test]
     fn key_names_case_insensitive() {
         let cfg = parse_str(
@@ -841,7 +2070,7 @@ mod tests {
         )
         .unwrap();
         assert_eq!(cfg.remaps[0].from, KeyCode::CapsLock);
-        assert_eq!(cfg.remaps[0].to, KeyCode::Escape);
+        assert_eq!(cfg.remaps[0].to, RemapTarget::Key(KeyCode::Escape));
     }
 
     #[test]
@@ -855,6 +2084,868 @@ mod tests {
         )
         .unwrap();
         assert_eq!(cfg.remaps[0].from, KeyCode::Semicolon);
-        assert_eq!(cfg.remaps[0].to, KeyCode::Apostrophe);
+        assert_eq!(cfg.remaps[0].to, RemapTarget::Key(KeyCode::Apostrophe));
+    }
+
+    // --- Key combination targets ---
+
+    #[test]
+    fn remap_to_key_combo() {
+        let cfg = parse_str(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = "Ctrl+Shift+T"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.remaps[0].to,
+            RemapTarget::Combo(vec![KeyCode::Ctrl, KeyCode::Shift, KeyCode::T])
+        );
+    }
+
+    /// `numpad+` contains a `+` but is a single key name, not a combination.
+    #[test]
+    fn numpad_plus_alias_is_not_a_combo() {
+        let cfg = parse_str(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = "numpad+"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.remaps[0].to, RemapTarget::Key(KeyCode::NumpadAdd));
+    }
+
+    #[test]
+    fn combo_with_non_modifier_prefix_is_rejected() {
+        assert_invalid_combo(
+            parse_str(
+                r#"
+                [[remap]]
+                from = "F13"
+                to   = "A+B"
+            "#,
+            ),
+            "A+B",
+        );
+    }
+
+    #[test]
+    fn combo_of_only_modifiers_is_rejected() {
+        assert_invalid_combo(
+            parse_str(
+                r#"
+                [[remap]]
+                from = "F13"
+                to   = "Ctrl+Shift"
+            "#,
+            ),
+            "Ctrl+Shift",
+        );
+    }
+
+    #[test]
+    fn combo_with_unknown_key_reports_that_key() {
+        assert_unknown_key(
+            parse_str(
+                r#"
+                [[remap]]
+                from = "F13"
+                to   = "Ctrl+Nope"
+            "#,
+            ),
+            "Nope",
+        );
+    }
+
+    // --- Char targets ---
+
+    #[test]
+    fn remap_to_char() {
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { char = \"\u{2014}\" }\n";
+        let cfg = parse_str(toml).unwrap();
+        assert_eq!(cfg.remaps[0].to, RemapTarget::Char('\u{2014}'));
+    }
+
+    #[test]
+    fn remap_with_modifiers() {
+        let toml = "[[remap]]\nfrom = \"J\"\nto = { with = [\"Ctrl\", \"Shift\"] }\n";
+        let cfg = parse_str(toml).unwrap();
+        assert_eq!(
+            cfg.remaps[0].to,
+            RemapTarget::With(vec![KeyCode::Ctrl, KeyCode::Shift])
+        );
+        for with in ["[]", "[\"Ctrl\", \"K\"]"] {
+            let toml = format!("[[remap]]\nfrom = \"J\"\nto = {{ with = {with} }}\n");
+            assert!(matches!(parse_str(&toml), Err(ConfigError::InvalidWith(_))));
+        }
+    }
+
+    #[test]
+    fn remap_button_targets() {
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { button = \"Middle\" }";
+        assert_eq!(
+            parse_str(toml).unwrap().remaps[0].to,
+            RemapTarget::Button(MouseButton::Middle)
+        );
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { button = \"wheel\" }";
+        assert!(matches!(
+            parse_str(toml),
+            Err(ConfigError::UnknownButton(_))
+        ));
+    }
+
+    #[test]
+    fn type_output_hotkey_and_capture_timeout() {
+        let toml = r#"
+            [exec]
+            capture_timeout_ms = 1500
+
+            [[hotkey]]
+            keys    = ["F9"]
+            action  = "type_output"
+            command = "date +%F"
+        "#;
+        let cfg = parse_str(toml).unwrap();
+        assert_eq!(
+            cfg.hotkeys[0].action,
+            HotkeyAction::TypeOutput("date +%F".into())
+        );
+        assert_eq!(cfg.exec.capture_timeout, Duration::from_millis(1500));
+        let toml = "[[hotkey]]\nkeys = [\"F9\"]\naction = \"type_output\"";
+        assert!(matches!(parse_str(toml), Err(ConfigError::MissingCommand)));
+        assert!(matches!(
+            parse_str("[exec]\ncapture_timeout_ms = 0"),
+            Err(ConfigError::CaptureTimeoutOutOfRange(0))
+        ));
+    }
+
+    #[test]
+    fn remap_repeat_every() {
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = \"Down\"\nrepeat_every_ms = 50";
+        assert_eq!(parse_str(toml).unwrap().remaps[0].repeat_every_ms, Some(50));
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = \"Down\"\nrepeat_every_ms = 5";
+        assert!(matches!(
+            parse_str(toml),
+            Err(ConfigError::RepeatEveryOutOfRange(5))
+        ));
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = \"Down\"\nrepeat = 2\nrepeat_every_ms = 50";
+        assert!(matches!(
+            parse_str(toml),
+            Err(ConfigError::InvalidRepeatEvery(_))
+        ));
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { up = \"A\" }\nrepeat_every_ms = 50";
+        assert!(matches!(
+            parse_str(toml),
+            Err(ConfigError::InvalidRepeatEvery(_))
+        ));
+    }
+
+    #[test]
+    fn remap_paste_targets() {
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { paste = \"hé\" }";
+        assert_eq!(
+            parse_str(toml).unwrap().remaps[0].to,
+            RemapTarget::Paste("hé".into())
+        );
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { paste = \"\" }";
+        assert!(matches!(parse_str(toml), Err(ConfigError::EmptyPaste(_))));
+    }
+
+    #[test]
+    fn clipboard_restore_delay() {
+        assert_eq!(parse_str("").unwrap().clipboard_restore, None);
+        let cfg = parse_str("[clipboard]\nrestore_after_ms = 500").unwrap();
+        assert_eq!(cfg.clipboard_restore, Some(Duration::from_millis(500)));
+        assert!(matches!(
+            parse_str("[clipboard]\nrestore_after_ms = 60000"),
+            Err(ConfigError::ClipboardRestoreTooLong(60000))
+        ));
+    }
+
+    #[test]
+    fn remap_steps_targets() {
+        let toml = r#"
+            [[remap]]
+            from = "F13"
+            to   = { steps = ["Ctrl+C", { delay_ms = 50 }, "V"] }
+        "#;
+        let cfg = parse_str(toml).unwrap();
+        assert_eq!(
+            cfg.remaps[0].to,
+            RemapTarget::Steps {
+                steps: vec![
+                    RemapStep::Keys(vec![KeyCode::Ctrl, KeyCode::C]),
+                    RemapStep::Delay(50),
+                    RemapStep::Keys(vec![KeyCode::V]),
+                ],
+                on_error: StepFailure::Abort,
+            }
+        );
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { steps = [\"A\"], on_error = \"continue\" }";
+        assert!(matches!(
+            parse_str(toml).unwrap().remaps[0].to,
+            RemapTarget::Steps {
+                on_error: StepFailure::Continue,
+                ..
+            }
+        ));
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { steps = [\"A\"], on_error = \"retry\" }";
+        assert!(matches!(
+            parse_str(toml),
+            Err(ConfigError::UnknownStepFailure(_))
+        ));
+        for bad in [
+            "to = { steps = [] }",
+            "to = { steps = [\"A\"] }\nrepeat = 2",
+        ] {
+            let toml = format!("[[remap]]\nfrom = \"F13\"\n{bad}\n");
+            assert!(
+                matches!(parse_str(&toml), Err(ConfigError::InvalidSteps(_))),
+                "{bad}"
+            );
+        }
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = { steps = [{ delay_ms = 60001 }] }";
+        assert!(matches!(
+            parse_str(toml),
+            Err(ConfigError::StepDelayTooLong(60_001))
+        ));
+    }
+
+    #[test]
+    fn remap_down_up_targets() {
+        let toml = r#"
+            [[remap]]
+            from = "F13"
+            to   = { down = "F14", up = "Ctrl+M" }
+
+            [[remap]]
+            from = "F15"
+            to   = { up = "F16" }
+        "#;
+        let cfg = parse_str(toml).unwrap();
+        assert_eq!(
+            cfg.remaps[0].to,
+            RemapTarget::Split {
+                down: vec![KeyCode::F14],
+                up: vec![KeyCode::Ctrl, KeyCode::M],
+            }
+        );
+        assert_eq!(
+            cfg.remaps[1].to,
+            RemapTarget::Split {
+                down: vec![],
+                up: vec![KeyCode::F16],
+            }
+        );
+        for bad in ["to = {}", "to = { down = \"F14\" }\nrepeat = 2"] {
+            let toml = format!("[[remap]]\nfrom = \"F13\"\n{bad}\n");
+            assert!(
+                matches!(parse_str(&toml), Err(ConfigError::InvalidSplit(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn remap_and_hotkey_when_conditions() {
+        let toml = r#"
+            [[remap]]
+            from = "F13"
+            to   = "F14"
+            when = { title = "YouTube", ignore_case = true }
+
+            [[hotkey]]
+            keys    = ["Ctrl", "K"]
+            action  = "exec"
+            command = "true"
+            when    = { app = "^org\\.mozilla\\." }
+        "#;
+        let cfg = parse_str(toml).unwrap();
+        let youtube = crate::platform::WindowContext {
+            app_id: None,
+            title: Some("cats - youtube".to_owned()),
+        };
+        assert!(cfg.remaps[0].when.as_ref().unwrap().matches(&youtube));
+        assert!(cfg.hotkeys[0].when.is_some());
+    }
+
+    #[test]
+    fn invalid_when_pattern_fails_at_load() {
+        let toml = r#"
+            [[remap]]
+            from = "F13"
+            to   = "F14"
+            when = { title = "[unclosed" }
+        "#;
+        let err = parse_str(toml).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidPattern { field: "title", .. }
+        ));
+        assert!(
+            err.to_string().starts_with("invalid title pattern"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn empty_when_is_rejected() {
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = \"F14\"\nwhen = {}\n";
+        assert!(matches!(parse_str(toml), Err(ConfigError::EmptyCondition)));
+    }
+
+    #[test]
+    fn passthrough_matches_apps_and_when() {
+        let toml = r#"
+            [[passthrough]]
+            apps = ["mstsc.exe"]
+
+            [[passthrough]]
+            when = { title = "Steam", ignore_case = true }
+        "#;
+        let cfg = parse_str(toml).unwrap();
+        let window = |app: &str, title: &str| WindowContext {
+            app_id: Some(app.to_owned()),
+            title: Some(title.to_owned()),
+        };
+        let matched = |w: &WindowContext| cfg.passthroughs.iter().any(|p| p.matches(w));
+        assert!(matched(&window("mstsc.exe", "Remote Desktop")));
+        assert!(matched(&window("game.exe", "steam overlay")));
+        assert!(!matched(&window("firefox.exe", "Inbox")));
+
+        assert!(matches!(
+            parse_str("[[passthrough]]\n"),
+            Err(ConfigError::EmptyPassthrough)
+        ));
+        assert_empty_apps(parse_str("[[passthrough]]\napps = []\n"));
+    }
+
+    #[test]
+    fn remap_repeat() {
+        let toml = "[[remap]]\nfrom = \"F13\"\nto = \"Down\"\nrepeat = 5\n";
+        let cfg = parse_str(toml).unwrap();
+        assert_eq!(cfg.remaps[0].repeat, Some(5));
+        let cfg = parse_str("[[remap]]\nfrom = \"F13\"\nto = \"Down\"\n").unwrap();
+        assert_eq!(cfg.remaps[0].repeat, None);
+    }
+
+    #[test]
+    fn capture_dedup_is_off_by_default() {
+        assert_eq!(parse_str("").unwrap().dedup_window, None);
+        let cfg = parse_str("[capture]\ndedup_window_us = 500\n").unwrap();
+        assert_eq!(cfg.dedup_window, None);
+    }
+
+    #[test]
+    fn capture_dedup_window() {
+        let cfg = parse_str("[capture]\ndedup = true\n").unwrap();
+        assert_eq!(
+            cfg.dedup_window,
+            Some(Duration::from_micros(DEFAULT_DEDUP_WINDOW_US))
+        );
+        let cfg = parse_str("[capture]\ndedup = true\ndedup_window_us = 250\n").unwrap();
+        assert_eq!(cfg.dedup_window, Some(Duration::from_micros(250)));
+    }
+
+    #[test]
+    fn capture_dedup_window_above_cap_is_rejected() {
+        let toml = format!(
+            "[capture]\ndedup = true\ndedup_window_us = {}\n",
+            MAX_DEDUP_WINDOW_US + 1
+        );
+        match parse_str(&toml) {
+            Err(ConfigError::DedupWindowTooLarge(n)) if n == MAX_DEDUP_WINDOW_US + 1 => {}
+            other => panic!("expected DedupWindowTooLarge, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn capture_device_wait() {
+        assert_eq!(parse_str("").unwrap().device_wait, None);
+        let cfg = parse_str("[capture]\ndevice_wait_ms = 0\n").unwrap();
+        assert_eq!(cfg.device_wait, Some(Duration::ZERO));
+        let toml = format!("[capture]\ndevice_wait_ms = {}\n", MAX_DEVICE_WAIT_MS + 1);
+        match parse_str(&toml) {
+            Err(ConfigError::DeviceWaitTooLong(n)) if n == MAX_DEVICE_WAIT_MS + 1 => {}
+            other => panic!("expected DeviceWaitTooLong, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn capture_backend() {
+        assert_eq!(parse_str("").unwrap().capture_backend, None);
+        let cfg = parse_str("[capture]\nbackend = \"raw_input\"\n").unwrap();
+        assert_eq!(cfg.capture_backend.as_deref(), Some("raw_input"));
+        match parse_str("[capture]\nbackend = \"interception\"\n") {
+            Err(ConfigError::UnknownCaptureBackend(b)) if b == "interception" => {}
+            other => panic!("expected UnknownCaptureBackend, got {other:?}"),
+        }
+    }
+
+    // --- Exec ---
+
+    #[test]
+    fn exec_settings() {
+        assert_eq!(parse_str("").unwrap().exec, ExecSettings::default());
+        let toml = r#"
+            [exec]
+            cwd = "/tmp"
+            min_interval_ms = 0
+            [exec.env]
+            LANG = "C"
+            EDITOR = "vi"
+        "#;
+        let exec = parse_str(toml).unwrap().exec;
+        assert_eq!(exec.cwd, Some(PathBuf::from("/tmp")));
+        assert_eq!(
+            exec.env,
+            vec![("EDITOR".into(), "vi".into()), ("LANG".into(), "C".into())]
+        );
+        assert_eq!(exec.min_interval, Duration::ZERO);
+    }
+
+    #[test]
+    fn exec_env_name_with_equals_is_rejected() {
+        match parse_str("[exec]\nenv = { \"A=B\" = \"1\" }\n") {
+            Err(ConfigError::ExecEnvName(name)) if name == "A=B" => {}
+            other => panic!("expected ExecEnvName, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn capture_match_mode() {
+        assert_eq!(parse_str("").unwrap().key_match, KeyMatch::Key);
+        let cfg = parse_str("[capture]\nmatch = \"position\"\n").unwrap();
+        assert_eq!(cfg.key_match, KeyMatch::Position);
+        match parse_str("[capture]\nmatch = \"scancode\"\n") {
+            Err(ConfigError::UnknownKeyMatch(m)) if m == "scancode" => {}
+            other => panic!("expected UnknownKeyMatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn capture_modifiers_mode() {
+        assert_eq!(
+            parse_str("").unwrap().modifier_match,
+            ModifierMatch::Logical
+        );
+        let cfg = parse_str("[capture]\nmodifiers = \"physical\"\n").unwrap();
+        assert_eq!(cfg.modifier_match, ModifierMatch::Physical);
+        assert!(matches!(
+            parse_str("[capture]\nmodifiers = \"raw\"\n"),
+            Err(ConfigError::UnknownModifierMatch(m)) if m == "raw"
+        ));
+    }
+
+    #[test]
+    fn capture_sides_mode() {
+        assert_eq!(parse_str("").unwrap().side_match, SideMatch::Right);
+        let cfg = parse_str("[capture]\nsides = \"both\"\n").unwrap();
+        assert_eq!(cfg.side_match, SideMatch::Both);
+        assert!(matches!(
+            parse_str("[capture]\nsides = \"left\"\n"),
+            Err(ConfigError::UnknownSideMatch(m)) if m == "left"
+        ));
+        assert_eq!(SideMatch::Right.normalize(KeyCode::LeftCtrl), KeyCode::Ctrl);
+        assert_eq!(
+            SideMatch::Right.normalize(KeyCode::RightCtrl),
+            KeyCode::RightCtrl
+        );
+        assert_eq!(
+            SideMatch::Unified.normalize(KeyCode::RightAlt),
+            KeyCode::Alt
+        );
+        assert_eq!(
+            SideMatch::Both.normalize(KeyCode::LeftMeta),
+            KeyCode::LeftMeta
+        );
+    }
+
+    #[test]
+    fn sides_folded_away_are_rejected_in_triggers() {
+        let remap = "[[remap]]\nfrom = \"LCtrl\"\nto = \"Escape\"\n";
+        assert!(matches!(
+            parse_str(remap),
+            Err(ConfigError::SideNeverMatched(KeyCode::LeftCtrl))
+        ));
+        let both = parse_str(&format!("[capture]\nsides = \"both\"\n\n{remap}")).unwrap();
+        assert_eq!(both.remaps[0].from, KeyCode::LeftCtrl);
+        let unified = "[capture]\nsides = \"unified\"\n\n\
+                       [[hotkey]]\nkeys = [\"RightAlt\", \"E\"]\naction = \"pause\"\n";
+        assert!(matches!(
+            parse_str(unified),
+            Err(ConfigError::SideNeverMatched(KeyCode::RightAlt))
+        ));
+        // Output keys are injected, not matched, so any side is fine there.
+        let cfg = parse_str("[[remap]]\nfrom = \"CapsLock\"\nto = \"LeftCtrl\"\n").unwrap();
+        assert_eq!(cfg.remaps.len(), 1);
+    }
+
+    #[test]
+    fn macos_capslock_target() {
+        assert_eq!(parse_str("").unwrap().caps_lock_to, None);
+        let cfg = parse_str("[macos]\ncapslock_to = \"F18\"\n").unwrap();
+        assert_eq!(cfg.caps_lock_to, Some(KeyCode::F18));
+        match parse_str("[macos]\ncapslock_to = \"Escape\"\n") {
+            Err(ConfigError::CapsLockTarget(k)) if k == "Escape" => {}
+            other => panic!("expected CapsLockTarget, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn disable_rules() {
+        let cfg =
+            parse_str("[[disable]]\nwhile_held = \"F13\"\nkeys = [\"Meta\", \"Tab\"]\n").unwrap();
+        assert_eq!(
+            cfg.disables,
+            [DisableRule {
+                while_held: KeyCode::F13,
+                keys: vec![KeyCode::Meta, KeyCode::Tab],
+            }]
+        );
+        assert!(matches!(
+            parse_str("[[disable]]\nwhile_held = \"F13\"\nkeys = []\n"),
+            Err(ConfigError::EmptyDisable)
+        ));
+    }
+
+    #[test]
+    fn ambiguous_fkeys_until_the_macos_setting_is_explicit() {
+        let rules = r#"
+            [[remap]]
+            from = "PrintScreen"
+            to = "F1"
+
+            [[hotkey]]
+            keys = ["Ctrl", "F13"]
+            action = "toggle_pause"
+        "#;
+        let cfg = parse_str(rules).unwrap();
+        assert_eq!(cfg.fkeys_as_system_keys, None);
+        assert_eq!(cfg.ambiguous_fkeys(), [KeyCode::PrintScreen, KeyCode::F13]);
+
+        for value in ["true", "false"] {
+            let toml = format!("[macos]\nfkeys_as_system_keys = {value}\n{rules}");
+            let cfg = parse_str(&toml).unwrap();
+            assert_eq!(cfg.fkeys_as_system_keys, Some(value == "true"));
+            assert!(cfg.ambiguous_fkeys().is_empty());
+        }
+    }
+
+    #[test]
+    fn remap_repeat_above_cap_is_rejected() {
+        let toml = format!(
+            "[[remap]]\nfrom = \"F13\"\nto = \"Down\"\nrepeat = {}\n",
+            MAX_REPEAT + 1
+        );
+        match parse_str(&toml) {
+            Err(ConfigError::RepeatTooLarge(n)) if n == MAX_REPEAT + 1 => {}
+            other => panic!("expected RepeatTooLarge, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn char_target_with_two_chars_is_rejected() {
+        assert_invalid_char(
+            parse_str(
+                r#"
+                [[remap]]
+                from = "F13"
+                to   = { char = "ab" }
+            "#,
+            ),
+            "ab",
+        );
+    }
+
+    #[test]
+    fn char_target_with_unknown_field_is_rejected() {
+        assert_parse_err(parse_str(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = { chr = "a" }
+        "#,
+        ));
+    }
+
+    // --- Numpad policy ---
+
+    #[test]
+    fn numpad_policy_defaults_to_toggle() {
+        let config = parse_str("").unwrap();
+        assert_eq!(config.numpad, NumpadPolicy::ToggleNumLock);
+    }
+
+    #[test]
+    fn numpad_policy_top_row() {
+        let toml = r#"
+            [numpad]
+            policy = "top_row"
+        "#;
+        assert_eq!(parse_str(toml).unwrap().numpad, NumpadPolicy::TopRow);
+    }
+
+    #[test]
+    fn unknown_numpad_policy() {
+        let toml = r#"
+            [numpad]
+            policy = "sometimes"
+        "#;
+        assert_unknown_numpad_policy(parse_str(toml), "sometimes");
+    }
+
+    #[test]
+    fn safety_key_defaults_to_none() {
+        assert_eq!(parse_str("").unwrap().safety_key, None);
+    }
+
+    #[test]
+    fn safety_key_is_parsed() {
+        let toml = r#"
+            [safety]
+            key = "ScrollLock"
+        "#;
+        assert_eq!(
+            parse_str(toml).unwrap().safety_key,
+            Some(KeyCode::ScrollLock)
+        );
+    }
+
+    #[test]
+    fn unknown_safety_key() {
+        let toml = r#"
+            [safety]
+            key = "RightHyper"
+        "#;
+        assert_unknown_key(parse_str(toml), "RightHyper");
+    }
+
+    #[test]
+    fn log_levels_are_parsed() {
+        let toml = r#"
+            [log]
+            level = "warn"
+
+            [log.modules]
+            engine = "debug"
+            "platform::linux::evdev" = "error"
+        "#;
+        let expected = LogFilter::new(
+            log::LevelFilter::Warn,
+            vec![
+                ("engine".to_owned(), log::LevelFilter::Debug),
+                ("platform::linux::evdev".to_owned(), log::LevelFilter::Error),
+            ],
+        );
+        assert_eq!(parse_str(toml).unwrap().log, expected);
+    }
+
+    #[test]
+    fn log_modules_without_level_keep_default() {
+        let toml = r#"
+            [log.modules]
+            control = "trace"
+        "#;
+        let cfg = parse_str(toml).unwrap();
+        assert_eq!(cfg.log.level_for("other"), logging::DEFAULT_LEVEL);
+    }
+
+    #[test]
+    fn unknown_log_level() {
+        let toml = r#"
+            [log.modules]
+            engine = "chatty"
+        "#;
+        assert_unknown_log_level(parse_str(toml), "chatty");
+    }
+
+    #[test]
+    fn valid_hotkey_toggle_pause() {
+        let toml = r#"
+            [[hotkey]]
+            keys   = ["Ctrl", "Alt", "Pause"]
+            action = "toggle_pause"
+        "#;
+        let cfg = parse_str(toml).unwrap();
+        assert_eq!(cfg.hotkeys[0].action, HotkeyAction::SetPaused(None));
+    }
+
+    #[test]
+    fn valid_hotkey_system_commands() {
+        let toml = r#"
+            [[hotkey]]
+            keys   = ["Meta", "L"]
+            action = "lock_screen"
+
+            [[hotkey]]
+            keys   = ["Meta", "Shift", "S"]
+            action = "sleep"
+
+            [[hotkey]]
+            keys   = ["Meta", "Shift", "Q"]
+            action = "log_out"
+        "#;
+        let cfg = parse_str(toml).unwrap();
+        let actions: Vec<_> = cfg.hotkeys.iter().map(|h| h.action.clone()).collect();
+        assert_eq!(
+            actions,
+            vec![
+                HotkeyAction::System(SystemCmd::LockScreen),
+                HotkeyAction::System(SystemCmd::Sleep),
+                HotkeyAction::System(SystemCmd::LogOut),
+            ]
+        );
+    }
+
+    // --- Executor backend ---
+
+    #[test]
+    fn executor_backend_defaults_to_none() {
+        assert_eq!(parse_str("").unwrap().executor_backend, None);
+    }
+
+    #[test]
+    fn executor_backend_pinned() {
+        let toml = r#"
+            [executor]
+            backend = "xtest"
+        "#;
+        assert_eq!(
+            parse_str(toml).unwrap().executor_backend.as_deref(),
+            Some("xtest")
+        );
+    }
+
+    #[test]
+    fn unknown_executor_backend() {
+        let toml = r#"
+            [executor]
+            backend = "xdotool"
+        "#;
+        assert_unknown_backend(parse_str(toml), "xdotool");
+    }
+
+    #[test]
+    fn headless_implies_uinput_backend() {
+        let toml = r#"
+            [executor]
+            headless = true
+        "#;
+        let config = parse_str(toml).unwrap();
+        assert!(config.headless);
+        assert_eq!(config.executor_backend.as_deref(), Some("uinput"));
+    }
+
+    #[test]
+    fn headless_with_other_backend_is_rejected() {
+        let toml = r#"
+            [executor]
+            headless = true
+            backend  = "portal"
+        "#;
+        assert_headless_backend(parse_str(toml), "portal");
+    }
+
+    #[test]
+    fn executor_target_parsed() {
+        let toml = r#"
+            [executor]
+            target = { title = "Notepad", class = "Notepad" }
+        "#;
+        let target = parse_str(toml).unwrap().executor_target.unwrap();
+        assert_eq!(target.title.as_deref(), Some("Notepad"));
+        assert_eq!(target.class.as_deref(), Some("Notepad"));
+        assert_eq!(parse_str("").unwrap().executor_target, None);
+    }
+
+    #[test]
+    fn executor_scancode_injection() {
+        let parse = |toml: &str| parse_str(toml).map(|c| c.scancode_injection);
+        assert_eq!(parse("").unwrap(), ScancodeInjection::Off);
+        assert_eq!(
+            parse("[executor]\ninjection = \"scancode\"").unwrap(),
+            ScancodeInjection::Always
+        );
+        assert_eq!(
+            parse("[executor]\nscancode_apps = [\"game.exe\"]").unwrap(),
+            ScancodeInjection::Apps(vec!["game.exe".into()])
+        );
+        assert!(matches!(
+            parse("[executor]\ninjection = \"raw\""),
+            Err(ConfigError::UnknownInjection(m)) if m == "raw"
+        ));
+        assert!(matches!(
+            parse("[executor]\nscancode_apps = []"),
+            Err(ConfigError::EmptyApps)
+        ));
+    }
+
+    #[test]
+    fn executor_max_rate() {
+        let parse = |toml: &str| parse_str(toml).unwrap().max_rate;
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("[executor]\nmax_rate = 200"), Some(200));
+        assert_eq!(parse("[executor]\nmax_rate = 0"), Some(0));
+    }
+
+    #[test]
+    fn executor_type_delay() {
+        assert_eq!(parse_str("").unwrap().type_delay, Duration::ZERO);
+        let cfg = parse_str("[executor]\ntype_delay_ms = 5").unwrap();
+        assert_eq!(cfg.type_delay, Duration::from_millis(5));
+        assert!(matches!(
+            parse_str("[executor]\ntype_delay_ms = 500"),
+            Err(ConfigError::TypeDelayTooLong(500))
+        ));
+    }
+
+    #[test]
+    fn empty_executor_target_is_rejected() {
+        let toml = r#"
+            [executor]
+            target = {}
+        "#;
+        assert!(matches!(parse_str(toml), Err(ConfigError::EmptyTarget)));
+    }
+
+    #[test]
+    fn drop_app_rules_keeps_only_global_rules() {
+        let toml = r#"
+            [[remap]]
+            from = "A"
+            to   = "B"
+
+            [[remap]]
+            from = "C"
+            to   = "D"
+            apps = ["firefox"]
+
+            [[hotkey]]
+            keys    = ["Ctrl", "T"]
+            action  = "exec"
+            command = "kitty"
+            apps    = ["firefox"]
+        "#;
+        let mut config = parse_str(toml).unwrap();
+        assert_eq!(config.drop_app_rules(), 2);
+        assert_eq!(config.remaps.len(), 1);
+        assert_eq!(config.remaps[0].from, KeyCode::A);
+        assert!(config.hotkeys.is_empty());
+    }
+
+    #[test]
+    fn needs_restart_names_only_startup_settings() {
+        let running = parse_str("[executor]\nmax_rate = 500").unwrap();
+        let reloaded = parse_str(
+            "[executor]\nmax_rate = 50\n\n[log]\nlevel = \"debug\"\n\n[[remap]]\nfrom = \"A\"\nto = \"B\"",
+        )
+        .unwrap();
+        assert_eq!(running.needs_restart(&reloaded), ["[executor] max_rate"]);
+        assert!(running.needs_restart(&running.clone()).is_empty());
     }
 }
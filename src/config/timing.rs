@@ -0,0 +1,123 @@
+//! Timing thresholds: `[timing]` defaults and per-rule `timing` overrides.
+//!
+//! Every time-based rule feature (tap versus hold, chords, key sequences,
+//! one-shot modifiers) reads its thresholds from a `Timing` instead of a
+//! constant of its own. `[timing]` replaces the built-in defaults for the
+//! whole config, and a rule's `timing` table replaces them again for that
+//! rule alone. Values are resolved at load, so each rule carries the
+//! complete set it runs with.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::ConfigError;
+
+/// Default `tap_ms`: a press released sooner is a tap, a longer one a hold.
+pub const DEFAULT_TAP_MS: u64 = 200;
+
+/// Default `chord_window_ms`: keys pressed within this long of each other
+/// count as pressed together.
+pub const DEFAULT_CHORD_WINDOW_MS: u64 = 50;
+
+/// Default `sequence_step_ms`: the longest pause between two keys of a
+/// sequence.
+pub const DEFAULT_SEQUENCE_STEP_MS: u64 = 1_000;
+
+/// Default `oneshot_ms`: how long a one-shot modifier waits for the next key.
+pub const DEFAULT_ONESHOT_MS: u64 = 1_000;
+
+/// The largest value any timing field accepts.
+pub const MAX_TIMING_MS: u64 = 10_000;
+
+/// Resolved timing thresholds for one rule, or the config-wide defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub tap: Duration,
+    pub chord_window: Duration,
+    pub sequence_step: Duration,
+    pub oneshot: Duration,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            tap: Duration::from_millis(DEFAULT_TAP_MS),
+            chord_window: Duration::from_millis(DEFAULT_CHORD_WINDOW_MS),
+            sequence_step: Duration::from_millis(DEFAULT_SEQUENCE_STEP_MS),
+            oneshot: Duration::from_millis(DEFAULT_ONESHOT_MS),
+        }
+    }
+}
+
+/// A `[timing]` table or a rule's `timing` table. Every field is optional;
+/// a missing one keeps the value it would otherwise have.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(super) struct RawTiming {
+    tap_ms: Option<u64>,
+    chord_window_ms: Option<u64>,
+    sequence_step_ms: Option<u64>,
+    oneshot_ms: Option<u64>,
+}
+
+impl Timing {
+    /// `self` with each value `raw` sets replaced, after checking that it is
+    /// between 1 and `MAX_TIMING_MS`.
+    pub(super) fn with_overrides(self, raw: &RawTiming) -> Result<Self, ConfigError> {
+        fn field(
+            name: &'static str,
+            value: Option<u64>,
+            current: Duration,
+        ) -> Result<Duration, ConfigError> {
+            match value {
+                None => Ok(current),
+                Some(ms @ 1..=MAX_TIMING_MS) => Ok(Duration::from_millis(ms)),
+                Some(ms) => Err(ConfigError::TimingOutOfRange { field: name, ms }),
+            }
+        }
+        Ok(Self {
+            tap: field("tap_ms", raw.tap_ms, self.tap)?,
+            chord_window: field("chord_window_ms", raw.chord_window_ms, self.chord_window)?,
+            sequence_step: field("sequence_step_ms", raw.sequence_step_ms, self.sequence_step)?,
+            oneshot: field("oneshot_ms", raw.oneshot_ms, self.oneshot)?,
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_fields_keep_current_values() {
+        let raw = RawTiming {
+            tap_ms: Some(150),
+            ..RawTiming::default()
+        };
+        let timing = Timing::default().with_overrides(&raw).unwrap();
+        assert_eq!(timing.tap, Duration::from_millis(150));
+        assert_eq!(timing.oneshot, Timing::default().oneshot);
+    }
+
+    #[test]
+    fn zero_and_oversized_values_are_rejected() {
+        for ms in [0, MAX_TIMING_MS + 1] {
+            let raw = RawTiming {
+                chord_window_ms: Some(ms),
+                ..RawTiming::default()
+            };
+            match Timing::default().with_overrides(&raw) {
+                Err(ConfigError::TimingOutOfRange {
+                    field: "chord_window_ms",
+                    ms: got,
+                }) if got == ms => {}
+                other => panic!("expected TimingOutOfRange for {ms}, got {other:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,409 @@
+//! Control socket: line-delimited JSON commands from front-ends and scripts.
+//!
+//! Each request is one JSON object on its own line, e.g. `{"cmd":"resume"}`.
+//! Each request gets exactly one JSON line back, carrying the daemon state on
+//! success (`{"ok":true,"paused":false,"executor":"portal"}`) and
+//! `{"ok":false,"error":"..."}` otherwise. While the capture backend has
+//! suspended rules, the reply also names the cause, e.g.
+//! `"paused":true,"reason":"secure input (loginwindow)"`.
+//!
+//! Commands:
+//!   - `pause`   -- stop applying rules; all keys pass through
+//!   - `resume`  -- apply rules again
+//!   - `toggle`  -- `pause` when rules apply, `resume` when paused
+//!   - `status`  -- report the current state without changing it
+//!   - `health`  -- the state plus liveness: milliseconds since the last
+//!     handled event, the last main loop run, and the capture backend's last
+//!     heartbeat, and `"stale":true` when the loop or backend looks hung
+//!   - `reload`  -- read the config file again and apply its rules; the main
+//!     loop does so once it next runs, and logs whether it worked
+//!
+//! The listener runs on its own thread and reaches engine state only through
+//! shared handles. Other front-ends in the process, such as the Windows tray
+//! icon, send commands through `apply`. Unix domain sockets only; Windows
+//! has no control channel yet.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::PauseHandle;
+use crate::health::{Health, Report};
+use crate::platform::PlatformError;
+
+// ---------------------------------------------------------------------------
+// Protocol
+// ---------------------------------------------------------------------------
+
+/// Daemon state the control socket can read or change.
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub pause: PauseHandle,
+    /// Executor backend name, as reported by `ActionExecutor::name`.
+    pub executor: &'static str,
+    pub health: Health,
+    pub reload: ReloadRequest,
+}
+
+/// Set by a `reload` command and taken by the main loop, which reloads the
+/// config. Clones refer to the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct ReloadRequest(Arc<AtomicBool>);
+
+impl ReloadRequest {
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a reload was requested since the last call.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// A command received on the control socket.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Command {
+    Pause,
+    Resume,
+    Toggle,
+    Status,
+    Health,
+    Reload,
+}
+
+/// The reply written for every request line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+struct Response {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    paused: Option<bool>,
+    /// Why the capture backend suspended rules, when it has.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    executor: Option<&'static str>,
+    /// Set by `health` only, as are the ages below.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stale: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_event_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    loop_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Response {
+    fn state(ctx: &Context) -> Self {
+        Self {
+            ok: true,
+            paused: Some(ctx.pause.is_paused()),
+            reason: ctx.pause.suspended_reason(),
+            executor: Some(ctx.executor),
+            ..Self::default()
+        }
+    }
+
+    fn health(ctx: &Context, report: Report) -> Self {
+        Self {
+            stale: Some(report.stale),
+            last_event_ms: report.last_event_age.map(|age| age.as_millis()),
+            loop_ms: Some(report.loop_age.as_millis()),
+            backend_ms: report.backend_age.map(|age| age.as_millis()),
+            ..Self::state(ctx)
+        }
+    }
+
+    fn error(msg: String) -> Self {
+        Self {
+            error: Some(msg),
+            ..Self::default()
+        }
+    }
+}
+
+/// Parse one request line, apply it, and return the JSON reply line (without
+/// the trailing newline).
+pub fn respond(line: &str, ctx: &Context) -> String {
+    let response = match serde_json::from_str::<Command>(line) {
+        Ok(Command::Health) => {
+            apply(Command::Health, ctx);
+            Response::health(ctx, ctx.health.report(Instant::now()))
+        }
+        Ok(cmd) => {
+            apply(cmd, ctx);
+            Response::state(ctx)
+        }
+        Err(e) => {
+            log::warn!("control: bad request: {e}");
+            Response::error(e.to_string())
+        }
+    };
+    reply(&response)
+}
+
+/// Carry out `cmd`. Commands that only report change nothing.
+pub fn apply(cmd: Command, ctx: &Context) {
+    log::info!("control: {cmd:?}");
+    match cmd {
+        // As `Action::SetPaused` does.
+        Command::Pause => {
+            ctx.pause.set_or_toggle(Some(true));
+        }
+        Command::Resume => {
+            ctx.pause.set_or_toggle(Some(false));
+        }
+        Command::Toggle => {
+            ctx.pause.set_or_toggle(None);
+        }
+        Command::Reload => ctx.reload.request(),
+        Command::Status | Command::Health => {}
+    }
+}
+
+fn reply(response: &Response) -> String {
+    // Serializing a struct of bools, numbers, and strings cannot fail.
+    serde_json::to_string(response).unwrap_or_default()
+}
+
+// ---------------------------------------------------------------------------
+// Socket location
+// ---------------------------------------------------------------------------
+
+/// Return the control socket path.
+///
+/// `$XDG_RUNTIME_DIR/pc-unifier.sock` when the runtime directory is set
+/// (Linux), otherwise the per-user temp directory (`$TMPDIR` on macOS).
+pub fn default_socket_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("pc-unifier.sock")
+}
+
+// ---------------------------------------------------------------------------
+// Listener
+// ---------------------------------------------------------------------------
+
+/// Bind the control socket at `path` and serve it on a background thread.
+///
+/// A stale socket left by a previous run is removed first. The socket is made
+/// owner-only so other users cannot pause the daemon.
+#[cfg(unix)]
+pub fn spawn(path: &std::path::Path, ctx: Context) -> Result<(), PlatformError> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::UnixListener;
+
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    let listener = UnixListener::bind(path).map_err(|e| {
+        PlatformError::Unavailable(format!("control: cannot bind {}: {e}", path.display()))
+    })?;
+    if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)) {
+        log::warn!("control: cannot restrict {}: {e}", path.display());
+    }
+    log::info!("control: listening on {}", path.display());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    log::warn!("control: accept failed: {e}");
+                    continue;
+                }
+            };
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                let mut writer = match stream.try_clone() {
+                    Ok(w) => w,
+                    Err(e) => {
+                        log::warn!("control: cannot clone stream: {e}");
+                        return;
+                    }
+                };
+                for line in BufReader::new(stream).lines() {
+                    let Ok(line) = line else { break };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let reply = respond(&line, &ctx);
+                    if writeln!(writer, "{reply}").is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Control sockets are not implemented on this platform yet.
+#[cfg(not(unix))]
+pub fn spawn(path: &std::path::Path, ctx: Context) -> Result<(), PlatformError> {
+    let _ = (path, ctx);
+    Err(PlatformError::Unavailable(
+        "control: no control channel on this platform".into(),
+    ))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn context() -> Context {
+        Context {
+            pause: PauseHandle::default(),
+            executor: "mock",
+            health: Health::new(),
+            reload: ReloadRequest::default(),
+        }
+    }
+
+    #[test]
+    fn reload_is_requested_once() {
+        let ctx = context();
+        assert_eq!(
+            respond(r#"{"cmd":"reload"}"#, &ctx),
+            r#"{"ok":true,"paused":false,"executor":"mock"}"#
+        );
+        assert!(ctx.reload.take());
+        assert!(!ctx.reload.take());
+    }
+
+    #[test]
+    fn resume_clears_pause() {
+        let ctx = context();
+        ctx.pause.set_paused(true);
+        let reply = respond(r#"{"cmd":"resume"}"#, &ctx);
+        assert!(!ctx.pause.is_paused());
+        assert_eq!(reply, r#"{"ok":true,"paused":false,"executor":"mock"}"#);
+    }
+
+    #[test]
+    fn pause_sets_pause() {
+        let ctx = context();
+        assert_eq!(
+            respond(r#"{"cmd":"pause"}"#, &ctx),
+            r#"{"ok":true,"paused":true,"executor":"mock"}"#
+        );
+        assert!(ctx.pause.is_paused());
+    }
+
+    #[test]
+    fn toggle_flips_pause() {
+        let ctx = context();
+        respond(r#"{"cmd":"toggle"}"#, &ctx);
+        assert!(ctx.pause.is_paused());
+        assert_eq!(
+            respond(r#"{"cmd":"toggle"}"#, &ctx),
+            r#"{"ok":true,"paused":false,"executor":"mock"}"#
+        );
+    }
+
+    #[test]
+    fn status_does_not_change_state() {
+        let ctx = context();
+        ctx.pause.set_paused(true);
+        assert_eq!(
+            respond(r#"{"cmd":"status"}"#, &ctx),
+            r#"{"ok":true,"paused":true,"executor":"mock"}"#
+        );
+        assert!(ctx.pause.is_paused());
+    }
+
+    #[test]
+    fn status_names_backend_suspension() {
+        let ctx = context();
+        ctx.pause.suspension().suspend("secure input (loginwindow)");
+        assert_eq!(
+            respond(r#"{"cmd":"resume"}"#, &ctx),
+            r#"{"ok":true,"paused":true,"reason":"secure input (loginwindow)","executor":"mock"}"#
+        );
+        ctx.pause.suspension().lift();
+        assert_eq!(
+            respond(r#"{"cmd":"status"}"#, &ctx),
+            r#"{"ok":true,"paused":false,"executor":"mock"}"#
+        );
+    }
+
+    #[test]
+    fn health_reports_ages_and_staleness() {
+        let ctx = context();
+        let fresh = respond(r#"{"cmd":"health"}"#, &ctx);
+        assert!(
+            fresh.starts_with(
+                r#"{"ok":true,"paused":false,"executor":"mock","stale":false,"loop_ms":"#
+            ),
+            "{fresh}"
+        );
+
+        // A backend silent for 12 s, as a hung one would be.
+        let report = Report {
+            last_event_age: Some(Duration::from_millis(1500)),
+            loop_age: Duration::from_millis(20),
+            backend_age: Some(Duration::from_secs(12)),
+            stale: true,
+        };
+        assert_eq!(
+            reply(&Response::health(&ctx, report)),
+            r#"{"ok":true,"paused":false,"executor":"mock","stale":true,"last_event_ms":1500,"loop_ms":20,"backend_ms":12000}"#
+        );
+    }
+
+    #[test]
+    fn unknown_command_is_an_error_reply() {
+        let ctx = context();
+        let reply = respond(r#"{"cmd":"explode"}"#, &ctx);
+        assert!(reply.starts_with(r#"{"ok":false,"error":"#), "{reply}");
+        assert!(!ctx.pause.is_paused());
+    }
+
+    #[test]
+    fn malformed_json_is_an_error_reply() {
+        let reply = respond("resume", &context());
+        assert!(reply.starts_with(r#"{"ok":false"#), "{reply}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_round_trip() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("pcu-test-{}.sock", std::process::id()));
+        let ctx = context();
+        ctx.pause.set_paused(true);
+        spawn(&path, ctx.clone()).unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, r#"{{"cmd":"resume"}}"#).unwrap();
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply).unwrap();
+
+        assert_eq!(
+            reply.trim_end(),
+            r#"{"ok":true,"paused":false,"executor":"mock"}"#
+        );
+        assert!(!ctx.pause.is_paused());
+        let _ = std::fs::remove_file(&path);
+    }
+}
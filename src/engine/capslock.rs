@@ -0,0 +1,142 @@
+//! CapsLock lock state for remapped CapsLock presses.
+//!
+//! The OS toggles the CapsLock lock on the press itself, before any rule
+//! runs. Where capture suppresses the original key (a grabbed evdev device,
+//! the Windows hook, an active macOS tap) the OS never sees that press, so a
+//! remapped CapsLock cannot toggle the lock. An observed press reached the OS
+//! anyway and has already toggled it. If the rules remapped that press, the
+//! user asked for another key rather than a lock change, so `CapsLockGuard`
+//! undoes the toggle with one injected CapsLock tap once the key is released.
+//! A CapsLock the rules pass through, or map to itself, keeps its toggle.
+
+use crate::platform::{Action, InputEvent, KeyCode, KeyState};
+
+/// Remembers an observed CapsLock press whose toggle must be undone.
+#[derive(Debug, Default)]
+pub(super) struct CapsLockGuard {
+    undo_on_release: bool,
+}
+
+impl CapsLockGuard {
+    /// Feed a captured event that went through the rules, with the actions
+    /// they made of it. Returns the keys to inject afterwards to restore the
+    /// lock state, which is nothing unless `event` released a remapped,
+    /// observed CapsLock.
+    pub(super) fn after(
+        &mut self,
+        event: &InputEvent,
+        actions: &[Action],
+    ) -> Vec<(KeyCode, KeyState)> {
+        if event.key != KeyCode::CapsLock || !event.observed {
+            return Vec::new();
+        }
+        match event.state {
+            KeyState::Down if !event.is_repeat => {
+                self.undo_on_release = !keeps_toggle(actions);
+                Vec::new()
+            }
+            KeyState::Down => Vec::new(),
+            KeyState::Up if std::mem::take(&mut self.undo_on_release) => vec![
+                (KeyCode::CapsLock, KeyState::Down),
+                (KeyCode::CapsLock, KeyState::Up),
+            ],
+            KeyState::Up => Vec::new(),
+        }
+    }
+}
+
+/// Whether the rules' output for a CapsLock press is still a CapsLock press,
+/// so the lock should toggle.
+pub(super) fn keeps_toggle(actions: &[Action]) -> bool {
+    actions.iter().any(|action| match action {
+        Action::InjectKey { key, .. } | Action::Remap { to: key, .. } => *key == KeyCode::CapsLock,
+        Action::KeyCombo(keys) => keys.contains(&KeyCode::CapsLock),
+        Action::Passthrough => true,
+        Action::Repeat { action, .. } | Action::RepeatWhileHeld { action, .. } => {
+            keeps_toggle(std::slice::from_ref(action.as_ref()))
+        }
+        Action::Sequence { steps, .. } => keeps_toggle(steps),
+        _ => false,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::platform::{Modifiers, WindowContext};
+
+    fn caps(state: KeyState, observed: bool) -> InputEvent {
+        InputEvent {
+            key: KeyCode::CapsLock,
+            state,
+            modifiers: Modifiers::default(),
+            window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed,
+            is_repeat: false,
+            position: None,
+        }
+    }
+
+    fn inject(key: KeyCode, state: KeyState) -> Action {
+        Action::InjectKey { key, state }
+    }
+
+    const TAP: [(KeyCode, KeyState); 2] = [
+        (KeyCode::CapsLock, KeyState::Down),
+        (KeyCode::CapsLock, KeyState::Up),
+    ];
+
+    #[test]
+    fn remapped_observed_capslock_is_toggled_back_on_release() {
+        let mut guard = CapsLockGuard::default();
+        let down = guard.after(
+            &caps(KeyState::Down, true),
+            &[inject(KeyCode::Escape, KeyState::Down)],
+        );
+        assert!(down.is_empty());
+        let up = guard.after(
+            &caps(KeyState::Up, true),
+            &[inject(KeyCode::Escape, KeyState::Up)],
+        );
+        assert_eq!(up, TAP);
+        // Only once per press.
+        assert!(guard.after(&caps(KeyState::Up, true), &[]).is_empty());
+    }
+
+    #[test]
+    fn capslock_mapped_to_itself_keeps_its_toggle() {
+        let mut guard = CapsLockGuard::default();
+        let to_itself = [inject(KeyCode::CapsLock, KeyState::Down)];
+        guard.after(&caps(KeyState::Down, true), &to_itself);
+        assert!(guard.after(&caps(KeyState::Up, true), &[]).is_empty());
+        assert!(keeps_toggle(&[Action::Passthrough]));
+    }
+
+    #[test]
+    fn suppressed_capslock_needs_nothing_undone() {
+        let mut guard = CapsLockGuard::default();
+        guard.after(&caps(KeyState::Down, false), &[Action::Suppress]);
+        assert!(guard.after(&caps(KeyState::Up, false), &[]).is_empty());
+    }
+
+    #[test]
+    fn remapped_outputs_do_not_keep_the_toggle() {
+        assert!(!keeps_toggle(&[]));
+        assert!(!keeps_toggle(&[Action::Suppress]));
+        assert!(!keeps_toggle(&[Action::KeyCombo(vec![
+            KeyCode::Ctrl,
+            KeyCode::Escape
+        ])]));
+        assert!(!keeps_toggle(&[Action::Exec {
+            command: "true".into()
+        }]));
+    }
+}
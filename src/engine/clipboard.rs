@@ -0,0 +1,210 @@
+//! Clipboard reads and writes for the clipboard actions, off the engine
+//! thread.
+//!
+//! Reaching the clipboard can block: Windows retries while another program
+//! holds it open, and Linux and macOS wait on a helper process. A worker
+//! thread does the work so captured keys keep flowing meanwhile; the engine
+//! picks up finished jobs in `Engine::poll`, in request order:
+//!   - a read for `Action::TypeClipboard` comes back as the text to type;
+//!   - a set for `Action::ClipboardSet` comes back as nothing;
+//!   - a paste for `Action::PasteText` sets the clipboard and comes back
+//!     once it is set, for the engine to press the paste shortcut.
+//!
+//! With `[clipboard] restore_after_ms` set, a paste first saves what the
+//! clipboard held, and the worker puts it back once that long has passed
+//! with no further paste. Pastes in between keep the first saved text, so
+//! a burst of them restores what was there before the burst.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::platform::{Clipboard, InputEvent};
+
+/// Work for the clipboard worker.
+enum Job {
+    /// Read the clipboard for this event's rule.
+    Read(InputEvent),
+    Set(String),
+    Paste {
+        text: String,
+        /// The event whose rule asked for the paste.
+        source: InputEvent,
+        /// How long after the paste to put the old text back, if at all.
+        restore: Option<Duration>,
+    },
+}
+
+/// A finished job the engine has to act on.
+pub(super) enum Done {
+    /// The clipboard's text, for a `TypeClipboard` asked for by this
+    /// event's rule.
+    Read(InputEvent, Option<String>),
+    /// The clipboard was set for a paste asked for by this event's rule.
+    Paste(InputEvent),
+}
+
+/// Handle to the clipboard worker. Dropping it ends the worker once its
+/// current job returns; a restore still waiting is made then.
+pub(super) struct ClipboardWorker {
+    jobs: Sender<Job>,
+    done: Receiver<Option<Done>>,
+    /// Reads and pastes sent whose result has not been taken yet.
+    pending: usize,
+}
+
+impl ClipboardWorker {
+    pub(super) fn spawn(clipboard: Box<dyn Clipboard>) -> Self {
+        let (jobs, inbox) = mpsc::channel();
+        let (outbox, done) = mpsc::channel();
+        thread::Builder::new()
+            .name("clipboard".into())
+            .spawn(move || work(clipboard.as_ref(), &inbox, &outbox))
+            .expect("failed to spawn clipboard thread");
+        Self {
+            jobs,
+            done,
+            pending: 0,
+        }
+    }
+
+    /// Ask the worker for the clipboard's text on behalf of `source`.
+    pub(super) fn request(&mut self, source: InputEvent) {
+        if self.jobs.send(Job::Read(source)).is_ok() {
+            self.pending += 1;
+        }
+    }
+
+    /// Set the clipboard to `text`.
+    pub(super) fn set(&mut self, text: String) {
+        if self.jobs.send(Job::Set(text)).is_err() {
+            log::warn!("engine: clipboard worker is gone; clipboard not set");
+        }
+    }
+
+    /// Set the clipboard to `text` for a paste on behalf of `source`, and
+    /// put the old text back `restore` later.
+    pub(super) fn paste(&mut self, text: String, source: InputEvent, restore: Option<Duration>) {
+        let job = Job::Paste {
+            text,
+            source,
+            restore,
+        };
+        if self.jobs.send(job).is_ok() {
+            self.pending += 1;
+        } else {
+            log::warn!("engine: clipboard worker is gone; nothing pasted");
+        }
+    }
+
+    /// The finished reads and pastes, oldest first.
+    pub(super) fn take(&mut self) -> Vec<Done> {
+        let mut done = Vec::new();
+        while let Ok(result) = self.done.try_recv() {
+            self.pending -= 1;
+            done.extend(result);
+        }
+        done
+    }
+
+    pub(super) fn pending(&self) -> bool {
+        self.pending > 0
+    }
+}
+
+/// The worker: run each job received, and restore the clipboard once due,
+/// until the `ClipboardWorker` is dropped.
+fn work(clipboard: &dyn Clipboard, inbox: &Receiver<Job>, outbox: &Sender<Option<Done>>) {
+    // The text to put back, and when.
+    let mut saved: Option<(Instant, Option<String>)> = None;
+    loop {
+        let next = match &saved {
+            None => inbox.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some((due, _)) => inbox.recv_timeout(due.saturating_duration_since(Instant::now())),
+        };
+        let job = match next {
+            Ok(job) => job,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some((_, text)) = saved.take() {
+                    restore(clipboard, text);
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                if let Some((_, text)) = saved.take() {
+                    restore(clipboard, text);
+                }
+                return;
+            }
+        };
+        let result = match job {
+            Job::Read(source) => Some(Done::Read(source, read(clipboard))),
+            Job::Set(text) => {
+                if let Err(e) = clipboard.write_text(&text) {
+                    log::warn!("engine: cannot set the clipboard: {e}");
+                }
+                // A later restore would undo this set.
+                saved = None;
+                continue;
+            }
+            Job::Paste {
+                text,
+                source,
+                restore,
+            } => {
+                let old = match (&saved, restore) {
+                    (Some((_, old)), Some(_)) => Some(old.clone()),
+                    (None, Some(_)) => match clipboard.read_text() {
+                        Ok(old) => Some(old),
+                        Err(e) => {
+                            log::warn!("engine: cannot save the clipboard: {e}; not restored");
+                            None
+                        }
+                    },
+                    (_, None) => None,
+                };
+                match clipboard.write_text(&text) {
+                    Ok(()) => {
+                        saved = old
+                            .zip(restore)
+                            .map(|(old, after)| (Instant::now() + after, old));
+                        Some(Done::Paste(source))
+                    }
+                    Err(e) => {
+                        log::warn!("engine: cannot set the clipboard: {e}; nothing pasted");
+                        None
+                    }
+                }
+            }
+        };
+        if outbox.send(result).is_err() {
+            return;
+        }
+    }
+}
+
+/// One read, with empty and failed reads logged and turned into `None`.
+fn read(clipboard: &dyn Clipboard) -> Option<String> {
+    match clipboard.read_text() {
+        Ok(Some(text)) => Some(text),
+        Ok(None) => {
+            log::info!("engine: clipboard holds no text; nothing typed");
+            None
+        }
+        Err(e) => {
+            log::warn!("engine: cannot read the clipboard: {e}");
+            None
+        }
+    }
+}
+
+/// Put `text` back on the clipboard after a paste. A clipboard that held
+/// no text is left with the pasted text, as there is nothing to set.
+fn restore(clipboard: &dyn Clipboard, text: Option<String>) {
+    let Some(text) = text else {
+        return;
+    };
+    if let Err(e) = clipboard.write_text(&text) {
+        log::warn!("engine: cannot restore the clipboard: {e}");
+    }
+}
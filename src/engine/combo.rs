@@ -0,0 +1,198 @@
+//! Key combination lowering: turns `Action::KeyCombo` into an
+//! `Action::Chord`, or into ordered key steps where a chord cannot say it.
+
+use std::collections::HashSet;
+
+use crate::platform::{KeyCode, KeyState, Modifiers};
+
+/// Modifiers, unified and both sides, in a fixed order so lowering is
+/// deterministic.
+const MODIFIERS: [KeyCode; 12] = [
+    KeyCode::Ctrl,
+    KeyCode::LeftCtrl,
+    KeyCode::RightCtrl,
+    KeyCode::Shift,
+    KeyCode::LeftShift,
+    KeyCode::RightShift,
+    KeyCode::Alt,
+    KeyCode::LeftAlt,
+    KeyCode::RightAlt,
+    KeyCode::Meta,
+    KeyCode::LeftMeta,
+    KeyCode::RightMeta,
+];
+
+/// Lower a combination into the `(key, state)` steps to inject, in order.
+///
+/// `keys` is modifiers followed by the main key. `held` is the set of keys the
+/// OS currently sees as down. The sequence is:
+///   1. release held modifiers that are not part of the combo,
+///   2. press combo modifiers that are not already held,
+///   3. tap the main key,
+///   4. release the modifiers pressed in step 2 (reverse order),
+///   5. re-press the modifiers released in step 1 (reverse order).
+///
+/// Modifiers the user already holds that belong to the combo are never
+/// touched, so their physical state survives the combo unchanged. A unified
+/// modifier in the combo is satisfied by either side being held.
+pub(super) fn steps(keys: &[KeyCode], held: &HashSet<KeyCode>) -> Vec<(KeyCode, KeyState)> {
+    let Some((&main, modifiers)) = keys.split_last() else {
+        return Vec::new();
+    };
+
+    let stray = stray(modifiers, held);
+    let missing = missing(modifiers, held);
+
+    let mut out = Vec::with_capacity(2 * (stray.len() + missing.len()) + 2);
+    out.extend(stray.iter().map(|&m| (m, KeyState::Up)));
+    out.extend(missing.iter().map(|&m| (m, KeyState::Down)));
+    out.push((main, KeyState::Down));
+    out.push((main, KeyState::Up));
+    out.extend(missing.iter().rev().map(|&m| (m, KeyState::Up)));
+    out.extend(stray.iter().rev().map(|&m| (m, KeyState::Down)));
+    out
+}
+
+/// The combination as one chord: the main key, and the modifiers to press
+/// around it, those not already held. `None` where there is no modifier to
+/// press, or where a chord cannot say it: a held modifier outside the
+/// combination must be let go first, or a right-side modifier in it is not
+/// held. A chord presses the left keys, so a left-side modifier is one.
+pub(super) fn chord(keys: &[KeyCode], held: &HashSet<KeyCode>) -> Option<(Modifiers, KeyCode)> {
+    let (&main, modifiers) = keys.split_last()?;
+    if !stray(modifiers, held).is_empty() {
+        return None;
+    }
+    let mut chord = Modifiers::default();
+    for m in missing(modifiers, held) {
+        let key = match m {
+            KeyCode::RightCtrl | KeyCode::RightShift | KeyCode::RightAlt | KeyCode::RightMeta => {
+                return None
+            }
+            m => m.unify_side(),
+        };
+        chord = chord.union(Modifiers::from_keycode(key)?);
+    }
+    (!chord.is_empty()).then_some((chord, main))
+}
+
+/// Held modifiers that are not part of the combination's `modifiers`, in
+/// `MODIFIERS` order.
+fn stray(modifiers: &[KeyCode], held: &HashSet<KeyCode>) -> Vec<KeyCode> {
+    MODIFIERS
+        .iter()
+        .copied()
+        .filter(|m| held.contains(m) && !modifiers.iter().any(|c| c.covers(*m)))
+        .collect()
+}
+
+/// The `modifiers` that are not already `held`, in order. A unified
+/// modifier is satisfied by either side being held.
+pub(super) fn missing(modifiers: &[KeyCode], held: &HashSet<KeyCode>) -> Vec<KeyCode> {
+    modifiers
+        .iter()
+        .copied()
+        .filter(|m| !held.iter().any(|&h| m.covers(h)))
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combo_with_nothing_held_wraps_main_key_in_modifiers() {
+        let got = steps(
+            &[KeyCode::Ctrl, KeyCode::Shift, KeyCode::T],
+            &HashSet::new(),
+        );
+        assert_eq!(
+            got,
+            vec![
+                (KeyCode::Ctrl, KeyState::Down),
+                (KeyCode::Shift, KeyState::Down),
+                (KeyCode::T, KeyState::Down),
+                (KeyCode::T, KeyState::Up),
+                (KeyCode::Shift, KeyState::Up),
+                (KeyCode::Ctrl, KeyState::Up),
+            ]
+        );
+    }
+
+    #[test]
+    fn already_held_combo_modifier_is_left_alone() {
+        let held: HashSet<KeyCode> = [KeyCode::Ctrl].into_iter().collect();
+        let got = steps(&[KeyCode::Ctrl, KeyCode::Shift, KeyCode::T], &held);
+        assert_eq!(
+            got,
+            vec![
+                (KeyCode::Shift, KeyState::Down),
+                (KeyCode::T, KeyState::Down),
+                (KeyCode::T, KeyState::Up),
+                (KeyCode::Shift, KeyState::Up),
+            ]
+        );
+    }
+
+    #[test]
+    fn held_modifier_outside_combo_is_released_then_restored() {
+        let held: HashSet<KeyCode> = [KeyCode::Alt, KeyCode::A].into_iter().collect();
+        let got = steps(&[KeyCode::Ctrl, KeyCode::C], &held);
+        assert_eq!(
+            got,
+            vec![
+                (KeyCode::Alt, KeyState::Up),
+                (KeyCode::Ctrl, KeyState::Down),
+                (KeyCode::C, KeyState::Down),
+                (KeyCode::C, KeyState::Up),
+                (KeyCode::Ctrl, KeyState::Up),
+                (KeyCode::Alt, KeyState::Down),
+            ]
+        );
+    }
+
+    #[test]
+    fn right_side_modifier_satisfies_unified_combo_key() {
+        let held: HashSet<KeyCode> = [KeyCode::RightMeta].into_iter().collect();
+        let got = steps(&[KeyCode::Meta, KeyCode::Tab], &held);
+        assert_eq!(
+            got,
+            vec![(KeyCode::Tab, KeyState::Down), (KeyCode::Tab, KeyState::Up)]
+        );
+    }
+
+    #[test]
+    fn chord_presses_only_modifiers_not_held() {
+        let held: HashSet<KeyCode> = [KeyCode::RightCtrl].into_iter().collect();
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(
+            chord(&[KeyCode::Ctrl, KeyCode::Shift, KeyCode::T], &held),
+            Some((shift, KeyCode::T))
+        );
+    }
+
+    #[test]
+    fn chord_is_declined_for_what_it_cannot_say() {
+        let ctrl: HashSet<KeyCode> = [KeyCode::Ctrl].into_iter().collect();
+        assert_eq!(chord(&[KeyCode::Ctrl, KeyCode::C], &ctrl), None);
+        let alt: HashSet<KeyCode> = [KeyCode::Alt].into_iter().collect();
+        assert_eq!(chord(&[KeyCode::Ctrl, KeyCode::C], &alt), None);
+        assert_eq!(
+            chord(&[KeyCode::RightAlt, KeyCode::E], &HashSet::new()),
+            None
+        );
+        assert_eq!(chord(&[], &HashSet::new()), None);
+    }
+
+    #[test]
+    fn empty_combo_produces_no_steps() {
+        assert!(steps(&[], &HashSet::new()).is_empty());
+    }
+}
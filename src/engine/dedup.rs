@@ -0,0 +1,107 @@
+//! Duplicate event filter for overlapping capture paths.
+//!
+//! Two capture paths can deliver the same physical key event, for example
+//! evdev capture on a Wayland session whose compositor also forwards it, or
+//! two device nodes for one keyboard. The copies arrive microseconds apart
+//! with the same key and state. A real key cannot do that: a second press
+//! needs a release in between, and auto-repeat fires tens of milliseconds
+//! apart. So an event is dropped only when the previous event for the same
+//! key had the same state and arrived within the window.
+//!
+//! Events do not carry a device yet, so duplicates are matched on key and
+//! state alone.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::platform::{KeyCode, KeyState};
+
+/// Drops an event identical to the previous one for its key when it arrives
+/// within `window`.
+pub(super) struct DedupFilter {
+    window: Duration,
+    /// Last admitted state and capture time per key.
+    last: HashMap<KeyCode, (KeyState, Instant)>,
+}
+
+impl DedupFilter {
+    pub(super) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Whether an event should be processed. Returns false for a duplicate,
+    /// which is not recorded, so the window stays anchored at the original.
+    pub(super) fn admit(&mut self, key: KeyCode, state: KeyState, at: Instant) -> bool {
+        if let Some(&(last_state, last_at)) = self.last.get(&key) {
+            if last_state == state && at.saturating_duration_since(last_at) <= self.window {
+                return false;
+            }
+        }
+        self.last.insert(key, (state, at));
+        true
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_micros(1000);
+
+    fn us(n: u64) -> Duration {
+        Duration::from_micros(n)
+    }
+
+    #[test]
+    fn second_identical_event_within_window_is_dropped() {
+        let t = Instant::now();
+        let mut filter = DedupFilter::new(WINDOW);
+        assert!(filter.admit(KeyCode::A, KeyState::Down, t));
+        assert!(!filter.admit(KeyCode::A, KeyState::Down, t + us(40)));
+        assert!(filter.admit(KeyCode::A, KeyState::Up, t + us(90_000)));
+        assert!(!filter.admit(KeyCode::A, KeyState::Up, t + us(90_050)));
+    }
+
+    #[test]
+    fn rapid_legitimate_presses_are_kept() {
+        let t = Instant::now();
+        let mut filter = DedupFilter::new(WINDOW);
+        // Down, Up, Down well inside the window: every one is real.
+        assert!(filter.admit(KeyCode::A, KeyState::Down, t));
+        assert!(filter.admit(KeyCode::A, KeyState::Up, t + us(100)));
+        assert!(filter.admit(KeyCode::A, KeyState::Down, t + us(200)));
+    }
+
+    #[test]
+    fn auto_repeat_outside_window_is_kept() {
+        let t = Instant::now();
+        let mut filter = DedupFilter::new(WINDOW);
+        assert!(filter.admit(KeyCode::A, KeyState::Down, t));
+        assert!(filter.admit(KeyCode::A, KeyState::Down, t + us(33_000)));
+    }
+
+    #[test]
+    fn keys_are_tracked_independently() {
+        let t = Instant::now();
+        let mut filter = DedupFilter::new(WINDOW);
+        assert!(filter.admit(KeyCode::Ctrl, KeyState::Down, t));
+        assert!(filter.admit(KeyCode::C, KeyState::Down, t + us(10)));
+        assert!(!filter.admit(KeyCode::Ctrl, KeyState::Down, t + us(20)));
+    }
+
+    #[test]
+    fn window_is_measured_from_the_original() {
+        let t = Instant::now();
+        let mut filter = DedupFilter::new(WINDOW);
+        assert!(filter.admit(KeyCode::A, KeyState::Down, t));
+        assert!(!filter.admit(KeyCode::A, KeyState::Down, t + us(900)));
+        assert!(filter.admit(KeyCode::A, KeyState::Down, t + us(1_100)));
+    }
+}
@@ -0,0 +1,554 @@
+//! Starting `Action::Exec` commands, off the engine thread.
+//!
+//! Every backend starts commands the same way, so it is done once here
+//! rather than in each executor. The engine drops a start of a command
+//! that comes sooner than `[exec] min_interval_ms` after its last one, as a
+//! held hotkey's auto-repeat would, and hands the rest to a worker thread.
+//! Each command is started:
+//!   - when written as a line, through `sh -c` on Unix and `cmd /C` on
+//!     Windows; when written as a list, as that program with those
+//!     arguments and no shell. No console window is opened on Windows;
+//!   - with stdin closed, in the rule's `cwd` or else `[exec] cwd`, and
+//!     with `[exec] env` and then the rule's `env` added to the daemon's
+//!     environment;
+//!   - on Unix, in a process group of its own, so a Ctrl+C meant for the
+//!     daemon's terminal does not reach it.
+//!
+//! The worker waits on every child it started and reaps it once it exits,
+//! so none is left a zombie. Failures to start are logged with the command.
+//!
+//! A command can also be started with its stdout captured (`capture`). The
+//! first `MAX_CAPTURE_BYTES` are kept and the rest read and dropped, so the
+//! command never blocks on a full pipe. One still running after
+//! `[exec] capture_timeout_ms` is killed along with its process group (its
+//! process tree on Windows). Either way the outcome comes back through
+//! `take_finished`, which the engine calls from `Engine::poll`, so the
+//! completion callback runs on the engine thread.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::Engine;
+use crate::config::ExecSettings;
+use crate::platform::{CommandLine, ExecCommand};
+
+/// How often the worker checks on running children.
+const REAP_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often the worker checks on running children while one is captured,
+/// so its output arrives soon after it exits.
+const CAPTURE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The most of a captured command's stdout that is kept.
+pub(super) const MAX_CAPTURE_BYTES: usize = 64 * 1024;
+
+/// How a captured command ended, and what it printed.
+#[derive(Debug)]
+pub(super) struct Captured {
+    /// Its exit status; `None` when it timed out or did not start.
+    pub(super) status: Option<ExitStatus>,
+    /// Its stdout, up to `MAX_CAPTURE_BYTES`, decoded lossily.
+    pub(super) stdout: String,
+}
+
+/// Run on the engine thread once a captured command ends.
+pub(super) type OnDone = Box<dyn FnOnce(&mut Engine, Captured) + Send>;
+
+/// Work for the exec worker.
+enum Job {
+    Start(String, Command),
+    Capture {
+        id: u64,
+        command: String,
+        cmd: Command,
+        timeout: Duration,
+    },
+}
+
+/// Handle to the exec worker. Dropping it ends the worker; children still
+/// running are left to finish on their own.
+pub(super) struct Launcher {
+    jobs: Sender<Job>,
+    finished: Receiver<(u64, Captured)>,
+    settings: ExecSettings,
+    /// When each command was last started, for `min_interval`.
+    started: HashMap<String, Instant>,
+    /// The callbacks of captured commands still running, by id.
+    callbacks: HashMap<u64, OnDone>,
+    next_id: u64,
+}
+
+impl Launcher {
+    pub(super) fn spawn(settings: ExecSettings) -> Self {
+        let (jobs, inbox) = mpsc::channel();
+        let (outbox, finished) = mpsc::channel();
+        thread::Builder::new()
+            .name("exec".into())
+            .spawn(move || work(inbox, outbox))
+            .expect("failed to spawn exec thread");
+        Self {
+            jobs,
+            finished,
+            settings,
+            started: HashMap::new(),
+            callbacks: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Use `settings` for the commands started from now on.
+    pub(super) fn configure(&mut self, settings: ExecSettings) {
+        self.settings = settings;
+    }
+
+    /// Start `command`, unless it was started less than `min_interval` ago.
+    pub(super) fn launch(&mut self, command: &ExecCommand) {
+        let command_line = command.to_string();
+        if !self.admit(&command_line, Instant::now()) {
+            log::debug!("exec: '{command}' started too recently; dropped");
+            return;
+        }
+        let cmd = build_command(command, &self.settings);
+        if self.jobs.send(Job::Start(command_line, cmd)).is_err() {
+            log::warn!("exec: worker is gone; '{command}' not started");
+        }
+    }
+
+    /// Start `command` with its stdout captured, unless it was started less
+    /// than `min_interval` ago, and have `on_done` called with the outcome.
+    pub(super) fn capture(&mut self, command: &ExecCommand, on_done: OnDone) {
+        let command_line = command.to_string();
+        if !self.admit(&command_line, Instant::now()) {
+            log::debug!("exec: '{command}' started too recently; dropped");
+            return;
+        }
+        let mut cmd = build_command(command, &self.settings);
+        cmd.stdout(Stdio::piped()).stderr(Stdio::null());
+        let id = self.next_id;
+        self.next_id += 1;
+        let job = Job::Capture {
+            id,
+            command: command_line,
+            cmd,
+            timeout: self.settings.capture_timeout,
+        };
+        if self.jobs.send(job).is_err() {
+            log::warn!("exec: worker is gone; '{command}' not started");
+            return;
+        }
+        self.callbacks.insert(id, on_done);
+    }
+
+    /// The captured commands that ended since the last call, with their
+    /// callbacks, in the order they ended.
+    pub(super) fn take_finished(&mut self) -> Vec<(OnDone, Captured)> {
+        let mut done = Vec::new();
+        while let Ok((id, captured)) = self.finished.try_recv() {
+            if let Some(on_done) = self.callbacks.remove(&id) {
+                done.push((on_done, captured));
+            }
+        }
+        done
+    }
+
+    /// Whether a captured command is still running.
+    pub(super) fn capturing(&self) -> bool {
+        !self.callbacks.is_empty()
+    }
+
+    /// Whether `command` may start at `now`, recording the start if so.
+    fn admit(&mut self, command: &str, now: Instant) -> bool {
+        let interval = self.settings.min_interval;
+        // Entries past the interval no longer limit anything.
+        self.started
+            .retain(|_, at| now.saturating_duration_since(*at) < interval);
+        if self.started.contains_key(command) {
+            return false;
+        }
+        if !interval.is_zero() {
+            self.started.insert(command.to_owned(), now);
+        }
+        true
+    }
+}
+
+/// `command` ready to start, set up per `settings` and its own `cwd` and
+/// `env`.
+fn build_command(command: &ExecCommand, settings: &ExecSettings) -> Command {
+    let mut cmd = match &command.line {
+        CommandLine::Shell(line) => shell(line),
+        CommandLine::Argv(argv) => {
+            // The config never gives an empty list.
+            let (program, args) = argv.split_first().expect("argv names a program");
+            let mut cmd = Command::new(program);
+            // On Windows, `arg` quotes each argument so that the program's
+            // `CommandLineToArgvW` splits the line back into the same list.
+            cmd.args(args);
+            cmd
+        }
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        /// `CREATE_NO_WINDOW`: run the command without a console window.
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    cmd.stdin(Stdio::null());
+    if let Some(cwd) = command.cwd.as_ref().or(settings.cwd.as_ref()) {
+        cmd.current_dir(cwd);
+    }
+    cmd.envs(settings.env.iter().map(|(k, v)| (k, v)));
+    cmd.envs(command.env.iter().map(|(k, v)| (k, v)));
+    cmd
+}
+
+/// `line` as run through the platform shell.
+fn shell(line: &str) -> Command {
+    #[cfg(not(target_os = "windows"))]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(line);
+        cmd
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        let mut cmd = Command::new("cmd");
+        // As written: cmd.exe parses its own command line, and the quoting
+        // `arg` adds would reach it.
+        cmd.arg("/C").raw_arg(line);
+        cmd
+    }
+}
+
+/// A child the worker started and has not reaped yet.
+struct Running {
+    command: String,
+    child: Child,
+    capture: Option<Capture>,
+}
+
+/// The capture of a running child's stdout.
+struct Capture {
+    id: u64,
+    deadline: Instant,
+    /// Reads stdout until the pipe closes.
+    reader: JoinHandle<String>,
+}
+
+/// The worker: start each command received, and reap children as they
+/// exit, until the `Launcher` is dropped.
+fn work(inbox: Receiver<Job>, outbox: Sender<(u64, Captured)>) {
+    let mut children: Vec<Running> = Vec::new();
+    loop {
+        // Block while there is nothing to reap.
+        let next = if children.is_empty() {
+            inbox.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else if children.iter().any(|c| c.capture.is_some()) {
+            inbox.recv_timeout(CAPTURE_INTERVAL)
+        } else {
+            inbox.recv_timeout(REAP_INTERVAL)
+        };
+        match next {
+            Ok(Job::Start(command, mut cmd)) => match cmd.spawn() {
+                Ok(child) => {
+                    log::info!("exec: started '{command}' (pid {})", child.id());
+                    children.push(Running {
+                        command,
+                        child,
+                        capture: None,
+                    });
+                }
+                Err(e) => log::warn!("exec: cannot start '{command}': {e}"),
+            },
+            Ok(Job::Capture {
+                id,
+                command,
+                mut cmd,
+                timeout,
+            }) => match cmd.spawn() {
+                Ok(mut child) => {
+                    log::info!("exec: started '{command}' (pid {}), capturing", child.id());
+                    let stdout = child.stdout.take();
+                    children.push(Running {
+                        command,
+                        child,
+                        capture: Some(Capture {
+                            id,
+                            deadline: Instant::now() + timeout,
+                            reader: thread::spawn(move || read_capped(stdout)),
+                        }),
+                    });
+                }
+                Err(e) => {
+                    log::warn!("exec: cannot start '{command}': {e}");
+                    let captured = Captured {
+                        status: None,
+                        stdout: String::new(),
+                    };
+                    if outbox.send((id, captured)).is_err() {
+                        return;
+                    }
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        children.retain_mut(|running| !reap(running, &outbox));
+    }
+}
+
+/// Reap `running` if it is done, returning whether it was. A captured
+/// child is done once its stdout is closed as well, so a background job
+/// it left holding the pipe keeps it running until the timeout.
+fn reap(running: &mut Running, outbox: &Sender<(u64, Captured)>) -> bool {
+    let status = match running.child.try_wait() {
+        Ok(status) => status,
+        Err(e) => {
+            log::warn!("exec: cannot wait on '{}': {e}", running.command);
+            if let Some(capture) = running.capture.take() {
+                capture.finish(None, outbox);
+            }
+            return true;
+        }
+    };
+    let Some(capture) = running.capture.as_ref() else {
+        if let Some(status) = status.filter(|s| !s.success()) {
+            log::info!("exec: '{}' exited with {status}", running.command);
+        }
+        return status.is_some();
+    };
+    let status = match status {
+        Some(status) if capture.reader.is_finished() => {
+            if !status.success() {
+                log::info!("exec: '{}' exited with {status}", running.command);
+            }
+            Some(status)
+        }
+        _ if Instant::now() >= capture.deadline => {
+            log::warn!("exec: '{}' timed out; killed", running.command);
+            kill_tree(&mut running.child);
+            None
+        }
+        _ => return false,
+    };
+    if let Some(capture) = running.capture.take() {
+        capture.finish(status, outbox);
+    }
+    true
+}
+
+impl Capture {
+    /// Send the outcome of the capture to the launcher.
+    fn finish(self, status: Option<ExitStatus>, outbox: &Sender<(u64, Captured)>) {
+        let captured = Captured {
+            status,
+            stdout: self.reader.join().unwrap_or_default(),
+        };
+        // A send fails only once the launcher is gone.
+        let _ = outbox.send((self.id, captured));
+    }
+}
+
+/// Everything read from `stdout` until it closes, keeping the first
+/// `MAX_CAPTURE_BYTES`.
+fn read_capped(stdout: Option<ChildStdout>) -> String {
+    let Some(stdout) = stdout else {
+        return String::new();
+    };
+    let mut kept = Vec::new();
+    let mut stdout = stdout.take(MAX_CAPTURE_BYTES as u64);
+    // A read error ends the capture with what was read before it.
+    let _ = stdout.read_to_end(&mut kept);
+    // Drain the rest, so the command does not block on a full pipe.
+    let _ = std::io::copy(&mut stdout.into_inner(), &mut std::io::sink());
+    String::from_utf8_lossy(&kept).into_owned()
+}
+
+/// Kill `child` and every process it started, and reap it.
+fn kill_tree(child: &mut Child) {
+    #[cfg(not(target_os = "windows"))]
+    {
+        extern "C" {
+            fn kill(pid: i32, sig: i32) -> i32;
+        }
+        const SIGKILL: i32 = 9;
+        // The child leads a process group of its own (see `build_command`),
+        // whose id is its pid.
+        // SAFETY: kill has no memory preconditions; a group that is already
+        // gone makes it fail with ESRCH, which is fine.
+        unsafe { kill(-(child.id() as i32), SIGKILL) };
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        // cmd.exe's own children are not killed with it.
+        let _ = Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &child.id().to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .creation_flags(CREATE_NO_WINDOW)
+            .status();
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn launcher(min_interval_ms: u64) -> Launcher {
+        Launcher::spawn(ExecSettings {
+            min_interval: Duration::from_millis(min_interval_ms),
+            ..ExecSettings::default()
+        })
+    }
+
+    #[test]
+    fn repeated_starts_within_the_interval_are_dropped() {
+        let mut launcher = launcher(250);
+        let t0 = Instant::now();
+        assert!(launcher.admit("kitty", t0));
+        assert!(!launcher.admit("kitty", t0 + Duration::from_millis(100)));
+        // Limited per command.
+        assert!(launcher.admit("firefox", t0 + Duration::from_millis(100)));
+        assert!(launcher.admit("kitty", t0 + Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn zero_interval_admits_every_start() {
+        let mut launcher = launcher(0);
+        let now = Instant::now();
+        assert!(launcher.admit("kitty", now));
+        assert!(launcher.admit("kitty", now));
+        assert!(launcher.started.is_empty());
+    }
+
+    /// Capture `command` with `settings` and wait for it to end.
+    #[cfg(not(target_os = "windows"))]
+    fn capture(command: &ExecCommand, settings: ExecSettings) -> Captured {
+        let mut launcher = Launcher::spawn(settings);
+        launcher.capture(command, Box::new(|_, _| {}));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if let Some((_, captured)) = launcher.take_finished().pop() {
+                assert!(!launcher.capturing());
+                return captured;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("'{command}' never ended");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn captured_output_comes_back_capped() {
+        let captured = capture(&"echo hello".into(), ExecSettings::default());
+        assert!(captured.status.is_some_and(|s| s.success()));
+        assert_eq!(captured.stdout, "hello\n");
+
+        let captured = capture(&"yes | head -c 100000".into(), ExecSettings::default());
+        assert_eq!(captured.stdout.len(), MAX_CAPTURE_BYTES);
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn captured_command_past_the_timeout_is_killed_with_its_group() {
+        let settings = ExecSettings {
+            capture_timeout: Duration::from_millis(50),
+            ..ExecSettings::default()
+        };
+        let started = Instant::now();
+        // The background sleep holds stdout open; it dies with the group.
+        let captured = capture(&"echo partial; sleep 30 & sleep 30".into(), settings);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(captured.status.is_none());
+        assert_eq!(captured.stdout, "partial\n");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn argv_arguments_arrive_as_written() {
+        let command = ExecCommand {
+            line: CommandLine::Argv(
+                [
+                    "printf",
+                    "%s|",
+                    "hi there",
+                    "say \"x\"",
+                    "it's",
+                    "$HOME",
+                    "héllo",
+                ]
+                .map(String::from)
+                .to_vec(),
+            ),
+            cwd: None,
+            env: Vec::new(),
+        };
+        let captured = capture(&command, ExecSettings::default());
+        assert_eq!(captured.stdout, "hi there|say \"x\"|it's|$HOME|héllo|");
+    }
+
+    #[test]
+    fn rule_cwd_and_env_go_over_the_settings() {
+        let settings = ExecSettings {
+            cwd: Some("/tmp".into()),
+            env: vec![("LANG".into(), "C".into())],
+            ..ExecSettings::default()
+        };
+        let command = ExecCommand {
+            line: CommandLine::Argv(vec!["notify-send".into(), "hi there".into()]),
+            cwd: Some("/srv".into()),
+            env: vec![("LANG".into(), "de_DE.UTF-8".into())],
+        };
+        let cmd = build_command(&command, &settings);
+        assert_eq!(cmd.get_program(), "notify-send");
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), vec!["hi there"]);
+        assert_eq!(cmd.get_current_dir(), Some(std::path::Path::new("/srv")));
+        // The rule's value is set last, so it is the one the child sees.
+        let env: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(env, vec![("LANG".as_ref(), Some("de_DE.UTF-8".as_ref()))]);
+    }
+
+    #[test]
+    fn shell_command_applies_settings() {
+        let settings = ExecSettings {
+            cwd: Some("/tmp".into()),
+            env: vec![("LANG".into(), "C".into())],
+            ..ExecSettings::default()
+        };
+        let cmd = build_command(&"echo hi".into(), &settings);
+        assert_eq!(cmd.get_current_dir(), Some(std::path::Path::new("/tmp")));
+        let env: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(env, vec![("LANG".as_ref(), Some("C".as_ref()))]);
+        #[cfg(not(target_os = "windows"))]
+        {
+            assert_eq!(cmd.get_program(), "sh");
+            let args: Vec<_> = cmd.get_args().collect();
+            assert_eq!(args, vec!["-c", "echo hi"]);
+        }
+    }
+}
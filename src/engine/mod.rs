@@ -1 +1,2636 @@
 //! Engine module: orchestrates all subsystems.
+//!
+//! `Engine` owns the rule engine and the platform action executor. Each
+//! captured event is resolved to an action by the rule engine; the engine then
+//! lowers composite actions (such as `Action::KeyCombo`) into primitive
+//! injections before handing them to the executor. Lowering lives here rather
+//! than in each backend so that all platforms share one implementation and the
+//! OS-visible held-key and NumLock state is tracked in a single place.
+//!
+//! The engine can be paused. While paused every key passes through unchanged;
+//! only `toggle_pause` hotkeys are still matched, so the user can always get
+//! back. The pause flag is shared through a `PauseHandle` so the control socket
+//! can flip it from another thread. Pausing also asks capture to listen only
+//! (see `ListenOnly`); events it then only observed have already reached
+//! applications and are not injected again.
+//!
+//! While the focused window matches a `[[passthrough]]` entry, the engine
+//! passes every key through as if paused, without touching the user's pause.
+//! The switch happens when capture reports the focus change (see `focus`),
+//! or failing that on the first key read in a new window. Keys held through
+//! the rules at that moment are released through them, and any other key
+//! still held down by an injection is released too, so nothing stays down
+//! in the passthrough app.
+//!
+//! With `[safety] key` set, rules are gated the same way: they only apply
+//! while the safety key is held, and every other key passes through. The
+//! safety key itself is never emitted.
+//!
+//! A remapped CapsLock never changes the lock state: where the press reached
+//! the OS anyway, the toggle is undone once the key is released (see
+//! `capslock`).
+//!
+//! Every captured key that is not re-emitted passes through `suppressed` with
+//! a `SuppressReason`; with `[debug] log_suppressed = true` each one is logged
+//! at info, whichever capture backend delivered it.
+//!
+//! `Action::TypeClipboard` reads the clipboard on a worker thread (see
+//! `clipboard`). The text is typed from `poll`, which the event loop calls
+//! on every iteration and more often while a read is outstanding, and
+//! dropped if the engine was paused meanwhile.
+//! `Action::ClipboardSet` and `Action::PasteText` set it on the same
+//! worker; a paste's shortcut is pressed from `poll` once the clipboard
+//! holds the text, and dropped if the engine was paused meanwhile.
+//!
+//! Text, from `Action::TypeString` and the clipboard, is typed the way the
+//! executor reports it can (see `text`): as whole strings, character by
+//! character, or as key presses, with `[executor] type_delay_ms` between
+//! characters. The pause is a delay like `Action::Delay`, so the rest of
+//! the text is typed from `poll` and the engine never blocks on it. Where
+//! the executor has no text path, `Action::InjectChar` is typed as a key
+//! press as well.
+//!
+//! `Action::Exec` commands are started by the engine's launcher (see
+//! `exec`) rather than by the executor, on every platform alike. Without a
+//! launcher they go to the executor like any other action, which is how
+//! `simulate` reports them without running them. `Action::TypeOutput`
+//! starts its command the same way with stdout captured; the launcher
+//! hands back the output in `poll`, where it is typed.
+//!
+//! The engine interprets `Action::Sequence`. A rule's output, like a
+//! sequence, runs in order up to the first `Action::Delay`; the rest is set
+//! aside with its due time and run from `poll` once it is due, so a long
+//! delay in one rule holds up nothing else. Pausing drops what was set
+//! aside. `Action::Repeat` runs as a sequence of its copies, with
+//! `[executor] type_delay_ms` between them, so the steps after it wait for
+//! every copy.
+//!
+//! `Action::RepeatWhileHeld` runs from `poll` the same way: the engine
+//! keeps one timer per held key and drops it on the key's Up, on pausing,
+//! and whenever held keys are released through the rules.
+//!
+//! `unsupported` checks the rules against the executor's `Capabilities`
+//! (see `support`), so rules that can never work are reported when loaded.
+//!
+//! `simulate` runs the same engine over a list of events without executing
+//! anything, for config tooling and tests.
+
+mod capslock;
+mod clipboard;
+mod combo;
+mod dedup;
+mod exec;
+mod numpad;
+mod support;
+mod text;
+// Public API for tooling; the daemon itself never calls it.
+#[cfg_attr(not(test), allow(dead_code))]
+mod simulate;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, ExecSettings, HotkeyAction, NumpadPolicy, PassthroughRule};
+use crate::platform::{
+    Action, ActionExecutor, Capabilities, Clipboard, InputEvent, KeyCode, KeyState, ListenOnly,
+    Modifiers, PlatformError, SessionLock, StepFailure, Suspension, TextInjection, WindowContext,
+    MAX_HELD_REPEATS, MAX_SEQUENCE_DEPTH,
+};
+use crate::rule_engine::{RuleEngine, RuleState};
+use capslock::CapsLockGuard;
+use clipboard::{ClipboardWorker, Done};
+use dedup::DedupFilter;
+use exec::{Captured, Launcher};
+#[allow(unused_imports)]
+pub use simulate::simulate;
+pub use support::{unsupported, Unsupported};
+
+/// How often the event loop should call `Engine::poll` while a clipboard
+/// read or a delayed action is outstanding.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// ---------------------------------------------------------------------------
+// Pause handle
+// ---------------------------------------------------------------------------
+
+/// Shared, thread-safe pause flag. Clones refer to the same flag.
+///
+/// Rules are paused while the user has paused them, while the capture
+/// backend has suspended them, or while the session is locked or
+/// disconnected. `set_paused` and `toggle` only change the user's pause;
+/// the others come and go on their own.
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle {
+    paused: Arc<AtomicBool>,
+    suspension: Suspension,
+    session: SessionLock,
+    /// Set while the focused window matches a `[[passthrough]]` entry.
+    app_passthrough: Arc<AtomicBool>,
+    /// Follows the user's pause and `app_passthrough`.
+    listen_only: ListenOnly,
+}
+
+impl PauseHandle {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+            || self.suspension.reason().is_some()
+            || self.session.reason().is_some()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+        self.sync_listen_only();
+    }
+
+    /// Set the user's pause to `paused`, or flip it when `None`, and
+    /// return its new state. The one way every front-end changes it, so
+    /// hotkeys, rule output, and control commands behave alike.
+    pub fn set_or_toggle(&self, paused: Option<bool>) -> bool {
+        match paused {
+            Some(paused) => {
+                self.set_paused(paused);
+                paused
+            }
+            None => self.toggle(),
+        }
+    }
+
+    /// Flip the user's pause and return its new state.
+    pub fn toggle(&self) -> bool {
+        let paused = !self.paused.fetch_xor(true, Ordering::Relaxed);
+        self.sync_listen_only();
+        paused
+    }
+
+    /// Whether the focused window is one the rules pass through.
+    pub fn app_passthrough(&self) -> bool {
+        self.app_passthrough.load(Ordering::Relaxed)
+    }
+
+    fn set_app_passthrough(&self, on: bool) {
+        self.app_passthrough.store(on, Ordering::Relaxed);
+        self.sync_listen_only();
+    }
+
+    fn sync_listen_only(&self) {
+        self.listen_only
+            .set(self.paused.load(Ordering::Relaxed) || self.app_passthrough());
+    }
+
+    /// Why the capture backend or the session has suspended rules, if
+    /// either has.
+    pub fn suspended_reason(&self) -> Option<String> {
+        self.suspension
+            .reason()
+            .or_else(|| self.session.reason().map(String::from))
+    }
+
+    /// The suspension flag to hand to the capture backend.
+    pub fn suspension(&self) -> Suspension {
+        self.suspension.clone()
+    }
+
+    /// The session state to hand to the platform's session watcher.
+    pub fn session(&self) -> SessionLock {
+        self.session.clone()
+    }
+
+    /// The listen-only request to hand to the capture backend.
+    pub fn listen_only(&self) -> ListenOnly {
+        self.listen_only.clone()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Suppression reasons
+// ---------------------------------------------------------------------------
+
+/// Why a captured key was not re-emitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressReason {
+    /// A rule returned `Action::Suppress`, e.g. the release of a hotkey
+    /// trigger or of a key remapped to a combination.
+    Explicit,
+    /// A rule matched and ran but emits no key, e.g. an exec hotkey or the
+    /// pause toggle.
+    NoOutput,
+    /// The event bus was full and dropped the event before it reached the
+    /// engine.
+    Overflow,
+    /// `[capture] dedup` dropped the event as a copy of the one before it.
+    Duplicate,
+}
+
+impl fmt::Display for SuppressReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SuppressReason::Explicit => "suppressed by rule",
+            SuppressReason::NoOutput => "matched rule with no key output",
+            SuppressReason::Overflow => "dropped on event bus overflow",
+            SuppressReason::Duplicate => "dropped as a duplicate",
+        })
+    }
+}
+
+/// Receives every suppressed key. The default logs at info.
+type SuppressObserver = Box<dyn FnMut(KeyCode, KeyState, SuppressReason) + Send>;
+
+fn log_suppressed(key: KeyCode, state: KeyState, reason: SuppressReason) {
+    log::info!("engine: {key:?} {state:?} not emitted: {reason}");
+}
+
+/// The chords of `config`'s `toggle_pause`, `pause`, and `resume` hotkeys,
+/// each with the pause it sets.
+fn pause_chords(config: &Config) -> Vec<(HashSet<KeyCode>, Option<bool>)> {
+    config
+        .hotkeys
+        .iter()
+        .filter_map(|h| match h.action {
+            HotkeyAction::SetPaused(paused) => Some((h.keys.iter().copied().collect(), paused)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The shortcut that pastes the clipboard in most programs.
+fn paste_keys() -> Vec<KeyCode> {
+    let modifier = if cfg!(target_os = "macos") {
+        KeyCode::Meta
+    } else {
+        KeyCode::Ctrl
+    };
+    vec![modifier, KeyCode::V]
+}
+
+/// A sequence being run: its steps, the next one to run, and what a step
+/// that fails does to the rest.
+struct Frame {
+    steps: Vec<Action>,
+    next: usize,
+    on_error: StepFailure,
+    /// Typed text (see `Engine::typed`), whose steps go to the executor as
+    /// they are; a `Sequence` among them is one character's actions, sent
+    /// as one batch.
+    typed: bool,
+}
+
+impl Frame {
+    fn new(steps: Vec<Action>, on_error: StepFailure) -> Self {
+        Self {
+            steps,
+            next: 0,
+            on_error,
+            typed: false,
+        }
+    }
+}
+
+/// `delay` as a step, or `None` if it rounds down to nothing.
+fn pause(delay: Duration) -> Option<Action> {
+    u64::try_from(delay.as_millis())
+        .ok()
+        .filter(|&ms| ms > 0)
+        .map(|ms| Action::Delay { ms })
+}
+
+/// An `Action::Repeat` as a frame: `count` copies of `action`, with a
+/// `delay` pause between two of them. A copy that fails stops the rest, and
+/// the sequence the repeat is in sees the failure.
+fn repetitions(action: &Action, count: u32, delay: Duration) -> Frame {
+    let pause = pause(delay);
+    let mut steps = Vec::new();
+    for i in 0..count {
+        if i > 0 {
+            steps.extend(pause.clone());
+        }
+        steps.push(action.clone());
+    }
+    Frame::new(steps, StepFailure::Abort)
+}
+
+/// Type what a `TypeOutput` command, started for `source`, printed, if it
+/// succeeded.
+fn type_output(engine: &mut Engine, source: &InputEvent, captured: Captured) {
+    match captured.status {
+        Some(status) if status.success() => {}
+        // The launcher logged why.
+        _ => return,
+    }
+    if engine.paused.is_paused() {
+        log::info!("engine: paused; command output dropped");
+        return;
+    }
+    let text = captured
+        .stdout
+        .strip_suffix('\n')
+        .unwrap_or(&captured.stdout);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    engine.type_text(text, source);
+}
+
+/// Whether `action` is or holds an `Action::RepeatWhileHeld`.
+fn repeats_while_held(action: &Action) -> bool {
+    match action {
+        Action::RepeatWhileHeld { .. } => true,
+        Action::Repeat { action, .. } => repeats_while_held(action),
+        Action::Sequence { steps, .. } => steps.iter().any(repeats_while_held),
+        _ => false,
+    }
+}
+
+/// An `Action::RepeatWhileHeld` whose key is still held.
+struct HeldRepeat {
+    action: Action,
+    interval: Duration,
+    /// When it runs next.
+    due: Instant,
+    /// How many times it has run.
+    runs: u32,
+    /// The captured Down that started it.
+    source: InputEvent,
+}
+
+/// The rest of a rule's output after an `Action::Delay`.
+struct Deferred {
+    due: Instant,
+    /// The sequences the delay was in, innermost last.
+    frames: Vec<Frame>,
+    /// The captured event the rule matched.
+    source: InputEvent,
+}
+
+// ---------------------------------------------------------------------------
+// Engine
+// ---------------------------------------------------------------------------
+
+/// Drives events through the rule engine and executes the resulting actions.
+pub struct Engine {
+    rules: RuleEngine,
+    /// Chord state the rules read, updated from each `Decision`.
+    rule_state: RuleState,
+    executor: Box<dyn ActionExecutor>,
+    /// Keys the engine has injected Down and not yet released: the held state
+    /// as seen by the OS. Used to leave user-held modifiers undisturbed when
+    /// lowering key combinations.
+    injected_held: HashSet<KeyCode>,
+    /// NumLock state as seen by the OS: seeded from the executor at startup,
+    /// then flipped on every injected NumLock press. `None` when unknown.
+    numlock: Option<bool>,
+    numpad_policy: NumpadPolicy,
+    paused: PauseHandle,
+    /// Chords of `toggle_pause` hotkeys, matched ahead of the rule engine.
+    pause_chords: Vec<(HashSet<KeyCode>, Option<bool>)>,
+    /// Physically held keys, for matching `pause_chords` and `safety_key`.
+    held: HashSet<KeyCode>,
+    /// Rules are only applied while this key is held, if set.
+    safety_key: Option<KeyCode>,
+    /// Windows in which no rule applies.
+    passthroughs: Vec<PassthroughRule>,
+    /// The window of the last event, to notice focus changes.
+    focus: WindowContext,
+    /// Trigger keys whose Down toggled pause; their Up is swallowed too.
+    pause_triggers: HashSet<KeyCode>,
+    /// Keys whose Down went through the rule engine. Their Up must as well,
+    /// even if pause was toggled in between, so remap and chord state stays
+    /// balanced.
+    rule_downs: HashSet<KeyCode>,
+    /// Undoes the lock toggle of observed, remapped CapsLock presses.
+    capslock: CapsLockGuard,
+    /// Set when `[capture] dedup` is on.
+    dedup: Option<DedupFilter>,
+    /// Set when `[debug] log_suppressed` is on.
+    suppress_observer: Option<SuppressObserver>,
+    /// Reads and sets the clipboard for the clipboard actions; set by
+    /// `with_clipboard`.
+    clipboard: Option<ClipboardWorker>,
+    /// How long after a paste the clipboard gets its old text back.
+    clipboard_restore: Option<Duration>,
+    /// Pause between two typed characters.
+    type_delay: Duration,
+    /// Starts `Exec` and `TypeOutput` commands; set by `with_launcher`.
+    launcher: Option<Launcher>,
+    /// Rule output waiting out an `Action::Delay`, in the order it was set
+    /// aside.
+    deferred: Vec<Deferred>,
+    /// Running `RepeatWhileHeld` actions, at most one per key.
+    held_repeats: Vec<HeldRepeat>,
+    /// How many times the executor has failed, so a sequence can tell
+    /// whether a step went through.
+    failures: Cell<usize>,
+    /// Whether the session was locked or disconnected at the last
+    /// `sync_session`.
+    session_away: bool,
+    /// Collects the actions `run` is given while `batched` runs, to go to
+    /// the executor in one call.
+    batch: RefCell<Option<Vec<Action>>>,
+}
+
+impl Engine {
+    /// Build an engine from the parsed configuration and a platform executor.
+    pub fn new(config: &Config, executor: Box<dyn ActionExecutor>) -> Self {
+        let numlock = executor.lock_state(KeyCode::NumLock);
+        log::debug!("engine: initial NumLock state {numlock:?}");
+        Self {
+            rules: RuleEngine::new(config),
+            rule_state: RuleState::default(),
+            executor,
+            injected_held: HashSet::new(),
+            numlock,
+            numpad_policy: config.numpad,
+            paused: PauseHandle::default(),
+            pause_chords: pause_chords(config),
+            held: HashSet::new(),
+            safety_key: config.safety_key,
+            passthroughs: config.passthroughs.clone(),
+            focus: WindowContext::default(),
+            pause_triggers: HashSet::new(),
+            rule_downs: HashSet::new(),
+            capslock: CapsLockGuard::default(),
+            dedup: config.dedup_window.map(DedupFilter::new),
+            suppress_observer: config
+                .log_suppressed
+                .then(|| Box::new(log_suppressed) as SuppressObserver),
+            clipboard: None,
+            clipboard_restore: config.clipboard_restore,
+            type_delay: config.type_delay,
+            launcher: None,
+            deferred: Vec::new(),
+            held_repeats: Vec::new(),
+            failures: Cell::new(0),
+            session_away: false,
+            batch: RefCell::new(None),
+        }
+    }
+
+    /// Apply the rules and rule settings of `config`, as read again from the
+    /// config file. Keys held through the old rules are released through
+    /// them first, so nothing stays held that the new rules know nothing
+    /// of; their own Up, when it comes, passes through. The executor, the
+    /// pause state, and the held keys are kept.
+    pub fn reload(&mut self, config: &Config) {
+        self.release_rule_downs();
+        self.rules = RuleEngine::new(config);
+        self.rule_state = RuleState::default();
+        self.numpad_policy = config.numpad;
+        self.pause_chords = pause_chords(config);
+        self.safety_key = config.safety_key;
+        self.passthroughs = config.passthroughs.clone();
+        // Decided again from the window of the next event.
+        self.focus = WindowContext::default();
+        self.paused.set_app_passthrough(false);
+        self.dedup = config.dedup_window.map(DedupFilter::new);
+        self.suppress_observer = config
+            .log_suppressed
+            .then(|| Box::new(log_suppressed) as SuppressObserver);
+        self.type_delay = config.type_delay;
+        self.clipboard_restore = config.clipboard_restore;
+        if let Some(launcher) = self.launcher.as_mut() {
+            launcher.configure(config.exec.clone());
+        }
+        log::info!("engine: rules reloaded");
+    }
+
+    /// Act on the session being locked or disconnected, or back in use,
+    /// since the last call. Rules pause for as long as it is away (see
+    /// `PauseHandle`); on the way out, every held key is released, since
+    /// the Ups of keys let go on the lock screen never reach us.
+    pub fn sync_session(&mut self) {
+        let reason = self.paused.session.reason();
+        if reason.is_some() == self.session_away {
+            return;
+        }
+        self.session_away = reason.is_some();
+        let Some(reason) = reason else {
+            log::info!("engine: session back in use; rules apply again");
+            return;
+        };
+        log::info!("engine: {reason}; rules paused and held keys released");
+        self.release_rule_downs();
+        let held: Vec<KeyCode> = self.injected_held.iter().copied().collect();
+        for key in held {
+            self.inject(key, KeyState::Up, false);
+        }
+        self.held.clear();
+        self.pause_triggers.clear();
+    }
+
+    /// Release every key held through the rules through them now. Their
+    /// own Up, when it comes, passes through.
+    fn release_rule_downs(&mut self) {
+        let released: Vec<KeyCode> = self.rule_downs.drain().collect();
+        for key in released {
+            self.apply_rules(&InputEvent {
+                key,
+                state: KeyState::Up,
+                modifiers: Modifiers::default(),
+                window: self.focus.clone(),
+                timestamp: Instant::now(),
+                device: None,
+                observed: false,
+                is_repeat: false,
+                position: None,
+            });
+        }
+    }
+
+    /// Read and set the clipboard through `clipboard` for the clipboard
+    /// actions. Without one they only log a warning.
+    pub fn with_clipboard(mut self, clipboard: Box<dyn Clipboard>) -> Self {
+        self.clipboard = Some(ClipboardWorker::spawn(clipboard));
+        self
+    }
+
+    /// Start `Exec` commands on a worker thread, per `settings`, instead of
+    /// passing them to the executor.
+    pub fn with_launcher(mut self, settings: ExecSettings) -> Self {
+        self.launcher = Some(Launcher::spawn(settings));
+        self
+    }
+
+    /// Name of the executor backend in use, for status output.
+    pub fn executor_name(&self) -> &'static str {
+        self.executor.name()
+    }
+
+    /// What the executor can do, with the clipboard where the engine has
+    /// one.
+    pub fn capabilities(&self) -> Capabilities {
+        let mut caps = self.executor.capabilities();
+        caps.clipboard |= self.clipboard.is_some();
+        caps
+    }
+
+    /// The rules in `config` that can never work with this engine's
+    /// executor, for reporting once when they are loaded.
+    pub fn unsupported_rules(&self, config: &Config) -> Vec<Unsupported> {
+        unsupported(config, self.capabilities(), self.executor.name())
+    }
+
+    /// Returns a handle to the engine's pause flag.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.paused.clone()
+    }
+
+    /// Resolve one captured event and execute the resulting action.
+    pub fn handle(&mut self, event: &InputEvent) {
+        // Text read before this key is typed before its output.
+        self.poll();
+        self.resolve(event);
+        log::trace!(
+            "engine: {:?} {:?} handled {:.2}ms after capture",
+            event.key,
+            event.state,
+            event.timestamp.elapsed().as_secs_f64() * 1000.0
+        );
+    }
+
+    /// Run delayed rule output that is due, call back on captured commands
+    /// that ended, type the clipboard text of finished `TypeClipboard`
+    /// reads, and paste what finished pastes put on the clipboard.
+    pub fn poll(&mut self) {
+        self.run_timers(Instant::now());
+        let finished = self
+            .launcher
+            .as_mut()
+            .map(Launcher::take_finished)
+            .unwrap_or_default();
+        for (on_done, captured) in finished {
+            on_done(self, captured);
+        }
+        let Some(clipboard) = self.clipboard.as_mut() else {
+            return;
+        };
+        for done in clipboard.take() {
+            match done {
+                Done::Read(_, Some(_)) if self.paused.is_paused() => {
+                    log::info!("engine: paused; clipboard text dropped");
+                }
+                Done::Read(source, Some(text)) => self.type_text(&text, &source),
+                Done::Read(_, None) => {}
+                Done::Paste(_) if self.paused.is_paused() => {
+                    log::info!("engine: paused; paste dropped");
+                }
+                Done::Paste(source) => self.dispatch(&Action::KeyCombo(paste_keys()), &source),
+            }
+        }
+    }
+
+    /// Run the delayed rule output and held-key repeats due by `now`.
+    /// Paused, they are dropped instead.
+    fn run_timers(&mut self, now: Instant) {
+        if self.paused.is_paused() {
+            if !self.deferred.is_empty() {
+                log::info!(
+                    "engine: paused; {} delayed sequence(s) dropped",
+                    self.deferred.len()
+                );
+                self.deferred.clear();
+            }
+            if !self.held_repeats.is_empty() {
+                log::info!(
+                    "engine: paused; {} held-key repeat(s) stopped",
+                    self.held_repeats.len()
+                );
+                self.held_repeats.clear();
+            }
+            return;
+        }
+        if !self.deferred.is_empty() {
+            let (due, waiting) = std::mem::take(&mut self.deferred)
+                .into_iter()
+                .partition(|d| d.due <= now);
+            self.deferred = waiting;
+            for d in due {
+                self.resume(d.frames, &d.source);
+            }
+        }
+        let mut i = 0;
+        while i < self.held_repeats.len() {
+            let repeat = &mut self.held_repeats[i];
+            if repeat.due > now {
+                i += 1;
+                continue;
+            }
+            if repeat.runs >= MAX_HELD_REPEATS {
+                log::warn!(
+                    "engine: {:?} repeated {MAX_HELD_REPEATS} times; stopped",
+                    repeat.source.key
+                );
+                self.held_repeats.remove(i);
+                continue;
+            }
+            repeat.runs += 1;
+            // Behind by more than an interval, as after a stall: start the
+            // cadence over rather than catch up in a burst.
+            repeat.due += repeat.interval;
+            if repeat.due <= now {
+                repeat.due = now + repeat.interval;
+            }
+            let (action, source) = (repeat.action.clone(), repeat.source.clone());
+            self.dispatch(&action, &source);
+            i += 1;
+        }
+    }
+
+    /// Whether a clipboard read or paste, a delayed action, a held-key
+    /// repeat, or a captured command is outstanding, so `poll` has work
+    /// coming.
+    pub fn has_pending(&self) -> bool {
+        !self.deferred.is_empty()
+            || !self.held_repeats.is_empty()
+            || self.launcher.as_ref().is_some_and(Launcher::capturing)
+            || self
+                .clipboard
+                .as_ref()
+                .is_some_and(ClipboardWorker::pending)
+    }
+
+    fn resolve(&mut self, event: &InputEvent) {
+        if let Some(dedup) = self.dedup.as_mut() {
+            if !dedup.admit(event.key, event.state, event.timestamp) {
+                log::debug!(
+                    "engine: dropped duplicate {:?} {:?}",
+                    event.key,
+                    event.state
+                );
+                self.suppressed(event, SuppressReason::Duplicate);
+                return;
+            }
+        }
+
+        if !self.passthroughs.is_empty() && event.window != self.focus {
+            self.focus_changed(&event.window, Some(event));
+        }
+
+        if self.match_pause_chord(event) {
+            self.suppressed(event, SuppressReason::NoOutput);
+            self.run(&Action::Suppress);
+            return;
+        }
+
+        if self.safety_key.is_some_and(|k| k.covers(event.key)) {
+            self.suppressed(event, SuppressReason::NoOutput);
+            self.run(&Action::Suppress);
+            return;
+        }
+
+        let through_rules = match event.state {
+            // Auto-repeat of a key pressed before pausing stays with the rules.
+            KeyState::Down => {
+                let through = self.rules_active() || self.rule_downs.contains(&event.key);
+                if through {
+                    self.rule_downs.insert(event.key);
+                }
+                through
+            }
+            KeyState::Up => self.rule_downs.remove(&event.key),
+        };
+        if !through_rules {
+            if !event.observed {
+                self.inject(event.key, event.state, event.is_repeat);
+            }
+            return;
+        }
+
+        self.apply_rules(event);
+    }
+
+    fn apply_rules(&mut self, event: &InputEvent) {
+        if event.state == KeyState::Up {
+            self.held_repeats.retain(|r| r.source.key != event.key);
+        }
+        let decision = self.rules.resolve(event, &self.rule_state);
+        self.rule_state.apply(&decision.state_changes);
+        let restore = self.capslock.after(event, &decision.actions);
+        // Each action on its own, as the steps of a sequence that goes on
+        // past a failure.
+        self.resume(
+            vec![Frame::new(decision.actions, StepFailure::Continue)],
+            event,
+        );
+        for (key, state) in restore {
+            self.inject(key, state, false);
+        }
+    }
+
+    /// Act on focus moving to `window`, as reported by capture, entering
+    /// or leaving passthrough at once rather than on the next key.
+    pub fn focus(&mut self, window: &WindowContext) {
+        if !self.passthroughs.is_empty() && *window != self.focus {
+            self.focus_changed(window, None);
+        }
+    }
+
+    /// Enter or leave passthrough for `window`, during `event` when a key
+    /// read there noticed the change. On entering, keys held through the
+    /// rules are released through them now, and every other key still held
+    /// by an injection is released; their own Up, when it comes, passes
+    /// through. The Up of `event` itself still goes through the rules as
+    /// usual.
+    fn focus_changed(&mut self, window: &WindowContext, event: Option<&InputEvent>) {
+        self.focus = window.clone();
+        let on = self.passthroughs.iter().any(|p| p.matches(window));
+        if on == self.paused.app_passthrough() {
+            return;
+        }
+        self.paused.set_app_passthrough(on);
+        let app = window.app_id.as_deref().unwrap_or("unknown app");
+        if !on {
+            log::info!("engine: left {app}; rules apply again");
+            return;
+        }
+        log::info!("engine: {app} is a passthrough app; rules paused while it is focused");
+        let releasing = event.filter(|e| e.state == KeyState::Up).map(|e| e.key);
+        let held: Vec<KeyCode> = self
+            .rule_downs
+            .iter()
+            .copied()
+            .filter(|&k| Some(k) != releasing)
+            .collect();
+        for key in held {
+            self.rule_downs.remove(&key);
+            self.apply_rules(&InputEvent {
+                key,
+                state: KeyState::Up,
+                modifiers: Modifiers::default(),
+                window: window.clone(),
+                timestamp: event.map_or_else(Instant::now, |e| e.timestamp),
+                device: None,
+                observed: false,
+                is_repeat: false,
+                position: None,
+            });
+        }
+        let injected: Vec<KeyCode> = self
+            .injected_held
+            .iter()
+            .copied()
+            .filter(|&k| Some(k) != releasing)
+            .collect();
+        for key in injected {
+            self.inject(key, KeyState::Up, false);
+        }
+    }
+
+    /// Whether a new key press goes through the rules: not paused, not in a
+    /// passthrough app, and the safety key, if any, is held.
+    fn rules_active(&self) -> bool {
+        !self.paused.is_paused()
+            && !self.paused.app_passthrough()
+            && self.safety_key.map_or(true, |k| is_held(&self.held, k))
+    }
+
+    /// Report a key the event bus dropped before it reached the engine.
+    pub fn report_dropped(&mut self, key: KeyCode, state: KeyState) {
+        if let Some(observe) = self.suppress_observer.as_mut() {
+            observe(key, state, SuppressReason::Overflow);
+        }
+    }
+
+    fn suppressed(&mut self, event: &InputEvent, reason: SuppressReason) {
+        if let Some(observe) = self.suppress_observer.as_mut() {
+            observe(event.key, event.state, reason);
+        }
+    }
+
+    /// Track physical key state and toggle pause when a `toggle_pause` chord
+    /// completes. Returns true if the event belongs to a chord trigger and
+    /// must be swallowed.
+    fn match_pause_chord(&mut self, event: &InputEvent) -> bool {
+        match event.state {
+            KeyState::Down => {
+                self.held.insert(event.key);
+                let fired = self.pause_chords.iter().find(|(c, _)| {
+                    c.iter().any(|k| k.covers(event.key))
+                        && c.iter().all(|&k| is_held(&self.held, k))
+                });
+                let Some(&(_, paused)) = fired else {
+                    return false;
+                };
+                self.set_paused(paused);
+                self.pause_triggers.insert(event.key);
+                true
+            }
+            KeyState::Up => {
+                self.held.remove(&event.key);
+                self.pause_triggers.remove(&event.key)
+            }
+        }
+    }
+
+    /// Pause, resume, or flip the pause, for `Action::SetPaused`.
+    fn set_paused(&self, paused: Option<bool>) {
+        let paused = self.paused.set_or_toggle(paused);
+        log::info!("engine: {}", if paused { "paused" } else { "resumed" });
+    }
+
+    /// Run the steps of `frames`, innermost last, until they are done or
+    /// reach an `Action::Delay`. The rest is then set aside for `poll` to
+    /// pick up once the delay is over.
+    fn resume(&mut self, mut frames: Vec<Frame>, source: &InputEvent) {
+        let mut failed = false;
+        while let Some(frame) = frames.last_mut() {
+            if failed && frame.on_error == StepFailure::Abort {
+                log::debug!("engine: a step failed; rest of the sequence skipped");
+                frames.pop();
+                continue;
+            }
+            failed = false;
+            let Some(step) = frame.steps.get(frame.next).cloned() else {
+                frames.pop();
+                continue;
+            };
+            frame.next += 1;
+            let typed = frame.typed;
+            match step {
+                Action::Sequence { steps, .. } if typed => {
+                    let failures = self.failures.get();
+                    self.batched(|engine| steps.iter().for_each(|action| engine.run(action)));
+                    failed = self.failures.get() != failures;
+                }
+                // The outermost frame is the rule's output, not a sequence.
+                Action::Sequence { .. } | Action::Repeat { .. }
+                    if frames.len() > MAX_SEQUENCE_DEPTH =>
+                {
+                    log::warn!("engine: sequence nested deeper than {MAX_SEQUENCE_DEPTH}; not run");
+                    failed = true;
+                }
+                Action::Sequence { steps, on_error } => frames.push(Frame::new(steps, on_error)),
+                Action::Repeat { action, count } if count > 0 => {
+                    frames.push(repetitions(&action, count, self.type_delay));
+                }
+                // Typed in place, so the steps after it wait for the text.
+                Action::TypeString { text } if !typed => frames.push(self.typed(&text)),
+                Action::InjectChar(c)
+                    if !typed && self.executor.text_injection() == TextInjection::Keys =>
+                {
+                    frames.push(self.typed(c.encode_utf8(&mut [0; 4])));
+                }
+                Action::SetPaused { paused } => {
+                    self.dispatch(&Action::SetPaused { paused }, source);
+                    if self.paused.is_paused() {
+                        log::info!("engine: paused by a rule; rest of its output dropped");
+                        return;
+                    }
+                }
+                Action::Delay { ms: 0 } => {}
+                Action::Delay { ms } => {
+                    self.deferred.push(Deferred {
+                        due: Instant::now() + Duration::from_millis(ms),
+                        frames,
+                        source: source.clone(),
+                    });
+                    return;
+                }
+                step => {
+                    let failures = self.failures.get();
+                    if typed {
+                        self.run(&step);
+                    } else {
+                        self.dispatch(&step, source);
+                    }
+                    failed = self.failures.get() != failures;
+                }
+            }
+        }
+    }
+
+    /// Lower composite actions and forward primitives to the executor.
+    ///
+    /// `source` is the captured event. Re-injecting its key unchanged is
+    /// passthrough and is sent as-is: a physical numpad press with NumLock off
+    /// is meant to navigate. An observed event already got through, so its
+    /// passthrough is dropped. Every other key a rule outputs goes through
+    /// `emit`.
+    fn dispatch(&mut self, action: &Action, source: &InputEvent) {
+        match action {
+            Action::KeyCombo(keys) => match self.chord(keys) {
+                Some(chord) => self.run(&chord),
+                None => self.batched(|engine| {
+                    for (key, state) in combo::steps(keys, &engine.injected_held) {
+                        engine.emit(key, state, false);
+                    }
+                }),
+            },
+            Action::WithModifiers(modifiers) => {
+                if source.observed {
+                    return;
+                }
+                if source.state == KeyState::Up {
+                    self.inject(source.key, KeyState::Up, false);
+                    return;
+                }
+                let missing = combo::missing(modifiers, &self.injected_held);
+                self.batched(|engine| {
+                    for &m in &missing {
+                        engine.inject(m, KeyState::Down, false);
+                    }
+                    engine.inject(source.key, KeyState::Down, source.is_repeat);
+                    for &m in missing.iter().rev() {
+                        engine.inject(m, KeyState::Up, false);
+                    }
+                });
+            }
+            Action::InjectKey { key, state } if *key == source.key => {
+                if !source.observed {
+                    self.inject(*key, *state, source.is_repeat);
+                }
+            }
+            Action::InjectKey { key, state } => self.emit(*key, *state, source.is_repeat),
+            Action::Suppress => {
+                self.suppressed(source, SuppressReason::Explicit);
+                self.run(action);
+            }
+            Action::Exec { command } if self.launcher.is_some() => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+                if let Some(launcher) = self.launcher.as_mut() {
+                    launcher.launch(command);
+                }
+            }
+            Action::SetPaused { paused } => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+                self.set_paused(*paused);
+            }
+            Action::TypeOutput { command } if self.launcher.is_some() => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+                if let Some(launcher) = self.launcher.as_mut() {
+                    let source = source.clone();
+                    launcher.capture(
+                        command,
+                        Box::new(move |engine, captured| type_output(engine, &source, captured)),
+                    );
+                }
+            }
+            Action::Exec { .. } | Action::TypeOutput { .. } | Action::SystemCommand(_) => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(action);
+            }
+            Action::TypeClipboard => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+                match self.clipboard.as_mut() {
+                    Some(clipboard) => clipboard.request(source.clone()),
+                    None => log::warn!("engine: no clipboard available; nothing typed"),
+                }
+            }
+            Action::ClipboardSet { text } => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+                match self.clipboard.as_mut() {
+                    Some(clipboard) => clipboard.set(text.clone()),
+                    None => log::warn!("engine: no clipboard available; clipboard not set"),
+                }
+            }
+            Action::PasteText { text } => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+                match self.clipboard.as_mut() {
+                    Some(clipboard) => {
+                        clipboard.paste(text.clone(), source.clone(), self.clipboard_restore);
+                    }
+                    None => log::warn!("engine: no clipboard available; nothing pasted"),
+                }
+            }
+            Action::TypeString { text } => self.type_text(text, source),
+            Action::InjectChar(c) if self.executor.text_injection() == TextInjection::Keys => {
+                self.type_text(c.encode_utf8(&mut [0; 4]), source);
+            }
+            Action::Sequence { steps, on_error } => {
+                self.resume(vec![Frame::new(steps.clone(), *on_error)], source);
+            }
+            Action::Repeat { count: 0, .. } => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+            }
+            Action::Repeat { action, count } => {
+                self.resume(vec![repetitions(action, *count, self.type_delay)], source);
+            }
+            Action::RepeatWhileHeld { action, .. } if repeats_while_held(action) => {
+                log::warn!("engine: RepeatWhileHeld inside another; not run");
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+            }
+            // The timer repeats it; the key's own auto-repeat does not.
+            Action::RepeatWhileHeld { .. } if source.state == KeyState::Up || source.is_repeat => {
+                self.suppressed(source, SuppressReason::NoOutput);
+                self.run(&Action::Suppress);
+            }
+            Action::RepeatWhileHeld {
+                interval_ms,
+                action,
+            } => {
+                self.dispatch(action, source);
+                let interval = Duration::from_millis((*interval_ms).max(1));
+                self.held_repeats.retain(|r| r.source.key != source.key);
+                self.held_repeats.push(HeldRepeat {
+                    action: (**action).clone(),
+                    interval,
+                    due: source.timestamp + interval,
+                    runs: 1,
+                    source: source.clone(),
+                });
+            }
+            Action::Remap { .. }
+            | Action::Chord { .. }
+            | Action::InjectChar(_)
+            | Action::InjectRepeat { .. }
+            | Action::Passthrough
+            | Action::Delay { .. }
+            | Action::MouseMoveRelative { .. }
+            | Action::MouseMoveAbsolute { .. }
+            | Action::MouseButton { .. }
+            | Action::Scroll { .. } => self.run(action),
+        }
+    }
+
+    /// `keys` as one `Action::Chord`, if one can say it with the numpad
+    /// policy applied to the main key. A chord leaves the held keys as they
+    /// were, so only a NumLock tap changes the tracked state.
+    fn chord(&mut self, keys: &[KeyCode]) -> Option<Action> {
+        let (modifiers, key) = combo::chord(keys, &self.injected_held)?;
+        let key = match numpad::resolve(key, KeyState::Down, self.numlock, self.numpad_policy)[..] {
+            [(key, KeyState::Down)] => key,
+            _ => return None,
+        };
+        if key == KeyCode::NumLock {
+            self.numlock = self.numlock.map(|on| !on);
+        }
+        Some(Action::Chord { modifiers, key })
+    }
+
+    /// Inject a key produced by a rule, applying the numpad NumLock policy.
+    fn emit(&mut self, key: KeyCode, state: KeyState, repeat: bool) {
+        for (key, state) in numpad::resolve(key, state, self.numlock, self.numpad_policy) {
+            self.inject(key, state, repeat);
+        }
+    }
+
+    /// Inject a single key and record its effect on the OS-visible held and
+    /// NumLock state.
+    ///
+    /// With `repeat` set, a Down for a key the OS already sees held is sent
+    /// as `InjectRepeat`, so applications get an autorepeat rather than a
+    /// fresh press. A Down for a key that is not held is a press either way.
+    fn inject(&mut self, key: KeyCode, state: KeyState, repeat: bool) {
+        if repeat && state == KeyState::Down && self.injected_held.contains(&key) {
+            self.run(&Action::InjectRepeat { key });
+            return;
+        }
+        match state {
+            KeyState::Down => {
+                if key == KeyCode::NumLock {
+                    self.numlock = self.numlock.map(|on| !on);
+                }
+                self.injected_held.insert(key)
+            }
+            KeyState::Up => self.injected_held.remove(&key),
+        };
+        self.run(&Action::InjectKey { key, state });
+    }
+
+    /// Type `text` for `source`'s rule. With a `type_delay`, the characters
+    /// after the first are typed from `poll` as they come due.
+    fn type_text(&mut self, text: &str, source: &InputEvent) {
+        let frame = self.typed(text);
+        self.resume(vec![frame], source);
+    }
+
+    /// `text` as a frame that types it the way the executor can, with a
+    /// `type_delay` pause between characters. Characters it cannot type are
+    /// logged once and left out; a character that fails stops the rest.
+    fn typed(&self, text: &str) -> Frame {
+        let pause = pause(self.type_delay);
+        let (steps, missing) = text::lower(text, self.executor.text_injection(), pause.is_some());
+        if !missing.is_empty() {
+            log::warn!(
+                "engine: the {} backend has no key for {missing:?}; left out",
+                self.executor.name()
+            );
+        }
+        let mut frame = Vec::new();
+        for (i, step) in steps.into_iter().enumerate() {
+            if i > 0 {
+                frame.extend(pause.clone());
+            }
+            frame.push(match <[Action; 1]>::try_from(step) {
+                Ok([action]) => action,
+                Err(steps) => Action::Sequence {
+                    steps,
+                    on_error: StepFailure::Continue,
+                },
+            });
+        }
+        Frame {
+            typed: true,
+            ..Frame::new(frame, StepFailure::Abort)
+        }
+    }
+
+    /// Run `steps`, sending every action they run to the executor as one
+    /// batch at the end, so no other input lands between a combination's
+    /// modifiers and its key.
+    fn batched(&mut self, steps: impl FnOnce(&mut Self)) {
+        self.batch.replace(Some(Vec::new()));
+        steps(self);
+        let actions = self.batch.take().unwrap_or_default();
+        if actions.is_empty() {
+            return;
+        }
+        if let Err(e) = self.executor.execute_batch(&actions) {
+            self.failed(e);
+        }
+    }
+
+    fn run(&self, action: &Action) {
+        if let Some(batch) = self.batch.borrow_mut().as_mut() {
+            batch.push(action.clone());
+            return;
+        }
+        if let Err(e) = self.executor.execute(action) {
+            self.failed(e);
+        }
+    }
+
+    /// Count a failed step. Actions a middleware dropped are only logged at
+    /// debug level, as the middleware reports them itself.
+    fn failed(&self, e: PlatformError) {
+        self.failures.set(self.failures.get() + 1);
+        match e {
+            PlatformError::Suppressed(_) => log::debug!("executor: {e}"),
+            _ => log::warn!("executor: inject failed: {e}"),
+        }
+    }
+}
+
+/// Whether `key` is held, counting either side of a unified modifier.
+fn is_held(held: &HashSet<KeyCode>, key: KeyCode) -> bool {
+    held.iter().any(|&h| key.covers(h))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::platform::mock::{MockClipboard, RecordingExecutor};
+    use crate::platform::{Modifiers, WindowContext};
+
+    fn event(key: KeyCode, state: KeyState) -> InputEvent {
+        InputEvent {
+            key,
+            state,
+            modifiers: Modifiers::default(),
+            window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
+        }
+    }
+
+    fn engine_from_toml(toml: &str) -> (Engine, RecordingExecutor) {
+        let config = crate::config::parse_str(toml).unwrap();
+        let recorder = RecordingExecutor::new();
+        (Engine::new(&config, Box::new(recorder.clone())), recorder)
+    }
+
+    fn inject(key: KeyCode, state: KeyState) -> Action {
+        Action::InjectKey { key, state }
+    }
+
+    const COMBO_CONFIG: &str = r#"
+        [[remap]]
+        from = "F13"
+        to   = "Ctrl+Shift+T"
+    "#;
+
+    fn chord(ctrl: bool, shift: bool, key: KeyCode) -> Action {
+        Action::Chord {
+            modifiers: Modifiers {
+                ctrl,
+                shift,
+                ..Modifiers::default()
+            },
+            key,
+        }
+    }
+
+    #[test]
+    fn key_combo_is_injected_as_one_chord() {
+        let (mut engine, recorder) = engine_from_toml(COMBO_CONFIG);
+
+        engine.handle(&event(KeyCode::F13, KeyState::Down));
+        engine.handle(&event(KeyCode::F13, KeyState::Up));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![chord(true, true, KeyCode::T), Action::Suppress]
+        );
+    }
+
+    /// A modifier held outside the combination is let go around it, which a
+    /// chord cannot say, so the steps go as one batch instead.
+    #[test]
+    fn key_combo_with_a_stray_modifier_held_is_one_batch() {
+        let (mut engine, recorder) = engine_from_toml(COMBO_CONFIG);
+
+        engine.handle(&event(KeyCode::Alt, KeyState::Down));
+        recorder.clear();
+        engine.handle(&event(KeyCode::F13, KeyState::Down));
+        assert_eq!(recorder.batches(), vec![8]);
+        assert_eq!(recorder.actions()[0], inject(KeyCode::Alt, KeyState::Up));
+        assert_eq!(recorder.actions()[7], inject(KeyCode::Alt, KeyState::Down));
+    }
+
+    /// A user holding Ctrl keeps holding it: the combo neither presses nor
+    /// releases Ctrl, and the later physical Ctrl Up is still delivered.
+    #[test]
+    fn key_combo_does_not_disturb_user_held_modifier() {
+        let (mut engine, recorder) = engine_from_toml(COMBO_CONFIG);
+
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Down));
+        recorder.clear();
+        engine.handle(&event(KeyCode::F13, KeyState::Down));
+
+        assert_eq!(recorder.actions(), vec![chord(false, true, KeyCode::T)]);
+
+        recorder.clear();
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Up));
+        assert_eq!(
+            recorder.actions(),
+            vec![inject(KeyCode::Ctrl, KeyState::Up)]
+        );
+    }
+
+    const WITH_CONFIG: &str = r#"
+        [[remap]]
+        from = "J"
+        to   = { with = ["Ctrl", "Shift"] }
+    "#;
+
+    #[test]
+    fn with_modifiers_wraps_the_source_key_and_releases_them() {
+        let (mut engine, recorder) = engine_from_toml(WITH_CONFIG);
+
+        engine.handle(&event(KeyCode::J, KeyState::Down));
+        engine.handle(&event(KeyCode::J, KeyState::Up));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::Ctrl, KeyState::Down),
+                inject(KeyCode::Shift, KeyState::Down),
+                inject(KeyCode::J, KeyState::Down),
+                inject(KeyCode::Shift, KeyState::Up),
+                inject(KeyCode::Ctrl, KeyState::Up),
+                inject(KeyCode::J, KeyState::Up),
+            ]
+        );
+    }
+
+    #[test]
+    fn with_modifiers_leaves_a_held_modifier_to_the_user() {
+        let (mut engine, recorder) = engine_from_toml(WITH_CONFIG);
+
+        engine.handle(&event(KeyCode::RightCtrl, KeyState::Down));
+        recorder.clear();
+        engine.handle(&event(KeyCode::J, KeyState::Down));
+        engine.handle(&event(KeyCode::J, KeyState::Up));
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::Shift, KeyState::Down),
+                inject(KeyCode::J, KeyState::Down),
+                inject(KeyCode::Shift, KeyState::Up),
+                inject(KeyCode::J, KeyState::Up),
+            ]
+        );
+
+        recorder.clear();
+        engine.handle(&event(KeyCode::RightCtrl, KeyState::Up));
+        assert_eq!(
+            recorder.actions(),
+            vec![inject(KeyCode::RightCtrl, KeyState::Up)]
+        );
+    }
+
+    /// Captured repeats reach the executor as repeats of the key the OS
+    /// sees held, remapped or not.
+    #[test]
+    fn captured_repeats_are_injected_as_repeats() {
+        let (mut engine, recorder) = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "CapsLock"
+            to   = "Escape"
+        "#,
+        );
+        let repeat = |key| InputEvent {
+            is_repeat: true,
+            ..event(key, KeyState::Down)
+        };
+
+        engine.handle(&repeat(KeyCode::B));
+        engine.handle(&repeat(KeyCode::B));
+        engine.handle(&event(KeyCode::CapsLock, KeyState::Down));
+        engine.handle(&repeat(KeyCode::CapsLock));
+        engine.handle(&event(KeyCode::CapsLock, KeyState::Up));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::B, KeyState::Down),
+                Action::InjectRepeat { key: KeyCode::B },
+                inject(KeyCode::Escape, KeyState::Down),
+                Action::InjectRepeat {
+                    key: KeyCode::Escape
+                },
+                inject(KeyCode::Escape, KeyState::Up),
+            ]
+        );
+    }
+
+    const NUMPAD_CONFIG: &str = r#"
+        [[remap]]
+        from = "F14"
+        to   = "Numpad8"
+    "#;
+
+    #[test]
+    fn numpad_digit_is_wrapped_in_numlock_taps_when_numlock_is_off() {
+        let config = crate::config::parse_str(NUMPAD_CONFIG).unwrap();
+        let recorder = RecordingExecutor::new();
+        recorder.set_lock_state(KeyCode::NumLock, false);
+        let mut engine = Engine::new(&config, Box::new(recorder.clone()));
+
+        engine.handle(&event(KeyCode::F14, KeyState::Down));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::NumLock, KeyState::Down),
+                inject(KeyCode::NumLock, KeyState::Up),
+                inject(KeyCode::Numpad8, KeyState::Down),
+                inject(KeyCode::NumLock, KeyState::Down),
+                inject(KeyCode::NumLock, KeyState::Up),
+            ]
+        );
+    }
+
+    /// A captured NumLock press flips the tracked state, so the next numpad
+    /// digit goes out without any toggling.
+    #[test]
+    fn captured_numlock_press_updates_tracked_state() {
+        let config = crate::config::parse_str(NUMPAD_CONFIG).unwrap();
+        let recorder = RecordingExecutor::new();
+        recorder.set_lock_state(KeyCode::NumLock, false);
+        let mut engine = Engine::new(&config, Box::new(recorder.clone()));
+
+        engine.handle(&event(KeyCode::NumLock, KeyState::Down));
+        engine.handle(&event(KeyCode::NumLock, KeyState::Up));
+        recorder.clear();
+        engine.handle(&event(KeyCode::F14, KeyState::Down));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![inject(KeyCode::Numpad8, KeyState::Down)]
+        );
+    }
+
+    #[test]
+    fn top_row_policy_translates_numpad_digit() {
+        let toml = format!("{NUMPAD_CONFIG}\n[numpad]\npolicy = \"top_row\"\n");
+        let (mut engine, recorder) = engine_from_toml(&toml);
+
+        engine.handle(&event(KeyCode::F14, KeyState::Down));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![inject(KeyCode::Key8, KeyState::Down)]
+        );
+    }
+
+    /// Passthrough of a physical numpad key is never rewritten: with NumLock
+    /// off the user means to navigate.
+    #[test]
+    fn passthrough_numpad_digit_is_not_rewritten() {
+        let (mut engine, recorder) = engine_from_toml("");
+        engine.handle(&event(KeyCode::Numpad8, KeyState::Down));
+        assert_eq!(
+            recorder.actions(),
+            vec![inject(KeyCode::Numpad8, KeyState::Down)]
+        );
+    }
+
+    const REMAP_AND_TOGGLE_CONFIG: &str = r#"
+        [[remap]]
+        from = "A"
+        to   = "B"
+
+        [[hotkey]]
+        keys   = ["Ctrl", "Pause"]
+        action = "toggle_pause"
+    "#;
+
+    #[test]
+    fn paused_engine_delivers_no_remaps_until_resumed() {
+        let (mut engine, recorder) = engine_from_toml(REMAP_AND_TOGGLE_CONFIG);
+        let pause = engine.pause_handle();
+        pause.set_paused(true);
+
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.handle(&event(KeyCode::A, KeyState::Up));
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::A, KeyState::Down),
+                inject(KeyCode::A, KeyState::Up),
+            ]
+        );
+
+        recorder.clear();
+        pause.set_paused(false);
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        assert_eq!(recorder.actions(), vec![inject(KeyCode::B, KeyState::Down)]);
+    }
+
+    #[test]
+    fn toggle_pause_hotkey_works_while_paused() {
+        let (mut engine, recorder) = engine_from_toml(REMAP_AND_TOGGLE_CONFIG);
+        let pause = engine.pause_handle();
+        pause.set_paused(true);
+
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Down));
+        engine.handle(&event(KeyCode::Pause, KeyState::Down));
+        engine.handle(&event(KeyCode::Pause, KeyState::Up));
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Up));
+        assert!(!pause.is_paused());
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::Ctrl, KeyState::Down),
+                Action::Suppress,
+                Action::Suppress,
+                inject(KeyCode::Ctrl, KeyState::Up),
+            ]
+        );
+
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Down));
+        engine.handle(&event(KeyCode::Pause, KeyState::Down));
+        assert!(pause.is_paused());
+    }
+
+    #[test]
+    fn pause_and_resume_hotkeys_set_rather_than_flip() {
+        let (mut engine, _) = engine_from_toml(
+            r#"
+            [[hotkey]]
+            keys   = ["F19"]
+            action = "pause"
+
+            [[hotkey]]
+            keys   = ["F20"]
+            action = "resume"
+            "#,
+        );
+        let pause = engine.pause_handle();
+        for (key, paused) in [
+            (KeyCode::F19, true),
+            (KeyCode::F19, true),
+            (KeyCode::F20, false),
+            (KeyCode::F20, false),
+        ] {
+            engine.handle(&event(key, KeyState::Down));
+            engine.handle(&event(key, KeyState::Up));
+            assert_eq!(pause.is_paused(), paused, "{key:?}");
+        }
+    }
+
+    #[test]
+    fn pausing_from_a_rule_drops_the_rest_of_its_output() {
+        let (mut engine, recorder) = engine_from_toml("");
+        let output = vec![
+            Action::KeyCombo(vec![KeyCode::F1]),
+            Action::Sequence {
+                steps: vec![
+                    Action::SetPaused { paused: None },
+                    Action::KeyCombo(vec![KeyCode::F2]),
+                ],
+                on_error: StepFailure::Continue,
+            },
+            Action::KeyCombo(vec![KeyCode::F3]),
+        ];
+        engine.resume(
+            vec![Frame::new(output, StepFailure::Continue)],
+            &event(KeyCode::F13, KeyState::Down),
+        );
+        assert!(engine.pause_handle().is_paused());
+        assert_eq!(
+            recorder.actions(),
+            [
+                inject(KeyCode::F1, KeyState::Down),
+                inject(KeyCode::F1, KeyState::Up),
+                Action::Suppress,
+            ]
+        );
+    }
+
+    #[test]
+    fn locked_session_releases_held_keys_and_pauses_until_unlocked() {
+        let (mut engine, recorder) = engine_from_toml(REMAP_AND_TOGGLE_CONFIG);
+        let session = engine.pause_handle().session();
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.handle(&event(KeyCode::Shift, KeyState::Down));
+
+        session.set_locked(true);
+        engine.sync_session();
+        assert_eq!(
+            engine.pause_handle().suspended_reason().as_deref(),
+            Some("session locked")
+        );
+        // Only the first call after the change releases anything.
+        engine.sync_session();
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+
+        session.set_locked(false);
+        engine.sync_session();
+        engine.handle(&event(KeyCode::A, KeyState::Up));
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        let mut actions = recorder.actions();
+        // Held keys are released in no particular order.
+        let released: Vec<Action> = actions.drain(2..4).collect();
+        assert!(released.contains(&inject(KeyCode::B, KeyState::Up)));
+        assert!(released.contains(&inject(KeyCode::Shift, KeyState::Up)));
+        assert_eq!(
+            actions,
+            vec![
+                inject(KeyCode::B, KeyState::Down),
+                inject(KeyCode::Shift, KeyState::Down),
+                inject(KeyCode::A, KeyState::Down),
+                inject(KeyCode::A, KeyState::Up),
+                inject(KeyCode::B, KeyState::Down),
+            ]
+        );
+    }
+
+    #[test]
+    fn backend_suspension_pauses_rules_until_lifted() {
+        let (mut engine, recorder) = engine_from_toml(REMAP_AND_TOGGLE_CONFIG);
+        let pause = engine.pause_handle();
+        pause.suspension().suspend("secure input (loginwindow)");
+
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.handle(&event(KeyCode::A, KeyState::Up));
+        // The user resuming does not override the backend.
+        pause.set_paused(false);
+        assert!(pause.is_paused());
+
+        pause.suspension().lift();
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::A, KeyState::Down),
+                inject(KeyCode::A, KeyState::Up),
+                inject(KeyCode::B, KeyState::Down),
+            ]
+        );
+    }
+
+    /// A key remapped before pausing is released as the remapped key, so the
+    /// OS never sees a stuck B.
+    #[test]
+    fn key_held_across_pause_is_released_through_rules() {
+        let (mut engine, recorder) = engine_from_toml(REMAP_AND_TOGGLE_CONFIG);
+
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.pause_handle().set_paused(true);
+        engine.handle(&event(KeyCode::A, KeyState::Up));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::B, KeyState::Down),
+                inject(KeyCode::B, KeyState::Up),
+            ]
+        );
+    }
+
+    // --- Passthrough apps ---
+
+    const PASSTHROUGH_CONFIG: &str = r#"
+        [[remap]]
+        from = "A"
+        to   = "B"
+
+        [[passthrough]]
+        apps = ["game.exe"]
+    "#;
+
+    fn event_in(app: &str, key: KeyCode, state: KeyState) -> InputEvent {
+        InputEvent {
+            window: WindowContext {
+                app_id: Some(app.to_owned()),
+                title: None,
+            },
+            ..event(key, state)
+        }
+    }
+
+    #[test]
+    fn passthrough_app_skips_rules_until_focus_moves_away() {
+        let (mut engine, recorder) = engine_from_toml(PASSTHROUGH_CONFIG);
+        let pause = engine.pause_handle();
+
+        engine.handle(&event_in("game.exe", KeyCode::A, KeyState::Down));
+        engine.handle(&event_in("game.exe", KeyCode::A, KeyState::Up));
+        assert!(pause.app_passthrough());
+        assert!(pause.listen_only().is_set());
+        assert!(!pause.is_paused());
+
+        engine.handle(&event_in("editor.exe", KeyCode::A, KeyState::Down));
+        assert!(!pause.app_passthrough());
+        assert!(!pause.listen_only().is_set());
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::A, KeyState::Down),
+                inject(KeyCode::A, KeyState::Up),
+                inject(KeyCode::B, KeyState::Down),
+            ]
+        );
+    }
+
+    /// A remapped key held while focus moves into a passthrough app is let
+    /// go at once, and its own release later passes through.
+    #[test]
+    fn keys_held_through_rules_are_released_on_entering_passthrough() {
+        let (mut engine, recorder) = engine_from_toml(PASSTHROUGH_CONFIG);
+
+        engine.handle(&event_in("editor.exe", KeyCode::A, KeyState::Down));
+        engine.handle(&event_in("game.exe", KeyCode::W, KeyState::Down));
+        engine.handle(&event_in("game.exe", KeyCode::A, KeyState::Up));
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::B, KeyState::Down),
+                inject(KeyCode::B, KeyState::Up),
+                inject(KeyCode::W, KeyState::Down),
+                inject(KeyCode::A, KeyState::Up),
+            ]
+        );
+    }
+
+    /// A focus change reported by capture switches at once, with no key
+    /// read in the new window: the remapped key and a key merely passed
+    /// through are both let go.
+    #[test]
+    fn reported_focus_change_releases_held_keys_without_a_key_event() {
+        let (mut engine, recorder) = engine_from_toml(PASSTHROUGH_CONFIG);
+        let pause = engine.pause_handle();
+        let window = |app: &str| WindowContext {
+            app_id: Some(app.to_owned()),
+            title: None,
+        };
+
+        engine.handle(&event_in("editor.exe", KeyCode::A, KeyState::Down));
+        engine.handle(&event_in("editor.exe", KeyCode::LeftShift, KeyState::Down));
+        engine.focus(&window("game.exe"));
+        assert!(pause.app_passthrough());
+        let mut actions = recorder.actions();
+        assert_eq!(
+            actions.drain(..2).collect::<Vec<_>>(),
+            vec![
+                inject(KeyCode::B, KeyState::Down),
+                inject(KeyCode::LeftShift, KeyState::Down),
+            ]
+        );
+        // Released in no particular order.
+        actions.sort_by_key(|a| format!("{a:?}"));
+        assert_eq!(
+            actions,
+            vec![
+                inject(KeyCode::B, KeyState::Up),
+                inject(KeyCode::LeftShift, KeyState::Up),
+            ]
+        );
+
+        engine.focus(&window("editor.exe"));
+        assert!(!pause.app_passthrough());
+    }
+
+    /// Once capture listens only, passed-through keys are not injected a
+    /// second time, but a key remapped before pausing is still released.
+    #[test]
+    fn observed_events_are_not_injected_again() {
+        let (mut engine, recorder) = engine_from_toml(REMAP_AND_TOGGLE_CONFIG);
+        let listen_only = engine.pause_handle().listen_only();
+        let observed = |key, state| InputEvent {
+            observed: true,
+            ..event(key, state)
+        };
+
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.pause_handle().set_paused(true);
+        assert!(listen_only.is_set());
+        engine.handle(&observed(KeyCode::A, KeyState::Up));
+        engine.handle(&observed(KeyCode::C, KeyState::Down));
+        engine.handle(&observed(KeyCode::C, KeyState::Up));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::B, KeyState::Down),
+                inject(KeyCode::B, KeyState::Up),
+            ]
+        );
+        engine.pause_handle().toggle();
+        assert!(!listen_only.is_set());
+    }
+
+    #[test]
+    fn observed_remapped_capslock_restores_the_lock() {
+        let (mut engine, recorder) = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "CapsLock"
+            to   = "Escape"
+            "#,
+        );
+        let observed = |state| InputEvent {
+            observed: true,
+            ..event(KeyCode::CapsLock, state)
+        };
+
+        engine.handle(&observed(KeyState::Down));
+        engine.handle(&observed(KeyState::Up));
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::Escape, KeyState::Down),
+                inject(KeyCode::Escape, KeyState::Up),
+                inject(KeyCode::CapsLock, KeyState::Down),
+                inject(KeyCode::CapsLock, KeyState::Up),
+            ]
+        );
+    }
+
+    type SuppressLog = std::sync::Arc<std::sync::Mutex<Vec<(KeyCode, KeyState, SuppressReason)>>>;
+
+    /// Replace the engine's suppress observer with one that records into the
+    /// returned log.
+    fn record_suppressed(engine: &mut Engine) -> SuppressLog {
+        let log = SuppressLog::default();
+        let sink = log.clone();
+        engine.suppress_observer = Some(Box::new(move |key, state, reason| {
+            sink.lock().unwrap().push((key, state, reason));
+        }));
+        log
+    }
+
+    #[test]
+    fn dedup_drops_copies_but_not_rapid_presses() {
+        let (mut engine, recorder) = engine_from_toml("[capture]\ndedup = true\n");
+        let log = record_suppressed(&mut engine);
+        let start = Instant::now();
+        let stamped = |state, us| InputEvent {
+            timestamp: start + std::time::Duration::from_micros(us),
+            ..event(KeyCode::A, state)
+        };
+
+        engine.handle(&stamped(KeyState::Down, 0));
+        engine.handle(&stamped(KeyState::Down, 30));
+        engine.handle(&stamped(KeyState::Up, 200));
+        engine.handle(&stamped(KeyState::Down, 400));
+
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::A, KeyState::Down),
+                inject(KeyCode::A, KeyState::Up),
+                inject(KeyCode::A, KeyState::Down),
+            ]
+        );
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![(KeyCode::A, KeyState::Down, SuppressReason::Duplicate)]
+        );
+    }
+
+    #[test]
+    fn log_suppressed_option_installs_observer() {
+        let (engine, _) = engine_from_toml("[debug]\nlog_suppressed = true\n");
+        assert!(engine.suppress_observer.is_some());
+        let (engine, _) = engine_from_toml("");
+        assert!(engine.suppress_observer.is_none());
+    }
+
+    #[test]
+    fn each_suppress_reason_is_reported_distinctly() {
+        let (mut engine, recorder) = engine_from_toml(
+            r#"
+            [[hotkey]]
+            keys    = ["Ctrl", "K"]
+            action  = "exec"
+            command = "true"
+        "#,
+        );
+        let log = record_suppressed(&mut engine);
+
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Down));
+        engine.handle(&event(KeyCode::K, KeyState::Down));
+        engine.handle(&event(KeyCode::K, KeyState::Up));
+        engine.report_dropped(KeyCode::Z, KeyState::Down);
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                (KeyCode::K, KeyState::Down, SuppressReason::NoOutput),
+                (KeyCode::K, KeyState::Up, SuppressReason::Explicit),
+                (KeyCode::Z, KeyState::Down, SuppressReason::Overflow),
+            ]
+        );
+        // The mock saw the Exec and the Suppress, and never a K injection.
+        assert!(!recorder.actions().iter().any(|a| matches!(
+            a,
+            Action::InjectKey {
+                key: KeyCode::K,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn pause_toggle_trigger_is_reported_as_no_output() {
+        let (mut engine, _) = engine_from_toml(REMAP_AND_TOGGLE_CONFIG);
+        let log = record_suppressed(&mut engine);
+
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Down));
+        engine.handle(&event(KeyCode::Pause, KeyState::Down));
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![(KeyCode::Pause, KeyState::Down, SuppressReason::NoOutput)]
+        );
+    }
+
+    #[test]
+    fn repeat_dispatches_inner_action_count_times_in_order() {
+        let (mut engine, recorder) = engine_from_toml("");
+        let repeat = Action::Repeat {
+            action: Box::new(Action::KeyCombo(vec![KeyCode::Down])),
+            count: 3,
+        };
+
+        engine.dispatch(&repeat, &event(KeyCode::F13, KeyState::Down));
+
+        let tap = vec![
+            inject(KeyCode::Down, KeyState::Down),
+            inject(KeyCode::Down, KeyState::Up),
+        ];
+        assert_eq!(recorder.actions(), [tap.clone(), tap.clone(), tap].concat());
+    }
+
+    #[test]
+    fn nested_repeat_multiplies() {
+        let (mut engine, recorder) = engine_from_toml("");
+        let repeat = Action::Repeat {
+            action: Box::new(Action::Repeat {
+                action: Box::new(Action::InjectChar('x')),
+                count: 2,
+            }),
+            count: 3,
+        };
+
+        engine.dispatch(&repeat, &event(KeyCode::F13, KeyState::Down));
+
+        assert_eq!(recorder.actions(), vec![Action::InjectChar('x'); 6]);
+    }
+
+    /// A delay inside a repeated sequence holds up the copies after it and
+    /// the steps after the repeat, which all run from `poll` in order.
+    #[test]
+    fn repeat_of_a_sequence_with_a_delay_keeps_its_order() {
+        let (mut engine, recorder) = engine_from_toml("");
+        let tap = |key| Action::KeyCombo(vec![key]);
+        let output = Action::Sequence {
+            steps: vec![
+                Action::Repeat {
+                    action: Box::new(Action::Sequence {
+                        steps: vec![tap(KeyCode::A), Action::Delay { ms: 1 }, tap(KeyCode::B)],
+                        on_error: StepFailure::Abort,
+                    }),
+                    count: 2,
+                },
+                tap(KeyCode::C),
+            ],
+            on_error: StepFailure::Abort,
+        };
+
+        engine.dispatch(&output, &event(KeyCode::F13, KeyState::Down));
+        let taps = |keys: &[KeyCode]| {
+            keys.iter()
+                .flat_map(|&k| [inject(k, KeyState::Down), inject(k, KeyState::Up)])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(recorder.actions(), taps(&[KeyCode::A]));
+        std::thread::sleep(Duration::from_millis(5));
+        engine.poll();
+        assert_eq!(
+            recorder.actions(),
+            taps(&[KeyCode::A, KeyCode::B, KeyCode::A])
+        );
+        std::thread::sleep(Duration::from_millis(5));
+        engine.poll();
+        assert_eq!(
+            recorder.actions(),
+            taps(&[KeyCode::A, KeyCode::B, KeyCode::A, KeyCode::B, KeyCode::C])
+        );
+        assert!(!engine.has_pending());
+    }
+
+    #[test]
+    fn repeated_copies_are_paced_by_the_type_delay() {
+        let (mut engine, recorder) = engine_from_toml("[executor]\ntype_delay_ms = 1");
+        let repeat = Action::Repeat {
+            action: Box::new(Action::InjectChar('x')),
+            count: 2,
+        };
+
+        engine.dispatch(&repeat, &event(KeyCode::F13, KeyState::Down));
+        assert_eq!(recorder.actions(), vec![Action::InjectChar('x')]);
+        assert!(engine.has_pending());
+        std::thread::sleep(Duration::from_millis(5));
+        engine.poll();
+        assert_eq!(recorder.actions(), vec![Action::InjectChar('x'); 2]);
+    }
+
+    #[test]
+    fn repeat_zero_is_a_no_op() {
+        let (mut engine, recorder) = engine_from_toml("");
+        let log = record_suppressed(&mut engine);
+        let repeat = Action::Repeat {
+            action: Box::new(Action::KeyCombo(vec![KeyCode::Down])),
+            count: 0,
+        };
+
+        engine.dispatch(&repeat, &event(KeyCode::F13, KeyState::Down));
+
+        assert_eq!(recorder.actions(), vec![Action::Suppress]);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![(KeyCode::F13, KeyState::Down, SuppressReason::NoOutput)]
+        );
+    }
+
+    const SAFETY_CONFIG: &str = r#"
+        [safety]
+        key = "ScrollLock"
+
+        [[remap]]
+        from = "A"
+        to   = "B"
+    "#;
+
+    #[test]
+    fn safety_key_gates_rules() {
+        let (mut engine, recorder) = engine_from_toml(SAFETY_CONFIG);
+
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.handle(&event(KeyCode::A, KeyState::Up));
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::A, KeyState::Down),
+                inject(KeyCode::A, KeyState::Up),
+            ]
+        );
+
+        recorder.clear();
+        engine.handle(&event(KeyCode::ScrollLock, KeyState::Down));
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.handle(&event(KeyCode::A, KeyState::Up));
+        engine.handle(&event(KeyCode::ScrollLock, KeyState::Up));
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                Action::Suppress,
+                inject(KeyCode::B, KeyState::Down),
+                inject(KeyCode::B, KeyState::Up),
+                Action::Suppress,
+            ]
+        );
+
+        recorder.clear();
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        assert_eq!(recorder.actions(), vec![inject(KeyCode::A, KeyState::Down)]);
+    }
+
+    /// Letting go of the safety key mid-press still releases the remapped
+    /// key, so nothing is left stuck.
+    #[test]
+    fn key_held_across_safety_release_is_released_through_rules() {
+        let (mut engine, recorder) = engine_from_toml(SAFETY_CONFIG);
+
+        engine.handle(&event(KeyCode::ScrollLock, KeyState::Down));
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.handle(&event(KeyCode::ScrollLock, KeyState::Up));
+        recorder.clear();
+        engine.handle(&event(KeyCode::A, KeyState::Up));
+
+        assert_eq!(recorder.actions(), vec![inject(KeyCode::B, KeyState::Up)]);
+    }
+
+    #[test]
+    fn plain_events_pass_through_to_executor() {
+        let (mut engine, recorder) = engine_from_toml("");
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        assert_eq!(recorder.actions(), vec![inject(KeyCode::A, KeyState::Down)]);
+    }
+
+    // --- Type clipboard ---
+
+    const CLIPBOARD_CONFIG: &str = r#"
+        [[hotkey]]
+        keys   = ["Ctrl", "Shift", "V"]
+        action = "type_clipboard"
+    "#;
+
+    /// Press the `CLIPBOARD_CONFIG` hotkey and poll until the read is done.
+    fn type_clipboard(text: Option<&str>) -> Vec<Action> {
+        let (engine, recorder) = engine_from_toml(CLIPBOARD_CONFIG);
+        let mut engine = engine.with_clipboard(Box::new(MockClipboard::new(text)));
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Down));
+        engine.handle(&event(KeyCode::Shift, KeyState::Down));
+        recorder.clear();
+        engine.handle(&event(KeyCode::V, KeyState::Down));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while engine.has_pending() && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+            engine.poll();
+        }
+        assert!(!engine.has_pending(), "clipboard read never finished");
+        recorder.actions()
+    }
+
+    #[test]
+    fn type_clipboard_types_the_clipboard_text() {
+        assert_eq!(
+            type_clipboard(Some("hello")),
+            vec![
+                Action::Suppress,
+                Action::TypeString {
+                    text: "hello".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn type_clipboard_types_nothing_without_text() {
+        assert_eq!(type_clipboard(None), vec![Action::Suppress]);
+    }
+
+    /// Text read after the user paused is dropped, like a paste.
+    #[test]
+    fn clipboard_text_read_after_pausing_is_not_typed() {
+        let (engine, recorder) = engine_from_toml(CLIPBOARD_CONFIG);
+        let mut engine = engine.with_clipboard(Box::new(MockClipboard::new(Some("hello"))));
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Down));
+        engine.handle(&event(KeyCode::Shift, KeyState::Down));
+        recorder.clear();
+        engine.handle(&event(KeyCode::V, KeyState::Down));
+        engine.pause_handle().set_paused(true);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while engine.has_pending() && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+            engine.poll();
+        }
+        assert!(!engine.has_pending(), "clipboard read never finished");
+        assert_eq!(recorder.actions(), vec![Action::Suppress]);
+    }
+
+    #[test]
+    fn unsupported_rules_count_the_engine_clipboard() {
+        let toml = r#"
+            [[remap]]
+            from = "F1"
+            to   = { paste = "pasted" }
+
+            [[remap]]
+            from = "F2"
+            to   = { button = "left" }
+            "#;
+        let (engine, recorder) = engine_from_toml(toml);
+        recorder.set_capabilities(Capabilities {
+            inject_key: true,
+            ..Capabilities::default()
+        });
+        let config = crate::config::parse_str(toml).unwrap();
+        let found: Vec<String> = engine
+            .unsupported_rules(&config)
+            .iter()
+            .map(|rule| rule.rule.to_string())
+            .collect();
+        assert_eq!(found, vec!["[[remap]] #1", "[[remap]] #2"]);
+
+        let engine = engine.with_clipboard(Box::new(MockClipboard::new(None)));
+        let found = engine.unsupported_rules(&config);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].reason, "cannot press mouse buttons");
+    }
+
+    #[test]
+    fn paste_sets_the_clipboard_presses_paste_and_restores() {
+        let (engine, recorder) = engine_from_toml(
+            r#"
+            [clipboard]
+            restore_after_ms = 20
+
+            [[remap]]
+            from = "F1"
+            to   = { paste = "pasted" }
+            "#,
+        );
+        let clipboard = MockClipboard::new(Some("old"));
+        let mut engine = engine.with_clipboard(Box::new(clipboard.clone()));
+        engine.handle(&event(KeyCode::F1, KeyState::Down));
+        engine.handle(&event(KeyCode::F1, KeyState::Up));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while engine.has_pending() && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+            engine.poll();
+        }
+        let actions = recorder.actions();
+        assert_eq!(actions[..2], [Action::Suppress, Action::Suppress]);
+        assert!(
+            matches!(
+                actions[2..],
+                [Action::Chord {
+                    key: KeyCode::V,
+                    ..
+                }]
+            ),
+            "{actions:?}"
+        );
+
+        // The old text comes back once the restore delay is over.
+        while clipboard.writes().len() < 2 && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        assert_eq!(clipboard.writes(), vec!["pasted", "old"]);
+        assert_eq!(clipboard.text().as_deref(), Some("old"));
+    }
+
+    // --- Text ---
+
+    #[test]
+    fn char_is_typed_as_keys_without_a_text_path() {
+        let (mut engine, recorder) =
+            engine_from_toml("[[remap]]\nfrom = \"F1\"\nto = { char = \"A\" }");
+        recorder.set_text_injection(TextInjection::Keys);
+        engine.handle(&event(KeyCode::F1, KeyState::Down));
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                inject(KeyCode::Shift, KeyState::Down),
+                inject(KeyCode::A, KeyState::Down),
+                inject(KeyCode::A, KeyState::Up),
+                inject(KeyCode::Shift, KeyState::Up),
+            ]
+        );
+        assert_eq!(recorder.batches(), vec![4]);
+    }
+
+    /// With a type delay, the characters after the first are typed from
+    /// `poll`, and a step after the text waits for all of it.
+    #[test]
+    fn paced_text_is_typed_from_poll_without_blocking() {
+        let (mut engine, recorder) = engine_from_toml("[executor]\ntype_delay_ms = 1");
+        let output = Action::Sequence {
+            steps: vec![
+                Action::TypeString { text: "aB".into() },
+                Action::KeyCombo(vec![KeyCode::F2]),
+            ],
+            on_error: StepFailure::Abort,
+        };
+        recorder.set_text_injection(TextInjection::Keys);
+        engine.dispatch(&output, &event(KeyCode::F1, KeyState::Down));
+        let a = vec![
+            inject(KeyCode::A, KeyState::Down),
+            inject(KeyCode::A, KeyState::Up),
+        ];
+        assert_eq!(recorder.actions(), a);
+        assert!(engine.has_pending());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while engine.has_pending() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(2));
+            engine.poll();
+        }
+        let shifted_b = vec![
+            inject(KeyCode::Shift, KeyState::Down),
+            inject(KeyCode::B, KeyState::Down),
+            inject(KeyCode::B, KeyState::Up),
+            inject(KeyCode::Shift, KeyState::Up),
+        ];
+        let f2 = vec![
+            inject(KeyCode::F2, KeyState::Down),
+            inject(KeyCode::F2, KeyState::Up),
+        ];
+        assert_eq!(recorder.actions(), [a, shifted_b, f2].concat());
+    }
+
+    #[test]
+    fn text_goes_by_character_to_a_char_backend() {
+        let (engine, recorder) = engine_from_toml(CLIPBOARD_CONFIG);
+        recorder.set_text_injection(TextInjection::Chars);
+        let mut engine = engine.with_clipboard(Box::new(MockClipboard::new(Some("hé"))));
+        engine.handle(&event(KeyCode::Ctrl, KeyState::Down));
+        engine.handle(&event(KeyCode::Shift, KeyState::Down));
+        recorder.clear();
+        engine.handle(&event(KeyCode::V, KeyState::Down));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while engine.has_pending() && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+            engine.poll();
+        }
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                Action::Suppress,
+                Action::InjectChar('h'),
+                Action::InjectChar('é')
+            ]
+        );
+    }
+
+    // --- Delays ---
+
+    #[test]
+    fn a_delay_holds_up_only_its_own_rule() {
+        let (mut engine, recorder) = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = { steps = ["F1", { delay_ms = 60000 }, "F2"] }
+
+            [[remap]]
+            from = "A"
+            to   = "B"
+        "#,
+        );
+        engine.handle(&event(KeyCode::F13, KeyState::Down));
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        engine.poll();
+        assert_eq!(
+            recorder.actions(),
+            [
+                inject(KeyCode::F1, KeyState::Down),
+                inject(KeyCode::F1, KeyState::Up),
+                inject(KeyCode::B, KeyState::Down),
+            ]
+        );
+        assert!(engine.has_pending());
+    }
+
+    #[test]
+    fn delayed_steps_run_from_poll_once_due() {
+        let (mut engine, recorder) = engine_from_toml(
+            "[[remap]]\nfrom = \"F13\"\nto = { steps = [{ delay_ms = 1 }, \"F2\"] }",
+        );
+        engine.handle(&event(KeyCode::F13, KeyState::Down));
+        assert!(recorder.actions().is_empty());
+        std::thread::sleep(Duration::from_millis(5));
+        engine.poll();
+        assert_eq!(
+            recorder.actions(),
+            [
+                inject(KeyCode::F2, KeyState::Down),
+                inject(KeyCode::F2, KeyState::Up),
+            ]
+        );
+        assert!(!engine.has_pending());
+    }
+
+    fn keys_then_f2(on_error: StepFailure) -> Action {
+        Action::Sequence {
+            steps: vec![
+                Action::KeyCombo(vec![KeyCode::F1]),
+                Action::KeyCombo(vec![KeyCode::F2]),
+            ],
+            on_error,
+        }
+    }
+
+    #[test]
+    fn a_failed_copy_of_a_repeat_aborts_its_sequence() {
+        let (mut engine, recorder) = engine_from_toml("");
+        recorder.fail_on(KeyCode::F1);
+        let output = Action::Sequence {
+            steps: vec![
+                Action::Repeat {
+                    action: Box::new(Action::KeyCombo(vec![KeyCode::F1])),
+                    count: 2,
+                },
+                Action::KeyCombo(vec![KeyCode::F2]),
+            ],
+            on_error: StepFailure::Abort,
+        };
+        engine.dispatch(&output, &event(KeyCode::F13, KeyState::Down));
+        assert!(recorder.actions().is_empty());
+    }
+
+    #[test]
+    fn a_failed_step_aborts_or_continues_its_sequence() {
+        let (mut engine, recorder) = engine_from_toml("");
+        recorder.fail_on(KeyCode::F1);
+        let source = event(KeyCode::F13, KeyState::Down);
+        engine.dispatch(&keys_then_f2(StepFailure::Abort), &source);
+        assert!(recorder.actions().is_empty());
+        engine.dispatch(&keys_then_f2(StepFailure::Continue), &source);
+        assert_eq!(
+            recorder.actions(),
+            [
+                inject(KeyCode::F2, KeyState::Down),
+                inject(KeyCode::F2, KeyState::Up),
+            ]
+        );
+        // An aborted inner sequence is a failed step of the outer one.
+        recorder.clear();
+        let outer = Action::Sequence {
+            steps: vec![
+                keys_then_f2(StepFailure::Abort),
+                Action::KeyCombo(vec![KeyCode::F3]),
+            ],
+            on_error: StepFailure::Abort,
+        };
+        engine.dispatch(&outer, &source);
+        assert!(recorder.actions().is_empty());
+    }
+
+    #[test]
+    fn sequences_nest_only_so_deep() {
+        let (mut engine, recorder) = engine_from_toml("");
+        let nest = |depth| {
+            (0..depth).fold(Action::KeyCombo(vec![KeyCode::F1]), |action, _| {
+                Action::Sequence {
+                    steps: vec![action],
+                    on_error: StepFailure::Abort,
+                }
+            })
+        };
+        let source = event(KeyCode::F13, KeyState::Down);
+        engine.resume(
+            vec![Frame::new(
+                vec![nest(MAX_SEQUENCE_DEPTH)],
+                StepFailure::Continue,
+            )],
+            &source,
+        );
+        assert_eq!(recorder.actions().len(), 2);
+        recorder.clear();
+        engine.resume(
+            vec![Frame::new(
+                vec![nest(MAX_SEQUENCE_DEPTH + 1)],
+                StepFailure::Continue,
+            )],
+            &source,
+        );
+        assert!(recorder.actions().is_empty());
+    }
+
+    #[test]
+    fn pausing_drops_delayed_steps() {
+        let (mut engine, recorder) = engine_from_toml(
+            "[[remap]]\nfrom = \"F13\"\nto = { steps = [{ delay_ms = 1 }, \"F2\"] }",
+        );
+        engine.handle(&event(KeyCode::F13, KeyState::Down));
+        engine.pause_handle().set_paused(true);
+        std::thread::sleep(Duration::from_millis(5));
+        engine.poll();
+        assert!(recorder.actions().is_empty());
+        assert!(!engine.has_pending());
+    }
+
+    // --- Repeat while held ---
+
+    /// Long enough that the real clock, which `handle` polls with, never
+    /// reaches a repeat; the tests run the timers on a clock of their own.
+    const HELD_CONFIG: &str = r#"
+        [[remap]]
+        from            = "F13"
+        to              = "F2"
+        repeat_every_ms = 1000
+    "#;
+
+    fn held_at(key: KeyCode, state: KeyState, at: Instant, is_repeat: bool) -> InputEvent {
+        InputEvent {
+            timestamp: at,
+            is_repeat,
+            ..event(key, state)
+        }
+    }
+
+    /// The key presses recorded.
+    fn taps(recorder: &RecordingExecutor) -> usize {
+        recorder
+            .actions()
+            .iter()
+            .filter(|a| {
+                matches!(
+                    a,
+                    Action::InjectKey {
+                        state: KeyState::Down,
+                        ..
+                    }
+                )
+            })
+            .count()
+    }
+
+    #[test]
+    fn held_key_repeats_on_the_interval_until_released() {
+        let (mut engine, recorder) = engine_from_toml(HELD_CONFIG);
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+
+        engine.handle(&held_at(KeyCode::F13, KeyState::Down, t0, false));
+        assert_eq!(
+            recorder.actions(),
+            [
+                inject(KeyCode::F2, KeyState::Down),
+                inject(KeyCode::F2, KeyState::Up),
+            ]
+        );
+        engine.run_timers(ms(999));
+        assert_eq!(taps(&recorder), 1);
+        engine.run_timers(ms(1000));
+        assert_eq!(taps(&recorder), 2);
+        // The key's own auto-repeat adds nothing.
+        engine.handle(&held_at(KeyCode::F13, KeyState::Down, ms(1500), true));
+        assert_eq!(taps(&recorder), 2);
+        engine.run_timers(ms(2000));
+        assert_eq!(taps(&recorder), 3);
+        // A stall is not caught up in a burst.
+        engine.run_timers(ms(9000));
+        engine.run_timers(ms(9500));
+        assert_eq!(taps(&recorder), 4);
+        engine.run_timers(ms(10_000));
+        assert_eq!(taps(&recorder), 5);
+
+        engine.handle(&held_at(KeyCode::F13, KeyState::Up, ms(10_100), false));
+        assert!(!engine.has_pending());
+        engine.run_timers(ms(20_000));
+        assert_eq!(taps(&recorder), 5);
+    }
+
+    #[test]
+    fn held_key_repeats_stop_on_pause_and_at_the_cap() {
+        let (mut engine, recorder) = engine_from_toml(HELD_CONFIG);
+        let t0 = Instant::now();
+        engine.handle(&held_at(KeyCode::F13, KeyState::Down, t0, false));
+        engine.pause_handle().set_paused(true);
+        engine.run_timers(t0 + Duration::from_secs(1));
+        assert_eq!(taps(&recorder), 1);
+        assert!(!engine.has_pending());
+
+        engine.pause_handle().set_paused(false);
+        engine.handle(&held_at(KeyCode::F13, KeyState::Down, t0, false));
+        recorder.clear();
+        for n in 1..=u64::from(MAX_HELD_REPEATS) + 5 {
+            engine.run_timers(t0 + Duration::from_secs(n));
+        }
+        // The Down counts as the first run.
+        assert_eq!(taps(&recorder), MAX_HELD_REPEATS as usize - 1);
+        assert!(!engine.has_pending());
+    }
+
+    #[test]
+    fn repeat_while_held_inside_another_does_not_run() {
+        let (mut engine, recorder) = engine_from_toml("");
+        let inner = Action::RepeatWhileHeld {
+            interval_ms: 10,
+            action: Box::new(Action::KeyCombo(vec![KeyCode::F2])),
+        };
+        let outer = Action::RepeatWhileHeld {
+            interval_ms: 10,
+            action: Box::new(Action::Sequence {
+                steps: vec![inner],
+                on_error: StepFailure::Abort,
+            }),
+        };
+        engine.dispatch(&outer, &event(KeyCode::F13, KeyState::Down));
+        assert_eq!(recorder.actions(), [Action::Suppress]);
+        assert!(!engine.has_pending());
+    }
+
+    // --- Exec ---
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn type_output_types_what_the_command_prints() {
+        let (engine, recorder) = engine_from_toml(
+            "[[hotkey]]\nkeys = [\"F9\"]\naction = \"type_output\"\ncommand = \"echo hello\"",
+        );
+        let mut engine = engine.with_launcher(ExecSettings::default());
+        engine.handle(&event(KeyCode::F9, KeyState::Down));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while engine.has_pending() && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+            engine.poll();
+        }
+        assert_eq!(
+            recorder.actions(),
+            vec![
+                Action::Suppress,
+                Action::TypeString {
+                    text: "hello".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn exec_goes_to_the_launcher_not_the_executor() {
+        let (engine, recorder) = engine_from_toml(
+            "[[hotkey]]\nkeys = [\"F9\"]\naction = \"exec\"\ncommand = \"exit 0\"",
+        );
+        let mut engine = engine.with_launcher(ExecSettings::default());
+        engine.handle(&event(KeyCode::F9, KeyState::Down));
+        assert_eq!(recorder.actions(), vec![Action::Suppress]);
+    }
+
+    #[test]
+    fn reload_releases_held_keys_and_applies_new_rules() {
+        let (mut engine, recorder) = engine_from_toml("[[remap]]\nfrom = \"A\"\nto = \"B\"");
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        let config = crate::config::parse_str("[[remap]]\nfrom = \"A\"\nto = \"C\"").unwrap();
+        engine.reload(&config);
+        engine.handle(&event(KeyCode::A, KeyState::Up));
+        engine.handle(&event(KeyCode::A, KeyState::Down));
+        assert_eq!(
+            recorder.actions(),
+            [
+                inject(KeyCode::B, KeyState::Down),
+                // Released through the old rule, then the key's own Up
+                // passes through.
+                inject(KeyCode::B, KeyState::Up),
+                inject(KeyCode::A, KeyState::Up),
+                inject(KeyCode::C, KeyState::Down),
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! NumLock policy for injected numpad digits.
+//!
+//! With NumLock off, Numpad0-9 and NumpadDecimal act as navigation keys
+//! (Numpad8 is Up, Numpad4 is Left; see `KeyCode::numpad_navigation`) on
+//! Linux, macOS, and Windows alike. When a rule outputs one of them the
+//! engine resolves it here, according to `NumpadPolicy`, so that
+//! backends only ever receive keys that already mean what the rule intended.
+
+use crate::config::NumpadPolicy;
+use crate::platform::{KeyCode, KeyState};
+
+/// Resolve one injected key into the `(key, state)` steps to send.
+///
+/// `numlock` is the tracked NumLock state, `None` when unknown. Keys that do
+/// not follow NumLock, and those injected while it is on, pass through
+/// unchanged. With
+/// NumLock off, `ToggleNumLock` taps NumLock before and after the digit, and
+/// `TopRow` swaps in the top-row digit. When the state is unknown a toggle
+/// could just as well turn NumLock off, so the top-row digit is used instead.
+/// NumpadDecimal's top-row stand-in is Period.
+pub(super) fn resolve(
+    key: KeyCode,
+    state: KeyState,
+    numlock: Option<bool>,
+    policy: NumpadPolicy,
+) -> Vec<(KeyCode, KeyState)> {
+    let Some(top_row) = top_row_key(key) else {
+        return vec![(key, state)];
+    };
+    match (numlock, policy) {
+        (Some(true), _) => vec![(key, state)],
+        (Some(false), NumpadPolicy::ToggleNumLock) => vec![
+            (KeyCode::NumLock, KeyState::Down),
+            (KeyCode::NumLock, KeyState::Up),
+            (key, state),
+            (KeyCode::NumLock, KeyState::Down),
+            (KeyCode::NumLock, KeyState::Up),
+        ],
+        (Some(false), NumpadPolicy::TopRow) | (None, _) => vec![(top_row, state)],
+    }
+}
+
+/// The main-block key typing what `key` types with NumLock on, or `None`
+/// for keys that do not follow NumLock.
+fn top_row_key(key: KeyCode) -> Option<KeyCode> {
+    match key {
+        KeyCode::Numpad0 => Some(KeyCode::Key0),
+        KeyCode::Numpad1 => Some(KeyCode::Key1),
+        KeyCode::Numpad2 => Some(KeyCode::Key2),
+        KeyCode::Numpad3 => Some(KeyCode::Key3),
+        KeyCode::Numpad4 => Some(KeyCode::Key4),
+        KeyCode::Numpad5 => Some(KeyCode::Key5),
+        KeyCode::Numpad6 => Some(KeyCode::Key6),
+        KeyCode::Numpad7 => Some(KeyCode::Key7),
+        KeyCode::Numpad8 => Some(KeyCode::Key8),
+        KeyCode::Numpad9 => Some(KeyCode::Key9),
+        KeyCode::NumpadDecimal => Some(KeyCode::Period),
+        _ => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_digit_keys_are_untouched() {
+        let got = resolve(
+            KeyCode::NumpadAdd,
+            KeyState::Down,
+            Some(false),
+            NumpadPolicy::TopRow,
+        );
+        assert_eq!(got, vec![(KeyCode::NumpadAdd, KeyState::Down)]);
+    }
+
+    #[test]
+    fn digit_with_numlock_on_is_untouched() {
+        let got = resolve(
+            KeyCode::Numpad8,
+            KeyState::Down,
+            Some(true),
+            NumpadPolicy::ToggleNumLock,
+        );
+        assert_eq!(got, vec![(KeyCode::Numpad8, KeyState::Down)]);
+    }
+
+    #[test]
+    fn toggle_policy_wraps_digit_in_numlock_taps() {
+        let got = resolve(
+            KeyCode::Numpad8,
+            KeyState::Up,
+            Some(false),
+            NumpadPolicy::ToggleNumLock,
+        );
+        assert_eq!(
+            got,
+            vec![
+                (KeyCode::NumLock, KeyState::Down),
+                (KeyCode::NumLock, KeyState::Up),
+                (KeyCode::Numpad8, KeyState::Up),
+                (KeyCode::NumLock, KeyState::Down),
+                (KeyCode::NumLock, KeyState::Up),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_row_policy_translates_digit() {
+        let got = resolve(
+            KeyCode::Numpad0,
+            KeyState::Down,
+            Some(false),
+            NumpadPolicy::TopRow,
+        );
+        assert_eq!(got, vec![(KeyCode::Key0, KeyState::Down)]);
+        let got = resolve(
+            KeyCode::NumpadDecimal,
+            KeyState::Down,
+            Some(false),
+            NumpadPolicy::TopRow,
+        );
+        assert_eq!(got, vec![(KeyCode::Period, KeyState::Down)]);
+    }
+
+    #[test]
+    fn unknown_numlock_state_falls_back_to_top_row() {
+        let got = resolve(
+            KeyCode::Numpad5,
+            KeyState::Down,
+            None,
+            NumpadPolicy::ToggleNumLock,
+        );
+        assert_eq!(got, vec![(KeyCode::Key5, KeyState::Down)]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Dry runs: what a config does with a list of events, without doing it.
+//!
+//! `simulate` builds a fresh `Engine` around an executor that only records,
+//! feeds it the events in order, and returns the actions each one produced.
+//! Everything the engine does on the way is included: pause and safety
+//! handling, combo lowering, the numpad policy, and dedup. Nothing reaches
+//! the OS, so `Exec` and `SystemCommand` actions are reported, not run.
+//!
+//! Timing is deterministic: the engine reads time only from each event's
+//! `timestamp`, which the caller supplies. The typing pace,
+//! `[executor] type_delay_ms`, is left out, so text and repeats are reported
+//! whole with the event that produced them. NumLock starts in an unknown
+//! state, as when a backend cannot read it.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::Engine;
+use crate::config::Config;
+use crate::platform::{
+    Action, ActionExecutor, Capabilities, InputEvent, PlatformError, TextInjection,
+};
+
+/// Executor that records actions and never fails.
+#[derive(Clone, Default)]
+struct DryRunExecutor {
+    actions: Arc<Mutex<Vec<Action>>>,
+}
+
+impl DryRunExecutor {
+    fn take(&self) -> Vec<Action> {
+        std::mem::take(&mut *self.actions.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl ActionExecutor for DryRunExecutor {
+    fn execute(&self, action: &Action) -> Result<(), PlatformError> {
+        self.actions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(action.clone());
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "simulate"
+    }
+
+    /// Text is reported as written.
+    fn text_injection(&self) -> TextInjection {
+        TextInjection::Strings
+    }
+
+    /// Everything is reported, so nothing is left out of a dry run.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            inject_key: true,
+            type_text: true,
+            pointer: true,
+            system_command: true,
+            clipboard: true,
+        }
+    }
+}
+
+/// Run `events` through an engine built from `config` and return, for each
+/// event, the actions it made the engine execute, in order. An event that
+/// produced nothing is paired with an empty list.
+pub fn simulate(config: &Config, events: &[InputEvent]) -> Vec<(InputEvent, Vec<Action>)> {
+    let recorder = DryRunExecutor::default();
+    let config = Config {
+        type_delay: Duration::ZERO,
+        ..config.clone()
+    };
+    let mut engine = Engine::new(&config, Box::new(recorder.clone()));
+    events
+        .iter()
+        .map(|event| {
+            engine.handle(event);
+            (event.clone(), recorder.take())
+        })
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::platform::{KeyCode, KeyState, Modifiers, WindowContext};
+
+    fn events(start: Instant, keys: &[(KeyCode, KeyState, u64)]) -> Vec<InputEvent> {
+        keys.iter()
+            .map(|&(key, state, ms)| InputEvent {
+                key,
+                state,
+                modifiers: Modifiers::default(),
+                window: WindowContext::default(),
+                timestamp: start + Duration::from_millis(ms),
+                device: None,
+                observed: false,
+                is_repeat: false,
+                position: None,
+            })
+            .collect()
+    }
+
+    fn inject(key: KeyCode, state: KeyState) -> Action {
+        Action::InjectKey { key, state }
+    }
+
+    fn actions(config: &str, keys: &[(KeyCode, KeyState, u64)]) -> Vec<Vec<Action>> {
+        let config = crate::config::parse_str(config).unwrap();
+        simulate(&config, &events(Instant::now(), keys))
+            .into_iter()
+            .map(|(_, actions)| actions)
+            .collect()
+    }
+
+    #[test]
+    fn remap_and_passthrough() {
+        let config = r#"
+            [[remap]]
+            from = "CapsLock"
+            to   = "Escape"
+        "#;
+        let got = actions(
+            config,
+            &[
+                (KeyCode::CapsLock, KeyState::Down, 0),
+                (KeyCode::CapsLock, KeyState::Up, 80),
+                (KeyCode::A, KeyState::Down, 200),
+            ],
+        );
+        assert_eq!(
+            got,
+            vec![
+                vec![inject(KeyCode::Escape, KeyState::Down)],
+                vec![inject(KeyCode::Escape, KeyState::Up)],
+                vec![inject(KeyCode::A, KeyState::Down)],
+            ]
+        );
+    }
+
+    #[test]
+    fn hotkey_chord_runs_nothing_and_reports_exec() {
+        let config = r#"
+            [[hotkey]]
+            keys   = ["Ctrl", "Alt", "T"]
+            action = "exec"
+            command = "xterm"
+        "#;
+        let got = actions(
+            config,
+            &[
+                (KeyCode::Ctrl, KeyState::Down, 0),
+                (KeyCode::Alt, KeyState::Down, 10),
+                (KeyCode::T, KeyState::Down, 20),
+            ],
+        );
+        assert_eq!(got[0], vec![inject(KeyCode::Ctrl, KeyState::Down)]);
+        assert_eq!(
+            got[2],
+            vec![Action::Exec {
+                command: "xterm".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn combo_output_is_lowered() {
+        let config = r#"
+            [[remap]]
+            from = "F13"
+            to   = "Ctrl+C"
+        "#;
+        let got = actions(config, &[(KeyCode::F13, KeyState::Down, 0)]);
+        assert_eq!(
+            got,
+            vec![vec![Action::Chord {
+                modifiers: Modifiers {
+                    ctrl: true,
+                    ..Modifiers::default()
+                },
+                key: KeyCode::C,
+            }]]
+        );
+    }
+
+    #[test]
+    fn typing_pace_does_not_split_a_repeat() {
+        let config = r#"
+            [executor]
+            type_delay_ms = 20
+
+            [[remap]]
+            from   = "F13"
+            to     = "Down"
+            repeat = 2
+        "#;
+        let got = actions(config, &[(KeyCode::F13, KeyState::Down, 0)]);
+        let tap = [
+            inject(KeyCode::Down, KeyState::Down),
+            inject(KeyCode::Down, KeyState::Up),
+        ];
+        assert_eq!(got, vec![[tap.clone(), tap].concat()]);
+    }
+
+    #[test]
+    fn supplied_timestamps_drive_dedup() {
+        let config = r#"
+            [capture]
+            dedup = true
+        "#;
+        let got = actions(
+            config,
+            &[
+                (KeyCode::A, KeyState::Down, 0),
+                (KeyCode::A, KeyState::Down, 0),
+                (KeyCode::A, KeyState::Up, 100),
+            ],
+        );
+        assert_eq!(
+            got,
+            vec![
+                vec![inject(KeyCode::A, KeyState::Down)],
+                vec![],
+                vec![inject(KeyCode::A, KeyState::Up)],
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Checking the loaded rules against what the executor can do.
+//!
+//! Some rules need more than key injection: a mouse button target needs the
+//! pointer, a paste needs the clipboard, and text needs a way to type each
+//! character in it. `unsupported` finds the rules that can never work with
+//! the executor's `Capabilities`, so they are reported once when the rules
+//! are loaded instead of failing quietly on every press.
+
+use std::fmt;
+
+use super::text;
+use crate::config::{Config, HotkeyAction, RemapTarget, RuleKind, RuleRef};
+use crate::platform::{Capabilities, TextInjection};
+
+/// A rule that can never work with the executor in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsupported {
+    pub rule: RuleRef,
+    /// The executor's name.
+    pub backend: &'static str,
+    /// What the executor lacks, worded to follow "the executor".
+    pub reason: String,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} can never work: the {} executor {}",
+            self.rule, self.backend, self.reason
+        )
+    }
+}
+
+/// Every rule in `config` that needs something `caps` lacks, in file order:
+/// remaps, then hotkeys, then hotstrings.
+pub fn unsupported(config: &Config, caps: Capabilities, backend: &'static str) -> Vec<Unsupported> {
+    let remaps = config.remaps.iter().map(|rule| match &rule.to {
+        RemapTarget::Button(_) => lacks(caps.pointer, "cannot press mouse buttons"),
+        RemapTarget::Paste(_) if !caps.clipboard => Some("cannot set the clipboard".into()),
+        RemapTarget::Char(c) => untypeable(&c.to_string(), caps),
+        _ => lacks(caps.inject_key, "cannot inject keys"),
+    });
+    let hotkeys = config.hotkeys.iter().map(|rule| match &rule.action {
+        HotkeyAction::System(_) => lacks(caps.system_command, "cannot run system commands"),
+        HotkeyAction::TypeClipboard if !caps.clipboard => Some("cannot read the clipboard".into()),
+        HotkeyAction::TypeClipboard | HotkeyAction::TypeOutput(_) => untypeable("", caps),
+        HotkeyAction::Exec(_) | HotkeyAction::SetPaused(_) => None,
+    });
+    let hotstrings = config
+        .hotstrings
+        .iter()
+        .map(|rule| untypeable(&rule.replacement, caps));
+    [
+        (RuleKind::Remap, remaps.collect::<Vec<_>>()),
+        (RuleKind::Hotkey, hotkeys.collect()),
+        (RuleKind::Hotstring, hotstrings.collect()),
+    ]
+    .into_iter()
+    .flat_map(|(kind, reasons)| {
+        reasons
+            .into_iter()
+            .enumerate()
+            .filter_map(move |(index, reason)| {
+                Some(Unsupported {
+                    rule: RuleRef { kind, index },
+                    backend,
+                    reason: reason?,
+                })
+            })
+    })
+    .collect()
+}
+
+/// `reason` unless the executor `can`.
+fn lacks(can: bool, reason: &str) -> Option<String> {
+    (!can).then(|| reason.to_owned())
+}
+
+/// Why `text` cannot be typed in full, if it cannot. Without a text path
+/// the executor types by key, and only what a key on a US layout produces
+/// comes through.
+fn untypeable(text: &str, caps: Capabilities) -> Option<String> {
+    if caps.type_text {
+        return None;
+    }
+    if !caps.inject_key {
+        return Some("cannot type text".into());
+    }
+    let (_, missing) = text::lower(text, TextInjection::Keys, false);
+    (!missing.is_empty()).then(|| format!("has no key for {missing:?}"))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys_only() -> Capabilities {
+        Capabilities {
+            inject_key: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn config(toml: &str) -> Config {
+        crate::config::parse_str(toml).unwrap()
+    }
+
+    #[test]
+    fn reports_each_rule_the_executor_cannot_run() {
+        let config = config(
+            r#"
+            [[remap]]
+            from = "F1"
+            to = "F2"
+
+            [[remap]]
+            from = "F3"
+            to = { button = "left" }
+
+            [[remap]]
+            from = "F4"
+            to = { char = "é" }
+
+            [[hotkey]]
+            keys = ["Ctrl", "L"]
+            action = "lock_screen"
+
+            [[hotstring]]
+            trigger = ";sig"
+            replacement = "Ana Núñez"
+            "#,
+        );
+        let found: Vec<String> = unsupported(&config, keys_only(), "uinput")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                "[[remap]] #2 can never work: the uinput executor cannot press mouse buttons",
+                "[[remap]] #3 can never work: the uinput executor has no key for [\"é\"]",
+                "[[hotkey]] #1 can never work: the uinput executor cannot run system commands",
+                "[[hotstring]] #1 can never work: the uinput executor has no key for [\"ú\", \"ñ\"]",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_full_executor_runs_everything() {
+        let config = config(
+            r#"
+            [[remap]]
+            from = "F3"
+            to = { paste = "héllo" }
+
+            [[hotkey]]
+            keys = ["Ctrl", "V"]
+            action = "type_clipboard"
+            "#,
+        );
+        let caps = Capabilities {
+            inject_key: true,
+            type_text: true,
+            pointer: true,
+            system_command: true,
+            clipboard: true,
+        };
+        assert!(unsupported(&config, caps, "mock").is_empty());
+        let found = unsupported(&config, keys_only(), "mock");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].reason, "cannot set the clipboard");
+        assert_eq!(found[1].reason, "cannot read the clipboard");
+    }
+}
@@ -0,0 +1,239 @@
+//! Lowering typed text to what the executor can type.
+//!
+//! Backends type text in one of three ways, which they report through
+//! `ActionExecutor::text_injection`: whole strings, any character one at a
+//! time, or not at all. `lower` turns a string into the actions for the
+//! executor's way, one step per grapheme cluster, so a character built from
+//! several code points (a letter and its accent, a flag) is never split
+//! across steps. Where the executor has no text path, each grapheme is
+//! typed with the key that produces it on a US layout, with Shift where it
+//! takes one; the graphemes no key produces are reported back.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::platform::{Action, KeyCode, KeyState, TextInjection};
+
+/// `text` as steps for an executor that types the `mode` way, and the
+/// graphemes left out, each once. With `paced` set, strings are split into
+/// one step per grapheme, so the caller can wait between them.
+pub(super) fn lower(text: &str, mode: TextInjection, paced: bool) -> (Vec<Vec<Action>>, Vec<&str>) {
+    let mut steps = Vec::new();
+    let mut missing: Vec<&str> = Vec::new();
+    if mode == TextInjection::Strings && !paced {
+        if !text.is_empty() {
+            steps.push(vec![Action::TypeString {
+                text: text.to_owned(),
+            }]);
+        }
+        return (steps, missing);
+    }
+    for grapheme in text.graphemes(true) {
+        match mode {
+            TextInjection::Strings => steps.push(vec![Action::TypeString {
+                text: grapheme.to_owned(),
+            }]),
+            TextInjection::Chars => steps.push(grapheme.chars().map(Action::InjectChar).collect()),
+            TextInjection::Keys => match keystroke(grapheme) {
+                Some((key, shift)) => steps.push(tap(key, shift)),
+                None if !missing.contains(&grapheme) => missing.push(grapheme),
+                None => {}
+            },
+        }
+    }
+    (steps, missing)
+}
+
+/// Press and release `key`, inside Shift when `shift` is set.
+fn tap(key: KeyCode, shift: bool) -> Vec<Action> {
+    let press = |key, state| Action::InjectKey { key, state };
+    let mut actions = vec![press(key, KeyState::Down), press(key, KeyState::Up)];
+    if shift {
+        actions.insert(0, press(KeyCode::Shift, KeyState::Down));
+        actions.push(press(KeyCode::Shift, KeyState::Up));
+    }
+    actions
+}
+
+/// The key that types `grapheme` on a US layout, and whether it takes
+/// Shift. `None` for anything else.
+fn keystroke(grapheme: &str) -> Option<(KeyCode, bool)> {
+    let mut chars = grapheme.chars();
+    let c = chars.next()?;
+    match chars.next() {
+        None => {}
+        // One grapheme, and one line break.
+        Some('\n') if c == '\r' && chars.next().is_none() => return Some((KeyCode::Enter, false)),
+        Some(_) => return None,
+    }
+    if c.is_ascii_alphabetic() {
+        return Some((letter(c.to_ascii_lowercase())?, c.is_ascii_uppercase()));
+    }
+    let key = match c {
+        '\n' | '\r' => (KeyCode::Enter, false),
+        '\t' => (KeyCode::Tab, false),
+        ' ' => (KeyCode::Space, false),
+        '0'..='9' => (digit(c)?, false),
+        ')' => (KeyCode::Key0, true),
+        '!' => (KeyCode::Key1, true),
+        '@' => (KeyCode::Key2, true),
+        '#' => (KeyCode::Key3, true),
+        '$' => (KeyCode::Key4, true),
+        '%' => (KeyCode::Key5, true),
+        '^' => (KeyCode::Key6, true),
+        '&' => (KeyCode::Key7, true),
+        '*' => (KeyCode::Key8, true),
+        '(' => (KeyCode::Key9, true),
+        '`' => (KeyCode::Backtick, false),
+        '~' => (KeyCode::Backtick, true),
+        '-' => (KeyCode::Minus, false),
+        '_' => (KeyCode::Minus, true),
+        '=' => (KeyCode::Equal, false),
+        '+' => (KeyCode::Equal, true),
+        '[' => (KeyCode::LeftBracket, false),
+        '{' => (KeyCode::LeftBracket, true),
+        ']' => (KeyCode::RightBracket, false),
+        '}' => (KeyCode::RightBracket, true),
+        '\\' => (KeyCode::Backslash, false),
+        '|' => (KeyCode::Backslash, true),
+        ';' => (KeyCode::Semicolon, false),
+        ':' => (KeyCode::Semicolon, true),
+        '\'' => (KeyCode::Apostrophe, false),
+        '"' => (KeyCode::Apostrophe, true),
+        ',' => (KeyCode::Comma, false),
+        '<' => (KeyCode::Comma, true),
+        '.' => (KeyCode::Period, false),
+        '>' => (KeyCode::Period, true),
+        '/' => (KeyCode::Slash, false),
+        '?' => (KeyCode::Slash, true),
+        _ => return None,
+    };
+    Some(key)
+}
+
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+];
+
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// The key of lowercase ASCII letter `c`.
+fn letter(c: char) -> Option<KeyCode> {
+    LETTERS
+        .get((c as usize).checked_sub('a' as usize)?)
+        .copied()
+}
+
+/// The top-row key of ASCII digit `c`.
+fn digit(c: char) -> Option<KeyCode> {
+    DIGITS.get((c as usize).checked_sub('0' as usize)?).copied()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: KeyCode, state: KeyState) -> Action {
+        Action::InjectKey { key, state }
+    }
+
+    #[test]
+    fn strings_go_whole_unless_paced() {
+        let (steps, missing) = lower("héllo", TextInjection::Strings, false);
+        assert_eq!(
+            steps,
+            vec![vec![Action::TypeString {
+                text: "héllo".into()
+            }]]
+        );
+        assert!(missing.is_empty());
+        let (steps, _) = lower("ab", TextInjection::Strings, true);
+        assert_eq!(steps.len(), 2);
+    }
+
+    #[test]
+    fn chars_keep_a_grapheme_in_one_step() {
+        // "e" and a combining acute accent make one grapheme.
+        let (steps, _) = lower("e\u{301}x", TextInjection::Chars, false);
+        assert_eq!(
+            steps,
+            vec![
+                vec![Action::InjectChar('e'), Action::InjectChar('\u{301}')],
+                vec![Action::InjectChar('x')],
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_type_with_shift_and_report_the_rest_once() {
+        let (steps, missing) = lower("a?é\r\né", TextInjection::Keys, false);
+        assert_eq!(
+            steps,
+            vec![
+                vec![
+                    key(KeyCode::A, KeyState::Down),
+                    key(KeyCode::A, KeyState::Up)
+                ],
+                vec![
+                    key(KeyCode::Shift, KeyState::Down),
+                    key(KeyCode::Slash, KeyState::Down),
+                    key(KeyCode::Slash, KeyState::Up),
+                    key(KeyCode::Shift, KeyState::Up),
+                ],
+                vec![
+                    key(KeyCode::Enter, KeyState::Down),
+                    key(KeyCode::Enter, KeyState::Up)
+                ],
+            ]
+        );
+        assert_eq!(missing, vec!["é"]);
+    }
+
+    #[test]
+    fn every_printable_ascii_character_has_a_key() {
+        for c in ' '..='~' {
+            assert!(keystroke(&c.to_string()).is_some(), "{c:?}");
+        }
+        assert_eq!(keystroke("Z"), Some((KeyCode::Z, true)));
+        assert_eq!(keystroke("7"), Some((KeyCode::Key7, false)));
+    }
+}
@@ -7,15 +7,32 @@
 //! Uses `try_send` to avoid stalling the capture thread when the channel is
 //! full. **Drop newest on full**: the in-flight event is discarded and a
 //! warning is logged (not the oldest; search for this phrase when debugging
-//! dropped inputs).
+//! dropped inputs). The dropped key is also recorded so the consumer can
+//! report it alongside the keys the engine itself suppresses.
+//!
+//! Pointer events share the channel as `CapturedEvent::Pointer`, so they stay
+//! in order with the keys around them. A dropped pointer event is logged but
+//! not recorded: there is no key to report.
+//!
+//! Focus changes travel the same way as `CapturedEvent::Focus`, so the
+//! consumer sees them between the keys typed before and after.
 
-use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::platform::InputEvent;
+use crate::platform::{CapturedEvent, InputEvent, KeyCode, KeyState, PointerEvent, WindowContext};
 
 /// Default channel capacity. Sized for keystroke bursts at human typing speeds.
 pub const DEFAULT_CAPACITY: usize = 256;
 
+/// Most drop records kept between two `take_dropped` calls. Further drops are
+/// still logged by the publisher but not recorded.
+const MAX_DROP_RECORDS: usize = 64;
+
+/// Keys dropped on overflow, shared by publishers and the subscriber.
+type DropLog = Arc<Mutex<Vec<(KeyCode, KeyState)>>>;
+
 // ---------------------------------------------------------------------------
 // Publisher
 // ---------------------------------------------------------------------------
@@ -26,7 +43,8 @@ pub const DEFAULT_CAPACITY: usize = 256;
 /// shared across producer threads.
 #[derive(Clone)]
 pub struct EventPublisher {
-    sender: mpsc::SyncSender<InputEvent>,
+    sender: mpsc::SyncSender<CapturedEvent>,
+    dropped: DropLog,
 }
 
 impl EventPublisher {
@@ -36,10 +54,42 @@ impl EventPublisher {
     /// full: the current event is discarded and a warning is logged.
     pub fn send(&self, event: InputEvent) {
         log::debug!("event_bus: publish {:?} {:?}", event.key, event.state);
+        let (key, state) = (event.key, event.state);
+        if let Err(e) = self.sender.try_send(event.into()) {
+            log::warn!("event_bus: dropped event ({})", e);
+            if let Ok(mut dropped) = self.dropped.lock() {
+                if dropped.len() < MAX_DROP_RECORDS {
+                    dropped.push((key, state));
+                }
+            }
+        }
+    }
+
+    /// Send a pointer event to the bus, stamped with the time it is sent.
+    /// Drop newest on full, as `send`.
+    pub fn send_pointer(&self, event: PointerEvent) {
+        log::debug!("event_bus: publish {:?}", event);
+        let event = CapturedEvent::Pointer {
+            event,
+            timestamp: Instant::now(),
+        };
         if let Err(e) = self.sender.try_send(event) {
             log::warn!("event_bus: dropped event ({})", e);
         }
     }
+
+    /// Send a focus change to the bus, stamped with the time it is sent.
+    /// Drop newest on full, as `send`.
+    pub fn send_focus(&self, window: WindowContext) {
+        log::debug!("event_bus: publish focus {:?}", window.app_id);
+        let event = CapturedEvent::Focus {
+            window,
+            timestamp: Instant::now(),
+        };
+        if let Err(e) = self.sender.try_send(event) {
+            log::warn!("event_bus: dropped focus change ({})", e);
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -52,15 +102,24 @@ impl EventPublisher {
 /// ergonomic `for event in subscriber` loops; the iterator returns `None`
 /// when all `EventPublisher` handles have been dropped.
 pub struct EventSubscriber {
-    receiver: mpsc::Receiver<InputEvent>,
+    receiver: mpsc::Receiver<CapturedEvent>,
+    dropped: DropLog,
 }
 
 impl EventSubscriber {
+    /// Returns and clears the keys dropped on overflow since the last call.
+    pub fn take_dropped(&self) -> Vec<(KeyCode, KeyState)> {
+        self.dropped
+            .lock()
+            .map(|mut d| std::mem::take(&mut *d))
+            .unwrap_or_default()
+    }
+
     /// Blocking receive. Returns `None` when all publishers have been dropped.
-    pub fn recv(&self) -> Option<InputEvent> {
+    pub fn recv(&self) -> Option<CapturedEvent> {
         match self.receiver.recv() {
             Ok(event) => {
-                log::debug!("event_bus: deliver {:?} {:?}", event.key, event.state);
+                log_delivery(&event);
                 Some(event)
             }
             Err(_) => {
@@ -69,10 +128,30 @@ impl EventSubscriber {
             }
         }
     }
+
+    /// Receive, waiting at most `timeout`. The main loop uses it to wake up
+    /// while no keys arrive.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<CapturedEvent, RecvTimeoutError> {
+        let event = self.receiver.recv_timeout(timeout)?;
+        log_delivery(&event);
+        Ok(event)
+    }
+}
+
+fn log_delivery(event: &CapturedEvent) {
+    match event {
+        CapturedEvent::Key(event) => {
+            log::debug!("event_bus: deliver {:?} {:?}", event.key, event.state)
+        }
+        CapturedEvent::Pointer { event, .. } => log::debug!("event_bus: deliver {:?}", event),
+        CapturedEvent::Focus { window, .. } => {
+            log::debug!("event_bus: deliver focus {:?}", window.app_id)
+        }
+    }
 }
 
 impl Iterator for EventSubscriber {
-    type Item = InputEvent;
+    type Item = CapturedEvent;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.recv()
@@ -86,7 +165,14 @@ impl Iterator for EventSubscriber {
 /// Create a linked publisher/subscriber pair with the given channel capacity.
 pub fn new(capacity: usize) -> (EventPublisher, EventSubscriber) {
     let (sender, receiver) = mpsc::sync_channel(capacity);
-    (EventPublisher { sender }, EventSubscriber { receiver })
+    let dropped = DropLog::default();
+    (
+        EventPublisher {
+            sender,
+            dropped: dropped.clone(),
+        },
+        EventSubscriber { receiver, dropped },
+    )
 }
 
 // ---------------------------------------------------------------------------
@@ -98,7 +184,7 @@ mod tests {
     use std::time::Instant;
 
     use super::*;
-    use crate::platform::{InputEvent, KeyCode, KeyState, Modifiers, WindowContext};
+    use crate::platform::{InputEvent, KeyCode, KeyState, Modifiers, MouseButton, WindowContext};
 
     fn make_event(key: KeyCode) -> InputEvent {
         InputEvent {
@@ -106,6 +192,18 @@ mod tests {
             state: KeyState::Down,
             modifiers: Modifiers::default(),
             window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
+        }
+    }
+
+    fn key_of(event: CapturedEvent) -> KeyCode {
+        match event {
+            CapturedEvent::Key(event) => event.key,
+            other => panic!("expected a key, got {other:?}"),
         }
     }
 
@@ -114,7 +212,7 @@ mod tests {
         let (publisher, subscriber) = new(8);
         publisher.send(make_event(KeyCode::A));
         let received = subscriber.recv().unwrap();
-        assert_eq!(received.key, KeyCode::A);
+        assert_eq!(key_of(received), KeyCode::A);
     }
 
     #[test]
@@ -124,6 +222,23 @@ mod tests {
         assert!(subscriber.recv().is_none());
     }
 
+    #[test]
+    fn recv_timeout_wakes_while_idle() {
+        let (publisher, subscriber) = new(8);
+        let wait = Duration::from_millis(10);
+        assert_eq!(
+            subscriber.recv_timeout(wait).map(key_of),
+            Err(RecvTimeoutError::Timeout)
+        );
+        publisher.send(make_event(KeyCode::A));
+        assert_eq!(subscriber.recv_timeout(wait).map(key_of), Ok(KeyCode::A));
+        drop(publisher);
+        assert_eq!(
+            subscriber.recv_timeout(wait).map(key_of),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
     #[test]
     fn events_are_ordered() {
         let (publisher, subscriber) = new(8);
@@ -132,10 +247,44 @@ mod tests {
             publisher.send(make_event(key));
         }
         drop(publisher);
-        let received: Vec<KeyCode> = subscriber.map(|e| e.key).collect();
+        let received: Vec<KeyCode> = subscriber.map(key_of).collect();
         assert_eq!(received, keys);
     }
 
+    #[test]
+    fn pointer_events_keep_their_place_among_keys() {
+        let (publisher, subscriber) = new(8);
+        let click = PointerEvent::Button {
+            button: MouseButton::Left,
+            state: KeyState::Down,
+        };
+        publisher.send(make_event(KeyCode::Ctrl));
+        publisher.send_pointer(click);
+        publisher.send(make_event(KeyCode::A));
+        drop(publisher);
+        let received: Vec<CapturedEvent> = subscriber.collect();
+        assert!(matches!(received[0], CapturedEvent::Key(ref e) if e.key == KeyCode::Ctrl));
+        assert!(matches!(received[1], CapturedEvent::Pointer { event, .. } if event == click));
+        assert!(matches!(received[2], CapturedEvent::Key(ref e) if e.key == KeyCode::A));
+    }
+
+    #[test]
+    fn focus_changes_keep_their_place_among_keys() {
+        let (publisher, subscriber) = new(8);
+        let game = WindowContext {
+            app_id: Some("game.exe".into()),
+            title: None,
+        };
+        publisher.send(make_event(KeyCode::A));
+        publisher.send_focus(game.clone());
+        publisher.send(make_event(KeyCode::B));
+        drop(publisher);
+        let received: Vec<CapturedEvent> = subscriber.collect();
+        assert!(matches!(received[0], CapturedEvent::Key(ref e) if e.key == KeyCode::A));
+        assert!(matches!(received[1], CapturedEvent::Focus { ref window, .. } if *window == game));
+        assert!(matches!(received[2], CapturedEvent::Key(ref e) if e.key == KeyCode::B));
+    }
+
     #[test]
     fn full_channel_warns_and_does_not_block() {
         // Capacity 2; send 4 events; only the first 2 should be received.
@@ -144,6 +293,8 @@ mod tests {
             publisher.send(make_event(KeyCode::A));
         }
         drop(publisher);
+        assert_eq!(subscriber.take_dropped().len(), 2);
+        assert!(subscriber.take_dropped().is_empty());
         assert_eq!(subscriber.count(), 2);
     }
 
@@ -155,7 +306,7 @@ mod tests {
         publisher2.send(make_event(KeyCode::B));
         drop(publisher);
         drop(publisher2);
-        let received: Vec<KeyCode> = subscriber.map(|e| e.key).collect();
+        let received: Vec<KeyCode> = subscriber.map(key_of).collect();
         assert_eq!(received.len(), 2);
     }
 
@@ -177,7 +328,7 @@ mod tests {
             // publisher drops here, signalling subscriber to drain and exit
         });
 
-        let received: Vec<InputEvent> = subscriber.collect();
+        let received: Vec<CapturedEvent> = subscriber.collect();
         sender_thread.join().unwrap();
 
         let elapsed = start.elapsed();
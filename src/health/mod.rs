@@ -0,0 +1,263 @@
+//! Liveness for service supervisors.
+//!
+//! `Health` records when the main loop last ran, when it last handled an
+//! event, and when the capture backend last beat its `Heartbeat`. The main
+//! loop wakes at least every `TICK_INTERVAL` even while no keys arrive, so a
+//! loop or backend that has not been heard from in `STALE_AFTER` is hung
+//! rather than idle.
+//!
+//! The control socket's `health` command reports this. On Linux, when
+//! systemd runs the daemon with `WatchdogSec=`, `Watchdog` also sends
+//! `WATCHDOG=1` on the notification socket while nothing is stale, so
+//! systemd restarts a hung daemon.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::platform::Heartbeat;
+
+/// Longest the main loop waits for an event before ticking anyway.
+pub const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the loop or the backend may go unheard before it counts as hung.
+pub const STALE_AFTER: Duration = Duration::from_secs(10);
+
+// ---------------------------------------------------------------------------
+// Health
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+struct Times {
+    last_tick: Instant,
+    last_event: Option<Instant>,
+}
+
+/// Shared liveness record. Clones refer to the same record.
+#[derive(Debug, Clone)]
+pub struct Health {
+    times: Arc<Mutex<Times>>,
+    backend: Heartbeat,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self {
+            times: Arc::new(Mutex::new(Times {
+                last_tick: Instant::now(),
+                last_event: None,
+            })),
+            backend: Heartbeat::default(),
+        }
+    }
+
+    /// The heartbeat handed to the capture backend.
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.backend.clone()
+    }
+
+    /// The main loop ran at `now`.
+    pub fn tick(&self, now: Instant) {
+        self.times().last_tick = now;
+    }
+
+    /// The main loop finished handling an event at `now`.
+    pub fn event(&self, now: Instant) {
+        let mut times = self.times();
+        times.last_tick = now;
+        times.last_event = Some(now);
+    }
+
+    /// The state as seen at `now`.
+    pub fn report(&self, now: Instant) -> Report {
+        let times = *self.times();
+        let loop_age = now.saturating_duration_since(times.last_tick);
+        let backend_age = self
+            .backend
+            .last()
+            .map(|at| now.saturating_duration_since(at));
+        Report {
+            last_event_age: times.last_event.map(|at| now.saturating_duration_since(at)),
+            loop_age,
+            backend_age,
+            stale: loop_age > STALE_AFTER || backend_age.is_some_and(|age| age > STALE_AFTER),
+        }
+    }
+
+    fn times(&self) -> std::sync::MutexGuard<'_, Times> {
+        self.times.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Ages of the last signs of life, and whether any is too old.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    /// Since the last handled event; `None` before the first.
+    pub last_event_age: Option<Duration>,
+    /// Since the main loop last ran.
+    pub loop_age: Duration,
+    /// Since the capture backend last beat; `None` for backends that do not.
+    pub backend_age: Option<Duration>,
+    /// The loop or the backend has been silent for longer than `STALE_AFTER`.
+    pub stale: bool,
+}
+
+// ---------------------------------------------------------------------------
+// systemd watchdog
+// ---------------------------------------------------------------------------
+
+/// Pings systemd's watchdog while the daemon is healthy.
+#[derive(Debug)]
+pub struct Watchdog {
+    interval: Duration,
+    pinged: Option<Instant>,
+}
+
+impl Watchdog {
+    /// The watchdog systemd asked for through `WATCHDOG_USEC`, if any and if
+    /// meant for this process.
+    pub fn from_env() -> Option<Self> {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let pid = std::env::var("WATCHDOG_PID").ok();
+        let usec = std::env::var("WATCHDOG_USEC").ok()?;
+        let timeout = parse_watchdog(pid.as_deref(), &usec, std::process::id())?;
+        log::info!("health: systemd watchdog every {timeout:?}");
+        Some(Self {
+            // Ping at twice the rate systemd requires, as sd_watchdog_enabled advises.
+            interval: timeout / 2,
+            pinged: None,
+        })
+    }
+
+    /// How often `tick` must be called.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Ping if one is due and `health` is not stale at `now`.
+    pub fn tick(&mut self, health: &Health, now: Instant) {
+        if self
+            .pinged
+            .is_some_and(|at| now.saturating_duration_since(at) < self.interval)
+        {
+            return;
+        }
+        let report = health.report(now);
+        if report.stale {
+            log::warn!("health: stale ({report:?}); not pinging the systemd watchdog");
+            return;
+        }
+        notify("WATCHDOG=1");
+        self.pinged = Some(now);
+    }
+}
+
+/// The watchdog timeout from `WATCHDOG_USEC`, unless `WATCHDOG_PID` names
+/// another process.
+fn parse_watchdog(pid: Option<&str>, usec: &str, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse() != Ok(own_pid)) {
+        return None;
+    }
+    match usec.parse::<u64>() {
+        Ok(0) | Err(_) => None,
+        Ok(usec) => Some(Duration::from_micros(usec)),
+    }
+}
+
+/// Send `state` to systemd's notification socket (`sd_notify`). Does nothing
+/// when `NOTIFY_SOCKET` is unset, that is, when not started by systemd.
+#[cfg(target_os = "linux")]
+pub fn notify(state: &str) {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let path = path.to_string_lossy();
+    // A leading '@' names an abstract socket.
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(&*path),
+    };
+    let sent = addr.and_then(|addr| {
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to_addr(state.as_bytes(), &addr)
+    });
+    if let Err(e) = sent {
+        log::warn!("health: cannot notify systemd at {path}: {e}");
+    }
+}
+
+/// systemd only exists on Linux.
+#[cfg(not(target_os = "linux"))]
+pub fn notify(state: &str) {
+    let _ = state;
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_ages_the_loop_events_and_backend() {
+        let health = Health::new();
+        let t0 = Instant::now();
+        health.event(t0);
+        health.heartbeat().beat();
+
+        let report = health.report(t0 + Duration::from_secs(2));
+        assert_eq!(report.last_event_age, Some(Duration::from_secs(2)));
+        assert_eq!(report.loop_age, Duration::from_secs(2));
+        assert!(report.backend_age.is_some());
+        assert!(!report.stale);
+
+        // Idle but ticking: the last event ages, the loop does not.
+        health.tick(t0 + Duration::from_secs(60));
+        let report = health.report(t0 + Duration::from_secs(61));
+        assert_eq!(report.last_event_age, Some(Duration::from_secs(61)));
+        assert_eq!(report.loop_age, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn silent_backend_or_loop_is_stale() {
+        let health = Health::new();
+        let t0 = Instant::now();
+        health.tick(t0);
+        // A backend without a heartbeat is only judged by the loop.
+        assert_eq!(health.report(t0).backend_age, None);
+        assert!(!health.report(t0 + Duration::from_secs(5)).stale);
+        assert!(health.report(t0 + Duration::from_secs(11)).stale);
+
+        health.heartbeat().beat();
+        let later = Instant::now() + Duration::from_secs(30);
+        health.tick(later);
+        assert!(health.report(later).stale, "the backend stopped beating");
+    }
+
+    #[test]
+    fn watchdog_env_is_for_this_process_only() {
+        assert_eq!(
+            parse_watchdog(None, "30000000", 7),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_watchdog(Some("7"), "30000000", 7),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse_watchdog(Some("8"), "30000000", 7), None);
+        assert_eq!(parse_watchdog(None, "0", 7), None);
+        assert_eq!(parse_watchdog(None, "soon", 7), None);
+    }
+}
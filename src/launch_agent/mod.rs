@@ -0,0 +1,387 @@
+//! `pcunifier install-agent` and `uninstall-agent`: start at login on macOS.
+//!
+//! The daemon is registered as a launchd LaunchAgent: a property list in
+//! `~/Library/LaunchAgents` that runs the current executable, with the
+//! current config path, whenever the user logs in. A LaunchAgent has no
+//! terminal, so its output goes to `~/Library/Logs/pcunifier.log`.
+//!
+//! Generating the plist is plain string work and is tested everywhere;
+//! writing it and talking to `launchctl` only happens on macOS.
+
+use std::path::{Path, PathBuf};
+
+/// The launchd label, also the plist's file name.
+pub const LABEL: &str = "com.aatozinnoinc.pcunifier";
+
+/// Seconds launchd waits before starting the agent again after it exits.
+const THROTTLE_INTERVAL_SECS: u32 = 30;
+
+// ---------------------------------------------------------------------------
+// Errors
+// ---------------------------------------------------------------------------
+
+/// Errors from installing or removing the LaunchAgent.
+#[derive(Debug, thiserror::Error)]
+pub enum AgentError {
+    /// LaunchAgents only exist on macOS.
+    #[error("{0} is only available on macOS")]
+    Unsupported(&'static str),
+
+    /// The current executable could not be found.
+    #[error("cannot locate the pcunifier executable: {0}")]
+    CurrentExe(#[source] std::io::Error),
+
+    /// The executable runs from a temporary Gatekeeper copy, which is gone
+    /// after the next restart.
+    #[error(
+        "pcunifier is running from a translocated copy at '{0}'; move it out of the \
+         Downloads folder (for example to /Applications) and run install-agent again"
+    )]
+    Translocated(PathBuf),
+
+    /// Reading, writing, or removing a file failed.
+    #[error("cannot {action} '{path}': {source}")]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A write was refused by System Integrity Protection or a managed
+    /// profile, not by ordinary file permissions.
+    #[error(
+        "'{0}' is protected by the system (System Integrity Protection or a configuration \
+         profile); check that the folder is not managed by your organization"
+    )]
+    Protected(PathBuf),
+
+    /// `launchctl` exited with an error.
+    #[error("launchctl {args} failed ({status}): {stderr}")]
+    Launchctl {
+        args: String,
+        status: String,
+        stderr: String,
+    },
+}
+
+// ---------------------------------------------------------------------------
+// Agent description
+// ---------------------------------------------------------------------------
+
+/// What the LaunchAgent runs and where it logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Agent {
+    /// The pcunifier executable.
+    pub program: PathBuf,
+    /// Passed to the daemon with `--config`.
+    pub config: PathBuf,
+    /// Receives the daemon's stdout and stderr.
+    pub log: PathBuf,
+}
+
+impl Agent {
+    /// An agent for the running executable and `config`, logging under
+    /// `home`.
+    pub fn for_current_exe(config: PathBuf, home: &Path) -> Result<Self, AgentError> {
+        let program = std::env::current_exe().map_err(AgentError::CurrentExe)?;
+        if program.to_string_lossy().contains("/AppTranslocation/") {
+            return Err(AgentError::Translocated(program));
+        }
+        Ok(Self {
+            program,
+            config,
+            log: home.join("Library").join("Logs").join("pcunifier.log"),
+        })
+    }
+
+    /// The property list launchd loads.
+    ///
+    /// The agent starts at login and is started again if it exits with an
+    /// error, at most every `THROTTLE_INTERVAL_SECS`. A clean exit, such as
+    /// after `pcunifier` is stopped on purpose, leaves it stopped.
+    pub fn plist(&self) -> String {
+        let program = escape(&self.program.to_string_lossy());
+        let config = escape(&self.config.to_string_lossy());
+        let log = escape(&self.log.to_string_lossy());
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Label</key>
+	<string>{LABEL}</string>
+	<key>ProgramArguments</key>
+	<array>
+		<string>{program}</string>
+		<string>--config</string>
+		<string>{config}</string>
+	</array>
+	<key>RunAtLoad</key>
+	<true/>
+	<key>KeepAlive</key>
+	<dict>
+		<key>SuccessfulExit</key>
+		<false/>
+	</dict>
+	<key>ThrottleInterval</key>
+	<integer>{THROTTLE_INTERVAL_SECS}</integer>
+	<key>ProcessType</key>
+	<string>Interactive</string>
+	<key>StandardOutPath</key>
+	<string>{log}</string>
+	<key>StandardErrorPath</key>
+	<string>{log}</string>
+</dict>
+</plist>
+"#
+        )
+    }
+}
+
+/// Where the agent's plist lives for the user with home directory `home`.
+pub fn plist_path(home: &Path) -> PathBuf {
+    home.join("Library")
+        .join("LaunchAgents")
+        .join(format!("{LABEL}.plist"))
+}
+
+/// Escape text for a plist `<string>`.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// ---------------------------------------------------------------------------
+// Install and uninstall
+// ---------------------------------------------------------------------------
+
+/// What is already at the plist path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Existing {
+    None,
+    /// The same plist: nothing to write.
+    Same,
+    /// A plist from another executable, config path, or version.
+    Different,
+}
+
+fn existing(current: Option<&str>, wanted: &str) -> Existing {
+    match current {
+        None => Existing::None,
+        Some(current) if current == wanted => Existing::Same,
+        Some(_) => Existing::Different,
+    }
+}
+
+/// Write the agent's plist and load it. With `dry_run`, print the plist and
+/// where it would go instead.
+///
+/// An existing installation is left alone when it is identical, and
+/// replaced (unloaded, rewritten, loaded again) when it differs.
+pub fn install(agent: &Agent, home: &Path, dry_run: bool) -> Result<(), AgentError> {
+    let path = plist_path(home);
+    let plist = agent.plist();
+    if dry_run {
+        println!("# {}", path.display());
+        print!("{plist}");
+        return Ok(());
+    }
+    if !cfg!(target_os = "macos") {
+        return Err(AgentError::Unsupported("install-agent"));
+    }
+
+    let current = match std::fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(source) => return Err(io_error("read", &path, source)),
+    };
+    match existing(current.as_deref(), &plist) {
+        Existing::Same => {
+            println!("{LABEL} is already installed at {}", path.display());
+            return Ok(());
+        }
+        Existing::Different => {
+            println!("replacing the existing {LABEL} at {}", path.display());
+            // Not loaded is fine: the file may have been put there by hand.
+            if let Err(e) = launchctl(&["bootout", &service_target()]) {
+                log::debug!("launch_agent: {e}");
+            }
+        }
+        Existing::None => {}
+    }
+
+    for dir in [path.parent(), agent.log.parent()].into_iter().flatten() {
+        std::fs::create_dir_all(dir).map_err(|source| io_error("create", dir, source))?;
+    }
+    std::fs::write(&path, &plist).map_err(|source| io_error("write", &path, source))?;
+    launchctl(&["bootstrap", &domain_target(), &path.to_string_lossy()])?;
+
+    println!("installed {LABEL} at {}", path.display());
+    println!(
+        "pcunifier now starts at login; it logs to {}",
+        agent.log.display()
+    );
+    Ok(())
+}
+
+/// Unload the agent and remove its plist. Reports, rather than fails, when
+/// nothing is installed.
+pub fn uninstall(home: &Path) -> Result<(), AgentError> {
+    if !cfg!(target_os = "macos") {
+        return Err(AgentError::Unsupported("uninstall-agent"));
+    }
+    let path = plist_path(home);
+    if !path.exists() {
+        println!(
+            "{LABEL} is not installed ({} does not exist)",
+            path.display()
+        );
+        return Ok(());
+    }
+    if let Err(e) = launchctl(&["bootout", &service_target()]) {
+        // Removing the file is still right: it was not running anyway.
+        log::warn!("launch_agent: {e}");
+    }
+    std::fs::remove_file(&path).map_err(|source| io_error("remove", &path, source))?;
+    println!("removed {LABEL} from {}", path.display());
+    Ok(())
+}
+
+/// An I/O error, telling a system-protected path apart from ordinary
+/// permissions. SIP refuses with `EPERM`, plain file modes with `EACCES`.
+fn io_error(action: &'static str, path: &Path, source: std::io::Error) -> AgentError {
+    const EPERM: i32 = 1;
+    if source.raw_os_error() == Some(EPERM) {
+        return AgentError::Protected(path.to_owned());
+    }
+    AgentError::Io {
+        action,
+        path: path.to_owned(),
+        source,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// launchctl
+// ---------------------------------------------------------------------------
+
+/// The user's GUI launchd domain, `gui/<uid>`.
+fn domain_target() -> String {
+    format!("gui/{}", uid())
+}
+
+/// The agent's service in the GUI domain, `gui/<uid>/<label>`.
+fn service_target() -> String {
+    format!("{}/{LABEL}", domain_target())
+}
+
+#[cfg(target_os = "macos")]
+fn uid() -> u32 {
+    extern "C" {
+        fn getuid() -> u32;
+    }
+    // SAFETY: getuid has no preconditions and cannot fail.
+    unsafe { getuid() }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn uid() -> u32 {
+    0
+}
+
+fn launchctl(args: &[&str]) -> Result<(), AgentError> {
+    let joined = args.join(" ");
+    let output = std::process::Command::new("launchctl")
+        .args(args)
+        .output()
+        .map_err(|e| AgentError::Launchctl {
+            args: joined.clone(),
+            status: "not started".into(),
+            stderr: e.to_string(),
+        })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(AgentError::Launchctl {
+        args: joined,
+        status: output.status.to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent() -> Agent {
+        Agent {
+            program: PathBuf::from("/Applications/PC Unifier/pcunifier"),
+            config: PathBuf::from("/Users/me/Library/Application Support/pc-unifier/config.toml"),
+            log: PathBuf::from("/Users/me/Library/Logs/pcunifier.log"),
+        }
+    }
+
+    #[test]
+    fn plist_runs_the_executable_with_its_config_and_logs_to_a_file() {
+        let plist = agent().plist();
+        assert!(plist.contains(&format!("<string>{LABEL}</string>")));
+        assert!(plist.contains(
+            "<string>/Applications/PC Unifier/pcunifier</string>\n\t\t<string>--config</string>\n\
+             \t\t<string>/Users/me/Library/Application Support/pc-unifier/config.toml</string>"
+        ));
+        assert!(plist.contains(
+            "<key>StandardErrorPath</key>\n\t<string>/Users/me/Library/Logs/pcunifier.log</string>"
+        ));
+        assert!(plist.contains("<key>RunAtLoad</key>\n\t<true/>"));
+    }
+
+    #[test]
+    fn plist_strings_are_escaped() {
+        let agent = Agent {
+            config: PathBuf::from("/tmp/a&b<c>.toml"),
+            ..agent()
+        };
+        assert!(agent
+            .plist()
+            .contains("<string>/tmp/a&amp;b&lt;c&gt;.toml</string>"));
+    }
+
+    #[test]
+    fn plist_lives_in_the_users_launch_agents() {
+        assert_eq!(
+            plist_path(Path::new("/Users/me")),
+            PathBuf::from(format!("/Users/me/Library/LaunchAgents/{LABEL}.plist"))
+        );
+    }
+
+    #[test]
+    fn existing_installation_is_compared_with_the_new_plist() {
+        let plist = agent().plist();
+        assert_eq!(existing(None, &plist), Existing::None);
+        assert_eq!(existing(Some(&plist), &plist), Existing::Same);
+        let other = Agent {
+            program: PathBuf::from("/usr/local/bin/pcunifier"),
+            ..agent()
+        };
+        assert_eq!(existing(Some(&other.plist()), &plist), Existing::Different);
+    }
+
+    #[test]
+    fn permission_errors_tell_protection_from_file_modes() {
+        let path = Path::new("/Users/me/Library/LaunchAgents/x.plist");
+        assert!(matches!(
+            io_error("write", path, std::io::Error::from_raw_os_error(1)),
+            AgentError::Protected(_)
+        ));
+        assert!(matches!(
+            io_error("write", path, std::io::Error::from_raw_os_error(13)),
+            AgentError::Io { .. }
+        ));
+    }
+}
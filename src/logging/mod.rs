@@ -0,0 +1,237 @@
+//! Logger setup with per-module levels from the `[log]` config table.
+//!
+//! `init` installs a logger that formats through `env_logger` but decides
+//! what to print with a `LogFilter` held behind a lock, so the filter can be
+//! replaced at any time with `set_filter` (at startup once the config is
+//! loaded, and again whenever the config is reloaded).
+//!
+//! `RUST_LOG` still wins: when it is set, `env_logger` is installed exactly as
+//! before and the config levels are ignored, so a developer's environment is
+//! never overridden by a config file.
+
+use std::str::FromStr;
+use std::sync::{OnceLock, RwLock};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Level used for any target no `[log.modules]` entry covers, unless
+/// `[log] level` says otherwise.
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// This crate's name as it appears at the start of log targets.
+const CRATE_NAME: &str = env!("CARGO_CRATE_NAME");
+
+// ---------------------------------------------------------------------------
+// Filter
+// ---------------------------------------------------------------------------
+
+/// Maps log targets (module paths) to the most verbose level printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    /// Module path and level, sorted so the longest path comes first.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_LEVEL, Vec::new())
+    }
+}
+
+impl LogFilter {
+    /// Build a filter from a default level and `(module, level)` overrides.
+    ///
+    /// A module is a path such as `engine` or `platform::linux::evdev`,
+    /// relative to this crate, or the name of a dependency such as `reis`.
+    pub fn new(default: LevelFilter, mut modules: Vec<(String, LevelFilter)>) -> Self {
+        modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Self { default, modules }
+    }
+
+    /// The level in effect for `target`: the longest module that covers it,
+    /// or the default.
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| covers(module, target))
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// The most verbose level any target can log at.
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+/// Whether `module` is `target` itself or one of its parent modules, either
+/// as written or relative to this crate.
+fn covers(module: &str, target: &str) -> bool {
+    let within = |path: &str| {
+        path.strip_prefix(module)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    };
+    within(target)
+        || target
+            .strip_prefix(CRATE_NAME)
+            .and_then(|rest| rest.strip_prefix("::"))
+            .is_some_and(within)
+}
+
+/// Parse a level name (`off`, `error`, `warn`, `info`, `debug`, `trace`),
+/// ignoring case.
+pub fn parse_level(s: &str) -> Option<LevelFilter> {
+    LevelFilter::from_str(s).ok()
+}
+
+// ---------------------------------------------------------------------------
+// Logger
+// ---------------------------------------------------------------------------
+
+struct Logger {
+    /// Formats and writes records. Its own filter passes everything.
+    inner: env_logger::Logger,
+    filter: RwLock<LogFilter>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter
+            .read()
+            .map_or(true, |f| metadata.level() <= f.level_for(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Install the global logger. Call once, before anything logs.
+pub fn init() {
+    if std::env::var_os(env_logger::DEFAULT_FILTER_ENV).is_some() {
+        env_logger::Builder::from_default_env().init();
+        return;
+    }
+
+    let filter = LogFilter::default();
+    let max = filter.max_level();
+    let logger = LOGGER.get_or_init(|| Logger {
+        // From the environment so RUST_LOG_STYLE is still honoured.
+        inner: env_logger::Builder::from_default_env()
+            .filter_level(LevelFilter::Trace)
+            .build(),
+        filter: RwLock::new(filter),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max);
+    }
+}
+
+/// Replace the active filter. Ignored when `RUST_LOG` is in charge.
+pub fn set_filter(filter: LogFilter) {
+    let Some(logger) = LOGGER.get() else {
+        log::debug!("logging: RUST_LOG is set; ignoring [log] levels");
+        return;
+    };
+    let max = filter.max_level();
+    if let Ok(mut current) = logger.filter.write() {
+        *current = filter;
+    }
+    log::set_max_level(max);
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(default: LevelFilter, modules: &[(&str, LevelFilter)]) -> LogFilter {
+        LogFilter::new(
+            default,
+            modules.iter().map(|(m, l)| (m.to_string(), *l)).collect(),
+        )
+    }
+
+    fn target(path: &str) -> String {
+        format!("{CRATE_NAME}::{path}")
+    }
+
+    #[test]
+    fn unmatched_target_uses_default() {
+        let f = filter(LevelFilter::Warn, &[("engine", LevelFilter::Trace)]);
+        assert_eq!(f.level_for(&target("config")), LevelFilter::Warn);
+        assert_eq!(f.level_for("reis::event"), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn module_covers_itself_and_submodules() {
+        let f = filter(LevelFilter::Info, &[("engine", LevelFilter::Trace)]);
+        assert_eq!(f.level_for(&target("engine")), LevelFilter::Trace);
+        assert_eq!(f.level_for(&target("engine::combo")), LevelFilter::Trace);
+        // A shared name prefix is not a parent module.
+        assert_eq!(f.level_for(&target("engineer")), LevelFilter::Info);
+    }
+
+    #[test]
+    fn longest_module_wins_regardless_of_order() {
+        let f = filter(
+            LevelFilter::Info,
+            &[
+                ("platform::linux::evdev", LevelFilter::Warn),
+                ("platform", LevelFilter::Debug),
+            ],
+        );
+        assert_eq!(
+            f.level_for(&target("platform::linux::evdev::capture")),
+            LevelFilter::Warn
+        );
+        assert_eq!(
+            f.level_for(&target("platform::linux::uinput::executor")),
+            LevelFilter::Debug
+        );
+    }
+
+    #[test]
+    fn dependency_and_full_paths_match() {
+        let f = filter(
+            LevelFilter::Info,
+            &[
+                ("ashpd", LevelFilter::Off),
+                (&target("control"), LevelFilter::Debug),
+            ],
+        );
+        assert_eq!(
+            f.level_for("ashpd::desktop::remote_desktop"),
+            LevelFilter::Off
+        );
+        assert_eq!(f.level_for(&target("control")), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn max_level_is_most_verbose_of_all() {
+        let f = filter(LevelFilter::Warn, &[("engine", LevelFilter::Debug)]);
+        assert_eq!(f.max_level(), LevelFilter::Debug);
+        assert_eq!(LogFilter::default().max_level(), DEFAULT_LEVEL);
+    }
+
+    #[test]
+    fn level_names_parse_case_insensitively() {
+        assert_eq!(parse_level("WARN"), Some(LevelFilter::Warn));
+        assert_eq!(parse_level("off"), Some(LevelFilter::Off));
+        assert_eq!(parse_level("loud"), None);
+    }
+}
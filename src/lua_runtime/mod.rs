@@ -1 +1,240 @@
 //! Lua runtime module: embed LuaJIT via mlua, expose PC Unifier API to scripts.
+//!
+//! The VM itself is not embedded yet (M12). This module holds the part that
+//! does not depend on it: `Runtime`, which owns the loaded VM and replaces
+//! it on reload without a dispatch ever seeing a half-loaded one.
+//!
+//! Threading model:
+//!   - `dispatch` may run on any thread, concurrently with other dispatches
+//!     and with `reload`. It takes a reference-counted handle to the current
+//!     VM and releases the lock before running the handler, so a slow
+//!     handler never blocks a reload.
+//!   - `reload` builds the new VM before taking the lock, then swaps it in.
+//!     A dispatch that started before the swap finishes on the old VM,
+//!     which is dropped when its last handle goes; one that starts after
+//!     uses the new VM. A VM that fails to build leaves the old one in place.
+//!   - Timers belong to the VM that scheduled them. `reload` cancels the
+//!     pending ones, and a timer scheduled by a dispatch still running on
+//!     the old VM after the swap is dropped by `fire_due` rather than run on
+//!     the new one.
+//!   - `fire_due` runs due timers on the calling thread, which is the event
+//!     loop, between events.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// A timer callback. It receives the VM that scheduled it.
+type TimerCallback<V> = Box<dyn FnOnce(&V) + Send>;
+
+struct Timer<V> {
+    due: Instant,
+    /// Generation of the VM that scheduled it.
+    generation: u64,
+    callback: TimerCallback<V>,
+}
+
+type TimerQueue<V> = Arc<Mutex<Vec<Timer<V>>>>;
+
+/// A loaded VM, as seen by a dispatch.
+pub struct Loaded<V> {
+    vm: V,
+    /// Counts reloads; tags the timers this VM schedules.
+    generation: u64,
+    timers: TimerQueue<V>,
+}
+
+impl<V> Loaded<V> {
+    pub fn vm(&self) -> &V {
+        &self.vm
+    }
+
+    /// Run `callback` on this VM once `delay` has passed, unless it has been
+    /// replaced by then.
+    pub fn schedule(&self, delay: Duration, callback: impl FnOnce(&V) + Send + 'static) {
+        if let Ok(mut timers) = self.timers.lock() {
+            timers.push(Timer {
+                due: Instant::now() + delay,
+                generation: self.generation,
+                callback: Box::new(callback),
+            });
+        }
+    }
+}
+
+/// Owns the current VM and swaps it on reload.
+pub struct Runtime<V> {
+    current: RwLock<Arc<Loaded<V>>>,
+    timers: TimerQueue<V>,
+}
+
+impl<V> Runtime<V> {
+    pub fn new(vm: V) -> Self {
+        let timers = TimerQueue::default();
+        Self {
+            current: RwLock::new(Arc::new(Loaded {
+                vm,
+                generation: 0,
+                timers: timers.clone(),
+            })),
+            timers,
+        }
+    }
+
+    /// Run `handler` on the current VM.
+    pub fn dispatch<R>(&self, handler: impl FnOnce(&Loaded<V>) -> R) -> R {
+        handler(&self.current())
+    }
+
+    /// Replace the VM with the one `build` returns, cancelling the old VM's
+    /// timers. If `build` fails the old VM stays and the error is returned.
+    pub fn reload<E: fmt::Display>(&self, build: impl FnOnce() -> Result<V, E>) -> Result<(), E> {
+        let vm = build().map_err(|e| {
+            log::warn!("lua: reload failed; keeping the loaded script: {e}");
+            e
+        })?;
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        let generation = current.generation + 1;
+        *current = Arc::new(Loaded {
+            vm,
+            generation,
+            timers: self.timers.clone(),
+        });
+        drop(current);
+        if let Ok(mut timers) = self.timers.lock() {
+            timers.retain(|t| t.generation >= generation);
+        }
+        log::info!("lua: script reloaded");
+        Ok(())
+    }
+
+    /// Run the timers due at `now` on the VM that scheduled them. Timers of
+    /// a replaced VM are dropped.
+    pub fn fire_due(&self, now: Instant) {
+        let due: Vec<Timer<V>> = match self.timers.lock() {
+            Ok(mut timers) => {
+                let (due, pending) = timers.drain(..).partition(|t| t.due <= now);
+                *timers = pending;
+                due
+            }
+            Err(_) => return,
+        };
+        // The VM is read once, so a reload meanwhile cancels the rest.
+        let loaded = self.current();
+        for timer in due {
+            if timer.generation == loaded.generation {
+                (timer.callback)(&loaded.vm);
+            }
+        }
+    }
+
+    /// When the next timer is due, if one is pending.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.timers.lock().ok()?.iter().map(|t| t.due).min()
+    }
+
+    fn current(&self) -> Arc<Loaded<V>> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::thread;
+
+    use super::*;
+
+    /// Stands in for a Lua VM: every handler of a fully loaded VM carries
+    /// its `id`, so a partly replaced VM would show mixed ids.
+    struct StubVm {
+        id: u32,
+        handlers: Vec<u32>,
+    }
+
+    impl StubVm {
+        fn load(id: u32) -> Self {
+            Self {
+                id,
+                handlers: vec![id; 64],
+            }
+        }
+    }
+
+    #[test]
+    fn reload_during_dispatch_never_exposes_a_partial_vm() {
+        let runtime = Arc::new(Runtime::new(StubVm::load(0)));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let dispatcher = {
+            let runtime = runtime.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut dispatches = 0u32;
+                while !done.load(Ordering::Relaxed) || dispatches == 0 {
+                    runtime.dispatch(|loaded| {
+                        let vm = loaded.vm();
+                        assert!(vm.handlers.iter().all(|&h| h == vm.id));
+                    });
+                    dispatches += 1;
+                }
+            })
+        };
+        for id in 1..=200 {
+            runtime
+                .reload(|| Ok::<_, String>(StubVm::load(id)))
+                .unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+
+        dispatcher.join().unwrap();
+        runtime.dispatch(|loaded| assert_eq!(loaded.vm().id, 200));
+    }
+
+    #[test]
+    fn failed_reload_keeps_the_loaded_vm() {
+        let runtime = Runtime::new(StubVm::load(1));
+        assert!(runtime
+            .reload(|| Err::<StubVm, _>("syntax error".to_string()))
+            .is_err());
+        runtime.dispatch(|loaded| assert_eq!(loaded.vm().id, 1));
+    }
+
+    #[test]
+    fn timers_of_a_replaced_vm_do_not_fire() {
+        let runtime = Runtime::new(StubVm::load(1));
+        let fired = Arc::new(AtomicU32::new(0));
+
+        // Scheduled before the reload, and by a dispatch still holding the
+        // old VM after it.
+        let old = runtime.current();
+        let counter = fired.clone();
+        old.schedule(Duration::ZERO, move |vm| {
+            counter.fetch_add(vm.id, Ordering::Relaxed);
+        });
+        runtime.reload(|| Ok::<_, String>(StubVm::load(2))).unwrap();
+        let counter = fired.clone();
+        old.schedule(Duration::ZERO, move |vm| {
+            counter.fetch_add(vm.id, Ordering::Relaxed);
+        });
+        runtime.fire_due(Instant::now());
+        assert_eq!(fired.load(Ordering::Relaxed), 0);
+        assert_eq!(runtime.next_due(), None);
+
+        let counter = fired.clone();
+        runtime.dispatch(|loaded| {
+            loaded.schedule(Duration::ZERO, move |vm| {
+                counter.fetch_add(vm.id, Ordering::Relaxed);
+            })
+        });
+        runtime.fire_due(Instant::now());
+        assert_eq!(fired.load(Ordering::Relaxed), 2);
+    }
+}
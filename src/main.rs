@@ -2,24 +2,91 @@
 //!
 //! Entry point, daemon lifecycle, and signal handling.
 
+// The tray icon replaces the console window.
+#![cfg_attr(
+    all(target_os = "windows", feature = "tray"),
+    windows_subsystem = "windows"
+)]
+
+mod cli;
 mod config;
+// The protocol is only served on Unix until a Windows transport exists.
+#[cfg_attr(not(unix), allow(dead_code))]
+mod control;
 mod engine;
 mod event_bus;
+mod health;
+mod launch_agent;
+mod logging;
+// Nothing loads scripts until the VM is embedded (M12).
+#[cfg_attr(not(test), allow(dead_code))]
 mod lua_runtime;
+mod monitor;
 #[allow(dead_code)]
 mod platform;
 mod rule_engine;
+mod shutdown;
+mod startup;
+
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Instant;
 
-use crate::platform::{create_action_executor, create_input_capture, PlatformError};
+use crate::platform::middleware::{self, MiddlewareExecutor};
+use crate::platform::{
+    create_action_executor, create_clipboard, create_input_capture, watch_session, ActionExecutor,
+    Capabilities, CapturedEvent, PlatformError, ScancodeInjection,
+};
 
 fn main() -> Result<(), PlatformError> {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    #[cfg(all(target_os = "windows", feature = "tray"))]
+    platform::attach_console();
+    logging::init();
 
     log::info!("pcunifier v{}", env!("CARGO_PKG_VERSION"));
 
+    let options =
+        cli::parse(std::env::args().skip(1)).map_err(|e| PlatformError::Other(e.to_string()))?;
+    let config_path = options
+        .config
+        .clone()
+        .unwrap_or_else(config::default_config_path);
+    match options.command {
+        cli::Command::Run => {}
+        cli::Command::Check { lint, dump } => return check(&config_path, lint, dump),
+        cli::Command::InstallAgent { dry_run } => {
+            let home = config::home_dir();
+            let agent = launch_agent::Agent::for_current_exe(config_path, &home)
+                .map_err(|e| PlatformError::Other(e.to_string()))?;
+            return launch_agent::install(&agent, &home, dry_run)
+                .map_err(|e| PlatformError::Other(e.to_string()));
+        }
+        cli::Command::UninstallAgent => {
+            return launch_agent::uninstall(&config::home_dir())
+                .map_err(|e| PlatformError::Other(e.to_string()));
+        }
+        cli::Command::InstallStartup { method, dry_run } => {
+            let entry = startup::Entry::for_current_exe(config_path)
+                .map_err(|e| PlatformError::Other(e.to_string()))?;
+            return startup::install(&entry, method, dry_run)
+                .map_err(|e| PlatformError::Other(e.to_string()));
+        }
+        cli::Command::UninstallStartup => {
+            return startup::uninstall().map_err(|e| PlatformError::Other(e.to_string()));
+        }
+        cli::Command::Monitor => {
+            let executor = create_action_executor(None)
+                .map_err(|e| log::warn!("monitor: {e}"))
+                .ok();
+            return monitor::run(
+                create_input_capture(None)?,
+                executor,
+                &control::default_socket_path(),
+            );
+        }
+    }
+
     // Load config; a missing file is normal on first run (full UX in M14).
-    let config_path = config::default_config_path();
-    let cfg = match config::load(&config_path) {
+    let mut cfg = match config::load(&config_path) {
         Ok(c) => {
             log::info!("config: loaded from {}", config_path.display());
             c
@@ -35,24 +102,231 @@ fn main() -> Result<(), PlatformError> {
         }
         Err(e) => return Err(PlatformError::Config(e.to_string())),
     };
-
-    let mut rule_engine = rule_engine::RuleEngine::new(&cfg);
+    logging::set_filter(cfg.log.clone());
+    for warning in config::lint(&cfg) {
+        log::warn!("config: {warning}");
+    }
 
     let (publisher, subscriber) = event_bus::new(event_bus::DEFAULT_CAPACITY);
 
-    let mut capture = create_input_capture()?;
-    let executor = create_action_executor()?;
+    let mut capture = create_input_capture(cfg.capture_backend.as_deref())?;
+    let mut executor = create_action_executor(cfg.executor_backend.as_deref())?;
+    if let Some(target) = cfg.executor_target.clone() {
+        executor.set_target(target)?;
+    }
+    if cfg!(target_os = "windows") {
+        executor.set_scancode_injection(cfg.scancode_injection.clone());
+    } else if cfg.scancode_injection != ScancodeInjection::Off {
+        log::warn!("config: [executor] injection and scancode_apps only apply on Windows; ignored");
+    }
+    let executed = middleware::Counter::default();
+    let mut executor = MiddlewareExecutor::new(executor).with(middleware::log());
+    match cfg.max_rate.unwrap_or(config::DEFAULT_MAX_RATE) {
+        0 => log::info!("config: [executor] max_rate = 0; actions are not rate limited"),
+        per_sec => executor = executor.with(middleware::rate_limit(per_sec)),
+    }
+    let executor = executor.with(middleware::count(executed.clone()));
+    // Checked before headless mode drops rules, so each is named by its
+    // place in the file. The engine below always has a clipboard.
+    let caps = Capabilities {
+        clipboard: true,
+        ..executor.capabilities()
+    };
+    for rule in engine::unsupported(&cfg, caps, executor.name()) {
+        log::warn!("engine: {rule}");
+    }
+    if cfg.headless {
+        let dropped = cfg.drop_app_rules();
+        if dropped > 0 {
+            log::warn!(
+                "config: headless mode has no window context; skipping {dropped} app-scoped rule(s)"
+            );
+        }
+    }
+    let mut engine = engine::Engine::new(&cfg, Box::new(executor))
+        .with_clipboard(create_clipboard())
+        .with_launcher(cfg.exec.clone());
 
+    // Paused before capture starts, so no key is ever remapped until resumed.
+    let pause = engine.pause_handle();
+    pause.set_paused(options.start_paused);
+    capture.set_suspension(pause.suspension());
+    capture.set_listen_only(pause.listen_only());
+    capture.set_permission_prompt(!options.no_permission_prompt);
+    if options.allow_uiaccess && !cfg!(target_os = "windows") {
+        log::warn!("--allow-uiaccess only applies on Windows; ignored");
+    }
+    capture.set_ui_access_expected(options.allow_uiaccess);
+    let health = health::Health::new();
+    capture.set_heartbeat(health.heartbeat());
+    watch_session(pause.session());
+    if let Some(key) = cfg.caps_lock_to {
+        if !cfg!(target_os = "macos") {
+            log::warn!("config: [macos] capslock_to only applies on macOS; ignored");
+        }
+        capture.set_caps_lock_remap(key);
+    }
+    if !cfg!(target_os = "macos") {
+        if cfg.fkeys_as_system_keys.is_some() {
+            log::warn!("config: [macos] fkeys_as_system_keys only applies on macOS; ignored");
+        }
+    } else {
+        let ambiguous = cfg.ambiguous_fkeys();
+        if !ambiguous.is_empty() {
+            log::warn!(
+                "config: rules name {ambiguous:?}, but macOS reports F13-F15 and \
+                 PrintScreen/ScrollLock/Pause alike, as F13-F15 unless \
+                 [macos] fkeys_as_system_keys = true; set it to say which"
+            );
+        }
+    }
+    capture.set_system_fkeys(cfg.fkeys_as_system_keys == Some(true));
+    if let Some(wait) = cfg.device_wait {
+        if !cfg!(target_os = "linux") {
+            log::warn!("config: [capture] device_wait_ms only applies on Linux; ignored");
+        }
+        capture.set_device_wait(wait);
+    }
+    let socket_path = control::default_socket_path();
+    let reload = control::ReloadRequest::default();
+    let ctx = control::Context {
+        pause,
+        executor: engine.executor_name(),
+        health: health.clone(),
+        reload: reload.clone(),
+    };
+    #[cfg(all(target_os = "windows", feature = "tray"))]
+    capture.set_tray(tray(&config_path, ctx.clone()));
+    match control::spawn(&socket_path, ctx) {
+        Ok(()) => {}
+        // Without the socket a paused daemon could only be resumed by hotkey.
+        Err(e) if options.start_paused => return Err(e),
+        Err(e) => log::warn!("{e}"),
+    }
+    if options.start_paused {
+        log::info!(
+            "engine: started paused; send {{\"cmd\":\"resume\"}} to {}",
+            socket_path.display()
+        );
+    }
+
+    let focus = publisher.clone();
+    capture.set_focus_callback(Box::new(move |window| focus.send_focus(window)));
     capture.start(Box::new(move |event| {
         publisher.send(event);
     }))?;
 
-    for event in subscriber {
-        let action = rule_engine.process(&event);
-        if let Err(e) = executor.execute(&action) {
-            log::warn!("executor: inject failed: {e}");
+    shutdown::install();
+    health::notify("READY=1");
+    let mut watchdog = health::Watchdog::from_env();
+    let tick = watchdog.as_ref().map_or(health::TICK_INTERVAL, |w| {
+        w.interval().min(health::TICK_INTERVAL)
+    });
+    loop {
+        let wait = if engine.has_pending() {
+            tick.min(engine::POLL_INTERVAL)
+        } else {
+            tick
+        };
+        match subscriber.recv_timeout(wait) {
+            Ok(CapturedEvent::Key(event)) => {
+                for (key, state) in subscriber.take_dropped() {
+                    engine.report_dropped(key, state);
+                }
+                engine.handle(&event);
+                health.event(Instant::now());
+            }
+            // No rule triggers on the pointer yet, so the daemon sets no
+            // pointer callback and none arrive.
+            Ok(CapturedEvent::Pointer { .. }) => {}
+            Ok(CapturedEvent::Focus { window, .. }) => engine.focus(&window),
+            Err(RecvTimeoutError::Timeout) => health.tick(Instant::now()),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        engine.poll();
+        engine.sync_session();
+        if let Some(watchdog) = &mut watchdog {
+            watchdog.tick(&health, Instant::now());
+        }
+        if reload.take() {
+            match config::load(&config_path) {
+                Ok(reloaded) => {
+                    logging::set_filter(reloaded.log.clone());
+                    for setting in cfg.needs_restart(&reloaded) {
+                        log::warn!("config: {setting} changed; restart to apply it");
+                    }
+                    engine.reload(&reloaded);
+                    for rule in engine.unsupported_rules(&reloaded) {
+                        log::warn!("engine: {rule}");
+                    }
+                }
+                Err(e) => log::warn!("config: reload failed, keeping the current rules: {e}"),
+            }
+        }
+        if shutdown::requested() {
+            log::info!("engine: shutting down");
+            break;
         }
     }
+    log::info!("engine: {} action(s) executed", executed.get());
 
     Ok(())
 }
+
+/// The tray icon's side of the engine. Menu choices go through
+/// `control::apply`, as socket commands do; Quit ends the main loop, as a
+/// signal does.
+#[cfg(all(target_os = "windows", feature = "tray"))]
+fn tray(config_path: &std::path::Path, ctx: control::Context) -> platform::Tray {
+    use platform::{TrayCommand, TrayStatus};
+
+    let status_ctx = ctx.clone();
+    platform::Tray {
+        config_path: config_path.to_path_buf(),
+        status: Box::new(move || TrayStatus {
+            paused: status_ctx.pause.is_paused(),
+            healthy: !status_ctx.health.report(Instant::now()).stale,
+        }),
+        command: Box::new(move |cmd| match cmd {
+            TrayCommand::Pause => control::apply(control::Command::Pause, &ctx),
+            TrayCommand::Resume => control::apply(control::Command::Resume, &ctx),
+            TrayCommand::Reload => control::apply(control::Command::Reload, &ctx),
+            TrayCommand::Quit => {
+                log::info!("engine: quit from the tray icon");
+                shutdown::request();
+            }
+        }),
+    }
+}
+
+/// `pcunifier check [--lint] [--dump | --dump-json]`: validate the config
+/// file and exit.
+///
+/// Lint warnings are printed but do not fail the check, matching startup,
+/// where they are logged and the daemon runs anyway. With a dump, stdout
+/// holds only the resolved rules, so the rest goes to stderr.
+fn check(
+    config_path: &std::path::Path,
+    lint: bool,
+    dump: Option<cli::DumpFormat>,
+) -> Result<(), PlatformError> {
+    let cfg = config::load(config_path).map_err(|e| PlatformError::Config(e.to_string()))?;
+    let report = |line: String| match dump {
+        Some(_) => eprintln!("{line}"),
+        None => println!("{line}"),
+    };
+    report(format!("{}: ok", config_path.display()));
+    if lint {
+        let warnings = config::lint(&cfg);
+        for warning in &warnings {
+            report(format!("warning: {warning}"));
+        }
+        report(format!("{} lint warning(s)", warnings.len()));
+    }
+    match dump {
+        Some(cli::DumpFormat::Toml) => print!("{}", cfg.dump_effective_config().to_toml()),
+        Some(cli::DumpFormat::Json) => println!("{}", cfg.dump_effective_config().to_json()),
+        None => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,409 @@
+//! `pcunifier monitor`: a live view of the keyboard for debugging.
+//!
+//! Shows the most recent key events, mouse clicks and scrolls, and focus
+//! changes, the keys held now, the 95th percentile
+//! of capture-to-monitor latency, and the running daemon's state, redrawn in
+//! place with ANSI escapes.
+//!
+//! Events come from a capture backend of the monitor's own, asked to listen
+//! only, so no rule runs and the monitor never holds on to a key. Backends
+//! that cannot observe without intercepting deliver the key to the monitor
+//! instead of the system; the monitor injects it again unchanged, as the
+//! engine does while paused. The daemon's state is read from its control
+//! socket; without a daemon the view says so and keeps going.
+//!
+//! `View` is the view-model: it takes events and daemon status and produces
+//! the lines to draw. `run` owns the terminal and the capture.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::event_bus;
+use crate::platform::{
+    Action, ActionExecutor, CapturedEvent, InputCapture, InputEvent, KeyCode, KeyState, ListenOnly,
+    PlatformError, PointerEvent, WindowContext,
+};
+use crate::shutdown;
+
+/// Event lines kept on screen.
+const RECENT_EVENTS: usize = 20;
+
+/// Latency samples the percentile is taken over.
+const LATENCY_WINDOW: usize = 256;
+
+/// How often the screen is redrawn while no key arrives.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often the daemon is asked for its state.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+// ---------------------------------------------------------------------------
+// View-model
+// ---------------------------------------------------------------------------
+
+/// The daemon state shown in the header, from a control socket `status`
+/// reply.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DaemonStatus {
+    pub paused: bool,
+    /// Why the capture backend suspended rules, when it has.
+    #[serde(default)]
+    pub reason: Option<String>,
+    pub executor: String,
+}
+
+/// What the monitor shows, updated from events and status replies.
+#[derive(Debug, Default)]
+pub struct View {
+    /// Newest last.
+    recent: VecDeque<String>,
+    /// In the order they were pressed.
+    held: Vec<KeyCode>,
+    latencies: VecDeque<Duration>,
+    events: u64,
+    /// `None` while no daemon answers.
+    daemon: Option<DaemonStatus>,
+}
+
+impl View {
+    /// Record one event, `latency` after it was captured.
+    pub fn observe(&mut self, event: &InputEvent, latency: Duration) {
+        self.events += 1;
+        match event.state {
+            KeyState::Down if !self.held.contains(&event.key) => self.held.push(event.key),
+            KeyState::Down => {}
+            KeyState::Up => self.held.retain(|&k| k != event.key),
+        }
+
+        let mut line = format!("{:?} {:?}", event.key, event.state);
+        if event.is_repeat {
+            line.push_str(" (repeat)");
+        }
+        if let Some(app) = &event.window.app_id {
+            line.push_str(&format!("  [{app}]"));
+        }
+        self.push_line(line);
+
+        if self.latencies.len() == LATENCY_WINDOW {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+    }
+
+    /// Record one pointer event. Buttons and scrolling get a line; motion
+    /// would push every key off the screen and is left out.
+    pub fn observe_pointer(&mut self, event: &PointerEvent) {
+        let line = match event {
+            PointerEvent::Motion { .. } => return,
+            PointerEvent::Button { button, state } => format!("Mouse {button:?} {state:?}"),
+            PointerEvent::Scroll { dx, dy, discrete } => {
+                let unit = if *discrete { "notches" } else { "px" };
+                format!("Scroll {dx},{dy} {unit}")
+            }
+        };
+        self.events += 1;
+        self.push_line(line);
+    }
+
+    /// Record a focus change. It gets a line but is no input event.
+    pub fn observe_focus(&mut self, window: &WindowContext) {
+        let mut line = format!(
+            "Focus {}",
+            window.app_id.as_deref().unwrap_or("(unknown app)")
+        );
+        if let Some(title) = &window.title {
+            line.push_str(&format!(" {title:?}"));
+        }
+        self.push_line(line);
+    }
+
+    fn push_line(&mut self, line: String) {
+        if self.recent.len() == RECENT_EVENTS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(line);
+    }
+
+    pub fn set_daemon(&mut self, daemon: Option<DaemonStatus>) {
+        self.daemon = daemon;
+    }
+
+    /// The 95th percentile of recent latencies, by nearest rank.
+    pub fn latency_p95(&self) -> Option<Duration> {
+        let mut sorted: Vec<Duration> = self.latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (sorted.len() * 95).div_ceil(100);
+        sorted.get(rank.checked_sub(1)?).copied()
+    }
+
+    /// The screen, one string per line.
+    pub fn lines(&self) -> Vec<String> {
+        let daemon = match &self.daemon {
+            None => "daemon: not running".to_owned(),
+            Some(d) => {
+                let state = match (&d.reason, d.paused) {
+                    (Some(reason), _) => format!("suspended ({reason})"),
+                    (None, true) => "paused".to_owned(),
+                    (None, false) => "active".to_owned(),
+                };
+                format!("daemon: {state}, executor {}", d.executor)
+            }
+        };
+        let latency = match self.latency_p95() {
+            Some(p95) => format!("{:.2}ms", p95.as_secs_f64() * 1000.0),
+            None => "-".to_owned(),
+        };
+        let held = if self.held.is_empty() {
+            "-".to_owned()
+        } else {
+            let names: Vec<String> = self.held.iter().map(|k| format!("{k:?}")).collect();
+            names.join(" + ")
+        };
+
+        let mut lines = vec![
+            "pcunifier monitor (Ctrl+C to quit)".to_owned(),
+            daemon,
+            format!("events: {}  latency p95: {latency}", self.events),
+            format!("held: {held}"),
+            String::new(),
+        ];
+        lines.extend(self.recent.iter().rev().cloned());
+        lines
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Terminal
+// ---------------------------------------------------------------------------
+
+/// Watch the keyboard until Ctrl+C, drawing `View` on stdout.
+///
+/// `executor` passes keys on for backends that ignore listen-only; without
+/// one such keys are lost while the monitor runs, which is logged once.
+pub fn run(
+    mut capture: Box<dyn InputCapture>,
+    executor: Option<Box<dyn ActionExecutor>>,
+    socket: &Path,
+) -> Result<(), PlatformError> {
+    let (publisher, subscriber) = event_bus::new(event_bus::DEFAULT_CAPACITY);
+    let listen_only = ListenOnly::default();
+    listen_only.set(true);
+    capture.set_listen_only(listen_only);
+    capture.set_permission_prompt(true);
+    let pointer = publisher.clone();
+    capture.set_pointer_callback(Box::new(move |event| pointer.send_pointer(event)));
+    let focus = publisher.clone();
+    capture.set_focus_callback(Box::new(move |window| focus.send_focus(window)));
+    capture.start(Box::new(move |event| publisher.send(event)))?;
+    shutdown::install();
+
+    let mut view = View::default();
+    let mut out = io::stdout().lock();
+    let mut status_at: Option<Instant> = None;
+    let mut warned = false;
+    // Hide the cursor while drawing.
+    let _ = write!(out, "\x1b[?25l");
+    while !shutdown::requested() {
+        match subscriber.recv_timeout(REDRAW_INTERVAL) {
+            Ok(CapturedEvent::Key(event)) => {
+                view.observe(&event, event.timestamp.elapsed());
+                if !event.observed {
+                    pass_through(executor.as_deref(), &event, &mut warned);
+                }
+            }
+            Ok(CapturedEvent::Pointer { event, .. }) => view.observe_pointer(&event),
+            Ok(CapturedEvent::Focus { window, .. }) => view.observe_focus(&window),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if status_at.map_or(true, |at| at.elapsed() >= STATUS_INTERVAL) {
+            view.set_daemon(query_status(socket));
+            status_at = Some(Instant::now());
+        }
+        draw(&mut out, &view).map_err(|e| PlatformError::io("cannot write to the terminal", e))?;
+    }
+    let _ = writeln!(out, "\x1b[?25h");
+    capture.stop()
+}
+
+/// Send a key the capture intercepted on to the system unchanged.
+fn pass_through(executor: Option<&dyn ActionExecutor>, event: &InputEvent, warned: &mut bool) {
+    let action = match (event.state, event.is_repeat) {
+        (KeyState::Down, true) => Action::InjectRepeat { key: event.key },
+        (state, _) => Action::InjectKey {
+            key: event.key,
+            state,
+        },
+    };
+    match executor {
+        Some(executor) => {
+            if let Err(e) = executor.execute(&action) {
+                log::warn!("executor: inject failed: {e}");
+            }
+        }
+        None if !*warned => {
+            log::warn!("monitor: no executor to pass keys on; typing is blocked while it runs");
+            *warned = true;
+        }
+        None => {}
+    }
+}
+
+fn draw(out: &mut impl Write, view: &View) -> io::Result<()> {
+    // Home, then each line cleared to its end, then the rest of the screen.
+    write!(out, "\x1b[H")?;
+    for line in view.lines() {
+        write!(out, "{line}\x1b[K\r\n")?;
+    }
+    write!(out, "\x1b[J")?;
+    out.flush()
+}
+
+/// The daemon's state, or `None` when nothing answers on `socket`.
+#[cfg(unix)]
+fn query_status(socket: &Path) -> Option<DaemonStatus> {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket).ok()?;
+    stream.set_read_timeout(Some(STATUS_INTERVAL)).ok()?;
+    writeln!(stream, r#"{{"cmd":"status"}}"#).ok()?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).ok()?;
+    serde_json::from_str(&reply).ok()
+}
+
+/// No control channel on this platform yet.
+#[cfg(not(unix))]
+fn query_status(socket: &Path) -> Option<DaemonStatus> {
+    let _ = socket;
+    None
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{Modifiers, WindowContext};
+
+    fn event(key: KeyCode, state: KeyState) -> InputEvent {
+        InputEvent {
+            key,
+            state,
+            modifiers: Modifiers::default(),
+            window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: true,
+            is_repeat: false,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn held_keys_follow_downs_and_ups_in_press_order() {
+        let mut view = View::default();
+        let ms = Duration::from_millis(1);
+        view.observe(&event(KeyCode::Ctrl, KeyState::Down), ms);
+        view.observe(&event(KeyCode::Shift, KeyState::Down), ms);
+        view.observe(&event(KeyCode::Ctrl, KeyState::Down), ms);
+        view.observe(&event(KeyCode::T, KeyState::Down), ms);
+        view.observe(&event(KeyCode::Shift, KeyState::Up), ms);
+
+        let lines = view.lines();
+        assert_eq!(lines[2], "events: 5  latency p95: 1.00ms");
+        assert_eq!(lines[3], "held: Ctrl + T");
+        assert_eq!(lines[5], "Shift Up");
+        assert_eq!(lines.len(), 5 + 5);
+    }
+
+    #[test]
+    fn recent_events_keep_the_newest() {
+        let mut view = View::default();
+        for _ in 0..RECENT_EVENTS {
+            view.observe(&event(KeyCode::A, KeyState::Down), Duration::ZERO);
+        }
+        view.observe(&event(KeyCode::B, KeyState::Down), Duration::ZERO);
+        let lines = view.lines();
+        assert_eq!(lines.len(), 5 + RECENT_EVENTS);
+        assert_eq!(lines[5], "B Down");
+    }
+
+    #[test]
+    fn clicks_and_scrolls_get_a_line_and_motion_none() {
+        use crate::platform::MouseButton;
+
+        let mut view = View::default();
+        view.observe_pointer(&PointerEvent::Motion {
+            dx: 3,
+            dy: 1,
+            position: None,
+        });
+        view.observe_pointer(&PointerEvent::Button {
+            button: MouseButton::Left,
+            state: KeyState::Down,
+        });
+        view.observe_pointer(&PointerEvent::Scroll {
+            dx: 0,
+            dy: -2,
+            discrete: true,
+        });
+        let lines = view.lines();
+        assert_eq!(lines[2], "events: 2  latency p95: -");
+        assert_eq!(lines[5], "Scroll 0,-2 notches");
+        assert_eq!(lines[6], "Mouse Left Down");
+    }
+
+    #[test]
+    fn focus_changes_get_a_line_but_no_count() {
+        let mut view = View::default();
+        view.observe_focus(&WindowContext {
+            app_id: Some("firefox".into()),
+            title: Some("Inbox".into()),
+        });
+        view.observe_focus(&WindowContext::default());
+        let lines = view.lines();
+        assert_eq!(lines[2], "events: 0  latency p95: -");
+        assert_eq!(lines[5], "Focus (unknown app)");
+        assert_eq!(lines[6], "Focus firefox \"Inbox\"");
+    }
+
+    #[test]
+    fn latency_p95_is_the_nearest_rank() {
+        let mut view = View::default();
+        assert_eq!(view.latency_p95(), None);
+        for ms in 1..=100 {
+            view.observe(
+                &event(KeyCode::A, KeyState::Down),
+                Duration::from_millis(ms),
+            );
+        }
+        assert_eq!(view.latency_p95(), Some(Duration::from_millis(95)));
+    }
+
+    #[test]
+    fn header_shows_the_daemon_state() {
+        let mut view = View::default();
+        assert_eq!(view.lines()[1], "daemon: not running");
+
+        let reply = r#"{"ok":true,"paused":true,"reason":"secure input (loginwindow)","executor":"cgevent"}"#;
+        view.set_daemon(serde_json::from_str(reply).ok());
+        assert_eq!(
+            view.lines()[1],
+            "daemon: suspended (secure input (loginwindow)), executor cgevent"
+        );
+
+        view.set_daemon(
+            serde_json::from_str(r#"{"ok":true,"paused":false,"executor":"portal"}"#).ok(),
+        );
+        assert_eq!(view.lines()[1], "daemon: active, executor portal");
+    }
+}
@@ -0,0 +1,359 @@
+//! Key names: the one place strings become `KeyCode`s and back.
+//!
+//! The config parser reads every key name through `parse`, and `name` gives
+//! the canonical name that `parse` reads back, as the config dump writes
+//! it. Names are case-insensitive and include the aliases the config schema
+//! documents (`Control`, `Option`, `Super`, `Return`, and so on). Top-row
+//! digits are named by the digit, with or without the `Key` prefix of their
+//! variant: `1` and `Key1` are the same key.
+//!
+//! An unknown name is reported with up to three known names within a small
+//! edit distance of it, so a typo comes back with what was meant.
+
+use std::fmt;
+
+use super::KeyCode;
+
+/// The most suggestions a `ParseKeyError` carries.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// A key name that names no key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseKeyError {
+    /// The name as given.
+    pub given: String,
+    /// Canonical names of the keys whose names are closest to `given`,
+    /// closest first. Empty when none is close.
+    pub suggestions: Vec<String>,
+}
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown key name '{}'", self.given)?;
+        match self.suggestions.as_slice() {
+            [] => Ok(()),
+            [only] => write!(f, " (did you mean '{only}'?)"),
+            [rest @ .., last] => {
+                let rest: Vec<String> = rest.iter().map(|s| format!("'{s}'")).collect();
+                write!(f, " (did you mean {} or '{last}'?)", rest.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseKeyError {}
+
+/// Resolve a key name to a `KeyCode`, ignoring case.
+pub fn parse(s: &str) -> Result<KeyCode, ParseKeyError> {
+    let lower = s.to_lowercase();
+    let unprefixed = match lower.strip_prefix("key") {
+        Some(digit) if digit.len() == 1 && digit.as_bytes()[0].is_ascii_digit() => digit,
+        _ => lower.as_str(),
+    };
+    NAMES
+        .iter()
+        .find(|(_, names)| names.contains(&unprefixed))
+        .map(|&(key, _)| key)
+        .ok_or_else(|| ParseKeyError {
+            given: s.to_owned(),
+            suggestions: suggest(&lower),
+        })
+}
+
+/// The canonical name of `key`, which `parse` reads back.
+pub fn name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    // Top-row digits are written as the digit alone.
+    match name.strip_prefix("Key") {
+        Some(digit) if digit.len() == 1 => digit.to_owned(),
+        _ => name,
+    }
+}
+
+/// Canonical names of the keys with a name close to `lower`, closest first.
+fn suggest(lower: &str) -> Vec<String> {
+    // Short names tolerate one edit, longer ones two.
+    let limit = if lower.chars().count() <= 4 { 1 } else { 2 };
+    let mut close: Vec<(usize, KeyCode)> = Vec::new();
+    for &(key, names) in NAMES {
+        let best = names.iter().map(|n| distance(lower, n)).min();
+        if let Some(d) = best.filter(|&d| d <= limit) {
+            close.push((d, key));
+        }
+    }
+    // Stable, so keys at the same distance keep table order.
+    close.sort_by_key(|&(d, _)| d);
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, key)| name(key))
+        .collect()
+}
+
+/// Levenshtein distance between `a` and `b`, by character.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Every key with its names, lowercase. Punctuation keys take both the
+/// symbol and a spelled-out name.
+const NAMES: &[(KeyCode, &[&str])] = &[
+    // Modifiers and aliases
+    (KeyCode::Ctrl, &["ctrl", "control"]),
+    (KeyCode::Shift, &["shift"]),
+    (KeyCode::Alt, &["alt", "option"]),
+    (KeyCode::Meta, &["meta", "super", "win", "cmd", "command"]),
+    (KeyCode::LeftCtrl, &["leftctrl", "lctrl", "leftcontrol"]),
+    (KeyCode::LeftShift, &["leftshift", "lshift"]),
+    (KeyCode::LeftAlt, &["leftalt", "lalt", "leftoption"]),
+    (
+        KeyCode::LeftMeta,
+        &[
+            "leftmeta",
+            "lmeta",
+            "leftsuper",
+            "leftwin",
+            "leftcmd",
+            "leftcommand",
+        ],
+    ),
+    (KeyCode::RightCtrl, &["rightctrl", "rctrl", "rightcontrol"]),
+    (KeyCode::RightShift, &["rightshift", "rshift"]),
+    (KeyCode::RightAlt, &["rightalt", "ralt", "rightoption"]),
+    (
+        KeyCode::RightMeta,
+        &[
+            "rightmeta",
+            "rmeta",
+            "rightsuper",
+            "rightwin",
+            "rightcmd",
+            "rightcommand",
+        ],
+    ),
+    // Letters
+    (KeyCode::A, &["a"]),
+    (KeyCode::B, &["b"]),
+    (KeyCode::C, &["c"]),
+    (KeyCode::D, &["d"]),
+    (KeyCode::E, &["e"]),
+    (KeyCode::F, &["f"]),
+    (KeyCode::G, &["g"]),
+    (KeyCode::H, &["h"]),
+    (KeyCode::I, &["i"]),
+    (KeyCode::J, &["j"]),
+    (KeyCode::K, &["k"]),
+    (KeyCode::L, &["l"]),
+    (KeyCode::M, &["m"]),
+    (KeyCode::N, &["n"]),
+    (KeyCode::O, &["o"]),
+    (KeyCode::P, &["p"]),
+    (KeyCode::Q, &["q"]),
+    (KeyCode::R, &["r"]),
+    (KeyCode::S, &["s"]),
+    (KeyCode::T, &["t"]),
+    (KeyCode::U, &["u"]),
+    (KeyCode::V, &["v"]),
+    (KeyCode::W, &["w"]),
+    (KeyCode::X, &["x"]),
+    (KeyCode::Y, &["y"]),
+    (KeyCode::Z, &["z"]),
+    // Digits
+    (KeyCode::Key0, &["0"]),
+    (KeyCode::Key1, &["1"]),
+    (KeyCode::Key2, &["2"]),
+    (KeyCode::Key3, &["3"]),
+    (KeyCode::Key4, &["4"]),
+    (KeyCode::Key5, &["5"]),
+    (KeyCode::Key6, &["6"]),
+    (KeyCode::Key7, &["7"]),
+    (KeyCode::Key8, &["8"]),
+    (KeyCode::Key9, &["9"]),
+    // Function keys
+    (KeyCode::F1, &["f1"]),
+    (KeyCode::F2, &["f2"]),
+    (KeyCode::F3, &["f3"]),
+    (KeyCode::F4, &["f4"]),
+    (KeyCode::F5, &["f5"]),
+    (KeyCode::F6, &["f6"]),
+    (KeyCode::F7, &["f7"]),
+    (KeyCode::F8, &["f8"]),
+    (KeyCode::F9, &["f9"]),
+    (KeyCode::F10, &["f10"]),
+    (KeyCode::F11, &["f11"]),
+    (KeyCode::F12, &["f12"]),
+    (KeyCode::F13, &["f13"]),
+    (KeyCode::F14, &["f14"]),
+    (KeyCode::F15, &["f15"]),
+    (KeyCode::F16, &["f16"]),
+    (KeyCode::F17, &["f17"]),
+    (KeyCode::F18, &["f18"]),
+    (KeyCode::F19, &["f19"]),
+    (KeyCode::F20, &["f20"]),
+    (KeyCode::F21, &["f21"]),
+    (KeyCode::F22, &["f22"]),
+    (KeyCode::F23, &["f23"]),
+    (KeyCode::F24, &["f24"]),
+    // Navigation and editing
+    (KeyCode::Space, &["space"]),
+    (KeyCode::Enter, &["enter", "return"]),
+    (KeyCode::Tab, &["tab"]),
+    (KeyCode::Escape, &["escape", "esc"]),
+    (KeyCode::Backspace, &["backspace"]),
+    (KeyCode::Delete, &["delete", "del"]),
+    (KeyCode::Insert, &["insert", "ins"]),
+    (KeyCode::Home, &["home"]),
+    (KeyCode::End, &["end"]),
+    (KeyCode::PageUp, &["pageup", "pgup"]),
+    (KeyCode::PageDown, &["pagedown", "pgdn", "pgdown"]),
+    (KeyCode::Up, &["up"]),
+    (KeyCode::Down, &["down"]),
+    (KeyCode::Left, &["left"]),
+    (KeyCode::Right, &["right"]),
+    // Lock and system keys
+    (KeyCode::CapsLock, &["capslock"]),
+    (KeyCode::NumLock, &["numlock"]),
+    (KeyCode::ScrollLock, &["scrolllock"]),
+    (KeyCode::PrintScreen, &["printscreen", "prtsc", "prtscn"]),
+    (KeyCode::Pause, &["pause", "break"]),
+    (KeyCode::Menu, &["menu", "apps", "compose", "contextmenu"]),
+    // Media keys
+    (KeyCode::VolumeUp, &["volumeup", "volup"]),
+    (KeyCode::VolumeDown, &["volumedown", "voldown"]),
+    (KeyCode::Mute, &["mute", "volumemute"]),
+    (KeyCode::PlayPause, &["playpause", "play"]),
+    (KeyCode::NextTrack, &["nexttrack", "medianext"]),
+    (
+        KeyCode::PrevTrack,
+        &["prevtrack", "previoustrack", "mediaprev"],
+    ),
+    (KeyCode::Stop, &["stop", "mediastop"]),
+    (KeyCode::BrightnessUp, &["brightnessup"]),
+    (KeyCode::BrightnessDown, &["brightnessdown"]),
+    // Browser and launch keys
+    (KeyCode::BrowserBack, &["browserback"]),
+    (KeyCode::BrowserForward, &["browserforward"]),
+    (KeyCode::BrowserRefresh, &["browserrefresh"]),
+    (KeyCode::BrowserStop, &["browserstop"]),
+    (KeyCode::BrowserSearch, &["browsersearch"]),
+    (KeyCode::BrowserFavorites, &["browserfavorites"]),
+    (KeyCode::BrowserHome, &["browserhome"]),
+    (KeyCode::LaunchMail, &["launchmail", "mail"]),
+    (KeyCode::LaunchMedia, &["launchmedia", "mediaselect"]),
+    (KeyCode::LaunchApp1, &["launchapp1", "mycomputer"]),
+    (KeyCode::LaunchApp2, &["launchapp2", "calculator"]),
+    // Numeric keypad
+    (KeyCode::Numpad0, &["numpad0"]),
+    (KeyCode::Numpad1, &["numpad1"]),
+    (KeyCode::Numpad2, &["numpad2"]),
+    (KeyCode::Numpad3, &["numpad3"]),
+    (KeyCode::Numpad4, &["numpad4"]),
+    (KeyCode::Numpad5, &["numpad5"]),
+    (KeyCode::Numpad6, &["numpad6"]),
+    (KeyCode::Numpad7, &["numpad7"]),
+    (KeyCode::Numpad8, &["numpad8"]),
+    (KeyCode::Numpad9, &["numpad9"]),
+    (KeyCode::NumpadAdd, &["numpadadd", "numpad+"]),
+    (KeyCode::NumpadSub, &["numpadsub", "numpad-"]),
+    (KeyCode::NumpadMul, &["numpadmul", "numpad*"]),
+    (KeyCode::NumpadDiv, &["numpaddiv", "numpad/"]),
+    (KeyCode::NumpadEnter, &["numpadenter"]),
+    (KeyCode::NumpadDecimal, &["numpaddecimal", "numpad."]),
+    (KeyCode::NumpadEquals, &["numpadequals", "numpad="]),
+    // Punctuation -- accept both the symbol and a spelled-out name
+    (KeyCode::Backtick, &["`", "backtick", "grave"]),
+    (KeyCode::Minus, &["-", "minus", "hyphen", "dash"]),
+    (KeyCode::Equal, &["=", "equal", "equals"]),
+    (KeyCode::LeftBracket, &["[", "leftbracket", "lbracket"]),
+    (KeyCode::RightBracket, &["]", "rightbracket", "rbracket"]),
+    (KeyCode::Backslash, &["\\", "backslash"]),
+    (KeyCode::Semicolon, &[";", "semicolon"]),
+    (KeyCode::Apostrophe, &["'", "apostrophe", "quote"]),
+    (KeyCode::Comma, &[",", "comma"]),
+    (KeyCode::Period, &[".", "period", "dot"]),
+    (KeyCode::Slash, &["/", "slash"]),
+    (
+        KeyCode::IntlBackslash,
+        &["intlbackslash", "iso102nd", "oem102"],
+    ),
+    (KeyCode::IntlYen, &["intlyen", "yen"]),
+    (KeyCode::IntlRo, &["intlro", "ro"]),
+    // Input method keys
+    (KeyCode::Lang1, &["lang1", "kana", "hangul"]),
+    (KeyCode::Lang2, &["lang2", "eisu", "hanja"]),
+];
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_key_reads_back_from_its_name() {
+        for &(key, names) in NAMES {
+            assert_eq!(parse(&name(key)), Ok(key), "{key:?}");
+            assert!(!names.is_empty(), "{key:?}");
+        }
+        let mut keys: Vec<KeyCode> = NAMES.iter().map(|&(key, _)| key).collect();
+        keys.sort_by_key(|k| format!("{k:?}"));
+        keys.dedup();
+        assert_eq!(keys.len(), NAMES.len(), "a key is listed twice");
+    }
+
+    #[test]
+    fn names_ignore_case_and_digits_take_the_prefix() {
+        assert_eq!(parse("CONTROL"), Ok(KeyCode::Ctrl));
+        assert_eq!(parse("PageDown"), Ok(KeyCode::PageDown));
+        assert_eq!(parse("7"), Ok(KeyCode::Key7));
+        assert_eq!(parse("Key7"), Ok(KeyCode::Key7));
+        assert_eq!(name(KeyCode::Key7), "7");
+        assert_eq!(name(KeyCode::LeftCtrl), "LeftCtrl");
+        assert!(parse("Key").is_err());
+        assert!(parse("Key12").is_err());
+    }
+
+    #[test]
+    fn unknown_names_suggest_close_ones() {
+        let err = parse("Escpae").unwrap_err();
+        assert_eq!(err.given, "Escpae");
+        assert_eq!(err.suggestions, vec!["Escape"]);
+        assert_eq!(
+            err.to_string(),
+            "unknown key name 'Escpae' (did you mean 'Escape'?)"
+        );
+
+        let err = parse("F33").unwrap_err();
+        assert_eq!(err.suggestions.len(), MAX_SUGGESTIONS);
+        assert!(err.to_string().contains(" or '"), "{err}");
+
+        let err = parse("Frobnicate").unwrap_err();
+        assert!(err.suggestions.is_empty());
+        assert_eq!(err.to_string(), "unknown key name 'Frobnicate'");
+    }
+
+    #[test]
+    fn distance_counts_edits() {
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("tab", "tab"), 0);
+        assert_eq!(distance("ctlr", "ctrl"), 2);
+    }
+}
@@ -0,0 +1,137 @@
+//! Clipboard reads and writes on Linux, through the session's clipboard
+//! tool.
+//!
+//! Wayland clients can only read the clipboard while focused, and X11
+//! selections are owned by the copying client, so a helper acts on our
+//! behalf: `wl-paste` and `wl-copy` on Wayland, `xclip` on X11 and XWayland.
+//! A session without one has no clipboard, which reads as `Unavailable`.
+//!
+//! Setting the clipboard hands the text to the tool on stdin. Both tools
+//! then fork a process that owns the selection and serves it until another
+//! program takes the clipboard; the one we start exits once it has the text.
+
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use super::detect::{detect_display_server, DisplayServer};
+use crate::platform::{Clipboard, PlatformError};
+
+pub struct LinuxClipboard;
+
+impl Clipboard for LinuxClipboard {
+    fn read_text(&self) -> Result<Option<String>, PlatformError> {
+        let argv = argv(detect_display_server()).ok_or_else(|| {
+            PlatformError::Unavailable("no display server to read the clipboard from".into())
+        })?;
+        let output = match Command::new(argv[0]).args(&argv[1..]).output() {
+            Ok(output) => output,
+            Err(e) => return Err(run_error(argv[0], "reading", e)),
+        };
+        // Both tools fail when the clipboard is empty or holds no text.
+        if !output.status.success() {
+            log::debug!(
+                "clipboard: {} exited with {}: {}",
+                argv[0],
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Ok(None);
+        }
+        Ok(String::from_utf8(output.stdout)
+            .ok()
+            .filter(|t| !t.is_empty()))
+    }
+
+    fn write_text(&self, text: &str) -> Result<(), PlatformError> {
+        let argv = write_argv(detect_display_server()).ok_or_else(|| {
+            PlatformError::Unavailable("no display server to set the clipboard on".into())
+        })?;
+        // The forked owner keeps stdout and stderr open, so they must not
+        // be pipes we would wait on.
+        let mut child = Command::new(argv[0])
+            .args(&argv[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| run_error(argv[0], "setting", e))?;
+        let written = child
+            .stdin
+            .take()
+            .map_or(Ok(()), |mut stdin| stdin.write_all(text.as_bytes()));
+        let status = child
+            .wait()
+            .map_err(|e| PlatformError::io(format!("cannot wait on {}", argv[0]), e))?;
+        written.map_err(|e| PlatformError::io(format!("cannot write to {}", argv[0]), e))?;
+        if !status.success() {
+            return Err(PlatformError::Other(format!(
+                "{} exited with {status}",
+                argv[0]
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The error for a clipboard tool that did not start.
+fn run_error(tool: &str, doing: &str, e: std::io::Error) -> PlatformError {
+    if e.kind() == ErrorKind::NotFound {
+        PlatformError::Unavailable(format!("{doing} the clipboard needs {tool}; install it"))
+    } else {
+        PlatformError::io(format!("cannot run {tool}"), e)
+    }
+}
+
+/// The command that prints the clipboard's text on `server`.
+fn argv(server: Option<DisplayServer>) -> Option<&'static [&'static str]> {
+    match server? {
+        DisplayServer::Wayland => Some(&["wl-paste", "--no-newline", "--type", "text/plain"]),
+        DisplayServer::X11 => Some(&[
+            "xclip",
+            "-selection",
+            "clipboard",
+            "-o",
+            "-t",
+            "UTF8_STRING",
+        ]),
+        DisplayServer::Tty => None,
+    }
+}
+
+/// The command that sets the clipboard to the text on its stdin on `server`.
+fn write_argv(server: Option<DisplayServer>) -> Option<&'static [&'static str]> {
+    match server? {
+        DisplayServer::Wayland => Some(&["wl-copy", "--type", "text/plain"]),
+        DisplayServer::X11 => Some(&[
+            "xclip",
+            "-selection",
+            "clipboard",
+            "-i",
+            "-t",
+            "UTF8_STRING",
+        ]),
+        DisplayServer::Tty => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_session_reads_with_its_own_tool() {
+        assert_eq!(argv(Some(DisplayServer::Wayland)).unwrap()[0], "wl-paste");
+        assert_eq!(argv(Some(DisplayServer::X11)).unwrap()[0], "xclip");
+        assert!(argv(Some(DisplayServer::Tty)).is_none());
+        assert!(argv(None).is_none());
+        assert_eq!(
+            write_argv(Some(DisplayServer::Wayland)).unwrap()[0],
+            "wl-copy"
+        );
+        assert!(write_argv(Some(DisplayServer::Tty)).is_none());
+    }
+}
@@ -1,9 +1,15 @@
 //! Display server detection for Linux.
 //!
-//! Determines whether the current session is Wayland or X11-only by inspecting
-//! the environment variables set by the session manager.
+//! Determines whether the current session is Wayland, X11, or a text console
+//! by inspecting the environment variables set by the session manager.
 //! The result drives which platform backend is selected at startup.
 //!
+//! `XDG_SESSION_TYPE` is authoritative when it names a known type: it is set by
+//! logind for every session, including ones whose systemd user units never
+//! import `WAYLAND_DISPLAY` or `DISPLAY`, and it tells a text console apart from
+//! a graphical session that merely left a stale `DISPLAY` behind. Otherwise the
+//! socket variables decide.
+//!
 //! Note: `DISPLAY` being set alongside `WAYLAND_DISPLAY` means XWayland is
 //! running as a compatibility layer for legacy X11 apps. Our app connects to
 //! the Wayland portal via D-Bus and is a native Wayland client regardless.
@@ -23,6 +29,9 @@ pub enum DisplayServer {
     Wayland,
     /// Pure X11 session. Only `DISPLAY` is set; no Wayland compositor present.
     X11,
+    /// Text console (`XDG_SESSION_TYPE=tty`). No compositor to inject through,
+    /// whatever `DISPLAY` says; only kernel-level injection can work.
+    Tty,
 }
 
 // ---------------------------------------------------------------------------
@@ -31,23 +40,36 @@ pub enum DisplayServer {
 
 /// Detects the active display server from environment variables.
 ///
-/// Returns `None` when neither `WAYLAND_DISPLAY` nor `DISPLAY` is set,
-/// which indicates the process is running outside of any graphical session.
+/// Returns `None` when `XDG_SESSION_TYPE` is unset or unrecognised and
+/// neither `WAYLAND_DISPLAY` nor `DISPLAY` is set, which indicates the process
+/// is running outside of any session that logind knows about.
 pub fn detect_display_server() -> Option<DisplayServer> {
+    let session_type = env::var("XDG_SESSION_TYPE").ok();
     let has_wayland = env::var_os("WAYLAND_DISPLAY")
         .map(|v| !v.is_empty())
         .unwrap_or(false);
     let has_display = env::var_os("DISPLAY")
         .map(|v| !v.is_empty())
         .unwrap_or(false);
-    classify_display(has_wayland, has_display)
+    classify_display(session_type.as_deref(), has_wayland, has_display)
 }
 
-/// Classifies the display server from boolean presence flags.
+/// Classifies the display server from `XDG_SESSION_TYPE` and socket presence.
 ///
 /// Extracted from `detect_display_server` so the classification logic
 /// can be unit-tested without mutating process environment variables.
-fn classify_display(has_wayland: bool, has_display: bool) -> Option<DisplayServer> {
+fn classify_display(
+    session_type: Option<&str>,
+    has_wayland: bool,
+    has_display: bool,
+) -> Option<DisplayServer> {
+    match session_type {
+        Some("wayland") => return Some(DisplayServer::Wayland),
+        Some("x11") => return Some(DisplayServer::X11),
+        Some("tty") => return Some(DisplayServer::Tty),
+        // Unset, empty, or "unspecified"/"mir": fall back to the sockets.
+        _ => {}
+    }
     match (has_wayland, has_display) {
         // WAYLAND_DISPLAY present means a Wayland compositor is running.
         // DISPLAY may also be set (XWayland compat layer); that is irrelevant
@@ -65,31 +87,75 @@ fn classify_display(has_wayland: bool, has_display: bool) -> Option<DisplayServe
 #[cfg(test)]
 mod tests {
     use super::*;
+    use DisplayServer::{Tty, Wayland, X11};
 
     #[test]
     fn wayland_only_detects_wayland() {
-        assert_eq!(classify_display(true, false), Some(DisplayServer::Wayland));
+        assert_eq!(classify_display(None, true, false), Some(Wayland));
     }
 
     #[test]
     fn wayland_and_display_still_detects_wayland() {
         // DISPLAY being set means XWayland compat is available for other apps;
         // we are a native Wayland client and use the Wayland path regardless.
-        assert_eq!(classify_display(true, true), Some(DisplayServer::Wayland));
+        assert_eq!(classify_display(None, true, true), Some(Wayland));
     }
 
     #[test]
     fn display_only_detects_x11() {
-        assert_eq!(classify_display(false, true), Some(DisplayServer::X11));
+        assert_eq!(classify_display(None, false, true), Some(X11));
     }
 
     #[test]
     fn no_vars_returns_none() {
-        assert_eq!(classify_display(false, false), None);
+        assert_eq!(classify_display(None, false, false), None);
+    }
+
+    #[test]
+    fn tty_session_with_stale_display_is_tty() {
+        assert_eq!(classify_display(Some("tty"), false, true), Some(Tty));
+    }
+
+    /// Every combination of session type and socket variables.
+    #[test]
+    fn full_truth_table() {
+        // (session type, WAYLAND_DISPLAY, DISPLAY) -> expected
+        let table: &[(Option<&str>, bool, bool, Option<DisplayServer>)] = &[
+            (None, false, false, None),
+            (None, false, true, Some(X11)),
+            (None, true, false, Some(Wayland)),
+            (None, true, true, Some(Wayland)),
+            (Some("wayland"), false, false, Some(Wayland)),
+            (Some("wayland"), false, true, Some(Wayland)),
+            (Some("wayland"), true, false, Some(Wayland)),
+            (Some("wayland"), true, true, Some(Wayland)),
+            (Some("x11"), false, false, Some(X11)),
+            (Some("x11"), false, true, Some(X11)),
+            (Some("x11"), true, false, Some(X11)),
+            (Some("x11"), true, true, Some(X11)),
+            (Some("tty"), false, false, Some(Tty)),
+            (Some("tty"), false, true, Some(Tty)),
+            (Some("tty"), true, false, Some(Tty)),
+            (Some("tty"), true, true, Some(Tty)),
+            (Some("unspecified"), false, false, None),
+            (Some("unspecified"), false, true, Some(X11)),
+            (Some("unspecified"), true, false, Some(Wayland)),
+            (Some("unspecified"), true, true, Some(Wayland)),
+            (Some(""), false, true, Some(X11)),
+        ];
+        for &(session, wayland, display, expected) in table {
+            assert_eq!(
+                classify_display(session, wayland, display),
+                expected,
+                "XDG_SESSION_TYPE={session:?} WAYLAND_DISPLAY={wayland} DISPLAY={display}"
+            );
+        }
     }
 
     #[test]
     fn display_server_variants_are_distinct() {
-        assert_ne!(DisplayServer::Wayland, DisplayServer::X11);
+        assert_ne!(Wayland, X11);
+        assert_ne!(Wayland, Tty);
+        assert_ne!(X11, Tty);
     }
 }
@@ -12,24 +12,61 @@
 //! reach the application). The grab is released automatically when the device
 //! is dropped on stop.
 //!
+//! A keyboard that cannot be grabbed is still read, but its keys also reach
+//! the compositor, so its events are reported with `observed` set.
+//!
+//! Key events carry the `DeviceId` of their keyboard, made once per device
+//! when it is opened. Keyboards with a vendor and product id get
+//! `vendor:product` in hex, such as `046d:c52b`, as on macOS and Windows; the
+//! i8042 keyboard of a laptop gets `built-in`. Anything else is named by its
+//! physical path, or by its event node where it reports none.
+//!
+//! A device whose stream errors (usually because it was unplugged) is dropped
+//! on its own; the other keyboards keep working. While one is missing,
+//! /dev/input is rescanned and a returning keyboard is grabbed again.
+//!
+//! Given a pointer callback, capture also reads the mice: devices with
+//! relative X motion and a left button. Mice are never grabbed, so the
+//! pointer keeps working while their events are observed. Motion and wheel
+//! steps are summed until the device ends its frame (`SYN_REPORT`), giving
+//! one `PointerEvent` per frame and kind; buttons go out as they arrive.
+//!
 //! Required permissions: the process user must be a member of the `input` group.
 //!   sudo usermod -aG input $USER   (then log out and back in)
+//!
+//! When no keyboard is found, `start` opens the nodes in /dev/input itself to
+//! tell a permission problem from missing devices, and fails with a matching
+//! error. Missing devices are waited for a little first, since at boot udev
+//! may not have created them yet.
 
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 
-use evdev::{Device, InputEventKind};
-use futures::stream::SelectAll;
+use evdev::{BusType, Device, InputEventKind, InputId};
+use futures::future;
+use futures::stream::{SelectAll, Stream};
 use futures::StreamExt;
 use tokio::sync::oneshot;
 
-use super::super::keycodes::evdev_to_keycode;
+use super::super::keycodes::{evdev_to_button, evdev_to_keycode};
+use super::super::uinput::DEVICE_NAME as UINPUT_DEVICE_NAME;
 // `evdev::InputEvent` and `crate::platform::InputEvent` share a name; alias ours.
 use crate::platform::{
-    InputCapture as InputCaptureTrait, InputEvent as PlatformInputEvent, KeyState, Modifiers,
-    PlatformError, WindowContext,
+    timestamp_from_age, DeviceId, Heartbeat, InputCapture as InputCaptureTrait,
+    InputEvent as PlatformInputEvent, KeyState, Modifiers, PlatformError, PointerEvent,
+    WindowContext,
 };
 
+/// The callback pointer events are handed to.
+type PointerCallback = Box<dyn Fn(PointerEvent) + Send>;
+
 // ---------------------------------------------------------------------------
 // Public struct
 // ---------------------------------------------------------------------------
@@ -38,6 +75,10 @@ use crate::platform::{
 pub struct LinuxEvdevCapture {
     stop_tx: Option<oneshot::Sender<()>>,
     thread: Option<JoinHandle<()>>,
+    heartbeat: Heartbeat,
+    device_wait: Duration,
+    /// Set when pointer events are wanted; mice are only read then.
+    pointer_callback: Option<PointerCallback>,
 }
 
 impl LinuxEvdevCapture {
@@ -45,6 +86,9 @@ impl LinuxEvdevCapture {
         Self {
             stop_tx: None,
             thread: None,
+            heartbeat: Heartbeat::default(),
+            device_wait: DEFAULT_DEVICE_WAIT,
+            pointer_callback: None,
         }
     }
 }
@@ -60,14 +104,23 @@ impl InputCaptureTrait for LinuxEvdevCapture {
 
         // Enumerate and open keyboard devices in the calling thread so errors
         // surface immediately rather than silently dying in the background.
-        let keyboards = find_keyboards()?;
+        let keyboards = find_keyboards(self.device_wait)?;
         log::info!("capture: found {} keyboard device(s)", keyboards.len());
-        for dev in &keyboards {
+        for (_, dev, _) in &keyboards {
             log::debug!("capture: monitoring {:?}", dev.name().unwrap_or("unnamed"));
         }
+        let pointer = self.pointer_callback.take();
+        let mice = if pointer.is_some() {
+            let mice = enumerate_mice(&HashSet::new());
+            log::info!("capture: found {} mouse device(s)", mice.len());
+            mice
+        } else {
+            Vec::new()
+        };
 
         let (stop_tx, stop_rx) = oneshot::channel();
         self.stop_tx = Some(stop_tx);
+        let heartbeat = self.heartbeat.clone();
 
         let thread = thread::spawn(move || {
             let rt = tokio::runtime::Builder::new_current_thread()
@@ -75,7 +128,12 @@ impl InputCaptureTrait for LinuxEvdevCapture {
                 .build()
                 .expect("capture: failed to build tokio runtime");
 
-            if let Err(e) = rt.block_on(capture_loop(keyboards, callback, stop_rx)) {
+            let devices = Devices { keyboards, mice };
+            let callbacks = Callbacks {
+                key: callback,
+                pointer,
+            };
+            if let Err(e) = rt.block_on(capture_loop(devices, callbacks, stop_rx, heartbeat)) {
                 log::error!("capture: fatal error: {e}");
             }
         });
@@ -84,6 +142,18 @@ impl InputCaptureTrait for LinuxEvdevCapture {
         Ok(())
     }
 
+    fn set_heartbeat(&mut self, heartbeat: Heartbeat) {
+        self.heartbeat = heartbeat;
+    }
+
+    fn set_device_wait(&mut self, wait: Duration) {
+        self.device_wait = wait;
+    }
+
+    fn set_pointer_callback(&mut self, callback: PointerCallback) {
+        self.pointer_callback = Some(callback);
+    }
+
     fn stop(&mut self) -> Result<(), PlatformError> {
         if let Some(tx) = self.stop_tx.take() {
             let _ = tx.send(());
@@ -105,92 +175,480 @@ impl Drop for LinuxEvdevCapture {
 // Device enumeration
 // ---------------------------------------------------------------------------
 
+/// How long `start` waits for keyboards by default.
+pub const DEFAULT_DEVICE_WAIT: Duration = Duration::from_secs(3);
+
+/// How often /dev/input is looked at again while waiting for keyboards.
+const DEVICE_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+const INPUT_DIR: &str = "/dev/input";
+
+/// Id shared by all built-in keyboards.
+const BUILT_IN_ID: &str = "built-in";
+
 /// Finds all keyboard devices in /dev/input/.
 ///
 /// A device is considered a keyboard if it reports support for `KEY_A`, which
-/// filters out mice, joysticks, and other non-keyboard HID devices.
+/// filters out mice, joysticks, and other non-keyboard HID devices. Our own
+/// uinput device is skipped so injected keys are not captured again.
 ///
-/// Returns `Err` when no keyboards are found (commonly because the process user
-/// is not in the `input` group -- see module-level documentation).
-fn find_keyboards() -> Result<Vec<Device>, PlatformError> {
-    let mut keyboards: Vec<Device> = evdev::enumerate()
-        .filter_map(|(_, dev)| {
-            let is_keyboard = dev
-                .supported_keys()
-                .is_some_and(|keys| keys.contains(evdev::Key::KEY_A));
-            if is_keyboard {
-                Some(dev)
-            } else {
-                None
-            }
+/// While none is found and the cause may pass, such as udev not having
+/// created the nodes yet, looks again until `wait` is over. Returns `Err`
+/// naming the cause when no keyboards are found (see `DeviceProblem`).
+///
+/// Each keyboard comes with whether it was grabbed.
+fn find_keyboards(wait: Duration) -> Result<Vec<(PathBuf, Device, bool)>, PlatformError> {
+    let deadline = Instant::now() + wait;
+    let mut waiting = false;
+    let keyboards = loop {
+        let keyboards = enumerate_keyboards(&HashSet::new());
+        if !keyboards.is_empty() {
+            break keyboards;
+        }
+        let problem = diagnose(&probe_devices(Path::new(INPUT_DIR)));
+        if !problem.may_pass() || Instant::now() >= deadline {
+            return Err(problem.into_error());
+        }
+        if !waiting {
+            log::info!("capture: no keyboard yet; waiting up to {wait:?} for one to appear");
+            waiting = true;
+        }
+        thread::sleep(DEVICE_RETRY_INTERVAL);
+    };
+
+    let keyboards: Vec<_> = keyboards
+        .into_iter()
+        .map(|(path, mut dev)| {
+            let grabbed = grab(&mut dev);
+            (path, dev, grabbed)
         })
         .collect();
 
-    if keyboards.is_empty() {
+    if !keyboards.iter().any(|(_, _, grabbed)| *grabbed) {
         return Err(PlatformError::Unavailable(
-            "No keyboard devices found in /dev/input/. \
-             Ensure this user is in the 'input' group: \
-             sudo usermod -aG input $USER (then log out and back in)."
+            "No keyboard device could be grabbed exclusively. \
+             Another process may hold the device, or permissions may be insufficient."
                 .into(),
         ));
     }
 
-    // Grab each device exclusively (EVIOCGRAB) so the compositor does not also
-    // receive the raw events. Without this, both the daemon and compositor see
-    // every keystroke, causing doubled input when remaps are active.
-    let mut grabbed = 0_usize;
-    for dev in &mut keyboards {
-        match dev.grab() {
-            Ok(()) => {
-                grabbed += 1;
-                log::debug!("capture: grabbed {:?}", dev.name().unwrap_or("unnamed"));
-            }
-            Err(e) => log::warn!(
-                "capture: failed to grab {:?}: {e} -- events from this device may be doubled",
+    Ok(keyboards)
+}
+
+/// Lists keyboard devices whose path is not in `skip`, without grabbing them.
+fn enumerate_keyboards(skip: &HashSet<PathBuf>) -> Vec<(PathBuf, Device)> {
+    evdev::enumerate()
+        .filter(|(path, dev)| {
+            !skip.contains(path)
+                && dev
+                    .supported_keys()
+                    .is_some_and(|keys| keys.contains(evdev::Key::KEY_A))
+                && dev.name() != Some(UINPUT_DEVICE_NAME)
+        })
+        .collect()
+}
+
+/// Lists mice whose path is not in `skip`: devices with relative X motion
+/// and a left button that are not keyboards, which `enumerate_keyboards`
+/// lists already. Never grabbed.
+fn enumerate_mice(skip: &HashSet<PathBuf>) -> Vec<(PathBuf, Device)> {
+    evdev::enumerate()
+        .filter(|(path, dev)| {
+            !skip.contains(path)
+                && is_pointer(dev)
+                && !dev
+                    .supported_keys()
+                    .is_some_and(|keys| keys.contains(evdev::Key::KEY_A))
+                && dev.name() != Some(UINPUT_DEVICE_NAME)
+        })
+        .collect()
+}
+
+/// Whether `dev` moves a pointer: relative X motion and a left button.
+fn is_pointer(dev: &Device) -> bool {
+    dev.supported_relative_axes()
+        .is_some_and(|axes| axes.contains(evdev::RelativeAxisType::REL_X))
+        && dev
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(evdev::Key::BTN_LEFT))
+}
+
+/// Why no keyboard was found.
+#[derive(Debug, PartialEq)]
+enum DeviceProblem {
+    /// A device node exists but this user may not open it.
+    Denied(PathBuf),
+    /// There are no device nodes at all, or they vanished while opened.
+    Absent,
+    /// Device nodes open, but none of them is a keyboard.
+    NotKeyboards,
+}
+
+impl DeviceProblem {
+    /// Whether waiting may help: devices still appearing at boot.
+    fn may_pass(&self) -> bool {
+        !matches!(self, Self::Denied(_))
+    }
+
+    fn into_error(self) -> PlatformError {
+        match self {
+            Self::Denied(path) => PlatformError::PermissionDenied(format!(
+                "cannot open {}: this user is not in the 'input' group. \
+                 Run: sudo usermod -aG input $USER, then log out and back in.",
+                path.display()
+            )),
+            Self::Absent => PlatformError::NotFound(format!(
+                "no input devices in {INPUT_DIR}. The kernel has not created any; \
+                 in a container, pass {INPUT_DIR} through to it."
+            )),
+            Self::NotKeyboards => PlatformError::Unavailable(format!(
+                "no keyboard among the devices in {INPUT_DIR}. \
+                 Check that one is plugged in; `libinput list-devices` shows what the kernel sees."
+            )),
+        }
+    }
+}
+
+/// Opens each event node in `dir` and returns its path with the error, if
+/// opening failed. A `dir` that cannot be read is reported as its own entry.
+fn probe_devices(dir: &Path) -> Vec<(PathBuf, Option<io::Error>)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return vec![(dir.to_path_buf(), Some(e))],
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"))
+        })
+        .map(|path| {
+            let error = File::open(&path).err();
+            (path, error)
+        })
+        .collect()
+}
+
+/// Names the problem from the outcome of opening each device node.
+///
+/// A node this user may not open wins, since the keyboard may be behind it.
+/// Nodes that are all missing mean the devices are not there (yet).
+fn diagnose(probes: &[(PathBuf, Option<io::Error>)]) -> DeviceProblem {
+    let denied = probes.iter().find(|(_, error)| {
+        error
+            .as_ref()
+            .is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+    });
+    if let Some((path, _)) = denied {
+        return DeviceProblem::Denied(path.clone());
+    }
+    let missing = probes.iter().all(|(_, error)| {
+        error
+            .as_ref()
+            .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+    });
+    if missing {
+        return DeviceProblem::Absent;
+    }
+    DeviceProblem::NotKeyboards
+}
+
+/// Grabs `dev` exclusively (EVIOCGRAB) so the compositor does not also
+/// receive the raw events. Without this, both the daemon and compositor see
+/// every keystroke, causing doubled input when remaps are active.
+fn grab(dev: &mut Device) -> bool {
+    match dev.grab() {
+        Ok(()) => {
+            log::debug!("capture: grabbed {:?}", dev.name().unwrap_or("unnamed"));
+            true
+        }
+        Err(e) => {
+            log::warn!(
+                "capture: failed to grab {:?}: {e} -- its keys also reach applications unremapped",
                 dev.name().unwrap_or("unnamed")
-            ),
+            );
+            false
         }
     }
+}
 
-    if grabbed == 0 {
-        return Err(PlatformError::Unavailable(
-            "No keyboard device could be grabbed exclusively. \
-             Another process may hold the device, or permissions may be insufficient."
-                .into(),
-        ));
+// ---------------------------------------------------------------------------
+// Per-device streams
+// ---------------------------------------------------------------------------
+
+/// How often /dev/input is rescanned while a device is missing.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the loop beats its heartbeat.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An item of the merged per-device stream.
+#[derive(Debug, PartialEq)]
+enum DeviceItem<T> {
+    Event(T),
+    /// The device at this path failed (typically unplugged); its stream has
+    /// ended and it is no longer read.
+    Lost(PathBuf),
+}
+
+/// Wraps one device's event stream so that its first error ends only that
+/// stream, reported once as `Lost`.
+///
+/// `SelectAll` keeps polling a stream that yields `Err`, and a disconnected
+/// device would yield errors forever, so the stream must end itself.
+fn device_stream<T, S>(path: PathBuf, stream: S) -> impl Stream<Item = DeviceItem<T>>
+where
+    S: Stream<Item = io::Result<T>>,
+{
+    stream.scan(Some(path), |path, item| {
+        // `path` is taken on the first error; once it is gone the stream ends.
+        let next = match (item, path.as_ref()) {
+            (_, None) => None,
+            (Ok(event), Some(_)) => Some(DeviceItem::Event(event)),
+            (Err(e), Some(p)) => {
+                log::warn!("capture: {} stopped: {e}", p.display());
+                path.take().map(DeviceItem::Lost)
+            }
+        };
+        future::ready(next)
+    })
+}
+
+/// What a device is read for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Role {
+    /// Its keys are forwarded.
+    keys: bool,
+    /// Its motion, wheel, and buttons are forwarded.
+    pointer: bool,
+    /// It is grabbed, so its keys reach nothing else.
+    grabbed: bool,
+}
+
+impl Role {
+    /// A mouse: pointer events only, never grabbed.
+    const MOUSE: Role = Role {
+        keys: false,
+        pointer: true,
+        grabbed: false,
+    };
+}
+
+/// What a device's raw events amount to, once read.
+#[derive(Debug, PartialEq)]
+enum Captured {
+    Key(PlatformInputEvent),
+    Pointer(PointerEvent),
+    /// The motion and wheel steps of one device frame.
+    Frame(PointerFrame),
+}
+
+type DeviceStreams = SelectAll<Pin<Box<dyn Stream<Item = DeviceItem<Captured>> + Send>>>;
+
+/// Opens `device`'s event stream and adds it to `streams`.
+fn add_device(
+    streams: &mut DeviceStreams,
+    active: &mut HashSet<PathBuf>,
+    path: PathBuf,
+    device: Device,
+    role: Role,
+) -> io::Result<()> {
+    let mut frame = PointerFrame::default();
+    let id = device_id(
+        &device.input_id(),
+        device.name(),
+        device.physical_path(),
+        &path,
+    );
+    let stream = device.into_event_stream()?.filter_map(move |item| {
+        let read = match item {
+            Ok(event) => read(
+                &event,
+                role,
+                &id,
+                &mut frame,
+                SystemTime::now(),
+                Instant::now(),
+            )
+            .map(Ok),
+            Err(e) => Some(Err(e)),
+        };
+        future::ready(read)
+    });
+    active.insert(path.clone());
+    streams.push(Box::pin(device_stream(path, stream)));
+    Ok(())
+}
+
+/// The id and name for the device at `path`; see the module docs.
+fn device_id(input: &InputId, name: Option<&str>, phys: Option<&str>, path: &Path) -> DeviceId {
+    let id = if input.bus_type() == BusType::BUS_I8042 {
+        BUILT_IN_ID.to_owned()
+    } else if input.vendor() != 0 || input.product() != 0 {
+        format!("{:04x}:{:04x}", input.vendor(), input.product())
+    } else {
+        match phys.filter(|p| !p.is_empty()) {
+            Some(phys) => phys.to_owned(),
+            None => path.display().to_string(),
+        }
+    };
+    let name = match name.map(str::trim).filter(|n| !n.is_empty()) {
+        Some(name) => name.to_owned(),
+        None => format!("keyboard {id}"),
+    };
+    DeviceId {
+        id: Arc::from(id),
+        name: Arc::from(name),
     }
+}
 
-    Ok(keyboards)
+/// The motion and wheel steps a device reported since its last frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct PointerFrame {
+    dx: i32,
+    dy: i32,
+    wheel: i32,
+    hwheel: i32,
+}
+
+impl PointerFrame {
+    /// Adds one relative axis event. High-resolution wheel axes repeat the
+    /// plain ones in finer units and are left out.
+    fn add(&mut self, axis: evdev::RelativeAxisType, value: i32) {
+        use evdev::RelativeAxisType as Rel;
+        let sum = match axis {
+            Rel::REL_X => &mut self.dx,
+            Rel::REL_Y => &mut self.dy,
+            Rel::REL_WHEEL => &mut self.wheel,
+            Rel::REL_HWHEEL => &mut self.hwheel,
+            _ => return,
+        };
+        *sum = sum.saturating_add(value);
+    }
+
+    /// The frame as pointer events: the motion, then the scrolling, each
+    /// where there was any. The kernel's wheel already counts up and right
+    /// as positive.
+    fn events(self) -> impl Iterator<Item = PointerEvent> {
+        let motion = (self.dx != 0 || self.dy != 0).then_some(PointerEvent::Motion {
+            dx: self.dx,
+            dy: self.dy,
+            position: None,
+        });
+        let scroll = (self.wheel != 0 || self.hwheel != 0).then_some(PointerEvent::Scroll {
+            dx: self.hwheel,
+            dy: self.wheel,
+            discrete: true,
+        });
+        motion.into_iter().chain(scroll)
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Async event loop
 // ---------------------------------------------------------------------------
 
-/// Reads keyboard events from all discovered devices concurrently until stopped.
+/// The devices found at start: keyboards with whether each was grabbed,
+/// and mice.
+struct Devices {
+    keyboards: Vec<(PathBuf, Device, bool)>,
+    mice: Vec<(PathBuf, Device)>,
+}
+
+struct Callbacks {
+    key: Box<dyn Fn(PlatformInputEvent) + Send>,
+    /// `None` when pointer events are not wanted.
+    pointer: Option<PointerCallback>,
+}
+
+impl Callbacks {
+    fn keyboard_role(&self, dev: &Device, grabbed: bool) -> Role {
+        Role {
+            keys: true,
+            pointer: self.pointer.is_some() && is_pointer(dev),
+            grabbed,
+        }
+    }
+
+    fn deliver(&self, captured: Captured) {
+        match (captured, &self.pointer) {
+            (Captured::Key(event), _) => (self.key)(event),
+            (Captured::Pointer(event), Some(pointer)) => pointer(event),
+            (Captured::Frame(frame), Some(pointer)) => frame.events().for_each(pointer),
+            (_, None) => {}
+        }
+    }
+}
+
+/// Reads events from all discovered devices concurrently until stopped.
+///
+/// A device that errors is dropped without disturbing the others. While any
+/// device is missing, /dev/input is rescanned so a replugged keyboard or
+/// mouse (under whatever event node it gets) is read again, and a keyboard
+/// grabbed again. `heartbeat` is beaten every `HEARTBEAT_INTERVAL` while the
+/// loop runs.
 async fn capture_loop(
-    keyboards: Vec<Device>,
-    callback: Box<dyn Fn(PlatformInputEvent) + Send>,
-    stop_rx: oneshot::Receiver<()>,
+    devices: Devices,
+    callbacks: Callbacks,
+    mut stop_rx: oneshot::Receiver<()>,
+    heartbeat: Heartbeat,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Convert each Device into a non-blocking async EventStream.
-    let mut all_streams: SelectAll<evdev::EventStream> = SelectAll::new();
-    for device in keyboards {
-        all_streams.push(device.into_event_stream()?);
+    let mut streams = DeviceStreams::new();
+    let mut active = HashSet::new();
+    for (path, device, grabbed) in devices.keyboards {
+        let role = callbacks.keyboard_role(&device, grabbed);
+        add_device(&mut streams, &mut active, path, device, role)?;
+    }
+    for (path, device) in devices.mice {
+        add_device(&mut streams, &mut active, path, device, Role::MOUSE)?;
     }
 
     log::info!("capture: evdev capture active");
 
-    tokio::select! {
-        _ = stop_rx => {
-            log::info!("capture: stop signal received");
-        }
-        _ = async {
-            while let Some(Ok(event)) = all_streams.next().await {
-                handle_evdev_event(event, &*callback);
+    let mut missing = 0_usize;
+    let mut rescan = tokio::time::interval(RESCAN_INTERVAL);
+    let mut beat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = &mut stop_rx => {
+                log::info!("capture: stop signal received");
+                break;
             }
-            log::info!("capture: all evdev streams ended");
-        } => {}
+            Some(item) = streams.next(), if !streams.is_empty() => match item {
+                DeviceItem::Event(captured) => callbacks.deliver(captured),
+                DeviceItem::Lost(path) => {
+                    active.remove(&path);
+                    missing += 1;
+                    log::info!(
+                        "capture: {} device(s) still active; waiting for the lost one to return",
+                        active.len()
+                    );
+                }
+            },
+            _ = beat.tick() => heartbeat.beat(),
+            _ = rescan.tick(), if missing > 0 => {
+                for (path, mut device) in enumerate_keyboards(&active) {
+                    let grabbed = grab(&mut device);
+                    let role = callbacks.keyboard_role(&device, grabbed);
+                    log::info!("capture: keyboard returned at {}", path.display());
+                    match add_device(&mut streams, &mut active, path, device, role) {
+                        Ok(()) => missing = missing.saturating_sub(1),
+                        Err(e) => log::warn!("capture: cannot read returned keyboard: {e}"),
+                    }
+                }
+                if callbacks.pointer.is_some() {
+                    for (path, device) in enumerate_mice(&active) {
+                        log::info!("capture: mouse returned at {}", path.display());
+                        match add_device(&mut streams, &mut active, path, device, Role::MOUSE) {
+                            Ok(()) => missing = missing.saturating_sub(1),
+                            Err(e) => log::warn!("capture: cannot read returned mouse: {e}"),
+                        }
+                    }
+                }
+            }
+        }
     }
 
     Ok(())
@@ -200,37 +658,91 @@ async fn capture_loop(
 // Event handler
 // ---------------------------------------------------------------------------
 
-/// Converts a raw evdev event into a `PlatformInputEvent` and calls `callback`.
+/// Reads one raw event from `device`, read for `role`, adding motion and
+/// wheel steps to `frame` until the device ends the frame. Events from a
+/// keyboard that is not grabbed also reached the compositor and are marked
+/// observed.
+fn read(
+    event: &evdev::InputEvent,
+    role: Role,
+    device: &DeviceId,
+    frame: &mut PointerFrame,
+    now_wall: SystemTime,
+    now: Instant,
+) -> Option<Captured> {
+    match event.kind() {
+        InputEventKind::Key(key) if role.pointer && evdev_to_button(key.code()).is_some() => {
+            let state = match event.value() {
+                1 => KeyState::Down,
+                0 => KeyState::Up,
+                _ => return None,
+            };
+            let button = evdev_to_button(key.code())?;
+            Some(Captured::Pointer(PointerEvent::Button { button, state }))
+        }
+        InputEventKind::Key(_) if role.keys => {
+            let event = translate(event, now_wall, now)?;
+            Some(Captured::Key(PlatformInputEvent {
+                observed: !role.grabbed,
+                device: Some(device.clone()),
+                ..event
+            }))
+        }
+        InputEventKind::RelAxis(axis) if role.pointer => {
+            frame.add(axis, event.value());
+            None
+        }
+        InputEventKind::Synchronization(evdev::Synchronization::SYN_REPORT) if role.pointer => {
+            let done = std::mem::take(frame);
+            (done != PointerFrame::default()).then_some(Captured::Frame(done))
+        }
+        _ => None,
+    }
+}
+
+/// Translates a raw evdev event, or returns `None` for events we do not forward.
 ///
 /// Key-down (value 1), key-up (value 0), and auto-repeat (value 2) are forwarded.
-/// Repeat is forwarded as `KeyState::Down` so that held keys repeat via injected
-/// events; the compositor no longer sees the real device under EVIOCGRAB.
-fn handle_evdev_event(event: evdev::InputEvent, callback: &dyn Fn(PlatformInputEvent)) {
+/// Repeat is forwarded as `KeyState::Down` with `is_repeat` set so that held
+/// keys repeat via injected events; the compositor no longer sees the real
+/// device under EVIOCGRAB.
+///
+/// The kernel stamps each event with `CLOCK_REALTIME`; its age against
+/// `now_wall` moves it onto the `Instant` timeline at `now`.
+fn translate(
+    event: &evdev::InputEvent,
+    now_wall: SystemTime,
+    now: Instant,
+) -> Option<PlatformInputEvent> {
     let InputEventKind::Key(evdev_key) = event.kind() else {
-        return;
+        return None;
     };
 
-    let state = match event.value() {
-        1 => KeyState::Down,
-        0 => KeyState::Up,
-        2 => KeyState::Down, // evdev auto-repeat: forward as Down so injection repeats
-        _ => return,
+    let (state, is_repeat) = match event.value() {
+        1 => (KeyState::Down, false),
+        0 => (KeyState::Up, false),
+        2 => (KeyState::Down, true), // evdev auto-repeat: forward as Down so injection repeats
+        _ => return None,
     };
 
-    match evdev_to_keycode(evdev_key.code() as u32) {
-        Some(key) => {
-            callback(PlatformInputEvent {
-                key,
-                state,
-                // Modifier tracking and window context are implemented in M11.
-                modifiers: Modifiers::default(),
-                window: WindowContext::default(),
-            });
-        }
-        None => {
-            log::debug!("capture: unknown evdev keycode {}", evdev_key.code());
-        }
-    }
+    let Some(key) = evdev_to_keycode(evdev_key.code() as u32) else {
+        log::debug!("capture: unknown evdev keycode {}", evdev_key.code());
+        return None;
+    };
+
+    let age = now_wall.duration_since(event.timestamp()).ok();
+    Some(PlatformInputEvent {
+        key,
+        state,
+        // Modifier tracking and window context are implemented in M11.
+        modifiers: Modifiers::default(),
+        window: WindowContext::default(),
+        timestamp: timestamp_from_age(age, now),
+        device: None,
+        observed: false,
+        is_repeat,
+        position: None,
+    })
 }
 
 // ---------------------------------------------------------------------------
@@ -253,4 +765,267 @@ mod tests {
         let mut capture = LinuxEvdevCapture::new();
         assert!(capture.stop().is_ok());
     }
+
+    #[test]
+    fn key_event_keeps_kernel_timestamp() {
+        use std::time::Duration;
+
+        let raw = evdev::InputEvent::new_now(evdev::EventType::KEY, 30, 1);
+        let now = Instant::now();
+        let now_wall = raw.timestamp() + Duration::from_millis(4);
+
+        let event = translate(&raw, now_wall, now).expect("KEY_A down is forwarded");
+        assert_eq!(event.key, crate::platform::KeyCode::A);
+        assert_eq!(event.state, KeyState::Down);
+        assert_eq!(event.timestamp, now - Duration::from_millis(4));
+    }
+
+    #[test]
+    fn auto_repeat_is_a_marked_down() {
+        let raw = evdev::InputEvent::new_now(evdev::EventType::KEY, 30, 2);
+        let event = translate(&raw, SystemTime::now(), Instant::now()).expect("repeat");
+        assert_eq!(event.state, KeyState::Down);
+        assert!(event.is_repeat);
+    }
+
+    #[test]
+    fn non_key_events_are_not_translated() {
+        let raw = evdev::InputEvent::new_now(evdev::EventType::SYNCHRONIZATION, 0, 0);
+        assert!(translate(&raw, SystemTime::now(), Instant::now()).is_none());
+    }
+
+    fn read_all(role: Role, raw: &[(evdev::EventType, u16, i32)]) -> Vec<Captured> {
+        let mut frame = PointerFrame::default();
+        let device = device_id(
+            &InputId::new(BusType::BUS_USB, 0x046d, 0xc52b, 0),
+            Some("USB Receiver"),
+            None,
+            Path::new("/dev/input/event3"),
+        );
+        raw.iter()
+            .filter_map(|&(kind, code, value)| {
+                let event = evdev::InputEvent::new_now(kind, code, value);
+                read(
+                    &event,
+                    role,
+                    &device,
+                    &mut frame,
+                    SystemTime::now(),
+                    Instant::now(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mouse_motion_and_wheel_are_summed_per_frame() {
+        use evdev::EventType as Ev;
+        let captured = read_all(
+            Role::MOUSE,
+            &[
+                (Ev::RELATIVE, 0, 3),  // REL_X
+                (Ev::RELATIVE, 1, -2), // REL_Y
+                (Ev::RELATIVE, 0, 1),
+                (Ev::SYNCHRONIZATION, 0, 0),
+                (Ev::RELATIVE, 8, -1),    // REL_WHEEL, toward the user
+                (Ev::RELATIVE, 11, -120), // REL_WHEEL_HI_RES, left out
+                (Ev::SYNCHRONIZATION, 0, 0),
+                (Ev::SYNCHRONIZATION, 0, 0),
+            ],
+        );
+        let events: Vec<PointerEvent> = captured
+            .into_iter()
+            .flat_map(|c| match c {
+                Captured::Frame(frame) => frame.events().collect::<Vec<_>>(),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                PointerEvent::Motion {
+                    dx: 4,
+                    dy: -2,
+                    position: None
+                },
+                PointerEvent::Scroll {
+                    dx: 0,
+                    dy: -1,
+                    discrete: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn mouse_buttons_go_out_at_once_and_keyboards_ignore_motion() {
+        use crate::platform::MouseButton;
+        use evdev::EventType as Ev;
+        let captured = read_all(Role::MOUSE, &[(Ev::KEY, 0x111, 1), (Ev::KEY, 0x111, 0)]);
+        assert_eq!(
+            captured,
+            vec![
+                Captured::Pointer(PointerEvent::Button {
+                    button: MouseButton::Right,
+                    state: KeyState::Down
+                }),
+                Captured::Pointer(PointerEvent::Button {
+                    button: MouseButton::Right,
+                    state: KeyState::Up
+                }),
+            ]
+        );
+
+        let keyboard = Role {
+            keys: true,
+            pointer: false,
+            grabbed: true,
+        };
+        let captured = read_all(
+            keyboard,
+            &[
+                (Ev::RELATIVE, 0, 5),
+                (Ev::SYNCHRONIZATION, 0, 0),
+                (Ev::KEY, 30, 1),
+            ],
+        );
+        assert!(matches!(
+            captured.as_slice(),
+            [Captured::Key(event)] if event.key == crate::platform::KeyCode::A && !event.observed
+        ));
+        let Captured::Key(event) = &captured[0] else {
+            unreachable!()
+        };
+        assert_eq!(event.device.as_ref().map(|d| &*d.id), Some("046d:c52b"));
+    }
+
+    #[test]
+    fn ids_are_vendor_product_built_in_or_path() {
+        let node = Path::new("/dev/input/event7");
+        let usb = device_id(
+            &InputId::new(BusType::BUS_USB, 0x046d, 0xc52b, 0x111),
+            Some(" Logitech USB Receiver "),
+            Some("usb-0000:00:14.0-2/input0"),
+            node,
+        );
+        assert_eq!(
+            (&*usb.id, &*usb.name),
+            ("046d:c52b", "Logitech USB Receiver")
+        );
+        let laptop = device_id(
+            &InputId::new(BusType::BUS_I8042, 0x0001, 0x0001, 0xab83),
+            Some("AT Translated Set 2 keyboard"),
+            Some("isa0060/serio0/input0"),
+            node,
+        );
+        assert_eq!(&*laptop.id, "built-in");
+        let virtual_kbd = device_id(
+            &InputId::new(BusType::BUS_VIRTUAL, 0, 0, 0),
+            None,
+            None,
+            node,
+        );
+        assert_eq!(
+            (&*virtual_kbd.id, &*virtual_kbd.name),
+            ("/dev/input/event7", "keyboard /dev/input/event7")
+        );
+    }
+
+    fn run_merged(streams: Vec<Vec<io::Result<u32>>>) -> Vec<DeviceItem<u32>> {
+        let mut merged = SelectAll::new();
+        for (n, items) in streams.into_iter().enumerate() {
+            let path = PathBuf::from(format!("/dev/input/event{n}"));
+            merged.push(Box::pin(device_stream(path, futures::stream::iter(items))));
+        }
+        futures::executor::block_on(merged.collect())
+    }
+
+    #[test]
+    fn error_mid_stream_ends_only_that_device() {
+        let failing = vec![
+            Ok(1),
+            Err(io::Error::from_raw_os_error(19)), // ENODEV
+            Ok(2),
+            Err(io::Error::from_raw_os_error(19)),
+        ];
+        let healthy = vec![Ok(10), Ok(11), Ok(12)];
+
+        let items = run_merged(vec![failing, healthy]);
+
+        let lost: Vec<_> = items
+            .iter()
+            .filter(|i| matches!(i, DeviceItem::Lost(_)))
+            .collect();
+        assert_eq!(
+            lost,
+            vec![&DeviceItem::Lost(PathBuf::from("/dev/input/event0"))]
+        );
+        let events: Vec<u32> = items
+            .iter()
+            .filter_map(|i| match i {
+                DeviceItem::Event(e) => Some(*e),
+                DeviceItem::Lost(_) => None,
+            })
+            .collect();
+        // Nothing after the failing device's error; everything from the other.
+        assert!(events.contains(&1));
+        assert!(!events.contains(&2));
+        for e in [10, 11, 12] {
+            assert!(events.contains(&e), "missing event {e} from healthy device");
+        }
+    }
+
+    #[test]
+    fn stream_without_errors_passes_every_event() {
+        let items = run_merged(vec![vec![Ok(1), Ok(2)]]);
+        assert_eq!(items, vec![DeviceItem::Event(1), DeviceItem::Event(2)]);
+    }
+
+    fn probe(node: &str, errno: Option<i32>) -> (PathBuf, Option<io::Error>) {
+        (
+            PathBuf::from(format!("/dev/input/{node}")),
+            errno.map(io::Error::from_raw_os_error),
+        )
+    }
+
+    const EPERM: i32 = 1;
+    const ENOENT: i32 = 2;
+    const EACCES: i32 = 13;
+
+    #[test]
+    fn a_node_without_access_is_a_permission_problem() {
+        for errno in [EACCES, EPERM] {
+            let probes = [probe("event0", None), probe("event1", Some(errno))];
+            let problem = diagnose(&probes);
+            assert_eq!(
+                problem,
+                DeviceProblem::Denied(PathBuf::from("/dev/input/event1"))
+            );
+            assert!(!problem.may_pass());
+            assert!(matches!(
+                problem.into_error(),
+                PlatformError::PermissionDenied(msg) if msg.contains("usermod -aG input")
+            ));
+        }
+    }
+
+    #[test]
+    fn missing_nodes_mean_no_devices_yet() {
+        assert_eq!(diagnose(&[]), DeviceProblem::Absent);
+        let problem = diagnose(&[probe("event0", Some(ENOENT))]);
+        assert_eq!(problem, DeviceProblem::Absent);
+        assert!(problem.may_pass());
+        assert!(matches!(problem.into_error(), PlatformError::NotFound(_)));
+    }
+
+    #[test]
+    fn nodes_that_open_mean_no_keyboard() {
+        let problem = diagnose(&[probe("event0", None), probe("event1", Some(ENOENT))]);
+        assert_eq!(problem, DeviceProblem::NotKeyboards);
+        assert!(problem.may_pass());
+        assert!(matches!(
+            problem.into_error(),
+            PlatformError::Unavailable(_)
+        ));
+    }
 }
@@ -8,7 +8,7 @@
 //! - `keycode_to_evdev`: for outgoing injection (always resolves; unified
 //!   modifiers map to their left-side variant).
 
-use crate::platform::{KeyCode, KeyState};
+use crate::platform::{KeyCode, KeyState, MouseButton};
 use reis::ei::keyboard::KeyState as EiKeyState;
 
 // ---------------------------------------------------------------------------
@@ -21,8 +21,8 @@ use reis::ei::keyboard::KeyState as EiKeyState;
 /// (e.g. media keys, browser buttons, or hardware-specific keys). Callers
 /// should log unknown codes at `debug` level and silently drop the event.
 ///
-/// Both left and right modifier variants are unified into the single canonical
-/// variant (`Ctrl`, `Shift`, `Alt`, `Meta`), matching the platform trait contract.
+/// Modifiers map to their sided variants (`LeftCtrl`, `RightCtrl`, ...); the
+/// engine folds them into the unified ones as configured.
 pub fn evdev_to_keycode(code: u32) -> Option<KeyCode> {
     match code {
         // Letters
@@ -93,11 +93,15 @@ pub fn evdev_to_keycode(code: u32) -> Option<KeyCode> {
         193 => Some(KeyCode::F23),
         194 => Some(KeyCode::F24),
 
-        // Modifiers: left and right both map to the unified variant.
-        29 | 97 => Some(KeyCode::Ctrl),
-        42 | 54 => Some(KeyCode::Shift),
-        56 | 100 => Some(KeyCode::Alt),
-        125 | 126 => Some(KeyCode::Meta),
+        // Modifiers, by side
+        29 => Some(KeyCode::LeftCtrl),
+        97 => Some(KeyCode::RightCtrl),
+        42 => Some(KeyCode::LeftShift),
+        54 => Some(KeyCode::RightShift),
+        56 => Some(KeyCode::LeftAlt),
+        100 => Some(KeyCode::RightAlt),
+        125 => Some(KeyCode::LeftMeta),
+        126 => Some(KeyCode::RightMeta),
 
         // Navigation and editing
         57 => Some(KeyCode::Space),
@@ -122,6 +126,31 @@ pub fn evdev_to_keycode(code: u32) -> Option<KeyCode> {
         70 => Some(KeyCode::ScrollLock),
         99 => Some(KeyCode::PrintScreen),
         119 => Some(KeyCode::Pause),
+        127 => Some(KeyCode::Menu), // KEY_COMPOSE
+
+        // Media keys
+        115 => Some(KeyCode::VolumeUp),
+        114 => Some(KeyCode::VolumeDown),
+        113 => Some(KeyCode::Mute),
+        164 => Some(KeyCode::PlayPause),
+        163 => Some(KeyCode::NextTrack), // KEY_NEXTSONG
+        165 => Some(KeyCode::PrevTrack), // KEY_PREVIOUSSONG
+        166 => Some(KeyCode::Stop),      // KEY_STOPCD
+        225 => Some(KeyCode::BrightnessUp),
+        224 => Some(KeyCode::BrightnessDown),
+
+        // Browser and launch keys
+        158 => Some(KeyCode::BrowserBack),      // KEY_BACK
+        159 => Some(KeyCode::BrowserForward),   // KEY_FORWARD
+        173 => Some(KeyCode::BrowserRefresh),   // KEY_REFRESH
+        128 => Some(KeyCode::BrowserStop),      // KEY_STOP
+        217 => Some(KeyCode::BrowserSearch),    // KEY_SEARCH
+        156 => Some(KeyCode::BrowserFavorites), // KEY_BOOKMARKS
+        172 => Some(KeyCode::BrowserHome),      // KEY_HOMEPAGE
+        155 => Some(KeyCode::LaunchMail),       // KEY_MAIL
+        226 => Some(KeyCode::LaunchMedia),      // KEY_MEDIA
+        157 => Some(KeyCode::LaunchApp1),       // KEY_COMPUTER
+        140 => Some(KeyCode::LaunchApp2),       // KEY_CALC
 
         // Numeric keypad
         82 => Some(KeyCode::Numpad0),
@@ -139,6 +168,8 @@ pub fn evdev_to_keycode(code: u32) -> Option<KeyCode> {
         55 => Some(KeyCode::NumpadMul),
         98 => Some(KeyCode::NumpadDiv),
         96 => Some(KeyCode::NumpadEnter),
+        83 => Some(KeyCode::NumpadDecimal), // KEY_KPDOT
+        117 => Some(KeyCode::NumpadEquals), // KEY_KPEQUAL
 
         // Punctuation / symbol keys
         41 => Some(KeyCode::Backtick),
@@ -152,6 +183,13 @@ pub fn evdev_to_keycode(code: u32) -> Option<KeyCode> {
         51 => Some(KeyCode::Comma),
         52 => Some(KeyCode::Period),
         53 => Some(KeyCode::Slash),
+        86 => Some(KeyCode::IntlBackslash), // KEY_102ND
+        124 => Some(KeyCode::IntlYen),      // KEY_YEN
+        89 => Some(KeyCode::IntlRo),        // KEY_RO
+
+        // Input method keys
+        122 => Some(KeyCode::Lang1), // KEY_HANGEUL
+        123 => Some(KeyCode::Lang2), // KEY_HANJA
 
         _ => None,
     }
@@ -236,10 +274,14 @@ pub fn keycode_to_evdev(key: KeyCode) -> u32 {
         KeyCode::F24 => 194,
 
         // Modifiers: emit left-side variant for synthetic events.
-        KeyCode::Ctrl => 29,
-        KeyCode::Shift => 42,
-        KeyCode::Alt => 56,
-        KeyCode::Meta => 125,
+        KeyCode::Ctrl | KeyCode::LeftCtrl => 29,
+        KeyCode::Shift | KeyCode::LeftShift => 42,
+        KeyCode::Alt | KeyCode::LeftAlt => 56,
+        KeyCode::Meta | KeyCode::LeftMeta => 125,
+        KeyCode::RightCtrl => 97,
+        KeyCode::RightShift => 54,
+        KeyCode::RightAlt => 100,
+        KeyCode::RightMeta => 126,
 
         // Navigation and editing
         KeyCode::Space => 57,
@@ -264,6 +306,31 @@ pub fn keycode_to_evdev(key: KeyCode) -> u32 {
         KeyCode::ScrollLock => 70,
         KeyCode::PrintScreen => 99,
         KeyCode::Pause => 119,
+        KeyCode::Menu => 127,
+
+        // Media keys
+        KeyCode::VolumeUp => 115,
+        KeyCode::VolumeDown => 114,
+        KeyCode::Mute => 113,
+        KeyCode::PlayPause => 164,
+        KeyCode::NextTrack => 163,
+        KeyCode::PrevTrack => 165,
+        KeyCode::Stop => 166,
+        KeyCode::BrightnessUp => 225,
+        KeyCode::BrightnessDown => 224,
+
+        // Browser and launch keys
+        KeyCode::BrowserBack => 158,
+        KeyCode::BrowserForward => 159,
+        KeyCode::BrowserRefresh => 173,
+        KeyCode::BrowserStop => 128,
+        KeyCode::BrowserSearch => 217,
+        KeyCode::BrowserFavorites => 156,
+        KeyCode::BrowserHome => 172,
+        KeyCode::LaunchMail => 155,
+        KeyCode::LaunchMedia => 226,
+        KeyCode::LaunchApp1 => 157,
+        KeyCode::LaunchApp2 => 140,
 
         // Numeric keypad
         KeyCode::Numpad0 => 82,
@@ -281,6 +348,8 @@ pub fn keycode_to_evdev(key: KeyCode) -> u32 {
         KeyCode::NumpadMul => 55,
         KeyCode::NumpadDiv => 98,
         KeyCode::NumpadEnter => 96,
+        KeyCode::NumpadDecimal => 83,
+        KeyCode::NumpadEquals => 117,
 
         // Punctuation / symbol keys
         KeyCode::Backtick => 41,
@@ -294,9 +363,80 @@ pub fn keycode_to_evdev(key: KeyCode) -> u32 {
         KeyCode::Comma => 51,
         KeyCode::Period => 52,
         KeyCode::Slash => 53,
+        KeyCode::IntlBackslash => 86,
+        KeyCode::IntlYen => 124,
+        KeyCode::IntlRo => 89,
+
+        // Input method keys
+        KeyCode::Lang1 => 122,
+        KeyCode::Lang2 => 123,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Unicode → X keysym
+// ---------------------------------------------------------------------------
+
+/// Converts a Unicode character to an X keysym for keysym-based injection.
+///
+/// Printable Latin-1 characters use their codepoint as the keysym; every
+/// other character uses the Unicode keysym range (`0x0100_0000 + codepoint`),
+/// per the X11 protocol's keysym encoding.
+pub fn char_to_keysym(c: char) -> u32 {
+    let cp = c as u32;
+    match cp {
+        0x20..=0x7E | 0xA0..=0xFF => cp,
+        _ => 0x0100_0000 | cp,
     }
 }
 
+// ---------------------------------------------------------------------------
+// Mouse buttons
+// ---------------------------------------------------------------------------
+
+/// `BTN_LEFT`, the first mouse button code.
+const BTN_LEFT: u16 = 0x110;
+
+/// `BTN_TASK`, the last mouse button code.
+const BTN_TASK: u16 = 0x117;
+
+/// Converts a mouse button to its evdev code, for injection.
+///
+/// Back and forward are the thumb buttons `BTN_SIDE` and `BTN_EXTRA`, as
+/// mice report them. `Other(n)` counts from `BTN_LEFT` by its number, so
+/// it is `None` past `BTN_TASK`.
+pub fn button_to_evdev(button: MouseButton) -> Option<u16> {
+    let code = match button {
+        MouseButton::Left => BTN_LEFT,
+        MouseButton::Right => 0x111,
+        MouseButton::Middle => 0x112,
+        MouseButton::Back => 0x113,
+        MouseButton::Forward => 0x114,
+        MouseButton::Other(n) => BTN_LEFT + u16::from(n),
+    };
+    (code <= BTN_TASK).then_some(code)
+}
+
+/// Converts an evdev button code to a mouse button, for capture. The
+/// inverse of `button_to_evdev`; `None` outside `BTN_LEFT..=BTN_TASK`.
+pub fn evdev_to_button(code: u16) -> Option<MouseButton> {
+    let button = match code {
+        BTN_LEFT => MouseButton::Left,
+        0x111 => MouseButton::Right,
+        0x112 => MouseButton::Middle,
+        0x113 => MouseButton::Back,
+        0x114 => MouseButton::Forward,
+        0x115..=BTN_TASK => MouseButton::Other((code - BTN_LEFT) as u8),
+        _ => return None,
+    };
+    Some(button)
+}
+
+/// Every mouse button code, for a device to advertise.
+pub fn button_codes() -> impl Iterator<Item = u16> {
+    BTN_LEFT..=BTN_TASK
+}
+
 // ---------------------------------------------------------------------------
 // Key state conversion
 // ---------------------------------------------------------------------------
@@ -317,9 +457,23 @@ pub fn key_state_from_reis(state: EiKeyState) -> KeyState {
 mod tests {
     use super::*;
 
+    #[test]
+    fn mouse_buttons_have_evdev_codes() {
+        assert_eq!(button_to_evdev(MouseButton::Left), Some(0x110));
+        assert_eq!(button_to_evdev(MouseButton::Back), Some(0x113));
+        assert_eq!(button_to_evdev(MouseButton::Other(7)), Some(0x117));
+        assert_eq!(button_to_evdev(MouseButton::Other(8)), None);
+        for code in button_codes() {
+            let button = evdev_to_button(code).unwrap();
+            assert_eq!(button_to_evdev(button), Some(code));
+        }
+        assert_eq!(evdev_to_button(0x10f), None);
+    }
+
     /// Every KeyCode has a known evdev code, and that code round-trips back
     /// through evdev_to_keycode. Unified modifier variants (Ctrl, Shift, etc.)
-    /// map to the left-side code, which maps back to the same unified variant.
+    /// map to the left-side code, which maps back to the left variant; see
+    /// `unified_modifiers_inject_the_left_key`.
     #[test]
     fn round_trip_all_keycodes() {
         let all_keys = [
@@ -383,10 +537,14 @@ mod tests {
             KeyCode::F22,
             KeyCode::F23,
             KeyCode::F24,
-            KeyCode::Ctrl,
-            KeyCode::Shift,
-            KeyCode::Alt,
-            KeyCode::Meta,
+            KeyCode::LeftCtrl,
+            KeyCode::LeftShift,
+            KeyCode::LeftAlt,
+            KeyCode::LeftMeta,
+            KeyCode::RightCtrl,
+            KeyCode::RightShift,
+            KeyCode::RightAlt,
+            KeyCode::RightMeta,
             KeyCode::Space,
             KeyCode::Enter,
             KeyCode::Tab,
@@ -422,6 +580,8 @@ mod tests {
             KeyCode::NumpadMul,
             KeyCode::NumpadDiv,
             KeyCode::NumpadEnter,
+            KeyCode::NumpadDecimal,
+            KeyCode::NumpadEquals,
             KeyCode::Backtick,
             KeyCode::Minus,
             KeyCode::Equal,
@@ -433,6 +593,32 @@ mod tests {
             KeyCode::Comma,
             KeyCode::Period,
             KeyCode::Slash,
+            KeyCode::IntlBackslash,
+            KeyCode::IntlYen,
+            KeyCode::IntlRo,
+            KeyCode::Lang1,
+            KeyCode::Lang2,
+            KeyCode::Menu,
+            KeyCode::VolumeUp,
+            KeyCode::VolumeDown,
+            KeyCode::Mute,
+            KeyCode::PlayPause,
+            KeyCode::NextTrack,
+            KeyCode::PrevTrack,
+            KeyCode::Stop,
+            KeyCode::BrightnessUp,
+            KeyCode::BrightnessDown,
+            KeyCode::BrowserBack,
+            KeyCode::BrowserForward,
+            KeyCode::BrowserRefresh,
+            KeyCode::BrowserStop,
+            KeyCode::BrowserSearch,
+            KeyCode::BrowserFavorites,
+            KeyCode::BrowserHome,
+            KeyCode::LaunchMail,
+            KeyCode::LaunchMedia,
+            KeyCode::LaunchApp1,
+            KeyCode::LaunchApp2,
         ];
 
         for key in all_keys {
@@ -447,23 +633,20 @@ mod tests {
     }
 
     #[test]
-    fn right_ctrl_maps_to_ctrl() {
-        assert_eq!(evdev_to_keycode(97), Some(KeyCode::Ctrl));
-    }
-
-    #[test]
-    fn right_shift_maps_to_shift() {
-        assert_eq!(evdev_to_keycode(54), Some(KeyCode::Shift));
-    }
-
-    #[test]
-    fn right_alt_maps_to_alt() {
-        assert_eq!(evdev_to_keycode(100), Some(KeyCode::Alt));
+    fn unified_modifiers_inject_the_left_key() {
+        for key in [KeyCode::Ctrl, KeyCode::Shift, KeyCode::Alt, KeyCode::Meta] {
+            let back = evdev_to_keycode(keycode_to_evdev(key)).unwrap();
+            assert!(back.is_left_modifier(), "{key:?} -> {back:?}");
+            assert_eq!(back.unify_side(), key);
+        }
     }
 
     #[test]
-    fn right_meta_maps_to_meta() {
-        assert_eq!(evdev_to_keycode(126), Some(KeyCode::Meta));
+    fn right_modifiers_keep_their_side() {
+        assert_eq!(evdev_to_keycode(97), Some(KeyCode::RightCtrl));
+        assert_eq!(evdev_to_keycode(54), Some(KeyCode::RightShift));
+        assert_eq!(evdev_to_keycode(100), Some(KeyCode::RightAlt));
+        assert_eq!(evdev_to_keycode(126), Some(KeyCode::RightMeta));
     }
 
     #[test]
@@ -505,4 +688,16 @@ mod tests {
         assert_eq!(keycode_to_evdev(KeyCode::F13), 183);
         assert_eq!(keycode_to_evdev(KeyCode::F24), 194);
     }
+
+    #[test]
+    fn latin1_chars_use_codepoint_keysym() {
+        assert_eq!(char_to_keysym('a'), 0x61);
+        assert_eq!(char_to_keysym('é'), 0xE9);
+    }
+
+    #[test]
+    fn other_chars_use_unicode_keysym() {
+        assert_eq!(char_to_keysym('\u{2014}'), 0x0100_2014);
+        assert_eq!(char_to_keysym('\u{1F600}'), 0x0101_F600);
+    }
 }
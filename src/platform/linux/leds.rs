@@ -0,0 +1,73 @@
+//! Lock key state from the kernel LED class (`/sys/class/leds`).
+//!
+//! Every keyboard exposes its lock LEDs as `inputN::numlock`,
+//! `inputN::capslock`, and `inputN::scrolllock`, each with a world-readable
+//! `brightness` file. The LEDs mirror the compositor's lock state, so reading
+//! them needs no display connection and no extra permissions. With several
+//! keyboards attached the LEDs are kept in sync; the first readable one wins.
+
+use std::fs;
+use std::path::Path;
+
+use crate::platform::KeyCode;
+
+const LEDS_DIR: &str = "/sys/class/leds";
+
+/// Returns whether `key`'s lock LED is lit, or `None` if `key` is not a lock
+/// key or no keyboard LED could be read.
+pub fn lock_state(key: KeyCode) -> Option<bool> {
+    let suffix = led_suffix(key)?;
+    let entries = fs::read_dir(Path::new(LEDS_DIR)).ok()?;
+    entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().ends_with(suffix))
+        .find_map(|e| {
+            let text = fs::read_to_string(e.path().join("brightness")).ok()?;
+            parse_brightness(&text)
+        })
+}
+
+/// LED class name suffix for a lock key.
+fn led_suffix(key: KeyCode) -> Option<&'static str> {
+    match key {
+        KeyCode::NumLock => Some("::numlock"),
+        KeyCode::CapsLock => Some("::capslock"),
+        KeyCode::ScrollLock => Some("::scrolllock"),
+        _ => None,
+    }
+}
+
+/// Parses a `brightness` file: `0` is off, any other number is on.
+fn parse_brightness(text: &str) -> Option<bool> {
+    text.trim().parse::<u32>().ok().map(|v| v != 0)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_keys_have_led_suffixes() {
+        assert_eq!(led_suffix(KeyCode::NumLock), Some("::numlock"));
+        assert_eq!(led_suffix(KeyCode::CapsLock), Some("::capslock"));
+        assert_eq!(led_suffix(KeyCode::ScrollLock), Some("::scrolllock"));
+    }
+
+    #[test]
+    fn non_lock_key_has_no_led() {
+        assert_eq!(led_suffix(KeyCode::A), None);
+        assert_eq!(lock_state(KeyCode::A), None);
+    }
+
+    #[test]
+    fn brightness_parsing() {
+        assert_eq!(parse_brightness("0\n"), Some(false));
+        assert_eq!(parse_brightness("1\n"), Some(true));
+        assert_eq!(parse_brightness("255"), Some(true));
+        assert_eq!(parse_brightness(""), None);
+    }
+}
@@ -1,50 +1,123 @@
 //! Linux platform backend.
 //!
 //! Capture: direct evdev (/dev/input/event*) via `LinuxEvdevCapture`.
-//! Injection: xdg-desktop-portal RemoteDesktop via `LinuxWaylandExecutor`.
+//! Injection: the first working backend from an ordered fallback chain (see
+//! `select`):
+//! 1. RemoteDesktop portal (`LinuxWaylandExecutor`) -- Wayland sessions
+//! 2. uinput (`LinuxUinputExecutor`)               -- any session, needs /dev/uinput
+//! 3. virtual-keyboard protocol                     -- not yet implemented
+//! 4. XTest (`LinuxXTestExecutor`)                  -- X11 or XWayland
 //!
-//! Startup detection (capture has no compositor dependency; executor does):
-//! 1. `WAYLAND_DISPLAY` set  → RemoteDesktop portal available, use Wayland executor
-//! 2. `DISPLAY` only (X11)   → not yet supported, clear error
-//! 3. Neither variable set   → no display, clear error
+//! `[executor] backend = "..."` in the config pins one backend and disables
+//! the chain. `[executor] headless = true` pins uinput, for consoles and
+//! macro boxes with no compositor at all.
+//!
+//! Clipboard: `wl-paste` or `xclip`, per session type (`clipboard`).
+//!
+//! Session lock: logind's signals and properties for our session (`session`).
 
+mod clipboard;
 mod detect;
 mod evdev;
 mod keycodes;
+mod leds;
+mod select;
+mod session;
+mod system;
+mod uinput;
 mod wayland;
+mod x11;
 
+use clipboard::LinuxClipboard;
 use evdev::LinuxEvdevCapture;
+use uinput::LinuxUinputExecutor;
 use wayland::LinuxWaylandExecutor;
+use x11::LinuxXTestExecutor;
 
-use crate::platform::{ActionExecutor, InputCapture, PlatformError};
+use crate::platform::{ActionExecutor, Clipboard, InputCapture, PlatformError};
 use detect::{detect_display_server, DisplayServer};
+use select::Backend;
+pub use session::watch_session;
 
 // ---------------------------------------------------------------------------
 // Factory: input capture
 // ---------------------------------------------------------------------------
 
-/// Returns the evdev-based keyboard capture backend.
+/// Returns the evdev-based keyboard capture backend, the only Linux one.
 ///
-/// Requires the process user to be in the `input` group.
-pub fn create_input_capture() -> Result<Box<dyn InputCapture>, PlatformError> {
-    Ok(Box::new(LinuxEvdevCapture::new()))
+/// Requires the process user to be in the `input` group. `pinned` is the
+/// `[capture] backend` config value; it must be `evdev` if set.
+pub fn create_input_capture(pinned: Option<&str>) -> Result<Box<dyn InputCapture>, PlatformError> {
+    match pinned {
+        None | Some("evdev") => Ok(Box::new(LinuxEvdevCapture::new())),
+        Some(name) => Err(PlatformError::Unavailable(format!(
+            "capture backend '{name}' is not available on Linux (available: evdev)"
+        ))),
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Factory: action executor
 // ---------------------------------------------------------------------------
 
-/// Returns the appropriate `ActionExecutor` for the current session.
-pub fn create_action_executor() -> Result<Box<dyn ActionExecutor>, PlatformError> {
-    match detect_display_server() {
-        Some(DisplayServer::Wayland) => {
-            LinuxWaylandExecutor::new().map(|e| Box::new(e) as Box<dyn ActionExecutor>)
+/// Returns the first `ActionExecutor` in the fallback chain that can start.
+///
+/// `pinned` names a single backend to use instead of the chain; it is the
+/// `[executor] backend` config value.
+pub fn create_action_executor(
+    pinned: Option<&str>,
+) -> Result<Box<dyn ActionExecutor>, PlatformError> {
+    let candidates = match pinned {
+        None => Backend::CHAIN.to_vec(),
+        Some(name) => vec![Backend::from_name(name).ok_or_else(|| {
+            PlatformError::Unavailable(format!(
+                "executor backend '{name}' is not available on Linux"
+            ))
+        })?],
+    };
+    match select::select(&candidates, probe) {
+        Ok((backend, executor)) => {
+            log::info!("executor: using {}", backend.name());
+            Ok(executor)
         }
-        Some(DisplayServer::X11) => Err(PlatformError::Unavailable(
-            "Pure X11 sessions are not yet supported.".into(),
-        )),
-        None => Err(PlatformError::Unavailable(
-            "No display server detected.".into(),
-        )),
+        Err(skipped) => Err(PlatformError::Unavailable(format!(
+            "no usable executor backend ({})",
+            select::describe(&skipped)
+        ))),
+    }
+}
+
+/// Try to start one backend, returning why it cannot be used on failure.
+fn probe(backend: Backend) -> Result<Box<dyn ActionExecutor>, String> {
+    match backend {
+        Backend::Portal => match detect_display_server() {
+            Some(DisplayServer::Wayland) => LinuxWaylandExecutor::new()
+                .map(|e| Box::new(e) as Box<dyn ActionExecutor>)
+                .map_err(|e| e.to_string()),
+            Some(DisplayServer::X11) => Err("not a Wayland session".into()),
+            Some(DisplayServer::Tty) => Err("text console session".into()),
+            None => Err("no display server detected".into()),
+        },
+        Backend::Uinput => LinuxUinputExecutor::new()
+            .map(|e| Box::new(e) as Box<dyn ActionExecutor>)
+            .map_err(|e| e.to_string()),
+        Backend::VirtualKeyboard => Err("not implemented yet".into()),
+        Backend::XTest => match detect_display_server() {
+            // A stale DISPLAY on a console would inject into someone else's
+            // X server, or nowhere.
+            Some(DisplayServer::Tty) => Err("text console session".into()),
+            _ => LinuxXTestExecutor::new()
+                .map(|e| Box::new(e) as Box<dyn ActionExecutor>)
+                .map_err(|e| e.to_string()),
+        },
     }
 }
+
+// ---------------------------------------------------------------------------
+// Factory: clipboard
+// ---------------------------------------------------------------------------
+
+/// Returns the clipboard reader, which runs `wl-paste` or `xclip` per read.
+pub fn create_clipboard() -> Box<dyn Clipboard> {
+    Box::new(LinuxClipboard)
+}
@@ -0,0 +1,143 @@
+//! Executor backend selection for Linux.
+//!
+//! Candidates are tried in a fixed order; the first whose probe succeeds is
+//! used. Every skipped candidate is logged with its reason so the chain can be
+//! reconstructed from the log. Pinning a backend in config reduces the chain
+//! to that single candidate.
+
+// ---------------------------------------------------------------------------
+// Backend type
+// ---------------------------------------------------------------------------
+
+/// An injection backend available to the Linux executor factory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// xdg-desktop-portal RemoteDesktop (Wayland).
+    Portal,
+    /// Kernel virtual input device via `/dev/uinput`.
+    Uinput,
+    /// `zwp_virtual_keyboard_v1` Wayland protocol (wlroots compositors).
+    VirtualKeyboard,
+    /// XTest extension (pure X11, or X11 clients under XWayland).
+    XTest,
+}
+
+impl Backend {
+    /// Fallback order used when no backend is pinned.
+    pub const CHAIN: [Backend; 4] = [
+        Backend::Portal,
+        Backend::Uinput,
+        Backend::VirtualKeyboard,
+        Backend::XTest,
+    ];
+
+    /// Name used in config and status output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Portal => "portal",
+            Backend::Uinput => "uinput",
+            Backend::VirtualKeyboard => "virtual_keyboard",
+            Backend::XTest => "xtest",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::CHAIN.into_iter().find(|b| b.name() == name)
+    }
+}
+
+/// A candidate that was not used, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skip {
+    pub backend: Backend,
+    pub reason: String,
+}
+
+// ---------------------------------------------------------------------------
+// Selection
+// ---------------------------------------------------------------------------
+
+/// Probe `candidates` in order and return the first that succeeds.
+///
+/// `probe` is injected so the ordering and skip bookkeeping can be tested
+/// without touching D-Bus, `/dev/uinput`, or an X server. On failure every
+/// candidate's skip reason is returned, in order.
+pub fn select<T>(
+    candidates: &[Backend],
+    mut probe: impl FnMut(Backend) -> Result<T, String>,
+) -> Result<(Backend, T), Vec<Skip>> {
+    let mut skipped = Vec::new();
+    for &backend in candidates {
+        match probe(backend) {
+            Ok(value) => return Ok((backend, value)),
+            Err(reason) => {
+                log::info!("executor: skipping {}: {reason}", backend.name());
+                skipped.push(Skip { backend, reason });
+            }
+        }
+    }
+    Err(skipped)
+}
+
+/// Render skip reasons as one line for an error message.
+pub fn describe(skipped: &[Skip]) -> String {
+    skipped
+        .iter()
+        .map(|s| format!("{}: {}", s.backend.name(), s.reason))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_successful_probe_wins() {
+        let mut probed = Vec::new();
+        let got = select(&Backend::CHAIN, |b| {
+            probed.push(b);
+            match b {
+                Backend::Portal => Err("no portal".to_string()),
+                other => Ok(other.name()),
+            }
+        });
+        assert_eq!(got, Ok((Backend::Uinput, "uinput")));
+        assert_eq!(probed, vec![Backend::Portal, Backend::Uinput]);
+    }
+
+    #[test]
+    fn all_failures_report_every_reason_in_order() {
+        let got = select::<()>(&Backend::CHAIN, |b| Err(format!("{} down", b.name())));
+        let skipped = got.unwrap_err();
+        assert_eq!(
+            skipped.iter().map(|s| s.backend).collect::<Vec<_>>(),
+            Backend::CHAIN.to_vec()
+        );
+        assert_eq!(skipped[3].reason, "xtest down");
+        assert!(describe(&skipped).starts_with("portal: portal down; uinput: uinput down"));
+    }
+
+    #[test]
+    fn pinned_backend_is_the_only_candidate() {
+        let mut probed = Vec::new();
+        let got = select::<()>(&[Backend::XTest], |b| {
+            probed.push(b);
+            Err("no X".into())
+        });
+        assert!(got.is_err());
+        assert_eq!(probed, vec![Backend::XTest]);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for b in Backend::CHAIN {
+            assert_eq!(Backend::from_name(b.name()), Some(b));
+        }
+        assert_eq!(Backend::from_name("sendinput"), None);
+    }
+}
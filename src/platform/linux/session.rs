@@ -0,0 +1,100 @@
+//! Session lock tracking through systemd-logind.
+//!
+//! The watcher finds our session with `GetSession("auto")` on the system
+//! bus, which resolves to the caller's own session, or to the user's
+//! graphical one for a daemon started outside it (a systemd user unit).
+//! It then follows the session object:
+//!   - `Lock` and `Unlock` signals, which logind sends when something asks
+//!     the session to lock (`loginctl lock-session`, suspend);
+//!   - `LockedHint`, which screen lockers set while they are up;
+//!   - `Active`, false while another session has the seat, as after
+//!     switching users or virtual terminals. That counts as disconnected.
+//!
+//! Without logind (no system bus, or a distribution without it) the watcher
+//! logs a warning and ends; rules then never pause for the session.
+
+use std::thread;
+
+use ashpd::zbus::{zvariant::OwnedObjectPath, Connection, Proxy};
+use futures::StreamExt;
+
+use crate::platform::{PlatformError, SessionLock};
+
+const LOGIND: &str = "org.freedesktop.login1";
+
+/// Follow the session on a background thread, for the life of the process.
+pub fn watch_session(lock: SessionLock) {
+    thread::spawn(move || {
+        let rt = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(rt) => rt,
+            Err(e) => {
+                log::error!("session: failed to build tokio runtime: {e}");
+                return;
+            }
+        };
+        if let Err(e) = rt.block_on(watch(lock)) {
+            log::warn!("session: {e}; rules do not pause while the session is locked");
+        }
+    });
+}
+
+async fn watch(lock: SessionLock) -> Result<(), PlatformError> {
+    let dbus = |context: &'static str| move |e| PlatformError::dbus(context, e);
+
+    let conn = Connection::system()
+        .await
+        .map_err(dbus("cannot connect to the system bus"))?;
+    let manager = Proxy::new(
+        &conn,
+        LOGIND,
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )
+    .await
+    .map_err(dbus("cannot reach logind"))?;
+    let path: OwnedObjectPath = manager
+        .call("GetSession", &("auto",))
+        .await
+        .map_err(dbus("logind found no session for us"))?;
+    let session = Proxy::new(&conn, LOGIND, path, "org.freedesktop.login1.Session")
+        .await
+        .map_err(dbus("cannot reach our logind session"))?;
+
+    let mut locks = session
+        .receive_signal("Lock")
+        .await
+        .map_err(dbus("cannot follow Lock"))?;
+    let mut unlocks = session
+        .receive_signal("Unlock")
+        .await
+        .map_err(dbus("cannot follow Unlock"))?;
+    let mut hints = session.receive_property_changed::<bool>("LockedHint").await;
+    let mut active = session.receive_property_changed::<bool>("Active").await;
+
+    // Starting on a locked screen is unusual but possible, from a unit.
+    if let Ok(true) = session.get_property::<bool>("LockedHint").await {
+        lock.set_locked(true);
+    }
+    log::info!("session: following logind session {}", session.path());
+
+    loop {
+        tokio::select! {
+            Some(_) = locks.next() => lock.set_locked(true),
+            Some(_) = unlocks.next() => lock.set_locked(false),
+            Some(change) = hints.next() => {
+                if let Ok(locked) = change.get().await {
+                    lock.set_locked(locked);
+                }
+            }
+            Some(change) = active.next() => {
+                if let Ok(active) = change.get().await {
+                    lock.set_disconnected(!active);
+                }
+            }
+            else => return Ok(()),
+        }
+    }
+}
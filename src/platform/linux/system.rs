@@ -0,0 +1,67 @@
+//! Portable system commands on Linux, through logind and systemd.
+//!
+//! Lock and log out address the session named by `XDG_SESSION_ID`, so they
+//! work from a user service that runs outside the graphical session. Lock
+//! falls back to the caller's own session when the variable is unset.
+
+use crate::platform::{spawn_program, PlatformError, SystemCmd};
+
+/// Runs `cmd` for the current session.
+pub fn run(cmd: SystemCmd) -> Result<(), PlatformError> {
+    let session = std::env::var("XDG_SESSION_ID").ok();
+    spawn_program(&argv(cmd, session.as_deref())?)
+}
+
+/// The command line that performs `cmd`, given the logind session id.
+fn argv(cmd: SystemCmd, session: Option<&str>) -> Result<Vec<String>, PlatformError> {
+    let args: Vec<&str> = match (cmd, session) {
+        (SystemCmd::LockScreen, Some(id)) => vec!["loginctl", "lock-session", id],
+        (SystemCmd::LockScreen, None) => vec!["loginctl", "lock-session"],
+        (SystemCmd::Sleep, _) => vec!["systemctl", "suspend"],
+        (SystemCmd::LogOut, Some(id)) => vec!["loginctl", "terminate-session", id],
+        // Without an id, loginctl would terminate whatever session it picks.
+        (SystemCmd::LogOut, None) => {
+            return Err(PlatformError::Unavailable(
+                "log out needs XDG_SESSION_ID to name the session".into(),
+            ))
+        }
+    };
+    Ok(args.into_iter().map(str::to_owned).collect())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_map_to_logind_and_systemd() {
+        assert_eq!(
+            argv(SystemCmd::LockScreen, Some("3")).unwrap(),
+            ["loginctl", "lock-session", "3"]
+        );
+        assert_eq!(
+            argv(SystemCmd::LockScreen, None).unwrap(),
+            ["loginctl", "lock-session"]
+        );
+        assert_eq!(
+            argv(SystemCmd::Sleep, None).unwrap(),
+            ["systemctl", "suspend"]
+        );
+        assert_eq!(
+            argv(SystemCmd::LogOut, Some("3")).unwrap(),
+            ["loginctl", "terminate-session", "3"]
+        );
+    }
+
+    #[test]
+    fn log_out_without_session_is_unavailable() {
+        assert!(matches!(
+            argv(SystemCmd::LogOut, None),
+            Err(PlatformError::Unavailable(_))
+        ));
+    }
+}
@@ -0,0 +1,406 @@
+//! Action executor via a `/dev/uinput` virtual keyboard.
+//!
+//! `LinuxUinputExecutor` implements `ActionExecutor`. `new()` creates one
+//! virtual device that advertises every key the unified `KeyCode` set maps to,
+//! the mouse buttons, and relative motion and wheels; `execute()` writes key
+//! and pointer events to it synchronously. The device has no absolute axes,
+//! so absolute pointer moves are skipped. The kernel removes the
+//! device when the file descriptor is closed on drop, so a daemon that exits
+//! or restarts leaves no stale keyboard behind.
+//!
+//! The capture backend skips the device by name so injected keys are not
+//! captured again.
+//!
+//! Required permissions: write access to `/dev/uinput`, usually through a
+//! udev rule such as
+//!   KERNEL=="uinput", GROUP="input", MODE="0660"
+
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, EventType, InputEvent, Key, RelativeAxisType};
+
+use super::super::keycodes::{button_codes, button_to_evdev, evdev_to_keycode, keycode_to_evdev};
+use crate::platform::{
+    chord_steps, Action, ActionExecutor, Capabilities, KeyCode, KeyState, PlatformError,
+};
+
+/// Name of the virtual device, as shown by `evtest` and `libinput list-devices`.
+pub const DEVICE_NAME: &str = "pcunifier virtual keyboard";
+
+const UINPUT_PATH: &str = "/dev/uinput";
+
+/// Highest evdev key code scanned when registering capabilities (`KEY_MAX`).
+const KEY_MAX: u16 = 0x2ff;
+
+/// Wheel units in one notch, for the high-resolution wheel axes.
+const WHEEL_UNITS_PER_NOTCH: i32 = 120;
+
+// ---------------------------------------------------------------------------
+// Public struct
+// ---------------------------------------------------------------------------
+
+/// Injects keyboard events through a uinput virtual keyboard.
+pub struct LinuxUinputExecutor {
+    device: Mutex<VirtualDevice>,
+}
+
+impl LinuxUinputExecutor {
+    /// Checks access to `/dev/uinput` and creates the virtual keyboard.
+    pub fn new() -> Result<Self, PlatformError> {
+        check_access()?;
+        let keys = key_capabilities();
+        let axes = relative_axes();
+        let device = VirtualDeviceBuilder::new()
+            .and_then(|b| b.name(DEVICE_NAME).with_keys(&keys))
+            .and_then(|b| b.with_relative_axes(&axes))
+            .and_then(|b| b.build())
+            .map_err(|e| PlatformError::io("cannot create uinput device", e))?;
+        log::info!("executor: created uinput device '{DEVICE_NAME}'");
+        Ok(Self {
+            device: Mutex::new(device),
+        })
+    }
+
+    /// Write `steps` in order, each with its `SYN_REPORT`, holding the
+    /// device throughout so no other injection lands between them.
+    fn emit_all(&self, steps: &[(KeyCode, KeyState)]) -> Result<(), PlatformError> {
+        let mut device = self
+            .device
+            .lock()
+            .map_err(|_| PlatformError::Other("uinput device lock poisoned".into()))?;
+        for &(key, state) in steps {
+            let Some(event) = key_event(key, state, false) else {
+                log::debug!("executor: {key:?} has no evdev code, skipping");
+                continue;
+            };
+            device
+                .emit(&[event])
+                .map_err(|e| PlatformError::io("uinput write failed", e))?;
+        }
+        Ok(())
+    }
+
+    fn emit(&self, key: KeyCode, state: KeyState, repeat: bool) -> Result<(), PlatformError> {
+        let Some(event) = key_event(key, state, repeat) else {
+            log::debug!("executor: {key:?} has no evdev code, skipping");
+            return Ok(());
+        };
+        self.emit_events(&[event])
+    }
+
+    /// Write `events` followed by one `SYN_REPORT`.
+    fn emit_events(&self, events: &[InputEvent]) -> Result<(), PlatformError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.device
+            .lock()
+            .map_err(|_| PlatformError::Other("uinput device lock poisoned".into()))?
+            .emit(events)
+            .map_err(|e| PlatformError::io("uinput write failed", e))
+    }
+}
+
+impl Drop for LinuxUinputExecutor {
+    fn drop(&mut self) {
+        // The device itself goes away when `VirtualDevice` closes its fd.
+        log::info!("executor: removing uinput device '{DEVICE_NAME}'");
+    }
+}
+
+/// Every evdev key code the unified `KeyCode` set maps to. The device must
+/// advertise a key at creation for the kernel to accept events for it.
+fn key_capabilities() -> AttributeSet<Key> {
+    let mut keys = AttributeSet::<Key>::new();
+    for code in 0..=KEY_MAX {
+        if evdev_to_keycode(u32::from(code)).is_some() {
+            keys.insert(Key::new(code));
+        }
+    }
+    for code in button_codes() {
+        keys.insert(Key::new(code));
+    }
+    keys
+}
+
+/// The motion and wheel axes pointer actions write.
+fn relative_axes() -> AttributeSet<RelativeAxisType> {
+    let mut axes = AttributeSet::<RelativeAxisType>::new();
+    for axis in [
+        RelativeAxisType::REL_X,
+        RelativeAxisType::REL_Y,
+        RelativeAxisType::REL_WHEEL,
+        RelativeAxisType::REL_HWHEEL,
+        RelativeAxisType::REL_WHEEL_HI_RES,
+        RelativeAxisType::REL_HWHEEL_HI_RES,
+    ] {
+        axes.insert(axis);
+    }
+    axes
+}
+
+/// The events of a pointer action, to write ahead of one `SYN_REPORT`, or
+/// `None` if `action` is not one. Empty where the device cannot do it.
+///
+/// A notch scroll writes both the notch axis and its high-resolution axis,
+/// as a wheel mouse does; pixel scrolling only the high-resolution axis,
+/// one unit per pixel. Positive `dy` scrolls up on `REL_WHEEL`, as in
+/// `PointerEvent::Scroll`.
+fn pointer_events(action: &Action) -> Option<Vec<InputEvent>> {
+    let rel = |axis: RelativeAxisType, value: i32| {
+        (value != 0).then(|| InputEvent::new(EventType::RELATIVE, axis.0, value))
+    };
+    let events = match *action {
+        Action::MouseMoveRelative { dx, dy } => [
+            rel(RelativeAxisType::REL_X, dx),
+            rel(RelativeAxisType::REL_Y, dy),
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+        Action::MouseMoveAbsolute { .. } => {
+            log::debug!("executor: uinput has no absolute pointer, skipping move");
+            Vec::new()
+        }
+        Action::MouseButton { button, state } => match button_to_evdev(button) {
+            Some(code) => {
+                let value = i32::from(state == KeyState::Down);
+                vec![InputEvent::new(EventType::KEY, code, value)]
+            }
+            None => {
+                log::debug!("executor: {button:?} has no evdev code, skipping");
+                Vec::new()
+            }
+        },
+        Action::Scroll { dx, dy, discrete } => {
+            let units = if discrete { WHEEL_UNITS_PER_NOTCH } else { 1 };
+            let notches = |axis, value| rel(axis, value).filter(|_| discrete);
+            [
+                notches(RelativeAxisType::REL_WHEEL, dy),
+                notches(RelativeAxisType::REL_HWHEEL, dx),
+                rel(RelativeAxisType::REL_WHEEL_HI_RES, dy.saturating_mul(units)),
+                rel(
+                    RelativeAxisType::REL_HWHEEL_HI_RES,
+                    dx.saturating_mul(units),
+                ),
+            ]
+            .into_iter()
+            .flatten()
+            .collect()
+        }
+        _ => return None,
+    };
+    Some(events)
+}
+
+/// The `EV_KEY` event for one transition of `key`, or `None` if it has no
+/// evdev code. `VirtualDevice::emit` follows it with the `SYN_REPORT`.
+/// A `repeat` Down is encoded as an autorepeat (value 2).
+fn key_event(key: KeyCode, state: KeyState, repeat: bool) -> Option<InputEvent> {
+    let value = match state {
+        KeyState::Down if repeat => 2,
+        KeyState::Down => 1,
+        KeyState::Up => 0,
+    };
+    let code = u16::try_from(keycode_to_evdev(key)).ok()?;
+    Some(InputEvent::new(EventType::KEY, code, value))
+}
+
+/// Opens `/dev/uinput` for writing to turn a missing node or missing
+/// permission into an actionable error before any device is built.
+fn check_access() -> Result<(), PlatformError> {
+    match OpenOptions::new().write(true).open(UINPUT_PATH) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(PlatformError::Unavailable(access_error_message(e.kind()))),
+    }
+}
+
+fn access_error_message(kind: ErrorKind) -> String {
+    match kind {
+        ErrorKind::NotFound => {
+            format!("{UINPUT_PATH} does not exist; load the module with: sudo modprobe uinput")
+        }
+        ErrorKind::PermissionDenied => format!(
+            "no write access to {UINPUT_PATH}; add a udev rule such as \
+             KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\" and join the 'input' group"
+        ),
+        other => format!("cannot open {UINPUT_PATH}: {other}"),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ActionExecutor trait impl
+// ---------------------------------------------------------------------------
+
+impl ActionExecutor for LinuxUinputExecutor {
+    /// Executes an action.
+    ///
+    /// `Action::InjectKey` writes a key event and a `SYN_REPORT` to the
+    /// virtual device, and `Action::InjectRepeat` an autorepeat event.
+    /// `Action::Chord` writes its key events in order under one hold of the
+    /// device, each with its `SYN_REPORT`.
+    /// Pointer actions write their motion, button, or wheel events with one
+    /// `SYN_REPORT`; absolute moves are skipped.
+    /// `Action::SystemCommand` runs loginctl or systemctl.
+    /// All other variants are silently accepted as no-ops; a uinput keyboard
+    /// has no keysym path, so `Action::InjectChar` is among them.
+    fn execute(&self, action: &Action) -> Result<(), PlatformError> {
+        match action {
+            Action::InjectKey { key, state } => self.emit(*key, *state, false),
+            Action::InjectRepeat { key } => self.emit(*key, KeyState::Down, true),
+            Action::Chord { modifiers, key } => self.emit_all(&chord_steps(*modifiers, *key)),
+            Action::MouseMoveRelative { .. }
+            | Action::MouseMoveAbsolute { .. }
+            | Action::MouseButton { .. }
+            | Action::Scroll { .. } => {
+                self.emit_events(&pointer_events(action).unwrap_or_default())
+            }
+            Action::SystemCommand(cmd) => super::super::system::run(*cmd),
+            Action::Delay { ms } => {
+                log::debug!("executor: {ms}ms delay outside a sequence; ignored");
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "uinput"
+    }
+
+    /// Keys, the pointer, and system commands. A keyboard device has no
+    /// text path.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            inject_key: true,
+            pointer: true,
+            system_command: true,
+            ..Capabilities::default()
+        }
+    }
+
+    /// Reads the keyboard lock LEDs from sysfs.
+    fn lock_state(&self, key: KeyCode) -> Option<bool> {
+        super::super::leds::lock_state(key)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_errors_name_the_remedy() {
+        assert!(access_error_message(ErrorKind::NotFound).contains("modprobe uinput"));
+        assert!(access_error_message(ErrorKind::PermissionDenied).contains("udev rule"));
+    }
+
+    #[test]
+    fn capabilities_cover_mapped_keys() {
+        let keys = key_capabilities();
+        for key in [
+            KeyCode::A,
+            KeyCode::F24,
+            KeyCode::NumpadDecimal,
+            KeyCode::Lang1,
+        ] {
+            assert!(
+                keys.contains(Key::new(keycode_to_evdev(key) as u16)),
+                "{key:?}"
+            );
+        }
+        assert!(!keys.contains(Key::KEY_RESERVED));
+    }
+
+    #[test]
+    fn key_events_encode_type_code_and_value() {
+        let down = key_event(KeyCode::A, KeyState::Down, false).unwrap();
+        assert_eq!(
+            (down.event_type(), down.code(), down.value()),
+            (EventType::KEY, Key::KEY_A.code(), 1)
+        );
+        let up = key_event(KeyCode::A, KeyState::Up, false).unwrap();
+        assert_eq!(up.value(), 0);
+        let repeat = key_event(KeyCode::A, KeyState::Down, true).unwrap();
+        assert_eq!(repeat.value(), 2);
+    }
+
+    #[test]
+    fn pointer_actions_encode_relative_and_button_events() {
+        let encode = |action| -> Vec<(EventType, u16, i32)> {
+            pointer_events(&action)
+                .unwrap()
+                .iter()
+                .map(|e| (e.event_type(), e.code(), e.value()))
+                .collect()
+        };
+        assert_eq!(
+            encode(Action::MouseMoveRelative { dx: 0, dy: -4 }),
+            [(EventType::RELATIVE, RelativeAxisType::REL_Y.0, -4)]
+        );
+        assert_eq!(
+            encode(Action::MouseButton {
+                button: crate::platform::MouseButton::Right,
+                state: KeyState::Down,
+            }),
+            [(EventType::KEY, Key::BTN_RIGHT.code(), 1)]
+        );
+        assert_eq!(
+            encode(Action::Scroll {
+                dx: 0,
+                dy: 2,
+                discrete: true,
+            }),
+            [
+                (EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, 2),
+                (
+                    EventType::RELATIVE,
+                    RelativeAxisType::REL_WHEEL_HI_RES.0,
+                    240
+                ),
+            ]
+        );
+        assert!(encode(Action::MouseMoveAbsolute {
+            x: 1,
+            y: 1,
+            normalized: false,
+        })
+        .is_empty());
+        assert!(pointer_events(&Action::Suppress).is_none());
+    }
+
+    /// Runs only where `/dev/uinput` is writable, such as a CI container
+    /// started with the device passed through; elsewhere it returns early.
+    /// Checks that the device exists while the executor lives and is gone
+    /// once it is dropped.
+    #[test]
+    fn device_accepts_key_events_when_uinput_is_available() {
+        if check_access().is_err() {
+            eprintln!("skipping: {UINPUT_PATH} is not writable");
+            return;
+        }
+        let executor = LinuxUinputExecutor::new().expect("uinput device");
+        for state in [KeyState::Down, KeyState::Up] {
+            executor
+                .execute(&Action::InjectKey {
+                    key: KeyCode::F24,
+                    state,
+                })
+                .expect("write");
+        }
+        let listed = || {
+            std::fs::read_to_string("/proc/bus/input/devices")
+                .is_ok_and(|s| s.contains(DEVICE_NAME))
+        };
+        assert!(listed());
+        drop(executor);
+        assert!(!listed());
+    }
+}
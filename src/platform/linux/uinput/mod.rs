@@ -0,0 +1,8 @@
+//! uinput platform backend: a kernel virtual keyboard (injection).
+//!
+//! Needs no compositor, so it is the injection path for headless mode on a
+//! text console, and a fallback wherever `/dev/uinput` is writable.
+
+mod executor;
+
+pub use executor::{LinuxUinputExecutor, DEVICE_NAME};
@@ -10,6 +10,7 @@
 
 use std::os::unix::net::UnixStream;
 use std::thread;
+use std::time::Instant;
 
 use ashpd::desktop::input_capture::{Capabilities, InputCapture};
 use futures::StreamExt;
@@ -106,14 +107,19 @@ async fn run_capture(callback: Box<dyn Fn(InputEvent) + Send>, stop_rx: oneshot:
 async fn capture_loop(
     callback: Box<dyn Fn(InputEvent) + Send>,
     stop_rx: oneshot::Receiver<()>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), PlatformError> {
+    let dbus = |context: &'static str| move |e| PlatformError::dbus(context, e);
+
     // Connect to the portal via D-Bus.
-    let portal = InputCapture::new().await?;
+    let portal = InputCapture::new()
+        .await
+        .map_err(dbus("cannot connect to the InputCapture portal"))?;
 
     // Request a keyboard-only capture session.
     let (session, granted_caps) = portal
         .create_session(None, Capabilities::Keyboard.into())
-        .await?;
+        .await
+        .map_err(dbus("cannot create an InputCapture session"))?;
     log::debug!(
         "capture: session created, granted capabilities: {:?}",
         granted_caps
@@ -122,7 +128,11 @@ async fn capture_loop(
     // The portal protocol requires GetZones + SetPointerBarriers before Enable,
     // even for keyboard-only sessions. An empty barrier list tells the compositor
     // there are no pointer triggers; it should activate capture immediately.
-    let zones = portal.zones(&session).await?.response()?;
+    let zones = portal
+        .zones(&session)
+        .await
+        .and_then(|r| r.response())
+        .map_err(dbus("cannot get capture zones"))?;
     log::debug!(
         "capture: got {} zone(s), zone_set={}",
         zones.regions().len(),
@@ -130,30 +140,42 @@ async fn capture_loop(
     );
     let failed = portal
         .set_pointer_barriers(&session, &[], zones.zone_set())
-        .await?
-        .response()?;
+        .await
+        .and_then(|r| r.response())
+        .map_err(dbus("cannot set pointer barriers"))?;
     log::debug!(
         "capture: pointer barriers set (failed: {:?})",
         failed.failed_barriers()
     );
 
-    let fd = portal.connect_to_eis(&session).await?;
+    let fd = portal
+        .connect_to_eis(&session)
+        .await
+        .map_err(dbus("cannot connect to EIS"))?;
     log::debug!("capture: EIS socket obtained");
 
     // Subscribe to Activated before Enable so the signal is not missed.
-    let activated_stream = portal.receive_activated().await?;
+    let activated_stream = portal
+        .receive_activated()
+        .await
+        .map_err(dbus("cannot subscribe to Activated"))?;
     futures::pin_mut!(activated_stream);
 
-    portal.enable(&session).await?;
+    portal
+        .enable(&session)
+        .await
+        .map_err(dbus("cannot enable capture"))?;
     log::debug!("capture: portal enable acknowledged");
 
     let stream = UnixStream::from(fd);
-    let context = ei::Context::new(stream)?;
+    let context =
+        ei::Context::new(stream).map_err(|e| PlatformError::io("cannot open the EIS socket", e))?;
 
     // Perform the libei protocol handshake as a Receiver (capture side).
     let (_conn, mut events) = context
         .handshake_tokio("pcunifier", ei::handshake::ContextType::Receiver)
-        .await?;
+        .await
+        .map_err(|e| PlatformError::Other(format!("libei handshake failed: {e}")))?;
 
     log::info!("capture: keyboard capture active");
 
@@ -217,6 +239,13 @@ fn handle_ei_event(event: EiEvent, callback: &dyn Fn(InputEvent), context: &ei::
                         // Modifier tracking and window context are added in M11.
                         modifiers: Modifiers::default(),
                         window: WindowContext::default(),
+                        // libei stamps events with CLOCK_MONOTONIC, which std
+                        // does not expose as an `Instant`.
+                        timestamp: Instant::now(),
+                        device: None,
+                        observed: false,
+                        is_repeat: false,
+                        position: None,
                     });
                 }
                 None => {
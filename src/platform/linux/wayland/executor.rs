@@ -8,35 +8,87 @@
 //! non-blocking `try_send()` so it is safe to call from both synchronous and
 //! asynchronous contexts (including from within the capture callback).
 //!
-//! Only `Action::InjectKey` is handled here.  Other action variants are no-ops
-//! until the rule engine and Lua runtime milestones are reached.
+//! `Action::InjectKey` and `Action::InjectChar` are handled here, and pointer
+//! actions once the session includes the pointer, which the permission dialog
+//! asks for alongside the keyboard. Absolute moves need a screen-cast stream
+//! to place them, which the session does not open, so they are skipped. Other
+//! action variants are no-ops.
+//!
+//! The user can grant the session fewer devices than asked for.
+//! `capabilities()` reports what was granted once the session has started,
+//! and what was asked for until then.
 
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 
 use ashpd::desktop::{
-    remote_desktop::{DeviceType, KeyState as PortalKeyState, RemoteDesktop},
+    remote_desktop::{Axis, DeviceType, KeyState as PortalKeyState, RemoteDesktop},
     PersistMode,
 };
 use tokio::sync::mpsc;
 
-use super::super::keycodes::keycode_to_evdev;
-use crate::platform::{Action, ActionExecutor, KeyState, PlatformError};
+use super::super::keycodes::{button_to_evdev, char_to_keysym, keycode_to_evdev};
+use crate::platform::{
+    chord_steps, Action, ActionExecutor, Capabilities, KeyCode, KeyState, PlatformError,
+    TextInjection,
+};
 
 // ---------------------------------------------------------------------------
 // Internal command type
 // ---------------------------------------------------------------------------
 
-/// A single key injection command sent from `execute()` to the executor task.
-struct InjectionCmd {
+/// What a portal injection command refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PortalInput {
     /// Linux evdev keycode (same namespace as `/dev/input/`).
-    keycode: i32,
-    /// Key state for the injection.
-    state: PortalKeyState,
+    Keycode(i32),
+    /// X keysym; the compositor picks or maps a key that produces it.
+    Keysym(i32),
+}
+
+/// One request to the portal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PortalStep {
+    /// A key transition.
+    Key(PortalInput, PortalKeyState),
+    /// A relative pointer move, in pixels.
+    Motion { dx: f64, dy: f64 },
+    /// A mouse button transition, by evdev code.
+    Button(i32, PortalKeyState),
+    /// Smooth scrolling, in pixels. Positive `dy` scrolls down, as the
+    /// portal counts it.
+    Axis { dx: f64, dy: f64 },
+    /// Scrolling by wheel notches on one axis, counted as `Axis` is.
+    AxisDiscrete(Axis, i32),
+}
+
+/// An injection command sent from `execute()` to the executor task: one
+/// key transition, the steps of a chord, or the steps of a pointer action,
+/// which the task sends in order before taking the next command.
+struct InjectionCmd {
+    steps: Vec<PortalStep>,
     /// Timestamp captured in `execute()` to measure end-to-end injection latency.
     captured_at: std::time::Instant,
 }
 
+/// The devices the session may use: both until the session starts, then
+/// those the portal granted.
+struct Granted {
+    keyboard: AtomicBool,
+    pointer: AtomicBool,
+}
+
+impl Granted {
+    fn all() -> Self {
+        Self {
+            keyboard: AtomicBool::new(true),
+            pointer: AtomicBool::new(true),
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Public struct
 // ---------------------------------------------------------------------------
@@ -48,6 +100,7 @@ struct InjectionCmd {
 pub struct LinuxWaylandExecutor {
     /// Bounded channel to the executor task (capacity `CMD_CAPACITY`).
     cmd_tx: mpsc::Sender<InjectionCmd>,
+    granted: Arc<Granted>,
     thread: Option<thread::JoinHandle<()>>,
 }
 
@@ -63,7 +116,9 @@ impl LinuxWaylandExecutor {
     /// the executor task processes commands only after the session is established.
     pub fn new() -> Result<Self, PlatformError> {
         let (cmd_tx, cmd_rx) = mpsc::channel::<InjectionCmd>(CMD_CAPACITY);
+        let granted = Arc::new(Granted::all());
 
+        let session_granted = Arc::clone(&granted);
         let thread = thread::spawn(move || {
             let rt = match tokio::runtime::Builder::new_current_thread()
                 .enable_all()
@@ -75,14 +130,89 @@ impl LinuxWaylandExecutor {
                     return;
                 }
             };
-            rt.block_on(run_executor(cmd_rx));
+            rt.block_on(run_executor(cmd_rx, &session_granted));
         });
 
         Ok(Self {
             cmd_tx,
+            granted,
             thread: Some(thread),
         })
     }
+
+    /// Queues one key transition without blocking.
+    fn send(&self, input: PortalInput, state: PortalKeyState) -> Result<(), PlatformError> {
+        self.send_all(vec![PortalStep::Key(input, state)])
+    }
+
+    /// Queues `steps` as one command without blocking, so they go out
+    /// together and in order.
+    fn send_all(&self, steps: Vec<PortalStep>) -> Result<(), PlatformError> {
+        if steps.is_empty() {
+            return Ok(());
+        }
+        match self.cmd_tx.try_send(InjectionCmd {
+            steps,
+            captured_at: std::time::Instant::now(),
+        }) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                log::warn!("executor: injection channel full, event dropped");
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                Err(PlatformError::Other("executor session closed".into()))
+            }
+        }
+    }
+}
+
+fn portal_state(state: KeyState) -> PortalKeyState {
+    match state {
+        KeyState::Down => PortalKeyState::Pressed,
+        KeyState::Up => PortalKeyState::Released,
+    }
+}
+
+/// The portal steps of a pointer action, or `None` if `action` is not one.
+/// Empty where the portal cannot do it.
+fn pointer_steps(action: &Action) -> Option<Vec<PortalStep>> {
+    let steps = match *action {
+        Action::MouseMoveRelative { dx, dy } => vec![PortalStep::Motion {
+            dx: f64::from(dx),
+            dy: f64::from(dy),
+        }],
+        Action::MouseMoveAbsolute { .. } => {
+            log::debug!(
+                "executor: the portal session has no stream to place an absolute move, skipping"
+            );
+            Vec::new()
+        }
+        Action::MouseButton { button, state } => match button_to_evdev(button) {
+            Some(code) => vec![PortalStep::Button(i32::from(code), portal_state(state))],
+            None => {
+                log::debug!("executor: {button:?} has no evdev code, skipping");
+                Vec::new()
+            }
+        },
+        // The portal scrolls down for positive values, the other way from
+        // `Action::Scroll`.
+        Action::Scroll {
+            dx,
+            dy,
+            discrete: true,
+        } => [(Axis::Vertical, -dy), (Axis::Horizontal, dx)]
+            .into_iter()
+            .filter(|&(_, notches)| notches != 0)
+            .map(|(axis, notches)| PortalStep::AxisDiscrete(axis, notches))
+            .collect(),
+        Action::Scroll { dx, dy, .. } => vec![PortalStep::Axis {
+            dx: f64::from(dx),
+            dy: -f64::from(dy),
+        }],
+        _ => return None,
+    };
+    Some(steps)
 }
 
 impl Drop for LinuxWaylandExecutor {
@@ -101,36 +231,78 @@ impl ActionExecutor for LinuxWaylandExecutor {
     /// Executes an action.
     ///
     /// `Action::InjectKey` is enqueued to the portal session via a non-blocking
-    /// channel. `Action::Exec` spawns a subprocess via `spawn_command`.
+    /// channel, and `Action::InjectRepeat` as a press; the portal has no repeat
+    /// flag. `Action::Chord` is enqueued as one command, which the session
+    /// sends whole before the next.
+    /// Pointer actions are enqueued the same way; absolute moves are
+    /// skipped.
+    /// `Action::SystemCommand` runs loginctl or systemctl.
     /// All other variants are silently accepted as no-ops.
     fn execute(&self, action: &Action) -> Result<(), PlatformError> {
         match action {
-            Action::InjectKey { key, state } => {
-                let keycode = keycode_to_evdev(*key) as i32;
-                let portal_state = match state {
-                    KeyState::Down => PortalKeyState::Pressed,
-                    KeyState::Up => PortalKeyState::Released,
-                };
-
-                match self.cmd_tx.try_send(InjectionCmd {
-                    keycode,
-                    state: portal_state,
-                    captured_at: std::time::Instant::now(),
-                }) {
-                    Ok(()) => Ok(()),
-                    Err(mpsc::error::TrySendError::Full(_)) => {
-                        log::warn!("executor: injection channel full, event dropped");
-                        Ok(())
-                    }
-                    Err(mpsc::error::TrySendError::Closed(_)) => {
-                        Err(PlatformError::Other("executor session closed".into()))
-                    }
-                }
+            Action::InjectKey { key, state } => self.send(
+                PortalInput::Keycode(keycode_to_evdev(*key) as i32),
+                portal_state(*state),
+            ),
+            Action::InjectRepeat { key } => self.send(
+                PortalInput::Keycode(keycode_to_evdev(*key) as i32),
+                PortalKeyState::Pressed,
+            ),
+            Action::InjectChar(c) => {
+                let keysym = PortalInput::Keysym(char_to_keysym(*c) as i32);
+                self.send_all(vec![
+                    PortalStep::Key(keysym, PortalKeyState::Pressed),
+                    PortalStep::Key(keysym, PortalKeyState::Released),
+                ])
+            }
+            Action::Chord { modifiers, key } => self.send_all(
+                chord_steps(*modifiers, *key)
+                    .into_iter()
+                    .map(|(key, state)| {
+                        let input = PortalInput::Keycode(keycode_to_evdev(key) as i32);
+                        PortalStep::Key(input, portal_state(state))
+                    })
+                    .collect(),
+            ),
+            Action::MouseMoveRelative { .. }
+            | Action::MouseMoveAbsolute { .. }
+            | Action::MouseButton { .. }
+            | Action::Scroll { .. } => self.send_all(pointer_steps(action).unwrap_or_default()),
+            Action::SystemCommand(cmd) => super::super::system::run(*cmd),
+            Action::Delay { ms } => {
+                log::debug!("executor: {ms}ms delay outside a sequence; ignored");
+                Ok(())
             }
-            Action::Exec { command } => crate::platform::spawn_command(command),
             _ => Ok(()),
         }
     }
+
+    fn name(&self) -> &'static str {
+        "portal"
+    }
+
+    /// The portal takes keysyms, and every character has one.
+    fn text_injection(&self) -> TextInjection {
+        TextInjection::Chars
+    }
+
+    /// Keys and text with the keyboard granted, the pointer with the
+    /// pointer granted, and system commands.
+    fn capabilities(&self) -> Capabilities {
+        let keyboard = self.granted.keyboard.load(Ordering::Relaxed);
+        Capabilities {
+            inject_key: keyboard,
+            type_text: keyboard,
+            pointer: self.granted.pointer.load(Ordering::Relaxed),
+            system_command: true,
+            clipboard: false,
+        }
+    }
+
+    /// Reads the keyboard lock LEDs from sysfs; the portal exposes no lock state.
+    fn lock_state(&self, key: KeyCode) -> Option<bool> {
+        super::super::leds::lock_state(key)
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -140,17 +312,23 @@ impl ActionExecutor for LinuxWaylandExecutor {
 /// Runs on the background thread's tokio runtime.
 /// Creates the RemoteDesktop portal session, then processes injection commands
 /// until the command channel is closed (executor is dropped).
-async fn run_executor(mut cmd_rx: mpsc::Receiver<InjectionCmd>) {
-    if let Err(e) = executor_loop(&mut cmd_rx).await {
+async fn run_executor(mut cmd_rx: mpsc::Receiver<InjectionCmd>, granted: &Granted) {
+    if let Err(e) = executor_loop(&mut cmd_rx, granted).await {
         log::error!("executor: {e}");
     }
 }
 
 async fn executor_loop(
     cmd_rx: &mut mpsc::Receiver<InjectionCmd>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let portal = RemoteDesktop::new().await?;
-    let session = portal.create_session().await?;
+    granted: &Granted,
+) -> Result<(), PlatformError> {
+    let portal = RemoteDesktop::new()
+        .await
+        .map_err(|e| PlatformError::dbus("cannot connect to the RemoteDesktop portal", e))?;
+    let session = portal
+        .create_session()
+        .await
+        .map_err(|e| PlatformError::dbus("cannot create a RemoteDesktop session", e))?;
 
     // Load any previously saved restore token so the permission dialog is
     // skipped on runs after the initial grant.
@@ -158,36 +336,78 @@ async fn executor_loop(
     portal
         .select_devices(
             &session,
-            DeviceType::Keyboard.into(),
+            DeviceType::Keyboard | DeviceType::Pointer,
             saved_token.as_deref(),
             // ExplicitlyRevoked: the portal saves the grant indefinitely and
             // returns a restore token we can reuse on the next start.
             PersistMode::ExplicitlyRevoked,
         )
-        .await?;
+        .await
+        .map_err(|e| PlatformError::dbus("cannot select keyboard and pointer devices", e))?;
 
-    let start_response = portal.start(&session, None).await?;
+    let start_response = portal
+        .start(&session, None)
+        .await
+        .and_then(|r| r.response())
+        .map_err(|e| PlatformError::dbus("RemoteDesktop session was not started", e))?;
 
     // Persist the restore token so subsequent runs skip the permission dialog.
-    if let Some(token) = start_response.response()?.restore_token() {
+    if let Some(token) = start_response.restore_token() {
         save_restore_token(token);
     }
 
+    let devices = start_response.devices();
+    for (device, name, flag, what) in [
+        (
+            DeviceType::Keyboard,
+            "keyboard",
+            &granted.keyboard,
+            "key and text",
+        ),
+        (DeviceType::Pointer, "pointer", &granted.pointer, "pointer"),
+    ] {
+        if !devices.contains(device) {
+            flag.store(false, Ordering::Relaxed);
+            log::warn!("executor: the portal granted no {name}; {what} actions are dropped");
+        }
+    }
+
     log::info!("executor: RemoteDesktop session active");
 
     while let Some(cmd) = cmd_rx.recv().await {
         let captured_at = cmd.captured_at;
-        if let Err(e) = portal
-            .notify_keyboard_keycode(&session, cmd.keycode, cmd.state)
-            .await
-        {
-            log::warn!("executor: notify_keyboard_keycode failed: {e}");
-        } else {
-            log::debug!(
-                "executor: injected in {:.2}ms",
-                captured_at.elapsed().as_secs_f64() * 1000.0
-            );
+        for step in cmd.steps {
+            let result = match step {
+                PortalStep::Key(PortalInput::Keycode(code), state) => {
+                    portal.notify_keyboard_keycode(&session, code, state).await
+                }
+                PortalStep::Key(PortalInput::Keysym(sym), state) => {
+                    portal.notify_keyboard_keysym(&session, sym, state).await
+                }
+                PortalStep::Motion { dx, dy } => {
+                    portal.notify_pointer_motion(&session, dx, dy).await
+                }
+                PortalStep::Button(code, state) => {
+                    portal.notify_pointer_button(&session, code, state).await
+                }
+                PortalStep::Axis { dx, dy } => {
+                    portal.notify_pointer_axis(&session, dx, dy, true).await
+                }
+                PortalStep::AxisDiscrete(axis, notches) => {
+                    portal
+                        .notify_pointer_axis_discrete(&session, axis, notches)
+                        .await
+                }
+            };
+            if let Err(e) = result {
+                log::warn!("executor: portal injection of {step:?} failed: {e}");
+                break;
+            }
         }
+        log::debug!(
+            "executor: injected in {:.2}ms",
+            captured_at.elapsed().as_secs_f64() * 1000.0
+        );
     }
 
     log::info!("executor: command channel closed, exiting");
@@ -266,6 +486,7 @@ mod tests {
         let (cmd_tx, _cmd_rx) = mpsc::channel::<InjectionCmd>(1);
         let executor = LinuxWaylandExecutor {
             cmd_tx,
+            granted: Arc::new(Granted::all()),
             thread: None,
         };
 
@@ -293,13 +514,16 @@ mod tests {
         // Fill the channel.
         cmd_tx
             .try_send(InjectionCmd {
-                keycode: 30,
-                state: PortalKeyState::Pressed,
+                steps: vec![PortalStep::Key(
+                    PortalInput::Keycode(30),
+                    PortalKeyState::Pressed,
+                )],
                 captured_at: std::time::Instant::now(),
             })
             .unwrap();
         let executor = LinuxWaylandExecutor {
             cmd_tx,
+            granted: Arc::new(Granted::all()),
             thread: None,
         };
 
@@ -311,12 +535,34 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn scrolling_turns_to_the_portal_direction() {
+        assert_eq!(
+            pointer_steps(&Action::Scroll {
+                dx: 0,
+                dy: 3,
+                discrete: true,
+            }),
+            Some(vec![PortalStep::AxisDiscrete(Axis::Vertical, -3)])
+        );
+        assert_eq!(
+            pointer_steps(&Action::Scroll {
+                dx: 4,
+                dy: -2,
+                discrete: false,
+            }),
+            Some(vec![PortalStep::Axis { dx: 4.0, dy: 2.0 }])
+        );
+        assert_eq!(pointer_steps(&Action::Suppress), None);
+    }
+
     #[test]
     fn inject_key_on_closed_channel_returns_error() {
         let (cmd_tx, cmd_rx) = mpsc::channel::<InjectionCmd>(1);
         drop(cmd_rx); // Close the receiving end.
         let executor = LinuxWaylandExecutor {
             cmd_tx,
+            granted: Arc::new(Granted::all()),
             thread: None,
         };
 
@@ -326,4 +572,20 @@ mod tests {
         });
         assert!(result.is_err());
     }
+
+    #[test]
+    fn capabilities_follow_the_granted_devices() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel::<InjectionCmd>(1);
+        let executor = LinuxWaylandExecutor {
+            cmd_tx,
+            granted: Arc::new(Granted::all()),
+            thread: None,
+        };
+        assert!(executor.capabilities().pointer);
+
+        executor.granted.pointer.store(false, Ordering::Relaxed);
+        let caps = executor.capabilities();
+        assert!(caps.inject_key && caps.type_text);
+        assert!(!caps.pointer);
+    }
 }
@@ -0,0 +1,237 @@
+//! X11 action executor via the XTest extension.
+//!
+//! `LinuxXTestExecutor` implements `ActionExecutor`. Injection is synchronous:
+//! each `execute()` sends a `FakeInput` request and flushes the connection, so
+//! no background thread is needed. X keycodes are evdev codes offset by 8,
+//! which holds for every X server using the evdev/libinput keyboard driver
+//! (including XWayland).
+//!
+//! Pointer actions are XTest `FakeInput` events as well: button presses,
+//! and motion either relative or to a position on the root window. XTest
+//! scrolls by wheel notch, as presses of buttons 4 to 7, so pixel scrolling
+//! is skipped.
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    Window, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT, KEY_RELEASE_EVENT,
+    MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+use super::super::keycodes::keycode_to_evdev;
+use crate::platform::{
+    chord_steps, Action, ActionExecutor, Capabilities, KeyCode, KeyState, MouseButton,
+    PlatformError,
+};
+
+/// Offset between Linux evdev key codes and X11 key codes.
+const X_KEYCODE_OFFSET: u32 = 8;
+
+// ---------------------------------------------------------------------------
+// Public struct
+// ---------------------------------------------------------------------------
+
+/// Injects keyboard events via XTest `FakeInput`.
+pub struct LinuxXTestExecutor {
+    conn: RustConnection,
+    root: Window,
+    /// Width and height of the root window, for normalized moves.
+    size: (u16, u16),
+}
+
+impl LinuxXTestExecutor {
+    /// Connects to `$DISPLAY` and verifies that the XTest extension exists.
+    pub fn new() -> Result<Self, PlatformError> {
+        let (conn, screen) = x11rb::connect(None)
+            .map_err(|e| PlatformError::Unavailable(format!("cannot connect to X server: {e}")))?;
+        let present = conn
+            .extension_information(xtest::X11_EXTENSION_NAME)
+            .map_err(|e| PlatformError::Other(format!("X11 extension query failed: {e}")))?
+            .is_some();
+        if !present {
+            return Err(PlatformError::Unavailable(
+                "X server lacks the XTEST extension".into(),
+            ));
+        }
+        let screen = &conn.setup().roots[screen];
+        let (root, size) = (
+            screen.root,
+            (screen.width_in_pixels, screen.height_in_pixels),
+        );
+        Ok(Self { conn, root, size })
+    }
+
+    /// Queue a `FakeInput` event for `key`. Queued events go to the server
+    /// together on the next `flush`.
+    fn fake_key(&self, key: KeyCode, state: KeyState) -> Result<(), PlatformError> {
+        let Ok(detail) = u8::try_from(keycode_to_evdev(key) + X_KEYCODE_OFFSET) else {
+            log::debug!("executor: {key:?} has no X11 keycode, skipping");
+            return Ok(());
+        };
+        let event_type = match state {
+            KeyState::Down => KEY_PRESS_EVENT,
+            KeyState::Up => KEY_RELEASE_EVENT,
+        };
+        self.fake(event_type, detail, 0, 0)
+    }
+
+    /// Queue a `FakeInput` event of `event_type` with `detail`, at `x`, `y`
+    /// for motion.
+    fn fake(&self, event_type: u8, detail: u8, x: i16, y: i16) -> Result<(), PlatformError> {
+        self.conn
+            .xtest_fake_input(event_type, detail, 0, self.root, x, y, 0)
+            .map(drop)
+            .map_err(|e| PlatformError::Other(format!("XTest injection failed: {e}")))
+    }
+
+    /// Queue the events of a pointer action. Returns false if `action` is
+    /// not one.
+    fn fake_pointer(&self, action: &Action) -> Result<bool, PlatformError> {
+        let clamp = |v: i32| v.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16;
+        match *action {
+            // Detail 1 makes the move relative.
+            Action::MouseMoveRelative { dx, dy } => {
+                self.fake(MOTION_NOTIFY_EVENT, 1, clamp(dx), clamp(dy))?
+            }
+            Action::MouseMoveAbsolute { x, y, normalized } => {
+                let (x, y) = if normalized {
+                    (denormalize(x, self.size.0), denormalize(y, self.size.1))
+                } else {
+                    (x, y)
+                };
+                self.fake(MOTION_NOTIFY_EVENT, 0, clamp(x), clamp(y))?;
+            }
+            Action::MouseButton { button, state } => {
+                let event_type = match state {
+                    KeyState::Down => BUTTON_PRESS_EVENT,
+                    KeyState::Up => BUTTON_RELEASE_EVENT,
+                };
+                self.fake(event_type, button_number(button), 0, 0)?;
+            }
+            Action::Scroll {
+                discrete: false, ..
+            } => log::debug!("executor: XTest cannot scroll by pixel, skipping"),
+            Action::Scroll { dx, dy, .. } => {
+                // Up, down, left, right.
+                for (notches, up, down) in [(dy, 4, 5), (dx, 7, 6)] {
+                    let button = if notches > 0 { up } else { down };
+                    for _ in 0..notches.unsigned_abs() {
+                        self.fake(BUTTON_PRESS_EVENT, button, 0, 0)?;
+                        self.fake(BUTTON_RELEASE_EVENT, button, 0, 0)?;
+                    }
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn flush(&self) -> Result<(), PlatformError> {
+        self.conn
+            .flush()
+            .map_err(|e| PlatformError::Other(format!("XTest injection failed: {e}")))
+    }
+}
+
+/// The X button number of `button`: 1 to 3 for left, middle, and right,
+/// and 8 and 9 for the thumb buttons. Further buttons follow from 10, past
+/// the wheel buttons 4 to 7.
+fn button_number(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Right => 3,
+        MouseButton::Back => 8,
+        MouseButton::Forward => 9,
+        MouseButton::Other(n) => n.saturating_add(5),
+    }
+}
+
+/// A normalized coordinate, 0 to 65535, in pixels across `extent`.
+fn denormalize(v: i32, extent: u16) -> i32 {
+    let max = i64::from(extent.saturating_sub(1));
+    (i64::from(v.clamp(0, 65535)) * max / 65535) as i32
+}
+
+// ---------------------------------------------------------------------------
+// ActionExecutor trait impl
+// ---------------------------------------------------------------------------
+
+impl ActionExecutor for LinuxXTestExecutor {
+    /// Executes an action.
+    ///
+    /// `Action::InjectKey` sends an XTest `FakeInput` key event.
+    /// `Action::InjectRepeat` sends a press; XTest has no repeat flag.
+    /// `Action::Chord` queues its key events and sends them in one flush.
+    /// Pointer actions send their motion or button events; pixel scrolling
+    /// is skipped.
+    /// `Action::SystemCommand` runs loginctl or systemctl.
+    /// All other variants are silently accepted as no-ops.
+    fn execute(&self, action: &Action) -> Result<(), PlatformError> {
+        match action {
+            Action::InjectKey { key, state } => {
+                self.fake_key(*key, *state)?;
+                self.flush()
+            }
+            Action::Chord { modifiers, key } => {
+                for (key, state) in chord_steps(*modifiers, *key) {
+                    self.fake_key(key, state)?;
+                }
+                self.flush()
+            }
+            Action::InjectRepeat { key } => self.execute(&Action::InjectKey {
+                key: *key,
+                state: KeyState::Down,
+            }),
+            Action::SystemCommand(cmd) => super::super::system::run(*cmd),
+            Action::Delay { ms } => {
+                log::debug!("executor: {ms}ms delay outside a sequence; ignored");
+                Ok(())
+            }
+            _ => {
+                if self.fake_pointer(action)? {
+                    self.flush()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "xtest"
+    }
+
+    /// Keys, the pointer, and system commands. XTest has no text path.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            inject_key: true,
+            pointer: true,
+            system_command: true,
+            ..Capabilities::default()
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buttons_skip_the_wheel_numbers() {
+        assert_eq!(button_number(MouseButton::Right), 3);
+        assert_eq!(button_number(MouseButton::Forward), 9);
+        assert_eq!(button_number(MouseButton::Other(5)), 10);
+    }
+
+    #[test]
+    fn normalized_coordinates_span_the_root_window() {
+        assert_eq!(denormalize(0, 1920), 0);
+        assert_eq!(denormalize(65535, 1920), 1919);
+        assert_eq!(denormalize(70000, 1920), 1919);
+    }
+}
@@ -0,0 +1,8 @@
+//! X11 platform backend: XTest (injection).
+//!
+//! Used for pure X11 sessions and as a late fallback under XWayland, where
+//! injected events reach X11 clients only.
+
+mod executor;
+
+pub use executor::LinuxXTestExecutor;
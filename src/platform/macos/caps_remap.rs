@@ -0,0 +1,310 @@
+//! CapsLock remapped at the HID layer, for `[macos] capslock_to`.
+//!
+//! macOS handles CapsLock below the event tap: the lock toggles in the HID
+//! system, and the tap only sees a FlagsChanged event, so a rule cannot
+//! remap it or hold it. The HID event system's `UserKeyMapping` property,
+//! which `hidutil property --set` writes, maps keys before all of that.
+//! `CapsLockRemap` adds an entry mapping CapsLock to an otherwise unused
+//! function key (F13-F20), which reaches the tap as an ordinary key that
+//! rules can use.
+//!
+//! The property is system state and outlives the process. The mapping that
+//! was in place before is saved when the entry is added and put back when
+//! `CapsLockRemap` is dropped. Other entries, such as those set with
+//! `hidutil` or by Keyboard settings, are kept; only a CapsLock entry is
+//! replaced while PC Unifier runs.
+
+use std::ffi::c_void;
+
+use super::focus::cfstring;
+use crate::platform::{KeyCode, PlatformError};
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+/// Keyboard usage page (0x07), shifted as `UserKeyMapping` expects.
+const KEYBOARD_PAGE: u64 = 0x7_0000_0000;
+
+/// HID usage of CapsLock on the keyboard page.
+const USAGE_CAPS_LOCK: u64 = 0x39;
+
+/// kCFNumberSInt64Type.
+const CF_NUMBER_SINT64: isize = 4;
+
+// ---------------------------------------------------------------------------
+// Raw FFI
+// ---------------------------------------------------------------------------
+
+type CFArrayRef = *const c_void;
+type CFDictionaryRef = *const c_void;
+type CFStringRef = *const c_void;
+type CFTypeRef = *const c_void;
+type IOHIDEventSystemClientRef = *const c_void;
+
+/// CFArrayCallBacks / CFDictionaryKeyCallBacks / CFDictionaryValueCallBacks;
+/// only their address is needed.
+#[repr(C)]
+struct CFCallBacks {
+    _private: [u8; 0],
+}
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOHIDEventSystemClientCreateSimpleClient(
+        allocator: *const c_void,
+    ) -> IOHIDEventSystemClientRef;
+    fn IOHIDEventSystemClientCopyProperty(
+        client: IOHIDEventSystemClientRef,
+        key: CFStringRef,
+    ) -> CFTypeRef;
+    fn IOHIDEventSystemClientSetProperty(
+        client: IOHIDEventSystemClientRef,
+        key: CFStringRef,
+        value: CFTypeRef,
+    ) -> bool;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFArrayCreate(
+        allocator: *const c_void,
+        values: *const *const c_void,
+        count: isize,
+        callbacks: *const CFCallBacks,
+    ) -> CFArrayRef;
+    fn CFArrayGetCount(array: CFArrayRef) -> isize;
+    fn CFArrayGetValueAtIndex(array: CFArrayRef, index: isize) -> *const c_void;
+    fn CFArrayGetTypeID() -> usize;
+    fn CFDictionaryCreate(
+        allocator: *const c_void,
+        keys: *const *const c_void,
+        values: *const *const c_void,
+        count: isize,
+        key_callbacks: *const CFCallBacks,
+        value_callbacks: *const CFCallBacks,
+    ) -> CFDictionaryRef;
+    fn CFDictionaryGetValue(dict: CFDictionaryRef, key: *const c_void) -> *const c_void;
+    fn CFNumberCreate(
+        allocator: *const c_void,
+        number_type: isize,
+        value: *const c_void,
+    ) -> CFTypeRef;
+    fn CFNumberGetValue(number: CFTypeRef, number_type: isize, value: *mut c_void) -> bool;
+    fn CFGetTypeID(cf: CFTypeRef) -> usize;
+    fn CFRelease(cf: *const c_void);
+
+    static kCFTypeArrayCallBacks: CFCallBacks;
+    static kCFTypeDictionaryKeyCallBacks: CFCallBacks;
+    static kCFTypeDictionaryValueCallBacks: CFCallBacks;
+}
+
+// ---------------------------------------------------------------------------
+// Mapping
+// ---------------------------------------------------------------------------
+
+/// One `UserKeyMapping` entry: source and destination, page and usage.
+type Entry = (u64, u64);
+
+/// The keyboard page usage of a key CapsLock can be mapped to.
+pub(super) fn target_usage(key: KeyCode) -> Option<u64> {
+    let usage = match key {
+        KeyCode::F13 => 0x68,
+        KeyCode::F14 => 0x69,
+        KeyCode::F15 => 0x6A,
+        KeyCode::F16 => 0x6B,
+        KeyCode::F17 => 0x6C,
+        KeyCode::F18 => 0x6D,
+        KeyCode::F19 => 0x6E,
+        KeyCode::F20 => 0x6F,
+        _ => return None,
+    };
+    Some(KEYBOARD_PAGE | usage)
+}
+
+/// `current` with CapsLock mapped to `target`, replacing any CapsLock entry.
+fn with_caps_lock(current: &[Entry], target: u64) -> Vec<Entry> {
+    let caps_lock = KEYBOARD_PAGE | USAGE_CAPS_LOCK;
+    current
+        .iter()
+        .copied()
+        .filter(|&(src, _)| src != caps_lock)
+        .chain([(caps_lock, target)])
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Remap guard
+// ---------------------------------------------------------------------------
+
+/// CapsLock mapped at the HID layer until dropped.
+pub(super) struct CapsLockRemap {
+    client: IOHIDEventSystemClientRef,
+    key: CFStringRef,
+    /// The mapping before ours, restored on drop.
+    previous: Vec<Entry>,
+}
+
+// SAFETY: the client and key are only used from `new` and `drop`, never
+// concurrently, and IOHIDEventSystemClient calls are thread-safe.
+unsafe impl Send for CapsLockRemap {}
+
+impl CapsLockRemap {
+    /// Map CapsLock to `target`, which must be one of F13-F20.
+    pub(super) fn new(target: KeyCode) -> Result<Self, PlatformError> {
+        let usage = target_usage(target).ok_or_else(|| {
+            PlatformError::Other(format!(
+                "capture: CapsLock cannot be remapped to {target:?} at the HID layer"
+            ))
+        })?;
+        unsafe {
+            let client = IOHIDEventSystemClientCreateSimpleClient(std::ptr::null());
+            if client.is_null() {
+                return Err(PlatformError::Unavailable(
+                    "capture: cannot open the HID event system to remap CapsLock".into(),
+                ));
+            }
+            let key = cfstring(b"UserKeyMapping\0");
+            let mut remap = Self {
+                client,
+                key,
+                previous: Vec::new(),
+            };
+            remap.previous = remap.read();
+            if !remap.write(&with_caps_lock(&remap.previous, usage)) {
+                return Err(PlatformError::Other(
+                    "capture: the HID event system refused the CapsLock mapping".into(),
+                ));
+            }
+            log::info!("capture: CapsLock mapped to {target:?} at the HID layer");
+            Ok(remap)
+        }
+    }
+
+    /// The current `UserKeyMapping` entries.
+    unsafe fn read(&self) -> Vec<Entry> {
+        let array = IOHIDEventSystemClientCopyProperty(self.client, self.key);
+        if array.is_null() {
+            return Vec::new();
+        }
+        let mut entries = Vec::new();
+        if CFGetTypeID(array) == CFArrayGetTypeID() {
+            let src_key = cfstring(b"HIDKeyboardModifierMappingSrc\0");
+            let dst_key = cfstring(b"HIDKeyboardModifierMappingDst\0");
+            for i in 0..CFArrayGetCount(array) {
+                let entry = CFArrayGetValueAtIndex(array, i);
+                let src = number(CFDictionaryGetValue(entry, src_key));
+                let dst = number(CFDictionaryGetValue(entry, dst_key));
+                if let (Some(src), Some(dst)) = (src, dst) {
+                    entries.push((src, dst));
+                }
+            }
+            CFRelease(src_key);
+            CFRelease(dst_key);
+        }
+        CFRelease(array);
+        entries
+    }
+
+    /// Replace `UserKeyMapping` with `entries`.
+    unsafe fn write(&self, entries: &[Entry]) -> bool {
+        let src_key = cfstring(b"HIDKeyboardModifierMappingSrc\0");
+        let dst_key = cfstring(b"HIDKeyboardModifierMappingDst\0");
+        let dicts: Vec<CFDictionaryRef> = entries
+            .iter()
+            .map(|&(src, dst)| {
+                let keys = [src_key, dst_key];
+                let values = [cf_number(src), cf_number(dst)];
+                let dict = CFDictionaryCreate(
+                    std::ptr::null(),
+                    keys.as_ptr(),
+                    values.as_ptr(),
+                    2,
+                    std::ptr::addr_of!(kCFTypeDictionaryKeyCallBacks),
+                    std::ptr::addr_of!(kCFTypeDictionaryValueCallBacks),
+                );
+                for value in values {
+                    CFRelease(value);
+                }
+                dict
+            })
+            .collect();
+        let array = CFArrayCreate(
+            std::ptr::null(),
+            dicts.as_ptr(),
+            dicts.len() as isize,
+            std::ptr::addr_of!(kCFTypeArrayCallBacks),
+        );
+        let ok = IOHIDEventSystemClientSetProperty(self.client, self.key, array);
+        for dict in dicts {
+            CFRelease(dict);
+        }
+        CFRelease(array);
+        CFRelease(src_key);
+        CFRelease(dst_key);
+        ok
+    }
+}
+
+impl Drop for CapsLockRemap {
+    fn drop(&mut self) {
+        unsafe {
+            if self.write(&self.previous) {
+                log::info!("capture: CapsLock mapping restored");
+            } else {
+                log::error!(
+                    "capture: cannot restore the CapsLock mapping; reset it with \
+                     hidutil property --set '{{\"UserKeyMapping\":[]}}'"
+                );
+            }
+            CFRelease(self.key);
+            CFRelease(self.client);
+        }
+    }
+}
+
+unsafe fn cf_number(value: u64) -> CFTypeRef {
+    let value = value as i64;
+    CFNumberCreate(
+        std::ptr::null(),
+        CF_NUMBER_SINT64,
+        (&value as *const i64).cast(),
+    )
+}
+
+unsafe fn number(value: CFTypeRef) -> Option<u64> {
+    let mut n: i64 = 0;
+    (!value.is_null() && CFNumberGetValue(value, CF_NUMBER_SINT64, (&mut n as *mut i64).cast()))
+        .then_some(n as u64)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPS_LOCK: u64 = 0x7_0000_0039;
+
+    #[test]
+    fn only_unused_function_keys_are_targets() {
+        assert_eq!(target_usage(KeyCode::F18), Some(0x7_0000_006D));
+        assert_eq!(target_usage(KeyCode::F13), Some(0x7_0000_0068));
+        assert_eq!(target_usage(KeyCode::Escape), None);
+        assert_eq!(target_usage(KeyCode::F12), None);
+    }
+
+    #[test]
+    fn caps_lock_entry_is_added_and_others_kept() {
+        let f18 = 0x7_0000_006D;
+        // Right Option to right Command, set by someone else.
+        let other = (0x7_0000_00E6, 0x7_0000_00E7);
+        assert_eq!(with_caps_lock(&[], f18), vec![(CAPS_LOCK, f18)]);
+        assert_eq!(
+            with_caps_lock(&[other, (CAPS_LOCK, 0x7_0000_0029)], f18),
+            vec![other, (CAPS_LOCK, f18)]
+        );
+    }
+}
@@ -6,25 +6,71 @@
 //!
 //! Required permissions: Accessibility must be granted in
 //!   System Settings > Privacy & Security > Accessibility.
-//! `AXIsProcessTrusted()` is called first; if it returns false the call fails
-//! with `PlatformError::PermissionDenied` before any tap is created.
+//! `permission::ensure()` is called first. If permission is missing it shows
+//! the system dialog and waits for the grant, unless prompting was turned off
+//! with `set_permission_prompt(false)`, in which case the call fails with
+//! `PlatformError::PermissionDenied` before any tap is created.
 //!
 //! Memory ownership:
 //!   The background thread owns the tap port (CFMachPortRef), the initial
 //!   run loop source, and the callback state (TapState). All three are
 //!   released after `CFRunLoopRun` returns (i.e. after `stop()` completes).
 //!
+//! Permission loss: Accessibility can be revoked while the tap runs, which
+//! silently stops it. A run loop timer re-checks `permission::is_trusted()`; on
+//! loss the tap is disabled and the loss reported loudly, and on restore the
+//! tap is enabled again (see `PermissionWatch`).
+//!
+//! Secure input: the same timer checks for Secure Event Input, during which
+//! the tap sees no keystrokes (see `secure_input`). Both conditions suspend
+//! rules through the engine's `Suspension`, so status output says why rules
+//! are off.
+//!
+//! Pointer: with a callback set through `set_pointer_callback`, the tap also
+//! receives mouse events, which are reported and always passed through (see
+//! `mouse`). Without one the mask stays keyboard-only.
+//!
+//! Keyboard identity: an IOHIDManager on the same run loop tracks connected
+//! keyboards, and each event is attributed to one where possible (see
+//! `devices`).
+//!
+//! Listen-only: while the engine's `ListenOnly` request is set (rules
+//! paused), the tap is created with kCGEventTapOptionListenOnly instead, so
+//! keys reach applications directly and are reported as observed. A tap's
+//! options are fixed at creation, so the focus timer recreates the tap when
+//! the request changes (see `sync_tap_mode`).
+//!
+//! Window context: a run loop timer polls the focused application and window
+//! title (see `focus`), and the cached result is stamped onto every event.
+//! With a callback set through `set_focus_callback`, each change is also
+//! reported as it is found.
+//!
 //! Keycode asymmetry: F13/F14/F15 share vkcodes with PrintScreen/ScrollLock/Pause;
-//! capture always yields F13/F14/F15. See `docs/platform-macos.md` for details.
+//! capture yields F13/F14/F15 unless `[macos] fkeys_as_system_keys` asks for
+//! the other names. See `docs/platform-macos.md` for details.
 
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::ffi::c_void;
 use std::sync::mpsc;
 use std::thread::{self, JoinHandle};
-
-use super::keycodes::vkcode_to_keycode;
+use std::time::{Duration, Instant};
+
+use super::caps_remap::CapsLockRemap;
+use super::devices::HidKeyboards;
+use super::focus::FocusTracker;
+use super::keycodes::{
+    as_system_key, side_bits, vkcode_to_keycode, DEVICE_SIDE_MASK, FLAG_MASK_ALT,
+    FLAG_MASK_COMMAND, FLAG_MASK_CTRL, FLAG_MASK_SHIFT, MODIFIER_FLAGS, MODIFIER_SIDES,
+};
+use super::media::{self, CG_EVENT_SYSTEM_DEFINED};
+use super::mouse;
+use super::permission;
+use super::secure_input::{self, SecureInputChange, SecureInputWatch};
 use crate::platform::{
-    InputCapture as InputCaptureTrait, InputEvent as PlatformInputEvent, KeyCode, KeyState,
-    Modifiers, PlatformError, WindowContext,
+    DeviceId, Heartbeat, HeldSides, InputCapture as InputCaptureTrait,
+    InputEvent as PlatformInputEvent, KeyCode, KeyState, ListenOnly, Modifiers, PlatformError,
+    PointerEvent, Suspension, WindowContext,
 };
 
 // ---------------------------------------------------------------------------
@@ -40,24 +86,53 @@ const CG_EVENT_KEY_UP: u32 = 11;
 /// CGEventType value for modifier-key state change events (Cmd, Ctrl, Shift, Option).
 const CG_EVENT_FLAGS_CHANGED: u32 = 12;
 
-/// Event mask: KeyDown | KeyUp | FlagsChanged.
-/// FlagsChanged is required so modifier key presses update `held_keys` in the
-/// rule engine, enabling hotkey chord detection (e.g. Command+T).
-const EVENT_MASK: u64 =
-    (1u64 << CG_EVENT_KEY_DOWN) | (1u64 << CG_EVENT_KEY_UP) | (1u64 << CG_EVENT_FLAGS_CHANGED);
-
-/// CGEventFlags bitmask for the Command modifier.
-const FLAG_MASK_COMMAND: u64 = 0x0010_0000;
-/// CGEventFlags bitmask for the Shift modifier.
-const FLAG_MASK_SHIFT: u64 = 0x0002_0000;
-/// CGEventFlags bitmask for the Option/Alt modifier.
-const FLAG_MASK_ALT: u64 = 0x0008_0000;
-/// CGEventFlags bitmask for the Control modifier.
-const FLAG_MASK_CTRL: u64 = 0x0004_0000;
+/// kCGEventTapDisabledByTimeout: the tap callback was too slow and the OS
+/// disabled the tap. Delivered regardless of the event mask.
+const CG_EVENT_TAP_DISABLED_BY_TIMEOUT: u32 = 0xFFFF_FFFE;
+
+/// kCGEventTapDisabledByUserInput: the OS disabled the tap, e.g. while a
+/// secure input context was active. Delivered regardless of the event mask.
+const CG_EVENT_TAP_DISABLED_BY_USER_INPUT: u32 = 0xFFFF_FFFF;
+
+/// Tap disables within `TAP_DISABLE_WINDOW` that are reported as an error
+/// rather than a warning.
+const TAP_DISABLE_LIMIT: usize = 3;
+
+/// Window over which tap disables are counted towards `TAP_DISABLE_LIMIT`.
+const TAP_DISABLE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Event mask: KeyDown | KeyUp | FlagsChanged | SystemDefined.
+/// FlagsChanged is the only event a modifier key produces on its own, so it is
+/// required for modifier-only rules and for hotkey chord detection
+/// (e.g. Command+T). Media keys arrive only as SystemDefined events.
+const EVENT_MASK: u64 = (1u64 << CG_EVENT_KEY_DOWN)
+    | (1u64 << CG_EVENT_KEY_UP)
+    | (1u64 << CG_EVENT_FLAGS_CHANGED)
+    | (1u64 << CG_EVENT_SYSTEM_DEFINED);
+
+/// Seconds between focused-window polls.
+///
+/// A key pressed within this long of switching apps may still carry the
+/// previous app's context.
+const FOCUS_POLL_INTERVAL: f64 = 0.2;
+
+/// Seconds between Accessibility permission and Secure Input checks.
+const STATUS_POLL_INTERVAL: f64 = 1.0;
+
+/// Suspension reason while Accessibility permission is missing.
+const PERMISSION_REVOKED_REASON: &str = "accessibility permission revoked";
 
 /// kCGKeyboardEventKeycode: CGEventField index for the virtual key code.
 const CG_KEYBOARD_EVENT_KEYCODE: u32 = 9;
 
+/// kCGKeyboardEventAutorepeat: CGEventField index, non-zero on a KeyDown
+/// generated by autorepeat.
+const CG_KEYBOARD_EVENT_AUTOREPEAT: u32 = 8;
+
+/// kCGKeyboardEventKeyboardType: CGEventField index for the keyboard's
+/// layout type (ANSI, ISO, JIS).
+const CG_KEYBOARD_EVENT_KEYBOARD_TYPE: u32 = 10;
+
 /// kCGHIDEventTap: tap at the HID level, before event dispatch.
 const CG_HID_EVENT_TAP: u32 = 0;
 
@@ -67,6 +142,10 @@ const CG_HEAD_INSERT_EVENT_TAP: u32 = 0;
 /// kCGEventTapOptionDefault: active tap; the callback may modify or suppress events.
 const CG_EVENT_TAP_OPTION_DEFAULT: u32 = 0;
 
+/// kCGEventTapOptionListenOnly: passive tap; events pass whatever the
+/// callback returns.
+const CG_EVENT_TAP_OPTION_LISTEN_ONLY: u32 = 1;
+
 // ---------------------------------------------------------------------------
 // Raw FFI types and declarations
 // ---------------------------------------------------------------------------
@@ -74,10 +153,24 @@ const CG_EVENT_TAP_OPTION_DEFAULT: u32 = 0;
 type CFMachPortRef = *mut c_void;
 type CFRunLoopRef = *mut c_void;
 type CFRunLoopSourceRef = *mut c_void;
+type CFRunLoopTimerRef = *mut c_void;
 type CFStringRef = *const c_void;
 type CGEventRef = *mut c_void;
 type CGEventTapProxy = *mut c_void;
 
+/// Signature required by CFRunLoopTimerCreate for the timer callback.
+type CFRunLoopTimerCallBack = unsafe extern "C" fn(timer: CFRunLoopTimerRef, info: *mut c_void);
+
+/// CFRunLoopTimerContext; only `info` is used.
+#[repr(C)]
+struct CFRunLoopTimerContext {
+    version: isize,
+    info: *mut c_void,
+    retain: *const c_void,
+    release: *const c_void,
+    copy_description: *const c_void,
+}
+
 /// Signature required by CGEventTapCreate for the C callback.
 type CGEventTapCallBack = unsafe extern "C" fn(
     proxy: CGEventTapProxy,
@@ -88,9 +181,6 @@ type CGEventTapCallBack = unsafe extern "C" fn(
 
 #[link(name = "ApplicationServices", kind = "framework")]
 extern "C" {
-    /// Returns true if this process has been granted Accessibility permission.
-    fn AXIsProcessTrusted() -> bool;
-
     /// Creates an event tap; returns null on permission failure or system error.
     fn CGEventTapCreate(
         tap: u32,
@@ -123,9 +213,32 @@ extern "C" {
     /// Returns the CFRunLoop for the calling thread.
     fn CFRunLoopGetCurrent() -> CFRunLoopRef;
 
+    /// Invalidates a CFMachPort, removing its run loop source.
+    fn CFMachPortInvalidate(port: CFMachPortRef);
+
     /// Adds a source to a run loop for the given mode.
     fn CFRunLoopAddSource(rl: CFRunLoopRef, source: CFRunLoopSourceRef, mode: CFStringRef);
 
+    /// Current time as a CFAbsoluteTime.
+    fn CFAbsoluteTimeGetCurrent() -> f64;
+
+    /// Creates a repeating run loop timer.
+    fn CFRunLoopTimerCreate(
+        allocator: *mut c_void,
+        fire_date: f64,
+        interval: f64,
+        flags: usize,
+        order: isize,
+        callout: CFRunLoopTimerCallBack,
+        context: *mut CFRunLoopTimerContext,
+    ) -> CFRunLoopTimerRef;
+
+    /// Adds a timer to a run loop for the given mode.
+    fn CFRunLoopAddTimer(rl: CFRunLoopRef, timer: CFRunLoopTimerRef, mode: CFStringRef);
+
+    /// Stops a timer from firing again.
+    fn CFRunLoopTimerInvalidate(timer: CFRunLoopTimerRef);
+
     /// Runs the current thread's run loop until CFRunLoopStop is called.
     fn CFRunLoopRun();
 
@@ -174,6 +287,141 @@ unsafe impl Send for SendableStatePtr {}
 /// returns.
 struct TapState {
     callback: Box<dyn Fn(PlatformInputEvent) + Send>,
+    /// Receives pointer events, if anyone asked for them.
+    pointer: Option<Box<dyn Fn(PointerEvent) + Send>>,
+    /// CGEventFlags of the last FlagsChanged event, diffed against the next
+    /// one to find which modifiers went down or up. Only the run loop thread
+    /// touches it.
+    flags: Cell<u64>,
+    /// Focused app and window, refreshed by the focus timer and stamped onto
+    /// every event. Only the run loop thread touches it.
+    focus: RefCell<FocusTracker>,
+    /// Connected keyboards, for attributing events. Set on the run loop
+    /// thread before the tap is enabled; `None` if IOKit is unavailable.
+    devices: Option<HidKeyboards>,
+    /// The tap itself, so the callback can re-enable it. Set before the tap
+    /// is enabled, and replaced when the tap is recreated.
+    tap: Cell<CFMachPortRef>,
+    /// Event mask the tap was created with, reused when it is recreated.
+    mask: u64,
+    /// Whether the tap should listen only, as requested by the engine.
+    listen_only: ListenOnly,
+    /// Whether the current tap is listen-only.
+    listening: Cell<bool>,
+    /// Set after a failed tap recreation was reported, so the retry on
+    /// every timer tick does not log again.
+    mode_switch_failed: Cell<bool>,
+    /// Recent tap disables, for deciding how loudly to report the next one.
+    disables: RefCell<TapDisables>,
+    /// Whether Accessibility permission is currently granted.
+    permission: RefCell<PermissionWatch>,
+    /// Whether Secure Event Input is on.
+    secure_input: RefCell<SecureInputWatch>,
+    /// Suspension reason while Secure Event Input is on, naming its holder.
+    secure_input_reason: RefCell<Option<String>>,
+    /// Suspends rules in the engine while capture is degraded.
+    suspension: Suspension,
+    /// Beaten by the focus timer while the run loop runs.
+    heartbeat: Heartbeat,
+    /// Report F13-F15 as PrintScreen, ScrollLock, and Pause.
+    system_fkeys: bool,
+}
+
+// ---------------------------------------------------------------------------
+// Permission watch
+// ---------------------------------------------------------------------------
+
+/// A change in Accessibility permission seen by `PermissionWatch::check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PermissionChange {
+    /// Permission was revoked; capture is degraded until it returns.
+    Lost,
+    /// Permission was granted again after `PermissionChange::Lost`.
+    Restored {
+        /// How long capture was degraded.
+        after: Duration,
+    },
+}
+
+/// Tracks Accessibility permission across periodic checks.
+///
+/// Only transitions are reported, so a permission that stays revoked logs
+/// once rather than on every check.
+#[derive(Debug)]
+struct PermissionWatch {
+    /// When permission was found missing, or `None` while it is granted.
+    lost_at: Option<Instant>,
+}
+
+impl PermissionWatch {
+    /// Start in the granted state; `start()` refuses to run otherwise.
+    fn new() -> Self {
+        Self { lost_at: None }
+    }
+
+    /// Whether capture is degraded because permission is missing.
+    fn is_degraded(&self) -> bool {
+        self.lost_at.is_some()
+    }
+
+    /// Run `trusted` and report whether permission changed since the last
+    /// check.
+    fn check(&mut self, now: Instant, trusted: impl FnOnce() -> bool) -> Option<PermissionChange> {
+        match (trusted(), self.lost_at) {
+            (false, None) => {
+                self.lost_at = Some(now);
+                Some(PermissionChange::Lost)
+            }
+            (true, Some(lost_at)) => {
+                self.lost_at = None;
+                Some(PermissionChange::Restored {
+                    after: now.duration_since(lost_at),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tap disable tracking
+// ---------------------------------------------------------------------------
+
+/// How a tap disable is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DisableSeverity {
+    /// Occasional: the tap is re-enabled and a warning logged.
+    Warn,
+    /// `TAP_DISABLE_LIMIT` or more within `TAP_DISABLE_WINDOW`: something
+    /// keeps stalling the callback or blocking the tap.
+    Repeated,
+}
+
+/// Counts tap disables, overall and within a sliding window.
+#[derive(Debug, Default)]
+struct TapDisables {
+    total: u64,
+    recent: VecDeque<Instant>,
+}
+
+impl TapDisables {
+    /// Record a disable at `now` and decide how to report it.
+    fn record(&mut self, now: Instant) -> DisableSeverity {
+        self.total += 1;
+        while self
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= TAP_DISABLE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+        if self.recent.len() >= TAP_DISABLE_LIMIT {
+            DisableSeverity::Repeated
+        } else {
+            DisableSeverity::Warn
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -184,6 +432,21 @@ struct TapState {
 pub struct MacOSCapture {
     run_loop: Option<SendableRunLoop>,
     thread: Option<JoinHandle<()>>,
+    suspension: Suspension,
+    /// Whether `start` may show the Accessibility permission dialog.
+    permission_prompt: bool,
+    /// Taken by `start`; mouse events are only tapped when set.
+    pointer_callback: Option<Box<dyn Fn(PointerEvent) + Send>>,
+    /// Taken by `start` and handed to the focus tracker.
+    focus_callback: Option<Box<dyn Fn(WindowContext) + Send>>,
+    listen_only: ListenOnly,
+    heartbeat: Heartbeat,
+    /// `[macos] capslock_to`, applied by `start`.
+    caps_lock_target: Option<KeyCode>,
+    /// Held from `start` to `stop`; dropping it restores the mapping.
+    caps_lock_remap: Option<CapsLockRemap>,
+    /// `[macos] fkeys_as_system_keys`.
+    system_fkeys: bool,
 }
 
 impl MacOSCapture {
@@ -191,6 +454,15 @@ impl MacOSCapture {
         Self {
             run_loop: None,
             thread: None,
+            suspension: Suspension::default(),
+            permission_prompt: true,
+            pointer_callback: None,
+            focus_callback: None,
+            listen_only: ListenOnly::default(),
+            heartbeat: Heartbeat::default(),
+            caps_lock_target: None,
+            caps_lock_remap: None,
+            system_fkeys: false,
         }
     }
 }
@@ -208,27 +480,50 @@ impl InputCaptureTrait for MacOSCapture {
             return Err(PlatformError::Other("capture is already running".into()));
         }
 
-        // Fail fast with a clear message rather than letting CGEventTapCreate
+        // Settle permission first rather than letting CGEventTapCreate
         // return null without explanation.
-        if !unsafe { AXIsProcessTrusted() } {
-            return Err(PlatformError::PermissionDenied(
-                "Accessibility permission required. \
-                 Grant it in System Settings > Privacy & Security > Accessibility."
-                    .into(),
-            ));
+        permission::ensure(self.permission_prompt)?;
+
+        if let Some(target) = self.caps_lock_target {
+            match CapsLockRemap::new(target) {
+                Ok(remap) => self.caps_lock_remap = Some(remap),
+                Err(e) => log::warn!("{e}; CapsLock keeps its usual behavior"),
+            }
         }
 
+        let pointer = self.pointer_callback.take();
+        let mask = if pointer.is_some() {
+            EVENT_MASK | mouse::EVENT_MASK
+        } else {
+            EVENT_MASK
+        };
+
         // Heap-allocate TapState so its address is stable for the tap lifetime.
-        let state_ptr = Box::into_raw(Box::new(TapState { callback }));
+        let state_ptr = Box::into_raw(Box::new(TapState {
+            callback,
+            pointer,
+            flags: Cell::new(0),
+            focus: RefCell::new(FocusTracker::new(self.focus_callback.take())),
+            devices: None,
+            tap: Cell::new(std::ptr::null_mut()),
+            mask,
+            listen_only: self.listen_only.clone(),
+            listening: Cell::new(self.listen_only.is_set()),
+            mode_switch_failed: Cell::new(false),
+            disables: RefCell::new(TapDisables::default()),
+            permission: RefCell::new(PermissionWatch::new()),
+            secure_input: RefCell::new(SecureInputWatch::default()),
+            secure_input_reason: RefCell::new(None),
+            suspension: self.suspension.clone(),
+            heartbeat: self.heartbeat.clone(),
+            system_fkeys: self.system_fkeys,
+        }));
 
         // Create the tap on the calling thread so errors surface synchronously.
         let tap_port = unsafe {
-            CGEventTapCreate(
-                CG_HID_EVENT_TAP,
-                CG_HEAD_INSERT_EVENT_TAP,
-                CG_EVENT_TAP_OPTION_DEFAULT,
-                EVENT_MASK,
-                event_tap_callback,
+            create_tap(
+                mask,
+                (*state_ptr).listening.get(),
                 state_ptr.cast::<c_void>(),
             )
         };
@@ -242,6 +537,7 @@ impl InputCaptureTrait for MacOSCapture {
                     .into(),
             ));
         }
+        unsafe { (*state_ptr).tap.set(tap_port) };
 
         // Send pointers into the worker via channel so the spawn closure only captures
         // Send types (the channel). The worker receives and owns them on its thread.
@@ -267,8 +563,50 @@ impl InputCaptureTrait for MacOSCapture {
                 // The run loop now retains the source; release our reference.
                 CFRelease(source.cast::<c_void>());
 
+                // Poll focus on the same thread as the tap, so the callback
+                // reads the cache without locking.
+                (*state_ptr).focus.borrow_mut().poll();
+                let mut timer_context = CFRunLoopTimerContext {
+                    version: 0,
+                    info: state_ptr.cast::<c_void>(),
+                    retain: std::ptr::null(),
+                    release: std::ptr::null(),
+                    copy_description: std::ptr::null(),
+                };
+                let focus_timer = CFRunLoopTimerCreate(
+                    std::ptr::null_mut(),
+                    CFAbsoluteTimeGetCurrent() + FOCUS_POLL_INTERVAL,
+                    FOCUS_POLL_INTERVAL,
+                    0,
+                    0,
+                    focus_timer_callback,
+                    &mut timer_context,
+                );
+                CFRunLoopAddTimer(run_loop, focus_timer, kCFRunLoopDefaultMode);
+                // Secure input may already be on, e.g. when started from a
+                // Terminal with Secure Keyboard Entry enabled.
+                check_secure_input(&*state_ptr);
+                let status_timer = CFRunLoopTimerCreate(
+                    std::ptr::null_mut(),
+                    CFAbsoluteTimeGetCurrent() + STATUS_POLL_INTERVAL,
+                    STATUS_POLL_INTERVAL,
+                    0,
+                    0,
+                    status_timer_callback,
+                    &mut timer_context,
+                );
+                CFRunLoopAddTimer(run_loop, status_timer, kCFRunLoopDefaultMode);
+
+                (*state_ptr).devices = HidKeyboards::start(run_loop);
+
                 CGEventTapEnable(tap_port, true);
                 log::info!("capture: CGEventTap active");
+                match media::function_keys_are_standard() {
+                    Some(true) => {
+                        log::info!("capture: top row sends F1-F12; media keys need Fn held")
+                    }
+                    _ => log::info!("capture: top row sends media keys; F1-F12 need Fn held"),
+                }
 
                 // Notify the calling thread that the run loop is ready.
                 let _ = rl_tx.send(SendableRunLoop(run_loop));
@@ -279,8 +617,15 @@ impl InputCaptureTrait for MacOSCapture {
                 log::info!("capture: CFRunLoop exited");
 
                 // Disable the tap and release all owned resources.
+                CFRunLoopTimerInvalidate(focus_timer);
+                CFRelease(focus_timer.cast::<c_void>());
+                CFRunLoopTimerInvalidate(status_timer);
+                CFRelease(status_timer.cast::<c_void>());
+                // The tap may have been recreated since it was handed over.
+                let tap_port = (*state_ptr).tap.get();
                 CGEventTapEnable(tap_port, false);
                 CFRelease(tap_port.cast::<c_void>());
+                (*state_ptr).suspension.lift();
                 drop(Box::from_raw(state_ptr));
             }
         });
@@ -303,6 +648,38 @@ impl InputCaptureTrait for MacOSCapture {
         }
     }
 
+    fn set_suspension(&mut self, suspension: Suspension) {
+        self.suspension = suspension;
+    }
+
+    fn set_permission_prompt(&mut self, allowed: bool) {
+        self.permission_prompt = allowed;
+    }
+
+    fn set_pointer_callback(&mut self, callback: Box<dyn Fn(PointerEvent) + Send>) {
+        self.pointer_callback = Some(callback);
+    }
+
+    fn set_focus_callback(&mut self, callback: Box<dyn Fn(WindowContext) + Send>) {
+        self.focus_callback = Some(callback);
+    }
+
+    fn set_listen_only(&mut self, listen_only: ListenOnly) {
+        self.listen_only = listen_only;
+    }
+
+    fn set_heartbeat(&mut self, heartbeat: Heartbeat) {
+        self.heartbeat = heartbeat;
+    }
+
+    fn set_caps_lock_remap(&mut self, target: KeyCode) {
+        self.caps_lock_target = Some(target);
+    }
+
+    fn set_system_fkeys(&mut self, on: bool) {
+        self.system_fkeys = on;
+    }
+
     fn stop(&mut self) -> Result<(), PlatformError> {
         // Signal the run loop to exit; the background thread releases the tap.
         if let Some(SendableRunLoop(rl)) = self.run_loop.take() {
@@ -311,6 +688,7 @@ impl InputCaptureTrait for MacOSCapture {
         if let Some(t) = self.thread.take() {
             let _ = t.join();
         }
+        self.caps_lock_remap = None;
         Ok(())
     }
 }
@@ -325,22 +703,264 @@ impl Drop for MacOSCapture {
 // C callback
 // ---------------------------------------------------------------------------
 
-/// Returns the `KeyState` for a modifier key based on the current CGEventFlags.
+/// Diffs two CGEventFlags values into modifier Down/Up transitions.
 ///
-/// Returns `None` for non-modifier keys (the caller should pass those through).
-fn modifier_key_state(key: KeyCode, flags: u64) -> Option<KeyState> {
-    let mask = match key {
-        KeyCode::Meta => FLAG_MASK_COMMAND,
-        KeyCode::Shift => FLAG_MASK_SHIFT,
-        KeyCode::Alt => FLAG_MASK_ALT,
-        KeyCode::Ctrl => FLAG_MASK_CTRL,
-        _ => return None,
+/// A FlagsChanged event carries the full modifier state, not the key that
+/// changed, so the previous state is needed to tell a press from a release.
+/// Diffing also recovers when an event was missed: every modifier whose bit
+/// differs is reported, in `MODIFIER_FLAGS` order.
+///
+/// When the flags carry the device side bits, as events from a keyboard do,
+/// each side is diffed on its own: each key is reported as its `Left*` or
+/// `Right*` variant. Pressing one side while the
+/// other is held is reported too, though the generic mask does not change.
+/// Flags without side bits are diffed on the generic mask alone.
+fn flag_transitions(prev: u64, now: u64) -> Vec<(KeyCode, KeyState)> {
+    let state = |down: bool| if down { KeyState::Down } else { KeyState::Up };
+    let mut out = Vec::new();
+    for &(key, mask) in &MODIFIER_FLAGS {
+        if (prev | now) & side_bits(mask) == 0 {
+            if (prev ^ now) & mask != 0 {
+                out.push((key, state(now & mask != 0)));
+            }
+            continue;
+        }
+        // A side bit only counts while the generic mask agrees.
+        let held = |flags: u64, side: u64| flags & mask != 0 && flags & side != 0;
+        for &(sided, _, side) in MODIFIER_SIDES.iter().filter(|(_, m, _)| *m == mask) {
+            if held(prev, side) != held(now, side) {
+                out.push((sided, state(held(now, side))));
+            }
+        }
+    }
+    out
+}
+
+/// Maps CGEventFlags to the `Modifiers` snapshot.
+///
+/// Uses the same masks as `flag_transitions`, so the snapshot on a
+/// FlagsChanged event always agrees with the transition it reports. The
+/// device side bits fill in `sides` where the flags carry them; other bits
+/// (CapsLock, Fn, NumericPad) are ignored.
+fn modifiers_from_flags(flags: u64) -> Modifiers {
+    let sides = (flags & DEVICE_SIDE_MASK != 0).then(|| {
+        let held = |key: KeyCode| {
+            MODIFIER_SIDES
+                .iter()
+                .any(|&(k, mask, side)| k == key && flags & mask != 0 && flags & side != 0)
+        };
+        HeldSides {
+            left_ctrl: held(KeyCode::LeftCtrl),
+            right_ctrl: held(KeyCode::RightCtrl),
+            left_shift: held(KeyCode::LeftShift),
+            right_shift: held(KeyCode::RightShift),
+            left_alt: held(KeyCode::LeftAlt),
+            right_alt: held(KeyCode::RightAlt),
+            left_meta: held(KeyCode::LeftMeta),
+            right_meta: held(KeyCode::RightMeta),
+        }
+    });
+    Modifiers {
+        ctrl: flags & FLAG_MASK_CTRL != 0,
+        shift: flags & FLAG_MASK_SHIFT != 0,
+        alt: flags & FLAG_MASK_ALT != 0,
+        meta: flags & FLAG_MASK_COMMAND != 0,
+        sides,
+    }
+}
+
+/// Called on the run loop thread every `FOCUS_POLL_INTERVAL` to refresh the
+/// cached window context and follow the listen-only request. Also beats the
+/// heartbeat, since a stuck run loop stops this timer too.
+unsafe extern "C" fn focus_timer_callback(_timer: CFRunLoopTimerRef, info: *mut c_void) {
+    let state = &*(info as *const TapState);
+    state.heartbeat.beat();
+    state.focus.borrow_mut().poll();
+    sync_tap_mode(state, info);
+}
+
+/// Creates the event tap, active or listen-only.
+unsafe fn create_tap(mask: u64, listen_only: bool, state: *mut c_void) -> CFMachPortRef {
+    let options = if listen_only {
+        CG_EVENT_TAP_OPTION_LISTEN_ONLY
+    } else {
+        CG_EVENT_TAP_OPTION_DEFAULT
     };
-    Some(if flags & mask != 0 {
-        KeyState::Down
+    CGEventTapCreate(
+        CG_HID_EVENT_TAP,
+        CG_HEAD_INSERT_EVENT_TAP,
+        options,
+        mask,
+        event_tap_callback,
+        state,
+    )
+}
+
+/// Replace the tap with one of the other kind when the listen-only request
+/// no longer matches it.
+///
+/// The new tap is in the run loop before the old one is invalidated, and
+/// both happen within this callback, so no event is handled in between. A
+/// failed creation keeps the old tap and is retried on the next tick.
+unsafe fn sync_tap_mode(state: &TapState, info: *mut c_void) {
+    let wanted = state.listen_only.is_set();
+    if wanted == state.listening.get() {
+        return;
+    }
+    let tap = create_tap(state.mask, wanted, info);
+    if tap.is_null() {
+        if !state.mode_switch_failed.replace(true) {
+            log::warn!("capture: could not recreate the event tap; keeping the current one");
+        }
+        return;
+    }
+    state.mode_switch_failed.set(false);
+    let source = CFMachPortCreateRunLoopSource(std::ptr::null_mut(), tap, 0);
+    CFRunLoopAddSource(CFRunLoopGetCurrent(), source, kCFRunLoopDefaultMode);
+    CFRelease(source.cast::<c_void>());
+
+    let old = state.tap.replace(tap);
+    CGEventTapEnable(old, false);
+    CFMachPortInvalidate(old);
+    CFRelease(old.cast::<c_void>());
+    state.listening.set(wanted);
+    // A tap whose permission is gone stays off until it returns.
+    CGEventTapEnable(tap, !state.permission.borrow().is_degraded());
+    log::info!(
+        "capture: event tap {}",
+        if wanted {
+            "listening only while rules are paused"
+        } else {
+            "intercepting keys again"
+        }
+    );
+}
+
+/// Called on the run loop thread every `STATUS_POLL_INTERVAL`.
+unsafe extern "C" fn status_timer_callback(_timer: CFRunLoopTimerRef, info: *mut c_void) {
+    let state = &*(info as *const TapState);
+    check_permission(state);
+    check_secure_input(state);
+}
+
+/// Re-check Secure Event Input and suspend or resume rules on any change.
+///
+/// The tap is left enabled: macOS simply stops feeding it while secure input
+/// is on, and feeds it again once it ends.
+fn check_secure_input(state: &TapState) {
+    let change = state
+        .secure_input
+        .borrow_mut()
+        .check(Instant::now(), secure_input::is_enabled);
+    match change {
+        Some(SecureInputChange::Started) => {
+            let holder = secure_input::holder();
+            log::warn!(
+                "capture: secure input enabled by {}; keystrokes cannot be captured or \
+                 injected, rules paused until it ends",
+                holder.as_deref().unwrap_or("an unknown process")
+            );
+            *state.secure_input_reason.borrow_mut() =
+                Some(secure_input::suspension_reason(holder.as_deref()));
+        }
+        Some(SecureInputChange::Ended { after }) => {
+            log::info!(
+                "capture: secure input ended after {}s; rules resumed",
+                after.as_secs()
+            );
+            *state.secure_input_reason.borrow_mut() = None;
+        }
+        None => return,
+    }
+    sync_suspension(state);
+}
+
+/// Suspend rules for the most serious degradation in effect, or lift the
+/// suspension when there is none.
+fn sync_suspension(state: &TapState) {
+    if state.permission.borrow().is_degraded() {
+        state.suspension.suspend(PERMISSION_REVOKED_REASON);
+    } else if let Some(reason) = state.secure_input_reason.borrow().as_ref() {
+        state.suspension.suspend(reason.clone());
     } else {
-        KeyState::Up
-    })
+        state.suspension.lift();
+    }
+}
+
+/// Re-check Accessibility permission and act on any change.
+///
+/// On loss the tap is disabled, so keys reach applications untouched instead
+/// of going through a tap the OS no longer feeds, and the user is told
+/// through the log and a notification. On restore the tap is enabled again.
+/// Returns whether permission is currently granted.
+unsafe fn check_permission(state: &TapState) -> bool {
+    let change = state
+        .permission
+        .borrow_mut()
+        .check(Instant::now(), permission::is_trusted);
+    if let Some(change) = change {
+        match change {
+            PermissionChange::Lost => {
+                CGEventTapEnable(state.tap.get(), false);
+                state.flags.set(0);
+                log::error!(
+                    "capture: Accessibility permission was revoked; no rules apply until it is \
+                     granted again in System Settings > Privacy & Security > Accessibility"
+                );
+                if let Err(e) = super::system::notify(
+                    "Accessibility permission was revoked. \
+                     Key rules are off until it is granted again.",
+                ) {
+                    log::warn!("capture: could not show notification: {e}");
+                }
+            }
+            PermissionChange::Restored { after } => {
+                CGEventTapEnable(state.tap.get(), true);
+                log::warn!(
+                    "capture: Accessibility permission restored after {}s; rules apply again",
+                    after.as_secs()
+                );
+            }
+        }
+        sync_suspension(state);
+    }
+    !state.permission.borrow().is_degraded()
+}
+
+/// Turn the tap back on after the OS disabled it, and report the disable.
+///
+/// Without this the tap stays off and no key reaches the engine again. A
+/// disable caused by revoked permission is left to `check_permission`.
+unsafe fn reenable_tap(state: &TapState, event_type: u32) {
+    if !check_permission(state) {
+        return;
+    }
+    CGEventTapEnable(state.tap.get(), true);
+    let cause = if event_type == CG_EVENT_TAP_DISABLED_BY_TIMEOUT {
+        "callback timeout"
+    } else {
+        "user input"
+    };
+    let mut disables = state.disables.borrow_mut();
+    match disables.record(Instant::now()) {
+        DisableSeverity::Warn => log::warn!(
+            "capture: event tap disabled by {cause}; re-enabled ({} so far)",
+            disables.total
+        ),
+        DisableSeverity::Repeated => log::error!(
+            "capture: event tap disabled by {cause} {} times in {}s; re-enabled ({} so far). \
+             Keys may be missed while the tap is off.",
+            disables.recent.len(),
+            TAP_DISABLE_WINDOW.as_secs(),
+            disables.total
+        ),
+    }
+}
+
+/// The keyboard `event` came from, if it can be told.
+unsafe fn event_device(state: &TapState, event: CGEventRef) -> Option<DeviceId> {
+    let keyboard_type = CGEventGetIntegerValueField(event, CG_KEYBOARD_EVENT_KEYBOARD_TYPE);
+    state.devices.as_ref()?.attribute(keyboard_type)
 }
 
 /// Called by the OS on the run loop thread for each captured keyboard event.
@@ -348,10 +968,21 @@ fn modifier_key_state(key: KeyCode, flags: u64) -> Option<KeyState> {
 /// KeyDown / KeyUp: the original event is suppressed (returns null); the executor
 /// re-injects the processed version at kCGSessionEventTap.
 ///
-/// FlagsChanged (modifier keys): the callback is invoked so the rule engine can
-/// update held-key state for chord detection, but the original event is passed
-/// through unchanged. Re-injecting modifiers requires synthesising a proper
-/// FlagsChanged event, which is deferred to M11.
+/// FlagsChanged (modifier keys): the flags are diffed against the previous
+/// FlagsChanged event and each modifier transition is reported as a Down or
+/// Up. The original event is passed through unchanged. Re-injecting modifiers
+/// requires synthesising a proper FlagsChanged event, which is deferred to M11.
+/// A FlagsChanged event that changes no tracked modifier (e.g. CapsLock) is
+/// passed through without a callback.
+///
+/// SystemDefined (media keys): suppressed and reported like KeyDown / KeyUp.
+/// Other system-defined events, unmapped media keys, and media events the
+/// executor injected are passed through.
+///
+/// Listen-only tap: events are reported with `observed` set and never
+/// suppressed.
+///
+/// Tap-disabled notifications re-enable the tap (see `reenable_tap`).
 ///
 /// Unknown key codes and unhandled event types are passed through so the user
 /// is never locked out.
@@ -363,48 +994,111 @@ unsafe extern "C" fn event_tap_callback(
 ) -> CGEventRef {
     let state = &*(user_info as *const TapState);
 
-    let vkcode = match event_type {
-        CG_EVENT_KEY_DOWN | CG_EVENT_KEY_UP | CG_EVENT_FLAGS_CHANGED => {
-            CGEventGetIntegerValueField(event, CG_KEYBOARD_EVENT_KEYCODE) as u16
+    if matches!(
+        event_type,
+        CG_EVENT_TAP_DISABLED_BY_TIMEOUT | CG_EVENT_TAP_DISABLED_BY_USER_INPUT
+    ) {
+        reenable_tap(state, event_type);
+        return event;
+    }
+
+    if event_type == CG_EVENT_FLAGS_CHANGED {
+        let flags = CGEventGetFlags(event);
+        let device = event_device(state, event);
+        for (key, key_state) in flag_transitions(state.flags.replace(flags), flags) {
+            (state.callback)(PlatformInputEvent {
+                key,
+                state: key_state,
+                modifiers: modifiers_from_flags(flags),
+                window: state.focus.borrow().current().clone(),
+                timestamp: Instant::now(),
+                device: device.clone(),
+                observed: state.listening.get(),
+                is_repeat: false,
+                position: None,
+            });
+            log::debug!("capture: key={:?} state={:?}", key, key_state);
         }
-        _ => return event,
-    };
+        // Passed through so OS modifier state stays correct.
+        return event;
+    }
 
-    let Some(key) = vkcode_to_keycode(vkcode) else {
-        log::debug!("capture: unknown CGKeyCode {}", vkcode);
+    if mouse::is_pointer_event(event_type) {
+        // Observed only: pointer events are never suppressed.
+        if let Some(pointer) = &state.pointer {
+            if !media::is_injected(event) {
+                if let Some(pointer_event) = mouse::read(event_type, event) {
+                    pointer(pointer_event);
+                }
+            }
+        }
         return event;
-    };
+    }
+
+    if event_type == CG_EVENT_SYSTEM_DEFINED {
+        if media::is_injected(event) {
+            return event;
+        }
+        let Some((key, key_state)) = media::read(event) else {
+            return event;
+        };
+        (state.callback)(PlatformInputEvent {
+            key,
+            state: key_state,
+            modifiers: modifiers_from_flags(CGEventGetFlags(event)),
+            window: state.focus.borrow().current().clone(),
+            timestamp: Instant::now(),
+            device: event_device(state, event),
+            observed: state.listening.get(),
+            is_repeat: false,
+            position: None,
+        });
+        log::debug!("capture: key={:?} state={:?}", key, key_state);
+        return if state.listening.get() {
+            event
+        } else {
+            std::ptr::null_mut()
+        };
+    }
 
     let key_state = match event_type {
         CG_EVENT_KEY_DOWN => KeyState::Down,
         CG_EVENT_KEY_UP => KeyState::Up,
-        CG_EVENT_FLAGS_CHANGED => {
-            let flags = CGEventGetFlags(event);
-            match modifier_key_state(key, flags) {
-                Some(s) => s,
-                // Non-tracked FlagsChanged key (e.g. CapsLock): pass through.
-                None => return event,
-            }
-        }
         _ => return event,
     };
 
+    let vkcode = CGEventGetIntegerValueField(event, CG_KEYBOARD_EVENT_KEYCODE) as u16;
+    let Some(key) = vkcode_to_keycode(vkcode) else {
+        log::debug!("capture: unknown CGKeyCode {}", vkcode);
+        return event;
+    };
+    let key = if state.system_fkeys {
+        as_system_key(key)
+    } else {
+        key
+    };
+
     (state.callback)(PlatformInputEvent {
         key,
         state: key_state,
-        // Modifier tracking and window context are implemented in M11.
-        modifiers: Modifiers::default(),
-        window: WindowContext::default(),
+        modifiers: modifiers_from_flags(CGEventGetFlags(event)),
+        window: state.focus.borrow().current().clone(),
+        // CGEventGetTimestamp is in mach_absolute_time units, which std does
+        // not expose as an `Instant`.
+        timestamp: Instant::now(),
+        device: event_device(state, event),
+        observed: state.listening.get(),
+        is_repeat: key_state == KeyState::Down
+            && CGEventGetIntegerValueField(event, CG_KEYBOARD_EVENT_AUTOREPEAT) != 0,
+        position: None,
     });
     log::debug!("capture: key={:?} state={:?}", key, key_state);
 
-    // Modifier events are passed through so OS modifier state stays correct.
-    // All other events are suppressed; the executor re-injects the processed version.
-    if event_type == CG_EVENT_FLAGS_CHANGED {
-        event
-    } else {
-        std::ptr::null_mut()
+    if state.listening.get() {
+        return event;
     }
+    // Suppressed; the executor re-injects the processed version.
+    std::ptr::null_mut()
 }
 
 // ---------------------------------------------------------------------------
@@ -415,6 +1109,46 @@ unsafe extern "C" fn event_tap_callback(
 mod tests {
     use super::*;
 
+    #[test]
+    fn permission_loss_and_restore_are_reported_once() {
+        let start = Instant::now();
+        let mut watch = PermissionWatch::new();
+        assert_eq!(watch.check(start, || true), None);
+        assert!(!watch.is_degraded());
+
+        assert_eq!(watch.check(start, || false), Some(PermissionChange::Lost));
+        assert!(watch.is_degraded());
+        assert_eq!(watch.check(start + Duration::from_secs(2), || false), None);
+        assert!(watch.is_degraded());
+
+        assert_eq!(
+            watch.check(start + Duration::from_secs(10), || true),
+            Some(PermissionChange::Restored {
+                after: Duration::from_secs(10)
+            })
+        );
+        assert!(!watch.is_degraded());
+        assert_eq!(watch.check(start + Duration::from_secs(12), || true), None);
+    }
+
+    #[test]
+    fn permission_can_be_lost_again_after_restore() {
+        let start = Instant::now();
+        let mut watch = PermissionWatch::new();
+        watch.check(start, || false);
+        watch.check(start, || true);
+        assert_eq!(
+            watch.check(start + Duration::from_secs(5), || false),
+            Some(PermissionChange::Lost)
+        );
+        assert_eq!(
+            watch.check(start + Duration::from_secs(8), || true),
+            Some(PermissionChange::Restored {
+                after: Duration::from_secs(3)
+            })
+        );
+    }
+
     #[test]
     fn new_produces_idle_state() {
         let capture = MacOSCapture::new();
@@ -428,4 +1162,167 @@ mod tests {
         let mut capture = MacOSCapture::new();
         assert!(capture.stop().is_ok());
     }
+
+    #[test]
+    fn modifier_press_and_release_are_diffed_from_flags() {
+        assert_eq!(
+            flag_transitions(0, FLAG_MASK_SHIFT),
+            vec![(KeyCode::Shift, KeyState::Down)]
+        );
+        assert_eq!(
+            flag_transitions(FLAG_MASK_SHIFT, 0),
+            vec![(KeyCode::Shift, KeyState::Up)]
+        );
+    }
+
+    #[test]
+    fn unchanged_modifiers_are_not_reported() {
+        let held = FLAG_MASK_COMMAND;
+        assert_eq!(
+            flag_transitions(held, held | FLAG_MASK_ALT),
+            vec![(KeyCode::Alt, KeyState::Down)]
+        );
+        assert!(flag_transitions(held, held).is_empty());
+    }
+
+    #[test]
+    fn missed_event_reports_every_changed_modifier() {
+        assert_eq!(
+            flag_transitions(FLAG_MASK_CTRL, FLAG_MASK_SHIFT | FLAG_MASK_COMMAND),
+            vec![
+                (KeyCode::Ctrl, KeyState::Up),
+                (KeyCode::Shift, KeyState::Down),
+                (KeyCode::Meta, KeyState::Down),
+            ]
+        );
+    }
+
+    /// Bits outside the four modifier masks (CapsLock, device-specific side
+    /// bits, NumericPad) never produce a transition.
+    #[test]
+    fn non_modifier_flag_bits_are_ignored() {
+        const FLAG_MASK_ALPHA_SHIFT: u64 = 0x0001_0000;
+        const NX_DEVICELSHIFTKEYMASK: u64 = 0x0000_0002;
+        assert!(flag_transitions(0, FLAG_MASK_ALPHA_SHIFT | NX_DEVICELSHIFTKEYMASK).is_empty());
+    }
+
+    #[test]
+    fn side_bits_report_the_key_that_changed() {
+        use crate::platform::macos::keycodes::{DEVICE_LEFT_COMMAND, DEVICE_RIGHT_COMMAND};
+        let left = FLAG_MASK_COMMAND | DEVICE_LEFT_COMMAND;
+        let both = left | DEVICE_RIGHT_COMMAND;
+        assert_eq!(
+            flag_transitions(0, FLAG_MASK_COMMAND | DEVICE_RIGHT_COMMAND),
+            vec![(KeyCode::RightMeta, KeyState::Down)]
+        );
+        assert_eq!(
+            flag_transitions(left, both),
+            vec![(KeyCode::RightMeta, KeyState::Down)]
+        );
+        assert_eq!(
+            flag_transitions(both, FLAG_MASK_COMMAND | DEVICE_RIGHT_COMMAND),
+            vec![(KeyCode::LeftMeta, KeyState::Up)]
+        );
+        assert_eq!(
+            flag_transitions(left, 0),
+            vec![(KeyCode::LeftMeta, KeyState::Up)]
+        );
+    }
+
+    #[test]
+    fn modifiers_from_flags_maps_each_mask() {
+        assert_eq!(modifiers_from_flags(0), Modifiers::default());
+        assert_eq!(
+            modifiers_from_flags(FLAG_MASK_COMMAND | FLAG_MASK_SHIFT),
+            Modifiers {
+                shift: true,
+                meta: true,
+                ..Modifiers::default()
+            }
+        );
+        assert_eq!(
+            modifiers_from_flags(
+                FLAG_MASK_CTRL | FLAG_MASK_ALT | FLAG_MASK_SHIFT | FLAG_MASK_COMMAND
+            ),
+            Modifiers {
+                ctrl: true,
+                shift: true,
+                alt: true,
+                meta: true,
+                sides: None,
+            }
+        );
+    }
+
+    #[test]
+    fn modifiers_from_flags_ignores_secondary_fn() {
+        const FLAG_MASK_SECONDARY_FN: u64 = 0x0080_0000;
+        assert_eq!(
+            modifiers_from_flags(FLAG_MASK_SECONDARY_FN | FLAG_MASK_ALT),
+            Modifiers {
+                alt: true,
+                ..Modifiers::default()
+            }
+        );
+    }
+
+    /// The snapshot carried by a FlagsChanged event matches the transition.
+    #[test]
+    fn side_bits_fill_in_the_held_sides() {
+        use crate::platform::macos::keycodes::{DEVICE_LEFT_SHIFT, DEVICE_RIGHT_COMMAND};
+        let flags = FLAG_MASK_SHIFT | DEVICE_LEFT_SHIFT | FLAG_MASK_COMMAND | DEVICE_RIGHT_COMMAND;
+        assert_eq!(
+            modifiers_from_flags(flags).sides.map(HeldSides::keys),
+            Some(vec![KeyCode::LeftShift, KeyCode::RightMeta])
+        );
+        assert_eq!(modifiers_from_flags(FLAG_MASK_SHIFT).sides, None);
+    }
+
+    #[test]
+    fn snapshot_agrees_with_transition() {
+        let now = FLAG_MASK_CTRL;
+        assert_eq!(
+            flag_transitions(0, now),
+            vec![(KeyCode::Ctrl, KeyState::Down)]
+        );
+        assert!(modifiers_from_flags(now).ctrl);
+        assert_eq!(
+            flag_transitions(now, 0),
+            vec![(KeyCode::Ctrl, KeyState::Up)]
+        );
+        assert!(!modifiers_from_flags(0).ctrl);
+    }
+
+    #[test]
+    fn occasional_tap_disables_warn() {
+        let mut disables = TapDisables::default();
+        let start = Instant::now();
+        for n in 0..5 {
+            let at = start + TAP_DISABLE_WINDOW * n;
+            assert_eq!(disables.record(at), DisableSeverity::Warn);
+        }
+        assert_eq!(disables.total, 5);
+    }
+
+    #[test]
+    fn repeated_tap_disables_escalate_then_recover() {
+        let mut disables = TapDisables::default();
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        assert_eq!(disables.record(start), DisableSeverity::Warn);
+        assert_eq!(disables.record(start + second), DisableSeverity::Warn);
+        assert_eq!(
+            disables.record(start + second * 2),
+            DisableSeverity::Repeated
+        );
+        assert_eq!(
+            disables.record(start + second * 3),
+            DisableSeverity::Repeated
+        );
+
+        // Once the burst ages out of the window, disables warn again.
+        let later = start + second * 3 + TAP_DISABLE_WINDOW;
+        assert_eq!(disables.record(later), DisableSeverity::Warn);
+        assert_eq!(disables.total, 5);
+    }
 }
@@ -0,0 +1,49 @@
+//! Clipboard reads and writes on macOS, through `pbpaste` and `pbcopy`.
+//!
+//! `NSPasteboard` needs the Objective-C runtime, which nothing else here
+//! uses. `pbpaste` prints the general pasteboard's text, or nothing when it
+//! holds none; `pbcopy` puts the text on its stdin there.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::platform::{Clipboard, PlatformError};
+
+pub struct MacOSClipboard;
+
+impl Clipboard for MacOSClipboard {
+    fn read_text(&self) -> Result<Option<String>, PlatformError> {
+        let output = Command::new("pbpaste")
+            .args(["-Prefer", "txt"])
+            .output()
+            .map_err(|e| PlatformError::io("cannot run pbpaste", e))?;
+        if !output.status.success() {
+            return Err(PlatformError::Other(format!(
+                "pbpaste exited with {}",
+                output.status
+            )));
+        }
+        Ok(String::from_utf8(output.stdout)
+            .ok()
+            .filter(|t| !t.is_empty()))
+    }
+
+    fn write_text(&self, text: &str) -> Result<(), PlatformError> {
+        let mut child = Command::new("pbcopy")
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| PlatformError::io("cannot run pbcopy", e))?;
+        let written = child
+            .stdin
+            .take()
+            .map_or(Ok(()), |mut stdin| stdin.write_all(text.as_bytes()));
+        let status = child
+            .wait()
+            .map_err(|e| PlatformError::io("cannot wait on pbcopy", e))?;
+        written.map_err(|e| PlatformError::io("cannot write to pbcopy", e))?;
+        if !status.success() {
+            return Err(PlatformError::Other(format!("pbcopy exited with {status}")));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,534 @@
+//! Keyboard identity: which keyboard a captured event came from.
+//!
+//! A CGEvent does not say which device produced it. It only carries a
+//! keyboard type (`kCGKeyboardEventKeyboardType`), the layout class of the
+//! keyboard (ANSI, ISO, JIS) rather than its identity. So `HidKeyboards`
+//! also watches keyboards through an IOHIDManager, scheduled on the tap's run
+//! loop:
+//!   - Device add and remove notifications keep the list of connected
+//!     keyboards current, so hotplugged keyboards are named correctly.
+//!   - Input value callbacks record which keyboard last reported a key. HID
+//!     values reach the process before the window server hands the same key
+//!     to the tap, so a tap event is attributed to the keyboard that reported
+//!     a key within `ATTRIBUTION_WINDOW` of it.
+//!
+//! Each attribution also records which keyboard reported that keyboard type,
+//! and events with no recent HID activity (media keys, or input values
+//! missing) fall back to that, and then to the only connected keyboard.
+//! Otherwise the device is unknown.
+//!
+//! Identity: built-in keyboards get the id `built-in` on every Mac, so one
+//! config works across machines; others get `vendor:product` in hex, such as
+//! `046d:c52b`.
+//!
+//! Permission: reading input values needs Input Monitoring permission, and
+//! opening the manager asks for it. Without it only the single-keyboard
+//! fallback attributes events.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::focus::{cfstring, string_from_cf};
+use crate::platform::DeviceId;
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+/// How long after a HID key report a tap event may be attributed to it.
+const ATTRIBUTION_WINDOW: Duration = Duration::from_millis(100);
+
+/// Id shared by all built-in keyboards.
+const BUILT_IN_ID: &str = "built-in";
+
+/// kHIDPage_GenericDesktop / kHIDUsage_GD_Keyboard: what the manager matches.
+const HID_PAGE_GENERIC_DESKTOP: i32 = 0x01;
+const HID_USAGE_KEYBOARD: i32 = 0x06;
+
+/// kHIDPage_KeyboardOrKeypad, and the range of real key usages in it
+/// (below 4 are error and rollover reports, above 0xE7 reserved).
+const HID_PAGE_KEYBOARD: u32 = 0x07;
+const HID_FIRST_KEY_USAGE: u32 = 0x04;
+const HID_LAST_KEY_USAGE: u32 = 0xE7;
+
+/// kIOHIDOptionsTypeNone: observe without seizing the devices.
+const IOHID_OPTIONS_NONE: u32 = 0;
+
+/// kIOReturnSuccess / kIOReturnNotPermitted.
+const IO_RETURN_SUCCESS: i32 = 0;
+const IO_RETURN_NOT_PERMITTED: i32 = 0xE000_02E2_u32 as i32;
+
+/// kCFNumberSInt32Type / kCFNumberSInt64Type.
+const CF_NUMBER_SINT32: isize = 3;
+const CF_NUMBER_SINT64: isize = 4;
+
+// ---------------------------------------------------------------------------
+// Raw FFI
+// ---------------------------------------------------------------------------
+
+type CFDictionaryRef = *const c_void;
+type CFRunLoopRef = *mut c_void;
+type CFStringRef = *const c_void;
+type CFTypeRef = *const c_void;
+type IOHIDDeviceRef = *mut c_void;
+type IOHIDElementRef = *mut c_void;
+type IOHIDManagerRef = *mut c_void;
+type IOHIDValueRef = *mut c_void;
+
+type IOHIDDeviceCallback = unsafe extern "C" fn(
+    context: *mut c_void,
+    result: i32,
+    sender: *mut c_void,
+    device: IOHIDDeviceRef,
+);
+type IOHIDValueCallback = unsafe extern "C" fn(
+    context: *mut c_void,
+    result: i32,
+    sender: *mut c_void,
+    value: IOHIDValueRef,
+);
+
+/// CFDictionaryKeyCallBacks / CFDictionaryValueCallBacks; only their address
+/// is needed.
+#[repr(C)]
+struct CFDictionaryCallBacks {
+    _private: [u8; 0],
+}
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOHIDManagerCreate(allocator: *const c_void, options: u32) -> IOHIDManagerRef;
+    fn IOHIDManagerSetDeviceMatching(manager: IOHIDManagerRef, matching: CFDictionaryRef);
+    fn IOHIDManagerRegisterDeviceMatchingCallback(
+        manager: IOHIDManagerRef,
+        callback: IOHIDDeviceCallback,
+        context: *mut c_void,
+    );
+    fn IOHIDManagerRegisterDeviceRemovalCallback(
+        manager: IOHIDManagerRef,
+        callback: IOHIDDeviceCallback,
+        context: *mut c_void,
+    );
+    fn IOHIDManagerRegisterInputValueCallback(
+        manager: IOHIDManagerRef,
+        callback: IOHIDValueCallback,
+        context: *mut c_void,
+    );
+    fn IOHIDManagerScheduleWithRunLoop(
+        manager: IOHIDManagerRef,
+        run_loop: CFRunLoopRef,
+        mode: CFStringRef,
+    );
+    fn IOHIDManagerUnscheduleFromRunLoop(
+        manager: IOHIDManagerRef,
+        run_loop: CFRunLoopRef,
+        mode: CFStringRef,
+    );
+    fn IOHIDManagerOpen(manager: IOHIDManagerRef, options: u32) -> i32;
+    fn IOHIDManagerClose(manager: IOHIDManagerRef, options: u32) -> i32;
+    fn IOHIDDeviceGetProperty(device: IOHIDDeviceRef, key: CFStringRef) -> CFTypeRef;
+    fn IOHIDValueGetElement(value: IOHIDValueRef) -> IOHIDElementRef;
+    fn IOHIDElementGetDevice(element: IOHIDElementRef) -> IOHIDDeviceRef;
+    fn IOHIDElementGetUsagePage(element: IOHIDElementRef) -> u32;
+    fn IOHIDElementGetUsage(element: IOHIDElementRef) -> u32;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFDictionaryCreate(
+        allocator: *const c_void,
+        keys: *const *const c_void,
+        values: *const *const c_void,
+        num_values: isize,
+        key_callbacks: *const CFDictionaryCallBacks,
+        value_callbacks: *const CFDictionaryCallBacks,
+    ) -> CFDictionaryRef;
+    fn CFNumberCreate(
+        allocator: *const c_void,
+        number_type: isize,
+        value: *const c_void,
+    ) -> CFTypeRef;
+    fn CFNumberGetValue(number: CFTypeRef, number_type: isize, value: *mut c_void) -> bool;
+    fn CFGetTypeID(cf: CFTypeRef) -> usize;
+    fn CFNumberGetTypeID() -> usize;
+    fn CFBooleanGetTypeID() -> usize;
+    fn CFStringGetTypeID() -> usize;
+    fn CFBooleanGetValue(boolean: CFTypeRef) -> bool;
+    fn CFRelease(cf: *const c_void);
+
+    static kCFTypeDictionaryKeyCallBacks: CFDictionaryCallBacks;
+    static kCFTypeDictionaryValueCallBacks: CFDictionaryCallBacks;
+    static kCFRunLoopDefaultMode: CFStringRef;
+}
+
+// ---------------------------------------------------------------------------
+// Device identity
+// ---------------------------------------------------------------------------
+
+/// What IOKit reports about one keyboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyboardInfo {
+    name: Option<String>,
+    vendor_id: u32,
+    product_id: u32,
+    built_in: bool,
+}
+
+impl KeyboardInfo {
+    fn device_id(&self) -> DeviceId {
+        let id = if self.built_in {
+            BUILT_IN_ID.to_owned()
+        } else {
+            format!("{:04x}:{:04x}", self.vendor_id, self.product_id)
+        };
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => format!("keyboard {id}"),
+        };
+        DeviceId {
+            id: Arc::from(id),
+            name: Arc::from(name),
+        }
+    }
+}
+
+/// Whether a keyboard is built in: the `Built-In` property where present,
+/// otherwise the internal transports laptops use.
+fn is_built_in(built_in: Option<bool>, transport: Option<&str>) -> bool {
+    built_in.unwrap_or(matches!(transport, Some("SPI" | "FIFO" | "ADB" | "I2C")))
+}
+
+// ---------------------------------------------------------------------------
+// Registry
+// ---------------------------------------------------------------------------
+
+/// Connected keyboards and the evidence for attributing events to them.
+/// Devices are keyed by their IOHIDDeviceRef address; one physical keyboard
+/// often has several HID interfaces, all mapping to the same `DeviceId`.
+#[derive(Debug, Default)]
+struct DeviceRegistry {
+    devices: HashMap<usize, DeviceId>,
+    /// The device that last reported a key, and when.
+    last_key: Option<(usize, Instant)>,
+    /// The device each keyboard type was last attributed to.
+    by_keyboard_type: HashMap<i64, usize>,
+}
+
+impl DeviceRegistry {
+    fn add(&mut self, handle: usize, info: &KeyboardInfo) {
+        let device = info.device_id();
+        log::info!(
+            "capture: keyboard connected: {} ({})",
+            device.name,
+            device.id
+        );
+        self.devices.insert(handle, device);
+    }
+
+    fn remove(&mut self, handle: usize) {
+        if let Some(device) = self.devices.remove(&handle) {
+            log::info!("capture: keyboard disconnected: {}", device.name);
+        }
+        self.by_keyboard_type.retain(|_, h| *h != handle);
+        if self.last_key.is_some_and(|(h, _)| h == handle) {
+            self.last_key = None;
+        }
+    }
+
+    fn key_reported(&mut self, handle: usize, at: Instant) {
+        if self.devices.contains_key(&handle) {
+            self.last_key = Some((handle, at));
+        }
+    }
+
+    /// The device a tap event of `keyboard_type` seen at `now` came from.
+    fn attribute(&mut self, keyboard_type: i64, now: Instant) -> Option<DeviceId> {
+        if let Some((handle, at)) = self.last_key {
+            if now.saturating_duration_since(at) <= ATTRIBUTION_WINDOW {
+                self.by_keyboard_type.insert(keyboard_type, handle);
+                return self.devices.get(&handle).cloned();
+            }
+        }
+        if let Some(device) = self
+            .by_keyboard_type
+            .get(&keyboard_type)
+            .and_then(|h| self.devices.get(h))
+        {
+            return Some(device.clone());
+        }
+        let mut distinct = self.devices.values();
+        let first = distinct.next()?;
+        distinct.all(|d| d == first).then(|| first.clone())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HID manager
+// ---------------------------------------------------------------------------
+
+/// An IOHIDManager watching keyboards on the capture run loop.
+///
+/// Created and dropped on the run loop thread; the callbacks run there too,
+/// so the registry needs no lock.
+pub(super) struct HidKeyboards {
+    manager: IOHIDManagerRef,
+    run_loop: CFRunLoopRef,
+    /// Boxed so the callbacks' context pointer stays valid.
+    registry: Box<RefCell<DeviceRegistry>>,
+}
+
+impl HidKeyboards {
+    /// Starts watching keyboards on `run_loop`, the current thread's run
+    /// loop. Returns `None` if the manager cannot be created.
+    pub(super) unsafe fn start(run_loop: CFRunLoopRef) -> Option<Self> {
+        let manager = IOHIDManagerCreate(std::ptr::null(), IOHID_OPTIONS_NONE);
+        if manager.is_null() {
+            log::warn!("capture: IOHIDManagerCreate failed; keyboards will not be identified");
+            return None;
+        }
+        let hid = Self {
+            manager,
+            run_loop,
+            registry: Box::default(),
+        };
+        let matching = keyboard_matching();
+        IOHIDManagerSetDeviceMatching(manager, matching);
+        if !matching.is_null() {
+            CFRelease(matching);
+        }
+        let context = (&*hid.registry as *const RefCell<DeviceRegistry>)
+            .cast_mut()
+            .cast::<c_void>();
+        IOHIDManagerRegisterDeviceMatchingCallback(manager, device_added, context);
+        IOHIDManagerRegisterDeviceRemovalCallback(manager, device_removed, context);
+        IOHIDManagerRegisterInputValueCallback(manager, input_value, context);
+        IOHIDManagerScheduleWithRunLoop(manager, run_loop, kCFRunLoopDefaultMode);
+        match IOHIDManagerOpen(manager, IOHID_OPTIONS_NONE) {
+            IO_RETURN_SUCCESS => {}
+            IO_RETURN_NOT_PERMITTED => log::warn!(
+                "capture: Input Monitoring permission missing; events are only attributed \
+                 to a keyboard while one is connected. Grant it in System Settings > \
+                 Privacy & Security > Input Monitoring."
+            ),
+            err => log::warn!(
+                "capture: IOHIDManagerOpen failed ({err:#x}); keyboards may not be identified"
+            ),
+        }
+        Some(hid)
+    }
+
+    /// The keyboard a tap event with `keyboard_type` came from, if known.
+    pub(super) fn attribute(&self, keyboard_type: i64) -> Option<DeviceId> {
+        self.registry
+            .borrow_mut()
+            .attribute(keyboard_type, Instant::now())
+    }
+}
+
+impl Drop for HidKeyboards {
+    fn drop(&mut self) {
+        unsafe {
+            IOHIDManagerUnscheduleFromRunLoop(self.manager, self.run_loop, kCFRunLoopDefaultMode);
+            IOHIDManagerClose(self.manager, IOHID_OPTIONS_NONE);
+            CFRelease(self.manager.cast_const());
+        }
+    }
+}
+
+/// Matching dictionary for keyboards: usage page 1, usage 6.
+unsafe fn keyboard_matching() -> CFDictionaryRef {
+    let page_key = cfstring(b"DeviceUsagePage\0");
+    let usage_key = cfstring(b"DeviceUsage\0");
+    let page = CFNumberCreate(
+        std::ptr::null(),
+        CF_NUMBER_SINT32,
+        (&HID_PAGE_GENERIC_DESKTOP as *const i32).cast(),
+    );
+    let usage = CFNumberCreate(
+        std::ptr::null(),
+        CF_NUMBER_SINT32,
+        (&HID_USAGE_KEYBOARD as *const i32).cast(),
+    );
+    let keys = [page_key, usage_key];
+    let values = [page, usage];
+    let matching = CFDictionaryCreate(
+        std::ptr::null(),
+        keys.as_ptr(),
+        values.as_ptr(),
+        2,
+        std::ptr::addr_of!(kCFTypeDictionaryKeyCallBacks),
+        std::ptr::addr_of!(kCFTypeDictionaryValueCallBacks),
+    );
+    for cf in keys.into_iter().chain(values) {
+        if !cf.is_null() {
+            CFRelease(cf);
+        }
+    }
+    matching
+}
+
+/// Reads the properties `KeyboardInfo` needs from a device.
+unsafe fn keyboard_info(device: IOHIDDeviceRef) -> KeyboardInfo {
+    let property = |name: &[u8]| {
+        let key = cfstring(name);
+        let value = IOHIDDeviceGetProperty(device, key);
+        CFRelease(key);
+        value
+    };
+    let string = |value: CFTypeRef| {
+        (!value.is_null() && CFGetTypeID(value) == CFStringGetTypeID())
+            .then(|| string_from_cf(value))
+            .flatten()
+    };
+    let number = |value: CFTypeRef| {
+        let mut n: i64 = 0;
+        (!value.is_null()
+            && CFGetTypeID(value) == CFNumberGetTypeID()
+            && CFNumberGetValue(value, CF_NUMBER_SINT64, (&mut n as *mut i64).cast()))
+        .then_some(n)
+    };
+    let built_in = property(b"Built-In\0");
+    let built_in = if built_in.is_null() {
+        None
+    } else if CFGetTypeID(built_in) == CFBooleanGetTypeID() {
+        Some(CFBooleanGetValue(built_in))
+    } else {
+        number(built_in).map(|n| n != 0)
+    };
+    let transport = string(property(b"Transport\0"));
+    KeyboardInfo {
+        name: string(property(b"Product\0")),
+        vendor_id: number(property(b"VendorID\0")).unwrap_or(0) as u32,
+        product_id: number(property(b"ProductID\0")).unwrap_or(0) as u32,
+        built_in: is_built_in(built_in, transport.as_deref()),
+    }
+}
+
+unsafe extern "C" fn device_added(
+    context: *mut c_void,
+    _result: i32,
+    _sender: *mut c_void,
+    device: IOHIDDeviceRef,
+) {
+    let registry = &*(context as *const RefCell<DeviceRegistry>);
+    registry
+        .borrow_mut()
+        .add(device as usize, &keyboard_info(device));
+}
+
+unsafe extern "C" fn device_removed(
+    context: *mut c_void,
+    _result: i32,
+    _sender: *mut c_void,
+    device: IOHIDDeviceRef,
+) {
+    let registry = &*(context as *const RefCell<DeviceRegistry>);
+    registry.borrow_mut().remove(device as usize);
+}
+
+unsafe extern "C" fn input_value(
+    context: *mut c_void,
+    _result: i32,
+    _sender: *mut c_void,
+    value: IOHIDValueRef,
+) {
+    let element = IOHIDValueGetElement(value);
+    if element.is_null()
+        || IOHIDElementGetUsagePage(element) != HID_PAGE_KEYBOARD
+        || !(HID_FIRST_KEY_USAGE..=HID_LAST_KEY_USAGE).contains(&IOHIDElementGetUsage(element))
+    {
+        return;
+    }
+    let registry = &*(context as *const RefCell<DeviceRegistry>);
+    registry
+        .borrow_mut()
+        .key_reported(IOHIDElementGetDevice(element) as usize, Instant::now());
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILT_IN: usize = 1;
+    const EXTERNAL: usize = 2;
+
+    fn info(name: &str, vendor_id: u32, product_id: u32, built_in: bool) -> KeyboardInfo {
+        KeyboardInfo {
+            name: Some(name.to_owned()),
+            vendor_id,
+            product_id,
+            built_in,
+        }
+    }
+
+    fn two_keyboards() -> DeviceRegistry {
+        let mut registry = DeviceRegistry::default();
+        registry.add(
+            BUILT_IN,
+            &info("Apple Internal Keyboard / Trackpad", 0x05ac, 0x0281, true),
+        );
+        registry.add(EXTERNAL, &info("Keychron K2", 0x05ac, 0x024f, false));
+        registry
+    }
+
+    #[test]
+    fn ids_are_built_in_or_vendor_product() {
+        let internal = info("Apple Internal Keyboard / Trackpad", 0x05ac, 0x0281, true);
+        assert_eq!(&*internal.device_id().id, "built-in");
+        let external = info("USB Receiver", 0x046d, 0xc52b, false).device_id();
+        assert_eq!(
+            (&*external.id, &*external.name),
+            ("046d:c52b", "USB Receiver")
+        );
+        assert!(is_built_in(None, Some("SPI")));
+        assert!(!is_built_in(None, Some("USB")));
+        assert!(!is_built_in(Some(false), Some("SPI")));
+    }
+
+    #[test]
+    fn recent_hid_report_wins_and_is_learned_per_keyboard_type() {
+        let mut registry = two_keyboards();
+        let t0 = Instant::now();
+        registry.key_reported(EXTERNAL, t0);
+        let device = registry.attribute(40, t0 + Duration::from_millis(2));
+        assert_eq!(device.as_ref().map(|d| &*d.id), Some("05ac:024f"));
+
+        // Long after: no recent report, but type 40 was the external one.
+        let later = t0 + Duration::from_secs(5);
+        let device = registry.attribute(40, later);
+        assert_eq!(device.as_ref().map(|d| &*d.id), Some("05ac:024f"));
+        assert_eq!(registry.attribute(58, later), None);
+    }
+
+    #[test]
+    fn single_keyboard_is_the_fallback() {
+        let mut registry = two_keyboards();
+        registry.remove(EXTERNAL);
+        let device = registry.attribute(58, Instant::now());
+        assert_eq!(device.as_ref().map(|d| &*d.id), Some("built-in"));
+        // Two interfaces of one keyboard still count as one.
+        registry.add(
+            3,
+            &info("Apple Internal Keyboard / Trackpad", 0x05ac, 0x0281, true),
+        );
+        assert!(registry.attribute(58, Instant::now()).is_some());
+    }
+
+    #[test]
+    fn removal_forgets_learned_and_recent_devices() {
+        let mut registry = two_keyboards();
+        let t0 = Instant::now();
+        registry.key_reported(EXTERNAL, t0);
+        registry.attribute(40, t0);
+        registry.remove(EXTERNAL);
+        let device = registry.attribute(40, t0);
+        assert_eq!(device.as_ref().map(|d| &*d.id), Some("built-in"));
+    }
+}
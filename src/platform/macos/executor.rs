@@ -2,13 +2,32 @@
 //!
 //! `MacOSExecutor` implements `ActionExecutor`. Injection is synchronous:
 //! `CGEventPost` delivers the event before returning, so no background thread
-//! is needed. Only `Action::InjectKey` is handled; all other variants are
-//! no-ops until later milestones implement them.
+//! is needed. `Action::InjectKey`, `Action::InjectRepeat`,
+//! `Action::InjectChar`, and `Action::TypeString` are handled, and
+//! the pointer actions are posted as mouse events (see `mouse`); all other
+//! variants are no-ops until later milestones implement them.
+//!
+//! The executor runs on the engine thread, behind the event bus, so injection
+//! time never counts against the capture tap's callback deadline. One
+//! `CGEventSource` is created up front and reused for every event.
+//!
+//! Modifier keys are not posted as events of their own. Instead the executor
+//! tracks which modifiers the engine has injected as held and stamps them as
+//! CGEventFlags on every key event it posts, so a lowered `Meta+Shift+4`
+//! combo reaches applications as one key carrying Command and Shift.
 
+use std::cell::{Cell, RefCell};
 use std::ffi::c_void;
 
-use super::keycodes::keycode_to_vkcode;
-use crate::platform::{Action, ActionExecutor, KeyCode, KeyState, PlatformError};
+use super::keycodes::{
+    keycode_to_vkcode, modifier_bits, side_bits, DEVICE_SIDE_MASK, MODIFIER_FLAGS,
+};
+use super::layout::LayoutResolver;
+use super::media;
+use super::mouse;
+use crate::platform::{
+    Action, ActionExecutor, Capabilities, KeyCode, KeyState, PlatformError, TextInjection,
+};
 
 // ---------------------------------------------------------------------------
 // Constants
@@ -22,6 +41,10 @@ const CG_SESSION_EVENT_TAP: u32 = 1;
 /// kCGEventSourceStateHIDSystemState = 1 -- use the real HID hardware state.
 const CG_EVENT_SOURCE_STATE_HID_SYSTEM_STATE: i32 = 1;
 
+/// kCGKeyboardEventAutorepeat: CGEventField index, set to 1 on a KeyDown
+/// posted as an autorepeat.
+const CG_KEYBOARD_EVENT_AUTOREPEAT: u32 = 8;
+
 // ---------------------------------------------------------------------------
 // Raw FFI
 // ---------------------------------------------------------------------------
@@ -38,6 +61,14 @@ extern "C" {
         key_down: bool,
     ) -> CGEventRef;
     fn CGEventPost(tap_location: u32, event: CGEventRef);
+    fn CGEventGetFlags(event: CGEventRef) -> u64;
+    fn CGEventSetFlags(event: CGEventRef, flags: u64);
+    fn CGEventSetIntegerValueField(event: CGEventRef, field: u32, value: i64);
+    fn CGEventKeyboardSetUnicodeString(
+        event: CGEventRef,
+        string_length: usize,
+        unicode_string: *const u16,
+    );
 }
 
 #[link(name = "CoreFoundation", kind = "framework")]
@@ -45,19 +76,103 @@ extern "C" {
     fn CFRelease(cf: *const c_void);
 }
 
+// ---------------------------------------------------------------------------
+// Event source
+// ---------------------------------------------------------------------------
+
+/// Owned CGEventSourceRef, released on drop. May be null, which
+/// `CGEventCreateKeyboardEvent` accepts as "no particular source".
+struct EventSource(CGEventSourceRef);
+
+/// The executor moves to the engine thread after creation and is only used
+/// there; Core Foundation objects may be used from any one thread at a time.
+unsafe impl Send for EventSource {}
+
+impl EventSource {
+    fn new() -> Self {
+        let source = unsafe { CGEventSourceCreate(CG_EVENT_SOURCE_STATE_HID_SYSTEM_STATE) };
+        if source.is_null() {
+            log::warn!("executor: CGEventSourceCreate returned null; posting without a source");
+        }
+        Self(source)
+    }
+}
+
+impl Drop for EventSource {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { CFRelease(self.0.cast::<c_void>()) };
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Modifier flags
+// ---------------------------------------------------------------------------
+
+/// The held-modifier flags after injecting `key` in `state`.
+///
+/// Returns `None` when `key` is not a modifier, so the caller posts it as a
+/// regular key event instead.
+///
+/// Each side of a modifier is tracked by its device bit, so applications that
+/// tell left from right see the side a rule asked for. The generic mask stays
+/// set until neither side is held.
+fn flags_after(held: u64, key: KeyCode, state: KeyState) -> Option<u64> {
+    let (mask, side) = modifier_bits(key)?;
+    Some(match state {
+        KeyState::Down => held | mask | side,
+        KeyState::Up => {
+            let held = held & !side;
+            if held & side_bits(mask) == 0 {
+                held & !mask
+            } else {
+                held
+            }
+        }
+    })
+}
+
+/// The flags to post a key event with: the event's own flags with every
+/// tracked modifier, and its side bits, replaced by the injected `held` set.
+///
+/// Non-modifier bits the source filled in (NumPad, Fn, CapsLock) are kept.
+/// Modifier bits are replaced rather than merged so that a suppressed
+/// physical modifier, such as Command remapped to Control, does not leak into
+/// the injected key.
+fn event_flags(source_flags: u64, held: u64) -> u64 {
+    let modifiers = MODIFIER_FLAGS
+        .iter()
+        .fold(DEVICE_SIDE_MASK, |all, (_, mask)| all | mask);
+    (source_flags & !modifiers) | held
+}
+
 // ---------------------------------------------------------------------------
 // Public struct
 // ---------------------------------------------------------------------------
 
 /// Injects keyboard events via CGEventPost on macOS.
 ///
-/// Stateless: each `execute()` call creates a `CGEvent`, posts it, and
-/// releases it immediately. No background thread is required.
-pub struct MacOSExecutor;
+/// Each `execute()` call creates a `CGEvent` from the shared source, posts
+/// it, and releases it immediately. No background thread is required.
+pub struct MacOSExecutor {
+    source: EventSource,
+    /// CGEventFlags of the modifiers injected as held.
+    held_flags: Cell<u64>,
+    /// Mouse buttons injected as held, bit n for button number n.
+    held_buttons: Cell<u32>,
+    /// The keys of the current layout, for `TypeString`.
+    layout: RefCell<LayoutResolver>,
+}
 
 impl MacOSExecutor {
     pub fn new() -> Self {
-        MacOSExecutor
+        Self {
+            source: EventSource::new(),
+            held_flags: Cell::new(0),
+            held_buttons: Cell::new(0),
+            layout: RefCell::new(LayoutResolver::default()),
+        }
     }
 }
 
@@ -68,26 +183,77 @@ impl MacOSExecutor {
 impl ActionExecutor for MacOSExecutor {
     /// Executes an action.
     ///
-    /// `Action::InjectKey` posts a `CGEvent` at the HID level.
-    /// `Action::Exec` spawns a subprocess via `spawn_command`.
+    /// `Action::InjectKey` posts a `CGEvent` at the HID level, or a
+    /// system-defined event for media keys. `Action::InjectRepeat` posts a
+    /// KeyDown with the autorepeat field set.
+    /// `Action::InjectChar` posts a key pair carrying a Unicode string.
+    /// `Action::TypeString` presses the key that types each character on the
+    /// current layout, and falls back to a Unicode string for characters no
+    /// key types.
+    /// `Action::Chord` posts one key pair carrying the chord's modifier
+    /// flags on top of those held.
+    /// `Action::SystemCommand` runs the platform's lock, sleep, or log out.
+    /// Pointer actions post mouse move, button, and scroll events.
     /// All other variants are silently accepted as no-ops.
     fn execute(&self, action: &Action) -> Result<(), PlatformError> {
-        if let Action::Exec { command } = action {
-            return crate::platform::spawn_command(command);
+        if let Action::InjectChar(c) = action {
+            return inject_char(self.source.0, *c);
+        }
+
+        if let Action::TypeString { text } = action {
+            return self.type_string(text);
+        }
+
+        if let Action::Chord { modifiers, key } = action {
+            let held = self.held_flags.get();
+            let flags = modifiers.keys().into_iter().fold(held, |flags, m| {
+                flags_after(flags, m, KeyState::Down).unwrap_or(flags)
+            });
+            self.held_flags.set(flags);
+            let result = [KeyState::Down, KeyState::Up]
+                .into_iter()
+                .try_for_each(|state| self.execute(&Action::InjectKey { key: *key, state }));
+            self.held_flags.set(held);
+            return result;
         }
 
-        let Action::InjectKey { key, state } = action else {
+        if let Action::SystemCommand(cmd) = action {
+            return super::system::run(*cmd);
+        }
+
+        if let Action::Delay { ms } = action {
+            log::debug!("executor: {ms}ms delay outside a sequence; ignored");
             return Ok(());
+        }
+
+        if mouse::inject(self.source.0, action, &self.held_buttons)? {
+            return Ok(());
+        }
+
+        let (key, state, repeat) = match action {
+            Action::InjectKey { key, state } => (key, state, false),
+            Action::InjectRepeat { key } => (key, &KeyState::Down, true),
+            _ => return Ok(()),
         };
 
         // Modifier keys (Ctrl, Shift, Alt, Meta) are delivered as kCGEventFlagsChanged
-        // events by the capture backend and passed through unchanged. Re-injecting them
-        // as a regular CGEventCreateKeyboardEvent would produce the wrong event type
-        // and duplicate modifier state. Full modifier re-injection is planned for M11.
-        if matches!(
-            key,
-            KeyCode::Ctrl | KeyCode::Shift | KeyCode::Alt | KeyCode::Meta
-        ) {
+        // events by the capture backend and passed through unchanged, so posting them
+        // again would duplicate modifier state. They are recorded and applied as flags
+        // to the key events that follow.
+        if let Some(flags) = flags_after(self.held_flags.get(), *key, *state) {
+            self.held_flags.set(flags);
+            log::debug!(
+                "executor: modifier {:?} {:?}, flags {:#x}",
+                key,
+                state,
+                flags
+            );
+            return Ok(());
+        }
+
+        if let Some(keytype) = media::keycode_to_keytype(*key) {
+            media::inject(keytype, *state)?;
+            log::debug!("executor: injected media key {:?} {:?}", key, state);
             return Ok(());
         }
 
@@ -100,24 +266,20 @@ impl ActionExecutor for MacOSExecutor {
         let inject_start = std::time::Instant::now();
 
         unsafe {
-            let source = CGEventSourceCreate(CG_EVENT_SOURCE_STATE_HID_SYSTEM_STATE);
-            if source.is_null() {
-                return Err(PlatformError::Other(
-                    "CGEventSourceCreate returned null".into(),
-                ));
-            }
-
-            let event = CGEventCreateKeyboardEvent(source, vkcode, key_down);
+            let event = CGEventCreateKeyboardEvent(self.source.0, vkcode, key_down);
             if event.is_null() {
-                CFRelease(source.cast::<c_void>());
                 return Err(PlatformError::Other(
                     "CGEventCreateKeyboardEvent returned null".into(),
                 ));
             }
 
+            let flags = event_flags(CGEventGetFlags(event), self.held_flags.get());
+            CGEventSetFlags(event, flags);
+            if repeat {
+                CGEventSetIntegerValueField(event, CG_KEYBOARD_EVENT_AUTOREPEAT, 1);
+            }
             CGEventPost(CG_SESSION_EVENT_TAP, event);
             CFRelease(event.cast::<c_void>());
-            CFRelease(source.cast::<c_void>());
         }
 
         log::debug!(
@@ -129,6 +291,90 @@ impl ActionExecutor for MacOSExecutor {
 
         Ok(())
     }
+
+    fn name(&self) -> &'static str {
+        "cgevent"
+    }
+
+    /// `type_string` picks layout keys or Unicode strings per character.
+    fn text_injection(&self) -> TextInjection {
+        TextInjection::Strings
+    }
+
+    /// Everything but the clipboard, which the engine owns.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            inject_key: true,
+            type_text: true,
+            pointer: true,
+            system_command: true,
+            clipboard: false,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Text
+// ---------------------------------------------------------------------------
+
+impl MacOSExecutor {
+    /// Types `text` one character at a time.
+    ///
+    /// Applications that read key codes rather than the attached string
+    /// (games, remote desktop clients, terminal emulators in some modes) only
+    /// see the right character when the right key is pressed, so each
+    /// character is posted as its key on the current layout with the Shift
+    /// and Option it needs. Characters the layout cannot type go through
+    /// `inject_char`.
+    fn type_string(&self, text: &str) -> Result<(), PlatformError> {
+        let mut layout = self.layout.borrow_mut();
+        layout.refresh();
+        for c in text.chars() {
+            let Some(stroke) = layout.resolve(c) else {
+                inject_char(self.source.0, c)?;
+                continue;
+            };
+            for key_down in [true, false] {
+                unsafe {
+                    let event = CGEventCreateKeyboardEvent(self.source.0, stroke.vkcode, key_down);
+                    if event.is_null() {
+                        return Err(PlatformError::Other(
+                            "CGEventCreateKeyboardEvent returned null".into(),
+                        ));
+                    }
+                    CGEventSetFlags(event, event_flags(CGEventGetFlags(event), stroke.flags()));
+                    CGEventPost(CG_SESSION_EVENT_TAP, event);
+                    CFRelease(event.cast::<c_void>());
+                }
+            }
+        }
+        log::debug!("executor: typed {} characters", text.chars().count());
+        Ok(())
+    }
+}
+
+/// Types `c` by attaching it to a synthetic key event as a Unicode string.
+///
+/// The virtual key code (0) is ignored by applications when a Unicode string
+/// is set, so the result does not depend on the keyboard layout. Surrogate
+/// pairs are passed as a two-unit string.
+fn inject_char(source: CGEventSourceRef, c: char) -> Result<(), PlatformError> {
+    let units = crate::platform::utf16_units(c);
+    unsafe {
+        for key_down in [true, false] {
+            let event = CGEventCreateKeyboardEvent(source, 0, key_down);
+            if event.is_null() {
+                return Err(PlatformError::Other(
+                    "CGEventCreateKeyboardEvent returned null".into(),
+                ));
+            }
+            CGEventKeyboardSetUnicodeString(event, units.len(), units.as_ptr());
+            CGEventPost(CG_SESSION_EVENT_TAP, event);
+            CFRelease(event.cast::<c_void>());
+        }
+    }
+    log::debug!("executor: injected char U+{:04X}", c as u32);
+    Ok(())
 }
 
 // ---------------------------------------------------------------------------
@@ -138,8 +384,113 @@ impl ActionExecutor for MacOSExecutor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::platform::macos::keycodes::{
+        DEVICE_LEFT_COMMAND, DEVICE_LEFT_SHIFT, DEVICE_RIGHT_COMMAND, FLAG_MASK_COMMAND,
+        FLAG_MASK_CTRL, FLAG_MASK_SHIFT,
+    };
     use crate::platform::{Action, KeyCode};
 
+    /// The executor is boxed as `dyn ActionExecutor` and moved to the engine
+    /// thread, so the cached source must not make it `!Send`.
+    #[test]
+    fn executor_with_cached_source_is_send() {
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&MacOSExecutor::new());
+    }
+
+    const CMD: u64 = FLAG_MASK_COMMAND | DEVICE_LEFT_COMMAND;
+    const SHIFT: u64 = FLAG_MASK_SHIFT | DEVICE_LEFT_SHIFT;
+    const RIGHT_CMD: u64 = FLAG_MASK_COMMAND | DEVICE_RIGHT_COMMAND;
+
+    /// Run `steps` through `flags_after`, returning the flags each non-modifier
+    /// key would be posted with.
+    fn key_flags(steps: &[(KeyCode, KeyState)]) -> Vec<(KeyCode, KeyState, u64)> {
+        let mut held = 0;
+        let mut posted = Vec::new();
+        for &(key, state) in steps {
+            match flags_after(held, key, state) {
+                Some(flags) => held = flags,
+                None => posted.push((key, state, event_flags(0, held))),
+            }
+        }
+        posted
+    }
+
+    #[test]
+    fn cmd_tab_posts_tab_with_command() {
+        use KeyState::{Down, Up};
+        let posted = key_flags(&[
+            (KeyCode::Meta, Down),
+            (KeyCode::Tab, Down),
+            (KeyCode::Tab, Up),
+            (KeyCode::Meta, Up),
+        ]);
+        assert_eq!(
+            posted,
+            vec![(KeyCode::Tab, Down, CMD), (KeyCode::Tab, Up, CMD)]
+        );
+    }
+
+    #[test]
+    fn cmd_shift_4_posts_digit_with_both_flags() {
+        use KeyState::{Down, Up};
+        let posted = key_flags(&[
+            (KeyCode::Meta, Down),
+            (KeyCode::Shift, Down),
+            (KeyCode::Key4, Down),
+            (KeyCode::Key4, Up),
+            (KeyCode::Shift, Up),
+            (KeyCode::Meta, Up),
+            (KeyCode::Key4, Down),
+        ]);
+        assert_eq!(
+            posted,
+            vec![
+                (KeyCode::Key4, Down, CMD | SHIFT),
+                (KeyCode::Key4, Up, CMD | SHIFT),
+                (KeyCode::Key4, Down, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn right_command_posts_its_own_side_bit() {
+        use KeyState::{Down, Up};
+        let posted = key_flags(&[
+            (KeyCode::RightMeta, Down),
+            (KeyCode::Tab, Down),
+            (KeyCode::Meta, Down),
+            (KeyCode::RightMeta, Up),
+            (KeyCode::Tab, Up),
+            (KeyCode::Meta, Up),
+            (KeyCode::Tab, Down),
+        ]);
+        assert_eq!(
+            posted,
+            vec![
+                (KeyCode::Tab, Down, RIGHT_CMD),
+                (KeyCode::Tab, Up, CMD),
+                (KeyCode::Tab, Down, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn modifier_bits_are_replaced_and_others_kept() {
+        const NUMPAD: u64 = 0x0020_0000;
+        assert_eq!(
+            event_flags(CMD | NUMPAD, FLAG_MASK_CTRL),
+            FLAG_MASK_CTRL | NUMPAD
+        );
+        assert_eq!(event_flags(CMD | SHIFT, 0), 0);
+    }
+
+    #[test]
+    fn non_modifier_keys_leave_flags_alone() {
+        assert_eq!(flags_after(CMD, KeyCode::A, KeyState::Down), None);
+        assert_eq!(flags_after(CMD, KeyCode::Shift, KeyState::Up), Some(CMD));
+    }
+
     /// Non-InjectKey variants must return Ok without touching any OS API.
     #[test]
     fn other_actions_are_noop() {
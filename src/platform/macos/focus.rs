@@ -0,0 +1,615 @@
+//! Focused-application tracking for macOS window context.
+//!
+//! `FocusTracker::poll()` asks the Accessibility API for the focused
+//! application and its focused window, then resolves the application's
+//! process to a bundle identifier (used as `app_id`) and reads the window
+//! title. The capture run loop calls it from a timer and stamps the cached
+//! result onto every key event, so the tap callback itself never blocks on
+//! another process. Each change is also handed to the focus callback, if
+//! capture was given one, so the engine hears of it without waiting for a
+//! key.
+//!
+//! A window's title can change without a focus change, as when a browser
+//! switches tabs. The tracker therefore also registers an AXObserver for
+//! `AXTitleChanged` on the focused window, on the same run loop, and updates
+//! the cached title, and tells the focus callback, as soon as the
+//! notification arrives instead of at the next poll. The observer follows the focus: when a poll finds another
+//! window focused, the old observer is removed and one is added for the new
+//! window.
+//!
+//! Everything here is plain C API (AX, libproc, CFBundle), so no Objective-C
+//! runtime is needed and the tracker works off the main thread, where
+//! `NSWorkspace` notifications would not be delivered.
+//!
+//! Edge cases:
+//!   - Processes outside an `.app` bundle (command line tools, some helpers)
+//!     have no bundle identifier; their executable name is used instead.
+//!   - Title queries fail for apps that do not implement AX properly and for
+//!     windows that are not standard windows. The title is then `None`, while
+//!     `app_id` is still resolved from the process.
+//!   - If Accessibility permission is revoked while running, every query
+//!     fails and the context is empty (so per-app rules stop matching). This
+//!     is logged once per loss.
+//!   - Some apps refuse observers or do not send `AXTitleChanged`. Their
+//!     title is then only refreshed by polling, and the observer is not
+//!     retried until another window is focused.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::path::Path;
+
+use crate::platform::WindowContext;
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+/// kCFStringEncodingUTF8.
+const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+
+/// kAXErrorSuccess.
+const AX_ERROR_SUCCESS: i32 = 0;
+
+/// kAXErrorAPIDisabled: Accessibility permission is not (or no longer) granted.
+const AX_ERROR_API_DISABLED: i32 = -25211;
+
+/// PROC_PIDPATHINFO_MAXSIZE from <libproc.h>.
+const PROC_PIDPATH_MAX: usize = 4096;
+
+/// Seconds an AX query may wait on an unresponsive application before failing.
+const AX_MESSAGING_TIMEOUT: f32 = 0.1;
+
+// ---------------------------------------------------------------------------
+// Raw FFI types and declarations
+// ---------------------------------------------------------------------------
+
+type AXObserverRef = *const c_void;
+type AXUIElementRef = *const c_void;
+type CFBundleRef = *const c_void;
+type CFRunLoopRef = *mut c_void;
+type CFRunLoopSourceRef = *mut c_void;
+type CFStringRef = *const c_void;
+type CFTypeRef = *const c_void;
+type CFURLRef = *const c_void;
+
+type AXObserverCallback = unsafe extern "C" fn(
+    observer: AXObserverRef,
+    element: AXUIElementRef,
+    notification: CFStringRef,
+    refcon: *mut c_void,
+);
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    /// Returns an element representing the whole system (owned; release it).
+    fn AXUIElementCreateSystemWide() -> AXUIElementRef;
+
+    /// Copies an attribute value of an element (owned on success).
+    fn AXUIElementCopyAttributeValue(
+        element: AXUIElementRef,
+        attribute: CFStringRef,
+        value: *mut CFTypeRef,
+    ) -> i32;
+
+    /// Reads the process id of the application an element belongs to.
+    fn AXUIElementGetPid(element: AXUIElementRef, pid: *mut c_int) -> i32;
+
+    /// Bounds how long AX calls on `element` wait for the target application.
+    fn AXUIElementSetMessagingTimeout(element: AXUIElementRef, timeout: f32) -> i32;
+
+    /// Creates an observer for notifications from process `pid` (owned).
+    fn AXObserverCreate(
+        pid: c_int,
+        callback: AXObserverCallback,
+        observer: *mut AXObserverRef,
+    ) -> i32;
+
+    /// Asks for `notification` from `element`, passed to the callback with
+    /// `refcon`.
+    fn AXObserverAddNotification(
+        observer: AXObserverRef,
+        element: AXUIElementRef,
+        notification: CFStringRef,
+        refcon: *mut c_void,
+    ) -> i32;
+
+    fn AXObserverRemoveNotification(
+        observer: AXObserverRef,
+        element: AXUIElementRef,
+        notification: CFStringRef,
+    ) -> i32;
+
+    /// The run loop source that delivers the observer's callbacks (not owned).
+    fn AXObserverGetRunLoopSource(observer: AXObserverRef) -> CFRunLoopSourceRef;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    /// Creates a CFString from a NUL-terminated C string.
+    fn CFStringCreateWithCString(
+        alloc: *const c_void,
+        c_str: *const c_char,
+        encoding: u32,
+    ) -> CFStringRef;
+
+    /// Number of UTF-16 code units in a CFString.
+    fn CFStringGetLength(string: CFStringRef) -> isize;
+
+    /// Upper bound on the bytes `length` code units need in `encoding`.
+    fn CFStringGetMaximumSizeForEncoding(length: isize, encoding: u32) -> isize;
+
+    /// Copies a CFString into a NUL-terminated buffer.
+    fn CFStringGetCString(
+        string: CFStringRef,
+        buffer: *mut c_char,
+        buffer_size: isize,
+        encoding: u32,
+    ) -> bool;
+
+    /// Returns the type id of a CF object.
+    fn CFGetTypeID(cf: CFTypeRef) -> usize;
+
+    /// Returns the type id shared by all CFStrings.
+    fn CFStringGetTypeID() -> usize;
+
+    /// Creates a file URL from a POSIX path.
+    fn CFURLCreateFromFileSystemRepresentation(
+        alloc: *const c_void,
+        buffer: *const u8,
+        len: isize,
+        is_directory: bool,
+    ) -> CFURLRef;
+
+    /// Opens the bundle at `url`; null if it is not a bundle.
+    fn CFBundleCreate(alloc: *const c_void, url: CFURLRef) -> CFBundleRef;
+
+    /// Returns the bundle identifier (not owned), or null if it has none.
+    fn CFBundleGetIdentifier(bundle: CFBundleRef) -> CFStringRef;
+
+    /// Whether two CF objects are equal; for AX elements, the same UI element.
+    fn CFEqual(a: CFTypeRef, b: CFTypeRef) -> bool;
+
+    fn CFRunLoopGetCurrent() -> CFRunLoopRef;
+    fn CFRunLoopAddSource(rl: CFRunLoopRef, source: CFRunLoopSourceRef, mode: CFStringRef);
+    fn CFRunLoopRemoveSource(rl: CFRunLoopRef, source: CFRunLoopSourceRef, mode: CFStringRef);
+
+    /// Releases a Core Foundation object.
+    fn CFRelease(cf: *const c_void);
+
+    static kCFRunLoopDefaultMode: CFStringRef;
+}
+
+extern "C" {
+    /// Writes the executable path of `pid` into `buffer` (libproc).
+    fn proc_pidpath(pid: c_int, buffer: *mut c_void, buffer_size: u32) -> c_int;
+}
+
+// ---------------------------------------------------------------------------
+// Tracker
+// ---------------------------------------------------------------------------
+
+/// Polls the focused application and window. Owned by the capture run loop
+/// thread; not `Send`.
+pub struct FocusTracker {
+    system: AXUIElementRef,
+    attr_focused_app: CFStringRef,
+    attr_focused_window: CFStringRef,
+    attr_title: CFStringRef,
+    /// The last pid resolved and its app id, since a process never changes
+    /// bundle and resolving means a filesystem read.
+    last_app: Option<(c_int, Option<String>)>,
+    /// Observes title changes of the focused window. Dropped before
+    /// `current`, which its callback writes to.
+    title_watch: Option<TitleWatch>,
+    /// Boxed so the title observer callback can hold its address.
+    current: Box<Focused>,
+    /// Whether a permission failure has been logged, so it is logged once.
+    warned_permission: bool,
+}
+
+/// The context as last seen, and who is told when it changes.
+struct Focused {
+    context: RefCell<WindowContext>,
+    /// Told of every change `poll` or a title notification finds.
+    on_change: Option<Box<dyn Fn(WindowContext) + Send>>,
+}
+
+impl Focused {
+    fn report(&self, context: &WindowContext) {
+        if let Some(on_change) = &self.on_change {
+            on_change(context.clone());
+        }
+    }
+}
+
+impl FocusTracker {
+    /// A tracker that hands each change to `on_change`, if set.
+    pub fn new(on_change: Option<Box<dyn Fn(WindowContext) + Send>>) -> Self {
+        unsafe {
+            let system = AXUIElementCreateSystemWide();
+            AXUIElementSetMessagingTimeout(system, AX_MESSAGING_TIMEOUT);
+            Self {
+                system,
+                attr_focused_app: cfstring(b"AXFocusedApplication\0"),
+                attr_focused_window: cfstring(b"AXFocusedWindow\0"),
+                attr_title: cfstring(b"AXTitle\0"),
+                last_app: None,
+                title_watch: None,
+                current: Box::new(Focused {
+                    context: RefCell::default(),
+                    on_change,
+                }),
+                warned_permission: false,
+            }
+        }
+    }
+
+    /// The context from the latest `poll` or title notification.
+    pub fn current(&self) -> std::cell::Ref<'_, WindowContext> {
+        self.current.context.borrow()
+    }
+
+    /// Re-reads the focused application and window title. Returns `true`
+    /// when either changed, after telling `on_change`.
+    pub fn poll(&mut self) -> bool {
+        let next = unsafe { self.query() };
+        if next == *self.current.context.borrow() {
+            return false;
+        }
+        log::debug!(
+            "capture: focus changed to app_id={:?} title={:?}",
+            next.app_id,
+            next.title
+        );
+        *self.current.context.borrow_mut() = next.clone();
+        self.current.report(&next);
+        true
+    }
+
+    unsafe fn query(&mut self) -> WindowContext {
+        let mut app: CFTypeRef = std::ptr::null();
+        let err = AXUIElementCopyAttributeValue(self.system, self.attr_focused_app, &mut app);
+        if err != AX_ERROR_SUCCESS {
+            self.note_error(err);
+            // No focused application (e.g. during a Space switch) or no
+            // permission: nothing is known about the focus.
+            return WindowContext::default();
+        }
+        self.warned_permission = false;
+
+        let mut pid: c_int = 0;
+        let (app_id, window) = if AXUIElementGetPid(app, &mut pid) == AX_ERROR_SUCCESS {
+            (self.app_id_for(pid), self.focused_window(app))
+        } else {
+            (None, None)
+        };
+        CFRelease(app);
+
+        let title = window.and_then(|window| match window_title(window, self.attr_title) {
+            Ok(title) => title,
+            Err(err) => {
+                self.note_error(err);
+                None
+            }
+        });
+        self.watch_title(pid, window);
+        WindowContext { app_id, title }
+    }
+
+    /// The focused window of `app` (owned), if it has one.
+    unsafe fn focused_window(&mut self, app: CFTypeRef) -> Option<AXUIElementRef> {
+        let mut window: CFTypeRef = std::ptr::null();
+        let err = AXUIElementCopyAttributeValue(app, self.attr_focused_window, &mut window);
+        if err != AX_ERROR_SUCCESS {
+            self.note_error(err);
+            return None;
+        }
+        Some(window)
+    }
+
+    /// Points the title observer at `window` of process `pid`, taking
+    /// ownership of `window`. Keeps the current observer when the window is
+    /// the same, so focus polls do not churn observers.
+    unsafe fn watch_title(&mut self, pid: c_int, window: Option<AXUIElementRef>) {
+        let Some(window) = window else {
+            self.title_watch = None;
+            return;
+        };
+        if self
+            .title_watch
+            .as_ref()
+            .is_some_and(|watch| watch.pid == pid && CFEqual(watch.window, window))
+        {
+            CFRelease(window);
+            return;
+        }
+        // Remove the old observer before adding the new one.
+        self.title_watch = None;
+        self.title_watch = Some(TitleWatch::new(pid, window, &self.current));
+    }
+
+    fn app_id_for(&mut self, pid: c_int) -> Option<String> {
+        if let Some((cached, app_id)) = &self.last_app {
+            if *cached == pid {
+                return app_id.clone();
+            }
+        }
+        let app_id = executable_path(pid).and_then(|path| {
+            let bundle_id = bundle_root(&path).and_then(|root| unsafe { bundle_identifier(root) });
+            bundle_id.or_else(|| executable_name(&path))
+        });
+        self.last_app = Some((pid, app_id.clone()));
+        app_id
+    }
+
+    fn note_error(&mut self, err: i32) {
+        if err == AX_ERROR_API_DISABLED && !self.warned_permission {
+            log::warn!(
+                "capture: Accessibility permission lost; window context unavailable until it is granted again"
+            );
+            self.warned_permission = true;
+        } else if err != AX_ERROR_API_DISABLED {
+            log::trace!("capture: AX focus query failed with error {err}");
+        }
+    }
+}
+
+impl Drop for FocusTracker {
+    fn drop(&mut self) {
+        self.title_watch = None;
+        unsafe {
+            for cf in [
+                self.system,
+                self.attr_focused_app,
+                self.attr_focused_window,
+                self.attr_title,
+            ] {
+                if !cf.is_null() {
+                    CFRelease(cf);
+                }
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Title observer
+// ---------------------------------------------------------------------------
+
+/// An AXObserver for `AXTitleChanged` on one window, scheduled on the run
+/// loop the tracker is polled on.
+struct TitleWatch {
+    pid: c_int,
+    /// Owned.
+    window: AXUIElementRef,
+    /// Owned; null when the app refused the observer.
+    observer: AXObserverRef,
+    notification: CFStringRef,
+}
+
+impl TitleWatch {
+    /// Observes `window` (owned) of process `pid`; the callback writes new
+    /// titles to `focused`, which must outlive the watch.
+    unsafe fn new(pid: c_int, window: AXUIElementRef, focused: &Focused) -> Self {
+        let mut watch = Self {
+            pid,
+            window,
+            observer: std::ptr::null(),
+            notification: cfstring(b"AXTitleChanged\0"),
+        };
+        let mut observer: AXObserverRef = std::ptr::null();
+        let err = AXObserverCreate(pid, title_changed, &mut observer);
+        if err != AX_ERROR_SUCCESS || observer.is_null() {
+            log::debug!("capture: cannot observe window titles of pid {pid} (error {err})");
+            return watch;
+        }
+        let refcon = (focused as *const Focused).cast_mut().cast::<c_void>();
+        let err = AXObserverAddNotification(observer, window, watch.notification, refcon);
+        if err != AX_ERROR_SUCCESS {
+            log::debug!("capture: pid {pid} does not report title changes (error {err})");
+            CFRelease(observer);
+            return watch;
+        }
+        CFRunLoopAddSource(
+            CFRunLoopGetCurrent(),
+            AXObserverGetRunLoopSource(observer),
+            kCFRunLoopDefaultMode,
+        );
+        watch.observer = observer;
+        watch
+    }
+}
+
+impl Drop for TitleWatch {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.observer.is_null() {
+                AXObserverRemoveNotification(self.observer, self.window, self.notification);
+                CFRunLoopRemoveSource(
+                    CFRunLoopGetCurrent(),
+                    AXObserverGetRunLoopSource(self.observer),
+                    kCFRunLoopDefaultMode,
+                );
+                CFRelease(self.observer);
+            }
+            CFRelease(self.window);
+            CFRelease(self.notification);
+        }
+    }
+}
+
+/// AXObserver callback: the observed window's title changed. Runs on the
+/// capture run loop, where nothing else holds the context borrowed. The
+/// focus callback is told once the borrow is over.
+unsafe extern "C" fn title_changed(
+    _observer: AXObserverRef,
+    element: AXUIElementRef,
+    _notification: CFStringRef,
+    refcon: *mut c_void,
+) {
+    let focused = &*(refcon as *const Focused);
+    let attr_title = cfstring(b"AXTitle\0");
+    let title = window_title(element, attr_title).ok().flatten();
+    CFRelease(attr_title);
+    let retitled = {
+        let Ok(mut context) = focused.context.try_borrow_mut() else {
+            return;
+        };
+        if context.title == title {
+            return;
+        }
+        log::debug!("capture: window title changed to {title:?}");
+        context.title = title;
+        context.clone()
+    };
+    focused.report(&retitled);
+}
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+/// Reads the title of `window`, or the AX error. Empty titles count as none.
+unsafe fn window_title(
+    window: AXUIElementRef,
+    attr_title: CFStringRef,
+) -> Result<Option<String>, i32> {
+    let mut title: CFTypeRef = std::ptr::null();
+    let err = AXUIElementCopyAttributeValue(window, attr_title, &mut title);
+    if err != AX_ERROR_SUCCESS {
+        return Err(err);
+    }
+    let text = if CFGetTypeID(title) == CFStringGetTypeID() {
+        string_from_cf(title)
+    } else {
+        None
+    };
+    CFRelease(title);
+    Ok(text.filter(|t| !t.is_empty()))
+}
+
+/// Creates an owned CFString from a NUL-terminated byte string.
+pub(super) unsafe fn cfstring(s: &[u8]) -> CFStringRef {
+    debug_assert_eq!(s.last(), Some(&0));
+    CFStringCreateWithCString(std::ptr::null(), s.as_ptr().cast(), CF_STRING_ENCODING_UTF8)
+}
+
+/// Copies a CFString into a Rust `String`.
+pub(super) unsafe fn string_from_cf(s: CFStringRef) -> Option<String> {
+    let max = CFStringGetMaximumSizeForEncoding(CFStringGetLength(s), CF_STRING_ENCODING_UTF8) + 1;
+    let mut buf = vec![0 as c_char; usize::try_from(max).ok()?];
+    if !CFStringGetCString(s, buf.as_mut_ptr(), max, CF_STRING_ENCODING_UTF8) {
+        return None;
+    }
+    CStr::from_ptr(buf.as_ptr())
+        .to_str()
+        .ok()
+        .map(str::to_owned)
+}
+
+/// The executable path of a process, or `None` if it has exited.
+pub(super) fn executable_path(pid: c_int) -> Option<String> {
+    let mut buf = vec![0u8; PROC_PIDPATH_MAX];
+    let len = unsafe { proc_pidpath(pid, buf.as_mut_ptr().cast(), buf.len() as u32) };
+    if len <= 0 {
+        return None;
+    }
+    buf.truncate(len as usize);
+    String::from_utf8(buf).ok()
+}
+
+/// The `.app` bundle an executable lives in: for
+/// `/Applications/Safari.app/Contents/MacOS/Safari` that is
+/// `/Applications/Safari.app`.
+///
+/// The innermost bundle wins, so helpers nested inside another app's bundle
+/// (`Foo.app/Contents/Frameworks/Helper.app/...`) report their own identity.
+fn bundle_root(executable: &str) -> Option<&Path> {
+    Path::new(executable)
+        .ancestors()
+        .skip(1)
+        .find(|p| p.extension().is_some_and(|ext| ext == "app"))
+}
+
+/// Fallback app id for processes with no bundle identifier.
+pub(super) fn executable_name(executable: &str) -> Option<String> {
+    Path::new(executable)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(str::to_owned)
+}
+
+/// Reads the `CFBundleIdentifier` of the bundle at `root`.
+unsafe fn bundle_identifier(root: &Path) -> Option<String> {
+    let path = root.to_str()?;
+    let url = CFURLCreateFromFileSystemRepresentation(
+        std::ptr::null(),
+        path.as_ptr(),
+        path.len() as isize,
+        true,
+    );
+    if url.is_null() {
+        return None;
+    }
+    let bundle = CFBundleCreate(std::ptr::null(), url);
+    CFRelease(url);
+    if bundle.is_null() {
+        return None;
+    }
+    let ident = CFBundleGetIdentifier(bundle);
+    let id = if ident.is_null() {
+        None
+    } else {
+        string_from_cf(ident)
+    };
+    CFRelease(bundle);
+    id
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundle_root_finds_enclosing_app() {
+        assert_eq!(
+            bundle_root("/Applications/Safari.app/Contents/MacOS/Safari"),
+            Some(Path::new("/Applications/Safari.app"))
+        );
+    }
+
+    #[test]
+    fn bundle_root_prefers_innermost_app() {
+        assert_eq!(
+            bundle_root(
+                "/Applications/Foo.app/Contents/Frameworks/Foo Helper.app/Contents/MacOS/Foo Helper"
+            ),
+            Some(Path::new(
+                "/Applications/Foo.app/Contents/Frameworks/Foo Helper.app"
+            ))
+        );
+    }
+
+    #[test]
+    fn executable_outside_bundle_has_no_root_and_uses_its_name() {
+        let path = "/opt/homebrew/bin/kitty";
+        assert_eq!(bundle_root(path), None);
+        assert_eq!(executable_name(path).as_deref(), Some("kitty"));
+    }
+
+    #[test]
+    fn own_process_resolves_to_an_app_id() {
+        let mut tracker = FocusTracker::new(None);
+        let pid = std::process::id() as c_int;
+        // The test binary is not bundled, so its file name is used.
+        let expected = std::env::current_exe()
+            .ok()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()));
+        assert_eq!(tracker.app_id_for(pid), expected);
+        assert_eq!(tracker.last_app.as_ref().map(|(p, _)| *p), Some(pid));
+    }
+}
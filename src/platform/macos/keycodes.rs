@@ -1,7 +1,9 @@
 //! macOS virtual key code (CGKeyCode, u16) <-> KeyCode mapping.
 //!
 //! Key codes are physical key positions per Apple HIToolbox/Events.h.
-//! They are layout-independent: this mapping assumes an ANSI keyboard.
+//! They are layout-independent: this mapping assumes an ANSI keyboard, plus
+//! the ISO Section key (`IntlBackslash`) that ISO keyboards add and the Yen,
+//! Ro, Eisu, and Kana keys that JIS keyboards add.
 //!
 //! Left/right modifier variants both map to the canonical KeyCode in
 //! `vkcode_to_keycode`. `keycode_to_vkcode` emits the left variant for
@@ -11,7 +13,8 @@ use crate::platform::KeyCode;
 
 /// Converts a macOS CGKeyCode to a canonical `KeyCode`.
 ///
-/// Returns `None` for unmapped codes (media keys, numpad decimal, etc.).
+/// Returns `None` for unmapped codes (media keys, which arrive as
+/// system-defined events, F16-F20 stand-ins, etc.).
 pub fn vkcode_to_keycode(vk: u16) -> Option<KeyCode> {
     match vk {
         // Letters
@@ -78,11 +81,15 @@ pub fn vkcode_to_keycode(vk: u16) -> Option<KeyCode> {
         0x5A => Some(KeyCode::F20),
         // F21-F24 have no standard macOS virtual key codes.
 
-        // Modifiers: left and right variants both map to the canonical form.
-        0x3B | 0x3E => Some(KeyCode::Ctrl),
-        0x38 | 0x3C => Some(KeyCode::Shift),
-        0x3A | 0x3D => Some(KeyCode::Alt),
-        0x37 | 0x36 => Some(KeyCode::Meta),
+        // Modifiers, by side
+        0x3B => Some(KeyCode::LeftCtrl),
+        0x38 => Some(KeyCode::LeftShift),
+        0x3A => Some(KeyCode::LeftAlt),
+        0x37 => Some(KeyCode::LeftMeta),
+        0x3E => Some(KeyCode::RightCtrl),
+        0x3C => Some(KeyCode::RightShift),
+        0x3D => Some(KeyCode::RightAlt),
+        0x36 => Some(KeyCode::RightMeta),
 
         // Navigation and editing
         0x31 => Some(KeyCode::Space),
@@ -101,10 +108,12 @@ pub fn vkcode_to_keycode(vk: u16) -> Option<KeyCode> {
         0x7B => Some(KeyCode::Left),
         0x7C => Some(KeyCode::Right),
 
-        // Lock keys
+        // Lock and system keys
         0x39 => Some(KeyCode::CapsLock),
         // kVK_ANSI_KeypadClear (0x47) acts as NumLock on PC-layout keyboards.
         0x47 => Some(KeyCode::NumLock),
+        // kVK_ContextualMenu: the Menu key on PC keyboards attached to a Mac.
+        0x6E => Some(KeyCode::Menu),
 
         // Numeric keypad
         0x52 => Some(KeyCode::Numpad0),
@@ -122,7 +131,8 @@ pub fn vkcode_to_keycode(vk: u16) -> Option<KeyCode> {
         0x43 => Some(KeyCode::NumpadMul),
         0x4B => Some(KeyCode::NumpadDiv),
         0x4C => Some(KeyCode::NumpadEnter),
-        // kVK_ANSI_KeypadDecimal (0x41) has no KeyCode equivalent.
+        0x41 => Some(KeyCode::NumpadDecimal), // kVK_ANSI_KeypadDecimal
+        0x51 => Some(KeyCode::NumpadEquals),  // kVK_ANSI_KeypadEquals
 
         // Punctuation / symbol keys
         0x32 => Some(KeyCode::Backtick),
@@ -136,11 +146,29 @@ pub fn vkcode_to_keycode(vk: u16) -> Option<KeyCode> {
         0x2B => Some(KeyCode::Comma),
         0x2F => Some(KeyCode::Period),
         0x2C => Some(KeyCode::Slash),
+        0x0A => Some(KeyCode::IntlBackslash), // kVK_ISO_Section
+        0x5D => Some(KeyCode::IntlYen),       // kVK_JIS_Yen
+        0x5E => Some(KeyCode::IntlRo),        // kVK_JIS_Underscore
+
+        // Input method keys
+        0x68 => Some(KeyCode::Lang1), // kVK_JIS_Kana
+        0x66 => Some(KeyCode::Lang2), // kVK_JIS_Eisu
 
         _ => None,
     }
 }
 
+/// The key an F13-F15 code stands for under `[macos] fkeys_as_system_keys`:
+/// `PrintScreen`, `ScrollLock`, and `Pause`. Other keys are returned as is.
+pub fn as_system_key(key: KeyCode) -> KeyCode {
+    match key {
+        KeyCode::F13 => KeyCode::PrintScreen,
+        KeyCode::F14 => KeyCode::ScrollLock,
+        KeyCode::F15 => KeyCode::Pause,
+        other => other,
+    }
+}
+
 /// Converts a canonical `KeyCode` to a macOS CGKeyCode.
 ///
 /// Returns `None` for keys with no standard macOS virtual key code (F21-F24).
@@ -213,10 +241,14 @@ pub fn keycode_to_vkcode(key: KeyCode) -> Option<u16> {
         KeyCode::F21 | KeyCode::F22 | KeyCode::F23 | KeyCode::F24 => None,
 
         // Modifiers: inject as left-hand variant.
-        KeyCode::Ctrl => Some(0x3B),
-        KeyCode::Shift => Some(0x38),
-        KeyCode::Alt => Some(0x3A),
-        KeyCode::Meta => Some(0x37),
+        KeyCode::Ctrl | KeyCode::LeftCtrl => Some(0x3B),
+        KeyCode::Shift | KeyCode::LeftShift => Some(0x38),
+        KeyCode::Alt | KeyCode::LeftAlt => Some(0x3A),
+        KeyCode::Meta | KeyCode::LeftMeta => Some(0x37),
+        KeyCode::RightCtrl => Some(0x3E),
+        KeyCode::RightShift => Some(0x3C),
+        KeyCode::RightAlt => Some(0x3D),
+        KeyCode::RightMeta => Some(0x36),
 
         // Navigation and editing
         KeyCode::Space => Some(0x31),
@@ -235,9 +267,36 @@ pub fn keycode_to_vkcode(key: KeyCode) -> Option<u16> {
         KeyCode::Left => Some(0x7B),
         KeyCode::Right => Some(0x7C),
 
-        // Lock keys
+        // Lock and system keys
         KeyCode::CapsLock => Some(0x39),
         KeyCode::NumLock => Some(0x47),
+        KeyCode::Menu => Some(0x6E),
+
+        // Media keys have no virtual key code: they are system-defined
+        // events, handled by `media`.
+        KeyCode::VolumeUp
+        | KeyCode::VolumeDown
+        | KeyCode::Mute
+        | KeyCode::PlayPause
+        | KeyCode::NextTrack
+        | KeyCode::PrevTrack
+        | KeyCode::BrightnessUp
+        | KeyCode::BrightnessDown => None,
+
+        // No media event exists for Stop, and the browser and launch keys
+        // never reach the event tap.
+        KeyCode::Stop
+        | KeyCode::BrowserBack
+        | KeyCode::BrowserForward
+        | KeyCode::BrowserRefresh
+        | KeyCode::BrowserStop
+        | KeyCode::BrowserSearch
+        | KeyCode::BrowserFavorites
+        | KeyCode::BrowserHome
+        | KeyCode::LaunchMail
+        | KeyCode::LaunchMedia
+        | KeyCode::LaunchApp1
+        | KeyCode::LaunchApp2 => None,
 
         // Numeric keypad
         KeyCode::Numpad0 => Some(0x52),
@@ -255,6 +314,8 @@ pub fn keycode_to_vkcode(key: KeyCode) -> Option<u16> {
         KeyCode::NumpadMul => Some(0x43),
         KeyCode::NumpadDiv => Some(0x4B),
         KeyCode::NumpadEnter => Some(0x4C),
+        KeyCode::NumpadDecimal => Some(0x41),
+        KeyCode::NumpadEquals => Some(0x51),
 
         // Punctuation / symbol keys
         KeyCode::Backtick => Some(0x32),
@@ -268,9 +329,91 @@ pub fn keycode_to_vkcode(key: KeyCode) -> Option<u16> {
         KeyCode::Comma => Some(0x2B),
         KeyCode::Period => Some(0x2F),
         KeyCode::Slash => Some(0x2C),
+        KeyCode::IntlBackslash => Some(0x0A),
+        KeyCode::IntlYen => Some(0x5D),
+        KeyCode::IntlRo => Some(0x5E),
+
+        // Input method keys
+        KeyCode::Lang1 => Some(0x68),
+        KeyCode::Lang2 => Some(0x66),
     }
 }
 
+// ---------------------------------------------------------------------------
+// Modifier flags
+// ---------------------------------------------------------------------------
+
+/// CGEventFlags bitmask for the Command modifier.
+pub(super) const FLAG_MASK_COMMAND: u64 = 0x0010_0000;
+/// CGEventFlags bitmask for the Shift modifier.
+pub(super) const FLAG_MASK_SHIFT: u64 = 0x0002_0000;
+/// CGEventFlags bitmask for the Option/Alt modifier.
+pub(super) const FLAG_MASK_ALT: u64 = 0x0008_0000;
+/// CGEventFlags bitmask for the Control modifier.
+pub(super) const FLAG_MASK_CTRL: u64 = 0x0004_0000;
+
+/// Modifier keys in the order their transitions are reported, paired with
+/// their CGEventFlags mask.
+pub(super) const MODIFIER_FLAGS: [(KeyCode, u64); 4] = [
+    (KeyCode::Ctrl, FLAG_MASK_CTRL),
+    (KeyCode::Shift, FLAG_MASK_SHIFT),
+    (KeyCode::Alt, FLAG_MASK_ALT),
+    (KeyCode::Meta, FLAG_MASK_COMMAND),
+];
+
+/// Device-dependent CGEventFlags bits saying which side of a modifier is held
+/// (NX_DEVICELCTLKEYMASK and friends, from IOLLEvent.h). Real events set them
+/// alongside the generic mask.
+pub(super) const DEVICE_LEFT_CTRL: u64 = 0x0000_0001;
+pub(super) const DEVICE_LEFT_SHIFT: u64 = 0x0000_0002;
+pub(super) const DEVICE_RIGHT_SHIFT: u64 = 0x0000_0004;
+pub(super) const DEVICE_LEFT_COMMAND: u64 = 0x0000_0008;
+pub(super) const DEVICE_RIGHT_COMMAND: u64 = 0x0000_0010;
+pub(super) const DEVICE_LEFT_ALT: u64 = 0x0000_0020;
+pub(super) const DEVICE_RIGHT_ALT: u64 = 0x0000_0040;
+pub(super) const DEVICE_RIGHT_CTRL: u64 = 0x0000_2000;
+
+/// Every device-dependent side bit.
+pub(super) const DEVICE_SIDE_MASK: u64 = DEVICE_LEFT_CTRL
+    | DEVICE_LEFT_SHIFT
+    | DEVICE_RIGHT_SHIFT
+    | DEVICE_LEFT_COMMAND
+    | DEVICE_RIGHT_COMMAND
+    | DEVICE_LEFT_ALT
+    | DEVICE_RIGHT_ALT
+    | DEVICE_RIGHT_CTRL;
+
+/// Each side of each modifier: the key, its generic mask, and its side bit,
+/// left before right.
+pub(super) const MODIFIER_SIDES: [(KeyCode, u64, u64); 8] = [
+    (KeyCode::LeftCtrl, FLAG_MASK_CTRL, DEVICE_LEFT_CTRL),
+    (KeyCode::RightCtrl, FLAG_MASK_CTRL, DEVICE_RIGHT_CTRL),
+    (KeyCode::LeftShift, FLAG_MASK_SHIFT, DEVICE_LEFT_SHIFT),
+    (KeyCode::RightShift, FLAG_MASK_SHIFT, DEVICE_RIGHT_SHIFT),
+    (KeyCode::LeftAlt, FLAG_MASK_ALT, DEVICE_LEFT_ALT),
+    (KeyCode::RightAlt, FLAG_MASK_ALT, DEVICE_RIGHT_ALT),
+    (KeyCode::LeftMeta, FLAG_MASK_COMMAND, DEVICE_LEFT_COMMAND),
+    (KeyCode::RightMeta, FLAG_MASK_COMMAND, DEVICE_RIGHT_COMMAND),
+];
+
+/// The generic mask and side bit of a modifier key, or `None` for any other
+/// key. A unified modifier gets the left key's, which is what it injects.
+pub(super) fn modifier_bits(key: KeyCode) -> Option<(u64, u64)> {
+    MODIFIER_SIDES
+        .iter()
+        // The left key comes first, so it is found for the unified one.
+        .find(|(modifier, _, _)| *modifier == key || modifier.unify_side() == key)
+        .map(|&(_, mask, side)| (mask, side))
+}
+
+/// The side bits that belong to the modifier with generic mask `mask`.
+pub(super) fn side_bits(mask: u64) -> u64 {
+    MODIFIER_SIDES
+        .iter()
+        .filter(|(_, m, _)| *m == mask)
+        .fold(0, |bits, (_, _, side)| bits | side)
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -302,6 +445,21 @@ mod tests {
         assert_eq!(vkcode_to_keycode(0x5A), Some(KeyCode::F20));
     }
 
+    #[test]
+    fn system_keys_replace_f13_to_f15_only() {
+        assert_eq!(as_system_key(KeyCode::F13), KeyCode::PrintScreen);
+        assert_eq!(as_system_key(KeyCode::F14), KeyCode::ScrollLock);
+        assert_eq!(as_system_key(KeyCode::F15), KeyCode::Pause);
+        assert_eq!(as_system_key(KeyCode::F16), KeyCode::F16);
+        // Injection reaches the same code either way.
+        for key in [KeyCode::F13, KeyCode::F14, KeyCode::F15] {
+            assert_eq!(
+                keycode_to_vkcode(as_system_key(key)),
+                keycode_to_vkcode(key)
+            );
+        }
+    }
+
     #[test]
     fn spot_check_navigation_keys() {
         assert_eq!(vkcode_to_keycode(0x7E), Some(KeyCode::Up));
@@ -313,16 +471,42 @@ mod tests {
     }
 
     #[test]
-    fn right_modifiers_map_to_canonical() {
-        // Right-side variants must produce the same canonical KeyCode as left.
-        assert_eq!(vkcode_to_keycode(0x3B), Some(KeyCode::Ctrl));
-        assert_eq!(vkcode_to_keycode(0x3E), Some(KeyCode::Ctrl));
-        assert_eq!(vkcode_to_keycode(0x38), Some(KeyCode::Shift));
-        assert_eq!(vkcode_to_keycode(0x3C), Some(KeyCode::Shift));
-        assert_eq!(vkcode_to_keycode(0x3A), Some(KeyCode::Alt));
-        assert_eq!(vkcode_to_keycode(0x3D), Some(KeyCode::Alt));
-        assert_eq!(vkcode_to_keycode(0x37), Some(KeyCode::Meta));
-        assert_eq!(vkcode_to_keycode(0x36), Some(KeyCode::Meta));
+    fn sided_modifiers_round_trip() {
+        let cases: &[(KeyCode, u16)] = &[
+            (KeyCode::LeftCtrl, 0x3B),
+            (KeyCode::RightCtrl, 0x3E),
+            (KeyCode::LeftShift, 0x38),
+            (KeyCode::RightShift, 0x3C),
+            (KeyCode::LeftAlt, 0x3A),
+            (KeyCode::RightAlt, 0x3D),
+            (KeyCode::LeftMeta, 0x37),
+            (KeyCode::RightMeta, 0x36),
+        ];
+        for &(key, vk) in cases {
+            assert_eq!(keycode_to_vkcode(key), Some(vk), "{key:?} -> vkcode");
+            assert_eq!(vkcode_to_keycode(vk), Some(key), "{vk:#04x} -> KeyCode");
+            if key.is_left_modifier() {
+                // The unified variant injects as the left key.
+                assert_eq!(keycode_to_vkcode(key.unify_side()), Some(vk));
+            }
+        }
+    }
+
+    #[test]
+    fn side_bits_cover_both_keys_of_a_modifier() {
+        assert_eq!(
+            side_bits(FLAG_MASK_COMMAND),
+            DEVICE_LEFT_COMMAND | DEVICE_RIGHT_COMMAND
+        );
+        assert_eq!(
+            modifier_bits(KeyCode::RightCtrl),
+            Some((FLAG_MASK_CTRL, DEVICE_RIGHT_CTRL))
+        );
+        assert_eq!(
+            modifier_bits(KeyCode::Ctrl),
+            Some((FLAG_MASK_CTRL, DEVICE_LEFT_CTRL))
+        );
+        assert_eq!(modifier_bits(KeyCode::A), None);
     }
 
     #[test]
@@ -341,17 +525,25 @@ mod tests {
             (KeyCode::F1, 0x7A),
             (KeyCode::F12, 0x6F),
             (KeyCode::F13, 0x69),
-            (KeyCode::Ctrl, 0x3B),
-            (KeyCode::Shift, 0x38),
-            (KeyCode::Alt, 0x3A),
-            (KeyCode::Meta, 0x37),
+            (KeyCode::LeftCtrl, 0x3B),
+            (KeyCode::LeftShift, 0x38),
+            (KeyCode::LeftAlt, 0x3A),
+            (KeyCode::LeftMeta, 0x37),
             (KeyCode::Enter, 0x24),
             (KeyCode::Space, 0x31),
             (KeyCode::Escape, 0x35),
             (KeyCode::Backspace, 0x33),
             (KeyCode::Delete, 0x75),
             (KeyCode::NumpadEnter, 0x4C),
+            (KeyCode::NumpadDecimal, 0x41),
+            (KeyCode::NumpadEquals, 0x51),
             (KeyCode::Backtick, 0x32),
+            (KeyCode::IntlBackslash, 0x0A),
+            (KeyCode::IntlYen, 0x5D),
+            (KeyCode::IntlRo, 0x5E),
+            (KeyCode::Lang1, 0x68),
+            (KeyCode::Lang2, 0x66),
+            (KeyCode::Menu, 0x6E),
         ];
         for &(key, vk) in cases {
             assert_eq!(keycode_to_vkcode(key), Some(vk), "{key:?} -> vkcode");
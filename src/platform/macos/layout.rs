@@ -0,0 +1,311 @@
+//! The key that types a character on the current keyboard layout.
+//!
+//! `keycode_to_vkcode` names keys by their US ANSI position, which is the
+//! wrong key for punctuation and some letters on most other layouts.
+//! `LayoutResolver` asks the current layout instead: it runs every virtual
+//! key code through `UCKeyTranslate`, with no modifiers, Shift, Option, and
+//! Shift+Option, and keeps for each character the stroke that needs the
+//! fewest modifiers. Dead keys, which type nothing on their own, are left
+//! out.
+//!
+//! The table is built on first use and rebuilt when the selected layout
+//! changes. The input source change notification is only delivered to a
+//! thread running the main run loop, which the engine thread does not, so
+//! the resolver compares the current layout's input source ID on each use
+//! instead.
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+
+use super::focus::string_from_cf;
+use super::keycodes::{DEVICE_LEFT_ALT, DEVICE_LEFT_SHIFT, FLAG_MASK_ALT, FLAG_MASK_SHIFT};
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+/// Virtual key codes go up to 0x7F.
+const VKCODE_COUNT: u16 = 0x80;
+
+/// kUCKeyActionDown.
+const UC_KEY_ACTION_DOWN: u16 = 0;
+
+/// Carbon `shiftKey` and `optionKey`, shifted right by 8 as
+/// `UCKeyTranslate` expects its modifier state.
+const UC_SHIFT: u32 = 0x02;
+const UC_OPTION: u32 = 0x08;
+
+// ---------------------------------------------------------------------------
+// Raw FFI
+// ---------------------------------------------------------------------------
+
+type CFDataRef = *const c_void;
+type CFStringRef = *const c_void;
+type TISInputSourceRef = *const c_void;
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    fn TISCopyCurrentKeyboardLayoutInputSource() -> TISInputSourceRef;
+    fn TISGetInputSourceProperty(source: TISInputSourceRef, key: CFStringRef) -> *const c_void;
+    fn LMGetKbdType() -> u8;
+    fn UCKeyTranslate(
+        layout: *const c_void,
+        virtual_key_code: u16,
+        key_action: u16,
+        modifier_key_state: u32,
+        keyboard_type: u32,
+        key_translate_options: u32,
+        dead_key_state: *mut u32,
+        max_string_length: usize,
+        actual_string_length: *mut usize,
+        unicode_string: *mut u16,
+    ) -> i32;
+
+    static kTISPropertyInputSourceID: CFStringRef;
+    static kTISPropertyUnicodeKeyLayoutData: CFStringRef;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFDataGetBytePtr(data: CFDataRef) -> *const u8;
+    fn CFRelease(cf: *const c_void);
+}
+
+// ---------------------------------------------------------------------------
+// Reverse table
+// ---------------------------------------------------------------------------
+
+/// A key press that types a character: the virtual key code and the
+/// modifiers to hold for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct KeyStroke {
+    pub(super) vkcode: u16,
+    pub(super) shift: bool,
+    pub(super) option: bool,
+}
+
+impl KeyStroke {
+    /// The CGEventFlags to post the key with.
+    pub(super) fn flags(self) -> u64 {
+        let mut flags = 0;
+        if self.shift {
+            flags |= FLAG_MASK_SHIFT | DEVICE_LEFT_SHIFT;
+        }
+        if self.option {
+            flags |= FLAG_MASK_ALT | DEVICE_LEFT_ALT;
+        }
+        flags
+    }
+
+    fn modifier_count(self) -> u8 {
+        u8::from(self.shift) + u8::from(self.option)
+    }
+}
+
+/// Characters to strokes, from what each stroke types. `None` marks a stroke
+/// that types nothing, such as a dead key. Control characters are skipped.
+/// Where several strokes type the same character, the one with the fewest
+/// modifiers wins, then the lowest key code, which puts the top row ahead
+/// of the numeric keypad.
+fn reverse(
+    translations: impl IntoIterator<Item = (KeyStroke, Option<char>)>,
+) -> HashMap<char, KeyStroke> {
+    let mut table: HashMap<char, KeyStroke> = HashMap::new();
+    for (stroke, c) in translations {
+        let Some(c) = c.filter(|c| !c.is_control()) else {
+            continue;
+        };
+        let better = table.get(&c).map_or(true, |have| {
+            (stroke.modifier_count(), stroke.vkcode) < (have.modifier_count(), have.vkcode)
+        });
+        if better {
+            table.insert(c, stroke);
+        }
+    }
+    table
+}
+
+// ---------------------------------------------------------------------------
+// Resolver
+// ---------------------------------------------------------------------------
+
+/// Looks characters up on the current layout, caching the table per layout.
+#[derive(Debug, Default)]
+pub(super) struct LayoutResolver {
+    /// Input source ID the table was built for.
+    source_id: Option<String>,
+    table: HashMap<char, KeyStroke>,
+}
+
+impl LayoutResolver {
+    /// Rebuild the table if the layout changed since the last call. Call
+    /// once before a run of `resolve` calls.
+    pub(super) fn refresh(&mut self) {
+        unsafe {
+            let source = TISCopyCurrentKeyboardLayoutInputSource();
+            if source.is_null() {
+                self.source_id = None;
+                self.table.clear();
+                return;
+            }
+            let id = TISGetInputSourceProperty(source, kTISPropertyInputSourceID);
+            let id = if id.is_null() {
+                None
+            } else {
+                string_from_cf(id)
+            };
+            if id.is_none() || id != self.source_id {
+                self.table = translate_all(source);
+                log::debug!(
+                    "executor: keyboard layout {} types {} characters",
+                    id.as_deref().unwrap_or("(unknown)"),
+                    self.table.len()
+                );
+                self.source_id = id;
+            }
+            CFRelease(source);
+        }
+    }
+
+    /// The stroke that types `c` on the layout as of the last `refresh`.
+    pub(super) fn resolve(&self, c: char) -> Option<KeyStroke> {
+        self.table.get(&c).copied()
+    }
+}
+
+/// The reverse table of `source`'s Unicode key layout. Empty for layouts
+/// without one.
+unsafe fn translate_all(source: TISInputSourceRef) -> HashMap<char, KeyStroke> {
+    let data = TISGetInputSourceProperty(source, kTISPropertyUnicodeKeyLayoutData);
+    if data.is_null() {
+        return HashMap::new();
+    }
+    let layout = CFDataGetBytePtr(data).cast::<c_void>();
+    let keyboard_type = u32::from(LMGetKbdType());
+    let mut translations = Vec::new();
+    for vkcode in 0..VKCODE_COUNT {
+        for (shift, option) in [(false, false), (true, false), (false, true), (true, true)] {
+            let stroke = KeyStroke {
+                vkcode,
+                shift,
+                option,
+            };
+            let mut modifiers = 0;
+            if shift {
+                modifiers |= UC_SHIFT;
+            }
+            if option {
+                modifiers |= UC_OPTION;
+            }
+            let mut dead_key_state = 0u32;
+            let mut buf = [0u16; 4];
+            let mut len = 0usize;
+            let status = UCKeyTranslate(
+                layout,
+                vkcode,
+                UC_KEY_ACTION_DOWN,
+                modifiers,
+                keyboard_type,
+                0,
+                &mut dead_key_state,
+                buf.len(),
+                &mut len,
+                buf.as_mut_ptr(),
+            );
+            let typed = if status != 0 || dead_key_state != 0 {
+                None
+            } else {
+                single_char(&buf[..len])
+            };
+            translations.push((stroke, typed));
+        }
+    }
+    reverse(translations)
+}
+
+/// The character `units` encode, if exactly one.
+fn single_char(units: &[u16]) -> Option<char> {
+    let mut chars = char::decode_utf16(units.iter().copied());
+    match (chars.next(), chars.next()) {
+        (Some(Ok(c)), None) => Some(c),
+        _ => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Part of the German layout, in the form `translate_all` collects it.
+    const GERMAN: &str = include_str!("testdata/layout_german.txt");
+
+    fn parse_fixture(text: &str) -> Vec<(KeyStroke, Option<char>)> {
+        text.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|line| {
+                let mut fields = line.split_whitespace();
+                let vk = fields.next().unwrap().trim_start_matches("0x");
+                let mods = fields.next().unwrap();
+                let typed = fields.next().unwrap();
+                let stroke = KeyStroke {
+                    vkcode: u16::from_str_radix(vk, 16).unwrap(),
+                    shift: mods.contains('S'),
+                    option: mods.contains('O'),
+                };
+                let c = match typed.strip_prefix("U+") {
+                    _ if typed == "dead" => None,
+                    Some(hex) => char::from_u32(u32::from_str_radix(hex, 16).unwrap()),
+                    None => typed.chars().next(),
+                };
+                (stroke, c)
+            })
+            .collect()
+    }
+
+    fn stroke(vkcode: u16, shift: bool, option: bool) -> KeyStroke {
+        KeyStroke {
+            vkcode,
+            shift,
+            option,
+        }
+    }
+
+    #[test]
+    fn german_layout_reverses_to_its_own_keys() {
+        let table = reverse(parse_fixture(GERMAN));
+        // Y and Z swap places with the US layout.
+        assert_eq!(table[&'z'], stroke(0x10, false, false));
+        assert_eq!(table[&'y'], stroke(0x06, false, false));
+        assert_eq!(table[&';'], stroke(0x2B, true, false));
+        assert_eq!(table[&'@'], stroke(0x25, false, true));
+        assert_eq!(table[&'€'], stroke(0x0E, false, true));
+        assert_eq!(table[&'ö'], stroke(0x29, false, false));
+        assert_eq!(table[&' '], stroke(0x31, false, false));
+        // The top row wins over the keypad, and fewer modifiers over both.
+        assert_eq!(table[&'1'], stroke(0x12, false, false));
+        assert_eq!(table[&'/'], stroke(0x4B, false, false));
+    }
+
+    #[test]
+    fn dead_keys_and_control_characters_are_not_typed() {
+        let table = reverse(parse_fixture(GERMAN));
+        assert!(!table.contains_key(&'\r'));
+        assert!(table.values().all(|s| s.vkcode != 0x18));
+    }
+
+    #[test]
+    fn stroke_flags_hold_left_shift_and_option() {
+        assert_eq!(stroke(0x25, false, false).flags(), 0);
+        assert_eq!(
+            stroke(0x25, true, true).flags(),
+            FLAG_MASK_SHIFT | DEVICE_LEFT_SHIFT | FLAG_MASK_ALT | DEVICE_LEFT_ALT
+        );
+        assert_eq!(single_char(&[0xD83D, 0xDE00]), Some('😀'));
+        assert_eq!(single_char(&[0x61, 0x62]), None);
+    }
+}
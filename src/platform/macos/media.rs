@@ -0,0 +1,354 @@
+//! Media keys as system-defined (NX_SYSDEFINED) events.
+//!
+//! Volume, brightness, and playback keys on Mac keyboards do not produce
+//! KeyDown/KeyUp events. They arrive as `NSEventTypeSystemDefined` events of
+//! subtype `NX_SUBTYPE_AUX_CONTROL_BUTTONS`, whose `data1` packs an
+//! `NX_KEYTYPE_*` code with a down/up state. CGEvent has no public accessor
+//! for `data1`, so reading and building these events goes through `NSEvent`
+//! via the Objective-C runtime.
+//!
+//! Injected media events are posted at the HID level, where the system acts
+//! on them, and tagged with `INJECTED_MARKER` so capture passes them through
+//! instead of capturing them again.
+//!
+//! Whether the top row sends media or function keys depends on the "Use F1,
+//! F2, etc. keys as standard function keys" setting, read by
+//! `function_keys_are_standard`.
+
+use std::ffi::{c_char, c_void};
+
+use super::focus::cfstring;
+use crate::platform::{KeyCode, KeyState, PlatformError};
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+/// CGEventType (and NSEventType) of system-defined events.
+pub const CG_EVENT_SYSTEM_DEFINED: u32 = 14;
+
+/// NSEvent subtype carrying media and other auxiliary keys.
+const NX_SUBTYPE_AUX_CONTROL_BUTTONS: i16 = 8;
+
+/// `data1` key state byte for a press.
+const NX_KEYDOWN: i64 = 0x0A;
+/// `data1` key state byte for a release.
+const NX_KEYUP: i64 = 0x0B;
+
+const NX_KEYTYPE_SOUND_UP: u32 = 0;
+const NX_KEYTYPE_SOUND_DOWN: u32 = 1;
+const NX_KEYTYPE_BRIGHTNESS_UP: u32 = 2;
+const NX_KEYTYPE_BRIGHTNESS_DOWN: u32 = 3;
+const NX_KEYTYPE_MUTE: u32 = 7;
+const NX_KEYTYPE_PLAY: u32 = 16;
+const NX_KEYTYPE_NEXT: u32 = 17;
+const NX_KEYTYPE_PREVIOUS: u32 = 18;
+/// Sent by the "next" key on Apple keyboards.
+const NX_KEYTYPE_FAST: u32 = 19;
+/// Sent by the "previous" key on Apple keyboards.
+const NX_KEYTYPE_REWIND: u32 = 20;
+
+/// kCGEventSourceUserData: a CGEvent field free for the poster's own use.
+pub(super) const CG_EVENT_SOURCE_USER_DATA: u32 = 42;
+
+/// Value of `CG_EVENT_SOURCE_USER_DATA` on media and mouse events posted by
+/// the executor ("PCUF").
+pub(super) const INJECTED_MARKER: i64 = 0x5043_5546;
+
+/// kCGHIDEventTap: post where hardware events enter, so the system handles
+/// the media key.
+const CG_HID_EVENT_TAP: u32 = 0;
+
+// ---------------------------------------------------------------------------
+// Raw FFI
+// ---------------------------------------------------------------------------
+
+type CGEventRef = *mut c_void;
+type Id = *mut c_void;
+type Sel = *mut c_void;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct NSPoint {
+    x: f64,
+    y: f64,
+}
+
+#[link(name = "objc")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> Id;
+    fn sel_registerName(name: *const c_char) -> Sel;
+    /// Cast to the exact signature of each message before calling.
+    fn objc_msgSend();
+    fn objc_autoreleasePoolPush() -> *mut c_void;
+    fn objc_autoreleasePoolPop(pool: *mut c_void);
+}
+
+// `NSEvent` lives in AppKit.
+#[link(name = "AppKit", kind = "framework")]
+extern "C" {}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn CGEventPost(tap_location: u32, event: CGEventRef);
+    fn CGEventGetIntegerValueField(event: CGEventRef, field: u32) -> i64;
+    fn CGEventSetIntegerValueField(event: CGEventRef, field: u32, value: i64);
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRetain(cf: *const c_void) -> *const c_void;
+    fn CFRelease(cf: *const c_void);
+    fn CFPreferencesGetAppBooleanValue(
+        key: *const c_void,
+        application_id: *const c_void,
+        key_exists_and_has_valid_format: *mut bool,
+    ) -> bool;
+    static kCFPreferencesAnyApplication: *const c_void;
+}
+
+/// Runs `f` inside an autorelease pool, so the NSEvents it creates are freed
+/// on this thread, which has no pool of its own.
+unsafe fn with_autorelease_pool<T>(f: impl FnOnce() -> T) -> T {
+    let pool = objc_autoreleasePoolPush();
+    let result = f();
+    objc_autoreleasePoolPop(pool);
+    result
+}
+
+unsafe fn class(name: &[u8]) -> Id {
+    objc_getClass(name.as_ptr().cast())
+}
+
+unsafe fn sel(name: &[u8]) -> Sel {
+    sel_registerName(name.as_ptr().cast())
+}
+
+// ---------------------------------------------------------------------------
+// Key mapping
+// ---------------------------------------------------------------------------
+
+/// Converts an `NX_KEYTYPE_*` code to a `KeyCode`.
+fn keytype_to_keycode(keytype: u32) -> Option<KeyCode> {
+    match keytype {
+        NX_KEYTYPE_SOUND_UP => Some(KeyCode::VolumeUp),
+        NX_KEYTYPE_SOUND_DOWN => Some(KeyCode::VolumeDown),
+        NX_KEYTYPE_MUTE => Some(KeyCode::Mute),
+        NX_KEYTYPE_PLAY => Some(KeyCode::PlayPause),
+        NX_KEYTYPE_NEXT | NX_KEYTYPE_FAST => Some(KeyCode::NextTrack),
+        NX_KEYTYPE_PREVIOUS | NX_KEYTYPE_REWIND => Some(KeyCode::PrevTrack),
+        NX_KEYTYPE_BRIGHTNESS_UP => Some(KeyCode::BrightnessUp),
+        NX_KEYTYPE_BRIGHTNESS_DOWN => Some(KeyCode::BrightnessDown),
+        _ => None,
+    }
+}
+
+/// Converts a media `KeyCode` to the `NX_KEYTYPE_*` code injected for it, or
+/// `None` for keys that are not media keys.
+pub fn keycode_to_keytype(key: KeyCode) -> Option<u32> {
+    match key {
+        KeyCode::VolumeUp => Some(NX_KEYTYPE_SOUND_UP),
+        KeyCode::VolumeDown => Some(NX_KEYTYPE_SOUND_DOWN),
+        KeyCode::Mute => Some(NX_KEYTYPE_MUTE),
+        KeyCode::PlayPause => Some(NX_KEYTYPE_PLAY),
+        KeyCode::NextTrack => Some(NX_KEYTYPE_NEXT),
+        KeyCode::PrevTrack => Some(NX_KEYTYPE_PREVIOUS),
+        KeyCode::BrightnessUp => Some(NX_KEYTYPE_BRIGHTNESS_UP),
+        KeyCode::BrightnessDown => Some(NX_KEYTYPE_BRIGHTNESS_DOWN),
+        _ => None,
+    }
+}
+
+/// Splits `data1` of an aux control event into its key type and state.
+/// Returns `None` for a state byte that is neither down nor up.
+fn decode_data1(data1: i64) -> Option<(u32, KeyState)> {
+    let keytype = ((data1 >> 16) & 0xFFFF) as u32;
+    let state = match (data1 >> 8) & 0xFF {
+        NX_KEYDOWN => KeyState::Down,
+        NX_KEYUP => KeyState::Up,
+        _ => return None,
+    };
+    Some((keytype, state))
+}
+
+/// Packs a key type and state into `data1`.
+fn encode_data1(keytype: u32, state: KeyState) -> i64 {
+    let state = match state {
+        KeyState::Down => NX_KEYDOWN,
+        KeyState::Up => NX_KEYUP,
+    };
+    (i64::from(keytype) << 16) | (state << 8)
+}
+
+// ---------------------------------------------------------------------------
+// Capture
+// ---------------------------------------------------------------------------
+
+/// Whether an event carries `INJECTED_MARKER`, i.e. the executor posted it.
+pub unsafe fn is_injected(event: CGEventRef) -> bool {
+    CGEventGetIntegerValueField(event, CG_EVENT_SOURCE_USER_DATA) == INJECTED_MARKER
+}
+
+/// Reads the media key of a system-defined event, or `None` if it is another
+/// kind of system-defined event or an unmapped key type.
+pub unsafe fn read(event: CGEventRef) -> Option<(KeyCode, KeyState)> {
+    let (subtype, data1) = with_autorelease_pool(|| {
+        let event_with_cg: unsafe extern "C" fn(Id, Sel, CGEventRef) -> Id =
+            std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+        let ns_event = event_with_cg(class(b"NSEvent\0"), sel(b"eventWithCGEvent:\0"), event);
+        if ns_event.is_null() {
+            return (0, 0);
+        }
+        let get_subtype: unsafe extern "C" fn(Id, Sel) -> i16 =
+            std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+        let get_data1: unsafe extern "C" fn(Id, Sel) -> isize =
+            std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+        (
+            get_subtype(ns_event, sel(b"subtype\0")),
+            get_data1(ns_event, sel(b"data1\0")) as i64,
+        )
+    });
+    if subtype != NX_SUBTYPE_AUX_CONTROL_BUTTONS {
+        return None;
+    }
+    let (keytype, state) = decode_data1(data1)?;
+    Some((keytype_to_keycode(keytype)?, state))
+}
+
+// ---------------------------------------------------------------------------
+// Injection
+// ---------------------------------------------------------------------------
+
+/// Posts a media key press or release.
+pub fn inject(keytype: u32, state: KeyState) -> Result<(), PlatformError> {
+    unsafe {
+        let event = with_autorelease_pool(|| {
+            let other_event: unsafe extern "C" fn(
+                Id,
+                Sel,
+                usize,
+                NSPoint,
+                usize,
+                f64,
+                isize,
+                Id,
+                i16,
+                isize,
+                isize,
+            ) -> Id = std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+            let data1 = encode_data1(keytype, state);
+            let ns_event = other_event(
+                class(b"NSEvent\0"),
+                sel(b"otherEventWithType:location:modifierFlags:timestamp:windowNumber:context:subtype:data1:data2:\0"),
+                CG_EVENT_SYSTEM_DEFINED as usize,
+                NSPoint { x: 0.0, y: 0.0 },
+                // The state byte also goes in the modifier flags, as the
+                // system does for hardware media keys.
+                (data1 & 0xFF00) as usize,
+                0.0,
+                0,
+                std::ptr::null_mut(),
+                NX_SUBTYPE_AUX_CONTROL_BUTTONS,
+                data1 as isize,
+                -1,
+            );
+            if ns_event.is_null() {
+                return std::ptr::null_mut();
+            }
+            let get_cg_event: unsafe extern "C" fn(Id, Sel) -> CGEventRef =
+                std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+            let event = get_cg_event(ns_event, sel(b"CGEvent\0"));
+            // Owned by the NSEvent; keep it past the pool.
+            if !event.is_null() {
+                CFRetain(event.cast_const());
+            }
+            event
+        });
+        if event.is_null() {
+            return Err(PlatformError::Other(
+                "could not create a system-defined media key event".into(),
+            ));
+        }
+        CGEventSetIntegerValueField(event, CG_EVENT_SOURCE_USER_DATA, INJECTED_MARKER);
+        CGEventPost(CG_HID_EVENT_TAP, event);
+        CFRelease(event.cast_const());
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Function key setting
+// ---------------------------------------------------------------------------
+
+/// Whether the top row sends F1-F12 without Fn ("Use F1, F2, etc. keys as
+/// standard function keys"). `None` when the setting has never been changed,
+/// which means the default: media keys.
+pub fn function_keys_are_standard() -> Option<bool> {
+    unsafe {
+        let key = cfstring(b"com.apple.keyboard.fnState\0");
+        if key.is_null() {
+            return None;
+        }
+        let mut valid = false;
+        let value = CFPreferencesGetAppBooleanValue(key, kCFPreferencesAnyApplication, &mut valid);
+        CFRelease(key);
+        valid.then_some(value)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_MEDIA: [KeyCode; 8] = [
+        KeyCode::VolumeUp,
+        KeyCode::VolumeDown,
+        KeyCode::Mute,
+        KeyCode::PlayPause,
+        KeyCode::NextTrack,
+        KeyCode::PrevTrack,
+        KeyCode::BrightnessUp,
+        KeyCode::BrightnessDown,
+    ];
+
+    #[test]
+    fn media_keys_round_trip_through_keytype() {
+        for key in ALL_MEDIA {
+            let keytype = keycode_to_keytype(key).unwrap();
+            assert_eq!(keytype_to_keycode(keytype), Some(key), "{key:?}");
+        }
+        assert_eq!(keycode_to_keytype(KeyCode::A), None);
+    }
+
+    #[test]
+    fn apple_keyboard_track_keys_map_to_next_and_previous() {
+        assert_eq!(
+            keytype_to_keycode(NX_KEYTYPE_FAST),
+            Some(KeyCode::NextTrack)
+        );
+        assert_eq!(
+            keytype_to_keycode(NX_KEYTYPE_REWIND),
+            Some(KeyCode::PrevTrack)
+        );
+    }
+
+    #[test]
+    fn data1_round_trips() {
+        for state in [KeyState::Down, KeyState::Up] {
+            let data1 = encode_data1(NX_KEYTYPE_PLAY, state);
+            assert_eq!(decode_data1(data1), Some((NX_KEYTYPE_PLAY, state)));
+        }
+    }
+
+    #[test]
+    fn data1_from_hardware_decodes() {
+        // Volume up press with the repeat bit set, as sent while held.
+        assert_eq!(decode_data1(0x0000_0A01), Some((0, KeyState::Down)));
+        assert_eq!(decode_data1(0x0010_0B00), Some((16, KeyState::Up)));
+        assert_eq!(decode_data1(0x0010_0C00), None);
+    }
+}
@@ -2,38 +2,88 @@
 //!
 //! Capture: CGEventTap (HID level) via `MacOSCapture`.
 //! Injection: CGEventPost (synchronous) via `MacOSExecutor`.
+//! Window context: Accessibility focus polling via `focus::FocusTracker`.
+//! Keyboard identity: IOHIDManager device tracking via `devices`.
+//! Secure Event Input: detected by `secure_input`, suspends rules while on.
+//! Media keys: system-defined events, read and built by `media`.
+//! Pointer: mouse events, read and posted by `mouse`.
+//! CapsLock: optionally mapped to a function key at the HID layer by
+//! `caps_remap`.
+//! Text: typed with the current layout's keys where it has them (`layout`).
+//! Clipboard: read through `pbpaste` (`clipboard`).
+//! Session lock: loginwindow's lock notifications, followed by `session`.
 //!
 //! Both backends require Accessibility permission. `MacOSCapture::start()`
-//! calls `AXIsProcessTrusted()` and returns `PlatformError::PermissionDenied`
-//! if permission has not been granted. Guide the user to:
+//! asks for it through the system dialog when it is missing (see
+//! `permission`) and returns `PlatformError::PermissionDenied` if it is not
+//! granted. Granting it by hand is done in:
 //!   System Settings > Privacy & Security > Accessibility
 
+mod caps_remap;
 mod capture;
+mod clipboard;
+mod devices;
 mod executor;
+mod focus;
 mod keycodes;
+mod layout;
+mod media;
+mod mouse;
+mod permission;
+mod secure_input;
+mod session;
+mod system;
 
 use capture::MacOSCapture;
+use clipboard::MacOSClipboard;
 use executor::MacOSExecutor;
+pub use session::watch_session;
 
-use crate::platform::{ActionExecutor, InputCapture, PlatformError};
+use crate::platform::{ActionExecutor, Clipboard, InputCapture, PlatformError};
 
 // ---------------------------------------------------------------------------
 // Factory: input capture
 // ---------------------------------------------------------------------------
 
-/// Returns the CGEventTap-based keyboard capture backend.
+/// Returns the CGEventTap-based keyboard capture backend, the only macOS
+/// one.
 ///
-/// Accessibility permission must be granted before `start()` is called.
-/// The check happens in `start()` so that `new()` always succeeds.
-pub fn create_input_capture() -> Result<Box<dyn InputCapture>, PlatformError> {
-    Ok(Box::new(MacOSCapture::new()))
+/// Accessibility permission is checked, and asked for, in `start()` so that
+/// `new()` always succeeds. `pinned` is the `[capture] backend` config
+/// value; it must be `event_tap` if set.
+pub fn create_input_capture(pinned: Option<&str>) -> Result<Box<dyn InputCapture>, PlatformError> {
+    match pinned {
+        None | Some("event_tap") => Ok(Box::new(MacOSCapture::new())),
+        Some(name) => Err(PlatformError::Unavailable(format!(
+            "capture backend '{name}' is not available on macOS (available: event_tap)"
+        ))),
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Factory: action executor
 // ---------------------------------------------------------------------------
 
-/// Returns the CGEventPost-based action executor.
-pub fn create_action_executor() -> Result<Box<dyn ActionExecutor>, PlatformError> {
-    Ok(Box::new(MacOSExecutor::new()))
+/// Returns the CGEventPost-based action executor, the only macOS backend.
+///
+/// `pinned` is the `[executor] backend` config value; it must be `cgevent`
+/// if set.
+pub fn create_action_executor(
+    pinned: Option<&str>,
+) -> Result<Box<dyn ActionExecutor>, PlatformError> {
+    match pinned {
+        None | Some("cgevent") => Ok(Box::new(MacOSExecutor::new())),
+        Some(name) => Err(PlatformError::Unavailable(format!(
+            "executor backend '{name}' is not available on macOS (available: cgevent)"
+        ))),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Factory: clipboard
+// ---------------------------------------------------------------------------
+
+/// Returns the `pbpaste`-based clipboard reader.
+pub fn create_clipboard() -> Box<dyn Clipboard> {
+    Box::new(MacOSClipboard)
 }
@@ -0,0 +1,606 @@
+//! Pointer events: reading them in the tap and posting them for the executor.
+//!
+//! Capture adds the mouse event types to the tap mask only when a pointer
+//! callback is set. Pointer events are reported and passed through unchanged;
+//! the engine does not remap them yet.
+//!
+//! Coordinates: CGEvent locations are already in the global desktop space
+//! that `PointerEvent` uses (origin at the top-left of the main display, y
+//! down, other displays at negative or larger coordinates), so no conversion
+//! is needed. Injected moves are clamped to the nearest point on an active
+//! display, since a location off every display makes the window server pin
+//! the cursor unpredictably.
+//!
+//! Feedback: every event the executor posts carries `media::INJECTED_MARKER`
+//! in its user data field, and the tap passes marked events through without
+//! reporting them.
+//!
+//! Motion posted while a button is held is sent as the matching dragged
+//! event, which is what applications expect during a drag.
+
+use std::cell::Cell;
+use std::ffi::c_void;
+
+use super::media::{CG_EVENT_SOURCE_USER_DATA, INJECTED_MARKER};
+use crate::platform::{Action, KeyState, MouseButton, PlatformError, PointerEvent};
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+const CG_EVENT_LEFT_MOUSE_DOWN: u32 = 1;
+const CG_EVENT_LEFT_MOUSE_UP: u32 = 2;
+const CG_EVENT_RIGHT_MOUSE_DOWN: u32 = 3;
+const CG_EVENT_RIGHT_MOUSE_UP: u32 = 4;
+const CG_EVENT_MOUSE_MOVED: u32 = 5;
+const CG_EVENT_LEFT_MOUSE_DRAGGED: u32 = 6;
+const CG_EVENT_RIGHT_MOUSE_DRAGGED: u32 = 7;
+const CG_EVENT_SCROLL_WHEEL: u32 = 22;
+const CG_EVENT_OTHER_MOUSE_DOWN: u32 = 25;
+const CG_EVENT_OTHER_MOUSE_UP: u32 = 26;
+const CG_EVENT_OTHER_MOUSE_DRAGGED: u32 = 27;
+
+/// Tap mask bits for every pointer event type.
+pub const EVENT_MASK: u64 = (1u64 << CG_EVENT_LEFT_MOUSE_DOWN)
+    | (1u64 << CG_EVENT_LEFT_MOUSE_UP)
+    | (1u64 << CG_EVENT_RIGHT_MOUSE_DOWN)
+    | (1u64 << CG_EVENT_RIGHT_MOUSE_UP)
+    | (1u64 << CG_EVENT_MOUSE_MOVED)
+    | (1u64 << CG_EVENT_LEFT_MOUSE_DRAGGED)
+    | (1u64 << CG_EVENT_RIGHT_MOUSE_DRAGGED)
+    | (1u64 << CG_EVENT_SCROLL_WHEEL)
+    | (1u64 << CG_EVENT_OTHER_MOUSE_DOWN)
+    | (1u64 << CG_EVENT_OTHER_MOUSE_UP)
+    | (1u64 << CG_EVENT_OTHER_MOUSE_DRAGGED);
+
+/// kCGMouseEventClickState: 1 for a single click.
+const CG_MOUSE_EVENT_CLICK_STATE: u32 = 1;
+/// kCGMouseEventButtonNumber: 0 left, 1 right, 2 middle, and so on.
+const CG_MOUSE_EVENT_BUTTON_NUMBER: u32 = 3;
+/// kCGMouseEventDeltaX / kCGMouseEventDeltaY: relative motion in pixels.
+const CG_MOUSE_EVENT_DELTA_X: u32 = 4;
+const CG_MOUSE_EVENT_DELTA_Y: u32 = 5;
+/// kCGScrollWheelEventDeltaAxis1 / 2: vertical and horizontal line deltas.
+const CG_SCROLL_DELTA_AXIS_1: u32 = 11;
+const CG_SCROLL_DELTA_AXIS_2: u32 = 12;
+/// kCGScrollWheelEventIsContinuous: nonzero for pixel (trackpad) scrolling.
+const CG_SCROLL_IS_CONTINUOUS: u32 = 88;
+/// kCGScrollWheelEventPointDeltaAxis1 / 2: vertical and horizontal pixels.
+const CG_SCROLL_POINT_DELTA_AXIS_1: u32 = 96;
+const CG_SCROLL_POINT_DELTA_AXIS_2: u32 = 97;
+
+/// kCGScrollEventUnitPixel / kCGScrollEventUnitLine.
+const CG_SCROLL_UNIT_PIXEL: u32 = 0;
+const CG_SCROLL_UNIT_LINE: u32 = 1;
+
+/// kCGHIDEventTap: post where hardware events enter, so moves warp the
+/// cursor and clicks reach whatever is under it.
+const CG_HID_EVENT_TAP: u32 = 0;
+
+/// Upper bound of `MouseMoveAbsolute` normalized coordinates.
+const NORMALIZED_MAX: f64 = 65535.0;
+
+/// Most displays `displays()` reads.
+const MAX_DISPLAYS: usize = 16;
+
+// ---------------------------------------------------------------------------
+// Raw FFI
+// ---------------------------------------------------------------------------
+
+type CGEventRef = *mut c_void;
+type CGEventSourceRef = *mut c_void;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CGPoint {
+    x: f64,
+    y: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGSize {
+    width: f64,
+    height: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGRect {
+    origin: CGPoint,
+    size: CGSize,
+}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn CGEventCreate(source: CGEventSourceRef) -> CGEventRef;
+    fn CGEventCreateMouseEvent(
+        source: CGEventSourceRef,
+        mouse_type: u32,
+        location: CGPoint,
+        button: u32,
+    ) -> CGEventRef;
+    fn CGEventCreateScrollWheelEvent(
+        source: CGEventSourceRef,
+        units: u32,
+        wheel_count: u32,
+        wheel1: i32,
+        ...
+    ) -> CGEventRef;
+    fn CGEventGetLocation(event: CGEventRef) -> CGPoint;
+    fn CGEventGetIntegerValueField(event: CGEventRef, field: u32) -> i64;
+    fn CGEventSetIntegerValueField(event: CGEventRef, field: u32, value: i64);
+    fn CGEventPost(tap_location: u32, event: CGEventRef);
+    fn CGGetActiveDisplayList(max: u32, displays: *mut u32, count: *mut u32) -> i32;
+    fn CGDisplayBounds(display: u32) -> CGRect;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(cf: *const c_void);
+}
+
+// ---------------------------------------------------------------------------
+// Buttons
+// ---------------------------------------------------------------------------
+
+fn button_from_number(number: i64) -> MouseButton {
+    match number {
+        0 => MouseButton::Left,
+        1 => MouseButton::Right,
+        2 => MouseButton::Middle,
+        3 => MouseButton::Back,
+        4 => MouseButton::Forward,
+        n => MouseButton::Other(n.clamp(0, i64::from(u8::MAX)) as u8),
+    }
+}
+
+fn button_number(button: MouseButton) -> u32 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Back => 3,
+        MouseButton::Forward => 4,
+        MouseButton::Other(n) => u32::from(n),
+    }
+}
+
+/// The event type for pressing or releasing `button`.
+fn button_event_type(button: MouseButton, state: KeyState) -> u32 {
+    match (button, state) {
+        (MouseButton::Left, KeyState::Down) => CG_EVENT_LEFT_MOUSE_DOWN,
+        (MouseButton::Left, KeyState::Up) => CG_EVENT_LEFT_MOUSE_UP,
+        (MouseButton::Right, KeyState::Down) => CG_EVENT_RIGHT_MOUSE_DOWN,
+        (MouseButton::Right, KeyState::Up) => CG_EVENT_RIGHT_MOUSE_UP,
+        (_, KeyState::Down) => CG_EVENT_OTHER_MOUSE_DOWN,
+        (_, KeyState::Up) => CG_EVENT_OTHER_MOUSE_UP,
+    }
+}
+
+/// The event type and button for a move while the buttons in `held` (bit n
+/// for button number n) are down: a drag of the lowest held button, or a
+/// plain move.
+fn motion_event_type(held: u32) -> (u32, u32) {
+    match held.trailing_zeros() {
+        32 => (CG_EVENT_MOUSE_MOVED, 0),
+        0 => (CG_EVENT_LEFT_MOUSE_DRAGGED, 0),
+        1 => (CG_EVENT_RIGHT_MOUSE_DRAGGED, 1),
+        n => (CG_EVENT_OTHER_MOUSE_DRAGGED, n),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Capture
+// ---------------------------------------------------------------------------
+
+/// Whether `event_type` is one of the pointer types in `EVENT_MASK`.
+pub fn is_pointer_event(event_type: u32) -> bool {
+    event_type < 64 && EVENT_MASK & (1u64 << event_type) != 0
+}
+
+/// Translate a pointer event from the tap, or `None` if it carries nothing
+/// to report (such as a scroll with no movement).
+pub unsafe fn read(event_type: u32, event: CGEventRef) -> Option<PointerEvent> {
+    let location = CGEventGetLocation(event);
+    decode(
+        event_type,
+        |field| CGEventGetIntegerValueField(event, field),
+        location,
+    )
+}
+
+fn decode(event_type: u32, field: impl Fn(u32) -> i64, location: CGPoint) -> Option<PointerEvent> {
+    let button = |state| PointerEvent::Button {
+        button: button_from_number(field(CG_MOUSE_EVENT_BUTTON_NUMBER)),
+        state,
+    };
+    match event_type {
+        CG_EVENT_LEFT_MOUSE_DOWN | CG_EVENT_RIGHT_MOUSE_DOWN | CG_EVENT_OTHER_MOUSE_DOWN => {
+            Some(button(KeyState::Down))
+        }
+        CG_EVENT_LEFT_MOUSE_UP | CG_EVENT_RIGHT_MOUSE_UP | CG_EVENT_OTHER_MOUSE_UP => {
+            Some(button(KeyState::Up))
+        }
+        CG_EVENT_MOUSE_MOVED
+        | CG_EVENT_LEFT_MOUSE_DRAGGED
+        | CG_EVENT_RIGHT_MOUSE_DRAGGED
+        | CG_EVENT_OTHER_MOUSE_DRAGGED => Some(PointerEvent::Motion {
+            dx: field(CG_MOUSE_EVENT_DELTA_X) as i32,
+            dy: field(CG_MOUSE_EVENT_DELTA_Y) as i32,
+            position: Some((location.x.round() as i32, location.y.round() as i32)),
+        }),
+        CG_EVENT_SCROLL_WHEEL => {
+            let discrete = field(CG_SCROLL_IS_CONTINUOUS) == 0;
+            let (vertical, horizontal) = if discrete {
+                (CG_SCROLL_DELTA_AXIS_1, CG_SCROLL_DELTA_AXIS_2)
+            } else {
+                (CG_SCROLL_POINT_DELTA_AXIS_1, CG_SCROLL_POINT_DELTA_AXIS_2)
+            };
+            // Axis 2 is positive to the left.
+            let (dx, dy) = (-field(horizontal) as i32, field(vertical) as i32);
+            (dx != 0 || dy != 0).then_some(PointerEvent::Scroll { dx, dy, discrete })
+        }
+        _ => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Displays
+// ---------------------------------------------------------------------------
+
+/// A display's bounds in the global desktop space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Bounds {
+    /// The point of these bounds nearest to `p`.
+    fn nearest(&self, p: CGPoint) -> CGPoint {
+        CGPoint {
+            x: p.x.clamp(self.x, self.x + self.width - 1.0),
+            y: p.y.clamp(self.y, self.y + self.height - 1.0),
+        }
+    }
+}
+
+/// Bounds of every active display.
+fn displays() -> Vec<Bounds> {
+    let mut ids = [0u32; MAX_DISPLAYS];
+    let mut count = 0u32;
+    let err = unsafe { CGGetActiveDisplayList(MAX_DISPLAYS as u32, ids.as_mut_ptr(), &mut count) };
+    if err != 0 {
+        return Vec::new();
+    }
+    ids[..count as usize]
+        .iter()
+        .map(|&id| {
+            let r = unsafe { CGDisplayBounds(id) };
+            Bounds {
+                x: r.origin.x,
+                y: r.origin.y,
+                width: r.size.width,
+                height: r.size.height,
+            }
+        })
+        .collect()
+}
+
+/// `p` if it is on a display, otherwise the nearest point that is. With no
+/// displays known, `p` unchanged.
+fn clamp_to_displays(p: CGPoint, displays: &[Bounds]) -> CGPoint {
+    displays
+        .iter()
+        .map(|d| d.nearest(p))
+        .min_by(|a, b| {
+            let dist = |q: &CGPoint| (q.x - p.x).powi(2) + (q.y - p.y).powi(2);
+            dist(a).total_cmp(&dist(b))
+        })
+        .unwrap_or(p)
+}
+
+/// Map normalized 0..=65535 coordinates onto the box enclosing all displays.
+fn denormalize(x: i32, y: i32, displays: &[Bounds]) -> CGPoint {
+    let left = displays.iter().map(|d| d.x).fold(f64::INFINITY, f64::min);
+    let top = displays.iter().map(|d| d.y).fold(f64::INFINITY, f64::min);
+    let right = displays
+        .iter()
+        .map(|d| d.x + d.width)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let bottom = displays
+        .iter()
+        .map(|d| d.y + d.height)
+        .fold(f64::NEG_INFINITY, f64::max);
+    if displays.is_empty() {
+        return CGPoint { x: 0.0, y: 0.0 };
+    }
+    let scale = |v: i32, lo: f64, hi: f64| {
+        lo + (f64::from(v).clamp(0.0, NORMALIZED_MAX) / NORMALIZED_MAX) * (hi - lo - 1.0)
+    };
+    CGPoint {
+        x: scale(x, left, right),
+        y: scale(y, top, bottom),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Injection
+// ---------------------------------------------------------------------------
+
+/// Where the cursor is now.
+unsafe fn cursor_location() -> CGPoint {
+    let event = CGEventCreate(std::ptr::null_mut());
+    if event.is_null() {
+        return CGPoint { x: 0.0, y: 0.0 };
+    }
+    let location = CGEventGetLocation(event);
+    CFRelease(event.cast::<c_void>());
+    location
+}
+
+/// Tag `event` as ours, post it, and release it.
+unsafe fn post(event: CGEventRef, what: &str) -> Result<(), PlatformError> {
+    if event.is_null() {
+        return Err(PlatformError::Other(format!(
+            "could not create {what} event"
+        )));
+    }
+    CGEventSetIntegerValueField(event, CG_EVENT_SOURCE_USER_DATA, INJECTED_MARKER);
+    CGEventPost(CG_HID_EVENT_TAP, event);
+    CFRelease(event.cast::<c_void>());
+    Ok(())
+}
+
+/// Post a pointer action. `held` tracks which buttons the executor has
+/// pressed (bit n for button number n), so moves during a press are posted
+/// as drags. Returns `Ok(false)` for actions that are not pointer actions.
+pub fn inject(
+    source: CGEventSourceRef,
+    action: &Action,
+    held: &Cell<u32>,
+) -> Result<bool, PlatformError> {
+    unsafe {
+        match *action {
+            Action::MouseMoveRelative { dx, dy } => {
+                let from = cursor_location();
+                let to = CGPoint {
+                    x: from.x + f64::from(dx),
+                    y: from.y + f64::from(dy),
+                };
+                move_to(source, from, to, held.get())?;
+            }
+            Action::MouseMoveAbsolute { x, y, normalized } => {
+                let to = if normalized {
+                    denormalize(x, y, &displays())
+                } else {
+                    CGPoint {
+                        x: f64::from(x),
+                        y: f64::from(y),
+                    }
+                };
+                move_to(source, cursor_location(), to, held.get())?;
+            }
+            Action::MouseButton { button, state } => {
+                let number = button_number(button);
+                let event = CGEventCreateMouseEvent(
+                    source,
+                    button_event_type(button, state),
+                    cursor_location(),
+                    number,
+                );
+                if !event.is_null() {
+                    CGEventSetIntegerValueField(event, CG_MOUSE_EVENT_CLICK_STATE, 1);
+                }
+                post(event, "mouse button")?;
+                let bit = 1u32.checked_shl(number).unwrap_or(0);
+                held.set(match state {
+                    KeyState::Down => held.get() | bit,
+                    KeyState::Up => held.get() & !bit,
+                });
+            }
+            Action::Scroll { dx, dy, discrete } => {
+                let units = if discrete {
+                    CG_SCROLL_UNIT_LINE
+                } else {
+                    CG_SCROLL_UNIT_PIXEL
+                };
+                // Axis 2 is positive to the left.
+                let event = CGEventCreateScrollWheelEvent(source, units, 2, dy, -dx);
+                post(event, "scroll")?;
+            }
+            _ => return Ok(false),
+        }
+    }
+    log::debug!("executor: injected {:?}", action);
+    Ok(true)
+}
+
+/// Post a move from `from` to `to`, clamped to the displays, with the
+/// deltas applications that track relative motion read.
+unsafe fn move_to(
+    source: CGEventSourceRef,
+    from: CGPoint,
+    to: CGPoint,
+    held: u32,
+) -> Result<(), PlatformError> {
+    let to = clamp_to_displays(to, &displays());
+    let (event_type, button) = motion_event_type(held);
+    let event = CGEventCreateMouseEvent(source, event_type, to, button);
+    if !event.is_null() {
+        CGEventSetIntegerValueField(
+            event,
+            CG_MOUSE_EVENT_DELTA_X,
+            (to.x - from.x).round() as i64,
+        );
+        CGEventSetIntegerValueField(
+            event,
+            CG_MOUSE_EVENT_DELTA_Y,
+            (to.y - from.y).round() as i64,
+        );
+    }
+    post(event, "mouse move")
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: CGPoint = CGPoint { x: 0.0, y: 0.0 };
+
+    fn fields(pairs: &'static [(u32, i64)]) -> impl Fn(u32) -> i64 {
+        move |f| pairs.iter().find(|(k, _)| *k == f).map_or(0, |(_, v)| *v)
+    }
+
+    /// A 1920x1080 main display with a 1280x1024 display to its left.
+    fn two_displays() -> Vec<Bounds> {
+        vec![
+            Bounds {
+                x: 0.0,
+                y: 0.0,
+                width: 1920.0,
+                height: 1080.0,
+            },
+            Bounds {
+                x: -1280.0,
+                y: 0.0,
+                width: 1280.0,
+                height: 1024.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn buttons_decode_by_number() {
+        let other_down = decode(
+            CG_EVENT_OTHER_MOUSE_DOWN,
+            fields(&[(CG_MOUSE_EVENT_BUTTON_NUMBER, 3)]),
+            ORIGIN,
+        );
+        assert_eq!(
+            other_down,
+            Some(PointerEvent::Button {
+                button: MouseButton::Back,
+                state: KeyState::Down
+            })
+        );
+        assert_eq!(
+            decode(CG_EVENT_RIGHT_MOUSE_UP, fields(&[(3, 1)]), ORIGIN),
+            Some(PointerEvent::Button {
+                button: MouseButton::Right,
+                state: KeyState::Up
+            })
+        );
+        assert_eq!(button_from_number(7), MouseButton::Other(7));
+        assert_eq!(button_number(MouseButton::Other(7)), 7);
+    }
+
+    #[test]
+    fn drags_decode_as_motion() {
+        let event = decode(
+            CG_EVENT_LEFT_MOUSE_DRAGGED,
+            fields(&[(CG_MOUSE_EVENT_DELTA_X, -4), (CG_MOUSE_EVENT_DELTA_Y, 2)]),
+            CGPoint { x: -300.4, y: 12.6 },
+        );
+        assert_eq!(
+            event,
+            Some(PointerEvent::Motion {
+                dx: -4,
+                dy: 2,
+                position: Some((-300, 13))
+            })
+        );
+    }
+
+    #[test]
+    fn scroll_units_and_direction() {
+        // One notch down and one to the right, from a wheel.
+        let wheel = decode(
+            CG_EVENT_SCROLL_WHEEL,
+            fields(&[(CG_SCROLL_DELTA_AXIS_1, -1), (CG_SCROLL_DELTA_AXIS_2, -1)]),
+            ORIGIN,
+        );
+        assert_eq!(
+            wheel,
+            Some(PointerEvent::Scroll {
+                dx: 1,
+                dy: -1,
+                discrete: true
+            })
+        );
+        let trackpad = decode(
+            CG_EVENT_SCROLL_WHEEL,
+            fields(&[
+                (CG_SCROLL_IS_CONTINUOUS, 1),
+                (CG_SCROLL_DELTA_AXIS_1, 1),
+                (CG_SCROLL_POINT_DELTA_AXIS_1, 14),
+            ]),
+            ORIGIN,
+        );
+        assert_eq!(
+            trackpad,
+            Some(PointerEvent::Scroll {
+                dx: 0,
+                dy: 14,
+                discrete: false
+            })
+        );
+        assert_eq!(decode(CG_EVENT_SCROLL_WHEEL, fields(&[]), ORIGIN), None);
+    }
+
+    #[test]
+    fn moves_during_a_press_are_drags() {
+        assert_eq!(motion_event_type(0), (CG_EVENT_MOUSE_MOVED, 0));
+        assert_eq!(motion_event_type(0b1), (CG_EVENT_LEFT_MOUSE_DRAGGED, 0));
+        assert_eq!(motion_event_type(0b10), (CG_EVENT_RIGHT_MOUSE_DRAGGED, 1));
+        assert_eq!(motion_event_type(0b1000), (CG_EVENT_OTHER_MOUSE_DRAGGED, 3));
+    }
+
+    #[test]
+    fn targets_off_every_display_are_clamped() {
+        let displays = two_displays();
+        let on_left = CGPoint {
+            x: -100.0,
+            y: 500.0,
+        };
+        assert_eq!(clamp_to_displays(on_left, &displays), on_left);
+        // Below the shorter left display: pulled up onto it.
+        assert_eq!(
+            clamp_to_displays(
+                CGPoint {
+                    x: -100.0,
+                    y: 1050.0
+                },
+                &displays
+            ),
+            CGPoint {
+                x: -100.0,
+                y: 1023.0
+            }
+        );
+        assert_eq!(
+            clamp_to_displays(CGPoint { x: 5000.0, y: -5.0 }, &displays),
+            CGPoint { x: 1919.0, y: 0.0 }
+        );
+    }
+
+    #[test]
+    fn normalized_coordinates_span_all_displays() {
+        let displays = two_displays();
+        assert_eq!(denormalize(0, 0, &displays), CGPoint { x: -1280.0, y: 0.0 });
+        assert_eq!(
+            denormalize(65535, 65535, &displays),
+            CGPoint {
+                x: 1919.0,
+                y: 1079.0
+            }
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Accessibility permission: checking for it and asking the user for it.
+//!
+//! `is_trusted()` wraps `AXIsProcessTrusted()`. `ensure(prompt)` is called by
+//! capture before the event tap is created. With `prompt` set and permission
+//! missing, it calls `AXIsProcessTrustedWithOptions` with the prompt option,
+//! so macOS shows its own dialog and adds pcunifier to the Accessibility list
+//! itself, then polls until the user allows it or `PROMPT_TIMEOUT` passes.
+//! The first run then needs no restart. Without `prompt` it fails at once,
+//! for service contexts where nobody can answer a dialog.
+
+use std::ffi::c_void;
+use std::time::Duration;
+
+use crate::platform::PlatformError;
+
+/// How long to wait for the user to grant permission after prompting.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How often to re-check permission while waiting.
+const PROMPT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often to log that capture is still waiting.
+const PROMPT_PROGRESS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Where the user grants permission by hand.
+const SETTINGS_PATH: &str = "System Settings > Privacy & Security > Accessibility";
+
+// ---------------------------------------------------------------------------
+// Raw FFI declarations
+// ---------------------------------------------------------------------------
+
+type CFDictionaryRef = *const c_void;
+type CFStringRef = *const c_void;
+
+/// CFDictionaryKeyCallBacks / CFDictionaryValueCallBacks; only their address
+/// is needed.
+#[repr(C)]
+struct CFDictionaryCallBacks {
+    _private: [u8; 0],
+}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    /// Returns true if this process has been granted Accessibility permission.
+    fn AXIsProcessTrusted() -> bool;
+
+    /// Like `AXIsProcessTrusted`, but may show the permission dialog,
+    /// depending on `options`. Returns without waiting for the user.
+    fn AXIsProcessTrustedWithOptions(options: CFDictionaryRef) -> bool;
+
+    /// Options key: show the permission dialog if not trusted.
+    static kAXTrustedCheckOptionPrompt: CFStringRef;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFDictionaryCreate(
+        allocator: *const c_void,
+        keys: *const *const c_void,
+        values: *const *const c_void,
+        num_values: isize,
+        key_callbacks: *const CFDictionaryCallBacks,
+        value_callbacks: *const CFDictionaryCallBacks,
+    ) -> CFDictionaryRef;
+
+    fn CFRelease(cf: *const c_void);
+
+    static kCFBooleanTrue: *const c_void;
+    static kCFTypeDictionaryKeyCallBacks: CFDictionaryCallBacks;
+    static kCFTypeDictionaryValueCallBacks: CFDictionaryCallBacks;
+}
+
+// ---------------------------------------------------------------------------
+// Checking and prompting
+// ---------------------------------------------------------------------------
+
+/// Whether this process currently has Accessibility permission.
+pub fn is_trusted() -> bool {
+    unsafe { AXIsProcessTrusted() }
+}
+
+/// Ask macOS to show its Accessibility permission dialog. Returns whether
+/// permission was already granted; the dialog is only shown when it was not.
+fn prompt() -> bool {
+    unsafe {
+        let keys = [kAXTrustedCheckOptionPrompt];
+        let values = [kCFBooleanTrue];
+        let options = CFDictionaryCreate(
+            std::ptr::null(),
+            keys.as_ptr(),
+            values.as_ptr(),
+            1,
+            std::ptr::addr_of!(kCFTypeDictionaryKeyCallBacks),
+            std::ptr::addr_of!(kCFTypeDictionaryValueCallBacks),
+        );
+        if options.is_null() {
+            return AXIsProcessTrusted();
+        }
+        let trusted = AXIsProcessTrustedWithOptions(options);
+        CFRelease(options);
+        trusted
+    }
+}
+
+/// Make sure Accessibility permission is granted before capture starts.
+///
+/// With `allow_prompt`, a missing permission is requested through the
+/// system dialog and waited for; otherwise, or if the wait times out, the
+/// result is `PlatformError::PermissionDenied`.
+pub fn ensure(allow_prompt: bool) -> Result<(), PlatformError> {
+    if is_trusted() {
+        return Ok(());
+    }
+    if !allow_prompt {
+        return Err(PlatformError::PermissionDenied(format!(
+            "Accessibility permission required. Grant it in {SETTINGS_PATH}."
+        )));
+    }
+    if prompt() {
+        return Ok(());
+    }
+    log::info!(
+        "capture: waiting up to {}s for Accessibility permission; \
+         click Allow in the dialog, or enable pcunifier in {SETTINGS_PATH}",
+        PROMPT_TIMEOUT.as_secs()
+    );
+    if wait_for_grant(
+        PROMPT_TIMEOUT,
+        PROMPT_POLL_INTERVAL,
+        is_trusted,
+        std::thread::sleep,
+    ) {
+        log::info!("capture: Accessibility permission granted");
+        Ok(())
+    } else {
+        Err(PlatformError::PermissionDenied(format!(
+            "Accessibility permission was not granted within {}s. \
+             Grant it in {SETTINGS_PATH}, then start pcunifier again.",
+            PROMPT_TIMEOUT.as_secs()
+        )))
+    }
+}
+
+/// Poll `trusted` every `interval` until it returns true or `timeout`
+/// passes, logging progress every `PROMPT_PROGRESS_INTERVAL`. Returns
+/// whether permission was granted.
+fn wait_for_grant(
+    timeout: Duration,
+    interval: Duration,
+    mut trusted: impl FnMut() -> bool,
+    mut sleep: impl FnMut(Duration),
+) -> bool {
+    let mut waited = Duration::ZERO;
+    let mut next_progress = PROMPT_PROGRESS_INTERVAL;
+    while waited < timeout {
+        sleep(interval);
+        waited += interval;
+        if trusted() {
+            return true;
+        }
+        if waited >= next_progress && waited < timeout {
+            log::info!(
+                "capture: still waiting for Accessibility permission ({}s left)",
+                (timeout - waited).as_secs()
+            );
+            next_progress += PROMPT_PROGRESS_INTERVAL;
+        }
+    }
+    false
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn grant_ends_the_wait_early() {
+        let mut polls = 0;
+        let mut slept = Duration::ZERO;
+        let granted = wait_for_grant(
+            10 * SECOND,
+            SECOND,
+            || {
+                polls += 1;
+                polls == 3
+            },
+            |d| slept += d,
+        );
+        assert!(granted);
+        assert_eq!(polls, 3);
+        assert_eq!(slept, 3 * SECOND);
+    }
+
+    #[test]
+    fn wait_gives_up_at_the_timeout() {
+        let mut polls = 0;
+        let mut slept = Duration::ZERO;
+        let granted = wait_for_grant(
+            5 * SECOND,
+            SECOND,
+            || {
+                polls += 1;
+                false
+            },
+            |d| slept += d,
+        );
+        assert!(!granted);
+        assert_eq!(polls, 5);
+        assert_eq!(slept, 5 * SECOND);
+    }
+}
@@ -0,0 +1,161 @@
+//! Secure Event Input detection.
+//!
+//! While an application has Secure Event Input enabled (password fields,
+//! Terminal's "Secure Keyboard Entry", the login window), macOS stops
+//! delivering keystrokes to event taps and blocks injected ones. Capture
+//! polls `IsSecureEventInputEnabled()` and suspends rules for as long as it
+//! is on, naming the process responsible so the cause is visible in logs and
+//! status output.
+//!
+//! The responsible process is read from the `kCGSSessionSecureInputPID`
+//! property of the console session, which only `ioreg` exposes without
+//! private API. It is looked up once per transition, never per event.
+
+use std::time::{Duration, Instant};
+
+use super::focus::{executable_name, executable_path};
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    /// Returns true while any process has Secure Event Input enabled.
+    fn IsSecureEventInputEnabled() -> bool;
+}
+
+/// Whether Secure Event Input is currently enabled.
+pub fn is_enabled() -> bool {
+    unsafe { IsSecureEventInputEnabled() }
+}
+
+// ---------------------------------------------------------------------------
+// Transitions
+// ---------------------------------------------------------------------------
+
+/// A change in Secure Event Input seen by `SecureInputWatch::check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecureInputChange {
+    /// Secure input was turned on.
+    Started,
+    /// Secure input was turned off again.
+    Ended {
+        /// How long it was on.
+        after: Duration,
+    },
+}
+
+/// Tracks Secure Event Input across periodic checks, reporting only
+/// transitions.
+#[derive(Debug, Default)]
+pub struct SecureInputWatch {
+    /// When secure input was found on, or `None` while it is off.
+    since: Option<Instant>,
+}
+
+impl SecureInputWatch {
+    /// Run `enabled` and report whether secure input changed since the last
+    /// check.
+    pub fn check(
+        &mut self,
+        now: Instant,
+        enabled: impl FnOnce() -> bool,
+    ) -> Option<SecureInputChange> {
+        match (enabled(), self.since) {
+            (true, None) => {
+                self.since = Some(now);
+                Some(SecureInputChange::Started)
+            }
+            (false, Some(since)) => {
+                self.since = None;
+                Some(SecureInputChange::Ended {
+                    after: now.duration_since(since),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Responsible process
+// ---------------------------------------------------------------------------
+
+/// Executable name of the process holding secure input, if it can be found.
+pub fn holder() -> Option<String> {
+    let output = std::process::Command::new("ioreg")
+        .args(["-l", "-w", "0", "-d", "1", "-k", "IOConsoleUsers"])
+        .output()
+        .ok()?;
+    let pid = holder_pid(&String::from_utf8_lossy(&output.stdout))?;
+    executable_path(pid).and_then(|path| executable_name(&path))
+}
+
+/// Extracts `kCGSSessionSecureInputPID` from `ioreg` output.
+fn holder_pid(ioreg: &str) -> Option<i32> {
+    const KEY: &str = "\"kCGSSessionSecureInputPID\"=";
+    let start = ioreg.find(KEY)? + KEY.len();
+    let digits = &ioreg[start..];
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    digits[..end].parse().ok().filter(|pid| *pid > 0)
+}
+
+/// Status text for rules suspended by secure input, e.g.
+/// `secure input (loginwindow)`.
+pub fn suspension_reason(holder: Option<&str>) -> String {
+    match holder {
+        Some(name) => format!("secure input ({name})"),
+        None => "secure input".to_owned(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_and_end_are_reported_once() {
+        let start = Instant::now();
+        let mut watch = SecureInputWatch::default();
+        assert_eq!(watch.check(start, || false), None);
+        assert_eq!(
+            watch.check(start, || true),
+            Some(SecureInputChange::Started)
+        );
+        assert_eq!(watch.check(start + Duration::from_secs(1), || true), None);
+        assert_eq!(
+            watch.check(start + Duration::from_secs(4), || false),
+            Some(SecureInputChange::Ended {
+                after: Duration::from_secs(4)
+            })
+        );
+        assert_eq!(watch.check(start + Duration::from_secs(5), || false), None);
+    }
+
+    #[test]
+    fn holder_pid_is_read_from_console_session() {
+        let ioreg = r#"  | "IOConsoleUsers" = ({"kCGSSessionOnConsoleKey"=Yes,"kCGSSessionSecureInputPID"=412,"kCGSSessionUserNameKey"="alice"})"#;
+        assert_eq!(holder_pid(ioreg), Some(412));
+    }
+
+    #[test]
+    fn missing_or_zero_pid_has_no_holder() {
+        assert_eq!(
+            holder_pid(r#""IOConsoleUsers" = ({"kCGSSessionUserNameKey"="alice"})"#),
+            None
+        );
+        assert_eq!(holder_pid(r#""kCGSSessionSecureInputPID"=0"#), None);
+    }
+
+    #[test]
+    fn reason_names_the_holder_when_known() {
+        assert_eq!(
+            suspension_reason(Some("loginwindow")),
+            "secure input (loginwindow)"
+        );
+        assert_eq!(suspension_reason(None), "secure input");
+    }
+}
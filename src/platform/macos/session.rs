@@ -0,0 +1,98 @@
+//! Screen lock tracking through distributed notifications.
+//!
+//! loginwindow posts `com.apple.screenIsLocked` and
+//! `com.apple.screenIsUnlocked` on the distributed notification center as
+//! the lock screen comes and goes. The watcher observes both on a thread
+//! of its own, whose run loop delivers them.
+//!
+//! Not covered: fast user switching moves our session off the console
+//! without a lock notification, but Secure Event Input is on for the login
+//! window then, which suspends rules anyway (see `secure_input`).
+
+use std::ffi::c_void;
+use std::sync::Mutex;
+use std::thread;
+
+use super::focus::{cfstring, string_from_cf};
+use crate::platform::SessionLock;
+
+type CFStringRef = *const c_void;
+type CFNotificationCenterRef = *mut c_void;
+type CFNotificationCallback = unsafe extern "C" fn(
+    center: CFNotificationCenterRef,
+    observer: *mut c_void,
+    name: CFStringRef,
+    object: *const c_void,
+    user_info: *const c_void,
+);
+
+/// CFNotificationSuspensionBehaviorDeliverImmediately.
+const DELIVER_IMMEDIATELY: isize = 4;
+
+const LOCKED: &str = "com.apple.screenIsLocked";
+const UNLOCKED: &str = "com.apple.screenIsUnlocked";
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFNotificationCenterGetDistributedCenter() -> CFNotificationCenterRef;
+    fn CFNotificationCenterAddObserver(
+        center: CFNotificationCenterRef,
+        observer: *const c_void,
+        callback: CFNotificationCallback,
+        name: CFStringRef,
+        object: *const c_void,
+        suspension_behavior: isize,
+    );
+    fn CFRunLoopRun();
+    fn CFRelease(cf: *const c_void);
+}
+
+/// Where the callback reports. Notification callbacks get an observer
+/// pointer, but a global keeps the state out of raw pointers, like the
+/// other macOS callbacks' state.
+static SESSION: Mutex<Option<SessionLock>> = Mutex::new(None);
+
+/// Follow the screen lock on a background thread, for the life of the
+/// process.
+pub fn watch_session(lock: SessionLock) {
+    if let Ok(mut session) = SESSION.lock() {
+        *session = Some(lock);
+    }
+    thread::spawn(|| unsafe {
+        let center = CFNotificationCenterGetDistributedCenter();
+        for name in [
+            &b"com.apple.screenIsLocked\0"[..],
+            b"com.apple.screenIsUnlocked\0",
+        ] {
+            let name = cfstring(name);
+            CFNotificationCenterAddObserver(
+                center,
+                // Any stable address; it only tells observers apart.
+                std::ptr::addr_of!(SESSION).cast(),
+                on_notification,
+                name,
+                std::ptr::null(),
+                DELIVER_IMMEDIATELY,
+            );
+            CFRelease(name);
+        }
+        CFRunLoopRun();
+    });
+}
+
+unsafe extern "C" fn on_notification(
+    _center: CFNotificationCenterRef,
+    _observer: *mut c_void,
+    name: CFStringRef,
+    _object: *const c_void,
+    _user_info: *const c_void,
+) {
+    let locked = match string_from_cf(name).as_deref() {
+        Some(LOCKED) => true,
+        Some(UNLOCKED) => false,
+        _ => return,
+    };
+    if let Some(session) = SESSION.lock().ok().and_then(|s| s.clone()) {
+        session.set_locked(locked);
+    }
+}
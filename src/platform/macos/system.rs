@@ -0,0 +1,68 @@
+//! Portable system commands on macOS, through `pmset` and System Events.
+//!
+//! Locking uses `pmset displaysleepnow`, which locks the screen when
+//! "Require password after screen saver begins or display is turned off" is
+//! set to immediately (the default on current macOS).
+//!
+//! `notify` posts a user notification through `osascript`, for problems the
+//! user must act on while PC Unifier runs without a terminal.
+
+use crate::platform::{spawn_program, PlatformError, SystemCmd};
+
+/// Runs `cmd` for the logged-in user.
+pub fn run(cmd: SystemCmd) -> Result<(), PlatformError> {
+    spawn_program(&argv(cmd))
+}
+
+/// The command line that performs `cmd`.
+fn argv(cmd: SystemCmd) -> Vec<String> {
+    let args: &[&str] = match cmd {
+        SystemCmd::LockScreen => &["pmset", "displaysleepnow"],
+        SystemCmd::Sleep => &["pmset", "sleepnow"],
+        SystemCmd::LogOut => &[
+            "osascript",
+            "-e",
+            "tell application \"System Events\" to log out",
+        ],
+    };
+    args.iter().map(|s| (*s).to_owned()).collect()
+}
+
+/// Shows `message` as a notification from PC Unifier.
+pub fn notify(message: &str) -> Result<(), PlatformError> {
+    spawn_program(&notify_argv(message))
+}
+
+/// The `osascript` command line that shows `message`.
+fn notify_argv(message: &str) -> Vec<String> {
+    let quoted = message.replace('\\', "\\\\").replace('"', "\\\"");
+    vec![
+        "osascript".to_owned(),
+        "-e".to_owned(),
+        format!("display notification \"{quoted}\" with title \"PC Unifier\""),
+    ]
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_map_to_pmset_and_system_events() {
+        assert_eq!(argv(SystemCmd::LockScreen), ["pmset", "displaysleepnow"]);
+        assert_eq!(argv(SystemCmd::Sleep), ["pmset", "sleepnow"]);
+        assert_eq!(argv(SystemCmd::LogOut)[0], "osascript");
+    }
+
+    #[test]
+    fn notification_text_is_quoted_for_applescript() {
+        assert_eq!(
+            notify_argv(r#"say "hi" \ bye"#)[2],
+            r#"display notification "say \"hi\" \\ bye" with title "PC Unifier""#
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! Executor middleware.
+//!
+//! Concerns that apply to every backend alike (logging, counting, throttling,
+//! delays) belong in front of the executor rather than in each of them.
+//! `MiddlewareExecutor` wraps any `ActionExecutor` and runs every action
+//! through a chain of middlewares, in the order they were added, before
+//! handing it to the wrapped executor. A batch goes through the chain as a
+//! whole, so it reaches the executor whole or not at all: no chord loses its
+//! modifiers. A middleware that returns `Verdict::Suppress` ends the chain:
+//! later middlewares and the wrapped executor never see the actions, and
+//! the call fails with `PlatformError::Suppressed`, so a sequence's
+//! `on_error` sees the step as failed.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::platform::{
+    Action, ActionExecutor, Capabilities, KeyCode, KeyState, PlatformError, TextInjection,
+    WindowTarget,
+};
+
+/// What a middleware wants done with the actions it was shown. Not to be
+/// confused with `rule_engine::Decision`, which is what a rule wants done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Pass the actions on to the next middleware, or the executor.
+    Continue,
+    /// Drop the actions, for the reason given.
+    Suppress(&'static str),
+}
+
+/// A step in the chain. It may act on the actions, one or a whole batch
+/// (log them, sleep, count them), before deciding whether they go on.
+pub type Middleware = Box<dyn Fn(&[Action]) -> Verdict + Send>;
+
+// ---------------------------------------------------------------------------
+// MiddlewareExecutor
+// ---------------------------------------------------------------------------
+
+/// An executor that runs a middleware chain in front of another.
+pub struct MiddlewareExecutor {
+    inner: Box<dyn ActionExecutor>,
+    chain: Vec<Middleware>,
+}
+
+impl MiddlewareExecutor {
+    pub fn new(inner: Box<dyn ActionExecutor>) -> Self {
+        Self {
+            inner,
+            chain: Vec::new(),
+        }
+    }
+
+    /// Append `middleware` to the chain; it runs after those added before.
+    pub fn with(mut self, middleware: impl Fn(&[Action]) -> Verdict + Send + 'static) -> Self {
+        self.chain.push(Box::new(middleware));
+        self
+    }
+
+    /// Run `actions` through the chain.
+    fn admit(&self, actions: &[Action]) -> Result<(), PlatformError> {
+        for middleware in &self.chain {
+            if let Verdict::Suppress(reason) = middleware(actions) {
+                return Err(PlatformError::Suppressed(reason.to_owned()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ActionExecutor for MiddlewareExecutor {
+    fn execute(&self, action: &Action) -> Result<(), PlatformError> {
+        self.admit(std::slice::from_ref(action))?;
+        self.inner.execute(action)
+    }
+
+    /// Runs the batch through the chain as a whole, then hands it to the
+    /// executor unless a middleware dropped it.
+    fn execute_batch(&self, actions: &[Action]) -> Result<(), PlatformError> {
+        self.admit(actions)?;
+        self.inner.execute_batch(actions)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn lock_state(&self, key: KeyCode) -> Option<bool> {
+        self.inner.lock_state(key)
+    }
+
+    fn set_target(&mut self, target: WindowTarget) -> Result<(), PlatformError> {
+        self.inner.set_target(target)
+    }
+
+    fn text_injection(&self) -> TextInjection {
+        self.inner.text_injection()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Built-in middlewares
+// ---------------------------------------------------------------------------
+
+/// Logs every action at trace level, as `executor: <action>`.
+pub fn log() -> impl Fn(&[Action]) -> Verdict + Send {
+    |actions| {
+        for action in actions {
+            log::trace!("executor: {action:?}");
+        }
+        Verdict::Continue
+    }
+}
+
+/// Number of actions that reached a `count` middleware. Clones share the
+/// same count.
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts every action into `counter`. Placed after a middleware that can
+/// suppress, it counts only the actions that got past it.
+pub fn count(counter: Counter) -> impl Fn(&[Action]) -> Verdict + Send {
+    move |actions| {
+        counter.0.fetch_add(actions.len() as u64, Ordering::Relaxed);
+        Verdict::Continue
+    }
+}
+
+/// Shortest time between two warnings about dropped actions.
+const DROP_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A token bucket over every action on its way to the executor, the last
+/// line of defense against a runaway (a macro loop, chattering hardware
+/// through a macro) flooding the system.
+///
+/// Holds up to one second's worth of `per_sec` tokens, refilled
+/// continuously. Each action takes one, and a batch takes one per action
+/// at once; actions finding too few are dropped, a batch whole. Key and
+/// button releases take no token, so releases alone always pass and a
+/// dropped press never leaves a key held that was pressed before.
+#[derive(Debug)]
+pub struct RateGate {
+    per_sec: f64,
+    tokens: f64,
+    last: Option<Instant>,
+    dropped: u64,
+    last_report: Option<Instant>,
+}
+
+impl RateGate {
+    pub fn new(per_sec: u32) -> Self {
+        Self {
+            per_sec: f64::from(per_sec),
+            tokens: f64::from(per_sec),
+            last: None,
+            dropped: 0,
+            last_report: None,
+        }
+    }
+
+    /// Whether `actions`, arriving together at `now`, may go on.
+    pub fn admit(&mut self, actions: &[Action], now: Instant) -> bool {
+        let needed = actions.iter().filter(|a| !is_release(a)).count() as f64;
+        if needed == 0.0 {
+            return true;
+        }
+        if let Some(last) = self.last {
+            let refill = now.saturating_duration_since(last).as_secs_f64() * self.per_sec;
+            self.tokens = (self.tokens + refill).min(self.per_sec);
+        }
+        self.last = Some(now);
+        if self.tokens >= needed {
+            self.tokens -= needed;
+            true
+        } else {
+            self.dropped += actions.len() as u64;
+            false
+        }
+    }
+
+    /// The number of actions dropped since the last report, at most once
+    /// per `DROP_REPORT_INTERVAL` and only when there are any.
+    pub fn report(&mut self, now: Instant) -> Option<u64> {
+        let due = self.last_report.map_or(true, |last| {
+            now.duration_since(last) >= DROP_REPORT_INTERVAL
+        });
+        if self.dropped == 0 || !due {
+            return None;
+        }
+        self.last_report = Some(now);
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+fn is_release(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::InjectKey {
+            state: KeyState::Up,
+            ..
+        } | Action::MouseButton {
+            state: KeyState::Up,
+            ..
+        }
+    )
+}
+
+/// Suppresses actions above `per_sec` per second through a `RateGate`,
+/// logging a warning with the count dropped at most once a second.
+pub fn rate_limit(per_sec: u32) -> impl Fn(&[Action]) -> Verdict + Send {
+    let gate = Mutex::new(RateGate::new(per_sec));
+    move |actions| {
+        let mut gate = gate.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let admitted = gate.admit(actions, now);
+        if let Some(dropped) = gate.report(now) {
+            log::warn!(
+                "executor: more than {per_sec} actions per second; dropped {dropped} \
+                 ([executor] max_rate)"
+            );
+        }
+        if admitted {
+            Verdict::Continue
+        } else {
+            Verdict::Suppress("rate limited")
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::platform::mock::RecordingExecutor;
+    use crate::platform::KeyState;
+
+    fn key(key: KeyCode) -> Action {
+        Action::InjectKey {
+            key,
+            state: KeyState::Down,
+        }
+    }
+
+    #[test]
+    fn middlewares_run_in_order_before_the_executor() {
+        let recorder = RecordingExecutor::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (first, second) = (seen.clone(), seen.clone());
+        let executor = MiddlewareExecutor::new(Box::new(recorder.clone()))
+            .with(move |_| {
+                first.lock().unwrap().push("first");
+                Verdict::Continue
+            })
+            .with(move |_| {
+                second.lock().unwrap().push("second");
+                Verdict::Continue
+            });
+
+        executor.execute(&key(KeyCode::A)).unwrap();
+        assert_eq!(*seen.lock().unwrap(), ["first", "second"]);
+        assert_eq!(recorder.actions(), [key(KeyCode::A)]);
+        assert_eq!(executor.name(), "mock");
+    }
+
+    #[test]
+    fn suppressing_middleware_ends_the_chain() {
+        let recorder = RecordingExecutor::new();
+        let (before, after) = (Counter::default(), Counter::default());
+        let executor = MiddlewareExecutor::new(Box::new(recorder.clone()))
+            .with(count(before.clone()))
+            .with(|actions| {
+                if actions.contains(&key(KeyCode::Escape)) {
+                    Verdict::Suppress("no Escape")
+                } else {
+                    Verdict::Continue
+                }
+            })
+            .with(count(after.clone()));
+
+        let dropped = executor.execute(&key(KeyCode::Escape));
+        assert!(matches!(dropped, Err(PlatformError::Suppressed(r)) if r == "no Escape"));
+        executor.execute(&key(KeyCode::B)).unwrap();
+        assert_eq!(recorder.actions(), [key(KeyCode::B)]);
+        assert_eq!((before.get(), after.get()), (2, 1));
+
+        // A batch runs the chain as a whole, and is dropped as a whole.
+        assert!(executor
+            .execute_batch(&[key(KeyCode::Escape), key(KeyCode::C), key(KeyCode::D)])
+            .is_err());
+        executor
+            .execute_batch(&[key(KeyCode::C), key(KeyCode::D)])
+            .unwrap();
+        assert_eq!(recorder.batches(), [2]);
+        assert_eq!((before.get(), after.get()), (7, 3));
+    }
+
+    /// A chord that finds the bucket half empty is dropped whole, never
+    /// sent without its modifier, and the caller hears of it.
+    #[test]
+    fn rate_limit_drops_a_batch_whole() {
+        let recorder = RecordingExecutor::new();
+        let executor = MiddlewareExecutor::new(Box::new(recorder.clone())).with(rate_limit(3));
+        let press = |key, state| Action::InjectKey { key, state };
+
+        executor.execute(&key(KeyCode::A)).unwrap();
+        executor.execute(&key(KeyCode::B)).unwrap();
+        let chord = [
+            press(KeyCode::Ctrl, KeyState::Down),
+            press(KeyCode::C, KeyState::Down),
+            press(KeyCode::C, KeyState::Up),
+            press(KeyCode::Ctrl, KeyState::Up),
+        ];
+        let dropped = executor.execute_batch(&chord);
+        assert!(matches!(dropped, Err(PlatformError::Suppressed(r)) if r == "rate limited"));
+        assert_eq!(recorder.actions(), [key(KeyCode::A), key(KeyCode::B)]);
+        assert!(recorder.batches().is_empty());
+
+        // Releases alone still pass.
+        executor.execute_batch(&chord[2..]).unwrap();
+        assert_eq!(recorder.batches(), [2]);
+    }
+
+    #[test]
+    fn rate_gate_caps_the_rate_but_never_drops_releases() {
+        let mut gate = RateGate::new(10);
+        let start = Instant::now();
+        let up = Action::InjectKey {
+            key: KeyCode::A,
+            state: KeyState::Up,
+        };
+
+        // A burst of one second's worth passes, then nothing but releases.
+        let admitted = (0..25)
+            .filter(|_| gate.admit(&[key(KeyCode::A)], start))
+            .count();
+        assert_eq!(admitted, 10);
+        assert!(gate.admit(&[up], start));
+        assert_eq!(gate.report(start), Some(15));
+        assert_eq!(gate.report(start), None);
+
+        // Tokens come back at the configured rate, and no faster.
+        let later = start + Duration::from_millis(500);
+        let admitted = (0..25)
+            .filter(|_| gate.admit(&[key(KeyCode::A)], later))
+            .count();
+        assert_eq!(admitted, 5);
+        // Reported again only once the interval has passed.
+        assert_eq!(gate.report(later), None);
+        assert_eq!(gate.report(start + DROP_REPORT_INTERVAL), Some(20));
+    }
+}
@@ -0,0 +1,162 @@
+//! Test doubles for `ActionExecutor` and `Clipboard`.
+//!
+//! `RecordingExecutor` records every action it is asked to execute so tests can
+//! assert on the exact injection order without touching any OS API. Clones
+//! share the same log, so a test can keep one handle while the engine owns a
+//! boxed clone.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::platform::{
+    Action, ActionExecutor, Capabilities, Clipboard, KeyCode, PlatformError, TextInjection,
+};
+
+/// Executor that appends each executed action to a shared log.
+#[derive(Clone, Default)]
+pub struct RecordingExecutor {
+    log: Arc<Mutex<Vec<Action>>>,
+    batches: Arc<Mutex<Vec<usize>>>,
+    locks: Arc<Mutex<HashMap<KeyCode, bool>>>,
+    /// Reported by `text_injection`; `None` is `Strings`.
+    text: Arc<Mutex<Option<TextInjection>>>,
+    /// Keys whose injection fails, and is not recorded.
+    failing: Arc<Mutex<Vec<KeyCode>>>,
+    /// Reported by `capabilities`; `None` is everything but the clipboard.
+    capabilities: Arc<Mutex<Option<Capabilities>>>,
+}
+
+impl RecordingExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of every action executed so far, in order.
+    pub fn actions(&self) -> Vec<Action> {
+        self.log.lock().unwrap().clone()
+    }
+
+    /// The length of every batch executed so far, in order. Batched
+    /// actions are in `actions` as well.
+    pub fn batches(&self) -> Vec<usize> {
+        self.batches.lock().unwrap().clone()
+    }
+
+    /// Discards all recorded actions.
+    pub fn clear(&self) {
+        self.log.lock().unwrap().clear();
+        self.batches.lock().unwrap().clear();
+    }
+
+    /// Sets the value reported by `lock_state` for `key`.
+    pub fn set_lock_state(&self, key: KeyCode, on: bool) {
+        self.locks.lock().unwrap().insert(key, on);
+    }
+
+    /// Sets the value reported by `text_injection`.
+    pub fn set_text_injection(&self, mode: TextInjection) {
+        *self.text.lock().unwrap() = Some(mode);
+    }
+
+    /// Sets the value reported by `capabilities`.
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        *self.capabilities.lock().unwrap() = Some(capabilities);
+    }
+
+    /// Makes every injection of `key` fail from now on.
+    pub fn fail_on(&self, key: KeyCode) {
+        self.failing.lock().unwrap().push(key);
+    }
+
+    /// An error if `action` injects a key set to fail.
+    fn check(&self, action: &Action) -> Result<(), PlatformError> {
+        let key = match action {
+            Action::InjectKey { key, .. } | Action::Chord { key, .. } => key,
+            _ => return Ok(()),
+        };
+        if self.failing.lock().unwrap().contains(key) {
+            return Err(PlatformError::Other(format!("{key:?} set to fail")));
+        }
+        Ok(())
+    }
+}
+
+impl ActionExecutor for RecordingExecutor {
+    fn execute(&self, action: &Action) -> Result<(), PlatformError> {
+        self.check(action)?;
+        self.log.lock().unwrap().push(action.clone());
+        Ok(())
+    }
+
+    fn execute_batch(&self, actions: &[Action]) -> Result<(), PlatformError> {
+        actions.iter().try_for_each(|action| self.check(action))?;
+        self.batches.lock().unwrap().push(actions.len());
+        self.log.lock().unwrap().extend_from_slice(actions);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn lock_state(&self, key: KeyCode) -> Option<bool> {
+        self.locks.lock().unwrap().get(&key).copied()
+    }
+
+    /// Whole strings unless set otherwise, so tests see text as typed.
+    fn text_injection(&self) -> TextInjection {
+        self.text.lock().unwrap().unwrap_or(TextInjection::Strings)
+    }
+
+    /// Everything but the clipboard unless set otherwise, with text as
+    /// `text_injection` reports it.
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.lock().unwrap().unwrap_or(Capabilities {
+            inject_key: true,
+            type_text: self.text_injection() != TextInjection::Keys,
+            pointer: true,
+            system_command: true,
+            clipboard: false,
+        })
+    }
+}
+
+/// Clipboard holding text in memory. Clones share the text, so a test can
+/// keep one handle while the engine owns a boxed clone.
+#[derive(Clone, Default)]
+pub struct MockClipboard {
+    text: Arc<Mutex<Option<String>>>,
+    /// Every text set, in order.
+    writes: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockClipboard {
+    pub fn new(text: Option<&str>) -> Self {
+        Self {
+            text: Arc::new(Mutex::new(text.map(str::to_owned))),
+            writes: Arc::default(),
+        }
+    }
+
+    /// The text the clipboard holds now.
+    pub fn text(&self) -> Option<String> {
+        self.text.lock().unwrap().clone()
+    }
+
+    /// Every text set so far, in order.
+    pub fn writes(&self) -> Vec<String> {
+        self.writes.lock().unwrap().clone()
+    }
+}
+
+impl Clipboard for MockClipboard {
+    fn read_text(&self) -> Result<Option<String>, PlatformError> {
+        Ok(self.text())
+    }
+
+    fn write_text(&self, text: &str) -> Result<(), PlatformError> {
+        *self.text.lock().unwrap() = Some(text.to_owned());
+        self.writes.lock().unwrap().push(text.to_owned());
+        Ok(())
+    }
+}
@@ -7,15 +7,30 @@
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "linux")]
-pub use linux::{create_action_executor, create_input_capture};
+pub use linux::{create_action_executor, create_clipboard, create_input_capture, watch_session};
 #[cfg(target_os = "macos")]
 mod macos;
 #[cfg(target_os = "macos")]
-pub use macos::{create_action_executor, create_input_capture};
+pub use macos::{create_action_executor, create_clipboard, create_input_capture, watch_session};
 #[cfg(target_os = "windows")]
 mod windows;
+#[cfg(all(target_os = "windows", feature = "tray"))]
+pub use windows::{attach_console, Tray, TrayCommand, TrayStatus};
 #[cfg(target_os = "windows")]
-pub use windows::{create_action_executor, create_input_capture};
+pub use windows::{create_action_executor, create_clipboard, create_input_capture, watch_session};
+
+pub mod keynames;
+pub mod middleware;
+#[cfg(test)]
+pub mod mock;
+#[cfg(feature = "serde")]
+pub mod serialized;
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 // ---------------------------------------------------------------------------
 // Key representation
@@ -25,9 +40,14 @@ pub use windows::{create_action_executor, create_input_capture};
 ///
 /// Config-level aliases (Control, Super, Win, Cmd, Return) are resolved by
 /// the config parser in M7. This enum contains only canonical names.
-/// Platform backends normalize left/right modifier variants into the unified
-/// `Ctrl`, `Shift`, `Alt`, and `Meta` variants.
+/// The unified `Ctrl`, `Shift`, `Alt`, and `Meta` variants stand for either
+/// side of a modifier. Backends report the left and right keys as `LeftCtrl`
+/// and `RightCtrl` and so on, and a modifier whose side they cannot tell as
+/// the unified variant. Before rules see an event, the engine folds sides
+/// back into the unified variants as `[capture] sides` asks (see
+/// `config::SideMatch`); by default only the left ones.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KeyCode {
     // Letters
     A,
@@ -95,11 +115,20 @@ pub enum KeyCode {
     F23,
     F24,
 
-    // Modifiers (unified -- backends normalize left/right into these)
+    // Modifiers (unified -- either side, injected as the left key)
     Ctrl,
     Shift,
     Alt,
     Meta,
+    // Sided modifiers, for rules that need a specific side
+    LeftCtrl,
+    LeftShift,
+    LeftAlt,
+    LeftMeta,
+    RightCtrl,
+    RightShift,
+    RightAlt,
+    RightMeta,
 
     // Navigation and editing
     Space,
@@ -124,6 +153,37 @@ pub enum KeyCode {
     ScrollLock,
     PrintScreen,
     Pause,
+    /// Context menu key (Windows "Apps" key, Linux Compose).
+    Menu,
+
+    // Media keys. Captured and injected on every platform (as system-defined
+    // events on macOS), except Stop on macOS, which has no such event, and
+    // brightness on Windows, which has no virtual key code for it.
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    PlayPause,
+    NextTrack,
+    PrevTrack,
+    Stop,
+    BrightnessUp,
+    BrightnessDown,
+
+    // Browser and launch keys of multimedia keyboards. Linux and Windows
+    // only: macOS handles them below the event tap, if at all.
+    BrowserBack,
+    BrowserForward,
+    BrowserRefresh,
+    BrowserStop,
+    BrowserSearch,
+    BrowserFavorites,
+    BrowserHome,
+    LaunchMail,
+    LaunchMedia,
+    /// "My Computer" on most keyboards.
+    LaunchApp1,
+    /// Calculator on most keyboards.
+    LaunchApp2,
 
     // Numeric keypad
     Numpad0,
@@ -141,6 +201,9 @@ pub enum KeyCode {
     NumpadMul,
     NumpadDiv,
     NumpadEnter,
+    NumpadDecimal,
+    /// Keypad `=`, found on Mac extended keyboards.
+    NumpadEquals,
 
     // Punctuation / symbol keys
     Backtick,
@@ -154,14 +217,100 @@ pub enum KeyCode {
     Comma,
     Period,
     Slash,
+    /// The extra ISO key between left Shift and Z (evdev `KEY_102ND`,
+    /// `VK_OEM_102`, macOS `kVK_ISO_Section`).
+    IntlBackslash,
+    /// The JIS Yen key left of Backspace (evdev `KEY_YEN`, macOS
+    /// `kVK_JIS_Yen`).
+    IntlYen,
+    /// The JIS Ro (underscore) key left of right Shift (evdev `KEY_RO`,
+    /// macOS `kVK_JIS_Underscore`).
+    IntlRo,
+
+    // Input method keys, named after their USB HID usages
+    /// Kana on JIS keyboards, Hangul on Korean ones.
+    Lang1,
+    /// Eisu on JIS keyboards, Hanja on Korean ones.
+    Lang2,
 }
 
+impl KeyCode {
+    /// Returns true for the modifier keys, unified or sided.
+    pub fn is_modifier(self) -> bool {
+        matches!(
+            self.unify_side(),
+            KeyCode::Ctrl | KeyCode::Shift | KeyCode::Alt | KeyCode::Meta
+        )
+    }
+
+    /// The unified modifier for a sided modifier. Every other key is
+    /// returned unchanged.
+    pub fn unify_side(self) -> KeyCode {
+        match self {
+            KeyCode::LeftCtrl | KeyCode::RightCtrl => KeyCode::Ctrl,
+            KeyCode::LeftShift | KeyCode::RightShift => KeyCode::Shift,
+            KeyCode::LeftAlt | KeyCode::RightAlt => KeyCode::Alt,
+            KeyCode::LeftMeta | KeyCode::RightMeta => KeyCode::Meta,
+            other => other,
+        }
+    }
+
+    /// Whether this is the left key of a modifier.
+    pub fn is_left_modifier(self) -> bool {
+        matches!(
+            self,
+            KeyCode::LeftCtrl | KeyCode::LeftShift | KeyCode::LeftAlt | KeyCode::LeftMeta
+        )
+    }
+
+    /// Whether a rule naming `self` applies to `key`: the same key, or either
+    /// side when `self` is a unified modifier.
+    pub fn covers(self, key: KeyCode) -> bool {
+        self == key || self == key.unify_side()
+    }
+
+    /// The key a numpad key acts as while NumLock is off, as `Numpad7` acts
+    /// as `Home`. `None` for every other key, and for `Numpad5`, which then
+    /// does nothing.
+    pub fn numpad_navigation(self) -> Option<KeyCode> {
+        NUMPAD_NAVIGATION
+            .iter()
+            .find(|&&(pad, _)| pad == self)
+            .map(|&(_, nav)| nav)
+    }
+
+    /// The numpad key that acts as `self` while NumLock is off, as `Numpad7`
+    /// for `Home`. For backends that report such presses as the navigation
+    /// key, to report the key pressed instead.
+    pub fn numpad_origin(self) -> Option<KeyCode> {
+        NUMPAD_NAVIGATION
+            .iter()
+            .find(|&&(_, nav)| nav == self)
+            .map(|&(pad, _)| pad)
+    }
+}
+
+/// Numpad keys and what they act as while NumLock is off.
+const NUMPAD_NAVIGATION: [(KeyCode, KeyCode); 10] = [
+    (KeyCode::Numpad0, KeyCode::Insert),
+    (KeyCode::Numpad1, KeyCode::End),
+    (KeyCode::Numpad2, KeyCode::Down),
+    (KeyCode::Numpad3, KeyCode::PageDown),
+    (KeyCode::Numpad4, KeyCode::Left),
+    (KeyCode::Numpad6, KeyCode::Right),
+    (KeyCode::Numpad7, KeyCode::Home),
+    (KeyCode::Numpad8, KeyCode::Up),
+    (KeyCode::Numpad9, KeyCode::PageUp),
+    (KeyCode::NumpadDecimal, KeyCode::Delete),
+];
+
 // ---------------------------------------------------------------------------
 // Key state
 // ---------------------------------------------------------------------------
 
 /// Whether a key was pressed or released.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KeyState {
     Down,
     Up,
@@ -175,13 +324,197 @@ pub enum KeyState {
 ///
 /// Uses plain booleans rather than bitflags to keep the dependency count at
 /// zero. Platform backends normalize left/right modifier variants into these
-/// unified flags.
+/// unified flags; those that track each side also report which is held in
+/// `sides`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Modifiers {
     pub ctrl: bool,
     pub shift: bool,
     pub alt: bool,
     pub meta: bool,
+    /// The side of each modifier held, or `None` where the backend does not
+    /// track sides.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub sides: Option<HeldSides>,
+}
+
+/// Which side of each modifier is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeldSides {
+    pub left_ctrl: bool,
+    pub right_ctrl: bool,
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_alt: bool,
+    pub right_alt: bool,
+    pub left_meta: bool,
+    pub right_meta: bool,
+}
+
+impl HeldSides {
+    /// The sided keys, in the order of `bits`.
+    const KEYS: [KeyCode; 8] = [
+        KeyCode::LeftCtrl,
+        KeyCode::RightCtrl,
+        KeyCode::LeftShift,
+        KeyCode::RightShift,
+        KeyCode::LeftAlt,
+        KeyCode::RightAlt,
+        KeyCode::LeftMeta,
+        KeyCode::RightMeta,
+    ];
+
+    /// The keys held, as their sided variants in Ctrl, Shift, Alt, Meta
+    /// order, left before right.
+    pub fn keys(self) -> Vec<KeyCode> {
+        let bits = self.bits();
+        (0..8)
+            .filter(|i| bits & 1 << i != 0)
+            .map(|i| Self::KEYS[i])
+            .collect()
+    }
+
+    /// One bit per side, in `KEYS` order.
+    fn bits(self) -> u8 {
+        [
+            self.left_ctrl,
+            self.right_ctrl,
+            self.left_shift,
+            self.right_shift,
+            self.left_alt,
+            self.right_alt,
+            self.left_meta,
+            self.right_meta,
+        ]
+        .into_iter()
+        .enumerate()
+        .fold(0, |bits, (i, held)| bits | u8::from(held) << i)
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        let held = |i: u8| bits & 1 << i != 0;
+        Self {
+            left_ctrl: held(0),
+            right_ctrl: held(1),
+            left_shift: held(2),
+            right_shift: held(3),
+            left_alt: held(4),
+            right_alt: held(5),
+            left_meta: held(6),
+            right_meta: held(7),
+        }
+    }
+}
+
+/// Matching and combining modifier sets. Only the unified flags are
+/// compared; `sides` is carried along where it is known.
+impl Modifiers {
+    /// `key` as a modifier set, with its side held where the key has one:
+    /// `LeftCtrl` sets `ctrl` and `sides.left_ctrl`, `Ctrl` only `ctrl`.
+    /// `None` for a key that is not a modifier.
+    pub fn from_keycode(key: KeyCode) -> Option<Self> {
+        let mut modifiers = Modifiers::default();
+        match key.unify_side() {
+            KeyCode::Ctrl => modifiers.ctrl = true,
+            KeyCode::Shift => modifiers.shift = true,
+            KeyCode::Alt => modifiers.alt = true,
+            KeyCode::Meta => modifiers.meta = true,
+            _ => return None,
+        }
+        modifiers.sides = HeldSides::KEYS
+            .iter()
+            .position(|&sided| sided == key)
+            .map(|i| HeldSides::from_bits(1 << i));
+        Some(modifiers)
+    }
+
+    /// Whether no modifier is set.
+    pub fn is_empty(self) -> bool {
+        self.flags() == 0
+    }
+
+    /// Whether every modifier set in `other` is set here too. Others may be
+    /// set as well.
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.flags() & other.flags() == other.flags()
+    }
+
+    /// Whether exactly the modifiers set in `other` are set here.
+    pub fn matches_exact(self, other: Modifiers) -> bool {
+        self.flags() == other.flags()
+    }
+
+    /// The modifiers set in either. Sides known in either are kept.
+    pub fn union(self, other: Modifiers) -> Self {
+        let sides = match (self.sides, other.sides) {
+            (None, None) => None,
+            (a, b) => Some(HeldSides::from_bits(
+                a.map_or(0, HeldSides::bits) | b.map_or(0, HeldSides::bits),
+            )),
+        };
+        Self::from_flags(self.flags() | other.flags(), sides)
+    }
+
+    /// The modifiers set here and not in `other`, with the sides of those
+    /// left.
+    pub fn difference(self, other: Modifiers) -> Self {
+        let flags = self.flags() & !other.flags();
+        // Each flag owns two side bits: Ctrl the lowest pair, and so on.
+        let mask = (0..4)
+            .filter(|i| flags & 1 << i != 0)
+            .fold(0, |mask, i| mask | 0b11 << (2 * i));
+        let sides = self.sides.map(|s| HeldSides::from_bits(s.bits() & mask));
+        Self::from_flags(flags, sides)
+    }
+
+    /// One bit per flag, in Ctrl, Shift, Alt, Meta order.
+    fn flags(self) -> u8 {
+        u8::from(self.ctrl)
+            | u8::from(self.shift) << 1
+            | u8::from(self.alt) << 2
+            | u8::from(self.meta) << 3
+    }
+
+    fn from_flags(flags: u8, sides: Option<HeldSides>) -> Self {
+        Self {
+            ctrl: flags & 1 != 0,
+            shift: flags & 1 << 1 != 0,
+            alt: flags & 1 << 2 != 0,
+            meta: flags & 1 << 3 != 0,
+            sides,
+        }
+    }
+
+    /// The modifiers set, as their unified keys in Ctrl, Shift, Alt, Meta
+    /// order.
+    pub fn keys(self) -> Vec<KeyCode> {
+        [
+            (self.ctrl, KeyCode::Ctrl),
+            (self.shift, KeyCode::Shift),
+            (self.alt, KeyCode::Alt),
+            (self.meta, KeyCode::Meta),
+        ]
+        .into_iter()
+        .filter_map(|(set, key)| set.then_some(key))
+        .collect()
+    }
+}
+
+/// The key steps of `Action::Chord`: `modifiers` down in order, `key` down
+/// and up, then `modifiers` up in reverse order.
+pub fn chord_steps(modifiers: Modifiers, key: KeyCode) -> Vec<(KeyCode, KeyState)> {
+    let modifiers = modifiers.keys();
+    let mut steps: Vec<(KeyCode, KeyState)> =
+        modifiers.iter().map(|&m| (m, KeyState::Down)).collect();
+    steps.push((key, KeyState::Down));
+    steps.push((key, KeyState::Up));
+    steps.extend(modifiers.iter().rev().map(|&m| (m, KeyState::Up)));
+    steps
 }
 
 // ---------------------------------------------------------------------------
@@ -190,24 +523,253 @@ pub struct Modifiers {
 
 /// Metadata about the focused window when an input event occurred.
 ///
-/// Fields default to `None` until window-context integration in M11.
+/// Fields are `None` when the backend cannot tell (no window tracking yet, no
+/// focused window, or the query failed).
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WindowContext {
     pub app_id: Option<String>,
     pub title: Option<String>,
 }
 
+/// Selects a top-level window to inject into instead of the focused one
+/// (`[executor] target`).
+///
+/// A window must satisfy every field present. Both comparisons ignore case.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WindowTarget {
+    /// Text the window title must contain.
+    pub title: Option<String>,
+    /// Window class name, matched in full.
+    pub class: Option<String>,
+}
+
+impl WindowTarget {
+    /// Whether a window with this title and class is selected.
+    pub fn matches(&self, title: &str, class: &str) -> bool {
+        let title_ok = self
+            .title
+            .as_ref()
+            .map_or(true, |t| title.to_lowercase().contains(&t.to_lowercase()));
+        let class_ok = self
+            .class
+            .as_ref()
+            .map_or(true, |c| class.eq_ignore_ascii_case(c));
+        title_ok && class_ok
+    }
+}
+
+/// Which injected keys carry hardware scan codes instead of virtual key
+/// codes (`[executor] injection` and `scancode_apps`).
+///
+/// Games that read DirectInput or Raw Input ignore keys sent by virtual key
+/// code alone. Scan codes reach them, but follow the physical layout rather
+/// than the active one, so virtual key codes stay the default. Only the
+/// Windows executor has the choice.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ScancodeInjection {
+    /// Virtual key codes for every key.
+    #[default]
+    Off,
+    /// Scan codes for every key.
+    Always,
+    /// Scan codes while an app in the list is focused, by app id.
+    Apps(Vec<String>),
+}
+
+/// How an executor types text, as reported by `ActionExecutor::text_injection`.
+/// The engine lowers `Action::TypeString` to match (see `engine::text`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextInjection {
+    /// No text path: text is typed as key presses, and only characters
+    /// with a key of their own come through.
+    Keys,
+    /// Any character through `Action::InjectChar`, one at a time.
+    Chars,
+    /// Whole strings through `Action::TypeString`.
+    Strings,
+}
+
+/// What an executor can do, as reported by `ActionExecutor::capabilities`.
+/// The engine checks the loaded rules against it, so a rule that can never
+/// work is reported when it is loaded instead of failing on every press.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Inject key presses and combinations.
+    pub inject_key: bool,
+    /// Type any character, not only those with a key of their own on a US
+    /// layout (see `TextInjection`).
+    pub type_text: bool,
+    /// Move the pointer, press mouse buttons, and scroll.
+    pub pointer: bool,
+    /// Run `Action::SystemCommand`: lock, sleep, and log out. `Action::Exec`
+    /// does not depend on it, as the engine's launcher starts commands.
+    pub system_command: bool,
+    /// Read and set the clipboard. No executor does; the engine sets this
+    /// when it has a `Clipboard`.
+    pub clipboard: bool,
+}
+
+// ---------------------------------------------------------------------------
+// Pointer
+// ---------------------------------------------------------------------------
+
+/// A mouse button, named by its usual role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    /// The thumb button that navigates back.
+    Back,
+    /// The thumb button that navigates forward.
+    Forward,
+    /// Any further button, by its 0-based platform number.
+    Other(u8),
+}
+
+/// A pointer (mouse) event captured from the platform.
+///
+/// Delivered apart from `InputEvent`, so keyboard code never sees one: a
+/// backend only captures pointer events once given a callback with
+/// `InputCapture::set_pointer_callback`, and passes them on unchanged. The
+/// event bus carries both kinds (see `CapturedEvent`).
+///
+/// Coordinates are pixels in the global desktop space: the origin is the
+/// top-left corner of the primary display, y grows downward, and displays
+/// left of or above the primary have negative coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PointerEvent {
+    /// The pointer moved by `dx`, `dy`, to `position` where the backend
+    /// knows it. Backends reading the device itself, as evdev does, see
+    /// only the movement.
+    Motion {
+        dx: i32,
+        dy: i32,
+        position: Option<(i32, i32)>,
+    },
+    /// A button was pressed or released.
+    Button {
+        button: MouseButton,
+        state: KeyState,
+    },
+    /// Scrolling. Positive `dy` scrolls up, positive `dx` right. With
+    /// `discrete`, the values count wheel notches; otherwise pixels, as from
+    /// a trackpad.
+    Scroll { dx: i32, dy: i32, discrete: bool },
+}
+
 // ---------------------------------------------------------------------------
 // Input event
 // ---------------------------------------------------------------------------
 
+/// The keyboard an event came from.
+///
+/// Cloned onto every event, so both fields are shared strings. Backends keep
+/// one `DeviceId` per device and hand out clones.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceId {
+    /// Stable across restarts and replugs where the platform allows, for
+    /// matching in config.
+    pub id: Arc<str>,
+    /// Human-readable name, as the device reports it.
+    pub name: Arc<str>,
+}
+
 /// A single input event captured from the platform.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Equality leaves out `timestamp`: two events are the same event when they
+/// say the same thing, whenever each was stamped.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InputEvent {
     pub key: KeyCode,
     pub state: KeyState,
     pub modifiers: Modifiers,
     pub window: WindowContext,
+    /// When the platform saw the event. Backends whose events carry a time
+    /// convert it with `timestamp_from_age`; the rest stamp the callback time.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "timestamp_us", with = "serialized::timestamp")
+    )]
+    pub timestamp: Instant,
+    /// The keyboard that produced the event, or `None` where the backend
+    /// cannot tell.
+    pub device: Option<DeviceId>,
+    /// Capture only observed the event: the original already reached
+    /// applications, so the engine must not inject it again. Set by backends
+    /// following a `ListenOnly` request.
+    pub observed: bool,
+    /// A Down generated by the keyboard's autorepeat while the key is held,
+    /// rather than a fresh press. Always false for Up.
+    pub is_repeat: bool,
+    /// The key at this physical position on a US ANSI keyboard, when it can
+    /// differ from `key`. Set by backends whose `key` follows the active
+    /// layout (Windows virtual key codes); `None` where `key` already names
+    /// the position, as evdev codes and macOS virtual key codes do.
+    pub position: Option<KeyCode>,
+}
+
+impl PartialEq for InputEvent {
+    fn eq(&self, other: &Self) -> bool {
+        // Destructured so a new field cannot be left out by accident.
+        let InputEvent {
+            key,
+            state,
+            modifiers,
+            window,
+            timestamp: _,
+            device,
+            observed,
+            is_repeat,
+            position,
+        } = self;
+        *key == other.key
+            && *state == other.state
+            && *modifiers == other.modifiers
+            && *window == other.window
+            && *device == other.device
+            && *observed == other.observed
+            && *is_repeat == other.is_repeat
+            && *position == other.position
+    }
+}
+
+impl Eq for InputEvent {}
+
+/// An event as the event bus carries it: a key event, or a pointer event or
+/// focus change stamped when capture handed it over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CapturedEvent {
+    Key(InputEvent),
+    Pointer {
+        event: PointerEvent,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "timestamp_us", with = "serialized::timestamp")
+        )]
+        timestamp: Instant,
+    },
+    /// Another window was focused, or the focused one changed its title.
+    Focus {
+        window: WindowContext,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "timestamp_us", with = "serialized::timestamp")
+        )]
+        timestamp: Instant,
+    },
+}
+
+impl From<InputEvent> for CapturedEvent {
+    fn from(event: InputEvent) -> Self {
+        Self::Key(event)
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -216,11 +778,13 @@ pub struct InputEvent {
 
 /// An action the engine asks the platform backend to execute.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Action {
     /// Remap one key press to another. Not currently emitted; rule engine resolves to InjectKey.
     Remap { from: KeyCode, to: KeyCode },
-    /// Execute a shell command.
-    Exec { command: String },
+    /// Start a command. The engine starts it (see `engine::exec`);
+    /// executors ignore it.
+    Exec { command: ExecCommand },
     /// Type a string via synthetic key events.
     TypeString { text: String },
     /// Let the original event pass through unmodified. Not currently emitted; rule engine uses InjectKey.
@@ -234,6 +798,220 @@ pub enum Action {
     /// pair. Backends that need the current event state (Down/Up) to inject
     /// correctly should receive this variant rather than Remap or Passthrough.
     InjectKey { key: KeyCode, state: KeyState },
+    /// Inject an autorepeat of `key`, which is already held down.
+    ///
+    /// The engine sends this instead of a second `InjectKey` Down when it
+    /// passes on or remaps a captured repeat. Executors that can mark a
+    /// repeat (the macOS autorepeat field, evdev value 2) do so; the rest post
+    /// a plain key press.
+    InjectRepeat { key: KeyCode },
+    /// Tap a key combination such as Ctrl+Shift+T.
+    ///
+    /// The last key is the main key; the preceding keys are modifiers. The
+    /// engine lowers this into a `Chord`, or into ordered `InjectKey` steps
+    /// where a modifier outside the combination must be let go first, so
+    /// that modifiers the user is already holding are left undisturbed;
+    /// executors never see it.
+    KeyCombo(Vec<KeyCode>),
+    /// Tap `key` with `modifiers` pressed around it, as one unit that no
+    /// other input lands inside. The modifiers are released again after the
+    /// key; ones already held are not listed, so they are left alone.
+    ///
+    /// Each executor injects it whole: one `SendInput` array on Windows,
+    /// one key pair carrying the modifier flags on macOS, and one submission
+    /// to the device or session on Linux. `chord_steps` gives its steps.
+    Chord { modifiers: Modifiers, key: KeyCode },
+    /// Pass the captured key on with `modifiers` held around it, as a remap
+    /// of J to Ctrl+J that keeps the key and its Down and Up.
+    ///
+    /// Lowered by the engine like `KeyCombo`: modifiers the user already
+    /// holds are left alone, and the rest are released again right after
+    /// the key, so the user's own modifier state is what remains.
+    WithModifiers(Vec<KeyCode>),
+    /// Type one Unicode character, independent of the keyboard layout.
+    ///
+    /// Uses the platform's Unicode path (`KEYEVENTF_UNICODE`,
+    /// `CGEventKeyboardSetUnicodeString`, portal keysyms) rather than key
+    /// codes, so it produces the same character on every layout.
+    InjectChar(char),
+    /// Run a portable system operation such as locking the screen. Each
+    /// platform maps it to its own mechanism, or reports `Unavailable`.
+    SystemCommand(SystemCmd),
+    /// Perform `action` `count` times in a row; `count = 0` does nothing.
+    ///
+    /// Run by the engine as a sequence of `count` copies with the inter-key
+    /// delay (`[executor] type_delay_ms`) between them, so a `Delay` inside
+    /// a copy holds up the rest in order; executors never see it.
+    Repeat { action: Box<Action>, count: u32 },
+    /// Perform `action` on the trigger key's Down, then again every
+    /// `interval_ms` for as long as the key is held.
+    ///
+    /// Interpreted by the engine, which runs the repeats from its timer and
+    /// stops them on the key's Up, on pausing, or after `MAX_HELD_REPEATS`
+    /// runs, whichever comes first. The key's own auto-repeat does nothing
+    /// meanwhile. `action` may not be another `RepeatWhileHeld`.
+    RepeatWhileHeld {
+        interval_ms: u64,
+        action: Box<Action>,
+    },
+    /// Wait `ms` milliseconds before the actions that follow it.
+    ///
+    /// The engine does not block on it: the rest of the rule's output is
+    /// set aside and run from `Engine::poll` once the time is up, so other
+    /// keys go on being remapped meanwhile. An executor handed one directly
+    /// has nothing to wait for and ignores it.
+    Delay { ms: u64 },
+    /// Perform `steps` one after another.
+    ///
+    /// Interpreted by the engine, so executors never see it: a `Delay` step
+    /// holds up the steps after it without blocking other input, and a
+    /// sequence may hold sequences of its own, up to `MAX_SEQUENCE_DEPTH`
+    /// deep. Where a step fails, `on_error` says whether the rest of the
+    /// sequence still runs. Pausing the engine drops sequences waiting out
+    /// a delay.
+    Sequence {
+        steps: Vec<Action>,
+        on_error: StepFailure,
+    },
+    /// Move the pointer by `dx`, `dy` pixels.
+    MouseMoveRelative { dx: i32, dy: i32 },
+    /// Move the pointer to `x`, `y`: pixels in the global desktop space (see
+    /// `PointerEvent`), or with `normalized`, 0 to 65535 across the bounding
+    /// box of all displays.
+    ///
+    /// uinput and the Wayland portal have nothing to place the pointer
+    /// with, and skip it.
+    MouseMoveAbsolute { x: i32, y: i32, normalized: bool },
+    /// Press or release a mouse button where the pointer is.
+    MouseButton {
+        button: MouseButton,
+        state: KeyState,
+    },
+    /// Scroll, with the sign and unit conventions of `PointerEvent::Scroll`.
+    ///
+    /// XTest scrolls by notch only, and skips pixel scrolling.
+    Scroll { dx: i32, dy: i32, discrete: bool },
+    /// Type the clipboard's text as `TypeString` would.
+    ///
+    /// The engine reads the clipboard on a worker thread and types the text
+    /// once it arrives; executors never see it. An empty clipboard, or one
+    /// holding no text, types nothing.
+    TypeClipboard,
+    /// Pause rules with `Some(true)`, resume them with `Some(false)`, or
+    /// flip the pause with `None`, as the `pause` and `resume` control
+    /// commands do.
+    ///
+    /// Handled by the engine. Pausing from a rule's output drops whatever
+    /// that output had left to run.
+    SetPaused { paused: Option<bool> },
+    /// Run `command` as `Exec` does and type what it prints to stdout as
+    /// `TypeString` would, without the line break it ends with.
+    ///
+    /// The engine's launcher captures the output and the engine types it
+    /// once the command exits. A command that fails, or is killed for
+    /// running past `[exec] capture_timeout_ms`, types nothing.
+    TypeOutput { command: ExecCommand },
+    /// Put `text` on the clipboard.
+    ///
+    /// Set on the engine's clipboard worker, like `TypeClipboard`'s read;
+    /// executors never see it.
+    ClipboardSet { text: String },
+    /// Paste `text`: put it on the clipboard, then press the paste shortcut
+    /// (Cmd+V on macOS, Ctrl+V elsewhere) once it is there.
+    ///
+    /// Faster than typing for long text, and exact whatever the layout. The
+    /// engine can put back what the clipboard held a while later (see
+    /// `[clipboard] restore_after_ms`). Nothing is pasted when the
+    /// clipboard cannot be set.
+    PasteText { text: String },
+}
+
+/// How deep `Action::Sequence` may nest. A step deeper than this fails.
+pub const MAX_SEQUENCE_DEPTH: usize = 8;
+
+/// The most times an `Action::RepeatWhileHeld` runs for one press, so a key
+/// whose Up is lost does not repeat forever.
+pub const MAX_HELD_REPEATS: u32 = 1_000;
+
+/// What an `Action::Sequence` does when one of its steps fails to inject.
+/// A sequence that stops counts as a failed step of the one around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StepFailure {
+    /// Skip the rest of the sequence.
+    #[default]
+    Abort,
+    /// Log the failure and go on with the next step.
+    Continue,
+}
+
+/// A system operation with a portable name, for `Action::SystemCommand`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SystemCmd {
+    LockScreen,
+    Sleep,
+    LogOut,
+}
+
+/// A command for `Action::Exec` and `Action::TypeOutput` to start, with
+/// where and how. `[exec]` settings apply underneath.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExecCommand {
+    pub line: CommandLine,
+    /// Working directory, in place of `[exec] cwd`.
+    pub cwd: Option<PathBuf>,
+    /// Variables set on top of `[exec] env`.
+    pub env: Vec<(String, String)>,
+}
+
+/// How a command is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CommandLine {
+    /// A line for the platform shell: `sh -c` on Unix, `cmd /C` on Windows.
+    Shell(String),
+    /// A program and its arguments, started directly with no shell, so
+    /// each argument arrives as written. Never empty.
+    Argv(Vec<String>),
+}
+
+impl From<&str> for ExecCommand {
+    /// A shell command with no settings of its own.
+    fn from(line: &str) -> Self {
+        Self {
+            line: CommandLine::Shell(line.to_owned()),
+            cwd: None,
+            env: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ExecCommand {
+    /// The command line for logs: a shell line as written, and an argv
+    /// list with spaces between arguments, quoting those that need it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let argv = match &self.line {
+            CommandLine::Shell(line) => return f.write_str(line),
+            CommandLine::Argv(argv) => argv,
+        };
+        for (i, arg) in argv.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            let plain = !arg.is_empty()
+                && !arg
+                    .chars()
+                    .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'));
+            if plain {
+                f.write_str(arg)?;
+            } else {
+                write!(f, "{arg:?}")?;
+            }
+        }
+        Ok(())
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -241,7 +1019,11 @@ pub enum Action {
 // ---------------------------------------------------------------------------
 
 /// Errors returned by platform operations.
-#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+///
+/// Where an underlying error caused the failure, the variant keeps it as its
+/// `source`, so callers can match on the kind of failure and walk the chain,
+/// while `Display` still reads as a single message.
+#[derive(Debug, thiserror::Error)]
 pub enum PlatformError {
     /// Required permission was not granted (e.g. macOS accessibility).
     #[error("permission denied: {0}")]
@@ -251,37 +1033,105 @@ pub enum PlatformError {
     #[error("unavailable: {0}")]
     Unavailable(String),
 
+    /// A required device does not exist (e.g. no nodes under /dev/input).
+    #[error("not found: {0}")]
+    NotFound(String),
+
     /// Config file could not be loaded or validated.
     #[error("config error: {0}")]
     Config(String),
 
+    /// An I/O operation failed, such as opening or writing a device node.
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A D-Bus call failed, such as a request to an xdg-desktop-portal.
+    #[error("{context}: {source}")]
+    Dbus {
+        context: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// An executor middleware dropped the action, such as the rate limit.
+    #[error("suppressed: {0}")]
+    Suppressed(String),
+
     /// Any other platform error.
     #[error("{0}")]
     Other(String),
 }
 
+impl PlatformError {
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub fn dbus(
+        context: impl Into<String>,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        Self::Dbus {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
-// Subprocess helpers
+// Event timestamps
 // ---------------------------------------------------------------------------
 
-/// Spawns a shell command as a non-blocking subprocess.
+/// Ages above this mean the platform clock does not match ours (or jumped),
+/// so the event is stamped on arrival instead.
+const MAX_EVENT_AGE: Duration = Duration::from_secs(5);
+
+/// Places a platform event time on the `Instant` timeline.
 ///
-/// On Unix, executes via `sh -c`; on Windows, via `cmd /C`.
-/// The child process is detached; its exit status is not observed, but on
-/// Unix a background thread waits on the child to avoid zombie processes.
-pub fn spawn_command(command: &str) -> Result<(), PlatformError> {
-    #[cfg(not(target_os = "windows"))]
-    let result = std::process::Command::new("sh")
-        .arg("-c")
-        .arg(command)
-        .spawn();
-
-    #[cfg(target_os = "windows")]
-    let result = std::process::Command::new("cmd")
-        .args(["/C", command])
-        .spawn();
-
-    match result {
+/// `age` is how long before `now` the platform says the event happened,
+/// computed on the platform's own clock; `None` when it could not be
+/// computed (the event is in that clock's future).
+pub fn timestamp_from_age(age: Option<Duration>, now: Instant) -> Instant {
+    age.filter(|a| *a <= MAX_EVENT_AGE)
+        .and_then(|a| now.checked_sub(a))
+        .unwrap_or(now)
+}
+
+// ---------------------------------------------------------------------------
+// Unicode helpers
+// ---------------------------------------------------------------------------
+
+/// Encodes `c` as UTF-16 code units, as taken by the Windows and macOS Unicode
+/// injection APIs. Codepoints above U+FFFF become a surrogate pair.
+pub fn utf16_units(c: char) -> Vec<u16> {
+    let mut buf = [0u16; 2];
+    c.encode_utf16(&mut buf).to_vec()
+}
+
+// ---------------------------------------------------------------------------
+// Subprocess helpers
+// ---------------------------------------------------------------------------
+
+/// Spawns `argv[0]` with the remaining arguments, without a shell, as a
+/// non-blocking subprocess. The child process is detached; its exit status
+/// is not observed, but on Unix a background thread waits on the child to
+/// avoid zombie processes.
+pub fn spawn_program(argv: &[String]) -> Result<(), PlatformError> {
+    let Some((program, args)) = argv.split_first() else {
+        return Err(PlatformError::Other("exec: empty command line".into()));
+    };
+    spawn_detached(std::process::Command::new(program).args(args))
+}
+
+fn spawn_detached(cmd: &mut std::process::Command) -> Result<(), PlatformError> {
+    match cmd.spawn() {
         Ok(child) => {
             log::debug!("exec: spawned pid {}", child.id());
 
@@ -302,7 +1152,119 @@ pub fn spawn_command(command: &str) -> Result<(), PlatformError> {
 
             Ok(())
         }
-        Err(e) => Err(PlatformError::Other(format!("exec: spawn failed: {e}"))),
+        Err(e) => Err(PlatformError::io("exec: spawn failed", e)),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Suspension
+// ---------------------------------------------------------------------------
+
+/// Shared flag through which a capture backend suspends rule processing while
+/// the platform cannot capture or inject reliably (e.g. macOS Secure Input).
+///
+/// Separate from the user's pause: the backend sets and lifts it on its own,
+/// and the reason is shown in status output. Clones refer to the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct Suspension(Arc<Mutex<Option<String>>>);
+
+impl Suspension {
+    /// Suspend rules, replacing any earlier reason.
+    pub fn suspend(&self, reason: impl Into<String>) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = Some(reason.into());
+        }
+    }
+
+    /// Lift the suspension.
+    pub fn lift(&self) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = None;
+        }
+    }
+
+    /// Why rules are suspended, or `None` when they are not.
+    pub fn reason(&self) -> Option<String> {
+        self.0.lock().ok().and_then(|current| current.clone())
+    }
+}
+
+/// Shared request for capture to listen only: let every event through
+/// untouched and report it with `InputEvent::observed` set.
+///
+/// The engine sets it while rules are paused, when nothing it does would
+/// change a key, so the keyboard takes no detour through the engine. Backends
+/// switch as it changes. Clones refer to the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct ListenOnly(Arc<AtomicBool>);
+
+impl ListenOnly {
+    pub fn set(&self, on: bool) {
+        self.0.store(on, Ordering::Relaxed);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// When the capture backend last showed it is alive.
+///
+/// Backends with a loop of their own beat it from that loop, key or no key,
+/// so a hung backend can be told from an idle keyboard. Clones refer to the
+/// same time.
+#[derive(Debug, Clone, Default)]
+pub struct Heartbeat(Arc<Mutex<Option<Instant>>>);
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+    }
+
+    /// The last beat, or `None` if the backend never beat.
+    pub fn last(&self) -> Option<Instant> {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether the user's session is locked or disconnected, as reported by
+/// the platform's session watcher (see `watch_session`).
+///
+/// Keys injected then would go to the lock screen or queue up for the
+/// session's return, so the engine pauses rules while either is set.
+/// Clones refer to the same state.
+#[derive(Debug, Clone, Default)]
+pub struct SessionLock(Arc<Mutex<SessionState>>);
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SessionState {
+    locked: bool,
+    disconnected: bool,
+}
+
+impl SessionLock {
+    pub fn set_locked(&self, locked: bool) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).locked = locked;
+    }
+
+    /// A remote session dropped, or the console switched to another user.
+    pub fn set_disconnected(&self, disconnected: bool) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .disconnected = disconnected;
+    }
+
+    /// Why rules are paused for the session, or `None` while it is in use.
+    pub fn reason(&self) -> Option<&'static str> {
+        let state = *self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if state.disconnected {
+            Some("session disconnected")
+        } else if state.locked {
+            Some("session locked")
+        } else {
+            None
+        }
     }
 }
 
@@ -319,6 +1281,100 @@ pub trait InputCapture: Send {
 
     /// Stop capturing input events.
     fn stop(&mut self) -> Result<(), PlatformError>;
+
+    /// Hand the backend the suspension flag it may set while capture is
+    /// degraded. Called before `start`. Backends that never degrade ignore
+    /// it, which is the default.
+    fn set_suspension(&mut self, suspension: Suspension) {
+        let _ = suspension;
+    }
+
+    /// Whether the user built and signed a binary that should run with
+    /// UIAccess, and wants a warning when it does not. Called before
+    /// `start`. Only the Windows backend follows it; the others ignore it,
+    /// which is the default.
+    fn set_ui_access_expected(&mut self, expected: bool) {
+        let _ = expected;
+    }
+
+    /// Whether `start` may ask the user for a missing permission through a
+    /// system dialog and wait for the answer. Called before `start`; when
+    /// false, a missing permission fails `start` at once. Backends with
+    /// nothing to ask for ignore it, which is the default.
+    fn set_permission_prompt(&mut self, allowed: bool) {
+        let _ = allowed;
+    }
+
+    /// Also capture pointer events and hand each to `callback`. Called
+    /// before `start`. Pointer events are observed, never suppressed.
+    /// Backends without pointer capture ignore it, which is the default.
+    fn set_pointer_callback(&mut self, callback: Box<dyn Fn(PointerEvent) + Send>) {
+        let _ = callback;
+    }
+
+    /// Hand `callback` the window context each time the focus moves to
+    /// another window, or the focused one is retitled, without waiting for
+    /// a key. Called before `start`. Backends that track no window ignore
+    /// it, which is the default.
+    fn set_focus_callback(&mut self, callback: Box<dyn Fn(WindowContext) + Send>) {
+        let _ = callback;
+    }
+
+    /// Fold the pointer moves within each `interval` into one, with the
+    /// summed deltas and the latest position; `None` delivers every move.
+    /// Called before `start`. Backends that deliver moves at a rate the
+    /// callback can take anyway ignore it, which is the default.
+    fn set_motion_coalescing(&mut self, interval: Option<Duration>) {
+        let _ = interval;
+    }
+
+    /// Hand the backend the engine's listen-only request. Called before
+    /// `start`. Backends that can observe without intercepting follow it,
+    /// also while running; the rest ignore it, which is the default, and the
+    /// engine re-injects the keys it passes through.
+    fn set_listen_only(&mut self, listen_only: ListenOnly) {
+        let _ = listen_only;
+    }
+
+    /// Hand the backend a heartbeat to beat from its own loop at least every
+    /// few seconds. Called before `start`. Backends whose loop only runs for
+    /// events ignore it, which is the default; their health is judged by the
+    /// engine loop alone.
+    fn set_heartbeat(&mut self, heartbeat: Heartbeat) {
+        let _ = heartbeat;
+    }
+
+    /// Ask the backend to have CapsLock arrive as `target`, mapped below
+    /// the OS lock handling, from `start` until `stop`. Called before
+    /// `start`. Only the macOS backend does this; the rest ignore it, which
+    /// is the default.
+    fn set_caps_lock_remap(&mut self, target: KeyCode) {
+        let _ = target;
+    }
+
+    /// Report the keys that share a code with F13-F15 as `PrintScreen`,
+    /// `ScrollLock`, and `Pause` instead. Called before `start`. Only macOS
+    /// cannot tell them apart; the other backends ignore it, which is the
+    /// default.
+    fn set_system_fkeys(&mut self, on: bool) {
+        let _ = on;
+    }
+
+    /// How long `start` keeps looking for keyboards that are not there yet,
+    /// as right after boot, before failing. Called before `start`. Only the
+    /// Linux backend opens devices that may still be appearing; the rest
+    /// ignore it, which is the default.
+    fn set_device_wait(&mut self, wait: Duration) {
+        let _ = wait;
+    }
+
+    /// Show a notification area icon for `tray` from `start` until `stop`.
+    /// Called before `start`. Only the Windows backend has a message loop
+    /// to host it; test doubles ignore it, which is the default.
+    #[cfg(all(target_os = "windows", feature = "tray"))]
+    fn set_tray(&mut self, tray: Tray) {
+        let _ = tray;
+    }
 }
 
 /// Executes actions on the platform (key synthesis, command execution, etc.).
@@ -327,6 +1383,78 @@ pub trait InputCapture: Send {
 pub trait ActionExecutor: Send {
     /// Execute the given action.
     fn execute(&self, action: &Action) -> Result<(), PlatformError>;
+
+    /// Execute `actions` in order as one unit, so no other input lands
+    /// between them, where the backend can. The engine sends the keys of a
+    /// combination this way. Backends that cannot do that run them one by
+    /// one and stop at the first error; that is the default.
+    fn execute_batch(&self, actions: &[Action]) -> Result<(), PlatformError> {
+        actions.iter().try_for_each(|action| self.execute(action))
+    }
+
+    /// Short backend name for logs and status output (e.g. `portal`).
+    fn name(&self) -> &'static str;
+
+    /// Query the current toggle state of a lock key (NumLock, CapsLock,
+    /// ScrollLock).
+    ///
+    /// Called once at startup so the engine can seed its lock tracking; after
+    /// that the engine follows the lock presses it injects. Returns `None`
+    /// when the backend cannot tell, which is the default.
+    fn lock_state(&self, key: KeyCode) -> Option<bool> {
+        let _ = key;
+        None
+    }
+
+    /// Send injected input to the window `target` selects instead of the
+    /// focused one. Called once, before the first `execute`. Backends that
+    /// cannot address a single window fail, which is the default.
+    fn set_target(&mut self, target: WindowTarget) -> Result<(), PlatformError> {
+        let _ = target;
+        Err(PlatformError::Unavailable(format!(
+            "executor backend '{}' cannot target a window (only sendinput on Windows can)",
+            self.name()
+        )))
+    }
+
+    /// Choose when keys are injected by scan code. Called once, before the
+    /// first `execute`. Only the Windows backend follows it; the others
+    /// ignore it, which is the default.
+    fn set_scancode_injection(&mut self, mode: ScancodeInjection) {
+        let _ = mode;
+    }
+
+    /// How the backend types text. Backends without a text path of their
+    /// own get text as key presses, which is the default.
+    fn text_injection(&self) -> TextInjection {
+        TextInjection::Keys
+    }
+
+    /// What the backend can do. The default is key injection, and text as
+    /// `text_injection` reports it; backends that do more say so.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            inject_key: true,
+            type_text: self.text_injection() != TextInjection::Keys,
+            ..Capabilities::default()
+        }
+    }
+}
+
+/// Reads and sets the system clipboard.
+///
+/// Both may block, on another program holding the clipboard or on a helper
+/// process, so the engine only calls these from a worker thread.
+pub trait Clipboard: Send {
+    /// The clipboard's text, or `None` when it is empty or holds no text.
+    fn read_text(&self) -> Result<Option<String>, PlatformError>;
+
+    /// Replace the clipboard's contents with `text`.
+    fn write_text(&self, _text: &str) -> Result<(), PlatformError> {
+        Err(PlatformError::Unavailable(
+            "this clipboard cannot be set".into(),
+        ))
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -337,6 +1465,51 @@ pub trait ActionExecutor: Send {
 mod tests {
     use super::*;
 
+    #[test]
+    fn window_target_requires_every_field() {
+        let target = WindowTarget {
+            title: Some("notepad".into()),
+            class: Some("Notepad".into()),
+        };
+        assert!(target.matches("notes.txt - Notepad", "NOTEPAD"));
+        assert!(!target.matches("notes.txt - Notepad", "Edit"));
+        assert!(!target.matches("Untitled - Paint", "Notepad"));
+
+        let title_only = WindowTarget {
+            title: Some("Paint".into()),
+            class: None,
+        };
+        assert!(title_only.matches("Untitled - Paint", "MSPaintApp"));
+    }
+
+    #[test]
+    fn suspension_is_shared_between_clones() {
+        let suspension = Suspension::default();
+        let clone = suspension.clone();
+        assert_eq!(clone.reason(), None);
+        suspension.suspend("secure input (loginwindow)");
+        assert_eq!(
+            clone.reason().as_deref(),
+            Some("secure input (loginwindow)")
+        );
+        clone.lift();
+        assert_eq!(suspension.reason(), None);
+    }
+
+    #[test]
+    fn session_reason_prefers_disconnect() {
+        let session = SessionLock::default();
+        let clone = session.clone();
+        session.set_locked(true);
+        assert_eq!(clone.reason(), Some("session locked"));
+        // A remote session is usually locked as it drops.
+        session.set_disconnected(true);
+        assert_eq!(clone.reason(), Some("session disconnected"));
+        session.set_disconnected(false);
+        session.set_locked(false);
+        assert_eq!(clone.reason(), None);
+    }
+
     #[test]
     fn key_code_variants_construct() {
         // Spot-check representative variants from each category
@@ -349,6 +1522,18 @@ mod tests {
         let _punct = KeyCode::Backtick;
     }
 
+    #[test]
+    fn numpad_navigation_round_trips() {
+        assert_eq!(KeyCode::Numpad7.numpad_navigation(), Some(KeyCode::Home));
+        assert_eq!(KeyCode::Home.numpad_origin(), Some(KeyCode::Numpad7));
+        assert_eq!(
+            KeyCode::Delete.numpad_origin(),
+            Some(KeyCode::NumpadDecimal)
+        );
+        assert_eq!(KeyCode::Numpad5.numpad_navigation(), None);
+        assert_eq!(KeyCode::Home.numpad_navigation(), None);
+    }
+
     #[test]
     fn key_state_variants_are_distinct() {
         assert_ne!(KeyState::Down, KeyState::Up);
@@ -363,6 +1548,120 @@ mod tests {
         assert!(!m.meta);
     }
 
+    #[test]
+    fn sided_modifiers_unify_and_covers_follows() {
+        for (left, right, unified) in [
+            (KeyCode::LeftCtrl, KeyCode::RightCtrl, KeyCode::Ctrl),
+            (KeyCode::LeftShift, KeyCode::RightShift, KeyCode::Shift),
+            (KeyCode::LeftAlt, KeyCode::RightAlt, KeyCode::Alt),
+            (KeyCode::LeftMeta, KeyCode::RightMeta, KeyCode::Meta),
+        ] {
+            for key in [left, right, unified] {
+                assert!(key.is_modifier(), "{key:?}");
+                assert_eq!(key.unify_side(), unified);
+                assert!(unified.covers(key));
+            }
+            assert!(left.is_left_modifier() && !right.is_left_modifier());
+            assert!(!left.covers(right) && !left.covers(unified));
+        }
+        assert!(!KeyCode::A.is_modifier());
+        assert_eq!(KeyCode::A.unify_side(), KeyCode::A);
+    }
+
+    #[test]
+    fn held_sides_list_sided_keys() {
+        let sides = HeldSides {
+            right_ctrl: true,
+            left_shift: true,
+            right_meta: true,
+            ..HeldSides::default()
+        };
+        assert_eq!(
+            sides.keys(),
+            vec![KeyCode::RightCtrl, KeyCode::LeftShift, KeyCode::RightMeta]
+        );
+    }
+
+    fn mods(keys: &[KeyCode]) -> Modifiers {
+        keys.iter()
+            .map(|&k| Modifiers::from_keycode(k).unwrap())
+            .fold(Modifiers::default(), Modifiers::union)
+    }
+
+    #[test]
+    fn modifiers_from_keycode() {
+        assert_eq!(
+            Modifiers::from_keycode(KeyCode::Alt),
+            Some(Modifiers {
+                alt: true,
+                ..Modifiers::default()
+            })
+        );
+        assert_eq!(
+            Modifiers::from_keycode(KeyCode::RightMeta),
+            Some(Modifiers {
+                meta: true,
+                sides: Some(HeldSides {
+                    right_meta: true,
+                    ..HeldSides::default()
+                }),
+                ..Modifiers::default()
+            })
+        );
+        assert_eq!(Modifiers::from_keycode(KeyCode::A), None);
+        assert_eq!(Modifiers::from_keycode(KeyCode::CapsLock), None);
+    }
+
+    #[test]
+    fn modifiers_contain_and_match() {
+        let ctrl_shift = mods(&[KeyCode::Ctrl, KeyCode::Shift]);
+        assert!(ctrl_shift.contains(mods(&[KeyCode::Ctrl])));
+        assert!(ctrl_shift.contains(Modifiers::default()));
+        assert!(!ctrl_shift.contains(mods(&[KeyCode::Ctrl, KeyCode::Alt])));
+        assert!(ctrl_shift.matches_exact(mods(&[KeyCode::Shift, KeyCode::Ctrl])));
+        assert!(!ctrl_shift.matches_exact(mods(&[KeyCode::Ctrl])));
+        // Sides do not take part in matching.
+        assert!(ctrl_shift.matches_exact(mods(&[KeyCode::RightCtrl, KeyCode::LeftShift])));
+        assert!(Modifiers::default().is_empty());
+        assert!(!ctrl_shift.is_empty());
+        assert!(!mods(&[KeyCode::LeftMeta]).is_empty());
+    }
+
+    #[test]
+    fn modifiers_union_and_difference() {
+        let held = mods(&[KeyCode::LeftCtrl, KeyCode::RightAlt]);
+        let wanted = mods(&[KeyCode::Ctrl, KeyCode::Shift]);
+        assert_eq!(
+            wanted.difference(held),
+            Modifiers {
+                shift: true,
+                ..Modifiers::default()
+            }
+        );
+        assert_eq!(
+            held.difference(wanted),
+            Modifiers {
+                alt: true,
+                sides: Some(HeldSides {
+                    right_alt: true,
+                    ..HeldSides::default()
+                }),
+                ..Modifiers::default()
+            }
+        );
+        let both = held.union(wanted);
+        assert_eq!(
+            both.keys(),
+            vec![KeyCode::Ctrl, KeyCode::Shift, KeyCode::Alt]
+        );
+        assert_eq!(
+            both.sides.map(HeldSides::keys),
+            Some(vec![KeyCode::LeftCtrl, KeyCode::RightAlt])
+        );
+        assert_eq!(wanted.union(wanted).sides, None);
+        assert!(held.difference(held).is_empty());
+    }
+
     #[test]
     fn modifiers_field_access() {
         let m = Modifiers {
@@ -370,6 +1669,7 @@ mod tests {
             shift: false,
             alt: true,
             meta: false,
+            sides: None,
         };
         assert!(m.ctrl);
         assert!(!m.shift);
@@ -404,6 +1704,11 @@ mod tests {
                 ..Modifiers::default()
             },
             window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
         };
         assert_eq!(event.key, KeyCode::A);
         assert_eq!(event.state, KeyState::Down);
@@ -411,6 +1716,49 @@ mod tests {
         assert!(event.window.app_id.is_none());
     }
 
+    #[test]
+    fn input_event_equality_ignores_the_timestamp() {
+        let event = InputEvent {
+            key: KeyCode::A,
+            state: KeyState::Down,
+            modifiers: Modifiers::default(),
+            window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
+        };
+        let later = InputEvent {
+            timestamp: event.timestamp + Duration::from_millis(5),
+            ..event.clone()
+        };
+        assert_eq!(event, later);
+        let repeat = InputEvent {
+            is_repeat: true,
+            ..event.clone()
+        };
+        assert_ne!(event, repeat);
+    }
+
+    #[test]
+    fn exec_command_displays_argv_quoted_where_needed() {
+        let command = ExecCommand {
+            line: CommandLine::Argv(
+                ["notify-send", "hi there", "say \"x\"", "", "héllo"]
+                    .map(String::from)
+                    .to_vec(),
+            ),
+            cwd: None,
+            env: Vec::new(),
+        };
+        assert_eq!(
+            command.to_string(),
+            r#"notify-send "hi there" "say \"x\"" "" héllo"#
+        );
+        assert_eq!(ExecCommand::from("echo 'a b'").to_string(), "echo 'a b'");
+    }
+
     #[test]
     fn action_variants_construct() {
         let _remap = Action::Remap {
@@ -473,6 +1821,27 @@ mod tests {
         let _ = e.to_string();
     }
 
+    #[test]
+    fn platform_error_keeps_its_cause_as_source() {
+        use std::error::Error;
+        use std::io;
+
+        let e = PlatformError::io(
+            "uinput write failed",
+            io::Error::new(io::ErrorKind::BrokenPipe, "device gone"),
+        );
+        assert_eq!(e.to_string(), "uinput write failed: device gone");
+        let source = e.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::BrokenPipe);
+
+        let e = PlatformError::dbus("cannot create a session", "portal refused");
+        assert_eq!(e.to_string(), "cannot create a session: portal refused");
+        assert_eq!(e.source().unwrap().to_string(), "portal refused");
+        assert!(matches!(e, PlatformError::Dbus { .. }));
+
+        assert!(PlatformError::Other("test".into()).source().is_none());
+    }
+
     /// Compile-time assertion that trait signatures are well-formed.
     /// This function is never called; it only needs to compile.
     #[allow(dead_code)]
@@ -485,4 +1854,52 @@ mod tests {
             let _ = e.execute(&Action::Passthrough);
         }
     }
+
+    #[test]
+    fn chord_steps_wrap_the_key_in_modifiers() {
+        let modifiers = Modifiers {
+            ctrl: true,
+            shift: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(
+            chord_steps(modifiers, KeyCode::T),
+            vec![
+                (KeyCode::Ctrl, KeyState::Down),
+                (KeyCode::Shift, KeyState::Down),
+                (KeyCode::T, KeyState::Down),
+                (KeyCode::T, KeyState::Up),
+                (KeyCode::Shift, KeyState::Up),
+                (KeyCode::Ctrl, KeyState::Up),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16_units_bmp_char_is_one_unit() {
+        assert_eq!(utf16_units('\u{2014}'), vec![0x2014]);
+    }
+
+    #[test]
+    fn utf16_units_high_codepoint_is_surrogate_pair() {
+        // U+1F600 GRINNING FACE
+        assert_eq!(utf16_units('\u{1F600}'), vec![0xD83D, 0xDE00]);
+    }
+
+    #[test]
+    fn timestamp_from_age_backdates_by_the_age() {
+        let now = Instant::now();
+        let age = Duration::from_millis(7);
+        assert_eq!(timestamp_from_age(Some(age), now), now - age);
+    }
+
+    #[test]
+    fn timestamp_from_age_falls_back_to_now() {
+        let now = Instant::now();
+        assert_eq!(timestamp_from_age(None, now), now);
+        assert_eq!(
+            timestamp_from_age(Some(Duration::from_secs(3600)), now),
+            now
+        );
+    }
 }
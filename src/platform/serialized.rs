@@ -0,0 +1,259 @@
+//! The serialized form of events and actions, behind the `serde` feature.
+//!
+//! Recordings, replay, and anything sent between processes read and write
+//! these types through serde, so their form is fixed here and by the golden
+//! tests below; a change that breaks one is a breaking change to every file
+//! and peer using it.
+//!
+//! - Keys, key states, and buttons are their variant names as strings:
+//!   `"LeftCtrl"`, `"Key1"`, `"Down"`.
+//! - Actions, pointer events, and captured events are externally tagged:
+//!   `{"InjectKey":{"key":"A","state":"Down"}}`, and a bare string for a
+//!   variant with no fields, such as `"Suppress"`.
+//! - `Modifiers::sides` is left out when `None`.
+//! - A timestamp is written as `timestamp_us`, signed microseconds from the
+//!   process epoch (see `epoch`). An `Instant` means nothing outside the
+//!   process that took it; microseconds from a shared point keep the gaps
+//!   between events, which is what replay needs.
+
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// The point timestamps are measured from: the first time it is asked for
+/// in this process. Events stamped before it have negative timestamps.
+pub fn epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+/// `Instant` as signed microseconds from `epoch`, for `#[serde(with)]`.
+pub(super) mod timestamp {
+    use std::time::{Duration, Instant};
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::epoch;
+
+    pub fn serialize<S: Serializer>(at: &Instant, serializer: S) -> Result<S::Ok, S::Error> {
+        let epoch = epoch();
+        let us = match at.checked_duration_since(epoch) {
+            Some(after) => i64::try_from(after.as_micros()).unwrap_or(i64::MAX),
+            None => {
+                let before = epoch.duration_since(*at).as_micros();
+                i64::try_from(before).map_or(i64::MIN, |us| -us)
+            }
+        };
+        serializer.serialize_i64(us)
+    }
+
+    /// Timestamps beyond what `Instant` can hold here become the epoch.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Instant, D::Error> {
+        let us = i64::deserialize(deserializer)?;
+        let offset = Duration::from_micros(us.unsigned_abs());
+        let at = if us < 0 {
+            epoch().checked_sub(offset)
+        } else {
+            epoch().checked_add(offset)
+        };
+        Ok(at.unwrap_or_else(epoch))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::platform::{
+        Action, CapturedEvent, CommandLine, DeviceId, ExecCommand, HeldSides, InputEvent, KeyCode,
+        KeyState, Modifiers, MouseButton, PointerEvent, StepFailure, SystemCmd, WindowContext,
+    };
+
+    fn event() -> InputEvent {
+        InputEvent {
+            key: KeyCode::LeftCtrl,
+            state: KeyState::Down,
+            modifiers: Modifiers {
+                ctrl: true,
+                sides: Some(HeldSides {
+                    left_ctrl: true,
+                    ..HeldSides::default()
+                }),
+                ..Modifiers::default()
+            },
+            window: WindowContext {
+                app_id: Some("firefox".into()),
+                title: None,
+            },
+            timestamp: epoch() + Duration::from_micros(1_500),
+            device: Some(DeviceId {
+                id: Arc::from("046d:c52b"),
+                name: Arc::from("USB Receiver"),
+            }),
+            observed: false,
+            is_repeat: true,
+            position: Some(KeyCode::Q),
+        }
+    }
+
+    /// `value` as JSON, checked against `golden` and read back unchanged.
+    fn frozen<T>(value: &T, golden: &str)
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        let json = serde_json::to_string(value).unwrap();
+        assert_eq!(json, golden);
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn input_event_golden() {
+        frozen(
+            &event(),
+            concat!(
+                r#"{"key":"LeftCtrl","state":"Down","#,
+                r#""modifiers":{"ctrl":true,"shift":false,"alt":false,"meta":false,"#,
+                r#""sides":{"left_ctrl":true,"right_ctrl":false,"left_shift":false,"#,
+                r#""right_shift":false,"left_alt":false,"right_alt":false,"left_meta":false,"#,
+                r#""right_meta":false}},"#,
+                r#""window":{"app_id":"firefox","title":null},"timestamp_us":1500,"#,
+                r#""device":{"id":"046d:c52b","name":"USB Receiver"},"#,
+                r#""observed":false,"is_repeat":true,"position":"Q"}"#,
+            ),
+        );
+    }
+
+    #[test]
+    fn timestamps_keep_their_offset_from_the_epoch() {
+        let json = serde_json::to_string(&event()).unwrap();
+        let read: InputEvent = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.timestamp, event().timestamp);
+
+        let early = InputEvent {
+            timestamp: epoch() - Duration::from_millis(2),
+            ..event()
+        };
+        let json = serde_json::to_string(&early).unwrap();
+        assert!(json.contains(r#""timestamp_us":-2000"#), "{json}");
+        let read: InputEvent = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.timestamp, early.timestamp);
+    }
+
+    #[test]
+    fn modifiers_without_sides_leave_them_out() {
+        frozen(
+            &Modifiers {
+                shift: true,
+                ..Modifiers::default()
+            },
+            r#"{"ctrl":false,"shift":true,"alt":false,"meta":false}"#,
+        );
+    }
+
+    #[test]
+    fn keys_are_variant_names() {
+        frozen(
+            &[KeyCode::Key1, KeyCode::RightMeta, KeyCode::NumpadEnter],
+            r#"["Key1","RightMeta","NumpadEnter"]"#,
+        );
+    }
+
+    #[test]
+    fn actions_golden() {
+        let cases: Vec<(Action, &str)> = vec![
+            (Action::Suppress, r#""Suppress""#),
+            (
+                Action::InjectKey {
+                    key: KeyCode::A,
+                    state: KeyState::Up,
+                },
+                r#"{"InjectKey":{"key":"A","state":"Up"}}"#,
+            ),
+            (
+                Action::KeyCombo(vec![KeyCode::Ctrl, KeyCode::C]),
+                r#"{"KeyCombo":["Ctrl","C"]}"#,
+            ),
+            (Action::InjectChar('é'), r#"{"InjectChar":"é"}"#),
+            (
+                Action::SystemCommand(SystemCmd::LockScreen),
+                r#"{"SystemCommand":"LockScreen"}"#,
+            ),
+            (
+                Action::Sequence {
+                    steps: vec![
+                        Action::Delay { ms: 20 },
+                        Action::MouseButton {
+                            button: MouseButton::Other(4),
+                            state: KeyState::Down,
+                        },
+                    ],
+                    on_error: StepFailure::Continue,
+                },
+                concat!(
+                    r#"{"Sequence":{"steps":[{"Delay":{"ms":20}},"#,
+                    r#"{"MouseButton":{"button":{"Other":4},"state":"Down"}}],"#,
+                    r#""on_error":"Continue"}}"#,
+                ),
+            ),
+            (
+                Action::Exec {
+                    command: ExecCommand {
+                        line: CommandLine::Argv(vec!["date".into(), "+%F".into()]),
+                        cwd: Some("/srv".into()),
+                        env: vec![("TZ".into(), "UTC".into())],
+                    },
+                },
+                concat!(
+                    r#"{"Exec":{"command":{"line":{"Argv":["date","+%F"]},"#,
+                    r#""cwd":"/srv","env":[["TZ","UTC"]]}}}"#,
+                ),
+            ),
+            (
+                Action::SetPaused { paused: None },
+                r#"{"SetPaused":{"paused":null}}"#,
+            ),
+        ];
+        for (action, golden) in &cases {
+            frozen(action, golden);
+        }
+    }
+
+    #[test]
+    fn captured_events_golden() {
+        let pointer = CapturedEvent::Pointer {
+            event: PointerEvent::Motion {
+                dx: 3,
+                dy: -1,
+                position: Some((640, 480)),
+            },
+            timestamp: epoch() + Duration::from_micros(42),
+        };
+        frozen(
+            &pointer,
+            concat!(
+                r#"{"Pointer":{"event":{"Motion":{"dx":3,"dy":-1,"position":[640,480]}},"#,
+                r#""timestamp_us":42}}"#,
+            ),
+        );
+        frozen(
+            &CapturedEvent::Focus {
+                window: WindowContext {
+                    app_id: Some("firefox".into()),
+                    title: Some("Inbox".into()),
+                },
+                timestamp: epoch() + Duration::from_micros(7),
+            },
+            concat!(
+                r#"{"Focus":{"window":{"app_id":"firefox","title":"Inbox"},"#,
+                r#""timestamp_us":7}}"#,
+            ),
+        );
+        let key = serde_json::to_string(&CapturedEvent::Key(event())).unwrap();
+        assert!(key.starts_with(r#"{"Key":{"key":"LeftCtrl","#), "{key}");
+    }
+}
@@ -2,54 +2,198 @@
 //!
 //! `WindowsCapture` implements `InputCapture`. `start()` spawns a background
 //! thread that installs the hook and runs a `GetMessageW` loop (required for
-//! low-level hooks to deliver events). `stop()` uninstalls the hook and posts
-//! `WM_QUIT` to exit the message loop, then joins the thread.
+//! low-level hooks to deliver events). `stop()` posts `WM_QUIT` to exit the
+//! message loop, which uninstalls the hook, then joins the thread.
 //!
-//! No special permissions are required on Windows for WH_KEYBOARD_LL.
+//! Hook timing: Windows silently removes a low-level hook whose proc runs
+//! past `LowLevelHooksTimeout`. The hook procs therefore only translate the
+//! event and push it onto a queue, taking no lock on the way; a second
+//! thread, the dispatcher, hands queued events to the callbacks. The message
+//! loop also runs a `HookWatchdog` (see `watchdog`), which installs the
+//! keyboard hook again if it was removed anyway.
 //!
-//! Feedback loop prevention: `SendInput` sets `LLKHF_INJECTED` on the
-//! resulting event. The hook proc checks this flag and passes injected events
-//! through unchanged, so only physical key events invoke the user callback.
+//! Pointer: with a callback set through `set_pointer_callback`, the same
+//! thread also installs a WH_MOUSE_LL hook (see `mouse`). Pointer events are
+//! observed, never suppressed, and queued like keys, so they stay in order
+//! with them. With motion coalescing on, a thread timer on the message loop
+//! queues the moves held back.
+//!
+//! Modifiers: every key event, ours included, updates a `ModifierTracker`
+//! (see `modifiers`), seeded with `GetAsyncKeyState` when the hook is
+//! installed. Its state is stamped onto every key event.
+//!
+//! Pause and PrintScreen: Ctrl+Pause arrives as `VK_CANCEL`, and PrintScreen
+//! often arrives as an Up alone. `quirks` turns both into ordinary events.
+//!
+//! Tray: with the `tray` feature and a `Tray` set through `set_tray`, the
+//! thread also hosts the notification area icon (see `tray`). Its window
+//! messages are dispatched from the message loop.
+//!
+//! Window context: the thread also runs the foreground-window tracker (see
+//! `focus`), whose cached context is stamped onto every key event. With a
+//! callback set through `set_focus_callback`, each change is queued too.
+//! The tracker suspends rules while the foreground window belongs to an
+//! elevated process our input cannot reach (see `elevation`).
+//!
+//! No special permissions are required on Windows for WH_KEYBOARD_LL or
+//! WH_MOUSE_LL.
+//!
+//! Feedback loop prevention: the executor tags every event it sends with
+//! `INJECTED_TAG` in `dwExtraInfo`. The hook proc passes tagged events through
+//! unchanged, so only physical keys and keys injected by other programs (an
+//! on-screen keyboard, a remote desktop client) invoke the user callback.
 //!
 //! Suppression: returning a non-zero `LRESULT` from the hook proc (without
 //! calling `CallNextHookEx`) suppresses the original physical event. The
 //! executor re-injects the processed version via `SendInput`.
 //!
-//! Callback storage: `WH_KEYBOARD_LL` hook procs receive no `user_info`
-//! pointer, so the user callback is stored in a process-global `Mutex`.
-//! Only one `WindowsCapture` instance should be active at a time.
-
+//! Callback storage: low-level hook procs receive no `user_info` pointer, so
+//! the event queue is stored in a process global, in a `Slot` (see `slot`)
+//! that is read without a lock. What the procs keep between events, such as
+//! the modifier state, lives in a thread-local on the hook thread, the only
+//! thread that runs them. Only one `WindowsCapture` instance may be active at
+//! a time; `start` on a second one fails until the first has stopped.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::mpsc;
-use std::sync::Mutex;
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use std::ptr;
 use windows_sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::System::SystemInformation::GetTickCount;
 use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    GetAsyncKeyState, GetLastInputInfo, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT,
+    KEYEVENTF_KEYUP, LASTINPUTINFO,
+};
 use windows_sys::Win32::UI::WindowsAndMessaging::{
-    CallNextHookEx, GetMessageW, PostThreadMessageW, SetWindowsHookExW, UnhookWindowsHookEx,
-    HC_ACTION, HHOOK, KBDLLHOOKSTRUCT, LLKHF_EXTENDED, LLKHF_INJECTED, MSG, WH_KEYBOARD_LL,
-    WM_KEYDOWN, WM_KEYUP, WM_QUIT, WM_SYSKEYDOWN, WM_SYSKEYUP,
+    CallNextHookEx, DispatchMessageW, GetMessageW, KillTimer, PostThreadMessageW, SetTimer,
+    SetWindowsHookExW, UnhookWindowsHookEx, HC_ACTION, HHOOK, KBDLLHOOKSTRUCT, LLKHF_EXTENDED,
+    LLKHF_INJECTED, LLMHF_INJECTED, MSG, MSLLHOOKSTRUCT, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_KEYDOWN,
+    WM_KEYUP, WM_QUIT, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_TIMER,
 };
 
-use super::keycodes::vkcode_to_keycode;
+use super::focus::{self, FocusHooks};
+use super::keycodes::scancode_to_position;
+use super::modifiers::ModifierTracker;
+use super::mouse::{self, Coalescer};
+use super::quirks::{CaptureQuirks, Translated};
+use super::slot::{Claim, Slot};
+#[cfg(feature = "tray")]
+use super::tray::{Tray, TrayIcon};
+use super::watchdog::{Check, HookWatchdog};
+use super::INJECTED_TAG;
 use crate::platform::{
-    InputCapture as InputCaptureTrait, InputEvent as PlatformInputEvent, KeyState, Modifiers,
-    PlatformError, WindowContext,
+    timestamp_from_age, InputCapture as InputCaptureTrait, InputEvent as PlatformInputEvent,
+    KeyState, PlatformError, PointerEvent, Suspension, WindowContext,
 };
 
 // ---------------------------------------------------------------------------
 // Process-global callback storage
 // ---------------------------------------------------------------------------
 
-/// Type of the capture callback stored in the global; factored for Clippy type_complexity.
-type HookCallback = Box<dyn Fn(PlatformInputEvent) + Send>;
+/// What the hook thread queues for the dispatcher.
+enum Hooked {
+    Key(PlatformInputEvent),
+    Pointer(PointerEvent),
+    Focus(WindowContext),
+}
 
-/// Stores the active capture callback.
+/// The queue the hook procs push events onto, claimed by the running
+/// capture and released by `stop` once the hook thread has exited.
 ///
-/// `WH_KEYBOARD_LL` hook procs have no `user_info` parameter, so the callback
-/// must live in a global. At most one `WindowsCapture` should be active.
-static HOOK_CALLBACK: Mutex<Option<HookCallback>> = Mutex::new(None);
+/// Low-level hook procs have no `user_info` parameter, so the queue must
+/// live in a global. At most one `WindowsCapture` may be active.
+static EVENT_QUEUE: Slot<mpsc::Sender<Hooked>> = Slot::new();
+
+/// `KBDLLHOOKSTRUCT.time` of the last key event through the hook, ours
+/// included, for the watchdog.
+static LAST_HOOKED: AtomicU32 = AtomicU32::new(0);
+
+/// Set when the hook swallows the watchdog's probe key.
+static PROBE_SEEN: AtomicBool = AtomicBool::new(false);
+
+/// `dwExtraInfo` of the watchdog's probe key, "PCUP".
+const PROBE_TAG: usize = 0x5043_5550;
+
+/// Virtual key of the probe: one Windows leaves unassigned.
+const PROBE_VK: u16 = 0x97;
+
+/// How often the watchdog checks on the keyboard hook.
+const WATCHDOG_INTERVAL_MS: u32 = 3_000;
+
+type PointerCallback = Box<dyn Fn(PointerEvent) + Send>;
+type FocusCallback = Box<dyn Fn(WindowContext) + Send>;
+
+/// Hand `event` to the dispatcher. Valid until the hook thread exits; see
+/// `stop`.
+fn enqueue(event: Hooked) {
+    if let Some(queue) = unsafe { EVENT_QUEUE.get() } {
+        let _ = queue.send(event);
+    }
+}
+
+/// What the mouse hook keeps between events.
+struct PointerTrack {
+    /// Position of the last move, for the motion deltas.
+    last: Option<(i32, i32)>,
+    coalescer: Option<Coalescer>,
+}
+
+impl PointerTrack {
+    fn report(&mut self, event: PointerEvent, now: Instant) {
+        let Some(coalescer) = self.coalescer.as_mut() else {
+            enqueue(Hooked::Pointer(event));
+            return;
+        };
+        if let PointerEvent::Motion { .. } = event {
+            if let Some(motion) = coalescer.push(event, now) {
+                enqueue(Hooked::Pointer(motion));
+            }
+            return;
+        }
+        if let Some(motion) = coalescer.flush(now) {
+            enqueue(Hooked::Pointer(motion));
+        }
+        enqueue(Hooked::Pointer(event));
+    }
+
+    fn flush(&mut self, now: Instant) {
+        if let Some(motion) = self.coalescer.as_mut().and_then(|c| c.flush(now)) {
+            enqueue(Hooked::Pointer(motion));
+        }
+    }
+}
+
+/// What the hook procs keep between events.
+struct HookState {
+    /// Modifier state as of the last key event through the hook.
+    modifiers: ModifierTracker,
+    /// Whether a PrintScreen Down has come through the hook (see `quirks`).
+    quirks: CaptureQuirks,
+    /// Set while the mouse hook is installed.
+    pointer: Option<PointerTrack>,
+}
+
+thread_local! {
+    /// The hook procs run only on the thread that installed them, so their
+    /// state lives there and no proc ever waits on a lock for it.
+    static HOOK: RefCell<HookState> = const {
+        RefCell::new(HookState {
+            modifiers: ModifierTracker::new(),
+            quirks: CaptureQuirks::new(),
+            pointer: None,
+        })
+    };
+}
+
+/// Run `f` on the hook thread's state. `None` if a proc already has it,
+/// which only a proc re-entered from within could see.
+fn with_hook<R>(f: impl FnOnce(&mut HookState) -> R) -> Option<R> {
+    HOOK.with(|hook| hook.try_borrow_mut().ok().map(|mut hook| f(&mut hook)))
+}
 
 // ---------------------------------------------------------------------------
 // Public struct
@@ -57,19 +201,41 @@ static HOOK_CALLBACK: Mutex<Option<HookCallback>> = Mutex::new(None);
 
 /// Windows keyboard capture backend using `WH_KEYBOARD_LL`.
 pub struct WindowsCapture {
-    /// Handle returned by `SetWindowsHookExW`; used to unhook in `stop()`. Stored as isize for Send.
-    hook: Option<isize>,
     /// Thread ID of the background message-loop thread; used for `PostThreadMessageW`.
     thread_id: u32,
     thread: Option<JoinHandle<()>>,
+    /// Hands queued key events to the callback.
+    dispatcher: Option<JoinHandle<()>>,
+    /// Our hold on `EVENT_QUEUE` while running.
+    queue: Option<Claim>,
+    /// Set before `start`; moved to the dispatcher when the capture starts.
+    pointer_callback: Option<PointerCallback>,
+    motion_coalescing: Option<Duration>,
+    /// Set while the foreground window is out of reach.
+    suspension: Suspension,
+    /// `--allow-uiaccess`: warn if the token has no UIAccess.
+    expect_ui_access: bool,
+    /// Set before `start`; moved to the dispatcher when the capture starts.
+    focus_callback: Option<FocusCallback>,
+    /// Set before `start`; moved to the capture thread, which shows it.
+    #[cfg(feature = "tray")]
+    tray: Option<Tray>,
 }
 
 impl WindowsCapture {
     pub fn new() -> Self {
         Self {
-            hook: None,
             thread_id: 0,
             thread: None,
+            dispatcher: None,
+            queue: None,
+            pointer_callback: None,
+            motion_coalescing: None,
+            suspension: Suspension::default(),
+            expect_ui_access: false,
+            focus_callback: None,
+            #[cfg(feature = "tray")]
+            tray: None,
         }
     }
 }
@@ -87,72 +253,162 @@ impl InputCaptureTrait for WindowsCapture {
             return Err(PlatformError::Other("capture is already running".into()));
         }
 
-        // Store callback globally before the hook is installed.
-        {
-            let mut guard = HOOK_CALLBACK
-                .lock()
-                .map_err(|_| PlatformError::Other("callback mutex poisoned".into()))?;
-            *guard = Some(callback);
-        }
-
-        // Channel: background thread sends (hook_handle, thread_id) after setup. isize for Send.
-        let (info_tx, info_rx) = mpsc::channel::<Result<(isize, u32), PlatformError>>();
+        // Publish the queue before the hook is installed.
+        let (queue_tx, queue_rx) = mpsc::channel();
+        let Ok(claim) = EVENT_QUEUE.claim(queue_tx) else {
+            return Err(PlatformError::Other("another capture is active".into()));
+        };
+        let pointer_callback = self.pointer_callback.take();
+        let with_pointer = pointer_callback.is_some();
+        let coalescing = self.motion_coalescing.filter(|_| with_pointer);
+        let timer_ms = coalescing.map(|d| d.as_millis().clamp(1, u128::from(u32::MAX)) as u32);
+        let focus_callback = self.focus_callback.take();
+        let on_focus = focus_callback
+            .is_some()
+            .then(|| Box::new(|window| enqueue(Hooked::Focus(window))) as FocusCallback);
+
+        let suspension = self.suspension.clone();
+        let expect_ui_access = self.expect_ui_access;
+        #[cfg(feature = "tray")]
+        let tray = self.tray.take();
+
+        // Channel: background thread sends its thread ID after setup.
+        let (info_tx, info_rx) = mpsc::channel::<Result<u32, PlatformError>>();
 
         let thread = thread::spawn(move || {
+            let _ = with_hook(|hook| {
+                hook.pointer = with_pointer.then(|| PointerTrack {
+                    last: None,
+                    coalescer: coalescing.map(Coalescer::new),
+                });
+            });
+
             // Install hook on this thread; the GetMessageW loop below keeps it alive.
-            let hook =
-                unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(hook_proc), ptr::null_mut(), 0) };
+            let mut hook = install_keyboard_hook();
 
             if hook.is_null() {
                 let _ = info_tx.send(Err(PlatformError::Other("SetWindowsHookExW failed".into())));
                 return;
             }
 
+            seed_modifiers();
+
+            // A missing mouse hook costs pointer events, not the keyboard.
+            let mouse_hook = if with_pointer {
+                let mouse_hook = unsafe {
+                    SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_hook_proc), ptr::null_mut(), 0)
+                };
+                if mouse_hook.is_null() {
+                    log::warn!("capture: WH_MOUSE_LL hook failed; pointer events are not captured");
+                    None
+                } else {
+                    log::info!("capture: WH_MOUSE_LL hook active");
+                    Some(mouse_hook)
+                }
+            } else {
+                None
+            };
+
             let thread_id = unsafe { GetCurrentThreadId() };
-            let _ = info_tx.send(Ok((hook as isize, thread_id)));
+            let _ = info_tx.send(Ok(thread_id));
 
             log::info!("capture: WH_KEYBOARD_LL hook active");
 
-            // Message loop: required for WH_KEYBOARD_LL to deliver events.
+            let focus_hooks = FocusHooks::install(suspension, expect_ui_access, on_focus);
+            #[cfg(feature = "tray")]
+            let tray_icon = tray.and_then(TrayIcon::install);
+
+            // Thread timer: posts WM_TIMER to this thread's queue, where the
+            // loop below flushes the moves held back.
+            let timer = match (mouse_hook, timer_ms) {
+                (Some(_), Some(ms)) => unsafe { SetTimer(ptr::null_mut(), 0, ms, None) },
+                _ => 0,
+            };
+            let watchdog_timer =
+                unsafe { SetTimer(ptr::null_mut(), 0, WATCHDOG_INTERVAL_MS, None) };
+            let mut watchdog = HookWatchdog::default();
+
+            // Message loop: required for low-level hooks to deliver events.
             // Returns 0 on WM_QUIT, -1 on error; both exit the loop.
             unsafe {
                 let mut msg: MSG = std::mem::zeroed();
-                while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {}
+                while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {
+                    // Window messages, only the tray's so far; thread
+                    // messages have no window.
+                    if !msg.hwnd.is_null() {
+                        DispatchMessageW(&msg);
+                        continue;
+                    }
+                    if msg.message != WM_TIMER {
+                        continue;
+                    }
+                    if watchdog_timer != 0 && msg.wParam == watchdog_timer {
+                        hook = watch(&mut watchdog, hook);
+                    } else {
+                        flush_pointer();
+                    }
+                }
             }
 
             log::info!("capture: message loop exited");
 
-            unsafe { UnhookWindowsHookEx(hook) };
+            #[cfg(feature = "tray")]
+            drop(tray_icon);
+            drop(focus_hooks);
+            unsafe {
+                if watchdog_timer != 0 {
+                    KillTimer(ptr::null_mut(), watchdog_timer);
+                }
+                if timer != 0 {
+                    KillTimer(ptr::null_mut(), timer);
+                }
+                if let Some(mouse_hook) = mouse_hook {
+                    UnhookWindowsHookEx(mouse_hook);
+                }
+                UnhookWindowsHookEx(hook);
+            }
         });
 
-        match info_rx.recv() {
-            Ok(Ok((hook, thread_id))) => {
-                self.hook = Some(hook);
-                self.thread_id = thread_id;
-                self.thread = Some(thread);
-                Ok(())
+        let thread_id = match info_rx.recv() {
+            Ok(Ok(thread_id)) => thread_id,
+            reported => {
+                // The thread has exited, so no hook proc can still hold the queue.
+                let _ = thread.join();
+                EVENT_QUEUE.release(claim);
+                return Err(match reported {
+                    Ok(Err(e)) => e,
+                    _ => PlatformError::Other(
+                        "capture thread exited before reporting hook status".into(),
+                    ),
+                });
             }
-            Ok(Err(e)) => {
-                // Background thread reported an error; clear callback and propagate.
-                let _ = HOOK_CALLBACK.lock().map(|mut g| *g = None);
-                Err(e)
+        };
+        self.thread_id = thread_id;
+        self.thread = Some(thread);
+        self.queue = Some(claim);
+        // Ends when `stop` releases the queue.
+        self.dispatcher = Some(thread::spawn(move || {
+            for event in queue_rx {
+                match event {
+                    Hooked::Key(event) => callback(event),
+                    Hooked::Pointer(event) => {
+                        if let Some(pointer) = &pointer_callback {
+                            pointer(event);
+                        }
+                    }
+                    Hooked::Focus(window) => {
+                        if let Some(focus) = &focus_callback {
+                            focus(window);
+                        }
+                    }
+                }
             }
-            Err(_) => Err(PlatformError::Other(
-                "capture thread exited before reporting hook status".into(),
-            )),
-        }
+        }));
+        Ok(())
     }
 
     fn stop(&mut self) -> Result<(), PlatformError> {
-        // Unhook first so no further callbacks fire after this returns.
-        if let Some(hook) = self.hook.take() {
-            unsafe { UnhookWindowsHookEx(hook as HHOOK) };
-        }
-
-        // Clear the callback while certain no more hook_proc calls are in flight.
-        let _ = HOOK_CALLBACK.lock().map(|mut g| *g = None);
-
-        // Signal the message loop to exit.
+        // Signal the message loop to exit; the thread unhooks on its way out.
         if self.thread_id != 0 {
             unsafe { PostThreadMessageW(self.thread_id, WM_QUIT, 0, 0) };
             self.thread_id = 0;
@@ -160,10 +416,44 @@ impl InputCaptureTrait for WindowsCapture {
 
         if let Some(t) = self.thread.take() {
             let _ = t.join();
+            // The hooks are gone with the thread, so no proc is in flight.
+            if let Some(claim) = self.queue.take() {
+                EVENT_QUEUE.release(claim);
+            }
+        }
+
+        // Delivers what was queued before the hook went, then ends.
+        if let Some(d) = self.dispatcher.take() {
+            let _ = d.join();
         }
 
         Ok(())
     }
+
+    fn set_pointer_callback(&mut self, callback: Box<dyn Fn(PointerEvent) + Send>) {
+        self.pointer_callback = Some(callback);
+    }
+
+    fn set_motion_coalescing(&mut self, interval: Option<Duration>) {
+        self.motion_coalescing = interval;
+    }
+
+    fn set_suspension(&mut self, suspension: Suspension) {
+        self.suspension = suspension;
+    }
+
+    fn set_ui_access_expected(&mut self, expected: bool) {
+        self.expect_ui_access = expected;
+    }
+
+    fn set_focus_callback(&mut self, callback: FocusCallback) {
+        self.focus_callback = Some(callback);
+    }
+
+    #[cfg(feature = "tray")]
+    fn set_tray(&mut self, tray: Tray) {
+        self.tray = Some(tray);
+    }
 }
 
 impl Drop for WindowsCapture {
@@ -172,19 +462,95 @@ impl Drop for WindowsCapture {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Hook installation and watchdog
+// ---------------------------------------------------------------------------
+
+/// Install `hook_proc` on the calling thread. Null on failure.
+fn install_keyboard_hook() -> HHOOK {
+    unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(hook_proc), ptr::null_mut(), 0) }
+}
+
+/// Read the modifiers already held, whose Down the hook never saw. On the
+/// hook thread.
+fn seed_modifiers() {
+    let _ = with_hook(|hook| {
+        hook.modifiers = ModifierTracker::seed(|vk| unsafe { GetAsyncKeyState(i32::from(vk)) } < 0);
+    });
+}
+
+/// One watchdog check, on the hook thread. Returns the hook now installed.
+fn watch(watchdog: &mut HookWatchdog, hook: HHOOK) -> HHOOK {
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return hook;
+    }
+    let probe_seen = PROBE_SEEN.swap(false, Ordering::Relaxed);
+    match watchdog.check(info.dwTime, LAST_HOOKED.load(Ordering::Relaxed), probe_seen) {
+        Check::Healthy => hook,
+        Check::Probe => {
+            send_probe();
+            hook
+        }
+        Check::Reinstall => {
+            let fresh = install_keyboard_hook();
+            if fresh.is_null() {
+                log::error!(
+                    "capture: Windows removed the keyboard hook and it could not be installed \
+                     again; keys reach applications unremapped"
+                );
+                return hook;
+            }
+            unsafe { UnhookWindowsHookEx(hook) };
+            // Keys went by unseen while it was gone.
+            seed_modifiers();
+            LAST_HOOKED.store(info.dwTime, Ordering::Relaxed);
+            log::warn!(
+                "capture: Windows removed the keyboard hook, likely after a slow callback; \
+                 installed it again"
+            );
+            fresh
+        }
+    }
+}
+
+/// Send the probe key, a press and release the hook swallows.
+fn send_probe() {
+    let key = |flags| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: PROBE_VK,
+                wScan: 0,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: PROBE_TAG,
+            },
+        },
+    };
+    let inputs = [key(0), key(KEYEVENTF_KEYUP)];
+    unsafe { SendInput(2, inputs.as_ptr(), std::mem::size_of::<INPUT>() as i32) };
+}
+
 // ---------------------------------------------------------------------------
 // Hook procedure
 // ---------------------------------------------------------------------------
 
 /// Low-level keyboard hook proc, called on the background message-loop thread.
 ///
-/// Physical events (no `LLKHF_INJECTED`): invoke the callback and suppress the
-/// original event (return 1). The executor re-injects the processed version via
-/// `SendInput`. This applies to all keys including modifiers; the Windows executor
-/// is synchronous, so suppress-and-reinject does not desync OS modifier state.
+/// Physical and foreign injected events: push onto `EVENT_QUEUE` and suppress
+/// the original event (return 1). The executor re-injects the processed version
+/// via `SendInput`. This applies to all keys including modifiers. Nothing here
+/// takes a lock or waits on the callback, which runs on the dispatcher
+/// thread, so the proc stays well inside `LowLevelHooksTimeout`.
+///
+/// The watchdog's probe (`PROBE_TAG`): note it and swallow it.
 ///
-/// Injected events (`LLKHF_INJECTED`): pass through via `CallNextHookEx`
-/// so re-injected events reach the application without re-triggering the hook.
+/// Our own events (`INJECTED_TAG`): pass through via `CallNextHookEx` so
+/// re-injected events reach the application without re-triggering the hook.
 ///
 /// Unknown key codes: pass through so the user is not locked out.
 unsafe extern "system" fn hook_proc(n_code: i32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
@@ -193,10 +559,11 @@ unsafe extern "system" fn hook_proc(n_code: i32, w_param: WPARAM, l_param: LPARA
     }
 
     let kb = &*(l_param as *const KBDLLHOOKSTRUCT);
+    LAST_HOOKED.store(kb.time, Ordering::Relaxed);
 
-    // Pass injected events (our own SendInput) through unchanged.
-    if kb.flags & LLKHF_INJECTED != 0 {
-        return CallNextHookEx(ptr::null_mut(), n_code, w_param, l_param);
+    if kb.flags & LLKHF_INJECTED != 0 && kb.dwExtraInfo == PROBE_TAG {
+        PROBE_SEEN.store(true, Ordering::Relaxed);
+        return 1;
     }
 
     let key_state = match w_param as u32 {
@@ -205,22 +572,49 @@ unsafe extern "system" fn hook_proc(n_code: i32, w_param: WPARAM, l_param: LPARA
         _ => return CallNextHookEx(ptr::null_mut(), n_code, w_param, l_param),
     };
 
+    // Every event counts, our own too, so modifiers a remap injects are
+    // held for the keys that follow.
+    let modifiers = with_hook(|hook| {
+        hook.modifiers
+            .observe(kb.vkCode as u16, key_state == KeyState::Down);
+        hook.modifiers.modifiers()
+    })
+    .unwrap_or_default();
+
+    // Pass our own SendInput events through unchanged.
+    if kb.flags & LLKHF_INJECTED != 0 && kb.dwExtraInfo == INJECTED_TAG {
+        return CallNextHookEx(ptr::null_mut(), n_code, w_param, l_param);
+    }
+
     let extended = kb.flags & LLKHF_EXTENDED != 0;
 
-    match vkcode_to_keycode(kb.vkCode as u16, extended) {
-        Some(key) => {
+    let translated = with_hook(|hook| hook.quirks.translate(kb, key_state)).flatten();
+    match translated {
+        Some(Translated {
+            key,
+            missing_down,
+            is_repeat,
+        }) => {
             log::debug!("capture: key {:?} {:?}", key, key_state);
-            if let Ok(guard) = HOOK_CALLBACK.lock() {
-                if let Some(cb) = guard.as_ref() {
-                    cb(PlatformInputEvent {
-                        key,
-                        state: key_state,
-                        // Modifier tracking and window context are implemented in M11.
-                        modifiers: Modifiers::default(),
-                        window: WindowContext::default(),
-                    });
-                }
+            let event = PlatformInputEvent {
+                key,
+                state: key_state,
+                modifiers,
+                window: focus::current(),
+                timestamp: event_timestamp(kb.time, GetTickCount()),
+                device: None,
+                observed: false,
+                is_repeat,
+                position: scancode_to_position(kb.scanCode as u16, extended),
+            };
+            if missing_down {
+                enqueue(Hooked::Key(PlatformInputEvent {
+                    state: KeyState::Down,
+                    is_repeat: false,
+                    ..event.clone()
+                }));
             }
+            enqueue(Hooked::Key(event));
             // Suppress original; executor will re-inject the processed version.
             1
         }
@@ -232,6 +626,58 @@ unsafe extern "system" fn hook_proc(n_code: i32, w_param: WPARAM, l_param: LPARA
     }
 }
 
+/// Low-level mouse hook proc, called on the same thread as `hook_proc`.
+///
+/// Every event passes through; events other than our own (`INJECTED_TAG`)
+/// are queued for the pointer callback first. Like `hook_proc`, it takes no
+/// lock, as moves arrive at the mouse's polling rate.
+unsafe extern "system" fn mouse_hook_proc(
+    n_code: i32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    if n_code == HC_ACTION as i32 {
+        let ms = &*(l_param as *const MSLLHOOKSTRUCT);
+        let ours = ms.flags & LLMHF_INJECTED != 0 && ms.dwExtraInfo == INJECTED_TAG;
+        if !ours {
+            let _ = with_hook(|hook| {
+                let Some(pointer) = hook.pointer.as_mut() else {
+                    return;
+                };
+                let event = mouse::decode(
+                    w_param as u32,
+                    ms.mouseData,
+                    ms.pt.x,
+                    ms.pt.y,
+                    &mut pointer.last,
+                );
+                if let Some(event) = event {
+                    pointer.report(event, Instant::now());
+                }
+            });
+        }
+    }
+    CallNextHookEx(ptr::null_mut(), n_code, w_param, l_param)
+}
+
+/// Queue the move the coalescer held back, if any. On the hook thread.
+fn flush_pointer() {
+    let _ = with_hook(|hook| {
+        if let Some(pointer) = hook.pointer.as_mut() {
+            pointer.flush(Instant::now());
+        }
+    });
+}
+
+/// Places `KBDLLHOOKSTRUCT.time` on the `Instant` timeline.
+///
+/// Both `time` and `tick_now` are `GetTickCount` milliseconds, which wrap
+/// every 49.7 days; wrapping subtraction keeps the age right across a wrap.
+fn event_timestamp(time: u32, tick_now: u32) -> Instant {
+    let age = Duration::from_millis(u64::from(tick_now.wrapping_sub(time)));
+    timestamp_from_age(Some(age), Instant::now())
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -243,9 +689,9 @@ mod tests {
     #[test]
     fn new_produces_idle_state() {
         let capture = WindowsCapture::new();
-        assert!(capture.hook.is_none());
         assert_eq!(capture.thread_id, 0);
         assert!(capture.thread.is_none());
+        assert!(capture.dispatcher.is_none());
     }
 
     /// Stopping a capture that was never started must return Ok and not panic.
@@ -254,4 +700,61 @@ mod tests {
         let mut capture = WindowsCapture::new();
         assert!(capture.stop().is_ok());
     }
+
+    #[test]
+    fn event_timestamp_is_backdated_by_tick_age() {
+        let before = Instant::now();
+        let stamp = event_timestamp(1_000, 1_003);
+        assert!(stamp <= Instant::now() - Duration::from_millis(3));
+        assert!(stamp >= before - Duration::from_millis(3));
+    }
+
+    /// End to end through the real hook: a key sent with `SendInput`, untagged
+    /// as another program's would be, reaches the callback and is suppressed.
+    /// Needs an interactive desktop session, and types F24 into it.
+    #[test]
+    #[ignore = "installs a system-wide keyboard hook; run by hand with --ignored"]
+    fn sendinput_key_reaches_the_hook() {
+        use crate::platform::KeyCode;
+        use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+            SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_KEYUP,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let mut capture = WindowsCapture::new();
+        capture
+            .start(Box::new(move |event| {
+                let _ = tx.send((event.key, event.state));
+            }))
+            .unwrap();
+
+        let f24 = |flags| INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: 0x87,
+                    wScan: 0,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                },
+            },
+        };
+        let inputs = [f24(0), f24(KEYEVENTF_KEYUP)];
+        let sent = unsafe { SendInput(2, inputs.as_ptr(), std::mem::size_of::<INPUT>() as i32) };
+        assert_eq!(sent, 2);
+
+        let timeout = Duration::from_secs(2);
+        assert_eq!(rx.recv_timeout(timeout), Ok((KeyCode::F24, KeyState::Down)));
+        assert_eq!(rx.recv_timeout(timeout), Ok((KeyCode::F24, KeyState::Up)));
+        capture.stop().unwrap();
+    }
+
+    #[test]
+    fn event_timestamp_survives_tick_wrap() {
+        let before = Instant::now();
+        let stamp = event_timestamp(u32::MAX - 1, 2);
+        assert!(stamp <= Instant::now() - Duration::from_millis(4));
+        assert!(stamp >= before - Duration::from_millis(4));
+    }
 }
@@ -0,0 +1,107 @@
+//! Clipboard reads and writes on Windows, as `CF_UNICODETEXT`.
+//!
+//! `OpenClipboard` fails while another program has the clipboard open,
+//! which lasts milliseconds, so opening is retried a few times first. Text
+//! set goes in a movable global allocation, which the clipboard owns once
+//! `SetClipboardData` takes it.
+
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use windows_sys::Win32::Foundation::{GlobalFree, HGLOBAL};
+use windows_sys::Win32::System::DataExchange::{
+    CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard,
+    SetClipboardData,
+};
+use windows_sys::Win32::System::Memory::{
+    GlobalAlloc, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE,
+};
+
+use crate::platform::{Clipboard, PlatformError};
+
+/// Standard clipboard format for UTF-16 text.
+const CF_UNICODETEXT: u32 = 13;
+
+/// Attempts at opening the clipboard, and the pause between them.
+const OPEN_ATTEMPTS: u32 = 5;
+const OPEN_RETRY: Duration = Duration::from_millis(10);
+
+pub struct WindowsClipboard;
+
+impl Clipboard for WindowsClipboard {
+    fn read_text(&self) -> Result<Option<String>, PlatformError> {
+        open()?;
+        let text = unsafe { read_unicode_text() };
+        unsafe { CloseClipboard() };
+        Ok(text.filter(|t| !t.is_empty()))
+    }
+
+    fn write_text(&self, text: &str) -> Result<(), PlatformError> {
+        open()?;
+        let written = unsafe { write_unicode_text(text) };
+        unsafe { CloseClipboard() };
+        written.map_err(|e| PlatformError::io("cannot set the clipboard", e))
+    }
+}
+
+/// Open the clipboard, retrying while another program has it.
+fn open() -> Result<(), PlatformError> {
+    let mut attempt = 1;
+    while unsafe { OpenClipboard(ptr::null_mut()) } == 0 {
+        if attempt == OPEN_ATTEMPTS {
+            return Err(PlatformError::io(
+                "cannot open the clipboard",
+                std::io::Error::last_os_error(),
+            ));
+        }
+        attempt += 1;
+        thread::sleep(OPEN_RETRY);
+    }
+    Ok(())
+}
+
+/// The clipboard's text. The clipboard must be open.
+unsafe fn read_unicode_text() -> Option<String> {
+    if IsClipboardFormatAvailable(CF_UNICODETEXT) == 0 {
+        return None;
+    }
+    let handle = GetClipboardData(CF_UNICODETEXT) as HGLOBAL;
+    if handle.is_null() {
+        return None;
+    }
+    let data = GlobalLock(handle).cast::<u16>();
+    if data.is_null() {
+        return None;
+    }
+    // NUL-terminated, within the allocation.
+    let units = std::slice::from_raw_parts(data, GlobalSize(handle) / 2);
+    let len = units.iter().position(|&u| u == 0).unwrap_or(units.len());
+    let text = String::from_utf16_lossy(&units[..len]);
+    GlobalUnlock(handle);
+    Some(text)
+}
+
+/// Replace the clipboard's contents with `text`. The clipboard must be open.
+unsafe fn write_unicode_text(text: &str) -> std::io::Result<()> {
+    let units: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+    let handle = GlobalAlloc(GMEM_MOVEABLE, units.len() * 2);
+    if handle.is_null() {
+        return Err(std::io::Error::last_os_error());
+    }
+    let data = GlobalLock(handle).cast::<u16>();
+    if data.is_null() {
+        let e = std::io::Error::last_os_error();
+        GlobalFree(handle);
+        return Err(e);
+    }
+    ptr::copy_nonoverlapping(units.as_ptr(), data, units.len());
+    GlobalUnlock(handle);
+    if EmptyClipboard() == 0 || SetClipboardData(CF_UNICODETEXT, handle as _).is_null() {
+        // Still ours to free: the clipboard did not take it.
+        let e = std::io::Error::last_os_error();
+        GlobalFree(handle);
+        return Err(e);
+    }
+    Ok(())
+}
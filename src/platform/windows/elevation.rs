@@ -0,0 +1,339 @@
+//! Detection of a foreground window our input cannot reach.
+//!
+//! User Interface Privilege Isolation keeps a process from sending input to
+//! the windows of a process with a higher integrity level. `SendInput`
+//! reports success and the input is dropped, and the low-level keyboard hook
+//! is not called for keys going to such a window. A non-elevated pcunifier
+//! therefore does nothing while a UAC prompt, an admin terminal, or Task
+//! Manager has focus, which looks like random key loss.
+//!
+//! On every foreground change the focus tracker asks `ElevationCheck`
+//! whether the new window's process is out of reach, comparing the integrity
+//! level of our token with that of the process. While it is, rules are
+//! suspended with the app named as the reason, so `status` says why, and a
+//! warning is logged at most once per `WARN_INTERVAL`. A process whose token
+//! we may not open counts as out of reach: from a medium-integrity process,
+//! that is what an elevated one looks like.
+//!
+//! Ways to reach elevated windows:
+//!   - Run pcunifier elevated. It then reaches every window but those of
+//!     protected and system processes.
+//!   - uiAccess. UIPI lets through a process whose token carries UIAccess,
+//!     which Windows grants only to a binary whose embedded manifest sets
+//!     `uiAccess="true"`, that is signed with a certificate the machine
+//!     trusts, and that runs from a secure location such as
+//!     `C:\Program Files`. pcunifier does not ship such a manifest. Users who
+//!     build, sign, and install their own binary run it with
+//!     `--allow-uiaccess`, which warns at startup when the token has no
+//!     UIAccess after all (an unsigned binary, or one outside a secure
+//!     location, starts without it). A token with UIAccess reaches every
+//!     window, so nothing is suspended, with the flag or without it.
+//!
+//! The token queries sit behind `ProcessInfo` so the decision can be tested
+//! without real processes.
+
+use std::time::{Duration, Instant};
+
+use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_ACCESS_DENIED, HANDLE};
+use windows_sys::Win32::Security::{
+    GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation, TokenIntegrityLevel,
+    TokenUIAccess, TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+};
+use windows_sys::Win32::System::Threading::{
+    GetCurrentProcess, OpenProcess, OpenProcessToken, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+
+/// Shortest time between two warnings about an out-of-reach window.
+const WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A process's integrity level, as far as we may read it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrity {
+    /// The mandatory label's RID: 0x2000 medium, 0x3000 high, 0x4000 system.
+    Level(u32),
+    /// The process or its token refused us access.
+    Denied,
+    /// Not known for another reason, e.g. the process has exited.
+    Unknown,
+}
+
+/// Token queries, real in `Tokens` and scripted in tests.
+pub trait ProcessInfo {
+    /// Our own integrity level.
+    fn own_integrity(&self) -> Integrity;
+
+    /// Whether our own token carries UIAccess.
+    fn own_ui_access(&self) -> bool;
+
+    /// The integrity level of process `pid`.
+    fn integrity(&self, pid: u32) -> Integrity;
+}
+
+/// A foreground window out of reach.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blocked {
+    /// Suspension reason, naming the app when known.
+    pub reason: String,
+    /// Whether to log a warning now; false while one was logged recently.
+    pub warn: bool,
+}
+
+/// Out-of-reach decisions for successive foreground windows.
+pub struct ElevationCheck<P> {
+    info: P,
+    own: Integrity,
+    ui_access: bool,
+    last_warning: Option<Instant>,
+}
+
+impl<P: ProcessInfo> ElevationCheck<P> {
+    /// Read our own token once; it does not change while we run.
+    pub fn new(info: P) -> Self {
+        Self {
+            own: info.own_integrity(),
+            ui_access: info.own_ui_access(),
+            info,
+            last_warning: None,
+        }
+    }
+
+    pub fn has_ui_access(&self) -> bool {
+        self.ui_access
+    }
+
+    /// Check the process `pid` of the new foreground window, whose
+    /// executable is `app`. `None` when our input reaches it.
+    pub fn focus_changed(&mut self, pid: u32, app: Option<&str>, now: Instant) -> Option<Blocked> {
+        if self.ui_access || pid == 0 {
+            return None;
+        }
+        // Without our own level there is nothing to compare against.
+        let Integrity::Level(own) = self.own else {
+            return None;
+        };
+        let out_of_reach = match self.info.integrity(pid) {
+            Integrity::Level(level) => level > own,
+            Integrity::Denied => true,
+            Integrity::Unknown => false,
+        };
+        if !out_of_reach {
+            return None;
+        }
+        let warn = self
+            .last_warning
+            .map_or(true, |last| now.duration_since(last) >= WARN_INTERVAL);
+        if warn {
+            self.last_warning = Some(now);
+        }
+        Some(Blocked {
+            reason: match app {
+                Some(app) => format!("elevated window ({app})"),
+                None => "elevated window".into(),
+            },
+            warn,
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Token queries
+// ---------------------------------------------------------------------------
+
+/// `ProcessInfo` from the processes' access tokens.
+pub struct Tokens;
+
+impl ProcessInfo for Tokens {
+    fn own_integrity(&self) -> Integrity {
+        with_token(unsafe { GetCurrentProcess() }, |token| unsafe {
+            token_integrity(token)
+        })
+        .unwrap_or_else(|refused| refused)
+    }
+
+    fn own_ui_access(&self) -> bool {
+        with_token(unsafe { GetCurrentProcess() }, |token| unsafe {
+            let mut flag = 0u32;
+            let mut len = 0u32;
+            let ok = GetTokenInformation(
+                token,
+                TokenUIAccess,
+                (&mut flag as *mut u32).cast(),
+                std::mem::size_of::<u32>() as u32,
+                &mut len,
+            );
+            ok != 0 && flag != 0
+        })
+        .unwrap_or(false)
+    }
+
+    fn integrity(&self, pid: u32) -> Integrity {
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                return refusal();
+            }
+            let integrity = with_token(process, |token| token_integrity(token));
+            CloseHandle(process);
+            integrity.unwrap_or_else(|refused| refused)
+        }
+    }
+}
+
+/// Run `query` on `process`'s token, opened for `TOKEN_QUERY`, or say why
+/// the token could not be opened.
+fn with_token<T>(process: HANDLE, query: impl FnOnce(HANDLE) -> T) -> Result<T, Integrity> {
+    unsafe {
+        let mut token: HANDLE = std::ptr::null_mut();
+        if OpenProcessToken(process, TOKEN_QUERY, &mut token) == 0 {
+            return Err(refusal());
+        }
+        let answer = query(token);
+        CloseHandle(token);
+        Ok(answer)
+    }
+}
+
+/// What the last failed call means, read from `GetLastError`.
+fn refusal() -> Integrity {
+    if unsafe { GetLastError() } == ERROR_ACCESS_DENIED {
+        Integrity::Denied
+    } else {
+        Integrity::Unknown
+    }
+}
+
+/// The RID of `token`'s mandatory label.
+unsafe fn token_integrity(token: HANDLE) -> Integrity {
+    let mut len = 0u32;
+    GetTokenInformation(
+        token,
+        TokenIntegrityLevel,
+        std::ptr::null_mut(),
+        0,
+        &mut len,
+    );
+    if len == 0 {
+        return Integrity::Unknown;
+    }
+    // u64 elements keep the label's pointer aligned.
+    let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+    if GetTokenInformation(
+        token,
+        TokenIntegrityLevel,
+        buf.as_mut_ptr().cast(),
+        len,
+        &mut len,
+    ) == 0
+    {
+        return Integrity::Unknown;
+    }
+    let label = &*(buf.as_ptr() as *const TOKEN_MANDATORY_LABEL);
+    let sid = label.Label.Sid;
+    let count = *GetSidSubAuthorityCount(sid);
+    if count == 0 {
+        return Integrity::Unknown;
+    }
+    Integrity::Level(*GetSidSubAuthority(sid, u32::from(count) - 1))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const MEDIUM: u32 = 0x2000;
+    const HIGH: u32 = 0x3000;
+
+    struct Scripted {
+        own: Integrity,
+        ui_access: bool,
+        processes: HashMap<u32, Integrity>,
+    }
+
+    impl ProcessInfo for Scripted {
+        fn own_integrity(&self) -> Integrity {
+            self.own
+        }
+
+        fn own_ui_access(&self) -> bool {
+            self.ui_access
+        }
+
+        fn integrity(&self, pid: u32) -> Integrity {
+            self.processes
+                .get(&pid)
+                .copied()
+                .unwrap_or(Integrity::Unknown)
+        }
+    }
+
+    /// We run at `own`; process 1 is medium, 2 high, 3 refuses us.
+    fn check(own: Integrity, ui_access: bool) -> ElevationCheck<Scripted> {
+        ElevationCheck::new(Scripted {
+            own,
+            ui_access,
+            processes: HashMap::from([
+                (1, Integrity::Level(MEDIUM)),
+                (2, Integrity::Level(HIGH)),
+                (3, Integrity::Denied),
+            ]),
+        })
+    }
+
+    #[test]
+    fn higher_and_refusing_processes_are_out_of_reach() {
+        let mut check = check(Integrity::Level(MEDIUM), false);
+        let now = Instant::now();
+        assert_eq!(check.focus_changed(1, Some("notepad.exe"), now), None);
+        assert_eq!(
+            check.focus_changed(2, Some("Taskmgr.exe"), now),
+            Some(Blocked {
+                reason: "elevated window (Taskmgr.exe)".into(),
+                warn: true,
+            })
+        );
+        let blocked = check.focus_changed(3, None, now).unwrap();
+        assert_eq!(blocked.reason, "elevated window");
+        // Exited processes and the desktop are given the benefit of the doubt.
+        assert_eq!(check.focus_changed(4, None, now), None);
+        assert_eq!(check.focus_changed(0, None, now), None);
+    }
+
+    #[test]
+    fn warnings_are_rate_limited() {
+        let mut check = check(Integrity::Level(MEDIUM), false);
+        let start = Instant::now();
+        assert!(check.focus_changed(2, None, start).unwrap().warn);
+        assert!(
+            !check
+                .focus_changed(2, None, start + WARN_INTERVAL / 2)
+                .unwrap()
+                .warn
+        );
+        assert!(
+            check
+                .focus_changed(2, None, start + WARN_INTERVAL)
+                .unwrap()
+                .warn
+        );
+    }
+
+    #[test]
+    fn elevated_or_ui_access_reaches_everything_it_can_compare() {
+        let mut elevated = check(Integrity::Level(HIGH), false);
+        assert_eq!(elevated.focus_changed(2, None, Instant::now()), None);
+
+        let mut ui_access = check(Integrity::Level(MEDIUM), true);
+        assert!(ui_access.has_ui_access());
+        assert_eq!(ui_access.focus_changed(3, None, Instant::now()), None);
+
+        // An unreadable own token decides nothing.
+        let mut unknown = check(Integrity::Unknown, false);
+        assert_eq!(unknown.focus_changed(2, None, Instant::now()), None);
+    }
+}
@@ -2,16 +2,61 @@
 //!
 //! `WindowsExecutor` implements `ActionExecutor`. Injection is synchronous:
 //! `SendInput` returns after the event is queued. No background thread is
-//! needed. Only `Action::InjectKey` is handled; all other variants are no-ops
-//! until later milestones implement them.
+//! needed. Keys, characters, and pointer actions (see `mouse`) are handled;
+//! all other variants are no-ops until later milestones implement them.
+//!
+//! With a target window set (`set_target`), keys and characters are posted
+//! to that window as messages instead; see `post` for what that cannot do.
+//! Pointer actions always go through `SendInput`.
+//!
+//! Keys are sent by virtual key code by default, with the scan code filled in
+//! as a hint. Games reading DirectInput or Raw Input ignore those; for them
+//! `set_scancode_injection` switches to `KEYEVENTF_SCANCODE`, for every key
+//! or only while one of a list of apps is focused (as tracked by `focus`,
+//! which runs with capture). The scan code comes from `MAPVK_VK_TO_VSC_EX`,
+//! whose `E0` prefix marks the navigation cluster and other extended keys.
+//! Keys with an `E1` prefix (Pause) or no scan code at all still go by
+//! virtual key code.
+//!
+//! Numpad digits and the decimal key always go by scan code. The layout
+//! then turns them into what the physical key sends, which follows NumLock:
+//! a passed-through Numpad7 with NumLock off still navigates, as the press
+//! did (capture reports it as Numpad7; see `keycodes`). Sent by virtual key
+//! code, VK_NUMPAD7 would type a 7 whatever NumLock says. Rules that mean
+//! the digit get NumLock sorted out by the engine first.
+//!
+//! Pause with Ctrl held and PrintScreen are sent the way their keys send
+//! them, as Break and with the right scan code; see `quirks`.
+//!
+//! Batches: `execute_batch` sends a run of keys, such as a combination's
+//! modifiers and key, as one `INPUT` array in a single `SendInput` call, so
+//! no other input can land between them. With one call per key, a key the
+//! user pressed in between could arrive without the modifiers, or a
+//! modifier the user let go of could end the combination early. Batches
+//! with anything but keys, or with a target window set, are executed one
+//! action at a time. How many batches were sent, and how many `SendInput`
+//! cut short (the rest of the array blocked, as by UIPI), is logged when
+//! the executor is dropped.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
 
 use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
-    MapVirtualKeyW, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_KEYUP,
-    MAPVK_VK_TO_VSC,
+    GetAsyncKeyState, GetKeyState, MapVirtualKeyW, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD,
+    KEYBDINPUT, KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE, KEYEVENTF_UNICODE,
+    MAPVK_VK_TO_VSC, MAPVK_VK_TO_VSC_EX, VK_CAPITAL, VK_CONTROL, VK_MENU, VK_NUMLOCK, VK_SCROLL,
 };
 
+use super::focus;
 use super::keycodes::keycode_to_vkcode;
-use crate::platform::{Action, ActionExecutor, KeyState, PlatformError};
+use super::post::PostTarget;
+use super::quirks::InjectQuirks;
+use super::INJECTED_TAG;
+use crate::platform::{
+    chord_steps, Action, ActionExecutor, Capabilities, KeyCode, KeyState, PlatformError,
+    ScancodeInjection, TextInjection, WindowTarget,
+};
 
 // ---------------------------------------------------------------------------
 // Public struct
@@ -19,13 +64,126 @@ use crate::platform::{Action, ActionExecutor, KeyState, PlatformError};
 
 /// Injects keyboard events via SendInput on Windows.
 ///
-/// Stateless: each `execute()` call builds an `INPUT` record and calls
-/// `SendInput` synchronously. No background thread is required.
-pub struct WindowsExecutor;
+/// Each `execute()` call builds an `INPUT` record and calls `SendInput`
+/// synchronously, or posts a message to the target window when one is set.
+/// No background thread is required.
+pub struct WindowsExecutor {
+    target: Option<PostTarget>,
+    scancodes: ScancodeInjection,
+    /// Whether the last Pause Down went as Break.
+    quirks: Mutex<InjectQuirks>,
+    batches: BatchStats,
+}
+
+/// Largest `INPUT` array given to one `SendInput` call. The call takes any
+/// length, but holds up the input queue for the whole array; a longer batch
+/// is split, so a long macro does not hold back the user's own keys.
+const MAX_BATCH_INPUTS: usize = 64;
+
+/// Counts for the summary logged on drop.
+#[derive(Debug, Default)]
+struct BatchStats {
+    sent: AtomicU64,
+    inputs: AtomicU64,
+    cut_short: AtomicU64,
+}
 
 impl WindowsExecutor {
     pub fn new() -> Self {
-        WindowsExecutor
+        WindowsExecutor {
+            target: None,
+            scancodes: ScancodeInjection::Off,
+            quirks: Mutex::new(InjectQuirks::default()),
+            batches: BatchStats::default(),
+        }
+    }
+
+    /// Whether keys go by scan code right now.
+    fn by_scancode(&self) -> bool {
+        match &self.scancodes {
+            ScancodeInjection::Off => false,
+            ScancodeInjection::Always => true,
+            ScancodeInjection::Apps(apps) => focus::current()
+                .app_id
+                .is_some_and(|id| apps.iter().any(|a| a.eq_ignore_ascii_case(&id))),
+        }
+    }
+
+    /// Virtual key, scan code, and `EXTENDED` or 0 for `key` going to
+    /// `state`, with Ctrl and Alt held as given. `None` for a key with no
+    /// virtual key code.
+    fn resolve(
+        &self,
+        key: KeyCode,
+        state: KeyState,
+        ctrl: bool,
+        alt: bool,
+    ) -> Option<(u16, u16, u32)> {
+        let Some((vk, extra_flags)) = keycode_to_vkcode(key) else {
+            log::debug!("executor: no Windows VK code for {:?}, skipping", key);
+            return None;
+        };
+        let quirk = self
+            .quirks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .resolve(key, state, ctrl, alt);
+        Some(quirk.unwrap_or_else(|| {
+            (
+                vk,
+                unsafe { MapVirtualKeyW(vk as u32, MAPVK_VK_TO_VSC) as u16 },
+                extra_flags,
+            )
+        }))
+    }
+}
+
+/// Whether the OS sees `vk` held.
+fn held(vk: u16) -> bool {
+    (unsafe { GetAsyncKeyState(i32::from(vk)) }) as u16 & 0x8000 != 0
+}
+
+/// `wScan` and `dwFlags` for sending a resolved key to `state`, by scan
+/// code when `by_scancode` is set or the key follows NumLock, and the key
+/// has one.
+fn send_fields(
+    vk: u16,
+    scan: u16,
+    extra_flags: u32,
+    state: KeyState,
+    by_scancode: bool,
+) -> (u16, u32) {
+    let (scan, mut dw_flags) = if by_scancode || follows_numlock(vk) {
+        let scan_ex = unsafe { MapVirtualKeyW(vk as u32, MAPVK_VK_TO_VSC_EX) };
+        scancode_input(scan_ex, extra_flags).unwrap_or((scan, extra_flags))
+    } else {
+        (scan, extra_flags)
+    };
+    if state == KeyState::Up {
+        dw_flags |= KEYEVENTF_KEYUP;
+    }
+    (scan, dw_flags)
+}
+
+/// Whether `vk` is a numpad digit or VK_DECIMAL, whose scan codes the
+/// layout maps by NumLock.
+fn follows_numlock(vk: u16) -> bool {
+    matches!(vk, 0x60..=0x69 | 0x6E)
+}
+
+/// A tagged keyboard `INPUT` record.
+fn keyboard_input(vk: u16, scan: u16, dw_flags: u32) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: vk,
+                wScan: scan,
+                dwFlags: dw_flags,
+                time: 0,
+                dwExtraInfo: INJECTED_TAG,
+            },
+        },
     }
 }
 
@@ -37,47 +195,78 @@ impl ActionExecutor for WindowsExecutor {
     /// Executes an action.
     ///
     /// `Action::InjectKey` posts a `KEYBDINPUT` event via `SendInput`.
-    /// `Action::Exec` spawns a subprocess via `spawn_command`.
+    /// `Action::InjectRepeat` posts another key down, which is how a held
+    /// key repeats on Windows.
+    /// `Action::InjectChar` posts `KEYEVENTF_UNICODE` events via `SendInput`.
+    /// `Action::Chord` posts its keys in one `SendInput` array, as
+    /// `execute_batch` does.
+    /// `Action::SystemCommand` runs the platform's lock, sleep, or log out.
+    /// Pointer actions post `MOUSEINPUT` events via `SendInput`.
     /// All other variants are silently accepted as no-ops.
     fn execute(&self, action: &Action) -> Result<(), PlatformError> {
-        if let Action::Exec { command } = action {
-            // TODO(M11): suppress modifier chord members to prevent leakage to the focused application.
-            return crate::platform::spawn_command(command);
+        if let Some(result) = super::mouse::inject(action) {
+            return result;
+        }
+
+        if let Action::Chord { modifiers, key } = action {
+            let steps: Vec<Action> = chord_steps(*modifiers, *key)
+                .into_iter()
+                .map(|(key, state)| Action::InjectKey { key, state })
+                .collect();
+            return self.execute_batch(&steps);
+        }
+
+        if let Action::InjectChar(c) = action {
+            return match &self.target {
+                Some(target) => target.post_char(*c),
+                None => inject_char(*c),
+            };
+        }
+
+        if let Action::SystemCommand(cmd) = action {
+            return super::system::run(*cmd);
+        }
+
+        if let Action::Delay { ms } = action {
+            log::debug!("executor: {ms}ms delay outside a sequence; ignored");
+            return Ok(());
+        }
+
+        if let Action::InjectRepeat { key } = action {
+            return self.execute(&Action::InjectKey {
+                key: *key,
+                state: KeyState::Down,
+            });
         }
 
         let Action::InjectKey { key, state } = action else {
             return Ok(());
         };
 
-        let Some((vk, extra_flags)) = keycode_to_vkcode(*key) else {
-            log::debug!("executor: no Windows VK code for {:?}, skipping", key);
+        let Some((vk, scan, extra_flags)) =
+            self.resolve(*key, *state, held(VK_CONTROL), held(VK_MENU))
+        else {
             return Ok(());
         };
 
-        let mut dw_flags = extra_flags;
-        if *state == KeyState::Up {
-            dw_flags |= KEYEVENTF_KEYUP;
+        if let Some(target) = &self.target {
+            let extended = extra_flags & KEYEVENTF_EXTENDEDKEY != 0;
+            return target.post_key(vk, scan, extended, *state);
         }
 
-        let inject_start = std::time::Instant::now();
-
-        let input = INPUT {
-            r#type: INPUT_KEYBOARD,
-            Anonymous: INPUT_0 {
-                ki: KEYBDINPUT {
-                    wVk: vk,
-                    wScan: unsafe { MapVirtualKeyW(vk as u32, MAPVK_VK_TO_VSC) as u16 },
-                    dwFlags: dw_flags,
-                    time: 0,
-                    dwExtraInfo: 0,
-                },
-            },
-        };
+        let (scan, dw_flags) = send_fields(vk, scan, extra_flags, *state, self.by_scancode());
+
+        let inject_start = Instant::now();
+
+        let input = keyboard_input(vk, scan, dw_flags);
 
         let sent = unsafe { SendInput(1, &input, std::mem::size_of::<INPUT>() as i32) };
 
         if sent == 0 {
-            return Err(PlatformError::Other("SendInput returned 0".into()));
+            return Err(PlatformError::io(
+                "SendInput returned 0",
+                std::io::Error::last_os_error(),
+            ));
         }
 
         log::debug!(
@@ -89,6 +278,206 @@ impl ActionExecutor for WindowsExecutor {
 
         Ok(())
     }
+
+    /// Sends a run of `Action::InjectKey` as one `INPUT` array. Anything
+    /// else in it, or a target window, executes the actions one by one.
+    fn execute_batch(&self, actions: &[Action]) -> Result<(), PlatformError> {
+        let keys: Option<Vec<(KeyCode, KeyState)>> = actions
+            .iter()
+            .map(|action| match action {
+                Action::InjectKey { key, state } => Some((*key, *state)),
+                _ => None,
+            })
+            .collect();
+        let Some(keys) = keys.filter(|_| self.target.is_none()) else {
+            return actions.iter().try_for_each(|action| self.execute(action));
+        };
+
+        let inputs = self.batch_inputs(&keys, held(VK_CONTROL), held(VK_MENU), self.by_scancode());
+        self.send_batch(&inputs)
+    }
+
+    fn name(&self) -> &'static str {
+        "sendinput"
+    }
+
+    /// `KEYEVENTF_UNICODE` types any character.
+    fn text_injection(&self) -> TextInjection {
+        TextInjection::Chars
+    }
+
+    /// Everything but the clipboard, which the engine owns.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            inject_key: true,
+            type_text: true,
+            pointer: true,
+            system_command: true,
+            clipboard: false,
+        }
+    }
+
+    fn set_scancode_injection(&mut self, mode: ScancodeInjection) {
+        if mode != ScancodeInjection::Off {
+            log::info!("executor: injecting keys by scan code ({mode:?})");
+        }
+        self.scancodes = mode;
+    }
+
+    fn set_target(&mut self, target: WindowTarget) -> Result<(), PlatformError> {
+        log::info!("executor: posting input to the window matching {target:?}");
+        self.target = Some(PostTarget::new(target));
+        Ok(())
+    }
+
+    /// Reads the toggle bit (low-order bit) of `GetKeyState` for the lock key.
+    fn lock_state(&self, key: KeyCode) -> Option<bool> {
+        let vk = match key {
+            KeyCode::NumLock => VK_NUMLOCK,
+            KeyCode::CapsLock => VK_CAPITAL,
+            KeyCode::ScrollLock => VK_SCROLL,
+            _ => return None,
+        };
+        Some(unsafe { GetKeyState(vk as i32) } & 1 != 0)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Batches
+// ---------------------------------------------------------------------------
+
+impl WindowsExecutor {
+    /// The `INPUT` records for `keys`, with Ctrl and Alt held as given
+    /// before the first. The OS sees the batch's own modifiers only once it
+    /// is sent, so they are followed here for the quirks. Keys with no
+    /// virtual key code are left out.
+    fn batch_inputs(
+        &self,
+        keys: &[(KeyCode, KeyState)],
+        mut ctrl: bool,
+        mut alt: bool,
+        by_scancode: bool,
+    ) -> Vec<INPUT> {
+        let mut inputs = Vec::with_capacity(keys.len());
+        for &(key, state) in keys {
+            if KeyCode::Ctrl.covers(key) {
+                ctrl = state == KeyState::Down;
+            } else if KeyCode::Alt.covers(key) {
+                alt = state == KeyState::Down;
+            }
+            if let Some((vk, scan, extra_flags)) = self.resolve(key, state, ctrl, alt) {
+                let (scan, dw_flags) = send_fields(vk, scan, extra_flags, state, by_scancode);
+                inputs.push(keyboard_input(vk, scan, dw_flags));
+            }
+        }
+        inputs
+    }
+
+    /// Send `inputs` in `MAX_BATCH_INPUTS` chunks, stopping at a chunk
+    /// `SendInput` cuts short.
+    fn send_batch(&self, inputs: &[INPUT]) -> Result<(), PlatformError> {
+        let start = Instant::now();
+        for chunk in inputs.chunks(MAX_BATCH_INPUTS) {
+            let sent = unsafe {
+                SendInput(
+                    chunk.len() as u32,
+                    chunk.as_ptr(),
+                    std::mem::size_of::<INPUT>() as i32,
+                )
+            };
+            self.batches.sent.fetch_add(1, Ordering::Relaxed);
+            self.batches
+                .inputs
+                .fetch_add(u64::from(sent), Ordering::Relaxed);
+            if sent as usize != chunk.len() {
+                self.batches.cut_short.fetch_add(1, Ordering::Relaxed);
+                return Err(PlatformError::io(
+                    format!("SendInput sent {sent} of {} key events", chunk.len()),
+                    std::io::Error::last_os_error(),
+                ));
+            }
+        }
+        log::debug!(
+            "executor: injected {} key events in one batch in {:.2}ms",
+            inputs.len(),
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+        Ok(())
+    }
+}
+
+impl Drop for WindowsExecutor {
+    fn drop(&mut self) {
+        let sent = self.batches.sent.load(Ordering::Relaxed);
+        if sent > 0 {
+            log::info!(
+                "executor: {sent} key batch(es) with {} event(s) sent whole, {} cut short",
+                self.batches.inputs.load(Ordering::Relaxed),
+                self.batches.cut_short.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+/// `wScan` and `dwFlags` for sending a key by scan code, from its
+/// `MAPVK_VK_TO_VSC_EX` value and its virtual-key flags. `None` when the key
+/// has no scan code or needs the `E1` prefix, which `SendInput` cannot send.
+fn scancode_input(scan_ex: u32, vk_flags: u32) -> Option<(u16, u32)> {
+    let scan = (scan_ex & 0xFF) as u16;
+    let mut flags = KEYEVENTF_SCANCODE | (vk_flags & KEYEVENTF_EXTENDEDKEY);
+    match scan_ex >> 8 {
+        _ if scan == 0 => return None,
+        0x00 => {}
+        0xE0 => flags |= KEYEVENTF_EXTENDEDKEY,
+        _ => return None,
+    }
+    Some((scan, flags))
+}
+
+// ---------------------------------------------------------------------------
+// Unicode injection
+// ---------------------------------------------------------------------------
+
+/// Types `c` with `KEYEVENTF_UNICODE`, which bypasses the keyboard layout.
+///
+/// Each UTF-16 unit is sent as its own key; a surrogate pair (codepoints
+/// above U+FFFF) is sent as both Downs then both Ups in a single `SendInput`
+/// call so no other input can land between the halves.
+fn inject_char(c: char) -> Result<(), PlatformError> {
+    let units = crate::platform::utf16_units(c);
+    let unicode_input = |unit: u16, flags: u32| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: 0,
+                wScan: unit,
+                dwFlags: KEYEVENTF_UNICODE | flags,
+                time: 0,
+                dwExtraInfo: INJECTED_TAG,
+            },
+        },
+    };
+    let inputs: Vec<INPUT> = units
+        .iter()
+        .map(|&u| unicode_input(u, 0))
+        .chain(units.iter().map(|&u| unicode_input(u, KEYEVENTF_KEYUP)))
+        .collect();
+
+    let sent = unsafe {
+        SendInput(
+            inputs.len() as u32,
+            inputs.as_ptr(),
+            std::mem::size_of::<INPUT>() as i32,
+        )
+    };
+    if sent as usize != inputs.len() {
+        return Err(PlatformError::Other(format!(
+            "SendInput sent {sent} of {} unicode events",
+            inputs.len()
+        )));
+    }
+    log::debug!("executor: injected char U+{:04X}", c as u32);
+    Ok(())
 }
 
 // ---------------------------------------------------------------------------
@@ -98,7 +487,57 @@ impl ActionExecutor for WindowsExecutor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::platform::{Action, KeyCode};
+    use crate::platform::Action;
+
+    #[test]
+    fn scancode_input_marks_extended_keys() {
+        // A, Left arrow (E0 4B), NumpadEnter (extended only by its VK flags).
+        assert_eq!(scancode_input(0x1E, 0), Some((0x1E, KEYEVENTF_SCANCODE)));
+        assert_eq!(
+            scancode_input(0xE04B, KEYEVENTF_EXTENDEDKEY),
+            Some((0x4B, KEYEVENTF_SCANCODE | KEYEVENTF_EXTENDEDKEY))
+        );
+        assert_eq!(
+            scancode_input(0x1C, KEYEVENTF_EXTENDEDKEY),
+            Some((0x1C, KEYEVENTF_SCANCODE | KEYEVENTF_EXTENDEDKEY))
+        );
+        // Pause (E1 1D) and keys without a scan code keep their VK.
+        assert_eq!(scancode_input(0xE11D, 0), None);
+        assert_eq!(scancode_input(0, 0), None);
+    }
+
+    #[test]
+    fn batch_inputs_are_tagged_and_ordered() {
+        let executor = WindowsExecutor::new();
+        let keys = [
+            (KeyCode::Ctrl, KeyState::Down),
+            (KeyCode::Pause, KeyState::Down),
+            (KeyCode::Pause, KeyState::Up),
+            (KeyCode::Ctrl, KeyState::Up),
+            (KeyCode::BrightnessUp, KeyState::Down),
+        ];
+        let inputs = executor.batch_inputs(&keys, false, false, false);
+        let fields: Vec<(u16, u32)> = inputs
+            .iter()
+            .map(|input| {
+                assert_eq!(input.r#type, INPUT_KEYBOARD);
+                let ki = unsafe { input.Anonymous.ki };
+                assert_eq!(ki.dwExtraInfo, INJECTED_TAG);
+                (ki.wVk, ki.dwFlags & KEYEVENTF_KEYUP)
+            })
+            .collect();
+        // Pause with the batch's own Ctrl down goes as Break (VK_CANCEL);
+        // brightness has no virtual key and is left out.
+        assert_eq!(
+            fields,
+            vec![
+                (0xA2, 0),
+                (0x03, 0),
+                (0x03, KEYEVENTF_KEYUP),
+                (0xA2, KEYEVENTF_KEYUP),
+            ]
+        );
+    }
 
     /// Non-InjectKey variants must return Ok without touching any OS API.
     #[test]
@@ -0,0 +1,380 @@
+//! Foreground-window tracking for Windows window context.
+//!
+//! `FocusHooks::install` registers two WinEvent hooks on the capture thread,
+//! whose message loop delivers them: `EVENT_SYSTEM_FOREGROUND` for a new
+//! foreground window, and `EVENT_OBJECT_NAMECHANGE` for title changes, as
+//! when a browser switches tabs. Each updates a cached `WindowContext`,
+//! which the keyboard hook, on the same thread, stamps onto every key event
+//! without a lock, so the hook itself never queries another process or
+//! waits. A new foreground window, and a new title
+//! of the foreground window, are also handed to the capture's focus
+//! callback, if it has one, so the engine hears of them without waiting for
+//! a key.
+//!
+//! `app_id` is the executable name of the window's process (`firefox.exe`),
+//! from `QueryFullProcessImageNameW`. `title` is the window text.
+//!
+//! Edge cases:
+//!   - UWP apps are framed by `ApplicationFrameHost.exe`, which owns the
+//!     foreground window. The app's own process owns a child window, so
+//!     the tracker looks for a child owned by another process and uses its
+//!     executable instead. Right after launch the child may not be attached
+//!     yet; the host's name is used until the next foreground change.
+//!   - Elevated and protected processes may refuse
+//!     `PROCESS_QUERY_LIMITED_INFORMATION`. `app_id` is then `None`, while
+//!     the title is still read.
+//!   - Title changes arrive for every window and control in the system;
+//!     all but those of the foreground window itself are ignored.
+//!
+//! Each foreground change is also checked with `ElevationCheck` (see
+//! `elevation`): while the window belongs to a process our input cannot
+//! reach, the tracker suspends rules.
+
+use std::cell::RefCell;
+use std::ptr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use windows_sys::Win32::Foundation::{CloseHandle, BOOL, HWND, LPARAM};
+use windows_sys::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows_sys::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    EnumChildWindows, GetForegroundWindow, GetWindowTextLengthW, GetWindowTextW,
+    GetWindowThreadProcessId, CHILDID_SELF, EVENT_OBJECT_NAMECHANGE, EVENT_SYSTEM_FOREGROUND,
+    OBJID_WINDOW, WINEVENT_OUTOFCONTEXT, WINEVENT_SKIPOWNPROCESS,
+};
+
+use super::elevation::{ElevationCheck, Tokens};
+use crate::platform::{Suspension, WindowContext};
+
+/// Host process of UWP app frames.
+const FRAME_HOST: &str = "ApplicationFrameHost.exe";
+
+/// Longest image path `QueryFullProcessImageNameW` is given room for.
+const MAX_PATH_CHARS: usize = 32_768;
+
+/// Out-of-reach checks and the flag they set, for `refresh`. WinEvent
+/// procs receive no `user_info`, so it lives in a global, like the event
+/// queue in `capture`.
+static REACH: Mutex<Option<Reach>> = Mutex::new(None);
+
+type FocusCallback = Box<dyn Fn(WindowContext) + Send>;
+
+// WinEvents are delivered on the thread that installed the hooks, which is
+// also where the keyboard hook reads the context, so what they share lives
+// there and is read without a lock.
+thread_local! {
+    /// The foreground window's context, as of the last WinEvent.
+    static FOCUS: RefCell<Option<Focus>> = const { RefCell::new(None) };
+    /// Told of each new focus.
+    static ON_CHANGE: RefCell<Option<FocusCallback>> = const { RefCell::new(None) };
+}
+
+struct Reach {
+    check: ElevationCheck<Tokens>,
+    suspension: Suspension,
+}
+
+/// The tracked window and its context.
+struct Focus {
+    hwnd: HWND,
+    context: WindowContext,
+}
+
+/// The context of the foreground window, or an empty one when it is not
+/// known. Only meaningful on the thread that installed the `FocusHooks`.
+pub(super) fn current() -> WindowContext {
+    FOCUS.with(|f| {
+        f.try_borrow()
+            .ok()
+            .and_then(|f| f.as_ref().map(|f| f.context.clone()))
+            .unwrap_or_default()
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Hooks
+// ---------------------------------------------------------------------------
+
+/// The installed WinEvent hooks; dropping it removes them and clears the
+/// cached context. Must be dropped on the thread that installed it.
+pub(super) struct FocusHooks {
+    hooks: Vec<HWINEVENTHOOK>,
+}
+
+impl FocusHooks {
+    /// Install the hooks on the calling thread, which must run a message
+    /// loop, and read the current foreground window. Rules are suspended
+    /// through `suspension` while that window is out of reach. With
+    /// `expect_ui_access`, warn when our token has no UIAccess. `on_change`,
+    /// if set, is told of the current window and of each one after it.
+    pub(super) fn install(
+        suspension: Suspension,
+        expect_ui_access: bool,
+        on_change: Option<FocusCallback>,
+    ) -> Self {
+        let flags = WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS;
+        let hooks: Vec<HWINEVENTHOOK> = [EVENT_SYSTEM_FOREGROUND, EVENT_OBJECT_NAMECHANGE]
+            .into_iter()
+            .map(|event| unsafe {
+                SetWinEventHook(
+                    event,
+                    event,
+                    ptr::null_mut(),
+                    Some(win_event_proc),
+                    0,
+                    0,
+                    flags,
+                )
+            })
+            .filter(|hook| !hook.is_null())
+            .collect();
+        if hooks.len() < 2 {
+            log::warn!("capture: WinEvent hook failed; window context may be stale");
+        }
+        let check = ElevationCheck::new(Tokens);
+        if check.has_ui_access() {
+            log::info!("capture: running with UIAccess; elevated windows are reachable");
+        } else if expect_ui_access {
+            log::warn!(
+                "capture: --allow-uiaccess given, but the process has no UIAccess; the binary \
+                 must embed a uiAccess manifest, be signed, and run from a secure location \
+                 such as Program Files"
+            );
+        }
+        let _ = REACH
+            .lock()
+            .map(|mut r| *r = Some(Reach { check, suspension }));
+        ON_CHANGE.with(|c| *c.borrow_mut() = on_change);
+        refresh(unsafe { GetForegroundWindow() });
+        Self { hooks }
+    }
+}
+
+impl Drop for FocusHooks {
+    fn drop(&mut self) {
+        for hook in self.hooks.drain(..) {
+            unsafe { UnhookWinEvent(hook) };
+        }
+        FOCUS.with(|f| f.borrow_mut().take());
+        ON_CHANGE.with(|c| c.borrow_mut().take());
+        if let Some(reach) = REACH.lock().ok().and_then(|mut r| r.take()) {
+            reach.suspension.lift();
+        }
+    }
+}
+
+unsafe extern "system" fn win_event_proc(
+    _hook: HWINEVENTHOOK,
+    event: u32,
+    hwnd: HWND,
+    id_object: i32,
+    id_child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    match event {
+        EVENT_SYSTEM_FOREGROUND => refresh(hwnd),
+        EVENT_OBJECT_NAMECHANGE if id_object == OBJID_WINDOW && id_child == CHILDID_SELF as i32 => {
+            let retitled = FOCUS.with(|focus| {
+                let mut focus = focus.try_borrow_mut().ok()?;
+                let focus = focus.as_mut().filter(|f| f.hwnd == hwnd)?;
+                let title = window_title(hwnd);
+                if title == focus.context.title {
+                    return None;
+                }
+                log::debug!("capture: focused window title {title:?}");
+                focus.context.title = title;
+                Some(focus.context.clone())
+            });
+            if let Some(context) = retitled {
+                report(context);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Read `hwnd`'s context, make it the tracked window, and report it if it
+/// differs from the last.
+fn refresh(hwnd: HWND) {
+    let focus = (!hwnd.is_null()).then(|| Focus {
+        hwnd,
+        context: WindowContext {
+            app_id: app_id(hwnd),
+            title: window_title(hwnd),
+        },
+    });
+    if let Some(f) = &focus {
+        log::debug!(
+            "capture: focus {} {:?}",
+            f.context.app_id.as_deref().unwrap_or("(unknown)"),
+            f.context.title
+        );
+    }
+    check_reach(
+        hwnd,
+        focus.as_ref().and_then(|f| f.context.app_id.as_deref()),
+    );
+    let context = focus
+        .as_ref()
+        .map(|f| f.context.clone())
+        .unwrap_or_default();
+    let changed = FOCUS.with(|current| match current.try_borrow_mut() {
+        Ok(mut current) => {
+            let before = current.take().map(|f| f.context).unwrap_or_default();
+            *current = focus;
+            before != context
+        }
+        Err(_) => false,
+    });
+    if changed {
+        report(context);
+    }
+}
+
+/// Hand `context` to the focus callback, if there is one.
+fn report(context: WindowContext) {
+    ON_CHANGE.with(|c| {
+        if let Some(on_change) = c.borrow().as_ref() {
+            on_change(context);
+        }
+    });
+}
+
+/// Suspend rules while `hwnd`, the new foreground window, is out of reach,
+/// and lift the suspension once it is not.
+fn check_reach(hwnd: HWND, app: Option<&str>) {
+    let Ok(mut reach) = REACH.lock() else {
+        return;
+    };
+    let Some(reach) = reach.as_mut() else {
+        return;
+    };
+    let pid = if hwnd.is_null() { 0 } else { window_pid(hwnd) };
+    match reach.check.focus_changed(pid, app, Instant::now()) {
+        Some(blocked) => {
+            if blocked.warn {
+                log::warn!(
+                    "capture: {} runs elevated; Windows drops our input to it, so rules are \
+                     suspended while it has focus (run pcunifier elevated, or see \
+                     --allow-uiaccess)",
+                    app.unwrap_or("the focused window")
+                );
+            }
+            reach.suspension.suspend(blocked.reason);
+        }
+        None => reach.suspension.lift(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Queries
+// ---------------------------------------------------------------------------
+
+/// The executable name of `hwnd`'s process, or of the framed app's process
+/// for a UWP frame.
+fn app_id(hwnd: HWND) -> Option<String> {
+    let pid = window_pid(hwnd);
+    let name = process_image(pid).as_deref().and_then(exe_name)?;
+    if !name.eq_ignore_ascii_case(FRAME_HOST) {
+        return Some(name);
+    }
+    framed_app_pid(hwnd, pid)
+        .and_then(process_image)
+        .as_deref()
+        .and_then(exe_name)
+        .or(Some(name))
+}
+
+fn window_pid(hwnd: HWND) -> u32 {
+    let mut pid = 0u32;
+    unsafe { GetWindowThreadProcessId(hwnd, &mut pid) };
+    pid
+}
+
+/// The process of the first child of `frame` not owned by the frame host
+/// `host_pid`.
+fn framed_app_pid(frame: HWND, host_pid: u32) -> Option<u32> {
+    struct Search {
+        host_pid: u32,
+        found: Option<u32>,
+    }
+
+    unsafe extern "system" fn visit(child: HWND, lparam: LPARAM) -> BOOL {
+        let search = &mut *(lparam as *mut Search);
+        let pid = window_pid(child);
+        if pid != 0 && pid != search.host_pid {
+            search.found = Some(pid);
+            return 0;
+        }
+        1
+    }
+
+    let mut search = Search {
+        host_pid,
+        found: None,
+    };
+    unsafe { EnumChildWindows(frame, Some(visit), &mut search as *mut Search as LPARAM) };
+    search.found
+}
+
+/// The full path of `pid`'s executable.
+fn process_image(pid: u32) -> Option<String> {
+    if pid == 0 {
+        return None;
+    }
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return None;
+        }
+        let mut buf = vec![0u16; MAX_PATH_CHARS];
+        let mut len = buf.len() as u32;
+        let ok =
+            QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, buf.as_mut_ptr(), &mut len);
+        CloseHandle(process);
+        (ok != 0).then(|| String::from_utf16_lossy(&buf[..len as usize]))
+    }
+}
+
+fn window_title(hwnd: HWND) -> Option<String> {
+    unsafe {
+        let len = GetWindowTextLengthW(hwnd);
+        if len <= 0 {
+            return None;
+        }
+        let mut buf = vec![0u16; len as usize + 1];
+        let copied = GetWindowTextW(hwnd, buf.as_mut_ptr(), buf.len() as i32);
+        (copied > 0).then(|| String::from_utf16_lossy(&buf[..copied as usize]))
+    }
+}
+
+/// The file name of an executable path.
+fn exe_name(path: &str) -> Option<String> {
+    path.rsplit(['\\', '/'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exe_name_is_the_last_path_component() {
+        assert_eq!(
+            exe_name(r"C:\Program Files\Mozilla Firefox\firefox.exe").as_deref(),
+            Some("firefox.exe")
+        );
+        assert_eq!(exe_name("notepad.exe").as_deref(), Some("notepad.exe"));
+        assert_eq!(exe_name(r"C:\broken\"), None);
+    }
+}
@@ -4,6 +4,12 @@
 //! (VK_LSHIFT, VK_RSHIFT, etc.) are both accepted in `vkcode_to_keycode`;
 //! `keycode_to_vkcode` emits the left-hand variant for injection.
 //!
+//! With NumLock off the numpad sends the navigation VK codes (VK_HOME for
+//! Numpad7) without the extended bit, where the dedicated navigation keys set
+//! it. `vkcode_to_keycode` reports those presses as the numpad key, as Linux
+//! and macOS do, so rules can tell Numpad7 from Home (see
+//! `KeyCode::numpad_origin`).
+//!
 //! `keycode_to_vkcode` returns `(vk, extra_flags)` where `extra_flags` is
 //! `KEYEVENTF_EXTENDEDKEY` (0x0001) for keys that require it (navigation keys,
 //! NumpadEnter, NumpadDiv) and 0 otherwise. The executor ORs in
@@ -18,11 +24,22 @@ pub const EXTENDED: u32 = 0x0001;
 // Capture direction
 // ---------------------------------------------------------------------------
 
+/// A navigation key as pressed: the dedicated key sets the extended bit, the
+/// numpad key acting as it with NumLock off does not.
+fn navigation(key: KeyCode, extended: bool) -> KeyCode {
+    if extended {
+        key
+    } else {
+        key.numpad_origin().unwrap_or(key)
+    }
+}
+
 /// Converts a Windows virtual key code and extended-key flag to a `KeyCode`.
 ///
 /// `extended` is true when bit 0 (`LLKHF_EXTENDED`) is set in the
 /// `KBDLLHOOKSTRUCT.flags` field, which distinguishes e.g. NumpadEnter
-/// (VK_RETURN + extended) from the main Enter (VK_RETURN, not extended).
+/// (VK_RETURN + extended) from the main Enter (VK_RETURN, not extended),
+/// and the numpad with NumLock off (VK_HOME, not extended) from Home.
 ///
 /// Returns `None` for codes with no `KeyCode` equivalent.
 pub fn vkcode_to_keycode(vk: u16, extended: bool) -> Option<KeyCode> {
@@ -93,12 +110,20 @@ pub fn vkcode_to_keycode(vk: u16, extended: bool) -> Option<KeyCode> {
         0x86 => Some(KeyCode::F23),
         0x87 => Some(KeyCode::F24),
 
-        // Modifiers -- left and right variants map to the canonical form.
-        // WH_KEYBOARD_LL sends VK_LSHIFT (0xA0) / VK_RSHIFT (0xA1), etc.
-        0x10 | 0xA0 | 0xA1 => Some(KeyCode::Shift),
-        0x11 | 0xA2 | 0xA3 => Some(KeyCode::Ctrl),
-        0x12 | 0xA4 | 0xA5 => Some(KeyCode::Alt),
-        0x5B | 0x5C => Some(KeyCode::Meta), // VK_LWIN / VK_RWIN
+        // Modifiers, by side. WH_KEYBOARD_LL sends VK_LSHIFT (0xA0) /
+        // VK_RSHIFT (0xA1), etc.; the generic codes some programs inject say
+        // no side and map to the unified form.
+        0xA0 => Some(KeyCode::LeftShift),
+        0xA1 => Some(KeyCode::RightShift),
+        0x10 => Some(KeyCode::Shift),
+        0xA2 => Some(KeyCode::LeftCtrl),
+        0xA3 => Some(KeyCode::RightCtrl),
+        0x11 => Some(KeyCode::Ctrl),
+        0xA4 => Some(KeyCode::LeftAlt),
+        0xA5 => Some(KeyCode::RightAlt),
+        0x12 => Some(KeyCode::Alt),
+        0x5B => Some(KeyCode::LeftMeta),  // VK_LWIN
+        0x5C => Some(KeyCode::RightMeta), // VK_RWIN
 
         // Navigation and editing
         0x20 => Some(KeyCode::Space),
@@ -108,16 +133,16 @@ pub fn vkcode_to_keycode(vk: u16, extended: bool) -> Option<KeyCode> {
         0x09 => Some(KeyCode::Tab),
         0x1B => Some(KeyCode::Escape),
         0x08 => Some(KeyCode::Backspace),
-        0x2E => Some(KeyCode::Delete),
-        0x2D => Some(KeyCode::Insert),
-        0x24 => Some(KeyCode::Home),
-        0x23 => Some(KeyCode::End),
-        0x21 => Some(KeyCode::PageUp),
-        0x22 => Some(KeyCode::PageDown),
-        0x26 => Some(KeyCode::Up),
-        0x28 => Some(KeyCode::Down),
-        0x25 => Some(KeyCode::Left),
-        0x27 => Some(KeyCode::Right),
+        0x2E => Some(navigation(KeyCode::Delete, extended)),
+        0x2D => Some(navigation(KeyCode::Insert, extended)),
+        0x24 => Some(navigation(KeyCode::Home, extended)),
+        0x23 => Some(navigation(KeyCode::End, extended)),
+        0x21 => Some(navigation(KeyCode::PageUp, extended)),
+        0x22 => Some(navigation(KeyCode::PageDown, extended)),
+        0x26 => Some(navigation(KeyCode::Up, extended)),
+        0x28 => Some(navigation(KeyCode::Down, extended)),
+        0x25 => Some(navigation(KeyCode::Left, extended)),
+        0x27 => Some(navigation(KeyCode::Right, extended)),
 
         // Lock and system keys
         0x14 => Some(KeyCode::CapsLock),
@@ -125,6 +150,27 @@ pub fn vkcode_to_keycode(vk: u16, extended: bool) -> Option<KeyCode> {
         0x91 => Some(KeyCode::ScrollLock),
         0x2C => Some(KeyCode::PrintScreen),
         0x13 => Some(KeyCode::Pause),
+        0x5D => Some(KeyCode::Menu), // VK_APPS
+
+        // Media, browser, and launch keys (VK_BROWSER_BACK .. VK_LAUNCH_APP2)
+        0xA6 => Some(KeyCode::BrowserBack),
+        0xA7 => Some(KeyCode::BrowserForward),
+        0xA8 => Some(KeyCode::BrowserRefresh),
+        0xA9 => Some(KeyCode::BrowserStop),
+        0xAA => Some(KeyCode::BrowserSearch),
+        0xAB => Some(KeyCode::BrowserFavorites),
+        0xAC => Some(KeyCode::BrowserHome),
+        0xAD => Some(KeyCode::Mute),
+        0xAE => Some(KeyCode::VolumeDown),
+        0xAF => Some(KeyCode::VolumeUp),
+        0xB0 => Some(KeyCode::NextTrack),
+        0xB1 => Some(KeyCode::PrevTrack),
+        0xB2 => Some(KeyCode::Stop),
+        0xB3 => Some(KeyCode::PlayPause),
+        0xB4 => Some(KeyCode::LaunchMail),
+        0xB5 => Some(KeyCode::LaunchMedia),
+        0xB6 => Some(KeyCode::LaunchApp1),
+        0xB7 => Some(KeyCode::LaunchApp2),
 
         // Numeric keypad
         0x60 => Some(KeyCode::Numpad0),
@@ -133,6 +179,7 @@ pub fn vkcode_to_keycode(vk: u16, extended: bool) -> Option<KeyCode> {
         0x63 => Some(KeyCode::Numpad3),
         0x64 => Some(KeyCode::Numpad4),
         0x65 => Some(KeyCode::Numpad5),
+        0x0C => Some(KeyCode::Numpad5), // VK_CLEAR: Numpad5 with NumLock off
         0x66 => Some(KeyCode::Numpad6),
         0x67 => Some(KeyCode::Numpad7),
         0x68 => Some(KeyCode::Numpad8),
@@ -141,6 +188,7 @@ pub fn vkcode_to_keycode(vk: u16, extended: bool) -> Option<KeyCode> {
         0x6D => Some(KeyCode::NumpadSub),
         0x6A => Some(KeyCode::NumpadMul),
         0x6F => Some(KeyCode::NumpadDiv),
+        0x6E => Some(KeyCode::NumpadDecimal), // VK_DECIMAL
 
         // Punctuation / symbol keys (OEM codes, ANSI layout assumed)
         0xC0 => Some(KeyCode::Backtick),
@@ -154,6 +202,11 @@ pub fn vkcode_to_keycode(vk: u16, extended: bool) -> Option<KeyCode> {
         0xBC => Some(KeyCode::Comma),
         0xBE => Some(KeyCode::Period),
         0xBF => Some(KeyCode::Slash),
+        0xE2 => Some(KeyCode::IntlBackslash), // VK_OEM_102
+
+        // Input method keys
+        0x15 => Some(KeyCode::Lang1), // VK_KANA / VK_HANGUL
+        0x19 => Some(KeyCode::Lang2), // VK_KANJI / VK_HANJA
 
         _ => None,
     }
@@ -238,10 +291,14 @@ pub fn keycode_to_vkcode(key: KeyCode) -> Option<(u16, u32)> {
         KeyCode::F24 => (0x87, 0),
 
         // Modifiers: inject as left-hand variant.
-        KeyCode::Shift => (0xA0, 0), // VK_LSHIFT
-        KeyCode::Ctrl => (0xA2, 0),  // VK_LCONTROL
-        KeyCode::Alt => (0xA4, 0),   // VK_LMENU
-        KeyCode::Meta => (0x5B, 0),  // VK_LWIN
+        KeyCode::Shift | KeyCode::LeftShift => (0xA0, 0), // VK_LSHIFT
+        KeyCode::Ctrl | KeyCode::LeftCtrl => (0xA2, 0),   // VK_LCONTROL
+        KeyCode::Alt | KeyCode::LeftAlt => (0xA4, 0),     // VK_LMENU
+        KeyCode::Meta | KeyCode::LeftMeta => (0x5B, 0),   // VK_LWIN
+        KeyCode::RightShift => (0xA1, 0),                 // VK_RSHIFT
+        KeyCode::RightCtrl => (0xA3, EXTENDED),           // VK_RCONTROL
+        KeyCode::RightAlt => (0xA5, EXTENDED),            // VK_RMENU
+        KeyCode::RightMeta => (0x5C, 0),                  // VK_RWIN
 
         // Navigation and editing
         // Navigation keys need EXTENDED to distinguish from numpad equivalents.
@@ -266,8 +323,33 @@ pub fn keycode_to_vkcode(key: KeyCode) -> Option<(u16, u32)> {
         KeyCode::CapsLock => (0x14, 0),
         KeyCode::NumLock => (0x90, 0),
         KeyCode::ScrollLock => (0x91, 0),
-        KeyCode::PrintScreen => (0x2C, 0),
+        KeyCode::PrintScreen => (0x2C, EXTENDED),
         KeyCode::Pause => (0x13, 0),
+        KeyCode::Menu => (0x5D, EXTENDED),
+
+        // Media, browser, and launch keys. Their scan codes all carry the
+        // E0 prefix, so they are extended keys.
+        KeyCode::BrowserBack => (0xA6, EXTENDED),
+        KeyCode::BrowserForward => (0xA7, EXTENDED),
+        KeyCode::BrowserRefresh => (0xA8, EXTENDED),
+        KeyCode::BrowserStop => (0xA9, EXTENDED),
+        KeyCode::BrowserSearch => (0xAA, EXTENDED),
+        KeyCode::BrowserFavorites => (0xAB, EXTENDED),
+        KeyCode::BrowserHome => (0xAC, EXTENDED),
+        KeyCode::Mute => (0xAD, EXTENDED),
+        KeyCode::VolumeDown => (0xAE, EXTENDED),
+        KeyCode::VolumeUp => (0xAF, EXTENDED),
+        KeyCode::NextTrack => (0xB0, EXTENDED),
+        KeyCode::PrevTrack => (0xB1, EXTENDED),
+        KeyCode::Stop => (0xB2, EXTENDED),
+        KeyCode::PlayPause => (0xB3, EXTENDED),
+        KeyCode::LaunchMail => (0xB4, EXTENDED),
+        KeyCode::LaunchMedia => (0xB5, EXTENDED),
+        KeyCode::LaunchApp1 => (0xB6, EXTENDED),
+        KeyCode::LaunchApp2 => (0xB7, EXTENDED),
+
+        // Windows has no virtual key code for brightness.
+        KeyCode::BrightnessUp | KeyCode::BrightnessDown => return None,
 
         // Numeric keypad
         KeyCode::Numpad0 => (0x60, 0),
@@ -284,6 +366,9 @@ pub fn keycode_to_vkcode(key: KeyCode) -> Option<(u16, u32)> {
         KeyCode::NumpadSub => (0x6D, 0),
         KeyCode::NumpadMul => (0x6A, 0),
         KeyCode::NumpadDiv => (0x6F, EXTENDED),
+        KeyCode::NumpadDecimal => (0x6E, 0),
+        // No VK on standard PC keyboards.
+        KeyCode::NumpadEquals => return None,
 
         // Punctuation / symbol keys
         KeyCode::Backtick => (0xC0, 0),
@@ -297,10 +382,88 @@ pub fn keycode_to_vkcode(key: KeyCode) -> Option<(u16, u32)> {
         KeyCode::Comma => (0xBC, 0),
         KeyCode::Period => (0xBE, 0),
         KeyCode::Slash => (0xBF, 0),
+        KeyCode::IntlBackslash => (0xE2, 0),
+        // The JIS layout reports these as VK_OEM_5 and VK_OEM_102, the VKs
+        // of Backslash and IntlBackslash; only their scancodes differ.
+        KeyCode::IntlYen | KeyCode::IntlRo => return None,
+
+        // Input method keys
+        KeyCode::Lang1 => (0x15, 0),
+        KeyCode::Lang2 => (0x19, 0),
     };
     Some((vk, flags))
 }
 
+// ---------------------------------------------------------------------------
+// Physical position
+// ---------------------------------------------------------------------------
+
+/// The key at a scancode's physical position on a US ANSI keyboard, for the
+/// typing area whose virtual key codes follow the active layout.
+///
+/// `scan` is the set 1 make code from `KBDLLHOOKSTRUCT.scanCode`. Extended
+/// keys and keys outside the typing area keep their virtual key code on every
+/// layout, so they return `None` and are matched by key.
+pub fn scancode_to_position(scan: u16, extended: bool) -> Option<KeyCode> {
+    if extended {
+        return None;
+    }
+    let key = match scan {
+        0x02 => KeyCode::Key1,
+        0x03 => KeyCode::Key2,
+        0x04 => KeyCode::Key3,
+        0x05 => KeyCode::Key4,
+        0x06 => KeyCode::Key5,
+        0x07 => KeyCode::Key6,
+        0x08 => KeyCode::Key7,
+        0x09 => KeyCode::Key8,
+        0x0A => KeyCode::Key9,
+        0x0B => KeyCode::Key0,
+        0x0C => KeyCode::Minus,
+        0x0D => KeyCode::Equal,
+        0x10 => KeyCode::Q,
+        0x11 => KeyCode::W,
+        0x12 => KeyCode::E,
+        0x13 => KeyCode::R,
+        0x14 => KeyCode::T,
+        0x15 => KeyCode::Y,
+        0x16 => KeyCode::U,
+        0x17 => KeyCode::I,
+        0x18 => KeyCode::O,
+        0x19 => KeyCode::P,
+        0x1A => KeyCode::LeftBracket,
+        0x1B => KeyCode::RightBracket,
+        0x1E => KeyCode::A,
+        0x1F => KeyCode::S,
+        0x20 => KeyCode::D,
+        0x21 => KeyCode::F,
+        0x22 => KeyCode::G,
+        0x23 => KeyCode::H,
+        0x24 => KeyCode::J,
+        0x25 => KeyCode::K,
+        0x26 => KeyCode::L,
+        0x27 => KeyCode::Semicolon,
+        0x28 => KeyCode::Apostrophe,
+        0x29 => KeyCode::Backtick,
+        0x2B => KeyCode::Backslash,
+        0x2C => KeyCode::Z,
+        0x2D => KeyCode::X,
+        0x2E => KeyCode::C,
+        0x2F => KeyCode::V,
+        0x30 => KeyCode::B,
+        0x31 => KeyCode::N,
+        0x32 => KeyCode::M,
+        0x33 => KeyCode::Comma,
+        0x34 => KeyCode::Period,
+        0x35 => KeyCode::Slash,
+        0x56 => KeyCode::IntlBackslash,
+        0x73 => KeyCode::IntlRo,
+        0x7D => KeyCode::IntlYen,
+        _ => return None,
+    };
+    Some(key)
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -336,15 +499,24 @@ mod tests {
     }
 
     #[test]
-    fn right_modifiers_map_to_canonical() {
-        assert_eq!(vkcode_to_keycode(0xA0, false), Some(KeyCode::Shift));
-        assert_eq!(vkcode_to_keycode(0xA1, false), Some(KeyCode::Shift));
-        assert_eq!(vkcode_to_keycode(0xA2, false), Some(KeyCode::Ctrl));
-        assert_eq!(vkcode_to_keycode(0xA3, false), Some(KeyCode::Ctrl));
-        assert_eq!(vkcode_to_keycode(0xA4, false), Some(KeyCode::Alt));
-        assert_eq!(vkcode_to_keycode(0xA5, false), Some(KeyCode::Alt));
-        assert_eq!(vkcode_to_keycode(0x5B, false), Some(KeyCode::Meta));
-        assert_eq!(vkcode_to_keycode(0x5C, false), Some(KeyCode::Meta));
+    fn numlock_off_numpad_is_told_from_navigation() {
+        assert_eq!(vkcode_to_keycode(0x24, true), Some(KeyCode::Home));
+        assert_eq!(vkcode_to_keycode(0x24, false), Some(KeyCode::Numpad7));
+        assert_eq!(vkcode_to_keycode(0x2E, false), Some(KeyCode::NumpadDecimal));
+        assert_eq!(vkcode_to_keycode(0x0C, false), Some(KeyCode::Numpad5));
+    }
+
+    #[test]
+    fn generic_modifiers_map_to_unified() {
+        assert_eq!(vkcode_to_keycode(0x10, false), Some(KeyCode::Shift));
+        assert_eq!(vkcode_to_keycode(0x11, false), Some(KeyCode::Ctrl));
+        assert_eq!(vkcode_to_keycode(0x12, false), Some(KeyCode::Alt));
+        for key in [KeyCode::Ctrl, KeyCode::Shift, KeyCode::Alt, KeyCode::Meta] {
+            let (vk, _) = keycode_to_vkcode(key).unwrap();
+            let back = vkcode_to_keycode(vk, false).unwrap();
+            assert!(back.is_left_modifier(), "{key:?} -> {back:?}");
+            assert_eq!(back.unify_side(), key);
+        }
     }
 
     #[test]
@@ -352,6 +524,20 @@ mod tests {
         assert_eq!(vkcode_to_keycode(0xFF, false), None);
     }
 
+    /// On AZERTY the key at the US Q position sends VK_A, but its scancode
+    /// still names Q.
+    #[test]
+    fn scancodes_name_us_positions() {
+        assert_eq!(scancode_to_position(0x10, false), Some(KeyCode::Q));
+        assert_eq!(scancode_to_position(0x1E, false), Some(KeyCode::A));
+        assert_eq!(
+            scancode_to_position(0x56, false),
+            Some(KeyCode::IntlBackslash)
+        );
+        assert_eq!(scancode_to_position(0x1C, false), None); // Enter
+        assert_eq!(scancode_to_position(0x35, true), None); // NumpadDiv
+    }
+
     #[test]
     fn round_trip_primary_mappings() {
         let cases: &[(KeyCode, u16)] = &[
@@ -364,6 +550,37 @@ mod tests {
             (KeyCode::Space, 0x20),
             (KeyCode::Escape, 0x1B),
             (KeyCode::Backtick, 0xC0),
+            (KeyCode::IntlBackslash, 0xE2),
+            (KeyCode::NumpadDecimal, 0x6E),
+            (KeyCode::Lang1, 0x15),
+            (KeyCode::Lang2, 0x19),
+            (KeyCode::Menu, 0x5D),
+            (KeyCode::LeftShift, 0xA0),
+            (KeyCode::RightShift, 0xA1),
+            (KeyCode::LeftCtrl, 0xA2),
+            (KeyCode::RightCtrl, 0xA3),
+            (KeyCode::LeftAlt, 0xA4),
+            (KeyCode::RightAlt, 0xA5),
+            (KeyCode::LeftMeta, 0x5B),
+            (KeyCode::RightMeta, 0x5C),
+            (KeyCode::VolumeUp, 0xAF),
+            (KeyCode::VolumeDown, 0xAE),
+            (KeyCode::Mute, 0xAD),
+            (KeyCode::PlayPause, 0xB3),
+            (KeyCode::NextTrack, 0xB0),
+            (KeyCode::PrevTrack, 0xB1),
+            (KeyCode::Stop, 0xB2),
+            (KeyCode::BrowserBack, 0xA6),
+            (KeyCode::BrowserForward, 0xA7),
+            (KeyCode::BrowserRefresh, 0xA8),
+            (KeyCode::BrowserStop, 0xA9),
+            (KeyCode::BrowserSearch, 0xAA),
+            (KeyCode::BrowserFavorites, 0xAB),
+            (KeyCode::BrowserHome, 0xAC),
+            (KeyCode::LaunchMail, 0xB4),
+            (KeyCode::LaunchMedia, 0xB5),
+            (KeyCode::LaunchApp1, 0xB6),
+            (KeyCode::LaunchApp2, 0xB7),
         ];
         for &(key, expected_vk) in cases {
             let (vk, _) = keycode_to_vkcode(key).expect("expected a mapping");
@@ -376,6 +593,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn media_keys_carry_extended_flag() {
+        for key in [KeyCode::PlayPause, KeyCode::VolumeUp, KeyCode::BrowserBack] {
+            let (_, flags) = keycode_to_vkcode(key).expect("expected a mapping");
+            assert_eq!(flags, EXTENDED, "{key:?} should carry EXTENDED flag");
+        }
+        assert_eq!(keycode_to_vkcode(KeyCode::BrightnessUp), None);
+    }
+
     #[test]
     fn navigation_keys_carry_extended_flag() {
         for key in [
@@ -1,23 +1,74 @@
 //! Windows platform backend: WH_KEYBOARD_LL / WH_MOUSE_LL capture, SendInput injection.
 //!
 //! M5 milestone. Factory functions return boxed trait objects backed by
-//! `WindowsCapture` (WH_KEYBOARD_LL) and `WindowsExecutor` (SendInput).
+//! `WindowsCapture` (WH_KEYBOARD_LL, and WH_MOUSE_LL for pointer events) and
+//! `WindowsExecutor` (SendInput). `RawInputCapture` (Raw Input) observes
+//! keys with the keyboard they came from instead, when the config asks.
 
 mod capture;
+mod clipboard;
+mod elevation;
 mod executor;
+mod focus;
 pub mod keycodes;
+mod modifiers;
+mod mouse;
+mod post;
+mod quirks;
+mod raw_input;
+mod session;
+mod slot;
+mod system;
+#[cfg(feature = "tray")]
+mod tray;
+mod watchdog;
 
 use capture::WindowsCapture;
+use clipboard::WindowsClipboard;
 use executor::WindowsExecutor;
+use raw_input::RawInputCapture;
+pub use session::watch_session;
+#[cfg(feature = "tray")]
+pub use tray::{attach_console, Tray, TrayCommand, TrayStatus};
 
-use crate::platform::{ActionExecutor, InputCapture, PlatformError};
+use crate::platform::{ActionExecutor, Clipboard, InputCapture, PlatformError};
 
-/// Returns a `WindowsCapture` backed by `WH_KEYBOARD_LL`.
-pub fn create_input_capture() -> Result<Box<dyn InputCapture>, PlatformError> {
-    Ok(Box::new(WindowsCapture::new()))
+/// `dwExtraInfo` of every event the executor sends, "PCUF". The hook passes
+/// events carrying it through, so injected keys are not captured again.
+const INJECTED_TAG: usize = 0x5043_5546;
+
+/// Returns a `WindowsCapture` backed by `WH_KEYBOARD_LL`, or with `pinned`
+/// set to `raw_input`, a `RawInputCapture`, which identifies keyboards but
+/// cannot suppress keys.
+///
+/// `pinned` is the `[capture] backend` config value; it must be `hook` or
+/// `raw_input` if set.
+pub fn create_input_capture(pinned: Option<&str>) -> Result<Box<dyn InputCapture>, PlatformError> {
+    match pinned {
+        None | Some("hook") => Ok(Box::new(WindowsCapture::new())),
+        Some("raw_input") => Ok(Box::new(RawInputCapture::new())),
+        Some(name) => Err(PlatformError::Unavailable(format!(
+            "capture backend '{name}' is not available on Windows (available: hook, raw_input)"
+        ))),
+    }
+}
+
+/// Returns a `WindowsExecutor` backed by `SendInput`, the only Windows backend.
+///
+/// `pinned` is the `[executor] backend` config value; it must be `sendinput`
+/// if set.
+pub fn create_action_executor(
+    pinned: Option<&str>,
+) -> Result<Box<dyn ActionExecutor>, PlatformError> {
+    match pinned {
+        None | Some("sendinput") => Ok(Box::new(WindowsExecutor::new())),
+        Some(name) => Err(PlatformError::Unavailable(format!(
+            "executor backend '{name}' is not available on Windows (available: sendinput)"
+        ))),
+    }
 }
 
-/// Returns a `WindowsExecutor` backed by `SendInput`.
-pub fn create_action_executor() -> Result<Box<dyn ActionExecutor>, PlatformError> {
-    Ok(Box::new(WindowsExecutor::new()))
+/// Returns a clipboard reader for `CF_UNICODETEXT`.
+pub fn create_clipboard() -> Box<dyn Clipboard> {
+    Box::new(WindowsClipboard)
 }
@@ -0,0 +1,205 @@
+//! Modifier state for Windows key events.
+//!
+//! `KBDLLHOOKSTRUCT` carries no modifier flags, and `GetKeyState` inside a
+//! low-level hook lags the event being hooked. The capture thread therefore
+//! follows the modifier keys' own Down and Up events: physical ones, those
+//! other programs inject, and those our executor injects, which pass through
+//! the hook as well. Each side of a modifier is tracked on its own, so
+//! releasing Left Shift while Right Shift is held leaves Shift on.
+//!
+//! `ModifierTracker::seed` reads the keys already held when the hook is
+//! installed, since their Down events came before it.
+
+use crate::platform::{HeldSides, Modifiers};
+
+/// Modifier virtual key codes and the bit each sets in `ModifierTracker`.
+/// The generic codes (VK_SHIFT, VK_CONTROL, VK_MENU), which some injecting
+/// programs send, count as the left side.
+const MODIFIER_BITS: [(u16, u8); 11] = [
+    (0xA0, LSHIFT), // VK_LSHIFT
+    (0xA1, RSHIFT), // VK_RSHIFT
+    (0x10, LSHIFT), // VK_SHIFT
+    (0xA2, LCTRL),  // VK_LCONTROL
+    (0xA3, RCTRL),  // VK_RCONTROL
+    (0x11, LCTRL),  // VK_CONTROL
+    (0xA4, LALT),   // VK_LMENU
+    (0xA5, RALT),   // VK_RMENU
+    (0x12, LALT),   // VK_MENU
+    (0x5B, LWIN),   // VK_LWIN
+    (0x5C, RWIN),   // VK_RWIN
+];
+
+const LSHIFT: u8 = 1 << 0;
+const RSHIFT: u8 = 1 << 1;
+const LCTRL: u8 = 1 << 2;
+const RCTRL: u8 = 1 << 3;
+const LALT: u8 = 1 << 4;
+const RALT: u8 = 1 << 5;
+const LWIN: u8 = 1 << 6;
+const RWIN: u8 = 1 << 7;
+
+/// The held modifier keys, one bit per side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ModifierTracker {
+    held: u8,
+}
+
+impl ModifierTracker {
+    /// A tracker with no modifier held.
+    pub(super) const fn new() -> Self {
+        Self { held: 0 }
+    }
+
+    /// A tracker holding the modifiers `is_down` reports for their
+    /// side-specific virtual key codes.
+    pub(super) fn seed(is_down: impl Fn(u16) -> bool) -> Self {
+        let held = MODIFIER_BITS
+            .iter()
+            .filter(|&&(vk, _)| !matches!(vk, 0x10..=0x12) && is_down(vk))
+            .fold(0, |held, &(_, bit)| held | bit);
+        Self { held }
+    }
+
+    /// Follow one key event. Keys other than modifiers are ignored.
+    pub(super) fn observe(&mut self, vk: u16, down: bool) {
+        let Some(&(_, bit)) = MODIFIER_BITS.iter().find(|&&(v, _)| v == vk) else {
+            return;
+        };
+        if down {
+            self.held |= bit;
+        } else {
+            self.held &= !bit;
+        }
+    }
+
+    pub(super) fn modifiers(&self) -> Modifiers {
+        Modifiers {
+            ctrl: self.held & (LCTRL | RCTRL) != 0,
+            shift: self.held & (LSHIFT | RSHIFT) != 0,
+            alt: self.held & (LALT | RALT) != 0,
+            meta: self.held & (LWIN | RWIN) != 0,
+            sides: Some(HeldSides {
+                left_ctrl: self.held & LCTRL != 0,
+                right_ctrl: self.held & RCTRL != 0,
+                left_shift: self.held & LSHIFT != 0,
+                right_shift: self.held & RSHIFT != 0,
+                left_alt: self.held & LALT != 0,
+                right_alt: self.held & RALT != 0,
+                left_meta: self.held & LWIN != 0,
+                right_meta: self.held & RWIN != 0,
+            }),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::KeyCode;
+
+    /// Run `(vk, down)` events through a fresh tracker.
+    fn tracked(events: &[(u16, bool)]) -> ModifierTracker {
+        let mut tracker = ModifierTracker::new();
+        for &(vk, down) in events {
+            tracker.observe(vk, down);
+        }
+        tracker
+    }
+
+    /// The unified modifiers after `events`.
+    fn after(events: &[(u16, bool)]) -> Modifiers {
+        Modifiers {
+            sides: None,
+            ..tracked(events).modifiers()
+        }
+    }
+
+    #[test]
+    fn sides_say_which_key_is_held() {
+        let modifiers = tracked(&[(0xA1, true), (0xA3, true), (0x11, true)]).modifiers();
+        assert_eq!(
+            modifiers.sides.map(HeldSides::keys),
+            Some(vec![
+                KeyCode::LeftCtrl,
+                KeyCode::RightCtrl,
+                KeyCode::RightShift
+            ])
+        );
+    }
+
+    #[test]
+    fn modifier_is_held_between_its_down_and_up() {
+        assert_eq!(
+            after(&[(0xA2, true), (0x41, true)]),
+            Modifiers {
+                ctrl: true,
+                ..Modifiers::default()
+            }
+        );
+        assert_eq!(after(&[(0xA2, true), (0xA2, false)]), Modifiers::default());
+    }
+
+    #[test]
+    fn modifier_stays_held_while_the_other_side_is() {
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(after(&[(0xA0, true), (0xA1, true), (0xA0, false)]), shift);
+        assert_eq!(
+            after(&[(0xA0, true), (0xA1, true), (0xA0, false), (0xA1, false)]),
+            Modifiers::default()
+        );
+    }
+
+    /// A wrapped chord from the executor presses and releases its own
+    /// modifiers around the key, and leaves the user's held.
+    #[test]
+    fn injected_chord_leaves_the_user_modifiers() {
+        let events = [
+            (0x5B, true),  // user: LWin
+            (0xA2, true),  // executor: LCtrl
+            (0x43, true),  // executor: C
+            (0x43, false), // executor: C
+            (0xA2, false), // executor: LCtrl
+        ];
+        assert_eq!(
+            after(&events),
+            Modifiers {
+                meta: true,
+                ..Modifiers::default()
+            }
+        );
+    }
+
+    #[test]
+    fn generic_codes_count_as_the_left_side() {
+        assert_eq!(
+            after(&[(0x12, true), (0x11, true), (0x12, false)]),
+            Modifiers {
+                ctrl: true,
+                ..Modifiers::default()
+            }
+        );
+        assert_eq!(after(&[(0x10, true), (0xA0, false)]), Modifiers::default());
+    }
+
+    #[test]
+    fn seed_reads_the_side_specific_codes() {
+        let tracker = ModifierTracker::seed(|vk| vk == 0xA5 || vk == 0x11);
+        assert_eq!(
+            Modifiers {
+                sides: None,
+                ..tracker.modifiers()
+            },
+            Modifiers {
+                alt: true,
+                ..Modifiers::default()
+            }
+        );
+    }
+}
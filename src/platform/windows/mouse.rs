@@ -0,0 +1,540 @@
+//! Pointer events: reading them in the WH_MOUSE_LL hook and sending them
+//! for the executor.
+//!
+//! Capture installs the mouse hook next to the keyboard hook, on the same
+//! message-loop thread, only when a pointer callback is set. Pointer events
+//! are reported and passed through unchanged; the engine does not remap them
+//! yet.
+//!
+//! Coordinates: `MSLLHOOKSTRUCT.pt` is in screen coordinates, which already
+//! match the global desktop space of `PointerEvent` (origin at the top-left
+//! of the primary display, y down). The hook reports positions only, so the
+//! motion deltas are the difference from the previous position, after
+//! pointer acceleration.
+//!
+//! Wheel: a delta that is a whole number of notches (`WHEEL_DELTA`, 120) is
+//! reported as discrete notches. High-resolution wheels and precision
+//! touchpads send smaller steps, which are reported as pixel scrolling, one
+//! pixel per wheel unit. Injection maps them back the same way.
+//!
+//! Injection: every pointer action is sent as `MOUSEINPUT` through
+//! `SendInput`, also when keys go to a target window. Moves are sent as
+//! absolute positions on the virtual desktop (`MOUSEEVENTF_ABSOLUTE |
+//! MOUSEEVENTF_VIRTUALDESK`, 0 to 65535 across the bounding box of all
+//! monitors), so a relative move is the current position plus the delta.
+//! A relative `MOUSEEVENTF_MOVE` would go through pointer acceleration and
+//! land somewhere else. Positions are physical pixels, as the hook reports
+//! them: the cursor and the desktop bounds are read with per-monitor DPI
+//! awareness, since a DPI-unaware caller gets them scaled to 96 DPI.
+//!
+//! Motion arrives at the polling rate of the mouse, up to 1000 events a
+//! second or more. Nothing on the path allocates, and with an interval set
+//! through `set_motion_coalescing`, `Coalescer` folds the moves within each
+//! interval into one.
+
+use std::time::{Duration, Instant};
+
+use windows_sys::Win32::Foundation::POINT;
+use windows_sys::Win32::UI::HiDpi::{
+    SetThreadDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+};
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    SendInput, INPUT, INPUT_0, INPUT_MOUSE, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_HWHEEL,
+    MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP,
+    MOUSEEVENTF_MOVE, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_VIRTUALDESK,
+    MOUSEEVENTF_WHEEL, MOUSEEVENTF_XDOWN, MOUSEEVENTF_XUP, MOUSEINPUT,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    GetCursorPos, GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+    SM_YVIRTUALSCREEN,
+};
+
+use super::INJECTED_TAG;
+use crate::platform::{Action, KeyState, MouseButton, PlatformError, PointerEvent};
+
+// ---------------------------------------------------------------------------
+// Constants
+// ---------------------------------------------------------------------------
+
+const WM_MOUSEMOVE: u32 = 0x0200;
+const WM_LBUTTONDOWN: u32 = 0x0201;
+const WM_LBUTTONUP: u32 = 0x0202;
+const WM_RBUTTONDOWN: u32 = 0x0204;
+const WM_RBUTTONUP: u32 = 0x0205;
+const WM_MBUTTONDOWN: u32 = 0x0207;
+const WM_MBUTTONUP: u32 = 0x0208;
+const WM_MOUSEWHEEL: u32 = 0x020A;
+const WM_XBUTTONDOWN: u32 = 0x020B;
+const WM_XBUTTONUP: u32 = 0x020C;
+const WM_MOUSEHWHEEL: u32 = 0x020E;
+
+/// `mouseData` high word of an X button message.
+const XBUTTON1: u16 = 1;
+const XBUTTON2: u16 = 2;
+
+/// Wheel delta of one notch.
+const WHEEL_DELTA: i32 = 120;
+
+/// Upper bound of absolute and normalized coordinates.
+const NORMALIZED_MAX: i64 = 65535;
+
+// ---------------------------------------------------------------------------
+// Decoding
+// ---------------------------------------------------------------------------
+
+/// Translate a hook message, or `None` for messages that carry nothing to
+/// report. `mouse_data` is `MSLLHOOKSTRUCT.mouseData`, and `last` the
+/// position of the previous move, which this updates.
+pub(super) fn decode(
+    message: u32,
+    mouse_data: u32,
+    x: i32,
+    y: i32,
+    last: &mut Option<(i32, i32)>,
+) -> Option<PointerEvent> {
+    let button = |button, state| Some(PointerEvent::Button { button, state });
+    let high = (mouse_data >> 16) as u16;
+    match message {
+        WM_MOUSEMOVE => {
+            let (dx, dy) = last.map_or((0, 0), |(lx, ly)| (x - lx, y - ly));
+            *last = Some((x, y));
+            Some(PointerEvent::Motion {
+                dx,
+                dy,
+                position: Some((x, y)),
+            })
+        }
+        WM_LBUTTONDOWN => button(MouseButton::Left, KeyState::Down),
+        WM_LBUTTONUP => button(MouseButton::Left, KeyState::Up),
+        WM_RBUTTONDOWN => button(MouseButton::Right, KeyState::Down),
+        WM_RBUTTONUP => button(MouseButton::Right, KeyState::Up),
+        WM_MBUTTONDOWN => button(MouseButton::Middle, KeyState::Down),
+        WM_MBUTTONUP => button(MouseButton::Middle, KeyState::Up),
+        WM_XBUTTONDOWN | WM_XBUTTONUP => {
+            let state = if message == WM_XBUTTONDOWN {
+                KeyState::Down
+            } else {
+                KeyState::Up
+            };
+            match high {
+                XBUTTON1 => button(MouseButton::Back, state),
+                XBUTTON2 => button(MouseButton::Forward, state),
+                _ => None,
+            }
+        }
+        WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
+            // Positive is away from the user, and tilted right.
+            let delta = i32::from(high as i16);
+            if delta == 0 {
+                return None;
+            }
+            let discrete = delta % WHEEL_DELTA == 0;
+            let amount = if discrete { delta / WHEEL_DELTA } else { delta };
+            let (dx, dy) = if message == WM_MOUSEWHEEL {
+                (0, amount)
+            } else {
+                (amount, 0)
+            };
+            Some(PointerEvent::Scroll { dx, dy, discrete })
+        }
+        _ => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Coalescing
+// ---------------------------------------------------------------------------
+
+/// Folds moves that arrive within `interval` of the last one delivered into
+/// a single move, with the summed deltas and the latest position.
+#[derive(Debug)]
+pub(super) struct Coalescer {
+    interval: Duration,
+    sent: Option<Instant>,
+    pending: Option<PointerEvent>,
+}
+
+impl Coalescer {
+    pub(super) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            sent: None,
+            pending: None,
+        }
+    }
+
+    /// Take a move; returns the move to deliver now, if the interval has
+    /// passed since the last one.
+    pub(super) fn push(&mut self, motion: PointerEvent, now: Instant) -> Option<PointerEvent> {
+        let merged = match (self.pending.take(), motion) {
+            (
+                Some(PointerEvent::Motion {
+                    dx: pdx, dy: pdy, ..
+                }),
+                PointerEvent::Motion { dx, dy, position },
+            ) => PointerEvent::Motion {
+                dx: pdx + dx,
+                dy: pdy + dy,
+                position,
+            },
+            _ => motion,
+        };
+        if self
+            .sent
+            .is_some_and(|sent| now.duration_since(sent) < self.interval)
+        {
+            self.pending = Some(merged);
+            return None;
+        }
+        self.sent = Some(now);
+        Some(merged)
+    }
+
+    /// The move held back, if any. Called on a timer, and before any other
+    /// pointer event so the order of events is kept.
+    pub(super) fn flush(&mut self, now: Instant) -> Option<PointerEvent> {
+        let pending = self.pending.take();
+        if pending.is_some() {
+            self.sent = Some(now);
+        }
+        pending
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Injection
+// ---------------------------------------------------------------------------
+
+/// The bounding box of all monitors, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Desktop {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Desktop {
+    /// Map a pixel position to 0..=65535 across the desktop, clamped to it.
+    fn normalize(&self, x: i32, y: i32) -> (i32, i32) {
+        let scale = |v: i32, lo: i32, len: i32| {
+            let span = i64::from(len.max(2) - 1);
+            let offset = (i64::from(v) - i64::from(lo)).clamp(0, span);
+            ((offset * NORMALIZED_MAX + span / 2) / span) as i32
+        };
+        (scale(x, self.x, self.width), scale(y, self.y, self.height))
+    }
+}
+
+fn mouse_input(dx: i32, dy: i32, mouse_data: i32, flags: u32) -> MOUSEINPUT {
+    MOUSEINPUT {
+        dx,
+        dy,
+        mouseData: mouse_data as u32,
+        dwFlags: flags,
+        time: 0,
+        dwExtraInfo: INJECTED_TAG,
+    }
+}
+
+/// The `MOUSEINPUT` records for a pointer action, or `None` for any other
+/// action. `cursor` is asked for the current position only for a relative
+/// move.
+fn mouse_inputs(
+    action: &Action,
+    desktop: &Desktop,
+    cursor: impl FnOnce() -> (i32, i32),
+) -> Option<Vec<MOUSEINPUT>> {
+    let absolute = MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK;
+    let inputs = match *action {
+        Action::MouseMoveRelative { dx, dy } => {
+            let (x, y) = cursor();
+            let (nx, ny) = desktop.normalize(x.saturating_add(dx), y.saturating_add(dy));
+            vec![mouse_input(nx, ny, 0, absolute)]
+        }
+        Action::MouseMoveAbsolute { x, y, normalized } => {
+            let (nx, ny) = if normalized {
+                let clamp = |v: i32| v.clamp(0, NORMALIZED_MAX as i32);
+                (clamp(x), clamp(y))
+            } else {
+                desktop.normalize(x, y)
+            };
+            vec![mouse_input(nx, ny, 0, absolute)]
+        }
+        Action::MouseButton { button, state } => {
+            let down = state == KeyState::Down;
+            let (flags, data) = match button {
+                MouseButton::Left if down => (MOUSEEVENTF_LEFTDOWN, 0),
+                MouseButton::Left => (MOUSEEVENTF_LEFTUP, 0),
+                MouseButton::Right if down => (MOUSEEVENTF_RIGHTDOWN, 0),
+                MouseButton::Right => (MOUSEEVENTF_RIGHTUP, 0),
+                MouseButton::Middle if down => (MOUSEEVENTF_MIDDLEDOWN, 0),
+                MouseButton::Middle => (MOUSEEVENTF_MIDDLEUP, 0),
+                MouseButton::Back | MouseButton::Forward => {
+                    let x = if button == MouseButton::Back {
+                        XBUTTON1
+                    } else {
+                        XBUTTON2
+                    };
+                    let flags = if down {
+                        MOUSEEVENTF_XDOWN
+                    } else {
+                        MOUSEEVENTF_XUP
+                    };
+                    (flags, i32::from(x))
+                }
+                MouseButton::Other(n) => {
+                    log::debug!("executor: Windows has no mouse button {n}, skipping");
+                    return Some(Vec::new());
+                }
+            };
+            vec![mouse_input(0, 0, data, flags)]
+        }
+        Action::Scroll { dx, dy, discrete } => {
+            let units = |v: i32| {
+                if discrete {
+                    v.saturating_mul(WHEEL_DELTA)
+                } else {
+                    v
+                }
+            };
+            let mut inputs = Vec::with_capacity(2);
+            if dy != 0 {
+                inputs.push(mouse_input(0, 0, units(dy), MOUSEEVENTF_WHEEL));
+            }
+            if dx != 0 {
+                inputs.push(mouse_input(0, 0, units(dx), MOUSEEVENTF_HWHEEL));
+            }
+            inputs
+        }
+        _ => return None,
+    };
+    Some(inputs)
+}
+
+/// Send a pointer action with `SendInput`. Returns `None` for any other
+/// action.
+pub(super) fn inject(action: &Action) -> Option<Result<(), PlatformError>> {
+    // Physical pixels for the cursor and the desktop, whatever the
+    // process's own awareness; restored before returning.
+    let previous =
+        unsafe { SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) };
+    let desktop = unsafe {
+        Desktop {
+            x: GetSystemMetrics(SM_XVIRTUALSCREEN),
+            y: GetSystemMetrics(SM_YVIRTUALSCREEN),
+            width: GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            height: GetSystemMetrics(SM_CYVIRTUALSCREEN),
+        }
+    };
+    let cursor = || {
+        let mut p = POINT { x: 0, y: 0 };
+        unsafe { GetCursorPos(&mut p) };
+        (p.x, p.y)
+    };
+    let inputs = mouse_inputs(action, &desktop, cursor);
+    if !previous.is_null() {
+        unsafe { SetThreadDpiAwarenessContext(previous) };
+    }
+
+    let inputs: Vec<INPUT> = inputs?
+        .into_iter()
+        .map(|mi| INPUT {
+            r#type: INPUT_MOUSE,
+            Anonymous: INPUT_0 { mi },
+        })
+        .collect();
+    if inputs.is_empty() {
+        return Some(Ok(()));
+    }
+    let sent = unsafe {
+        SendInput(
+            inputs.len() as u32,
+            inputs.as_ptr(),
+            std::mem::size_of::<INPUT>() as i32,
+        )
+    };
+    if sent as usize != inputs.len() {
+        return Some(Err(PlatformError::Other(format!(
+            "SendInput sent {sent} of {} mouse events",
+            inputs.len()
+        ))));
+    }
+    log::debug!("executor: injected {action:?}");
+    Some(Ok(()))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motion(dx: i32, dy: i32, x: i32, y: i32) -> PointerEvent {
+        PointerEvent::Motion {
+            dx,
+            dy,
+            position: Some((x, y)),
+        }
+    }
+
+    #[test]
+    fn messages_decode_to_pointer_events() {
+        let mut last = None;
+        assert_eq!(
+            decode(WM_MOUSEMOVE, 0, 10, 20, &mut last),
+            Some(motion(0, 0, 10, 20))
+        );
+        assert_eq!(
+            decode(WM_MOUSEMOVE, 0, 7, 25, &mut last),
+            Some(motion(-3, 5, 7, 25))
+        );
+        assert_eq!(
+            decode(WM_XBUTTONUP, u32::from(XBUTTON2) << 16, 0, 0, &mut last),
+            Some(PointerEvent::Button {
+                button: MouseButton::Forward,
+                state: KeyState::Up,
+            })
+        );
+        assert_eq!(
+            decode(WM_RBUTTONDOWN, 0, 0, 0, &mut last),
+            Some(PointerEvent::Button {
+                button: MouseButton::Right,
+                state: KeyState::Down,
+            })
+        );
+        assert_eq!(last, Some((7, 25)));
+    }
+
+    #[test]
+    fn wheel_reports_notches_or_fine_steps() {
+        let mut last = None;
+        let wheel = |delta: i16| u32::from(delta as u16) << 16;
+        assert_eq!(
+            decode(WM_MOUSEWHEEL, wheel(-240), 0, 0, &mut last),
+            Some(PointerEvent::Scroll {
+                dx: 0,
+                dy: -2,
+                discrete: true,
+            })
+        );
+        assert_eq!(
+            decode(WM_MOUSEHWHEEL, wheel(30), 0, 0, &mut last),
+            Some(PointerEvent::Scroll {
+                dx: 30,
+                dy: 0,
+                discrete: false,
+            })
+        );
+        assert_eq!(decode(WM_MOUSEWHEEL, 0, 0, 0, &mut last), None);
+    }
+
+    /// Two 1920x1080 monitors, the second left of the primary.
+    const DESKTOP: Desktop = Desktop {
+        x: -1920,
+        y: 0,
+        width: 3840,
+        height: 1080,
+    };
+
+    fn sent(action: Action) -> Vec<(i32, i32, u32, u32)> {
+        mouse_inputs(&action, &DESKTOP, || (100, 200))
+            .unwrap()
+            .into_iter()
+            .map(|mi| (mi.dx, mi.dy, mi.mouseData, mi.dwFlags))
+            .collect()
+    }
+
+    #[test]
+    fn moves_are_absolute_on_the_virtual_desktop() {
+        let absolute = MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK;
+        let corners = [
+            (-1920, 0, 0, 0),
+            (1919, 1079, 65535, 65535),
+            (5000, -5, 65535, 0),
+        ];
+        for (x, y, nx, ny) in corners {
+            let action = Action::MouseMoveAbsolute {
+                x,
+                y,
+                normalized: false,
+            };
+            assert_eq!(sent(action), [(nx, ny, 0, absolute)]);
+        }
+        // The primary's origin sits halfway across.
+        let (nx, _) = DESKTOP.normalize(0, 0);
+        assert!((32760..=32775).contains(&nx));
+
+        let relative = Action::MouseMoveRelative { dx: -100, dy: -200 };
+        assert_eq!(
+            sent(relative),
+            sent(Action::MouseMoveAbsolute {
+                x: 0,
+                y: 0,
+                normalized: false,
+            })
+        );
+        let normalized = Action::MouseMoveAbsolute {
+            x: 70000,
+            y: 10,
+            normalized: true,
+        };
+        assert_eq!(sent(normalized), [(65535, 10, 0, absolute)]);
+    }
+
+    #[test]
+    fn buttons_and_wheel_build_mouse_inputs() {
+        let button = |button, state| Action::MouseButton { button, state };
+        assert_eq!(
+            sent(button(MouseButton::Right, KeyState::Up)),
+            [(0, 0, 0, MOUSEEVENTF_RIGHTUP)]
+        );
+        assert_eq!(
+            sent(button(MouseButton::Forward, KeyState::Down)),
+            [(0, 0, u32::from(XBUTTON2), MOUSEEVENTF_XDOWN)]
+        );
+        let scroll = Action::Scroll {
+            dx: 1,
+            dy: -2,
+            discrete: true,
+        };
+        assert_eq!(
+            sent(scroll),
+            [
+                (0, 0, -240i32 as u32, MOUSEEVENTF_WHEEL),
+                (0, 0, 120, MOUSEEVENTF_HWHEEL),
+            ]
+        );
+        let fine = Action::Scroll {
+            dx: 0,
+            dy: 30,
+            discrete: false,
+        };
+        assert_eq!(sent(fine), [(0, 0, 30, MOUSEEVENTF_WHEEL)]);
+        assert!(mouse_inputs(&Action::Suppress, &DESKTOP, || (0, 0)).is_none());
+    }
+
+    #[test]
+    fn coalescer_folds_moves_within_the_interval() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut coalescer = Coalescer::new(Duration::from_millis(10));
+
+        assert_eq!(
+            coalescer.push(motion(1, 1, 1, 1), ms(0)),
+            Some(motion(1, 1, 1, 1))
+        );
+        assert_eq!(coalescer.push(motion(2, 0, 3, 1), ms(4)), None);
+        assert_eq!(coalescer.push(motion(1, -1, 4, 0), ms(8)), None);
+        assert_eq!(
+            coalescer.push(motion(1, 0, 5, 0), ms(12)),
+            Some(motion(4, -1, 5, 0))
+        );
+
+        assert_eq!(coalescer.push(motion(1, 0, 6, 0), ms(14)), None);
+        assert_eq!(coalescer.flush(ms(15)), Some(motion(1, 0, 6, 0)));
+        assert_eq!(coalescer.flush(ms(16)), None);
+    }
+}
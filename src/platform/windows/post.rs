@@ -0,0 +1,246 @@
+//! Targeted injection: posting keyboard messages to one window.
+//!
+//! With `[executor] target` set, `WindowsExecutor` posts `WM_KEYDOWN`,
+//! `WM_KEYUP`, and `WM_CHAR` messages to the first visible top-level window
+//! the selector matches, instead of calling `SendInput`. Keys then reach that
+//! window whether or not it has focus.
+//!
+//! Caveat: a posted message is not real input, and many applications notice.
+//!   - Keyboard state (`GetKeyState`, `GetAsyncKeyState`) is not updated, so
+//!     an application that checks it for modifiers sees no Ctrl or Shift
+//!     held, and shortcuts such as Ctrl+S may not fire.
+//!   - Applications that read input through Raw Input or DirectInput (most
+//!     games) see nothing.
+//!   - System shortcuts (Alt+Tab, Win combinations) are never triggered.
+//!
+//! The window handle is cached and looked up again once the window is gone,
+//! so a target that is closed and reopened is found again.
+
+use std::cell::Cell;
+
+use windows_sys::Win32::Foundation::{BOOL, HWND, LPARAM};
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{VK_LMENU, VK_MENU, VK_RMENU};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    EnumWindows, GetClassNameW, GetWindowTextW, IsWindow, IsWindowVisible, PostMessageW, WM_CHAR,
+    WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+};
+
+use crate::platform::{KeyState, PlatformError, WindowTarget};
+
+/// Longest window title or class name read, in UTF-16 units.
+const MAX_TEXT: usize = 512;
+
+// ---------------------------------------------------------------------------
+// Target
+// ---------------------------------------------------------------------------
+
+/// The window injected input is posted to.
+pub(super) struct PostTarget {
+    selector: WindowTarget,
+    /// Cached handle as an integer (HWND is not `Send`); 0 when unresolved.
+    hwnd: Cell<isize>,
+    /// Whether an Alt key posted to the window is down, which turns key
+    /// messages into their `WM_SYS*` forms.
+    alt_held: Cell<bool>,
+}
+
+impl PostTarget {
+    pub(super) fn new(selector: WindowTarget) -> Self {
+        Self {
+            selector,
+            hwnd: Cell::new(0),
+            alt_held: Cell::new(false),
+        }
+    }
+
+    /// Post one key transition for virtual key `vk`.
+    pub(super) fn post_key(
+        &self,
+        vk: u16,
+        scan: u16,
+        extended: bool,
+        state: KeyState,
+    ) -> Result<(), PlatformError> {
+        if matches!(vk, VK_MENU | VK_LMENU | VK_RMENU) && state == KeyState::Down {
+            self.alt_held.set(true);
+        }
+        let (msg, wparam, lparam) = key_message(vk, scan, extended, state, self.alt_held.get());
+        if matches!(vk, VK_MENU | VK_LMENU | VK_RMENU) && state == KeyState::Up {
+            self.alt_held.set(false);
+        }
+        self.post(msg, wparam, lparam)
+    }
+
+    /// Post `c` as `WM_CHAR` messages, one per UTF-16 unit.
+    pub(super) fn post_char(&self, c: char) -> Result<(), PlatformError> {
+        for unit in crate::platform::utf16_units(c) {
+            self.post(WM_CHAR, unit as usize, 1)?;
+        }
+        Ok(())
+    }
+
+    fn post(&self, msg: u32, wparam: usize, lparam: isize) -> Result<(), PlatformError> {
+        let hwnd = self.window()?;
+        // SAFETY: PostMessageW copies its arguments; a stale handle fails
+        // with an error rather than touching memory.
+        if unsafe { PostMessageW(hwnd, msg, wparam, lparam) } == 0 {
+            self.hwnd.set(0);
+            return Err(PlatformError::io(
+                "PostMessageW to target window failed",
+                std::io::Error::last_os_error(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The cached window if it still exists, otherwise a fresh lookup.
+    fn window(&self) -> Result<HWND, PlatformError> {
+        let cached = self.hwnd.get() as HWND;
+        if !cached.is_null() && unsafe { IsWindow(cached) } != 0 {
+            return Ok(cached);
+        }
+        let found = find_window(&self.selector, top_level_windows()).ok_or_else(|| {
+            PlatformError::Unavailable(format!(
+                "no window matches [executor] target {:?}",
+                self.selector
+            ))
+        })?;
+        log::info!("executor: posting input to window {found:#x}");
+        self.hwnd.set(found);
+        Ok(found as HWND)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Window lookup
+// ---------------------------------------------------------------------------
+
+/// The first window, in enumeration (z-)order, that `selector` matches.
+fn find_window(
+    selector: &WindowTarget,
+    windows: impl IntoIterator<Item = (isize, String, String)>,
+) -> Option<isize> {
+    windows
+        .into_iter()
+        .find(|(_, title, class)| selector.matches(title, class))
+        .map(|(hwnd, _, _)| hwnd)
+}
+
+/// Handle, title, and class of every visible top-level window.
+fn top_level_windows() -> Vec<(isize, String, String)> {
+    unsafe extern "system" fn collect(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let windows = &mut *(lparam as *mut Vec<(isize, String, String)>);
+        if IsWindowVisible(hwnd) != 0 {
+            let mut buf = [0u16; MAX_TEXT];
+            let len = GetWindowTextW(hwnd, buf.as_mut_ptr(), MAX_TEXT as i32);
+            let title = String::from_utf16_lossy(&buf[..len.max(0) as usize]);
+            let len = GetClassNameW(hwnd, buf.as_mut_ptr(), MAX_TEXT as i32);
+            let class = String::from_utf16_lossy(&buf[..len.max(0) as usize]);
+            windows.push((hwnd as isize, title, class));
+        }
+        1
+    }
+
+    let mut windows: Vec<(isize, String, String)> = Vec::new();
+    // SAFETY: `collect` only runs during this call, while `windows` is alive.
+    unsafe { EnumWindows(Some(collect), &mut windows as *mut _ as LPARAM) };
+    windows
+}
+
+// ---------------------------------------------------------------------------
+// Message construction
+// ---------------------------------------------------------------------------
+
+/// Message, `wParam`, and `lParam` for one key transition, laid out as the
+/// system would deliver them.
+///
+/// `lParam` bits: 0-15 repeat count (always 1), 16-23 scan code, 24 extended
+/// key, 29 Alt held, 30 previous key state, 31 transition (set on release).
+fn key_message(
+    vk: u16,
+    scan: u16,
+    extended: bool,
+    state: KeyState,
+    alt: bool,
+) -> (u32, usize, isize) {
+    let msg = match (state, alt) {
+        (KeyState::Down, false) => WM_KEYDOWN,
+        (KeyState::Up, false) => WM_KEYUP,
+        (KeyState::Down, true) => WM_SYSKEYDOWN,
+        (KeyState::Up, true) => WM_SYSKEYUP,
+    };
+    let mut bits: u32 = 1 | (u32::from(scan & 0xFF) << 16);
+    if extended {
+        bits |= 1 << 24;
+    }
+    if alt {
+        bits |= 1 << 29;
+    }
+    if state == KeyState::Up {
+        bits |= (1 << 30) | (1 << 31);
+    }
+    // Sign-extended, as LPARAM values from the system are.
+    (msg, vk as usize, bits as i32 as isize)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(hwnd: isize, title: &str, class: &str) -> (isize, String, String) {
+        (hwnd, title.to_owned(), class.to_owned())
+    }
+
+    #[test]
+    fn first_matching_window_is_chosen() {
+        let selector = WindowTarget {
+            title: Some("notepad".into()),
+            class: None,
+        };
+        let windows = vec![
+            window(1, "Inbox - Outlook", "rctrl_renwnd32"),
+            window(2, "a.txt - Notepad", "Notepad"),
+            window(3, "b.txt - Notepad", "Notepad"),
+        ];
+        assert_eq!(find_window(&selector, windows), Some(2));
+    }
+
+    #[test]
+    fn no_matching_window_is_none() {
+        let selector = WindowTarget {
+            title: None,
+            class: Some("Notepad".into()),
+        };
+        assert_eq!(
+            find_window(&selector, vec![window(1, "Paint", "MSPaintApp")]),
+            None
+        );
+    }
+
+    #[test]
+    fn key_down_and_up_lparam_layout() {
+        // 'A': VK 0x41, scan 0x1E.
+        let (msg, wparam, lparam) = key_message(0x41, 0x1E, false, KeyState::Down, false);
+        assert_eq!((msg, wparam), (WM_KEYDOWN, 0x41));
+        assert_eq!(lparam, 0x001E_0001);
+
+        let (msg, _, lparam) = key_message(0x41, 0x1E, false, KeyState::Up, false);
+        assert_eq!(msg, WM_KEYUP);
+        assert_eq!(lparam, 0xC01E_0001_u32 as i32 as isize);
+    }
+
+    #[test]
+    fn extended_and_alt_bits() {
+        // Right arrow: VK 0x27, scan 0x4D, extended.
+        let (_, _, lparam) = key_message(0x27, 0x4D, true, KeyState::Down, false);
+        assert_eq!(lparam, 0x014D_0001);
+
+        let (msg, _, lparam) = key_message(0x46, 0x21, false, KeyState::Down, true);
+        assert_eq!(msg, WM_SYSKEYDOWN);
+        assert_eq!(lparam, 0x2021_0001);
+    }
+}
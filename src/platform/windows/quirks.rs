@@ -0,0 +1,316 @@
+//! Keys whose low-level events break the usual one-key, Down-then-Up pattern.
+//!
+//! Capture (`CaptureQuirks`):
+//!   - Autorepeat arrives as another Down, with nothing to tell it from a
+//!     fresh press. A Down for a key already down is marked as a repeat.
+//!   - Ctrl+Pause is reported as `VK_CANCEL` (Break), scan code `E0 46`,
+//!     instead of `VK_PAUSE`. It is captured as Pause; the held Ctrl is in
+//!     the event's modifiers as usual.
+//!   - PrintScreen: Windows often consumes the Down for its own screenshot
+//!     handling, so the hook sees only the Up. An Up without a Down is
+//!     delivered after a Down made up for it, so rules see a normal press.
+//!
+//! Injection (`InjectQuirks`):
+//!   - Pause with Ctrl held is sent as `VK_CANCEL` with the Break scan code,
+//!     as the keyboard sends it; applications watching for Ctrl+Break look
+//!     for that, not for Pause. The Up goes the same way as its Down, even
+//!     if Ctrl was let go in between.
+//!   - PrintScreen is sent with its `E0 37` scan code, or as SysRq (`54`)
+//!     with Alt held, which is what Alt+PrintScreen produces.
+//!
+//! Pause itself needs nothing: its key sends the whole Down and Up on
+//! press, so the hook sees an ordinary tap.
+
+use windows_sys::Win32::UI::WindowsAndMessaging::{KBDLLHOOKSTRUCT, LLKHF_EXTENDED};
+
+use super::keycodes::{vkcode_to_keycode, EXTENDED};
+use crate::platform::{KeyCode, KeyState};
+
+const VK_CANCEL: u16 = 0x03;
+const VK_PAUSE: u16 = 0x13;
+const VK_SNAPSHOT: u16 = 0x2C;
+
+/// Scan codes, without their `E0` prefix.
+const SCAN_PAUSE: u16 = 0x45;
+const SCAN_BREAK: u16 = 0x46;
+const SCAN_PRINT_SCREEN: u16 = 0x37;
+const SCAN_SYSRQ: u16 = 0x54;
+
+// ---------------------------------------------------------------------------
+// Capture
+// ---------------------------------------------------------------------------
+
+/// A hook event's key, and whether a Down must be delivered before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Translated {
+    pub key: KeyCode,
+    /// The event is the Up of a press whose Down never arrived.
+    pub missing_down: bool,
+    /// The event is a Down for a key already down: autorepeat.
+    pub is_repeat: bool,
+}
+
+/// The virtual keys currently down, to tell autorepeat from a press. A key
+/// whose Up is never seen counts as held until its next Up.
+#[derive(Debug, Default)]
+pub(super) struct HeldKeys {
+    bits: [u64; 4],
+}
+
+impl HeldKeys {
+    pub(super) const fn new() -> Self {
+        Self { bits: [0; 4] }
+    }
+
+    /// Note virtual key `vk` going to `state`. True for a Down of a key
+    /// already down.
+    pub(super) fn repeat(&mut self, vk: u16, state: KeyState) -> bool {
+        let (word, bit) = (usize::from(vk >> 6) & 3, 1u64 << (vk & 63));
+        let held = self.bits[word] & bit != 0;
+        match state {
+            KeyState::Down => self.bits[word] |= bit,
+            KeyState::Up => self.bits[word] &= !bit,
+        }
+        held && state == KeyState::Down
+    }
+}
+
+/// Capture-side state, kept on the hook thread.
+#[derive(Debug, Default)]
+pub(super) struct CaptureQuirks {
+    print_screen_down: bool,
+    held: HeldKeys,
+}
+
+impl CaptureQuirks {
+    pub(super) const fn new() -> Self {
+        Self {
+            print_screen_down: false,
+            held: HeldKeys::new(),
+        }
+    }
+
+    /// The key for the hook event `kb`, whose state is `state`. `None` for
+    /// a virtual key with no `KeyCode`.
+    pub(super) fn translate(
+        &mut self,
+        kb: &KBDLLHOOKSTRUCT,
+        state: KeyState,
+    ) -> Option<Translated> {
+        let vk = kb.vkCode as u16;
+        let key = match vk {
+            VK_CANCEL => KeyCode::Pause,
+            _ => vkcode_to_keycode(vk, kb.flags & LLKHF_EXTENDED != 0)?,
+        };
+        let mut missing_down = false;
+        if key == KeyCode::PrintScreen {
+            missing_down = state == KeyState::Up && !self.print_screen_down;
+            self.print_screen_down = state == KeyState::Down;
+        }
+        let is_repeat = self.held.repeat(vk, state);
+        Some(Translated {
+            key,
+            missing_down,
+            is_repeat,
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Injection
+// ---------------------------------------------------------------------------
+
+/// What to send for a key: virtual key, scan code, and `EXTENDED` or 0.
+pub(super) type KeyInput = (u16, u16, u32);
+
+/// Injection-side state, kept by the executor.
+#[derive(Debug, Default)]
+pub(super) struct InjectQuirks {
+    break_down: bool,
+}
+
+impl InjectQuirks {
+    /// What to send for `key` going to `state` with Ctrl and Alt as given,
+    /// for the keys that need more than the `keycodes` table. `None` for
+    /// every other key.
+    pub(super) fn resolve(
+        &mut self,
+        key: KeyCode,
+        state: KeyState,
+        ctrl: bool,
+        alt: bool,
+    ) -> Option<KeyInput> {
+        match key {
+            KeyCode::Pause => {
+                let as_break = match state {
+                    KeyState::Down => ctrl,
+                    KeyState::Up => self.break_down,
+                };
+                self.break_down = state == KeyState::Down && as_break;
+                Some(if as_break {
+                    (VK_CANCEL, SCAN_BREAK, EXTENDED)
+                } else {
+                    (VK_PAUSE, SCAN_PAUSE, 0)
+                })
+            }
+            KeyCode::PrintScreen if alt => Some((VK_SNAPSHOT, SCAN_SYSRQ, 0)),
+            KeyCode::PrintScreen => Some((VK_SNAPSHOT, SCAN_PRINT_SCREEN, EXTENDED)),
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{LLKHF_ALTDOWN, LLKHF_UP};
+
+    use super::*;
+
+    /// `vkCode`, `scanCode`, and `flags` of a recorded hook event.
+    type Fixture = (u32, u32, u32);
+
+    /// A name, the hook events, and the key events they should become.
+    type Case = (
+        &'static str,
+        &'static [Fixture],
+        &'static [(KeyCode, KeyState)],
+    );
+
+    fn hooked(fixtures: &[Fixture]) -> Vec<(KeyCode, KeyState)> {
+        let mut quirks = CaptureQuirks::new();
+        let mut events = Vec::new();
+        for &(vk, scan, flags) in fixtures {
+            let kb = KBDLLHOOKSTRUCT {
+                vkCode: vk,
+                scanCode: scan,
+                flags,
+                time: 0,
+                dwExtraInfo: 0,
+            };
+            let state = if flags & LLKHF_UP != 0 {
+                KeyState::Up
+            } else {
+                KeyState::Down
+            };
+            let Some(t) = quirks.translate(&kb, state) else {
+                continue;
+            };
+            if t.missing_down {
+                events.push((t.key, KeyState::Down));
+            }
+            events.push((t.key, state));
+        }
+        events
+    }
+
+    #[test]
+    fn captured_sequences() {
+        use KeyCode::{LeftAlt, LeftCtrl, Pause, PrintScreen};
+        use KeyState::{Down, Up};
+
+        let cases: &[Case] = &[
+            (
+                "pause",
+                &[(0x13, 0x45, 0), (0x13, 0x45, LLKHF_UP)],
+                &[(Pause, Down), (Pause, Up)],
+            ),
+            (
+                "ctrl+pause reports VK_CANCEL",
+                &[
+                    (0xA2, 0x1D, 0),
+                    (0x03, 0x46, LLKHF_EXTENDED),
+                    (0x03, 0x46, LLKHF_EXTENDED | LLKHF_UP),
+                    (0xA2, 0x1D, LLKHF_UP),
+                ],
+                &[(LeftCtrl, Down), (Pause, Down), (Pause, Up), (LeftCtrl, Up)],
+            ),
+            (
+                "print screen with only an up",
+                &[(0x2C, 0x37, LLKHF_EXTENDED | LLKHF_UP)],
+                &[(PrintScreen, Down), (PrintScreen, Up)],
+            ),
+            (
+                "a full print screen press, then an up alone",
+                &[
+                    (0x2C, 0x37, LLKHF_EXTENDED),
+                    (0x2C, 0x37, LLKHF_EXTENDED | LLKHF_UP),
+                    (0x2C, 0x37, LLKHF_EXTENDED | LLKHF_UP),
+                ],
+                &[
+                    (PrintScreen, Down),
+                    (PrintScreen, Up),
+                    (PrintScreen, Down),
+                    (PrintScreen, Up),
+                ],
+            ),
+            (
+                "alt+print screen reports SysRq",
+                &[
+                    (0xA4, 0x38, LLKHF_ALTDOWN),
+                    (0x2C, 0x54, LLKHF_ALTDOWN),
+                    (0x2C, 0x54, LLKHF_ALTDOWN | LLKHF_UP),
+                    (0xA4, 0x38, LLKHF_UP),
+                ],
+                &[
+                    (LeftAlt, Down),
+                    (PrintScreen, Down),
+                    (PrintScreen, Up),
+                    (LeftAlt, Up),
+                ],
+            ),
+        ];
+        for (name, fixtures, expected) in cases {
+            assert_eq!(hooked(fixtures), *expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn a_second_down_is_a_repeat() {
+        let mut held = HeldKeys::new();
+        assert!(!held.repeat(0x41, KeyState::Down));
+        assert!(held.repeat(0x41, KeyState::Down));
+        assert!(!held.repeat(0xA2, KeyState::Down));
+        assert!(!held.repeat(0x41, KeyState::Up));
+        assert!(!held.repeat(0x41, KeyState::Down));
+        // Keys are told apart across the whole virtual key range.
+        assert!(!held.repeat(0xFE, KeyState::Down));
+        assert!(held.repeat(0xA2, KeyState::Down));
+    }
+
+    #[test]
+    fn injected_inputs() {
+        let mut quirks = InjectQuirks::default();
+        let mut send = |key, state, ctrl, alt| quirks.resolve(key, state, ctrl, alt);
+
+        assert_eq!(
+            send(KeyCode::Pause, KeyState::Down, false, false),
+            Some((VK_PAUSE, SCAN_PAUSE, 0))
+        );
+        assert_eq!(
+            send(KeyCode::Pause, KeyState::Up, true, false),
+            Some((VK_PAUSE, SCAN_PAUSE, 0))
+        );
+        // Break's Up matches its Down after Ctrl is released.
+        assert_eq!(
+            send(KeyCode::Pause, KeyState::Down, true, false),
+            Some((VK_CANCEL, SCAN_BREAK, EXTENDED))
+        );
+        assert_eq!(
+            send(KeyCode::Pause, KeyState::Up, false, false),
+            Some((VK_CANCEL, SCAN_BREAK, EXTENDED))
+        );
+        assert_eq!(
+            send(KeyCode::PrintScreen, KeyState::Down, false, false),
+            Some((VK_SNAPSHOT, SCAN_PRINT_SCREEN, EXTENDED))
+        );
+        assert_eq!(
+            send(KeyCode::PrintScreen, KeyState::Down, false, true),
+            Some((VK_SNAPSHOT, SCAN_SYSRQ, 0))
+        );
+        assert_eq!(send(KeyCode::A, KeyState::Down, true, true), None);
+    }
+}
@@ -0,0 +1,562 @@
+//! Windows keyboard capture via Raw Input (`WM_INPUT`), for keyboard
+//! identity.
+//!
+//! `RawInputCapture` implements `InputCapture` as an alternative to
+//! `WindowsCapture`, picked with `[capture] backend = "raw_input"`.
+//! `start()` spawns a thread that creates a message-only window and
+//! registers it for keyboard Raw Input with `RIDEV_INPUTSINK`, so input
+//! arrives whichever window has focus. Every `WM_INPUT` names the device
+//! that produced it, which `WH_KEYBOARD_LL` never does, so events carry a
+//! `DeviceId`.
+//!
+//! Observation only: Raw Input cannot suppress a key, so every event is
+//! delivered with `observed` set, and the original reaches applications
+//! whatever the rules do. The backend cannot be paired with the low-level
+//! hook to suppress by device either. Windows calls the hook before it
+//! hands the key to Raw Input, and a key the hook suppresses never reaches
+//! Raw Input at all, so the device is only known once the key has gone
+//! through. Rules that add to a key (hotkeys that run commands, chords of
+//! unused keys) work per device; a remap types its target next to the
+//! original key.
+//!
+//! Identity: keyboards with a USB or Bluetooth vendor and product id get
+//! `vendor:product` in hex, such as `046d:c52b`, as on macOS. Keyboards
+//! enumerated by ACPI, the built-in keyboard of a laptop, get `built-in`.
+//! Anything else is named by its device interface path. Names come from
+//! the HID product string, read through the device path that
+//! `GetRawInputDeviceInfoW` reports. `RIDEV_DEVNOTIFY` reports keyboards
+//! as they connect and disconnect.
+//!
+//! Keys the executor injects carry `INJECTED_TAG` in `ExtraInformation` and
+//! are not reported, but still update the modifier state, as in `capture`.
+//! Keys injected by other programs have no device and are reported without
+//! one. Pointer events are not captured by this backend.
+//!
+//! Window context, elevation, and the tray icon are handled on this thread
+//! as on the hook thread (see `capture`).
+
+use std::collections::HashMap;
+use std::ptr;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use windows_sys::Win32::Devices::HumanInterfaceDevice::HidD_GetProductString;
+use windows_sys::Win32::Foundation::{
+    CloseHandle, HANDLE, HWND, INVALID_HANDLE_VALUE, LPARAM, LRESULT, WPARAM,
+};
+use windows_sys::Win32::Storage::FileSystem::{
+    CreateFileW, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::GetAsyncKeyState;
+use windows_sys::Win32::UI::Input::{
+    GetRawInputData, GetRawInputDeviceInfoW, RegisterRawInputDevices, HRAWINPUT, RAWINPUT,
+    RAWINPUTDEVICE, RAWINPUTHEADER, RIDEV_DEVNOTIFY, RIDEV_INPUTSINK, RIDEV_REMOVE,
+    RIDI_DEVICENAME, RID_INPUT, RIM_TYPEKEYBOARD,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW,
+    PostThreadMessageW, RegisterClassW, GIDC_ARRIVAL, GIDC_REMOVAL, HWND_MESSAGE, MSG,
+    RI_KEY_BREAK, RI_KEY_E0, WM_INPUT, WM_INPUT_DEVICE_CHANGE, WM_QUIT, WNDCLASSW,
+};
+
+use super::focus::{self, FocusHooks};
+use super::keycodes::{scancode_to_position, vkcode_to_keycode};
+use super::modifiers::ModifierTracker;
+use super::quirks::HeldKeys;
+#[cfg(feature = "tray")]
+use super::tray::{Tray, TrayIcon};
+use super::INJECTED_TAG;
+use crate::platform::{
+    DeviceId, InputCapture as InputCaptureTrait, InputEvent as PlatformInputEvent, KeyState,
+    PlatformError, Suspension, WindowContext,
+};
+
+/// HID usage page and usage of keyboards.
+const HID_PAGE_GENERIC_DESKTOP: u16 = 0x01;
+const HID_USAGE_KEYBOARD: u16 = 0x06;
+
+/// The `VKey` Raw Input reports for the fake prefix keys some keyboards
+/// send around others.
+const VK_FAKE: u16 = 0xFF;
+
+/// Id shared by all built-in keyboards.
+const BUILT_IN_ID: &str = "built-in";
+
+// ---------------------------------------------------------------------------
+// Process-global state
+// ---------------------------------------------------------------------------
+
+type Callback = Box<dyn Fn(PlatformInputEvent) + Send>;
+
+/// What the window proc needs between messages.
+struct RawState {
+    callback: Callback,
+    modifiers: ModifierTracker,
+    /// Keys down, to mark autorepeat, which Raw Input reports as a Down.
+    held: HeldKeys,
+    /// Keyboards seen so far, by device handle.
+    devices: HashMap<usize, DeviceId>,
+}
+
+/// The running capture's state. Window procs receive no `user_info` unless
+/// set up for it, so it lives in a global, like the hook state in
+/// `capture`. Only the capture thread touches it while running.
+static STATE: Mutex<Option<RawState>> = Mutex::new(None);
+
+// ---------------------------------------------------------------------------
+// Public struct
+// ---------------------------------------------------------------------------
+
+/// Windows keyboard capture backend using Raw Input. Observes only.
+pub struct RawInputCapture {
+    /// Thread ID of the message-loop thread; used for `PostThreadMessageW`.
+    thread_id: u32,
+    thread: Option<JoinHandle<()>>,
+    /// Set while the foreground window is out of reach.
+    suspension: Suspension,
+    /// `--allow-uiaccess`: warn if the token has no UIAccess.
+    expect_ui_access: bool,
+    /// Set before `start`; moved to the focus tracker on the capture thread.
+    focus_callback: Option<Box<dyn Fn(WindowContext) + Send>>,
+    /// Set before `start`; moved to the capture thread, which shows it.
+    #[cfg(feature = "tray")]
+    tray: Option<Tray>,
+}
+
+impl RawInputCapture {
+    pub fn new() -> Self {
+        Self {
+            thread_id: 0,
+            thread: None,
+            suspension: Suspension::default(),
+            expect_ui_access: false,
+            focus_callback: None,
+            #[cfg(feature = "tray")]
+            tray: None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// InputCapture trait impl
+// ---------------------------------------------------------------------------
+
+impl InputCaptureTrait for RawInputCapture {
+    fn start(&mut self, callback: Callback) -> Result<(), PlatformError> {
+        if self.thread.is_some() {
+            return Err(PlatformError::Other("capture is already running".into()));
+        }
+        {
+            let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+            if state.is_some() {
+                return Err(PlatformError::Other("another capture is active".into()));
+            }
+            *state = Some(RawState {
+                callback,
+                modifiers: ModifierTracker::seed(|vk| unsafe {
+                    GetAsyncKeyState(i32::from(vk)) < 0
+                }),
+                held: HeldKeys::new(),
+                devices: HashMap::new(),
+            });
+        }
+
+        let suspension = self.suspension.clone();
+        let expect_ui_access = self.expect_ui_access;
+        let on_focus = self.focus_callback.take();
+        #[cfg(feature = "tray")]
+        let tray = self.tray.take();
+
+        let (info_tx, info_rx) = mpsc::channel::<Result<u32, PlatformError>>();
+        let thread = thread::spawn(move || {
+            let hwnd = match create_window() {
+                Ok(hwnd) => hwnd,
+                Err(e) => {
+                    let _ = info_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = info_tx.send(Ok(unsafe { GetCurrentThreadId() }));
+            log::info!("capture: Raw Input active; keys are observed, never suppressed");
+
+            let focus_hooks = FocusHooks::install(suspension, expect_ui_access, on_focus);
+            #[cfg(feature = "tray")]
+            let tray_icon = tray.and_then(TrayIcon::install);
+
+            unsafe {
+                let mut msg: MSG = std::mem::zeroed();
+                while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {
+                    DispatchMessageW(&msg);
+                }
+            }
+            log::info!("capture: message loop exited");
+
+            #[cfg(feature = "tray")]
+            drop(tray_icon);
+            drop(focus_hooks);
+            unsafe {
+                register(ptr::null_mut(), RIDEV_REMOVE);
+                DestroyWindow(hwnd);
+            }
+        });
+
+        match info_rx.recv() {
+            Ok(Ok(thread_id)) => {
+                self.thread_id = thread_id;
+                self.thread = Some(thread);
+                Ok(())
+            }
+            reported => {
+                let _ = thread.join();
+                clear_state();
+                Err(match reported {
+                    Ok(Err(e)) => e,
+                    _ => PlatformError::Other(
+                        "capture thread exited before reporting Raw Input status".into(),
+                    ),
+                })
+            }
+        }
+    }
+
+    fn stop(&mut self) -> Result<(), PlatformError> {
+        if self.thread_id != 0 {
+            unsafe { PostThreadMessageW(self.thread_id, WM_QUIT, 0, 0) };
+            self.thread_id = 0;
+        }
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+            clear_state();
+        }
+        Ok(())
+    }
+
+    fn set_suspension(&mut self, suspension: Suspension) {
+        self.suspension = suspension;
+    }
+
+    fn set_ui_access_expected(&mut self, expected: bool) {
+        self.expect_ui_access = expected;
+    }
+
+    fn set_focus_callback(&mut self, callback: Box<dyn Fn(WindowContext) + Send>) {
+        self.focus_callback = Some(callback);
+    }
+
+    #[cfg(feature = "tray")]
+    fn set_tray(&mut self, tray: Tray) {
+        self.tray = Some(tray);
+    }
+}
+
+impl Drop for RawInputCapture {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn clear_state() {
+    if let Ok(mut state) = STATE.lock() {
+        *state = None;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Window and registration
+// ---------------------------------------------------------------------------
+
+/// Create the message-only window and register it for keyboard input.
+fn create_window() -> Result<HWND, PlatformError> {
+    let class: Vec<u16> = "pcunifier-raw-input\0".encode_utf16().collect();
+    unsafe {
+        let instance = GetModuleHandleW(ptr::null());
+        let wc = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: class.as_ptr(),
+            ..std::mem::zeroed()
+        };
+        RegisterClassW(&wc);
+        let hwnd = CreateWindowExW(
+            0,
+            class.as_ptr(),
+            class.as_ptr(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            ptr::null_mut(),
+            instance,
+            ptr::null(),
+        );
+        if hwnd.is_null() {
+            return Err(PlatformError::Other(
+                "cannot create the Raw Input window".into(),
+            ));
+        }
+        if !register(hwnd, RIDEV_INPUTSINK | RIDEV_DEVNOTIFY) {
+            DestroyWindow(hwnd);
+            return Err(PlatformError::Other(
+                "RegisterRawInputDevices failed for keyboards".into(),
+            ));
+        }
+        Ok(hwnd)
+    }
+}
+
+/// Register `hwnd` for keyboard Raw Input with `flags`, or with
+/// `RIDEV_REMOVE` and no window, stop.
+unsafe fn register(hwnd: HWND, flags: u32) -> bool {
+    let device = RAWINPUTDEVICE {
+        usUsagePage: HID_PAGE_GENERIC_DESKTOP,
+        usUsage: HID_USAGE_KEYBOARD,
+        dwFlags: flags,
+        hwndTarget: hwnd,
+    };
+    RegisterRawInputDevices(&device, 1, std::mem::size_of::<RAWINPUTDEVICE>() as u32) != 0
+}
+
+// ---------------------------------------------------------------------------
+// Window proc
+// ---------------------------------------------------------------------------
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_INPUT => {
+            if let Some(raw) = read_input(lparam as HRAWINPUT) {
+                on_keyboard(&raw);
+            }
+            // Lets the system free the input data.
+            DefWindowProcW(hwnd, msg, wparam, lparam)
+        }
+        WM_INPUT_DEVICE_CHANGE => {
+            let handle = lparam as HANDLE;
+            let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(state) = guard.as_mut() {
+                match wparam as u32 {
+                    GIDC_ARRIVAL => {
+                        let device = resolve(&mut state.devices, handle);
+                        log::info!(
+                            "capture: keyboard connected: {} ({})",
+                            device.name,
+                            device.id
+                        );
+                    }
+                    GIDC_REMOVAL => {
+                        if let Some(device) = state.devices.remove(&(handle as usize)) {
+                            log::info!("capture: keyboard disconnected: {}", device.name);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            0
+        }
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
+
+/// The keyboard input behind a `WM_INPUT`, if it is keyboard input.
+unsafe fn read_input(handle: HRAWINPUT) -> Option<RAWINPUT> {
+    let mut raw: RAWINPUT = std::mem::zeroed();
+    let mut size = std::mem::size_of::<RAWINPUT>() as u32;
+    let copied = GetRawInputData(
+        handle,
+        RID_INPUT,
+        (&mut raw as *mut RAWINPUT).cast(),
+        &mut size,
+        std::mem::size_of::<RAWINPUTHEADER>() as u32,
+    );
+    (copied != u32::MAX && raw.header.dwType == RIM_TYPEKEYBOARD).then_some(raw)
+}
+
+/// Report one keyboard input.
+unsafe fn on_keyboard(raw: &RAWINPUT) {
+    let kb = raw.data.keyboard;
+    if kb.VKey == VK_FAKE {
+        return;
+    }
+    let flags = u32::from(kb.Flags);
+    let state = if flags & RI_KEY_BREAK != 0 {
+        KeyState::Up
+    } else {
+        KeyState::Down
+    };
+    let extended = flags & RI_KEY_E0 != 0;
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(raw_state) = guard.as_mut() else {
+        return;
+    };
+    // Every key counts, our own too, as in the hook.
+    let vk = sided(kb.VKey, kb.MakeCode, extended);
+    raw_state.modifiers.observe(vk, state == KeyState::Down);
+    if kb.ExtraInformation as usize == INJECTED_TAG {
+        return;
+    }
+    let is_repeat = raw_state.held.repeat(vk, state);
+    let Some(key) = vkcode_to_keycode(kb.VKey, extended) else {
+        log::debug!("capture: unknown VK code {:#04x}", kb.VKey);
+        return;
+    };
+    // Injected by another program: no device.
+    let device = (!raw.header.hDevice.is_null())
+        .then(|| resolve(&mut raw_state.devices, raw.header.hDevice));
+    log::debug!("capture: key {:?} {:?}", key, state);
+    let event = PlatformInputEvent {
+        key,
+        state,
+        modifiers: raw_state.modifiers.modifiers(),
+        window: focus::current(),
+        timestamp: Instant::now(),
+        device,
+        observed: true,
+        is_repeat,
+        position: scancode_to_position(kb.MakeCode, extended),
+    };
+    (raw_state.callback)(event);
+}
+
+/// The side-specific virtual key for a generic modifier, which is what Raw
+/// Input reports: Right Shift has its own make code, the right Ctrl and Alt
+/// the `E0` prefix.
+fn sided(vk: u16, make_code: u16, extended: bool) -> u16 {
+    match vk {
+        0x10 if make_code == 0x36 => 0xA1,
+        0x10 => 0xA0,
+        0x11 if extended => 0xA3,
+        0x11 => 0xA2,
+        0x12 if extended => 0xA5,
+        0x12 => 0xA4,
+        _ => vk,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Device identity
+// ---------------------------------------------------------------------------
+
+/// The `DeviceId` for `handle`, looked up once and cached.
+unsafe fn resolve(devices: &mut HashMap<usize, DeviceId>, handle: HANDLE) -> DeviceId {
+    devices
+        .entry(handle as usize)
+        .or_insert_with(|| {
+            let path = device_path(handle);
+            let product = path.as_deref().and_then(|p| product_string(p));
+            device_id(path.as_deref().unwrap_or(""), product)
+        })
+        .clone()
+}
+
+/// The device interface path, such as `\\?\HID#VID_046D&PID_C52B&MI_00#...`.
+unsafe fn device_path(handle: HANDLE) -> Option<String> {
+    let mut len = 0u32;
+    GetRawInputDeviceInfoW(handle, RIDI_DEVICENAME, ptr::null_mut(), &mut len);
+    if len == 0 {
+        return None;
+    }
+    let mut buf = vec![0u16; len as usize];
+    let copied = GetRawInputDeviceInfoW(handle, RIDI_DEVICENAME, buf.as_mut_ptr().cast(), &mut len);
+    if copied == u32::MAX {
+        return None;
+    }
+    let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    Some(String::from_utf16_lossy(&buf[..end]))
+}
+
+/// The HID product string of the device at `path`. Opening it without
+/// access rights is enough to ask, even for keyboards Windows holds.
+unsafe fn product_string(path: &str) -> Option<String> {
+    let wide: Vec<u16> = path.encode_utf16().chain(Some(0)).collect();
+    let file = CreateFileW(
+        wide.as_ptr(),
+        0,
+        FILE_SHARE_READ | FILE_SHARE_WRITE,
+        ptr::null(),
+        OPEN_EXISTING,
+        0,
+        ptr::null_mut(),
+    );
+    if file == INVALID_HANDLE_VALUE {
+        return None;
+    }
+    // The HID limit is 126 characters plus the terminator.
+    let mut buf = [0u16; 127];
+    let ok = HidD_GetProductString(file, buf.as_mut_ptr().cast(), (buf.len() * 2) as u32) != 0;
+    CloseHandle(file);
+    let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    let name = String::from_utf16_lossy(&buf[..end]);
+    (ok && !name.trim().is_empty()).then(|| name.trim().to_owned())
+}
+
+/// The id and name for the keyboard at device interface `path`.
+fn device_id(path: &str, product: Option<String>) -> DeviceId {
+    let upper = path.to_ascii_uppercase();
+    let hex_after = |tag: &str| {
+        let start = upper.find(tag)? + tag.len();
+        let digits = upper.get(start..start + 4)?;
+        u16::from_str_radix(digits, 16).ok()
+    };
+    let id = match (hex_after("VID_"), hex_after("PID_")) {
+        (Some(vendor), Some(product)) => format!("{vendor:04x}:{product:04x}"),
+        _ if upper.starts_with(r"\\?\ACPI#") => BUILT_IN_ID.to_owned(),
+        _ => path.to_owned(),
+    };
+    let name = product.unwrap_or_else(|| format!("keyboard {id}"));
+    DeviceId {
+        id: Arc::from(id),
+        name: Arc::from(name),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_vendor_product_or_built_in() {
+        let usb = device_id(
+            r"\\?\HID#VID_046D&PID_C52B&MI_00#7&1a2b3c4d&0&0000#{884b96c3-56ef-11d1-bc8c-00a0c91405dd}",
+            Some("USB Receiver".into()),
+        );
+        assert_eq!((&*usb.id, &*usb.name), ("046d:c52b", "USB Receiver"));
+        let laptop = device_id(
+            r"\\?\ACPI#PNP0303#4&1d401fb5&0#{884b96c3-56ef-11d1-bc8c-00a0c91405dd}",
+            None,
+        );
+        assert_eq!(
+            (&*laptop.id, &*laptop.name),
+            ("built-in", "keyboard built-in")
+        );
+    }
+
+    #[test]
+    fn generic_modifiers_are_given_a_side() {
+        assert_eq!(sided(0x10, 0x2A, false), 0xA0);
+        assert_eq!(sided(0x10, 0x36, false), 0xA1);
+        assert_eq!(sided(0x11, 0x1D, true), 0xA3);
+        assert_eq!(sided(0x12, 0x38, false), 0xA4);
+        assert_eq!(sided(0x41, 0x1E, false), 0x41);
+    }
+
+    #[test]
+    fn stop_on_unstarted_capture_is_noop() {
+        let mut capture = RawInputCapture::new();
+        assert!(capture.stop().is_ok());
+    }
+}
@@ -0,0 +1,130 @@
+//! Session lock and disconnect tracking through WTS notifications.
+//!
+//! `WTSRegisterSessionNotification` sends `WM_WTSSESSION_CHANGE` to a
+//! window for changes to our session. The watcher creates a message-only
+//! window on a thread of its own, whose message loop dispatches them:
+//!   - `WTS_SESSION_LOCK` and `WTS_SESSION_UNLOCK` for the lock screen;
+//!   - `WTS_REMOTE_DISCONNECT` and `WTS_CONSOLE_DISCONNECT` when a remote
+//!     desktop client drops or another user takes the console, and the
+//!     matching connects when the session is back.
+//!
+//! Low-level hooks see nothing while the secure desktop has the input, so
+//! keys let go on the lock screen never report their Up; the engine
+//! releases them at the boundary (see `Engine::sync_session`).
+
+use std::ptr;
+use std::sync::Mutex;
+use std::thread;
+
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::System::RemoteDesktop::{
+    WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, HWND_MESSAGE,
+    MSG, WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_CONSOLE_CONNECT, WTS_CONSOLE_DISCONNECT,
+    WTS_REMOTE_CONNECT, WTS_REMOTE_DISCONNECT, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+};
+
+use crate::platform::SessionLock;
+
+/// Where the window proc reports. Window procs receive no `user_info`
+/// unless set up for it, so it lives in a global, like the hook state in
+/// `capture`.
+static SESSION: Mutex<Option<SessionLock>> = Mutex::new(None);
+
+/// Follow the session on a background thread, for the life of the process.
+pub fn watch_session(lock: SessionLock) {
+    if let Ok(mut session) = SESSION.lock() {
+        *session = Some(lock);
+    }
+    thread::spawn(|| {
+        let class: Vec<u16> = "pcunifier-session\0".encode_utf16().collect();
+        unsafe {
+            let instance = GetModuleHandleW(ptr::null());
+            let wc = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                hInstance: instance,
+                lpszClassName: class.as_ptr(),
+                ..std::mem::zeroed()
+            };
+            RegisterClassW(&wc);
+            let hwnd = CreateWindowExW(
+                0,
+                class.as_ptr(),
+                class.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                ptr::null_mut(),
+                instance,
+                ptr::null(),
+            );
+            if hwnd.is_null() || WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) == 0
+            {
+                log::warn!(
+                    "session: cannot register for session notifications; \
+                     rules do not pause while the session is locked"
+                );
+                return;
+            }
+            let mut msg: MSG = std::mem::zeroed();
+            while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {
+                DispatchMessageW(&msg);
+            }
+        }
+    });
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg != WM_WTSSESSION_CHANGE {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+    if let Some(session) = SESSION.lock().ok().and_then(|s| s.clone()) {
+        apply(&session, wparam as u32);
+    }
+    0
+}
+
+/// Record the session change `event`, a `WTS_*` code. Others are ignored.
+fn apply(session: &SessionLock, event: u32) {
+    match event {
+        WTS_SESSION_LOCK => session.set_locked(true),
+        WTS_SESSION_UNLOCK => session.set_locked(false),
+        WTS_REMOTE_DISCONNECT | WTS_CONSOLE_DISCONNECT => session.set_disconnected(true),
+        WTS_REMOTE_CONNECT | WTS_CONSOLE_CONNECT => session.set_disconnected(false),
+        _ => {}
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_session_round_trip() {
+        let session = SessionLock::default();
+        // As an RDP client drops and reconnects: locked as it goes, then
+        // back, then unlocked once the user signs in again.
+        apply(&session, WTS_SESSION_LOCK);
+        apply(&session, WTS_REMOTE_DISCONNECT);
+        assert_eq!(session.reason(), Some("session disconnected"));
+        apply(&session, WTS_REMOTE_CONNECT);
+        assert_eq!(session.reason(), Some("session locked"));
+        apply(&session, WTS_SESSION_UNLOCK);
+        assert_eq!(session.reason(), None);
+    }
+}
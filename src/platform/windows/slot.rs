@@ -0,0 +1,149 @@
+//! Ownership of the process-global state a low-level hook proc reads.
+//!
+//! Hook procs receive no `user_info`, so what they hand events to lives in a
+//! global, and only one capture can own it at a time. `Slot` makes that
+//! ownership explicit: `claim` succeeds for one instance and fails for every
+//! other until the owner calls `release` with its `Claim`. A `Claim` can be
+//! neither copied nor cloned, so only the owner releases, and only once; an
+//! instance whose claim failed has nothing to release. Stop and drop order
+//! between two instances therefore does not matter.
+//!
+//! Reads take no lock, so the hook proc never waits on one.
+
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// A value one capture instance at a time may publish to its hook proc.
+pub struct Slot<T> {
+    value: AtomicPtr<T>,
+}
+
+/// Proof of a successful `Slot::claim`, needed to release it. Holds the
+/// published address, as `usize` so the capture stays `Send`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Claim(usize);
+
+impl<T> Slot<T> {
+    pub const fn new() -> Self {
+        Self {
+            value: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Publish `value` unless another instance holds the slot, in which
+    /// case `value` is handed back.
+    pub fn claim(&self, value: T) -> Result<Claim, T> {
+        let boxed = Box::into_raw(Box::new(value));
+        match self.value.compare_exchange(
+            ptr::null_mut(),
+            boxed,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => Ok(Claim(boxed as usize)),
+            Err(_) => Err(*unsafe { Box::from_raw(boxed) }),
+        }
+    }
+
+    /// The published value, if any.
+    ///
+    /// # Safety
+    ///
+    /// The reference must not outlive the claim. In the capture it is used
+    /// only on the hook thread, and `release` waits for that thread to exit.
+    pub unsafe fn get(&self) -> Option<&T> {
+        self.value.load(Ordering::Acquire).as_ref()
+    }
+
+    /// Withdraw and drop the value published under `claim`.
+    ///
+    /// Call only once no reference from `get` is in use.
+    pub fn release(&self, claim: Claim) {
+        let published = claim.0 as *mut T;
+        if self
+            .value
+            .compare_exchange(
+                published,
+                ptr::null_mut(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        {
+            drop(unsafe { Box::from_raw(published) });
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    type Callback = Box<dyn Fn(u32) + Send>;
+
+    /// A callback that forwards to a channel, and its receiving end.
+    fn callback() -> (Callback, mpsc::Receiver<u32>) {
+        let (tx, rx) = mpsc::channel();
+        (
+            Box::new(move |n| {
+                let _ = tx.send(n);
+            }),
+            rx,
+        )
+    }
+
+    /// What a hook proc does with the slot.
+    fn fire(slot: &Slot<Callback>, n: u32) {
+        if let Some(cb) = unsafe { slot.get() } {
+            cb(n);
+        }
+    }
+
+    #[test]
+    fn a_second_claim_fails_and_leaves_the_first_in_place() {
+        let slot = Slot::new();
+        let (first, first_rx) = callback();
+        let (second, second_rx) = callback();
+
+        let claim = slot.claim(first).ok().unwrap();
+        let second = slot.claim(second).err().unwrap();
+        fire(&slot, 1);
+        assert_eq!(first_rx.try_recv(), Ok(1));
+        assert!(second_rx.try_recv().is_err());
+
+        // Once released, the slot can be claimed again, and the first
+        // callback is gone.
+        slot.release(claim);
+        assert!(first_rx.recv().is_err());
+        let claim = slot.claim(second).ok().unwrap();
+        fire(&slot, 2);
+        assert_eq!(second_rx.try_recv(), Ok(2));
+        slot.release(claim);
+    }
+
+    /// The instance that lost the race being dropped first leaves the
+    /// owner running; the owner stopping then leaves the slot empty.
+    #[test]
+    fn dropping_the_losing_instance_leaves_the_owner_running() {
+        let slot = Slot::new();
+        let (first, first_rx) = callback();
+        let (second, second_rx) = callback();
+
+        let claim = slot.claim(first).ok().unwrap();
+        drop(slot.claim(second).err().unwrap());
+        assert!(second_rx.recv().is_err());
+        fire(&slot, 3);
+        assert_eq!(first_rx.try_recv(), Ok(3));
+
+        slot.release(claim);
+        assert!(unsafe { slot.get() }.is_none());
+        fire(&slot, 4);
+        assert!(first_rx.recv().is_err());
+    }
+}
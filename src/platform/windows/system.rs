@@ -0,0 +1,31 @@
+//! Portable system commands on Windows, through Win32 calls.
+//!
+//! Each call only starts the operation: it returns before the screen locks,
+//! the machine sleeps, or the session ends.
+
+use windows_sys::Win32::System::Power::SetSuspendState;
+use windows_sys::Win32::System::Shutdown::{
+    ExitWindowsEx, LockWorkStation, EWX_LOGOFF, SHTDN_REASON_FLAG_PLANNED,
+};
+
+use crate::platform::{PlatformError, SystemCmd};
+
+/// Runs `cmd` for the interactive session.
+pub fn run(cmd: SystemCmd) -> Result<(), PlatformError> {
+    // SAFETY: none of these calls take pointers; each returns zero on failure.
+    let ok = unsafe {
+        match cmd {
+            SystemCmd::LockScreen => LockWorkStation(),
+            // Sleep, not hibernate; wake events stay enabled.
+            SystemCmd::Sleep => i32::from(SetSuspendState(0, 0, 0)),
+            SystemCmd::LogOut => ExitWindowsEx(EWX_LOGOFF, SHTDN_REASON_FLAG_PLANNED),
+        }
+    };
+    if ok == 0 {
+        return Err(PlatformError::io(
+            format!("{cmd:?} failed"),
+            std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
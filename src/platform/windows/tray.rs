@@ -0,0 +1,439 @@
+//! Notification area icon, built with the `tray` feature.
+//!
+//! `TrayIcon::install` creates a hidden window on the capture thread, whose
+//! message loop dispatches its messages, and adds an icon for it with
+//! `Shell_NotifyIconW`. A window timer polls `Tray::status` and updates the
+//! icon and tooltip when it changes: the application icon while running,
+//! the information icon while paused, the warning icon while the engine
+//! looks hung.
+//!
+//! Right-clicking the icon opens a menu with Pause or Resume, Reload config,
+//! Open config file, and Quit. Everything but Open goes to `Tray::command`,
+//! which main sends down the same paths as control socket commands. Open
+//! hands the config file to its associated editor.
+//!
+//! Edge cases:
+//!   - Explorer restarting drops every icon and broadcasts `TaskbarCreated`;
+//!     the window adds the icon again. Message-only windows miss broadcasts,
+//!     so the window is an ordinary one that is never shown.
+//!   - `TrackPopupMenu` runs a modal loop on the capture thread until the
+//!     menu closes. Hooks are still called from it, but the thread timers
+//!     of `capture` are not handled, so the hook watchdog and pointer
+//!     coalescing wait for the menu. The window proc holds no lock while
+//!     the menu is open, since the modal loop calls it again.
+//!   - The menu only dismisses on an outside click if our window is in the
+//!     foreground while it is open (a documented `TrackPopupMenu` quirk).
+
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Mutex;
+
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::UI::Shell::{
+    ShellExecuteW, Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE,
+    NIM_MODIFY, NOTIFYICONDATAW,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DestroyWindow,
+    GetCursorPos, KillTimer, LoadIconW, PostMessageW, RegisterClassW, RegisterWindowMessageW,
+    SetForegroundWindow, SetTimer, TrackPopupMenu, IDI_APPLICATION, IDI_INFORMATION, IDI_WARNING,
+    MF_SEPARATOR, MF_STRING, SW_SHOWNORMAL, TPM_RETURNCMD, TPM_RIGHTBUTTON, WM_APP, WM_CONTEXTMENU,
+    WM_NULL, WM_RBUTTONUP, WM_TIMER, WNDCLASSW,
+};
+
+/// Sent by the icon for mouse events over it.
+const WM_TRAY: u32 = WM_APP + 1;
+
+/// Window timer that polls the status.
+const REFRESH_TIMER: usize = 1;
+const REFRESH_INTERVAL_MS: u32 = 1_000;
+
+const ICON_ID: u32 = 1;
+
+const ID_TOGGLE: u32 = 1;
+const ID_RELOAD: u32 = 2;
+const ID_OPEN: u32 = 3;
+const ID_QUIT: u32 = 4;
+
+/// What the icon shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrayStatus {
+    pub paused: bool,
+    /// False while the engine loop or capture looks hung.
+    pub healthy: bool,
+}
+
+/// A menu choice for the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayCommand {
+    Pause,
+    Resume,
+    Reload,
+    Quit,
+}
+
+/// The engine side of the icon, handed to `InputCapture::set_tray`.
+pub struct Tray {
+    /// Opened by Open config file.
+    pub config_path: PathBuf,
+    /// Polled on the capture thread; must return quickly.
+    pub status: Box<dyn Fn() -> TrayStatus + Send>,
+    /// Called on the capture thread for each menu choice.
+    pub command: Box<dyn Fn(TrayCommand) + Send>,
+}
+
+/// Attach to the console of the process that started us, if any, so a
+/// windows subsystem build still logs to the terminal it was run from.
+/// Without one, such as when started from Explorer, logging goes nowhere.
+pub fn attach_console() {
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+/// The tray and what was last shown. Window procs receive no `user_info`
+/// unless set up for it, so it lives in a global, like the hook state in
+/// `capture`.
+static TRAY: Mutex<Option<State>> = Mutex::new(None);
+
+struct State {
+    tray: Tray,
+    /// Stored as isize for Send.
+    hwnd: isize,
+    shown: TrayStatus,
+    /// `TaskbarCreated`, registered at install.
+    taskbar_created: u32,
+}
+
+/// Guard for the icon and its window; dropping it removes both. Must be
+/// dropped on the thread that installed it.
+pub(super) struct TrayIcon {
+    hwnd: HWND,
+}
+
+impl TrayIcon {
+    /// Create the window and add the icon. `None`, after a warning, when
+    /// either fails; capture runs on without the icon.
+    pub(super) fn install(tray: Tray) -> Option<Self> {
+        let class = wide("pcunifier-tray");
+        unsafe {
+            let instance = GetModuleHandleW(ptr::null());
+            let wc = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                hInstance: instance,
+                lpszClassName: class.as_ptr(),
+                ..std::mem::zeroed()
+            };
+            // Fails harmlessly when a previous capture registered it.
+            RegisterClassW(&wc);
+            let hwnd = CreateWindowExW(
+                0,
+                class.as_ptr(),
+                class.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                instance,
+                ptr::null(),
+            );
+            if hwnd.is_null() {
+                log::warn!("capture: tray window could not be created; running without the icon");
+                return None;
+            }
+            let shown = (tray.status)();
+            let taskbar_created = RegisterWindowMessageW(wide("TaskbarCreated").as_ptr());
+            if let Ok(mut state) = TRAY.lock() {
+                *state = Some(State {
+                    tray,
+                    hwnd: hwnd as isize,
+                    shown,
+                    taskbar_created,
+                });
+            }
+            if !notify(NIM_ADD, hwnd, shown) {
+                log::warn!("capture: tray icon could not be added; running without it");
+            }
+            SetTimer(hwnd, REFRESH_TIMER, REFRESH_INTERVAL_MS, None);
+            Some(Self { hwnd })
+        }
+    }
+}
+
+impl Drop for TrayIcon {
+    fn drop(&mut self) {
+        unsafe {
+            KillTimer(self.hwnd, REFRESH_TIMER);
+            let shown = TRAY.lock().ok().and_then(|t| t.as_ref().map(|t| t.shown));
+            if let Some(shown) = shown {
+                notify(NIM_DELETE, self.hwnd, shown);
+            }
+            DestroyWindow(self.hwnd);
+        }
+        let _ = TRAY.lock().map(|mut t| *t = None);
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_TIMER if wparam == REFRESH_TIMER => refresh(hwnd, false),
+        WM_TRAY => {
+            let event = (lparam & 0xFFFF) as u32;
+            if event == WM_RBUTTONUP || event == WM_CONTEXTMENU {
+                show_menu(hwnd);
+            }
+        }
+        _ if msg != 0 && msg == taskbar_created() => refresh(hwnd, true),
+        _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+    0
+}
+
+fn taskbar_created() -> u32 {
+    TRAY.lock()
+        .ok()
+        .and_then(|t| t.as_ref().map(|t| t.taskbar_created))
+        .unwrap_or(0)
+}
+
+/// Show the current status if it changed, or add the icon again when
+/// `re_add` is set.
+fn refresh(hwnd: HWND, re_add: bool) {
+    let Ok(mut guard) = TRAY.lock() else {
+        return;
+    };
+    let Some(state) = guard.as_mut().filter(|s| s.hwnd == hwnd as isize) else {
+        return;
+    };
+    let status = (state.tray.status)();
+    if re_add {
+        notify(NIM_ADD, hwnd, status);
+    } else if status != state.shown {
+        notify(NIM_MODIFY, hwnd, status);
+    }
+    state.shown = status;
+}
+
+/// Open the context menu at the cursor and carry out the choice.
+fn show_menu(hwnd: HWND) {
+    let Some(status) = TRAY
+        .lock()
+        .ok()
+        .and_then(|t| t.as_ref().map(|t| (t.tray.status)()))
+    else {
+        return;
+    };
+    let id = unsafe {
+        let menu = CreatePopupMenu();
+        if menu.is_null() {
+            return;
+        }
+        for (id, label) in menu_items(status) {
+            match label {
+                Some(label) => AppendMenuW(menu, MF_STRING, id as usize, wide(label).as_ptr()),
+                None => AppendMenuW(menu, MF_SEPARATOR, 0, ptr::null()),
+            };
+        }
+        let mut at = POINT { x: 0, y: 0 };
+        GetCursorPos(&mut at);
+        SetForegroundWindow(hwnd);
+        let id = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_RIGHTBUTTON,
+            at.x,
+            at.y,
+            0,
+            hwnd,
+            ptr::null(),
+        );
+        // Lets the menu close properly on the next outside click.
+        PostMessageW(hwnd, WM_NULL, 0, 0);
+        DestroyMenu(menu);
+        id as u32
+    };
+    match choice(id, status) {
+        Some(Choice::Command(cmd)) => {
+            if let Some(state) = TRAY.lock().ok().as_ref().and_then(|t| t.as_ref()) {
+                (state.tray.command)(cmd);
+            }
+        }
+        Some(Choice::OpenConfig) => {
+            // Cloned out: ShellExecuteW may pump messages, calling us again.
+            let path = TRAY
+                .lock()
+                .ok()
+                .and_then(|t| t.as_ref().map(|t| t.tray.config_path.clone()));
+            if let Some(path) = path {
+                open(&path);
+            }
+        }
+        None => {}
+    }
+}
+
+/// Open `path` with its associated program.
+fn open(path: &std::path::Path) {
+    let file = wide(&path.to_string_lossy());
+    // Values of 32 and below are errors.
+    let result = unsafe {
+        ShellExecuteW(
+            ptr::null_mut(),
+            wide("open").as_ptr(),
+            file.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+    if result as usize <= 32 {
+        log::warn!("capture: could not open {}", path.display());
+    }
+}
+
+/// Add, change, or remove the icon. Whether the shell accepted it.
+fn notify(message: u32, hwnd: HWND, status: TrayStatus) -> bool {
+    unsafe {
+        let mut data: NOTIFYICONDATAW = std::mem::zeroed();
+        data.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
+        data.hWnd = hwnd;
+        data.uID = ICON_ID;
+        data.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+        data.uCallbackMessage = WM_TRAY;
+        data.hIcon = LoadIconW(
+            ptr::null_mut(),
+            match icon(status) {
+                Icon::Running => IDI_APPLICATION,
+                Icon::Paused => IDI_INFORMATION,
+                Icon::Unhealthy => IDI_WARNING,
+            },
+        );
+        let tip = tooltip(status).encode_utf16();
+        let room = data.szTip.len() - 1;
+        for (slot, unit) in data.szTip.iter_mut().zip(tip).take(room) {
+            *slot = unit;
+        }
+        Shell_NotifyIconW(message, &data) != 0
+    }
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+// ---------------------------------------------------------------------------
+// Menu and icon choices
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Icon {
+    Running,
+    Paused,
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Command(TrayCommand),
+    OpenConfig,
+}
+
+/// A hung engine outranks a pause: pausing cannot fix it.
+fn icon(status: TrayStatus) -> Icon {
+    if !status.healthy {
+        Icon::Unhealthy
+    } else if status.paused {
+        Icon::Paused
+    } else {
+        Icon::Running
+    }
+}
+
+fn tooltip(status: TrayStatus) -> &'static str {
+    match icon(status) {
+        Icon::Running => "pcunifier: running",
+        Icon::Paused => "pcunifier: paused",
+        Icon::Unhealthy => "pcunifier: not responding",
+    }
+}
+
+/// Menu ids and labels in order; `None` is a separator.
+fn menu_items(status: TrayStatus) -> [(u32, Option<&'static str>); 5] {
+    [
+        (
+            ID_TOGGLE,
+            Some(if status.paused { "Resume" } else { "Pause" }),
+        ),
+        (ID_RELOAD, Some("Reload config")),
+        (ID_OPEN, Some("Open config file")),
+        (0, None),
+        (ID_QUIT, Some("Quit")),
+    ]
+}
+
+/// What menu item `id` does, given the status the menu was built from.
+/// `None` when the menu was dismissed.
+fn choice(id: u32, status: TrayStatus) -> Option<Choice> {
+    match id {
+        ID_TOGGLE if status.paused => Some(Choice::Command(TrayCommand::Resume)),
+        ID_TOGGLE => Some(Choice::Command(TrayCommand::Pause)),
+        ID_RELOAD => Some(Choice::Command(TrayCommand::Reload)),
+        ID_OPEN => Some(Choice::OpenConfig),
+        ID_QUIT => Some(Choice::Command(TrayCommand::Quit)),
+        _ => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUNNING: TrayStatus = TrayStatus {
+        paused: false,
+        healthy: true,
+    };
+    const PAUSED: TrayStatus = TrayStatus {
+        paused: true,
+        healthy: true,
+    };
+    const HUNG: TrayStatus = TrayStatus {
+        paused: true,
+        healthy: false,
+    };
+
+    #[test]
+    fn toggle_follows_the_pause_state() {
+        assert_eq!(menu_items(RUNNING)[0], (ID_TOGGLE, Some("Pause")));
+        assert_eq!(menu_items(PAUSED)[0], (ID_TOGGLE, Some("Resume")));
+        assert_eq!(
+            choice(ID_TOGGLE, RUNNING),
+            Some(Choice::Command(TrayCommand::Pause))
+        );
+        assert_eq!(
+            choice(ID_TOGGLE, PAUSED),
+            Some(Choice::Command(TrayCommand::Resume))
+        );
+        assert_eq!(choice(ID_OPEN, PAUSED), Some(Choice::OpenConfig));
+        // Dismissed.
+        assert_eq!(choice(0, PAUSED), None);
+    }
+
+    #[test]
+    fn icon_shows_hung_before_paused() {
+        assert_eq!(tooltip(RUNNING), "pcunifier: running");
+        assert_eq!(tooltip(PAUSED), "pcunifier: paused");
+        assert_eq!(icon(HUNG), Icon::Unhealthy);
+        assert_eq!(tooltip(HUNG), "pcunifier: not responding");
+    }
+}
@@ -0,0 +1,97 @@
+//! Detection of a keyboard hook Windows removed without telling us.
+//!
+//! Windows drops a `WH_KEYBOARD_LL` hook whose proc runs past
+//! `LowLevelHooksTimeout`, and from then on the keys simply reach
+//! applications unremapped. Nothing reports the removal, so the capture
+//! thread checks on a timer: when `GetLastInputInfo` shows input newer than
+//! the last key event through the hook, it sends a probe key the hook
+//! swallows. If the probe has not arrived by the next check, the hook is gone
+//! and is installed again.
+//!
+//! Pointer input also moves `GetLastInputInfo`, so a probe does not mean
+//! the hook is gone; only a lost probe does. Ticks are `GetTickCount`
+//! milliseconds and are compared with wrapping arithmetic.
+
+/// Input newer than the last hooked key by more than this leads to a probe.
+/// Keeps input the hook is about to see from counting as missed.
+const GRACE_MS: u32 = 500;
+
+/// What the capture thread should do after a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// The hook is keeping up.
+    Healthy,
+    /// Send a probe key and check again.
+    Probe,
+    /// The last probe never arrived: install the hook again.
+    Reinstall,
+}
+
+/// Watchdog state, kept on the capture thread between timer ticks.
+#[derive(Debug, Default)]
+pub struct HookWatchdog {
+    probing: bool,
+}
+
+impl HookWatchdog {
+    /// Decide from the tick of the last input (`GetLastInputInfo`), the
+    /// tick of the last key event the hook saw, and whether the probe sent
+    /// after the previous check arrived.
+    pub fn check(&mut self, last_input: u32, last_hooked: u32, probe_seen: bool) -> Check {
+        if std::mem::take(&mut self.probing) {
+            return if probe_seen {
+                Check::Healthy
+            } else {
+                Check::Reinstall
+            };
+        }
+        // Negative when the hook saw something after the last input.
+        let unseen = last_input.wrapping_sub(last_hooked) as i32;
+        if unseen > GRACE_MS as i32 {
+            self.probing = true;
+            Check::Probe
+        } else {
+            Check::Healthy
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_the_hook_saw_is_healthy() {
+        let mut watchdog = HookWatchdog::default();
+        assert_eq!(watchdog.check(1_000, 1_000, false), Check::Healthy);
+        assert_eq!(watchdog.check(1_200, 1_000, false), Check::Healthy);
+        assert_eq!(watchdog.check(1_000, 1_200, false), Check::Healthy);
+    }
+
+    #[test]
+    fn unseen_input_is_probed_and_a_lost_probe_reinstalls() {
+        let mut watchdog = HookWatchdog::default();
+        assert_eq!(watchdog.check(5_000, 1_000, false), Check::Probe);
+        assert_eq!(watchdog.check(5_000, 1_000, false), Check::Reinstall);
+        // The new hook gets a fresh start.
+        assert_eq!(watchdog.check(9_000, 9_000, false), Check::Healthy);
+    }
+
+    #[test]
+    fn a_probe_that_arrives_means_the_hook_is_alive() {
+        let mut watchdog = HookWatchdog::default();
+        assert_eq!(watchdog.check(5_000, 1_000, false), Check::Probe);
+        assert_eq!(watchdog.check(5_100, 1_000, true), Check::Healthy);
+    }
+
+    #[test]
+    fn ticks_compare_across_a_wrap() {
+        let mut watchdog = HookWatchdog::default();
+        assert_eq!(watchdog.check(100, u32::MAX - 100, false), Check::Healthy);
+        assert_eq!(watchdog.check(1_000, u32::MAX - 100, false), Check::Probe);
+    }
+}
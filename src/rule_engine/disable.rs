@@ -0,0 +1,50 @@
+//! Disable table: keys that do nothing while a controlling key is held.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::config::DisableRule;
+use crate::platform::KeyCode;
+
+/// Compiled `[[disable]]` rules, keyed by the controlling key.
+///
+/// Several controllers may disable the same key; it stays disabled while any
+/// of them is held. Controllers and disabled keys both match side-specific
+/// modifiers through their unified name, as remaps do.
+pub(super) struct DisableTable {
+    rules: HashMap<KeyCode, HashSet<KeyCode>>,
+    /// Every key some rule disables.
+    disabled: HashSet<KeyCode>,
+}
+
+impl DisableTable {
+    pub(super) fn build(disables: &[DisableRule]) -> Self {
+        let mut rules: HashMap<KeyCode, HashSet<KeyCode>> = HashMap::new();
+        for rule in disables {
+            rules
+                .entry(rule.while_held)
+                .or_default()
+                .extend(rule.keys.iter().copied());
+        }
+        let disabled = rules.values().flatten().copied().collect();
+        Self { rules, disabled }
+    }
+
+    /// Whether some rule disables `key` or its unified modifier, whatever is
+    /// held.
+    pub(super) fn covers(&self, key: KeyCode) -> bool {
+        self.disabled.contains(&key) || self.disabled.contains(&key.unify_side())
+    }
+
+    /// Whether `key` is disabled while `held` keys are down.
+    pub(super) fn disables(&self, held: &HashSet<KeyCode>, key: KeyCode) -> bool {
+        if !self.covers(key) {
+            return false;
+        }
+        held.iter().filter(|&&h| h != key).any(|&h| {
+            [h, h.unify_side()]
+                .iter()
+                .filter_map(|c| self.rules.get(c))
+                .any(|keys| keys.contains(&key) || keys.contains(&key.unify_side()))
+        })
+    }
+}
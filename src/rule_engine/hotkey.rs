@@ -2,79 +2,131 @@
 
 use std::collections::HashSet;
 
-use crate::config::{HotkeyAction, HotkeyRule};
-use crate::platform::{Action, KeyCode};
+use super::scope_matches;
+use crate::config::{HotkeyAction, HotkeyRule, Timing, WindowCondition};
+use crate::platform::{Action, KeyCode, WindowContext};
 
 /// A compiled hotkey entry: all keys that must be held simultaneously, and the
 /// action to fire when they are.
 struct HotkeyEntry {
     keys: HashSet<KeyCode>,
-    action: HotkeyAction,
+    action: Action,
     apps: Option<Vec<String>>,
+    when: Option<WindowCondition>,
+    timing: Timing,
 }
 
 impl HotkeyEntry {
-    fn to_action(&self) -> Action {
-        match &self.action {
+    /// Compile a rule, or `None` for actions the engine handles itself.
+    fn compile(rule: &HotkeyRule) -> Option<Self> {
+        let action = match &rule.action {
             HotkeyAction::Exec(cmd) => Action::Exec {
                 command: cmd.clone(),
             },
-        }
+            HotkeyAction::System(cmd) => Action::SystemCommand(*cmd),
+            HotkeyAction::TypeClipboard => Action::TypeClipboard,
+            HotkeyAction::TypeOutput(cmd) => Action::TypeOutput {
+                command: cmd.clone(),
+            },
+            // Matched by the engine so it still fires while rules are paused.
+            HotkeyAction::SetPaused(_) => return None,
+        };
+        Some(Self {
+            keys: rule.keys.iter().copied().collect(),
+            action,
+            apps: rule.apps.clone(),
+            when: rule.when.clone(),
+            timing: rule.timing,
+        })
+    }
+
+    fn to_match(&self) -> (Action, Timing) {
+        (self.action.clone(), self.timing)
     }
 }
 
-/// Compiled hotkey table. Per-app entries are stored before global entries so
-/// that app-specific overrides win when window context is available (M11 readiness).
+/// Compiled hotkey table. Scoped entries (`apps` or `when`) are stored before
+/// global entries so that app-specific overrides win.
 pub(super) struct HotkeyTable {
     entries: Vec<HotkeyEntry>,
+    /// The modifiers each hotkey needs held, without repeats and without
+    /// sets that contain another: some set must be satisfied for any hotkey
+    /// to fire. An empty set means a hotkey needs no modifier.
+    modifier_states: Vec<Vec<KeyCode>>,
 }
 
 impl HotkeyTable {
     pub(super) fn build(hotkeys: &[HotkeyRule]) -> Self {
         let mut entries: Vec<HotkeyEntry> = Vec::new();
 
-        // Per-app rules first.
-        for rule in hotkeys.iter().filter(|r| r.apps.is_some()) {
-            entries.push(HotkeyEntry {
-                keys: rule.keys.iter().copied().collect(),
-                action: rule.action.clone(),
-                apps: rule.apps.clone(),
-            });
+        // Scoped rules first.
+        let scoped = |r: &&HotkeyRule| r.apps.is_some() || r.when.is_some();
+        for rule in hotkeys.iter().filter(scoped) {
+            entries.extend(HotkeyEntry::compile(rule));
+        }
+        for rule in hotkeys.iter().filter(|r| !scoped(r)) {
+            entries.extend(HotkeyEntry::compile(rule));
+        }
+
+        let modifiers = |e: &HotkeyEntry| -> HashSet<KeyCode> {
+            e.keys.iter().copied().filter(|k| k.is_modifier()).collect()
+        };
+        let mut states: Vec<HashSet<KeyCode>> = Vec::new();
+        for set in entries.iter().map(modifiers) {
+            if states.iter().any(|s| s.is_subset(&set)) {
+                continue;
+            }
+            states.retain(|s| !set.is_subset(s));
+            states.push(set);
         }
-        for rule in hotkeys.iter().filter(|r| r.apps.is_none()) {
-            entries.push(HotkeyEntry {
-                keys: rule.keys.iter().copied().collect(),
-                action: rule.action.clone(),
-                apps: rule.apps.clone(),
-            });
+        let modifier_states = states
+            .into_iter()
+            .map(|s| s.into_iter().collect())
+            .collect();
+
+        Self {
+            entries,
+            modifier_states,
         }
+    }
 
-        Self { entries }
+    /// Whether any hotkey could fire when `pressed` goes down while `held`
+    /// are held, judged by modifiers alone. False guarantees `lookup`
+    /// returns `None`; true only means it has to be asked.
+    pub(super) fn may_fire(&self, held: &HashSet<KeyCode>, pressed: KeyCode) -> bool {
+        self.modifier_states.iter().any(|state| {
+            state
+                .iter()
+                .all(|&m| m.covers(pressed) || held.iter().any(|&h| m.covers(h)))
+        })
     }
 
-    /// Find the first matching hotkey given the set of currently held keys.
+    /// Find the first matching hotkey when `pressed` goes down while `held`
+    /// are already held.
     ///
-    /// A hotkey matches when every key in its set is present in `held`.
-    /// Per-app entries are checked first; the first matching global entry is
-    /// the fallback. Returns `None` when no hotkey matches.
-    /// Per-app entries are silently skipped when `app_id` is `None` (window
-    /// context unavailable until M11).
-    pub(super) fn lookup(&self, held: &HashSet<KeyCode>, app_id: Option<&str>) -> Option<Action> {
+    /// A hotkey matches when every key in its set is `pressed` or in `held`.
+    /// A unified modifier in the set is satisfied by either side.
+    /// Scoped entries are checked first; the first matching global entry is
+    /// the fallback. Returns the action with the matched rule's timing, or
+    /// `None` when no hotkey matches.
+    /// Scoped entries never match when the window context is unknown.
+    pub(super) fn lookup(
+        &self,
+        held: &HashSet<KeyCode>,
+        pressed: KeyCode,
+        window: &WindowContext,
+    ) -> Option<(Action, Timing)> {
         let mut global_match: Option<&HotkeyEntry> = None;
 
         for entry in &self.entries {
-            if !entry.keys.iter().all(|k| held.contains(k)) {
+            let satisfied = |k: &KeyCode| k.covers(pressed) || held.iter().any(|&h| k.covers(h));
+            if !entry.keys.iter().all(satisfied) {
                 continue;
             }
 
-            match &entry.apps {
-                Some(apps) => {
-                    if let Some(id) = app_id {
-                        if apps.iter().any(|a| a == id) {
-                            return Some(entry.to_action());
-                        }
-                    }
-                }
+            match scope_matches(entry.apps.as_deref(), entry.when.as_ref(), window) {
+                Some(true) => return Some(entry.to_match()),
+                Some(false) => {}
                 None => {
                     if global_match.is_none() {
                         global_match = Some(entry);
@@ -83,6 +135,6 @@ impl HotkeyTable {
             }
         }
 
-        global_match.map(|e| e.to_action())
+        global_match.map(HotkeyEntry::to_match)
     }
 }
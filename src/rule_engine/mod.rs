@@ -4,36 +4,159 @@
 //! trigger exec actions. Per-app window filtering (M11) and Lua script handlers
 //! (M12/M13) will extend this module further.
 //!
-//! Rules are compiled into lookup tables at startup; `process` performs only
+//! Rules are compiled into lookup tables at startup; `resolve` performs only
 //! hash lookups and set membership tests, never re-parsing configuration.
+//!
+//! Resolution is pure: `RuleEngine::resolve` reads the event and a
+//! `RuleState` and returns a `Decision` describing the actions to run and the
+//! state changes to make. The engine loop is the only place that applies
+//! either, so every rule outcome can be tested as a plain value.
 
+mod disable;
 mod hotkey;
 mod remap;
 
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 
-use crate::config::Config;
-use crate::platform::{Action, InputEvent, KeyCode, KeyState};
+use crate::config::{
+    Config, KeyMatch, ModifierMatch, RemapRule, RemapStep, RemapTarget, SideMatch, Timing,
+    WindowCondition,
+};
+use crate::platform::{Action, InputEvent, KeyCode, KeyState, StepFailure, WindowContext};
+use disable::DisableTable;
 use hotkey::HotkeyTable;
 use remap::RemapTable;
 
 // ---------------------------------------------------------------------------
-// Rule engine
+// Decision
 // ---------------------------------------------------------------------------
 
-/// Processes input events against compiled rules and produces actions.
-///
-/// Build once at startup with `RuleEngine::new`. `process` is `&mut self`
-/// because it updates the transient held-key and suppression sets that track
-/// chord state across events.
-pub struct RuleEngine {
-    remaps: RemapTable,
-    hotkeys: HotkeyTable,
-    /// Keys currently held down. Updated on every KeyDown and KeyUp event.
+/// The outcome of resolving one event: what to run and how `RuleState`
+/// changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    /// Actions to execute, in order.
+    pub actions: Vec<Action>,
+    /// Whether a rule claimed the event. False when the key passes through
+    /// unchanged.
+    pub consumed: bool,
+    /// Changes to apply to `RuleState` before the next event.
+    pub state_changes: Vec<StateChange>,
+    /// Thresholds for time-based handling of the event: the matched rule's,
+    /// or the `[timing]` defaults when no rule matched.
+    pub timing: Timing,
+}
+
+impl Decision {
+    /// Re-inject the event's key unchanged.
+    fn passthrough(event: &InputEvent, state_changes: Vec<StateChange>, timing: Timing) -> Self {
+        Self {
+            actions: vec![Action::InjectKey {
+                key: event.key,
+                state: event.state,
+            }],
+            consumed: false,
+            state_changes,
+            timing,
+        }
+    }
+
+    /// Run `action` on behalf of a matched rule.
+    fn consumed(action: Action, state_changes: Vec<StateChange>, timing: Timing) -> Self {
+        Self {
+            actions: vec![action],
+            consumed: true,
+            state_changes,
+            timing,
+        }
+    }
+}
+
+/// One change to `RuleState` requested by a `Decision`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateChange {
+    /// The key went down.
+    Pressed(KeyCode),
+    /// The key went up.
+    Released(KeyCode),
+    /// The key's Down fired a hotkey, so its Up is suppressed too.
+    TriggerConsumed(KeyCode),
+    /// The Up of a hotkey trigger was suppressed; stop tracking it.
+    TriggerReleased(KeyCode),
+    /// The key's Down was dropped by a `[[disable]]` rule, so its repeats
+    /// and its Up are dropped too.
+    Disabled(KeyCode),
+    /// The Up of a disabled key was dropped; stop tracking it.
+    DisabledReleased(KeyCode),
+    /// The key's Down was remapped to the second key, which chords see in
+    /// its place until the key is released.
+    Remapped(KeyCode, KeyCode),
+    /// The key's Down fired the Down side of a `{ down, up }` remap; its Up
+    /// taps these keys, whatever the rules say by then.
+    Split(KeyCode, Vec<KeyCode>),
+}
+
+/// Chord state carried between events: which keys are held, and which held
+/// keys fired a hotkey.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleState {
+    /// Keys currently held down.
     held_keys: HashSet<KeyCode>,
     /// Trigger keys whose KeyDown was consumed by a hotkey match.
     /// The corresponding KeyUp is also suppressed to prevent ghost key-ups.
     suppressed_keys: HashSet<KeyCode>,
+    /// Keys whose KeyDown a `[[disable]]` rule dropped. They are not in
+    /// `held_keys`, so they take no part in chords, and they stay dropped
+    /// until released even if the controlling key is released first.
+    disabled_keys: HashSet<KeyCode>,
+    /// Held keys remapped to a single key, and the key each became.
+    remapped_keys: HashMap<KeyCode, KeyCode>,
+    /// Held keys whose Down fired a `{ down, up }` remap, and the keys their
+    /// Up taps.
+    split_keys: HashMap<KeyCode, Vec<KeyCode>>,
+}
+
+impl RuleState {
+    /// Apply the changes of a `Decision`.
+    pub fn apply(&mut self, changes: &[StateChange]) {
+        for change in changes {
+            match change.clone() {
+                StateChange::Pressed(key) => self.held_keys.insert(key),
+                StateChange::Released(key) => {
+                    self.remapped_keys.remove(&key);
+                    self.split_keys.remove(&key);
+                    self.held_keys.remove(&key)
+                }
+                StateChange::Remapped(key, to) => self.remapped_keys.insert(key, to).is_none(),
+                StateChange::Split(key, up) => self.split_keys.insert(key, up).is_none(),
+                StateChange::TriggerConsumed(key) => self.suppressed_keys.insert(key),
+                StateChange::TriggerReleased(key) => self.suppressed_keys.remove(&key),
+                StateChange::Disabled(key) => self.disabled_keys.insert(key),
+                StateChange::DisabledReleased(key) => self.disabled_keys.remove(&key),
+            };
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Rule engine
+// ---------------------------------------------------------------------------
+
+/// Compiled rules. Immutable after `RuleEngine::new`; per-event state lives
+/// in `RuleState`.
+pub struct RuleEngine {
+    remaps: RemapTable,
+    hotkeys: HotkeyTable,
+    disables: DisableTable,
+    /// `[timing]` defaults, for events no rule matches.
+    timing: Timing,
+    /// `[capture] match`: rules name physical positions rather than keys.
+    by_position: bool,
+    /// `[capture] modifiers`: chords see held keys before or after remapping.
+    modifier_match: ModifierMatch,
+    /// `[capture] sides`: which modifier sides rules see.
+    sides: SideMatch,
 }
 
 impl RuleEngine {
@@ -42,100 +165,331 @@ impl RuleEngine {
         Self {
             remaps: RemapTable::build(&config.remaps),
             hotkeys: HotkeyTable::build(&config.hotkeys),
-            held_keys: HashSet::new(),
-            suppressed_keys: HashSet::new(),
+            disables: DisableTable::build(&config.disables),
+            timing: config.timing,
+            by_position: config.key_match == KeyMatch::Position,
+            modifier_match: config.modifier_match,
+            sides: config.side_match,
+        }
+    }
+
+    /// The held keys hotkey chords match against: as pressed, or in logical
+    /// mode with each remapped key replaced by what it became.
+    fn chord_keys<'a>(&self, state: &'a RuleState) -> Cow<'a, HashSet<KeyCode>> {
+        if self.modifier_match == ModifierMatch::Physical || state.remapped_keys.is_empty() {
+            return Cow::Borrowed(&state.held_keys);
         }
+        Cow::Owned(
+            state
+                .held_keys
+                .iter()
+                .map(|k| state.remapped_keys.get(k).unwrap_or(k))
+                .copied()
+                .collect(),
+        )
+    }
+
+    /// The key rules see for `event`: its position in position mode, where
+    /// the backend reports one, and otherwise the key itself, with modifier
+    /// sides folded as `[capture] sides` says. Passthrough always re-injects
+    /// the key as captured.
+    fn rule_key(&self, event: &InputEvent) -> KeyCode {
+        let key = match event.position {
+            Some(position) if self.by_position => position,
+            _ => event.key,
+        };
+        self.sides.normalize(key)
     }
 
-    /// Map an input event to an action.
+    /// Decide what an input event does, given the current `state`.
     ///
     /// On KeyDown, evaluation order:
-    ///   1. Hotkey rules -- fires when all chord keys are held; per-app rules
+    ///   1. Disable rules -- suppress the key while a key that disables it is
+    ///      held, and keep suppressing repeats of a disabled press.
+    ///   2. Hotkey rules -- fires when all chord keys are held; per-app rules
     ///      first (M11 readiness), then global. The trigger key is suppressed.
-    ///   2. Remap rules -- per-app first (M11), then global.
-    ///   3. Passthrough -- re-inject the original key unchanged.
+    ///   3. Remap rules -- per-app first (M11), then global.
+    ///   4. Passthrough -- re-inject the original key unchanged.
     ///
     /// On KeyUp:
-    ///   1. Suppress if the corresponding KeyDown was consumed by a hotkey.
+    ///   1. Suppress if the corresponding KeyDown was disabled or consumed by
+    ///      a hotkey.
     ///   2. Remap / passthrough as for KeyDown.
     ///
+    /// A key already down when its controller is pressed is not disabled,
+    /// so it is released normally.
+    ///
     /// All platform backends suppress the original event at capture time, so
     /// passthrough is implemented as re-injection rather than `Action::Passthrough`.
-    /// Per-app rules are silently skipped when `event.window.app_id` is `None`
-    /// (window context unavailable until M11).
-    pub fn process(&mut self, event: &InputEvent) -> Action {
-        let app_id = event.window.app_id.as_deref();
+    /// Scoped rules (`apps` or `when`) are silently skipped when the window
+    /// context lacks what they match on.
+    ///
+    /// Most keystrokes match nothing, so `resolve` first checks, with a few
+    /// hash lookups, whether any rule could apply: a remap or disable rule
+    /// naming the key, a press or release being tracked, or a hotkey whose
+    /// modifiers are all held. When none could, the key passes through
+    /// without the rule tables being walked. The outcome is the same either
+    /// way.
+    pub fn resolve(&self, event: &InputEvent, state: &RuleState) -> Decision {
+        let key = self.rule_key(event);
+        let tracked = state.disabled_keys.contains(&key)
+            || state.split_keys.contains_key(&key)
+            || (event.state == KeyState::Up && state.suppressed_keys.contains(&key));
+        let inert = !tracked
+            && !self.remaps.covers(key)
+            && !self.disables.covers(key)
+            && (event.state == KeyState::Up
+                || !self.hotkeys.may_fire(&self.chord_keys(state), key));
+        if !inert {
+            return self.resolve_rules(event, state);
+        }
+        let change = match event.state {
+            KeyState::Down => StateChange::Pressed(key),
+            KeyState::Up => StateChange::Released(key),
+        };
+        Decision::passthrough(event, vec![change], self.timing)
+    }
+
+    /// `resolve` without the fast path.
+    fn resolve_rules(&self, event: &InputEvent, state: &RuleState) -> Decision {
+        let window = &event.window;
+        let key = self.rule_key(event);
 
         match event.state {
             KeyState::Down => {
-                self.held_keys.insert(event.key);
+                // Auto-repeat of a split key: the Down side already fired.
+                if state.disabled_keys.contains(&key) || state.split_keys.contains_key(&key) {
+                    return Decision::consumed(Action::Suppress, Vec::new(), self.timing);
+                }
+                if !state.held_keys.contains(&key) && self.disables.disables(&state.held_keys, key)
+                {
+                    log::debug!("rule_engine: {:?} disabled by a held key", key);
+                    return Decision::consumed(
+                        Action::Suppress,
+                        vec![StateChange::Disabled(key)],
+                        self.timing,
+                    );
+                }
 
                 // Hotkeys take priority over remaps.
-                if let Some(action) = self.hotkeys.lookup(&self.held_keys, app_id) {
-                    log::debug!("rule_engine: hotkey fired on {:?}: {:?}", event.key, action);
-                    self.suppressed_keys.insert(event.key);
-                    return action;
+                let chord_keys = self.chord_keys(state);
+                if let Some((action, timing)) = self.hotkeys.lookup(&chord_keys, key, window) {
+                    log::debug!("rule_engine: hotkey fired on {:?}: {:?}", key, action);
+                    return Decision::consumed(
+                        action,
+                        vec![StateChange::Pressed(key), StateChange::TriggerConsumed(key)],
+                        timing,
+                    );
                 }
 
-                if let Some(target) = self.remaps.lookup(event.key, app_id) {
+                let mut pressed = vec![StateChange::Pressed(key)];
+                if let Some(rule) = self.remaps.lookup(key, window) {
                     log::debug!(
                         "rule_engine: remap {:?} -> {:?} ({:?})",
-                        event.key,
-                        target,
+                        key,
+                        rule.to,
                         event.state
                     );
-                    return Action::InjectKey {
-                        key: target,
-                        state: event.state,
-                    };
+                    match &rule.to {
+                        RemapTarget::Key(to)
+                            if rule.repeat.is_none() && rule.repeat_every_ms.is_none() =>
+                        {
+                            pressed.push(StateChange::Remapped(key, *to))
+                        }
+                        RemapTarget::Split { up, .. } => {
+                            pressed.push(StateChange::Split(key, up.clone()))
+                        }
+                        _ => {}
+                    }
+                    return Decision::consumed(
+                        remap_action(rule, event.state),
+                        pressed,
+                        rule.timing,
+                    );
                 }
 
-                Action::InjectKey {
-                    key: event.key,
-                    state: event.state,
-                }
+                Decision::passthrough(event, pressed, self.timing)
             }
 
             KeyState::Up => {
-                self.held_keys.remove(&event.key);
+                if state.disabled_keys.contains(&key) {
+                    return Decision::consumed(
+                        Action::Suppress,
+                        vec![StateChange::DisabledReleased(key)],
+                        self.timing,
+                    );
+                }
 
                 // Suppress the KeyUp for any key whose KeyDown was consumed by a hotkey.
-                if self.suppressed_keys.remove(&event.key) {
+                if state.suppressed_keys.contains(&key) {
                     log::debug!(
                         "rule_engine: suppressing KeyUp for hotkey trigger {:?}",
-                        event.key
+                        key
+                    );
+                    return Decision::consumed(
+                        Action::Suppress,
+                        vec![
+                            StateChange::Released(key),
+                            StateChange::TriggerReleased(key),
+                        ],
+                        self.timing,
                     );
-                    return Action::Suppress;
                 }
 
-                if let Some(target) = self.remaps.lookup(event.key, app_id) {
+                // Paired with the Down even if the rule is gone or no longer
+                // in scope.
+                if let Some(up) = state.split_keys.get(&key) {
+                    log::debug!("rule_engine: {:?} up side {:?}", key, up);
+                    return Decision::consumed(
+                        tap(up),
+                        vec![StateChange::Released(key)],
+                        self.timing,
+                    );
+                }
+
+                let released = vec![StateChange::Released(key)];
+                if let Some(rule) = self.remaps.lookup(key, window) {
                     log::debug!(
                         "rule_engine: remap {:?} -> {:?} ({:?})",
-                        event.key,
-                        target,
+                        key,
+                        rule.to,
                         event.state
                     );
-                    return Action::InjectKey {
-                        key: target,
-                        state: event.state,
-                    };
+                    return Decision::consumed(
+                        remap_action(rule, event.state),
+                        released,
+                        rule.timing,
+                    );
                 }
 
-                Action::InjectKey {
-                    key: event.key,
-                    state: event.state,
-                }
+                Decision::passthrough(event, released, self.timing)
             }
         }
     }
 }
 
+/// Whether a rule's scope covers `window`: `None` for a global rule,
+/// otherwise whether the app id is in `apps` (if given) and `when` matches
+/// (if given).
+fn scope_matches(
+    apps: Option<&[String]>,
+    when: Option<&WindowCondition>,
+    window: &WindowContext,
+) -> Option<bool> {
+    if apps.is_none() && when.is_none() {
+        return None;
+    }
+    let app_ok = apps.map_or(true, |apps| {
+        window
+            .app_id
+            .as_deref()
+            .is_some_and(|id| apps.iter().any(|a| a == id))
+    });
+    Some(app_ok && when.map_or(true, |w| w.matches(window)))
+}
+
+/// Resolve a remap rule to the action emitted for the source key's `state`.
+///
+/// Single-key and button targets mirror the source state. Combinations,
+/// characters, steps, and repeated targets are emitted in full on Down, so
+/// the matching Up is suppressed.
+fn remap_action(rule: &RemapRule, state: KeyState) -> Action {
+    if let Some(count) = rule.repeat {
+        return match state {
+            KeyState::Down => Action::Repeat {
+                action: Box::new(tap_action(rule)),
+                count,
+            },
+            KeyState::Up => Action::Suppress,
+        };
+    }
+    if let Some(interval_ms) = rule.repeat_every_ms {
+        return match state {
+            KeyState::Down => Action::RepeatWhileHeld {
+                interval_ms,
+                action: Box::new(tap_action(rule)),
+            },
+            KeyState::Up => Action::Suppress,
+        };
+    }
+    match (&rule.to, state) {
+        (RemapTarget::Key(key), _) => Action::InjectKey { key: *key, state },
+        (RemapTarget::Combo(keys), KeyState::Down) => Action::KeyCombo(keys.clone()),
+        (RemapTarget::Char(c), KeyState::Down) => Action::InjectChar(*c),
+        (RemapTarget::With(modifiers), _) => Action::WithModifiers(modifiers.clone()),
+        (RemapTarget::Button(button), _) => Action::MouseButton {
+            button: *button,
+            state,
+        },
+        (RemapTarget::Split { down, .. }, KeyState::Down) => tap(down),
+        (RemapTarget::Split { up, .. }, KeyState::Up) => tap(up),
+        (RemapTarget::Steps { steps, on_error }, KeyState::Down) => Action::Sequence {
+            steps: steps.iter().map(step_action).collect(),
+            on_error: *on_error,
+        },
+        (RemapTarget::Paste(text), KeyState::Down) => Action::PasteText { text: text.clone() },
+        (
+            RemapTarget::Combo(_)
+            | RemapTarget::Char(_)
+            | RemapTarget::Steps { .. }
+            | RemapTarget::Paste(_),
+            KeyState::Up,
+        ) => Action::Suppress,
+    }
+}
+
+/// The action for one step of a `steps` target.
+fn step_action(step: &RemapStep) -> Action {
+    match step {
+        RemapStep::Keys(keys) => Action::KeyCombo(keys.clone()),
+        RemapStep::Delay(ms) => Action::Delay { ms: *ms },
+    }
+}
+
+/// Tap `keys` as a combination, or emit nothing for an empty side of a
+/// `{ down, up }` target.
+fn tap(keys: &[KeyCode]) -> Action {
+    if keys.is_empty() {
+        Action::Suppress
+    } else {
+        Action::KeyCombo(keys.to_vec())
+    }
+}
+
+/// The action that emits the rule's target once in full: a single key is
+/// tapped, and a `with` target taps the source key with its modifiers.
+fn tap_action(rule: &RemapRule) -> Action {
+    match &rule.to {
+        RemapTarget::Key(key) => Action::KeyCombo(vec![*key]),
+        RemapTarget::Combo(keys) => Action::KeyCombo(keys.clone()),
+        RemapTarget::Char(c) => Action::InjectChar(*c),
+        RemapTarget::Paste(text) => Action::PasteText { text: text.clone() },
+        RemapTarget::With(modifiers) => {
+            Action::KeyCombo(modifiers.iter().copied().chain([rule.from]).collect())
+        }
+        RemapTarget::Button(button) => Action::Sequence {
+            steps: [KeyState::Down, KeyState::Up]
+                .map(|state| Action::MouseButton {
+                    button: *button,
+                    state,
+                })
+                .into(),
+            on_error: StepFailure::Abort,
+        },
+        // The config rejects `repeat` and `repeat_every_ms` on these.
+        RemapTarget::Split { down, .. } => tap(down),
+        RemapTarget::Steps { .. } => Action::Suppress,
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
+
     use super::*;
     use crate::platform::{KeyState, Modifiers, WindowContext};
 
@@ -145,6 +499,11 @@ mod tests {
             state: KeyState::Down,
             modifiers: Modifiers::default(),
             window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
         }
     }
 
@@ -154,6 +513,11 @@ mod tests {
             state,
             modifiers: Modifiers::default(),
             window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
         }
     }
 
@@ -166,12 +530,191 @@ mod tests {
                 app_id: Some(app_id.to_string()),
                 title: None,
             },
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
+        }
+    }
+
+    /// Rules plus the state the engine loop would carry, applying each
+    /// decision as the engine does.
+    struct Harness {
+        rules: RuleEngine,
+        state: RuleState,
+    }
+
+    impl Harness {
+        /// Resolve `event`, apply its state changes, and return its only
+        /// action.
+        fn process(&mut self, event: &InputEvent) -> Action {
+            let decision = self.rules.resolve(event, &self.state);
+            self.state.apply(&decision.state_changes);
+            let [action] = <[Action; 1]>::try_from(decision.actions).unwrap();
+            action
         }
     }
 
-    fn engine_from_toml(toml: &str) -> RuleEngine {
+    fn engine_from_toml(toml: &str) -> Harness {
         let config = crate::config::parse_str(toml).unwrap();
-        RuleEngine::new(&config)
+        Harness {
+            rules: RuleEngine::new(&config),
+            state: RuleState::default(),
+        }
+    }
+
+    // --- Decision tests ---
+
+    const DECISION_CONFIG: &str = r#"
+        [[remap]]
+        from = "A"
+        to   = "B"
+
+        [[hotkey]]
+        keys    = ["Ctrl", "Alt", "T"]
+        action  = "exec"
+        command = "kitty"
+    "#;
+
+    fn state_holding(keys: &[KeyCode]) -> RuleState {
+        let mut state = RuleState::default();
+        let changes: Vec<_> = keys.iter().map(|k| StateChange::Pressed(*k)).collect();
+        state.apply(&changes);
+        state
+    }
+
+    #[test]
+    fn passthrough_decision_is_not_consumed() {
+        let rules = engine_from_toml(DECISION_CONFIG).rules;
+        assert_eq!(
+            rules.resolve(&make_event(KeyCode::C), &RuleState::default()),
+            Decision {
+                actions: vec![Action::InjectKey {
+                    key: KeyCode::C,
+                    state: KeyState::Down
+                }],
+                consumed: false,
+                state_changes: vec![StateChange::Pressed(KeyCode::C)],
+                timing: Timing::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn remap_decision_is_consumed() {
+        let rules = engine_from_toml(DECISION_CONFIG).rules;
+        let up = make_event_with_state(KeyCode::A, KeyState::Up);
+        let decision = rules.resolve(&up, &state_holding(&[KeyCode::A]));
+        assert!(decision.consumed);
+        assert_eq!(
+            decision.actions,
+            vec![Action::InjectKey {
+                key: KeyCode::B,
+                state: KeyState::Up
+            }]
+        );
+        assert_eq!(
+            decision.state_changes,
+            vec![StateChange::Released(KeyCode::A)]
+        );
+    }
+
+    #[test]
+    fn hotkey_decision_depends_only_on_given_state() {
+        let rules = engine_from_toml(DECISION_CONFIG).rules;
+        let t = make_event(KeyCode::T);
+
+        // Nothing held: T is just a key.
+        assert!(!rules.resolve(&t, &RuleState::default()).consumed);
+
+        let state = state_holding(&[KeyCode::Ctrl, KeyCode::Alt]);
+        let decision = rules.resolve(&t, &state);
+        assert_eq!(
+            decision,
+            Decision {
+                actions: vec![Action::Exec {
+                    command: "kitty".into()
+                }],
+                consumed: true,
+                state_changes: vec![
+                    StateChange::Pressed(KeyCode::T),
+                    StateChange::TriggerConsumed(KeyCode::T),
+                ],
+                timing: Timing::default(),
+            }
+        );
+        // Resolving again from the same state gives the same answer.
+        assert_eq!(rules.resolve(&t, &state), decision);
+    }
+
+    #[test]
+    fn rule_timing_override_beats_global_default() {
+        let rules = engine_from_toml(
+            r#"
+            [timing]
+            tap_ms = 180
+
+            [[remap]]
+            from   = "A"
+            to     = "B"
+            timing = { tap_ms = 300 }
+
+            [[remap]]
+            from = "C"
+            to   = "D"
+
+            [[hotkey]]
+            keys    = ["Ctrl", "T"]
+            action  = "exec"
+            command = "kitty"
+            timing  = { chord_window_ms = 80 }
+        "#,
+        )
+        .rules;
+        let state = RuleState::default();
+        let ms = std::time::Duration::from_millis;
+
+        assert_eq!(
+            rules.resolve(&make_event(KeyCode::A), &state).timing.tap,
+            ms(300)
+        );
+        assert_eq!(
+            rules.resolve(&make_event(KeyCode::C), &state).timing.tap,
+            ms(180)
+        );
+        // Unmatched keys get the [timing] defaults too.
+        assert_eq!(
+            rules.resolve(&make_event(KeyCode::X), &state).timing.tap,
+            ms(180)
+        );
+
+        let hotkey = rules
+            .resolve(&make_event(KeyCode::T), &state_holding(&[KeyCode::Ctrl]))
+            .timing;
+        assert_eq!(hotkey.chord_window, ms(80));
+        assert_eq!(hotkey.tap, ms(180));
+    }
+
+    #[test]
+    fn trigger_release_decision_suppresses_and_clears_tracking() {
+        let rules = engine_from_toml(DECISION_CONFIG).rules;
+        let mut state = state_holding(&[KeyCode::Ctrl, KeyCode::Alt]);
+        state.apply(&rules.resolve(&make_event(KeyCode::T), &state).state_changes);
+
+        let up = make_event_with_state(KeyCode::T, KeyState::Up);
+        let decision = rules.resolve(&up, &state);
+        assert_eq!(decision.actions, vec![Action::Suppress]);
+        assert_eq!(
+            decision.state_changes,
+            vec![
+                StateChange::Released(KeyCode::T),
+                StateChange::TriggerReleased(KeyCode::T),
+            ]
+        );
+
+        state.apply(&decision.state_changes);
+        assert_eq!(state, state_holding(&[KeyCode::Ctrl, KeyCode::Alt]));
     }
 
     // --- Remap tests (M8) ---
@@ -244,6 +787,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn button_target_follows_the_key() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = { button = "left" }
+        "#,
+        );
+        for state in [KeyState::Down, KeyState::Up] {
+            assert_eq!(
+                engine.process(&make_event_with_state(KeyCode::F13, state)),
+                Action::MouseButton {
+                    button: crate::platform::MouseButton::Left,
+                    state,
+                }
+            );
+        }
+    }
+
     #[test]
     fn multiple_remaps_each_independent() {
         let mut engine = engine_from_toml(
@@ -361,6 +924,138 @@ mod tests {
         );
     }
 
+    /// On AZERTY the key at the US Q position types A. A rule on "Q" follows
+    /// the key in key mode and the position in position mode, and an
+    /// unmatched key passes through as the key the layout produced.
+    #[test]
+    fn position_mode_matches_physical_key() {
+        let azerty = |key, position| InputEvent {
+            position: Some(position),
+            ..make_event(key)
+        };
+        let rules = r#"
+            [[remap]]
+            from = "Q"
+            to   = "Escape"
+        "#;
+        let escape = Action::InjectKey {
+            key: KeyCode::Escape,
+            state: KeyState::Down,
+        };
+
+        let mut by_key = engine_from_toml(rules);
+        assert_eq!(
+            by_key.process(&azerty(KeyCode::A, KeyCode::Q)),
+            Action::InjectKey {
+                key: KeyCode::A,
+                state: KeyState::Down
+            }
+        );
+        assert_eq!(by_key.process(&azerty(KeyCode::Q, KeyCode::A)), escape);
+
+        let mut by_position =
+            engine_from_toml(&format!("[capture]\nmatch = \"position\"\n{rules}"));
+        assert_eq!(by_position.process(&azerty(KeyCode::A, KeyCode::Q)), escape);
+        assert_eq!(
+            by_position.process(&azerty(KeyCode::Q, KeyCode::A)),
+            Action::InjectKey {
+                key: KeyCode::Q,
+                state: KeyState::Down
+            }
+        );
+        // Without a reported position, the key is the position.
+        assert_eq!(by_position.process(&make_event(KeyCode::Q)), escape);
+    }
+
+    /// A rule on a unified modifier covers the right key too, unless the
+    /// right key has a rule of its own.
+    #[test]
+    fn unified_modifier_rule_covers_right_side() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "Meta"
+            to   = "Ctrl"
+
+            [[remap]]
+            from = "RightAlt"
+            to   = "Menu"
+        "#,
+        );
+        assert_eq!(
+            engine.process(&make_event(KeyCode::RightMeta)),
+            Action::InjectKey {
+                key: KeyCode::Ctrl,
+                state: KeyState::Down
+            }
+        );
+        assert_eq!(
+            engine.process(&make_event(KeyCode::RightAlt)),
+            Action::InjectKey {
+                key: KeyCode::Menu,
+                state: KeyState::Down
+            }
+        );
+        assert_eq!(
+            engine.process(&make_event(KeyCode::Alt)),
+            Action::InjectKey {
+                key: KeyCode::Alt,
+                state: KeyState::Down
+            }
+        );
+    }
+
+    /// Left-side modifiers are seen as the unified modifier unless
+    /// `[capture] sides` keeps them.
+    #[test]
+    fn left_modifiers_fold_unless_sides_are_kept() {
+        let rules = r#"
+            [[remap]]
+            from = "Meta"
+            to   = "Ctrl"
+        "#;
+        let mut engine = engine_from_toml(rules);
+        assert_eq!(
+            engine.process(&make_event(KeyCode::LeftMeta)),
+            Action::InjectKey {
+                key: KeyCode::Ctrl,
+                state: KeyState::Down
+            }
+        );
+        assert_eq!(
+            engine.process(&make_event(KeyCode::LeftAlt)),
+            Action::InjectKey {
+                key: KeyCode::LeftAlt,
+                state: KeyState::Down
+            }
+        );
+
+        let mut both = engine_from_toml(&format!(
+            "[capture]\nsides = \"both\"\n{rules}\n[[remap]]\nfrom = \"LeftAlt\"\nto = \"Menu\"\n"
+        ));
+        assert_eq!(
+            both.process(&make_event(KeyCode::LeftMeta)),
+            Action::InjectKey {
+                key: KeyCode::Ctrl,
+                state: KeyState::Down
+            }
+        );
+        assert_eq!(
+            both.process(&make_event(KeyCode::LeftAlt)),
+            Action::InjectKey {
+                key: KeyCode::Menu,
+                state: KeyState::Down
+            }
+        );
+        assert_eq!(
+            both.process(&make_event(KeyCode::RightAlt)),
+            Action::InjectKey {
+                key: KeyCode::RightAlt,
+                state: KeyState::Down
+            }
+        );
+    }
+
     /// Two global rules with the same `from` key: the first in config order wins.
     /// NOTE: In future releases (maybe v2) we should explicitly validate against this behavior!
     #[test]
@@ -385,6 +1080,104 @@ mod tests {
         );
     }
 
+    /// A `when` rule wins over the global rule only in matching windows.
+    #[test]
+    fn when_rule_applies_only_in_matching_windows() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = "F14"
+            when = { title = "YouTube$" }
+
+            [[remap]]
+            from = "F13"
+            to   = "F15"
+        "#,
+        );
+        let mut event = make_event(KeyCode::F13);
+        event.window.title = Some("Cats - YouTube".to_owned());
+        assert_eq!(
+            engine.process(&event),
+            Action::InjectKey {
+                key: KeyCode::F14,
+                state: KeyState::Down
+            }
+        );
+        event.window.title = Some("YouTube - Wikipedia".to_owned());
+        assert_eq!(
+            engine.process(&event),
+            Action::InjectKey {
+                key: KeyCode::F15,
+                state: KeyState::Down
+            }
+        );
+    }
+
+    /// A combination target taps the whole combo on Down and swallows the Up.
+    #[test]
+    fn combo_remap_emits_key_combo_on_down_and_suppresses_up() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = "Ctrl+Shift+T"
+        "#,
+        );
+        assert_eq!(
+            engine.process(&make_event(KeyCode::F13)),
+            Action::KeyCombo(vec![KeyCode::Ctrl, KeyCode::Shift, KeyCode::T])
+        );
+        assert_eq!(
+            engine.process(&make_event_with_state(KeyCode::F13, KeyState::Up)),
+            Action::Suppress
+        );
+    }
+
+    /// A repeated target is tapped `repeat` times on Down; a single key
+    /// becomes a one-key combo, i.e. a tap.
+    #[test]
+    fn repeat_remap_emits_repeat_on_down_and_suppresses_up() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[remap]]
+            from   = "F13"
+            to     = "Down"
+            repeat = 5
+        "#,
+        );
+        assert_eq!(
+            engine.process(&make_event(KeyCode::F13)),
+            Action::Repeat {
+                action: Box::new(Action::KeyCombo(vec![KeyCode::Down])),
+                count: 5,
+            }
+        );
+        assert_eq!(
+            engine.process(&make_event_with_state(KeyCode::F13, KeyState::Up)),
+            Action::Suppress
+        );
+    }
+
+    #[test]
+    fn char_remap_emits_inject_char_on_down_and_suppresses_up() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = { char = "é" }
+        "#,
+        );
+        assert_eq!(
+            engine.process(&make_event(KeyCode::F13)),
+            Action::InjectChar('é')
+        );
+        assert_eq!(
+            engine.process(&make_event_with_state(KeyCode::F13, KeyState::Up)),
+            Action::Suppress
+        );
+    }
+
     // --- Hotkey tests (M9) ---
 
     /// Gate test: Ctrl+Alt+T fires an exec action when all three keys are held.
@@ -409,6 +1202,122 @@ mod tests {
         );
     }
 
+    const CAPS_CTRL_CONFIG: &str = r#"
+        [[remap]]
+        from = "CapsLock"
+        to   = "Ctrl"
+
+        [[remap]]
+        from = "Ctrl"
+        to   = "Meta"
+
+        [[hotkey]]
+        keys    = ["Ctrl", "C"]
+        action  = "exec"
+        command = "copy"
+    "#;
+
+    #[test]
+    fn logical_modifiers_match_chords_after_remapping() {
+        let copy = Action::Exec {
+            command: "copy".into(),
+        };
+        let mut engine = engine_from_toml(CAPS_CTRL_CONFIG);
+        engine.process(&make_event(KeyCode::CapsLock));
+        assert_eq!(engine.process(&make_event(KeyCode::C)), copy);
+        engine.process(&make_event_with_state(KeyCode::C, KeyState::Up));
+        engine.process(&make_event_with_state(KeyCode::CapsLock, KeyState::Up));
+
+        // The real Ctrl is Meta now, and CapsLock no longer counts once up.
+        engine.process(&make_event(KeyCode::Ctrl));
+        assert_ne!(engine.process(&make_event(KeyCode::C)), copy);
+    }
+
+    #[test]
+    fn physical_modifiers_match_chords_as_pressed() {
+        let copy = Action::Exec {
+            command: "copy".into(),
+        };
+        let toml = format!("[capture]\nmodifiers = \"physical\"\n{CAPS_CTRL_CONFIG}");
+        let mut engine = engine_from_toml(&toml);
+        engine.process(&make_event(KeyCode::CapsLock));
+        assert_eq!(
+            engine.process(&make_event(KeyCode::C)),
+            inject(KeyCode::C, KeyState::Down)
+        );
+        engine.process(&make_event_with_state(KeyCode::C, KeyState::Up));
+        engine.process(&make_event_with_state(KeyCode::CapsLock, KeyState::Up));
+
+        engine.process(&make_event(KeyCode::Ctrl));
+        assert_eq!(engine.process(&make_event(KeyCode::C)), copy);
+    }
+
+    // --- Down/up remaps ---
+
+    #[test]
+    fn down_up_remap_taps_each_side_and_ignores_repeats() {
+        let mut engine = engine_from_toml(
+            "[[remap]]\nfrom = \"F13\"\nto = { down = \"F14\", up = \"Ctrl+M\" }\n",
+        );
+        assert_eq!(
+            engine.process(&make_event(KeyCode::F13)),
+            Action::KeyCombo(vec![KeyCode::F14])
+        );
+        let mut repeat = make_event(KeyCode::F13);
+        repeat.is_repeat = true;
+        assert_eq!(engine.process(&repeat), Action::Suppress);
+        assert_eq!(
+            engine.process(&make_event_with_state(KeyCode::F13, KeyState::Up)),
+            Action::KeyCombo(vec![KeyCode::Ctrl, KeyCode::M])
+        );
+        assert_eq!(engine.state, RuleState::default());
+    }
+
+    /// The Up pairs with the Down that fired, even once the app-scoped rule
+    /// no longer applies.
+    #[test]
+    fn down_up_remap_pairs_across_a_focus_change() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[remap]]
+            from = "F13"
+            to   = { up = "F15" }
+            apps = ["discord"]
+            "#,
+        );
+        assert_eq!(
+            engine.process(&make_event_with_app(KeyCode::F13, "discord")),
+            Action::Suppress
+        );
+        assert_eq!(
+            engine.process(&make_event_with_state(KeyCode::F13, KeyState::Up)),
+            Action::KeyCombo(vec![KeyCode::F15])
+        );
+
+        // Elsewhere the key is not remapped at all.
+        engine.process(&make_event(KeyCode::F13));
+        assert_eq!(
+            engine.process(&make_event_with_state(KeyCode::F13, KeyState::Up)),
+            inject(KeyCode::F13, KeyState::Up)
+        );
+    }
+
+    #[test]
+    fn hotkey_lock_screen_fires_system_command() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[hotkey]]
+            keys   = ["Meta", "L"]
+            action = "lock_screen"
+        "#,
+        );
+        engine.process(&make_event(KeyCode::Meta));
+        assert_eq!(
+            engine.process(&make_event(KeyCode::L)),
+            Action::SystemCommand(crate::platform::SystemCmd::LockScreen)
+        );
+    }
+
     /// The trigger key's Up is suppressed after a hotkey fires.
     #[test]
     fn hotkey_trigger_key_up_is_suppressed() {
@@ -541,8 +1450,266 @@ mod tests {
         );
     }
 
+    // --- Disable tests ---
+
+    fn down(key: KeyCode) -> InputEvent {
+        make_event(key)
+    }
+
+    fn up(key: KeyCode) -> InputEvent {
+        make_event_with_state(key, KeyState::Up)
+    }
+
+    fn inject(key: KeyCode, state: KeyState) -> Action {
+        Action::InjectKey { key, state }
+    }
+
+    #[test]
+    fn disable_applies_while_the_controller_is_held() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[disable]]
+            while_held = "F13"
+            keys       = ["Meta"]
+        "#,
+        );
+        assert_eq!(
+            engine.process(&down(KeyCode::F13)),
+            inject(KeyCode::F13, KeyState::Down)
+        );
+        assert_eq!(engine.process(&down(KeyCode::Meta)), Action::Suppress);
+        // Repeats and the release of a disabled press stay dropped, even
+        // once the controller is up.
+        assert_eq!(engine.process(&down(KeyCode::RightMeta)), Action::Suppress);
+        engine.process(&up(KeyCode::F13));
+        assert_eq!(engine.process(&down(KeyCode::Meta)), Action::Suppress);
+        assert_eq!(engine.process(&up(KeyCode::Meta)), Action::Suppress);
+        assert_eq!(engine.process(&up(KeyCode::RightMeta)), Action::Suppress);
+
+        // Released controller: Meta works again.
+        assert_eq!(
+            engine.process(&down(KeyCode::Meta)),
+            inject(KeyCode::Meta, KeyState::Down)
+        );
+        assert_eq!(
+            engine.process(&up(KeyCode::Meta)),
+            inject(KeyCode::Meta, KeyState::Up)
+        );
+    }
+
+    #[test]
+    fn overlapping_disables_last_until_every_controller_is_up() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[disable]]
+            while_held = "F13"
+            keys       = ["Tab", "Q"]
+
+            [[disable]]
+            while_held = "F14"
+            keys       = ["Tab"]
+        "#,
+        );
+        engine.process(&down(KeyCode::F13));
+        engine.process(&down(KeyCode::F14));
+        engine.process(&up(KeyCode::F13));
+        assert_eq!(engine.process(&down(KeyCode::Tab)), Action::Suppress);
+        engine.process(&up(KeyCode::Tab));
+        // Only F13 disabled Q.
+        assert_eq!(
+            engine.process(&down(KeyCode::Q)),
+            inject(KeyCode::Q, KeyState::Down)
+        );
+        engine.process(&up(KeyCode::F14));
+        assert_eq!(
+            engine.process(&down(KeyCode::Tab)),
+            inject(KeyCode::Tab, KeyState::Down)
+        );
+    }
+
+    #[test]
+    fn disable_composes_with_remaps_and_hotkeys() {
+        let mut engine = engine_from_toml(
+            r#"
+            [[disable]]
+            while_held = "CapsLock"
+            keys       = ["Alt"]
+
+            [[remap]]
+            from = "CapsLock"
+            to   = "Ctrl"
+
+            [[remap]]
+            from = "Alt"
+            to   = "Meta"
+
+            [[hotkey]]
+            keys    = ["CapsLock", "Alt", "T"]
+            action  = "exec"
+            command = "kitty"
+        "#,
+        );
+        // A key down before its controller is released as usual.
+        assert_eq!(
+            engine.process(&down(KeyCode::Alt)),
+            inject(KeyCode::Meta, KeyState::Down)
+        );
+        assert_eq!(
+            engine.process(&down(KeyCode::CapsLock)),
+            inject(KeyCode::Ctrl, KeyState::Down)
+        );
+        assert_eq!(
+            engine.process(&up(KeyCode::Alt)),
+            inject(KeyCode::Meta, KeyState::Up)
+        );
+
+        // A disabled key is not held as far as chords go.
+        assert_eq!(engine.process(&down(KeyCode::Alt)), Action::Suppress);
+        assert_eq!(
+            engine.process(&down(KeyCode::T)),
+            inject(KeyCode::T, KeyState::Down)
+        );
+        engine.process(&up(KeyCode::T));
+        engine.process(&up(KeyCode::Alt));
+        engine.process(&up(KeyCode::CapsLock));
+        assert_eq!(
+            engine.process(&down(KeyCode::Alt)),
+            inject(KeyCode::Meta, KeyState::Down)
+        );
+    }
+
+    // --- Fast path ---
+
+    /// Feed the same pseudo-random key sequence through `resolve` and
+    /// `resolve_rules`, comparing every decision.
+    fn assert_fast_path_matches(toml: &str) {
+        const KEYS: [KeyCode; 14] = [
+            KeyCode::Meta,
+            KeyCode::RightMeta,
+            KeyCode::Ctrl,
+            KeyCode::RightCtrl,
+            KeyCode::Shift,
+            KeyCode::RightAlt,
+            KeyCode::A,
+            KeyCode::B,
+            KeyCode::T,
+            KeyCode::X,
+            KeyCode::F,
+            KeyCode::F13,
+            KeyCode::Tab,
+            KeyCode::Q,
+        ];
+        let rules = engine_from_toml(toml).rules;
+        let mut state = RuleState::default();
+        let mut seed: u32 = 0x2393;
+        for step in 0..5_000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let key = KEYS[(seed >> 16) as usize % KEYS.len()];
+            let held = state.held_keys.contains(&key) || state.disabled_keys.contains(&key);
+            let key_state = if held && seed & 1 == 0 {
+                KeyState::Up
+            } else {
+                KeyState::Down
+            };
+            let mut event = make_event_with_state(key, key_state);
+            if seed & 0x100 != 0 {
+                event.window.app_id = Some("firefox".into());
+            }
+            let full = rules.resolve_rules(&event, &state);
+            assert_eq!(
+                rules.resolve(&event, &state),
+                full,
+                "step {step}: {key:?} {key_state:?}"
+            );
+            state.apply(&full.state_changes);
+        }
+    }
+
+    #[test]
+    fn fast_path_decides_like_the_rule_tables() {
+        assert_fast_path_matches(
+            r#"
+            [[hotkey]]
+            keys    = ["Meta", "X"]
+            action  = "exec"
+            command = "x"
+
+            [[hotkey]]
+            keys    = ["Meta", "Shift", "T"]
+            action  = "exec"
+            command = "t"
+        "#,
+        );
+        assert_fast_path_matches(
+            r#"
+            [[remap]]
+            from = "A"
+            to   = "B"
+
+            [[remap]]
+            from = "Tab"
+            to   = "Escape"
+            apps = ["firefox"]
+
+            [[hotkey]]
+            keys    = ["Ctrl", "T"]
+            action  = "exec"
+            command = "t"
+
+            [[hotkey]]
+            keys    = ["RightAlt", "F"]
+            action  = "exec"
+            command = "f"
+
+            [[hotkey]]
+            keys    = ["F13", "Q"]
+            action  = "exec"
+            command = "q"
+
+            [[disable]]
+            while_held = "F13"
+            keys       = ["Meta"]
+        "#,
+        );
+    }
+
+    #[test]
+    fn hotkeys_may_fire_only_with_their_modifiers() {
+        let rules = engine_from_toml(
+            r#"
+            [[hotkey]]
+            keys    = ["Meta", "Shift", "T"]
+            action  = "exec"
+            command = "t"
+
+            [[hotkey]]
+            keys    = ["Meta", "X"]
+            action  = "exec"
+            command = "x"
+        "#,
+        )
+        .rules;
+        let none = HashSet::new();
+        assert!(!rules.hotkeys.may_fire(&none, KeyCode::X));
+        assert!(!rules
+            .hotkeys
+            .may_fire(&HashSet::from([KeyCode::Shift]), KeyCode::T));
+        assert!(rules.hotkeys.may_fire(&none, KeyCode::RightMeta));
+        assert!(rules
+            .hotkeys
+            .may_fire(&HashSet::from([KeyCode::RightMeta]), KeyCode::X));
+    }
+
     // --- Higher-level smoke tests: event_bus -> rule_engine pipeline ---
 
+    /// The next event off the bus, which must be a key event.
+    fn next_key(subscriber: &mut crate::event_bus::EventSubscriber) -> InputEvent {
+        match subscriber.next() {
+            Some(crate::platform::CapturedEvent::Key(event)) => event,
+            other => panic!("expected a key event, got {other:?}"),
+        }
+    }
+
     #[test]
     fn smoke_bus_to_rule_engine_remap() {
         // Verifies the integration path from EventPublisher through RuleEngine
@@ -555,7 +1722,10 @@ mod tests {
         "#,
         )
         .unwrap();
-        let mut engine = RuleEngine::new(&config);
+        let mut engine = Harness {
+            rules: RuleEngine::new(&config),
+            state: RuleState::default(),
+        };
 
         let (publisher, mut subscriber) = crate::event_bus::new(8);
         publisher.send(InputEvent {
@@ -563,10 +1733,15 @@ mod tests {
             state: KeyState::Down,
             modifiers: Modifiers::default(),
             window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
         });
         drop(publisher);
 
-        let event = subscriber.next().unwrap();
+        let event = next_key(&mut subscriber);
         assert_eq!(
             engine.process(&event),
             Action::InjectKey {
@@ -588,7 +1763,10 @@ mod tests {
         "#,
         )
         .unwrap();
-        let mut engine = RuleEngine::new(&config);
+        let mut engine = Harness {
+            rules: RuleEngine::new(&config),
+            state: RuleState::default(),
+        };
 
         let (publisher, mut subscriber) = crate::event_bus::new(8);
         publisher.send(InputEvent {
@@ -596,24 +1774,39 @@ mod tests {
             state: KeyState::Down,
             modifiers: Modifiers::default(),
             window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
         });
         publisher.send(InputEvent {
             key: KeyCode::Alt,
             state: KeyState::Down,
             modifiers: Modifiers::default(),
             window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
         });
         publisher.send(InputEvent {
             key: KeyCode::T,
             state: KeyState::Down,
             modifiers: Modifiers::default(),
             window: WindowContext::default(),
+            timestamp: Instant::now(),
+            device: None,
+            observed: false,
+            is_repeat: false,
+            position: None,
         });
         drop(publisher);
 
-        engine.process(&subscriber.next().unwrap()); // Ctrl Down
-        engine.process(&subscriber.next().unwrap()); // Alt Down
-        let action = engine.process(&subscriber.next().unwrap()); // T Down -> hotkey fires
+        engine.process(&next_key(&mut subscriber)); // Ctrl Down
+        engine.process(&next_key(&mut subscriber)); // Alt Down
+        let action = engine.process(&next_key(&mut subscriber)); // T Down -> hotkey fires
         assert_eq!(
             action,
             Action::Exec {
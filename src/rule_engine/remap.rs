@@ -2,14 +2,15 @@
 
 use std::collections::HashMap;
 
+use super::scope_matches;
 use crate::config::RemapRule;
-use crate::platform::KeyCode;
+use crate::platform::{KeyCode, WindowContext};
 
 /// Compiled remap lookup table, keyed by the `from` key.
 ///
-/// Within each entry, per-app rules are stored before global rules so that
-/// app-specific overrides are evaluated first when window context is available
-/// (M11 readiness). Config file order is preserved within each category.
+/// Within each entry, scoped rules (`apps` or `when`) are stored before global
+/// rules so that app-specific overrides are evaluated first. Config file order
+/// is preserved within each category.
 pub(super) struct RemapTable {
     rules: HashMap<KeyCode, Vec<RemapRule>>,
 }
@@ -18,44 +19,56 @@ impl RemapTable {
     pub(super) fn build(remaps: &[RemapRule]) -> Self {
         let mut rules: HashMap<KeyCode, Vec<RemapRule>> = HashMap::new();
 
-        // Per-app rules inserted first -- they win over globals when app matches.
-        for rule in remaps.iter().filter(|r| r.apps.is_some()) {
+        // Scoped rules inserted first -- they win over globals when they match.
+        let scoped = |r: &&RemapRule| r.apps.is_some() || r.when.is_some();
+        for rule in remaps.iter().filter(scoped) {
             rules.entry(rule.from).or_default().push(rule.clone());
         }
-        for rule in remaps.iter().filter(|r| r.apps.is_none()) {
+        for rule in remaps.iter().filter(|r| !scoped(r)) {
             rules.entry(rule.from).or_default().push(rule.clone());
         }
 
         Self { rules }
     }
 
-    /// Resolve `from` to a target key given the current app identifier.
+    /// Whether any rule, in any scope, names `from` or its unified modifier.
+    pub(super) fn covers(&self, from: KeyCode) -> bool {
+        self.rules.contains_key(&from) || self.rules.contains_key(&from.unify_side())
+    }
+
+    /// Resolve `from` to the remap rule in effect for the focused window.
     ///
-    /// Per-app rules are evaluated first. The first matching global rule is
+    /// Scoped rules are evaluated first. The first matching global rule is
     /// the fallback. Returns `None` when no rule covers `from`.
-    /// Per-app rules are silently skipped when `app_id` is `None` (window
-    /// context unavailable until M11).
-    pub(super) fn lookup(&self, from: KeyCode, app_id: Option<&str>) -> Option<KeyCode> {
+    /// Scoped rules never match when the window context is unknown.
+    ///
+    /// A right-side modifier with no rule of its own falls back to the rules
+    /// for the unified modifier, so a rule on `Meta` covers both keys.
+    pub(super) fn lookup(&self, from: KeyCode, window: &WindowContext) -> Option<&RemapRule> {
+        self.lookup_exact(from, window).or_else(|| {
+            let unified = from.unify_side();
+            (unified != from)
+                .then(|| self.lookup_exact(unified, window))
+                .flatten()
+        })
+    }
+
+    fn lookup_exact(&self, from: KeyCode, window: &WindowContext) -> Option<&RemapRule> {
         let rules = self.rules.get(&from)?;
-        let mut global_target: Option<KeyCode> = None;
+        let mut global_rule: Option<&RemapRule> = None;
 
         for rule in rules {
-            match &rule.apps {
-                Some(apps) => {
-                    if let Some(id) = app_id {
-                        if apps.iter().any(|a| a == id) {
-                            return Some(rule.to);
-                        }
-                    }
-                }
+            match scope_matches(rule.apps.as_deref(), rule.when.as_ref(), window) {
+                Some(true) => return Some(rule),
+                Some(false) => {}
                 None => {
-                    if global_target.is_none() {
-                        global_target = Some(rule.to);
+                    if global_rule.is_none() {
+                        global_rule = Some(rule);
                     }
                 }
             }
         }
 
-        global_target
+        global_rule
     }
 }
@@ -0,0 +1,54 @@
+//! SIGINT and SIGTERM end the main loop rather than the process.
+//!
+//! Returning from `main` drops the capture backend, which releases what it
+//! changed outside the process, such as the macOS CapsLock mapping (see
+//! `[macos] capslock_to`). The handler only sets a flag, which the main loop
+//! checks on every event and tick. A second signal exits at once, for when
+//! the loop itself is stuck.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a shutdown signal has arrived.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Ask the main loop to end, as a signal does. For front-ends inside the
+/// process, such as the Windows tray icon's Quit.
+#[cfg(all(target_os = "windows", feature = "tray"))]
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Install the SIGINT and SIGTERM handlers.
+#[cfg(unix)]
+pub fn install() {
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+        fn _exit(status: i32) -> !;
+    }
+
+    extern "C" fn on_signal(signum: i32) {
+        if REQUESTED.swap(true, Ordering::Relaxed) {
+            // SAFETY: _exit is async-signal-safe.
+            unsafe { _exit(128 + signum) }
+        }
+    }
+
+    for signum in [SIGINT, SIGTERM] {
+        // SAFETY: the handler only touches an atomic and calls _exit.
+        if unsafe { signal(signum, on_signal) } == SIG_ERR {
+            log::warn!("engine: cannot handle signal {signum}; it will end the process at once");
+        }
+    }
+}
+
+/// Console control events are not handled yet; Ctrl+C ends the process.
+#[cfg(not(unix))]
+pub fn install() {}
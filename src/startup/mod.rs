@@ -0,0 +1,516 @@
+//! `pcunifier install-startup` and `uninstall-startup`: start at login on
+//! Windows.
+//!
+//! The daemon is registered one of two ways:
+//!   - A value in the user's Run key (the default). Windows runs it at
+//!     logon with the user's ordinary rights, so elevated windows stay out of
+//!     reach (see `platform::windows::elevation`).
+//!   - A Task Scheduler task (`--task`) that runs at logon with the highest
+//!     run level the user has. For an administrator that is elevated, which
+//!     lets injection reach elevated windows. Creating it takes an elevated
+//!     prompt, which is where the user consents.
+//!
+//! Only one is kept: installing either removes the other, so the daemon is
+//! never started twice.
+//!
+//! Running as a Windows service is refused. Services run in session 0,
+//! which has no desktop the user sees: a keyboard hook there sees none of
+//! the user's keys and injected input reaches none of their windows.
+//!
+//! Both entries are written by `reg.exe` and `schtasks.exe`. Building what
+//! they are given and reading back what they report is plain string work
+//! and is tested everywhere; running them only happens on Windows.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cli::StartupMethod;
+
+/// The Run key value name, also the task name.
+pub const NAME: &str = "PC Unifier";
+
+/// The user's Run key, as `reg.exe` names it.
+const RUN_KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+
+// ---------------------------------------------------------------------------
+// Errors
+// ---------------------------------------------------------------------------
+
+/// Errors from installing or removing the startup entry.
+#[derive(Debug, thiserror::Error)]
+pub enum StartupError {
+    /// Run keys and scheduled tasks only exist on Windows.
+    #[error("{0} is only available on Windows")]
+    Unsupported(&'static str),
+
+    /// A service was asked for.
+    #[error(
+        "pcunifier cannot run as a Windows service: services run in session 0, apart from \
+         the user's desktop, where a keyboard hook sees no keys and injected input reaches \
+         no window; use install-startup, or install-startup --task to reach elevated windows"
+    )]
+    Service,
+
+    /// The current executable could not be found.
+    #[error("cannot locate the pcunifier executable: {0}")]
+    CurrentExe(#[source] std::io::Error),
+
+    /// Writing or removing the task definition file failed.
+    #[error("cannot {action} '{path}': {source}")]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// `schtasks` refused to create the task.
+    #[error(
+        "schtasks could not create the task ({status}): {stderr}; a task with the highest \
+         run level must be created from a prompt started with 'Run as administrator'"
+    )]
+    TaskRefused { status: String, stderr: String },
+
+    /// `reg` or `schtasks` exited with an error.
+    #[error("{program} {args} failed ({status}): {stderr}")]
+    Tool {
+        program: &'static str,
+        args: String,
+        status: String,
+        stderr: String,
+    },
+}
+
+// ---------------------------------------------------------------------------
+// Entry description
+// ---------------------------------------------------------------------------
+
+/// What the startup entry runs, and for whom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The pcunifier executable.
+    pub program: PathBuf,
+    /// Passed to the daemon with `--config`.
+    pub config: PathBuf,
+    /// The account the task runs for, as `DOMAIN\name`.
+    pub user: String,
+}
+
+impl Entry {
+    /// An entry for the running executable and `config`, for the current
+    /// user. A relative `config` is resolved against the current directory,
+    /// which logon does not start in.
+    pub fn for_current_exe(config: PathBuf) -> Result<Self, StartupError> {
+        let program = std::env::current_exe().map_err(StartupError::CurrentExe)?;
+        let config = match std::env::current_dir() {
+            Ok(dir) if config.is_relative() => dir.join(config),
+            _ => config,
+        };
+        let name = std::env::var("USERNAME").unwrap_or_default();
+        let user = match std::env::var("USERDOMAIN") {
+            Ok(domain) => format!("{domain}\\{name}"),
+            Err(_) => name,
+        };
+        Ok(Self {
+            program,
+            config,
+            user,
+        })
+    }
+
+    /// The arguments the daemon is started with.
+    fn arguments(&self) -> String {
+        format!("--config \"{}\"", self.config.display())
+    }
+
+    /// The command line the Run key value holds.
+    pub fn command_line(&self) -> String {
+        format!("\"{}\" {}", self.program.display(), self.arguments())
+    }
+
+    /// The task definition `schtasks` imports.
+    ///
+    /// The task starts at the user's logon, in their session, with the
+    /// highest run level they have. Task Scheduler stops tasks after three
+    /// days and starts them below normal priority unless told otherwise, so
+    /// the limit is lifted and the priority set to normal.
+    pub fn task_xml(&self) -> String {
+        let user = escape(&self.user);
+        let program = escape(&self.program.to_string_lossy());
+        let arguments = escape(&self.arguments());
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>Starts PC Unifier at logon.</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+      <UserId>{user}</UserId>
+    </LogonTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>{user}</UserId>
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>HighestAvailable</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <Priority>4</Priority>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{program}</Command>
+      <Arguments>{arguments}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#
+        )
+    }
+}
+
+/// Escape text for an XML element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// ---------------------------------------------------------------------------
+// Reading back
+// ---------------------------------------------------------------------------
+
+/// The data of the `REG_SZ` value in `reg query` output, such as
+/// `    PC Unifier    REG_SZ    "C:\pcunifier.exe" --config "..."`.
+fn run_value(query: &str) -> Option<String> {
+    query.lines().find_map(|line| {
+        let (name, data) = line.split_once("REG_SZ")?;
+        (name.trim() == NAME).then(|| data.trim().to_owned())
+    })
+}
+
+/// What a task exported by `schtasks /Query /XML` runs, as a command line.
+fn task_action(xml: &str) -> Option<String> {
+    let command = element(xml, "Command")?;
+    let command = command.trim_matches('"');
+    match element(xml, "Arguments") {
+        Some(arguments) => Some(format!("\"{command}\" {arguments}")),
+        None => Some(format!("\"{command}\"")),
+    }
+}
+
+/// The unescaped text of the first `<name>` element in `xml`.
+fn element(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{name}>"))? + name.len() + 2;
+    let len = xml[start..].find(&format!("</{name}>"))?;
+    Some(unescape(&xml[start..start + len]))
+}
+
+/// The executable a command line starts: the quoted first word, or the
+/// first word.
+fn program_of(command_line: &str) -> &str {
+    let line = command_line.trim_start();
+    match line.strip_prefix('"') {
+        Some(rest) => rest.split('"').next().unwrap_or(rest),
+        None => line.split(' ').next().unwrap_or(line),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Install and uninstall
+// ---------------------------------------------------------------------------
+
+/// What is registered already.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Existing {
+    None,
+    /// The same command line: nothing to write.
+    Same,
+    /// Another executable or config path, as after the executable moved.
+    Different,
+}
+
+fn existing(current: Option<&str>, wanted: &str) -> Existing {
+    match current {
+        None => Existing::None,
+        Some(current) if current == wanted => Existing::Same,
+        Some(_) => Existing::Different,
+    }
+}
+
+/// Register `entry` to start at login with `method`. With `dry_run`, print
+/// what would be written instead.
+///
+/// An identical entry is left alone, and a different one, such as one that
+/// runs the executable from where it used to be, is replaced. The entry of
+/// the other method is removed. Every change is printed.
+pub fn install(entry: &Entry, method: StartupMethod, dry_run: bool) -> Result<(), StartupError> {
+    if method == StartupMethod::Service {
+        return Err(StartupError::Service);
+    }
+    if dry_run {
+        match method {
+            StartupMethod::Task => {
+                println!("# scheduled task '{NAME}'");
+                print!("{}", entry.task_xml());
+            }
+            _ => {
+                println!("# {RUN_KEY}");
+                println!("{NAME} = {}", entry.command_line());
+            }
+        }
+        return Ok(());
+    }
+    if !cfg!(target_os = "windows") {
+        return Err(StartupError::Unsupported("install-startup"));
+    }
+    match method {
+        StartupMethod::Task => {
+            install_task(entry)?;
+            remove_run_value()?;
+        }
+        _ => {
+            install_run_value(entry)?;
+            remove_task()?;
+        }
+    }
+    println!("pcunifier now starts at login");
+    Ok(())
+}
+
+/// Remove both kinds of entry. Reports, rather than fails, when nothing is
+/// installed.
+pub fn uninstall() -> Result<(), StartupError> {
+    if !cfg!(target_os = "windows") {
+        return Err(StartupError::Unsupported("uninstall-startup"));
+    }
+    let removed_value = remove_run_value()?;
+    let removed_task = remove_task()?;
+    if !removed_value && !removed_task {
+        println!("{NAME} is not registered to start at login");
+    }
+    Ok(())
+}
+
+/// Say what an existing entry ran before it is replaced.
+fn report_replaced(what: &str, current: &str) {
+    println!("replacing the existing {what}, which ran {current}");
+    let program = program_of(current);
+    if !Path::new(program).exists() {
+        println!("({program} no longer exists; the executable has probably moved)");
+    }
+}
+
+fn install_run_value(entry: &Entry) -> Result<(), StartupError> {
+    let wanted = entry.command_line();
+    let current = query(REG, &["query", RUN_KEY, "/v", NAME]).and_then(|out| run_value(&out));
+    match existing(current.as_deref(), &wanted) {
+        Existing::Same => {
+            println!("{RUN_KEY}\\{NAME} is already {wanted}");
+            return Ok(());
+        }
+        Existing::Different => report_replaced(&format!("{RUN_KEY}\\{NAME}"), &current.unwrap()),
+        Existing::None => {}
+    }
+    run(
+        REG,
+        &[
+            "add", RUN_KEY, "/v", NAME, "/t", "REG_SZ", "/d", &wanted, "/f",
+        ],
+    )?;
+    println!("wrote {RUN_KEY}\\{NAME} = {wanted}");
+    Ok(())
+}
+
+fn install_task(entry: &Entry) -> Result<(), StartupError> {
+    let wanted = entry.command_line();
+    let current =
+        query(SCHTASKS, &["/Query", "/TN", NAME, "/XML"]).and_then(|xml| task_action(&xml));
+    match existing(current.as_deref(), &wanted) {
+        Existing::Same => {
+            println!("scheduled task '{NAME}' already runs {wanted}");
+            return Ok(());
+        }
+        Existing::Different => {
+            report_replaced(&format!("scheduled task '{NAME}'"), &current.unwrap())
+        }
+        Existing::None => {}
+    }
+
+    // schtasks reads the definition from a file, in the UTF-16 its header
+    // declares.
+    let path = std::env::temp_dir().join("pcunifier-task.xml");
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(entry.task_xml().encode_utf16().flat_map(u16::to_le_bytes));
+    std::fs::write(&path, bytes).map_err(|source| StartupError::Io {
+        action: "write",
+        path: path.clone(),
+        source,
+    })?;
+    let path_arg = path.to_string_lossy();
+    let created = run(SCHTASKS, &["/Create", "/TN", NAME, "/XML", &path_arg, "/F"]);
+    if let Err(e) = std::fs::remove_file(&path) {
+        log::debug!("startup: cannot remove {}: {e}", path.display());
+    }
+    created.map_err(|e| match e {
+        StartupError::Tool { status, stderr, .. } => StartupError::TaskRefused { status, stderr },
+        e => e,
+    })?;
+    println!(
+        "created scheduled task '{NAME}': at logon of {}, highest run level, runs {wanted}",
+        entry.user
+    );
+    Ok(())
+}
+
+/// Delete the Run key value. Returns whether there was one.
+fn remove_run_value() -> Result<bool, StartupError> {
+    let Some(current) = query(REG, &["query", RUN_KEY, "/v", NAME]).and_then(|out| run_value(&out))
+    else {
+        return Ok(false);
+    };
+    run(REG, &["delete", RUN_KEY, "/v", NAME, "/f"])?;
+    println!("removed {RUN_KEY}\\{NAME}, which ran {current}");
+    Ok(true)
+}
+
+/// Delete the scheduled task. Returns whether there was one.
+fn remove_task() -> Result<bool, StartupError> {
+    let Some(current) =
+        query(SCHTASKS, &["/Query", "/TN", NAME, "/XML"]).and_then(|xml| task_action(&xml))
+    else {
+        return Ok(false);
+    };
+    run(SCHTASKS, &["/Delete", "/TN", NAME, "/F"])?;
+    println!("removed scheduled task '{NAME}', which ran {current}");
+    Ok(true)
+}
+
+// ---------------------------------------------------------------------------
+// reg and schtasks
+// ---------------------------------------------------------------------------
+
+const REG: &str = "reg";
+const SCHTASKS: &str = "schtasks";
+
+/// Run `program` and return its output on success.
+fn run(program: &'static str, args: &[&str]) -> Result<String, StartupError> {
+    let joined = args.join(" ");
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| StartupError::Tool {
+            program,
+            args: joined.clone(),
+            status: "not started".into(),
+            stderr: e.to_string(),
+        })?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    Err(StartupError::Tool {
+        program,
+        args: joined,
+        status: output.status.to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+    })
+}
+
+/// Run a query whose failure means nothing is registered. The tools say so
+/// in the user's language, so any failure counts.
+fn query(program: &'static str, args: &[&str]) -> Option<String> {
+    run(program, args)
+        .map_err(|e| log::debug!("startup: {e}"))
+        .ok()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            program: PathBuf::from(r"C:\Tools\PC Unifier\pcunifier.exe"),
+            config: PathBuf::from(r"C:\Users\me\AppData\Roaming\pc-unifier\config.toml"),
+            user: r"DESKTOP\me".into(),
+        }
+    }
+
+    #[test]
+    fn command_line_quotes_both_paths() {
+        assert_eq!(
+            entry().command_line(),
+            r#""C:\Tools\PC Unifier\pcunifier.exe" --config "C:\Users\me\AppData\Roaming\pc-unifier\config.toml""#
+        );
+    }
+
+    #[test]
+    fn task_runs_at_logon_elevated_without_a_time_limit() {
+        let xml = entry().task_xml();
+        assert!(xml.contains("<UserId>DESKTOP\\me</UserId>\n    </LogonTrigger>"));
+        assert!(xml.contains("<RunLevel>HighestAvailable</RunLevel>"));
+        assert!(xml.contains("<ExecutionTimeLimit>PT0S</ExecutionTimeLimit>"));
+        assert!(xml.contains(r"<Command>C:\Tools\PC Unifier\pcunifier.exe</Command>"));
+        assert_eq!(task_action(&xml), Some(entry().command_line()));
+    }
+
+    #[test]
+    fn reg_query_output_is_read_back() {
+        let wanted = entry().command_line();
+        let out = format!(
+            "\r\n{RUN_KEY}\r\n    OneDrive    REG_SZ    \"C:\\od.exe\" /background\r\n    \
+             {NAME}    REG_SZ    {wanted}\r\n\r\n"
+        );
+        assert_eq!(run_value(&out), Some(wanted.clone()));
+        assert_eq!(
+            existing(run_value(&out).as_deref(), &wanted),
+            Existing::Same
+        );
+        assert_eq!(run_value("ERROR: not found"), None);
+    }
+
+    #[test]
+    fn moved_executable_is_a_different_entry() {
+        let moved = Entry {
+            program: PathBuf::from(r"D:\pcunifier.exe"),
+            ..entry()
+        };
+        let old = entry().command_line();
+        assert_eq!(
+            existing(Some(&old), &moved.command_line()),
+            Existing::Different
+        );
+        assert_eq!(program_of(&old), r"C:\Tools\PC Unifier\pcunifier.exe");
+        assert_eq!(
+            program_of(r"C:\pcunifier.exe --config c.toml"),
+            r"C:\pcunifier.exe"
+        );
+    }
+
+    #[test]
+    fn service_is_refused_before_anything_is_written() {
+        assert!(matches!(
+            install(&entry(), StartupMethod::Service, true),
+            Err(StartupError::Service)
+        ));
+    }
+}